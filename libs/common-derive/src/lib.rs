@@ -0,0 +1,138 @@
+//! `#[derive(Redact)]` for response DTOs
+//!
+//! Generates a `common::middleware::Redact` impl listing the JSON names of
+//! the fields marked `#[redact]`:
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, Redact)]
+//! #[serde(rename_all = "camelCase")]
+//! struct PayoutAccount {
+//!     bank_name: String,
+//!     #[redact]
+//!     account_number: String, // listed as "accountNumber"
+//! }
+//! ```
+//!
+//! Field `#[serde(rename = "...")]` and container `#[serde(rename_all = "...")]`
+//! are honoured, so the names match what the DTO serializes to.
+//!
+//! Use the re-export `common::middleware::Redact` rather than depending on
+//! this crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Fields, LitStr, parse_macro_input};
+
+#[proc_macro_derive(Redact, attributes(redact))]
+pub fn derive_redact(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "#[derive(Redact)] requires a struct with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "#[derive(Redact)] only supports structs",
+            ));
+        }
+    };
+
+    let rename_all = serde_value(&input.attrs, "rename_all")?;
+    let mut redacted = Vec::new();
+    for field in fields {
+        let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("redact")) else {
+            continue;
+        };
+        attr.meta.require_path_only()?;
+        let ident = field.ident.as_ref().expect("named field");
+        let json_name = match serde_value(&field.attrs, "rename")? {
+            Some(rename) => rename.value(),
+            None => {
+                let ident = ident.to_string();
+                let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+                match &rename_all {
+                    Some(rule) => apply_rename_rule(ident, rule)?,
+                    None => ident.to_string(),
+                }
+            }
+        };
+        redacted.push(json_name);
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::common::middleware::Redact for #name #ty_generics #where_clause {
+            fn redacted_fields() -> &'static [&'static str] {
+                &[#(#redacted),*]
+            }
+        }
+    })
+}
+
+/// Value of `#[serde(<key> = "...")]` among `attrs`, if present
+fn serde_value(attrs: &[Attribute], key: &str) -> syn::Result<Option<LitStr>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                value = Some(meta.value()?.parse()?);
+            } else if meta.input.peek(syn::Token![=]) {
+                // Some other `name = value` option; skip its value
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|nested| {
+                    if nested.input.peek(syn::Token![=]) {
+                        nested.value()?.parse::<syn::Expr>()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(value)
+}
+
+/// `field` renamed by a serde `rename_all` rule
+fn apply_rename_rule(field: &str, rule: &LitStr) -> syn::Result<String> {
+    let words = field.split('_').filter(|w| !w.is_empty());
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+            .unwrap_or_default()
+    };
+    Ok(match rule.value().as_str() {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_uppercase(),
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.replace('_', "-").to_uppercase(),
+        "PascalCase" => words.map(capitalize).collect(),
+        "camelCase" => words
+            .enumerate()
+            .map(|(i, w)| if i == 0 { w.to_string() } else { capitalize(w) })
+            .collect(),
+        other => {
+            return Err(syn::Error::new_spanned(
+                rule,
+                format!("unsupported rename_all rule {other:?}"),
+            ));
+        }
+    })
+}
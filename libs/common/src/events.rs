@@ -0,0 +1,303 @@
+//! Domain event envelopes
+//!
+//! Every published event is wrapped in an [`EventEnvelope`] carrying two
+//! ids that let an event chain be put back together:
+//!
+//! - `correlation_id` is shared by everything that happened because of one
+//!   originating request, across services;
+//! - `causation_id` is the id of the request or event that directly caused
+//!   this one.
+//!
+//! Handlers publish through [`RequestEvents`], which stamps both from the
+//! request's [`TrackingContext`] (the correlation id, and the request id as
+//! the cause).  Consumers reacting to an event use
+//! [`EventEnvelope::caused_by`], so their events join the same chain.
+//!
+//! Events relayed from an outbox may be published more than once.  They
+//! carry a [`stable_event_id`] derived from their aggregate and sequence
+//! instead of a random one, so every copy has the same `event_id` and
+//! consumers can dedupe on it.
+//!
+//! Publishers stamp `published_at` as they hand an event to the bus
+//! ([`EventEnvelope::published`]), and consumers call
+//! [`EventEnvelope::record_consumed`] when they pick one up.  The delay
+//! between the two is recorded as [`EVENT_CONSUME_LAG_SECONDS`], so a
+//! consumer falling behind shows up on the metrics endpoint.
+//!
+//! ```rust,ignore
+//! async fn fund_escrow(events: RequestEvents, ...) -> AppResult<()> {
+//!     // ...
+//!     events.publish("escrow.funded", &EscrowFunded { escrow_id }).await?;
+//! }
+//! ```
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::value_objects::tracking::TrackingContext;
+
+/// Histogram of the delay between an event being published and a consumer
+/// picking it up, labelled by `event_type`
+pub const EVENT_CONSUME_LAG_SECONDS: &str = "event_consume_lag_seconds";
+
+/// Namespace of the name-based UUIDs minted by [`stable_event_id`]
+const EVENT_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_5a0e_93d2_4b7f_a8e4_27c9_1d3b_5e60);
+
+/// Event id determined by the event's aggregate and its sequence number
+///
+/// The same inputs always give the same id (a UUIDv5), so re-publishing an
+/// event reuses its id.
+pub fn stable_event_id(aggregate_type: &str, aggregate_id: &str, sequence: i64) -> String {
+    let name = format!("{aggregate_type}\0{aggregate_id}\0{sequence}");
+    Uuid::new_v5(&EVENT_ID_NAMESPACE, name.as_bytes()).to_string()
+}
+
+/// A domain event with the ids tying it to what caused it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope<T = serde_json::Value> {
+    pub event_id: String,
+    pub event_type: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+    /// When the event was handed to the bus; `None` until it is published
+    #[serde(
+        default,
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub published_at: Option<OffsetDateTime>,
+    /// Originating request of the chain this event belongs to
+    pub correlation_id: String,
+    /// Request or event that directly caused this one; `None` for events
+    /// that start a chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub causation_id: Option<String>,
+    pub payload: T,
+}
+
+impl<T> EventEnvelope<T> {
+    /// Event starting a new chain, e.g. from a scheduled job
+    pub fn new(event_type: impl Into<String>, payload: T) -> Self {
+        let event_id = Uuid::new_v4().to_string();
+        Self {
+            correlation_id: event_id.clone(),
+            event_id,
+            event_type: event_type.into(),
+            occurred_at: OffsetDateTime::now_utc(),
+            published_at: None,
+            causation_id: None,
+            payload,
+        }
+    }
+
+    /// Event caused by the request tracked by `tracking`
+    pub fn for_request(
+        event_type: impl Into<String>,
+        payload: T,
+        tracking: &TrackingContext,
+    ) -> Self {
+        Self {
+            correlation_id: tracking.correlation_id.to_string(),
+            causation_id: Some(tracking.request_id.to_string()),
+            ..Self::new(event_type, payload)
+        }
+    }
+
+    /// Event caused by handling `cause`
+    pub fn caused_by<C>(
+        event_type: impl Into<String>,
+        payload: T,
+        cause: &EventEnvelope<C>,
+    ) -> Self {
+        Self {
+            correlation_id: cause.correlation_id.clone(),
+            causation_id: Some(cause.event_id.clone()),
+            ..Self::new(event_type, payload)
+        }
+    }
+
+    /// This event stamped as published now
+    pub fn published(mut self) -> Self {
+        self.published_at = Some(OffsetDateTime::now_utc());
+        self
+    }
+
+    /// How long the event had been waiting for a consumer at `now`
+    ///
+    /// Measured from `published_at`, or from `occurred_at` for events
+    /// published without the stamp.
+    pub fn lag_at(&self, now: OffsetDateTime) -> Duration {
+        let since = self.published_at.unwrap_or(self.occurred_at);
+        Duration::try_from(now - since).unwrap_or_default()
+    }
+
+    /// Record the event's consume lag on the installed metrics recorder
+    pub fn record_consumed(&self) {
+        metrics::histogram!(EVENT_CONSUME_LAG_SECONDS, "event_type" => self.event_type.clone())
+            .record(self.lag_at(OffsetDateTime::now_utc()).as_secs_f64());
+    }
+}
+
+#[cfg(feature = "http")]
+pub use publisher::{EventPublisher, RequestEvents};
+
+#[cfg(feature = "http")]
+mod publisher {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::extract::FromRequestParts;
+    use axum::http::request::Parts;
+    use error::AppResult;
+    use error::http::ApiError;
+    use serde::Serialize;
+
+    use super::EventEnvelope;
+    use crate::value_objects::tracking::TrackingContext;
+
+    /// Destination of published events (outbox, message broker)
+    ///
+    /// Implementations handing the event to the bus stamp it with
+    /// [`EventEnvelope::published`]; an outbox leaves that to its relay.
+    #[async_trait]
+    pub trait EventPublisher: Send + Sync {
+        async fn publish(&self, envelope: EventEnvelope) -> AppResult<()>;
+    }
+
+    /// Publishes events on behalf of the current request
+    ///
+    /// Extracted from the request: the publisher comes from an
+    /// `Extension<Arc<dyn EventPublisher>>` layer and the ids from the
+    /// tracking middleware's [`TrackingContext`].
+    #[derive(Clone)]
+    pub struct RequestEvents {
+        publisher: Arc<dyn EventPublisher>,
+        tracking: TrackingContext,
+    }
+
+    impl RequestEvents {
+        pub fn new(publisher: Arc<dyn EventPublisher>, tracking: TrackingContext) -> Self {
+            Self {
+                publisher,
+                tracking,
+            }
+        }
+
+        /// Publish `payload` as an `event_type` event caused by this request
+        pub async fn publish<T: Serialize>(&self, event_type: &str, payload: &T) -> AppResult<()> {
+            let payload = serde_json::to_value(payload).map_err(|e| {
+                error::AppError::internal(format!("event payload not serializable: {e}"))
+            })?;
+            self.publisher
+                .publish(EventEnvelope::for_request(
+                    event_type,
+                    payload,
+                    &self.tracking,
+                ))
+                .await
+        }
+    }
+
+    impl<S> FromRequestParts<S> for RequestEvents
+    where
+        S: Send + Sync,
+    {
+        type Rejection = ApiError;
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            _state: &S,
+        ) -> Result<Self, Self::Rejection> {
+            let publisher = parts
+                .extensions
+                .get::<Arc<dyn EventPublisher>>()
+                .cloned()
+                .ok_or_else(|| ApiError::internal("event publisher not configured"))?;
+            // Outside the tracking middleware the request starts its own chain
+            let tracking = parts
+                .extensions
+                .get::<TrackingContext>()
+                .cloned()
+                .unwrap_or_default();
+            Ok(Self::new(publisher, tracking))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+    use crate::middleware::{TrackingConfig, tracking_middleware};
+    use async_trait::async_trait;
+    use axum::{
+        Extension, Router, body::Body, extract::Request, http::StatusCode, middleware::from_fn,
+        routing::post,
+    };
+    use error::AppResult;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<EventEnvelope>>,
+    }
+
+    #[async_trait]
+    impl EventPublisher for RecordingPublisher {
+        async fn publish(&self, envelope: EventEnvelope) -> AppResult<()> {
+            self.published.lock().unwrap().push(envelope);
+            Ok(())
+        }
+    }
+
+    async fn fund_escrow(events: RequestEvents) -> StatusCode {
+        events
+            .publish(
+                "escrow.funded",
+                &serde_json::json!({ "escrow_id": "esc_1" }),
+            )
+            .await
+            .unwrap();
+        StatusCode::ACCEPTED
+    }
+
+    #[tokio::test]
+    async fn test_published_event_carries_request_correlation_id() {
+        let publisher = Arc::new(RecordingPublisher::default());
+        let app = Router::new()
+            .route("/escrows/esc_1/fund", post(fund_escrow))
+            .layer(Extension(publisher.clone() as Arc<dyn EventPublisher>))
+            .layer(from_fn(|req: Request, next| {
+                tracking_middleware(req, next, TrackingConfig::default())
+            }));
+
+        let correlation_id = "7f3c2a9e-4b1d-4e8a-9c6f-2d5b8e1a0f34";
+        let request_id = "0b8e6d2c-1f4a-4c3b-8e7d-9a5f3c2b1d0e";
+        let response = app
+            .oneshot(
+                Request::post("/escrows/esc_1/fund")
+                    .header("x-correlation-id", correlation_id)
+                    .header("x-request-id", request_id)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let published = publisher.published.lock().unwrap().clone();
+        assert_eq!(published.len(), 1);
+        let funded = &published[0];
+        assert_eq!(funded.event_type, "escrow.funded");
+        assert_eq!(funded.correlation_id, correlation_id);
+        assert_eq!(funded.causation_id.as_deref(), Some(request_id));
+
+        // A consumer's follow-up event stays in the chain
+        let released = EventEnvelope::caused_by("escrow.released", (), funded);
+        assert_eq!(released.correlation_id, correlation_id);
+        assert_eq!(released.causation_id.as_ref(), Some(&funded.event_id));
+    }
+}
@@ -1,10 +1,15 @@
 //! Authentication extractor
 
+use std::sync::Arc;
+
 use axum::{
     extract::FromRequestParts,
     http::{StatusCode, request::Parts},
 };
 
+use crate::middleware::auth_context::AuthContext;
+use crate::middleware::authorization::Authorizer;
+
 /// Bearer token extractor
 pub struct BearerToken(pub String);
 
@@ -38,6 +43,53 @@ where
     }
 }
 
+/// Authenticated caller, from bearer auth or a session cookie
+///
+/// Reads the [`AuthContext`] the auth middleware inserted; rejects the
+/// request with `401` when neither authenticated it.
+pub struct Authenticated(pub Arc<AuthContext>);
+
+impl<S> FromRequestParts<S> for Authenticated
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthorityRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Arc<AuthContext>>()
+            .cloned()
+            .map(Authenticated)
+            .ok_or(AuthorityRejection(
+                StatusCode::UNAUTHORIZED,
+                "Authentication required",
+            ))
+    }
+}
+
+/// Request-scoped authorization checks for the caller
+///
+/// Set by the authorization middleware for authenticated requests; rejects
+/// with `401` otherwise.
+impl<S> FromRequestParts<S> for Authorizer
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthorityRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Authorizer>()
+            .cloned()
+            .ok_or(AuthorityRejection(
+                StatusCode::UNAUTHORIZED,
+                "Authentication required",
+            ))
+    }
+}
+
 /// Authentication rejection
 pub struct AuthorityRejection(pub StatusCode, pub &'static str);
 
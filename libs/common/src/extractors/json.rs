@@ -8,6 +8,9 @@ use axum::{
 };
 use serde::de::DeserializeOwned;
 
+use crate::http::error::{ApiError, FieldError};
+use crate::validation::{Normalize, Validate, ValidationErrors};
+
 /// Custom JSON extractor with better error messages
 pub struct JsonExtractor<T>(pub T);
 
@@ -26,6 +29,42 @@ where
     }
 }
 
+/// JSON body that has been normalized and then validated
+///
+/// Runs [`Normalize::normalize`] before [`Validate::validate`], so rules see
+/// (and handlers receive) canonical values.  Validation failures are
+/// rejected with 422 and one field error per failed rule.
+pub struct ValidatedJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ValidatedJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Normalize + Validate,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(mut value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        value.normalize();
+        value.validate().map_err(validation_rejection)?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+fn validation_rejection(errors: ValidationErrors) -> ApiError {
+    let fields = errors
+        .as_slice()
+        .iter()
+        .map(|e| FieldError {
+            field: e.field.clone(),
+            message: e.message.clone(),
+        })
+        .collect();
+    ApiError::validation_error_with_fields("Request validation failed", fields)
+}
+
 /// JSON rejection response
 pub struct JsonRejection(pub StatusCode, pub &'static str);
 
@@ -38,10 +77,67 @@ impl IntoResponse for JsonRejection {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::value_objects::EmailAddress;
 
     #[test]
     fn test_json_extractor_exists() {
         // Compile-time test
         let _: Option<JsonExtractor<serde_json::Value>> = None;
     }
+
+    #[derive(serde::Deserialize)]
+    struct SignupRequest {
+        email: EmailAddress,
+        name: String,
+    }
+
+    impl Normalize for SignupRequest {
+        fn normalize(&mut self) {
+            self.email.normalize();
+            self.name.normalize();
+        }
+    }
+
+    impl Validate for SignupRequest {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if !EmailAddress::is_valid_addr(self.email.as_str()) {
+                errors.add("email", "invalid email address");
+            }
+            if self.name.is_empty() {
+                errors.add("name", "required");
+            }
+            errors.into_result()
+        }
+    }
+
+    async fn extract(body: serde_json::Value) -> Result<SignupRequest, ApiError> {
+        let request = Request::post("/signup")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        ValidatedJson::<SignupRequest>::from_request(request, &())
+            .await
+            .map(|ValidatedJson(value)| value)
+    }
+
+    #[tokio::test]
+    async fn test_email_is_normalized_before_validation() {
+        // The raw value fails the email pattern; the normalized one passes
+        let request = extract(serde_json::json!({
+            "email": "  Ada.Lovelace@Example.COM ",
+            "name": " Ada ",
+        }))
+        .await
+        .unwrap();
+        assert_eq!(request.email.as_str(), "ada.lovelace@example.com");
+        assert_eq!(request.name, "Ada");
+
+        let err = extract(serde_json::json!({ "email": "not-an-email", "name": "   " }))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status_code, Some(StatusCode::UNPROCESSABLE_ENTITY));
+        assert_eq!(err.details.unwrap().as_array().unwrap().len(), 2);
+    }
 }
@@ -14,6 +14,12 @@ pub mod path;
 #[cfg(feature = "http")]
 pub mod auth;
 
+#[cfg(feature = "http")]
+pub mod upload;
+
+#[cfg(feature = "http")]
+pub mod versioned;
+
 #[cfg(feature = "http")]
 pub use auth::*;
 
@@ -26,5 +32,11 @@ pub use path::*;
 #[cfg(feature = "http")]
 pub use query::*;
 
+#[cfg(feature = "http")]
+pub use upload::*;
+
+#[cfg(feature = "http")]
+pub use versioned::*;
+
 #[cfg(not(feature = "http"))]
 compile_error!("extractors module requires 'http' feature to be enabled");
@@ -1,11 +1,18 @@
 //! Path parameter extractor
+//!
+//! [`PathExtractor`] rejects with an [`ApiError`] instead of axum's plain
+//! text rejection, so a malformed ID in the path — most often a
+//! [`Ulid`](crate::value_objects::Ulid) — gets the same structured `400` as
+//! any other bad input.
 
 use axum::{
-    extract::{FromRequestParts, Path},
+    extract::{FromRequestParts, Path, path::ErrorKind, rejection::PathRejection},
     http::request::Parts,
 };
 use serde::de::DeserializeOwned;
 
+use crate::http::error::ApiError;
+
 /// Path parameter extractor
 pub struct PathExtractor<T>(pub T);
 
@@ -14,23 +21,83 @@ where
     S: Send + Sync,
     T: DeserializeOwned + Send,
 {
-    type Rejection = axum::extract::rejection::PathRejection;
+    type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         match Path::<T>::from_request_parts(parts, state).await {
             Ok(Path(value)) => Ok(PathExtractor(value)),
-            Err(e) => Err(e),
+            Err(e) => Err(path_rejection(e)),
+        }
+    }
+}
+
+fn path_rejection(rejection: PathRejection) -> ApiError {
+    let PathRejection::FailedToDeserializePathParams(e) = &rejection else {
+        // The route and the extractor disagree: a bug, not bad input
+        return ApiError::internal(rejection.body_text());
+    };
+    match e.kind() {
+        ErrorKind::ParseErrorAtKey {
+            key,
+            value,
+            expected_type,
+        } => ApiError::bad_request(format!("Invalid path parameter '{key}'")).with_details(
+            serde_json::json!({ "parameter": key, "value": value, "expected_type": expected_type }),
+        ),
+        ErrorKind::ParseErrorAtIndex { index, value, .. } => {
+            ApiError::bad_request(format!("Invalid path parameter at index {index}"))
+                .with_details(serde_json::json!({ "value": value }))
         }
+        ErrorKind::ParseError { value, .. } => ApiError::bad_request("Invalid path parameter")
+            .with_details(serde_json::json!({ "value": value })),
+        // Value objects such as `Ulid` report their own message
+        ErrorKind::Message(message) => ApiError::bad_request(message.clone()),
+        _ => ApiError::bad_request(e.body_text()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::body_text;
+    use crate::value_objects::Ulid;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    async fn show_order(PathExtractor(id): PathExtractor<Ulid>) -> String {
+        id.to_string()
+    }
+
+    async fn get_body(app: Router, uri: &str) -> (StatusCode, String) {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        (response.status(), body_text(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_malformed_ulid_in_path_is_a_structured_400() {
+        let app = Router::new().route("/orders/{id}", get(show_order));
+
+        let (status, body) = get_body(app.clone(), "/orders/not-a-ulid").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"]["code"], "BAD_REQUEST", "{body}");
+        assert_eq!(
+            body["error"]["message"],
+            "Invalid ULID 'not-a-ulid': invalid length"
+        );
 
-    #[test]
-    fn test_path_extractor_exists() {
-        // Compile-time test
-        let _: Option<PathExtractor<String>> = None;
+        let id = Ulid::new();
+        let (status, body) =
+            get_body(app, &format!("/orders/{}", id.to_string().to_lowercase())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, id.to_string());
     }
 }
@@ -0,0 +1,404 @@
+//! Multipart document upload extractor
+//!
+//! Wraps axum's `Multipart` for the verification flow (ID documents,
+//! evidence).  Files are checked against an allowlist of content types,
+//! both as declared by the client and by sniffing the leading bytes, and
+//! per-file / total size limits are enforced while streaming, so handlers can
+//! pipe each file straight into the object store without buffering it:
+//!
+//! ```rust,ignore
+//! async fn upload(mut upload: Upload, State(store): State<Arc<dyn ObjectStore>>) -> ApiResult {
+//!     while let Some(file) = upload.next_file().await? {
+//!         let key = format!("kyc/{}", Uuid::new_v4());
+//!         let content_type = file.content_type().to_string();
+//!         let body = file.into_stream().map_err(std::io::Error::other);
+//!         store.put_stream(&key, &content_type, Box::pin(body)).await?;
+//!     }
+//!     Ok(ApiResponse::success_message("uploaded"))
+//! }
+//! ```
+//!
+//! Limits come from an `UploadLimits` request extension (e.g. a route-level
+//! `Extension` layer) and fall back to [`UploadLimits::default`].  The body
+//! is read directly from the request, so axum's `DefaultBodyLimit` does not
+//! apply; `max_total_size` (plus a small allowance for form fields) caps it.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use error::http::{ApiError, ErrorCode};
+use futures::{Stream, stream};
+use multer::{Constraints, Field, Multipart, SizeLimit};
+use thiserror::Error;
+
+/// Content types accepted for identity documents
+pub const DOCUMENT_CONTENT_TYPES: &[&str] = &["image/jpeg", "image/png", "application/pdf"];
+
+/// Room for non-file form fields and part headers on top of the file limit
+const FORM_OVERHEAD: u64 = 64 * 1024;
+
+/// Size and type limits for an upload request
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    /// Maximum size of a single file in bytes
+    pub max_file_size: u64,
+    /// Maximum combined size of all files in bytes
+    pub max_total_size: u64,
+    /// Accepted content types
+    pub allowed_types: Vec<&'static str>,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: 10 * 1024 * 1024,
+            max_total_size: 25 * 1024 * 1024,
+            allowed_types: DOCUMENT_CONTENT_TYPES.to_vec(),
+        }
+    }
+}
+
+/// Upload rejection
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("invalid multipart body: {0}")]
+    Malformed(String),
+
+    #[error("file '{file_name}' has disallowed content type '{content_type}'")]
+    DisallowedType {
+        file_name: String,
+        content_type: String,
+    },
+
+    #[error("file '{file_name}' content does not match its declared type '{content_type}'")]
+    ContentMismatch {
+        file_name: String,
+        content_type: String,
+    },
+
+    #[error("file '{file_name}' exceeds the {limit} byte limit")]
+    FileTooLarge { file_name: String, limit: u64 },
+
+    #[error("upload exceeds the {limit} byte total limit")]
+    TotalTooLarge { limit: u64 },
+}
+
+impl From<UploadError> for ApiError {
+    fn from(err: UploadError) -> Self {
+        let status = match err {
+            UploadError::Malformed(_) => StatusCode::BAD_REQUEST,
+            UploadError::DisallowedType { .. } | UploadError::ContentMismatch { .. } => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            UploadError::FileTooLarge { .. } | UploadError::TotalTooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+        };
+        ApiError::new(ErrorCode::ValidationError, err.to_string()).with_status(status)
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Validated multipart upload
+pub struct Upload {
+    multipart: Multipart<'static>,
+    limits: Arc<UploadLimits>,
+    total: Arc<AtomicU64>,
+}
+
+impl<S> FromRequest<S> for Upload
+where
+    S: Send + Sync,
+{
+    type Rejection = UploadError;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let limits = req
+            .extensions()
+            .get::<UploadLimits>()
+            .cloned()
+            .unwrap_or_default();
+        let boundary = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| multer::parse_boundary(value).ok())
+            .ok_or_else(|| UploadError::Malformed("expected multipart/form-data".to_string()))?;
+
+        let constraints = Constraints::new()
+            .size_limit(SizeLimit::new().whole_stream(limits.max_total_size + FORM_OVERHEAD));
+        let multipart =
+            Multipart::with_constraints(req.into_body().into_data_stream(), boundary, constraints);
+
+        Ok(Self {
+            multipart,
+            limits: Arc::new(limits),
+            total: Arc::new(AtomicU64::new(0)),
+        })
+    }
+}
+
+impl Upload {
+    /// Limits applied to this upload
+    pub fn limits(&self) -> &UploadLimits {
+        &self.limits
+    }
+
+    /// Next file part, skipping plain form fields.
+    ///
+    /// The file's type is validated before it is returned; its size is
+    /// checked as it is read.  The previous file must be dropped (or fully
+    /// read) before asking for the next one.
+    pub async fn next_file(&mut self) -> Result<Option<UploadedFile>, UploadError> {
+        loop {
+            let Some(mut field) = self
+                .multipart
+                .next_field()
+                .await
+                .map_err(|e| self.map_multer(e))?
+            else {
+                return Ok(None);
+            };
+            let Some(file_name) = field.file_name().map(str::to_string) else {
+                continue;
+            };
+
+            let content_type = field
+                .content_type()
+                .map(|mime| mime.essence_str().to_ascii_lowercase())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            if !self.limits.allowed_types.contains(&content_type.as_str()) {
+                return Err(UploadError::DisallowedType {
+                    file_name,
+                    content_type,
+                });
+            }
+
+            let first = field.chunk().await.map_err(|e| self.map_multer(e))?;
+            let head = first.as_deref().unwrap_or_default();
+            if sniff(head) != Some(content_type.as_str()) {
+                return Err(UploadError::ContentMismatch {
+                    file_name,
+                    content_type,
+                });
+            }
+
+            let mut file = UploadedFile {
+                field,
+                file_name,
+                content_type,
+                limits: self.limits.clone(),
+                total: self.total.clone(),
+                size: 0,
+                pending: None,
+            };
+            if let Some(first) = first {
+                file.account(first.len() as u64)?;
+                file.pending = Some(first);
+            }
+            return Ok(Some(file));
+        }
+    }
+
+    fn map_multer(&self, err: multer::Error) -> UploadError {
+        map_multer(err, &self.limits)
+    }
+}
+
+/// A single validated file being read from the request
+pub struct UploadedFile {
+    field: Field<'static>,
+    file_name: String,
+    content_type: String,
+    limits: Arc<UploadLimits>,
+    total: Arc<AtomicU64>,
+    size: u64,
+    pending: Option<Bytes>,
+}
+
+impl UploadedFile {
+    /// Form field name
+    pub fn name(&self) -> Option<&str> {
+        self.field.name()
+    }
+
+    /// Client-supplied file name (do not use as a storage key)
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Validated content type
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Next chunk of the file, `None` at the end
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, UploadError> {
+        if let Some(pending) = self.pending.take() {
+            return Ok(Some(pending));
+        }
+
+        match self
+            .field
+            .chunk()
+            .await
+            .map_err(|e| map_multer(e, &self.limits))?
+        {
+            Some(chunk) => {
+                self.account(chunk.len() as u64)?;
+                Ok(Some(chunk))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The remaining file content as a stream of chunks
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, UploadError>> + Send + 'static {
+        stream::try_unfold(self, |mut file| async move {
+            Ok(file.chunk().await?.map(|chunk| (chunk, file)))
+        })
+    }
+
+    fn account(&mut self, len: u64) -> Result<(), UploadError> {
+        self.size += len;
+        let total = self.total.fetch_add(len, Ordering::Relaxed) + len;
+
+        if self.size > self.limits.max_file_size {
+            return Err(UploadError::FileTooLarge {
+                file_name: self.file_name.clone(),
+                limit: self.limits.max_file_size,
+            });
+        }
+        if total > self.limits.max_total_size {
+            return Err(UploadError::TotalTooLarge {
+                limit: self.limits.max_total_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn map_multer(err: multer::Error, limits: &UploadLimits) -> UploadError {
+    match err {
+        multer::Error::StreamSizeExceeded { .. } => UploadError::TotalTooLarge {
+            limit: limits.max_total_size,
+        },
+        other => UploadError::Malformed(other.to_string()),
+    }
+}
+
+/// Content type implied by a file's leading bytes
+fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if head.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some("image/png")
+    } else if head.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use futures::TryStreamExt;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 13];
+    const EXE: &[u8] = b"MZ\x90\x00\x03\x00\x00\x00";
+
+    fn request(parts: &[(&str, &str, &[u8])], limits: Option<UploadLimits>) -> Request {
+        let mut body = Vec::new();
+        for (file_name, content_type, data) in parts {
+            body.extend_from_slice(
+                format!(
+                    "--X\r\nContent-Disposition: form-data; name=\"document\"; filename=\"{file_name}\"\r\nContent-Type: {content_type}\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--X--\r\n");
+
+        let mut req = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .body(Body::from(body))
+            .unwrap();
+        if let Some(limits) = limits {
+            req.extensions_mut().insert(limits);
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_valid_image_is_streamed() {
+        let mut upload = Upload::from_request(request(&[("id.png", "image/png", PNG)], None), &())
+            .await
+            .unwrap();
+
+        let file = upload.next_file().await.unwrap().unwrap();
+        assert_eq!(file.file_name(), "id.png");
+        assert_eq!(file.content_type(), "image/png");
+        let chunks: Vec<Bytes> = file.into_stream().try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), PNG);
+
+        assert!(upload.next_file().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_executable_is_rejected() {
+        let mut upload = Upload::from_request(
+            request(&[("setup.exe", "application/x-msdownload", EXE)], None),
+            &(),
+        )
+        .await
+        .unwrap();
+        let err = upload.next_file().await.err().unwrap();
+        assert!(matches!(err, UploadError::DisallowedType { .. }));
+        assert_eq!(
+            ApiError::from(err).status_code,
+            Some(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        );
+
+        // Renaming the executable does not get it past content sniffing
+        let mut upload = Upload::from_request(request(&[("id.png", "image/png", EXE)], None), &())
+            .await
+            .unwrap();
+        assert!(matches!(
+            upload.next_file().await.err().unwrap(),
+            UploadError::ContentMismatch { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_size_limits() {
+        let limits = UploadLimits {
+            max_file_size: 8,
+            ..UploadLimits::default()
+        };
+        let mut upload =
+            Upload::from_request(request(&[("id.png", "image/png", PNG)], Some(limits)), &())
+                .await
+                .unwrap();
+
+        assert!(matches!(
+            upload.next_file().await.err().unwrap(),
+            UploadError::FileTooLarge { limit: 8, .. }
+        ));
+    }
+}
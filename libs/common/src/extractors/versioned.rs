@@ -0,0 +1,215 @@
+//! Versioned request bodies
+//!
+//! A route can accept several request shapes as its DTOs evolve.  The client
+//! picks one with the `Accept-Version` header (`2` or `v2`) or a version
+//! segment in the URL (`/v2/auth/login`); without either the oldest
+//! supported version is assumed, so existing clients keep working.
+//!
+//! The target type implements [`VersionedRequest`], listing its versions and
+//! decoding each one, usually by deserializing the version's DTO and
+//! upcasting it with [`decode_as`].  [`Versioned<T>`] then hands the handler
+//! the current shape along with the version that was negotiated.
+
+use std::fmt;
+use std::str::FromStr;
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, OriginalUri, Request},
+    http::HeaderMap,
+};
+use serde::de::DeserializeOwned;
+
+use crate::http::error::ApiError;
+use crate::http::headers::constants::ACCEPT_VERSION;
+
+/// API version number, written `v1`, `v2`, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion(pub u16);
+
+impl ApiVersion {
+    pub const V1: Self = Self(1);
+    pub const V2: Self = Self(2);
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = ApiError;
+
+    /// Accepts `2`, `v2` and `V2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let digits = s
+            .strip_prefix('v')
+            .or_else(|| s.strip_prefix('V'))
+            .unwrap_or(s);
+        digits
+            .parse()
+            .map(Self)
+            .map_err(|_| ApiError::bad_request(format!("Invalid API version '{s}'")))
+    }
+}
+
+/// Request body type with one decoding per supported API version
+pub trait VersionedRequest: Sized {
+    /// Supported versions, oldest first
+    const VERSIONS: &'static [ApiVersion];
+
+    /// Decode a body sent as `version`, which is one of [`Self::VERSIONS`]
+    fn decode(version: ApiVersion, body: &[u8]) -> Result<Self, serde_json::Error>;
+}
+
+/// Deserialize `body` as the DTO `D` and upcast it into `T`
+pub fn decode_as<D, T>(body: &[u8]) -> Result<T, serde_json::Error>
+where
+    D: DeserializeOwned,
+    T: From<D>,
+{
+    serde_json::from_slice::<D>(body).map(T::from)
+}
+
+/// Version named by the first `v<N>` segment of `path`, if any
+fn path_version(path: &str) -> Option<ApiVersion> {
+    path.split('/')
+        .find(|segment| {
+            segment.len() > 1
+                && segment.starts_with('v')
+                && segment[1..].bytes().all(|b| b.is_ascii_digit())
+        })
+        .and_then(|segment| segment.parse().ok())
+}
+
+/// Pick the version a request asks for out of `supported` (oldest first)
+///
+/// `Accept-Version` and the URL must agree when both are present; with
+/// neither the oldest supported version is used.
+pub fn negotiate_version(
+    headers: &HeaderMap,
+    path: &str,
+    supported: &[ApiVersion],
+) -> Result<ApiVersion, ApiError> {
+    let from_header = headers
+        .get(ACCEPT_VERSION)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| ApiError::bad_request("Invalid Accept-Version header"))
+                .and_then(str::parse::<ApiVersion>)
+        })
+        .transpose()?;
+    let from_path = path_version(path);
+
+    let requested = match (from_header, from_path) {
+        (Some(header), Some(url)) if header != url => {
+            return Err(ApiError::bad_request(format!(
+                "Accept-Version {header} conflicts with URL version {url}"
+            )));
+        }
+        (Some(version), _) | (None, Some(version)) => version,
+        (None, None) => {
+            return supported
+                .first()
+                .copied()
+                .ok_or_else(|| ApiError::internal("route declares no API versions"));
+        }
+    };
+
+    if supported.contains(&requested) {
+        Ok(requested)
+    } else {
+        let supported: Vec<String> = supported.iter().map(ApiVersion::to_string).collect();
+        Err(ApiError::bad_request(format!(
+            "API version {requested} is not supported; use one of {}",
+            supported.join(", ")
+        )))
+    }
+}
+
+/// JSON body decoded according to the negotiated API version
+#[derive(Debug, Clone)]
+pub struct Versioned<T> {
+    pub version: ApiVersion,
+    pub body: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn into_inner(self) -> T {
+        self.body
+    }
+}
+
+impl<S, T> FromRequest<S> for Versioned<T>
+where
+    S: Send + Sync,
+    T: VersionedRequest,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        // Nested routers strip their prefix from the URI; the version segment
+        // may be part of it
+        let path = req.extensions().get::<OriginalUri>().map_or_else(
+            || req.uri().path().to_string(),
+            |uri| uri.path().to_string(),
+        );
+        let version = negotiate_version(req.headers(), &path, T::VERSIONS)?;
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|_| ApiError::bad_request("Failed to read request body"))?;
+        let body = T::decode(version, &bytes).map_err(|e| {
+            ApiError::validation_error(format!("Invalid {version} request body: {e}"))
+        })?;
+
+        Ok(Self { version, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: &[ApiVersion] = &[ApiVersion::V1, ApiVersion::V2];
+
+    fn headers(version: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_VERSION, version.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_negotiates_from_header_then_url_then_default() {
+        let none = HeaderMap::new();
+        assert_eq!(
+            negotiate_version(&headers("2"), "/auth/login", SUPPORTED).unwrap(),
+            ApiVersion::V2
+        );
+        assert_eq!(
+            negotiate_version(&none, "/api/v2/auth/login", SUPPORTED).unwrap(),
+            ApiVersion::V2
+        );
+        assert_eq!(
+            negotiate_version(&none, "/auth/login", SUPPORTED).unwrap(),
+            ApiVersion::V1
+        );
+        // Not a version segment
+        assert_eq!(
+            negotiate_version(&none, "/videos/v/1", SUPPORTED).unwrap(),
+            ApiVersion::V1
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsupported_and_conflicting_versions() {
+        let none = HeaderMap::new();
+        assert!(negotiate_version(&headers("v3"), "/auth/login", SUPPORTED).is_err());
+        assert!(negotiate_version(&none, "/v9/auth/login", SUPPORTED).is_err());
+        assert!(negotiate_version(&headers("v1"), "/v2/auth/login", SUPPORTED).is_err());
+        assert!(negotiate_version(&headers("latest"), "/auth/login", SUPPORTED).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Feature flags with request-scoped overrides
+//!
+//! [`FeatureFlags`] holds the service-wide flag values.  A request-scoped
+//! view created with [`FeatureFlags::with_overrides`] answers
+//! [`FeatureFlags::is_enabled`] from its [`FeatureOverrides`] first, which is
+//! how trusted internal callers test a flag in production without flipping
+//! it for everyone (see the `feature_overrides` middleware).
+//!
+//! ```rust
+//! use common::feature_flags::{FeatureFlags, FeatureOverrides};
+//!
+//! let flags = FeatureFlags::new().with_flag("instant_payouts", false);
+//! let scoped = flags.with_overrides("instant_payouts=on".parse().unwrap());
+//! assert!(!flags.is_enabled("instant_payouts"));
+//! assert!(scoped.is_enabled("instant_payouts"));
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Per-request flag values that take precedence over the defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureOverrides(HashMap<String, bool>);
+
+impl FeatureOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, flag: impl Into<String>, enabled: bool) -> Self {
+        self.0.insert(flag.into(), enabled);
+        self
+    }
+
+    pub fn get(&self, flag: &str) -> Option<bool> {
+        self.0.get(flag).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for FeatureOverrides {
+    type Err = String;
+
+    /// Parse `flag=on,other=off`; a bare flag name means `on`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (flag, value) = entry.split_once('=').unwrap_or((entry, "on"));
+                let enabled = match value.trim().to_ascii_lowercase().as_str() {
+                    "on" | "true" | "1" => true,
+                    "off" | "false" | "0" => false,
+                    other => return Err(format!("invalid value '{other}' for flag '{flag}'")),
+                };
+                Ok((flag.trim().to_string(), enabled))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map(Self)
+    }
+}
+
+/// Feature flag values, optionally scoped to a request
+#[derive(Debug, Clone, Default)]
+pub struct FeatureFlags {
+    defaults: Arc<HashMap<String, bool>>,
+    overrides: Option<Arc<FeatureOverrides>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a flag's service-wide value
+    pub fn with_flag(mut self, flag: impl Into<String>, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.defaults).insert(flag.into(), enabled);
+        self
+    }
+
+    /// View of these flags that consults `overrides` first
+    pub fn with_overrides(&self, overrides: FeatureOverrides) -> Self {
+        Self {
+            defaults: self.defaults.clone(),
+            overrides: Some(Arc::new(overrides)),
+        }
+    }
+
+    /// Whether `flag` is on; unknown flags are off
+    pub fn is_enabled(&self, flag: &str) -> bool {
+        self.overrides
+            .as_ref()
+            .and_then(|overrides| overrides.get(flag))
+            .or_else(|| self.defaults.get(flag).copied())
+            .unwrap_or(false)
+    }
+
+    /// Whether this view carries request-scoped overrides
+    pub fn is_overridden(&self) -> bool {
+        self.overrides
+            .as_ref()
+            .is_some_and(|overrides| !overrides.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_parse_and_take_precedence() {
+        let overrides: FeatureOverrides = "new_checkout=off, risk_v2 ,payouts=1".parse().unwrap();
+        assert_eq!(overrides.get("new_checkout"), Some(false));
+        assert_eq!(overrides.get("risk_v2"), Some(true));
+        assert!("payouts=maybe".parse::<FeatureOverrides>().is_err());
+
+        let flags = FeatureFlags::new()
+            .with_flag("new_checkout", true)
+            .with_flag("search_v2", true);
+        let scoped = flags.with_overrides(overrides);
+        assert!(!scoped.is_enabled("new_checkout"));
+        assert!(scoped.is_enabled("risk_v2"));
+        assert!(scoped.is_enabled("search_v2"));
+        assert!(flags.is_enabled("new_checkout"));
+        assert!(!flags.is_enabled("risk_v2"));
+    }
+}
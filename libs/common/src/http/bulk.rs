@@ -0,0 +1,156 @@
+//! Partial-success results for bulk operations
+//!
+//! Bulk endpoints (suspend many users, approve many verifications) keep
+//! going when a single item fails and report every outcome.  The response
+//! status tells clients at a glance how it went: `200` when every item
+//! succeeded, `207 Multi-Status` on partial success and `422` when nothing
+//! succeeded.
+
+use std::future::Future;
+
+use axum::http::StatusCode;
+use error::http::ApiError;
+use serde::Serialize;
+
+use super::response::ApiResponse;
+
+/// An item that failed during a bulk operation
+#[derive(Debug, Serialize, Clone)]
+pub struct BulkFailure {
+    pub id: String,
+    pub error: ApiError,
+}
+
+/// Per-item outcome of a bulk operation
+#[derive(Debug, Serialize, Clone)]
+pub struct BulkResult<T> {
+    pub succeeded: Vec<T>,
+    pub failed: Vec<BulkFailure>,
+}
+
+impl<T> Default for BulkResult<T> {
+    fn default() -> Self {
+        Self {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+}
+
+impl<T> BulkResult<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `op` for every id in order, recording failures instead of
+    /// stopping at the first one
+    pub async fn process<I, Id, F, Fut>(ids: I, mut op: F) -> Self
+    where
+        I: IntoIterator<Item = Id>,
+        Id: ToString,
+        F: FnMut(Id) -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let mut result = Self::new();
+        for id in ids {
+            let key = id.to_string();
+            match op(id).await {
+                Ok(value) => result.push_success(value),
+                Err(error) => result.push_failure(key, error),
+            }
+        }
+        result
+    }
+
+    pub fn push_success(&mut self, value: T) {
+        self.succeeded.push(value);
+    }
+
+    pub fn push_failure(&mut self, id: impl Into<String>, error: ApiError) {
+        self.failed.push(BulkFailure {
+            id: id.into(),
+            error,
+        });
+    }
+
+    pub fn total(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    /// Some items succeeded and some failed
+    pub fn is_partial(&self) -> bool {
+        !self.succeeded.is_empty() && !self.failed.is_empty()
+    }
+
+    /// HTTP status summarising the outcome
+    pub fn status(&self) -> StatusCode {
+        if self.failed.is_empty() {
+            StatusCode::OK
+        } else if self.succeeded.is_empty() {
+            StatusCode::UNPROCESSABLE_ENTITY
+        } else {
+            StatusCode::MULTI_STATUS
+        }
+    }
+
+    /// Wrap in the standard response envelope.
+    ///
+    /// `success` is true if at least one item succeeded (or there were none).
+    pub fn into_api_response(self, action: &str) -> ApiResponse<Self> {
+        let status = self.status();
+        let message = format!(
+            "{action}: {} of {} succeeded",
+            self.succeeded.len(),
+            self.total()
+        );
+        let mut response = ApiResponse::success(message, self).with_status(status);
+        response.success = status != StatusCode::UNPROCESSABLE_ENTITY;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::body_json;
+    use axum::response::IntoResponse;
+    use serde_json::Value;
+
+    async fn suspend(id: &str) -> Result<String, ApiError> {
+        uuid::Uuid::parse_str(id)
+            .map(|_| id.to_string())
+            .map_err(|_| ApiError::bad_request(format!("'{id}' is not a valid user id")))
+    }
+
+    #[tokio::test]
+    async fn test_bulk_suspend_with_invalid_id_is_partial_success() {
+        let ids = [
+            "6f1c2a9e-3d4b-4c5a-9e8f-1a2b3c4d5e6f",
+            "not-a-user-id",
+            "0b7e6d5c-4b3a-4928-8776-655443322110",
+        ];
+
+        let result = BulkResult::process(ids, suspend).await;
+        assert!(result.is_partial());
+        assert_eq!(result.succeeded.len(), 2);
+        assert_eq!(result.failed[0].id, "not-a-user-id");
+
+        let response = result.into_api_response("suspend users").into_response();
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let body = body_json(response).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["message"], "suspend users: 2 of 3 succeeded");
+        assert_eq!(body["data"]["failed"][0]["id"], "not-a-user-id");
+        assert_eq!(body["data"]["failed"][0]["error"]["code"], "BAD_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn test_bulk_status_reflects_outcome() {
+        let ok = BulkResult::process(["6f1c2a9e-3d4b-4c5a-9e8f-1a2b3c4d5e6f"], suspend).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+
+        let failed = BulkResult::process(["nope"], suspend).await;
+        assert_eq!(failed.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(!failed.into_api_response("suspend users").success);
+    }
+}
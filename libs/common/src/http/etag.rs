@@ -0,0 +1,104 @@
+//! Entity tags for conditional requests
+//!
+//! Handlers return [`ETagged`] instead of a bare [`ApiResponse`] to attach
+//! an `ETag` derived from the response data.  Pair it with
+//! [`conditional_get_middleware`](crate::middleware::conditional_get_middleware)
+//! so polling clients that send a matching `If-None-Match` get an empty
+//! `304 Not Modified` instead of the full body.
+
+use axum::{
+    http::{HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::response::ApiResponse;
+
+/// Response whose `ETag` is computed from its data
+///
+/// Only `data` is hashed, so per-response `meta` such as timestamps does
+/// not defeat caching.
+#[derive(Debug, Clone)]
+pub struct ETagged<T> {
+    response: ApiResponse<T>,
+    version: Option<String>,
+}
+
+impl<T: Serialize> ETagged<T> {
+    pub fn new(response: ApiResponse<T>) -> Self {
+        Self {
+            response,
+            version: None,
+        }
+    }
+
+    /// Derive the tag from an explicit version (e.g. `updated_at`) instead
+    /// of hashing the data
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Strong entity tag, including the surrounding quotes
+    pub fn etag(&self) -> String {
+        match &self.version {
+            Some(version) => compute_etag(version.as_bytes()),
+            None => compute_etag(&serde_json::to_vec(&self.response.data).unwrap_or_default()),
+        }
+    }
+}
+
+impl<T: Serialize> From<ApiResponse<T>> for ETagged<T> {
+    fn from(response: ApiResponse<T>) -> Self {
+        Self::new(response)
+    }
+}
+
+impl<T: Serialize> IntoResponse for ETagged<T> {
+    fn into_response(self) -> Response {
+        let etag = self.etag();
+        let mut response = self.response.into_response();
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+        response
+    }
+}
+
+/// Strong entity tag for `bytes`
+pub fn compute_etag(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    format!("\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Whether an `If-None-Match` header value matches `etag`
+///
+/// Uses the weak comparison RFC 9110 prescribes for `If-None-Match`:
+/// `W/` prefixes are ignored and `*` matches any current representation.
+pub fn if_none_match(header_value: &str, etag: &str) -> bool {
+    let etag = strip_weak(etag.trim());
+    header_value
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || strip_weak(candidate) == etag)
+}
+
+fn strip_weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match_comparison() {
+        let etag = compute_etag(b"order-1:pending");
+        assert!(if_none_match(&etag, &etag));
+        assert!(if_none_match(&format!("\"stale\", W/{etag}"), &etag));
+        assert!(if_none_match("*", &etag));
+        assert!(!if_none_match("\"stale\"", &etag));
+        assert_ne!(etag, compute_etag(b"order-1:shipped"));
+    }
+}
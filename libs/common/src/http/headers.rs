@@ -111,9 +111,13 @@ pub mod constants {
     pub const REQUEST_ID: &str = "x-request-id";
     pub const CORRELATION_ID: &str = "x-correlation-id";
     pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+    pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+    pub const API_KEY: &str = "x-api-key";
     pub const RATE_LIMIT_LIMIT: &str = "x-ratelimit-limit";
     pub const RATE_LIMIT_REMAINING: &str = "x-ratelimit-remaining";
     pub const RATE_LIMIT_RESET: &str = "x-ratelimit-reset";
+    pub const FEATURE_OVERRIDES: &str = "x-feature-overrides";
+    pub const ACCEPT_VERSION: &str = "accept-version";
 }
 
 // #[cfg(test)]
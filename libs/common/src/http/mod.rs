@@ -3,10 +3,13 @@
 //! This module provides common HTTP utilities used across all services.
 //! Available when the `http` feature is enabled.
 
+pub mod bulk;
 pub mod error;
+pub mod etag;
 pub mod fallback;
 pub mod headers;
 pub mod health;
 pub mod meta;
 pub mod pagination;
 pub mod response;
+pub mod streaming;
@@ -0,0 +1,142 @@
+//! Streamed JSON responses
+//!
+//! Exports over large tables shouldn't collect every row before answering.
+//! [`Ndjson`] and [`JsonArray`] wrap a stream of rows and serialize each one
+//! as it arrives, so memory use stays flat however many rows there are:
+//!
+//! ```rust,ignore
+//! async fn export_payments(State(db): State<DbPool>) -> Ndjson<BoxStream<'static, AppResult<Payment>>> {
+//!     Ndjson(db.stream(Filter::new().order_by("id")))
+//! }
+//! ```
+//!
+//! The status and headers are sent before the first row is read, so an
+//! error part-way through can't become an error response.  Instead the
+//! error is logged and the body is cut off, which clients see as a failed
+//! transfer rather than a short but complete export.
+
+use axum::body::{Body, Bytes};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use futures::{Stream, StreamExt, stream};
+use serde::Serialize;
+
+/// Content type of newline-delimited JSON
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Response writing one JSON document per line
+#[derive(Debug)]
+pub struct Ndjson<S>(pub S);
+
+/// Response writing the items as a single JSON array
+#[derive(Debug)]
+pub struct JsonArray<S>(pub S);
+
+impl<S, T, E> IntoResponse for Ndjson<S>
+where
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Serialize,
+    E: Into<BoxError>,
+{
+    fn into_response(self) -> Response {
+        let body = self.0.map(|item| {
+            let mut line = serde_json::to_vec(&item.map_err(abort)?).map_err(abort)?;
+            line.push(b'\n');
+            Ok::<_, BoxError>(Bytes::from(line))
+        });
+        (
+            [(CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+            Body::from_stream(body),
+        )
+            .into_response()
+    }
+}
+
+impl<S, T, E> IntoResponse for JsonArray<S>
+where
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Serialize,
+    E: Into<BoxError>,
+{
+    fn into_response(self) -> Response {
+        let items = self.0.enumerate().map(|(i, item)| {
+            let mut chunk = if i == 0 { Vec::new() } else { b",".to_vec() };
+            serde_json::to_writer(&mut chunk, &item.map_err(abort)?).map_err(abort)?;
+            Ok::<_, BoxError>(Bytes::from(chunk))
+        });
+        let body = stream::once(async { Ok(Bytes::from_static(b"[")) })
+            .chain(items)
+            .chain(stream::once(async { Ok(Bytes::from_static(b"]")) }));
+        (
+            [(CONTENT_TYPE, "application/json")],
+            Body::from_stream(body),
+        )
+            .into_response()
+    }
+}
+
+/// Log why a streamed body is being cut off
+fn abort(e: impl Into<BoxError>) -> BoxError {
+    let e = e.into();
+    tracing::error!(error = %e, "streamed response aborted");
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{body_bytes, body_json, body_text};
+    use axum::body::to_bytes;
+    use error::AppError;
+    use serde_json::Value;
+
+    #[derive(Serialize)]
+    struct Payment {
+        id: u32,
+        amount: u64,
+    }
+
+    fn payments(n: u32) -> impl Stream<Item = Result<Payment, AppError>> + Send {
+        stream::iter((1..=n).map(|id| {
+            Ok(Payment {
+                id,
+                amount: u64::from(id) * 1_000,
+            })
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_writes_one_parseable_line_per_row() {
+        let response = Ndjson(payments(5)).into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], NDJSON_CONTENT_TYPE);
+
+        let body = body_text(response).await;
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line["id"], i as u64 + 1);
+            assert_eq!(line["amount"], (i as u64 + 1) * 1_000);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_array_and_mid_stream_error() {
+        let items = body_json(JsonArray(payments(3))).await;
+        assert_eq!(items.as_array().unwrap().len(), 3);
+        assert_eq!(body_bytes(JsonArray(payments(0))).await, "[]");
+
+        let failing = payments(2).chain(stream::once(async {
+            Err(AppError::internal("connection reset"))
+        }));
+        assert!(
+            to_bytes(Ndjson(failing).into_response().into_body(), usize::MAX)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -11,9 +11,15 @@
 //! - `value_objects` - Shared value objects (Email, Phone, Money, etc.)
 //! - `validation` - Validation rules and request validation
 //! - `security` - Security utilities (hashing, secrets, CSRF)
+//! - `feature_flags` - Feature flags with request-scoped overrides
+//! - `events` - Domain event envelopes with correlation and causation ids
 //! - `utils` - General utilities
 //! - `observability` - Observability utilities (metrics, tracing)
 //! - `time` - Time utilities
+//! - `testing` - Test helpers (requires `testing` feature)
+
+// Lets `#[derive(Redact)]` output, which names `::common`, compile here too
+extern crate self as common;
 
 // HTTP module - requires axum
 #[cfg(feature = "http")]
@@ -36,6 +42,12 @@ pub mod validation;
 // Security module
 pub mod security;
 
+// Feature flags module
+pub mod feature_flags;
+
+// Events module
+pub mod events;
+
 // Utils module
 pub mod utils;
 
@@ -44,3 +56,7 @@ pub mod observability;
 
 // Time module
 pub mod time;
+
+// Test helpers - for tests here and, with `testing`, in other crates
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
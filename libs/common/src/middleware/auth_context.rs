@@ -2,11 +2,25 @@
 //!
 //! Extracts authentication context from bearer tokens and inserts it into
 //! request extensions for use in handlers.
+//!
+//! Routes that need a scope on the caller's key or token are guarded with
+//! [`require_scope`], which answers `403` naming the missing scope:
+//!
+//! ```rust,ignore
+//! Router::new()
+//!     .route("/orders", post(create_order))
+//!     .route_layer(middleware::from_fn(require_scope(Scope::new("orders:write")?)));
+//! ```
 
+use crate::security::JwtClaims;
+use crate::value_objects::Scope;
 use axum::http::StatusCode;
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
 use axum::{extract::Request, middleware};
+use error::core::AuthErrorCode;
+use error::http::ApiError;
+use error::{AppError, AppResult};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -21,6 +35,13 @@ pub struct AuthContext {
     pub scopes: Vec<String>,
     /// Token issuer
     pub issuer: Option<String>,
+    /// Staff member acting as the user, when the token is an impersonation
+    /// token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonator: Option<String>,
+    /// Tenant the caller acts within; repositories scope queries to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
 }
 
 impl AuthContext {
@@ -31,6 +52,20 @@ impl AuthContext {
             subject: None,
             scopes: Vec::new(),
             issuer: None,
+            impersonator: None,
+            tenant_id: None,
+        }
+    }
+
+    /// Context of the caller a verified token was issued to
+    pub fn from_claims(claims: &JwtClaims) -> Self {
+        Self {
+            user_id: claims.sub.clone(),
+            subject: Some(claims.sub.clone()),
+            scopes: claims.scopes.clone(),
+            issuer: Some(claims.iss.clone()),
+            impersonator: claims.impersonator().map(str::to_string),
+            tenant_id: claims.tenant_id.clone(),
         }
     }
 
@@ -52,10 +87,52 @@ impl AuthContext {
         self
     }
 
+    /// Set the tenant the caller acts within
+    pub fn with_tenant(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    /// Mark the caller as `impersonator` acting as the user
+    pub fn with_impersonator(mut self, impersonator: impl Into<String>) -> Self {
+        self.impersonator = Some(impersonator.into());
+        self
+    }
+
+    /// Whether someone else is acting as the user
+    pub fn is_impersonated(&self) -> bool {
+        self.impersonator.is_some()
+    }
+
+    /// Fail with `InsufficientPermissions` if the caller is impersonating
+    /// the user, for actions only the user themselves may take (changing
+    /// their password, MFA or email)
+    pub fn deny_if_impersonated(&self, action: &str) -> AppResult<()> {
+        if self.is_impersonated() {
+            return Err(AppError::authz(
+                format!("{action} is not allowed during impersonation"),
+                AuthErrorCode::InsufficientPermissions,
+            ));
+        }
+        Ok(())
+    }
+
     /// Check if context has a specific scope
     pub fn has_scope(&self, scope: &str) -> bool {
         self.scopes.iter().any(|s| s == scope)
     }
+
+    /// Fail with `InsufficientPermissions` unless the caller was granted
+    /// `scope`
+    pub fn require_scope(&self, scope: &Scope) -> AppResult<()> {
+        if self.has_scope(scope.as_str()) {
+            return Ok(());
+        }
+        Err(AppError::authz(
+            format!("missing scope '{scope}'"),
+            AuthErrorCode::InsufficientPermissions,
+        ))
+    }
 }
 
 /// Middleware for handling authentication context
@@ -85,3 +162,93 @@ pub async fn auth_context(mut req: Request, next: Next) -> Result<Response, Stat
 pub fn auth_context_layer() -> impl Clone {
     middleware::from_fn::<_, ()>(|req, next| Box::pin(auth_context(req, next)))
 }
+
+/// Route guard rejecting callers whose [`AuthContext`] lacks `scope`
+///
+/// Unauthenticated requests get `401`; authenticated ones without the scope
+/// get `403` with the scope in `details.missing_scope`.  Install it with
+/// `route_layer` inside the authentication middleware.
+pub fn require_scope(
+    scope: Scope,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| {
+        let scope = scope.clone();
+        Box::pin(async move {
+            let Some(context) = req.extensions().get::<Arc<AuthContext>>() else {
+                return ApiError::unauthorized("Authentication required").into_response();
+            };
+            if context.require_scope(&scope).is_err() {
+                return ApiError::forbidden_with_code(
+                    format!("missing scope '{scope}'"),
+                    AuthErrorCode::InsufficientPermissions,
+                )
+                .with_details(serde_json::json!({
+                    "auth_code": format!("{:?}", AuthErrorCode::InsufficientPermissions),
+                    "missing_scope": scope,
+                }))
+                .into_response();
+            }
+            next.run(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::JwtService;
+    use crate::testing::body_json;
+    use axum::{Router, body::Body, routing::post};
+    use tower::ServiceExt;
+
+    /// Stands in for the auth middleware: verifies the bearer token and
+    /// stores the caller's context
+    async fn authenticate(mut req: Request, next: Next, jwt: Arc<JwtService>) -> Response {
+        let token = req
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if let Some(Ok(claims)) = token.map(|token| jwt.verify_access(token, None)) {
+            req.extensions_mut()
+                .insert(Arc::new(AuthContext::from_claims(&claims)));
+        }
+        next.run(req).await
+    }
+
+    #[tokio::test]
+    async fn test_token_lacking_orders_write_is_forbidden_from_write_endpoint() {
+        let jwt = Arc::new(JwtService::new("test-secret", "trustflow", "partners", 900, 86_400));
+        let app = Router::new()
+            .route("/orders", post(|| async { StatusCode::CREATED }))
+            .route_layer(middleware::from_fn(require_scope(
+                Scope::new("orders:write").unwrap(),
+            )))
+            .layer(middleware::from_fn({
+                let jwt = jwt.clone();
+                move |req, next| authenticate(req, next, jwt.clone())
+            }));
+        let create = |token: Option<String>| {
+            let mut request = Request::post("/orders");
+            if let Some(token) = token {
+                request = request.header("authorization", format!("Bearer {token}"));
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let read_only = jwt.issue_access("partner-1", vec!["orders:read".into()]).unwrap();
+        let response = app.clone().oneshot(create(Some(read_only))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["details"]["missing_scope"], "orders:write", "{body}");
+
+        let writer = jwt
+            .issue_access("partner-1", vec!["orders:read".into(), "orders:write".into()])
+            .unwrap();
+        let response = app.clone().oneshot(create(Some(writer))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app.oneshot(create(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
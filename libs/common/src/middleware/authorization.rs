@@ -0,0 +1,230 @@
+//! Request-scoped authorization checks
+//!
+//! Handlers and extractors often check several permissions, or the caller's
+//! verification level, in one request, and each check would otherwise go to
+//! Redis or the database.  The middleware puts an [`Authorizer`] for the
+//! authenticated caller into the request extensions; it asks the
+//! [`PermissionSource`] at most once per permission (and once for the
+//! verification level) and answers repeated checks from memory.  The memo
+//! lives as long as the request, so changes to roles take effect on the next
+//! one.  Install it inside the authentication middleware so the
+//! [`AuthContext`] is already set.
+//!
+//! ```rust,ignore
+//! async fn approve_payout(authz: Authorizer) -> AppResult<Json<Payout>> {
+//!     authz.require_permission("payouts:approve").await?;
+//!     authz.require_level(2).await?;
+//!     // ...
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use error::core::AuthErrorCode;
+use error::{AppError, AppResult};
+use tokio::sync::OnceCell;
+
+use super::auth_context::AuthContext;
+
+/// Where permissions and verification levels are looked up
+#[async_trait]
+pub trait PermissionSource: Send + Sync {
+    /// Whether `user_id` has been granted `permission`
+    async fn has_permission(&self, user_id: &str, permission: &str) -> AppResult<bool>;
+
+    /// Identity verification level of `user_id`, from 0 (contact details
+    /// only) upwards
+    async fn verification_level(&self, user_id: &str) -> AppResult<u8>;
+}
+
+struct AuthorizerInner {
+    context: Arc<AuthContext>,
+    source: Arc<dyn PermissionSource>,
+    permissions: Mutex<HashMap<String, Arc<OnceCell<bool>>>>,
+    level: OnceCell<u8>,
+}
+
+/// Authorization checks for the current caller, memoized for the request
+///
+/// Cheap to clone; clones share the memo.  Failed lookups are not memoized,
+/// so the next check retries them.
+#[derive(Clone)]
+pub struct Authorizer {
+    inner: Arc<AuthorizerInner>,
+}
+
+impl Authorizer {
+    pub fn new(context: Arc<AuthContext>, source: Arc<dyn PermissionSource>) -> Self {
+        Self {
+            inner: Arc::new(AuthorizerInner {
+                context,
+                source,
+                permissions: Mutex::new(HashMap::new()),
+                level: OnceCell::new(),
+            }),
+        }
+    }
+
+    /// Caller the checks are made for
+    pub fn context(&self) -> &AuthContext {
+        &self.inner.context
+    }
+
+    /// Whether the caller has `permission`
+    pub async fn has_permission(&self, permission: &str) -> AppResult<bool> {
+        let cell = self
+            .inner
+            .permissions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(permission.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_try_init(|| {
+            self.inner
+                .source
+                .has_permission(&self.inner.context.user_id, permission)
+        })
+        .await
+        .copied()
+    }
+
+    /// Fail with `InsufficientPermissions` unless the caller has `permission`
+    pub async fn require_permission(&self, permission: &str) -> AppResult<()> {
+        if self.has_permission(permission).await? {
+            Ok(())
+        } else {
+            Err(AppError::auth(
+                format!("missing permission '{permission}'"),
+                AuthErrorCode::InsufficientPermissions,
+            ))
+        }
+    }
+
+    /// Caller's verification level
+    pub async fn verification_level(&self) -> AppResult<u8> {
+        self.inner
+            .level
+            .get_or_try_init(|| {
+                self.inner
+                    .source
+                    .verification_level(&self.inner.context.user_id)
+            })
+            .await
+            .copied()
+    }
+
+    /// Fail with `InsufficientPermissions` unless the caller is verified to
+    /// at least `minimum`
+    pub async fn require_level(&self, minimum: u8) -> AppResult<()> {
+        if self.verification_level().await? >= minimum {
+            Ok(())
+        } else {
+            Err(AppError::auth(
+                format!("verification level {minimum} required"),
+                AuthErrorCode::InsufficientPermissions,
+            ))
+        }
+    }
+}
+
+/// Middleware giving each authenticated request its own [`Authorizer`]
+pub async fn authorization_middleware(
+    mut req: Request,
+    next: Next,
+    source: Arc<dyn PermissionSource>,
+) -> Response {
+    if let Some(context) = req.extensions().get::<Arc<AuthContext>>().cloned() {
+        req.extensions_mut()
+            .insert(Authorizer::new(context, source));
+    }
+    next.run(req).await
+}
+
+/// Create authorization middleware backed by `source`
+pub fn make_authorization_middleware(
+    source: Arc<dyn PermissionSource>,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| Box::pin(authorization_middleware(req, next, source.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, middleware, routing::get};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct CountingSource {
+        permission_lookups: AtomicUsize,
+        level_lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PermissionSource for CountingSource {
+        async fn has_permission(&self, _user_id: &str, permission: &str) -> AppResult<bool> {
+            self.permission_lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(permission == "payouts:approve")
+        }
+
+        async fn verification_level(&self, _user_id: &str) -> AppResult<u8> {
+            self.level_lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(2)
+        }
+    }
+
+    async fn approve(authz: Authorizer) -> StatusCode {
+        for _ in 0..3 {
+            if authz.require_permission("payouts:approve").await.is_err()
+                || authz.require_level(2).await.is_err()
+            {
+                return StatusCode::FORBIDDEN;
+            }
+        }
+        if authz.has_permission("payouts:delete").await.unwrap() {
+            return StatusCode::FORBIDDEN;
+        }
+        StatusCode::OK
+    }
+
+    #[tokio::test]
+    async fn test_repeated_checks_query_the_source_once_per_request() {
+        let source = Arc::new(CountingSource::default());
+        let app = Router::new()
+            .route("/payouts/approve", get(approve))
+            .layer(middleware::from_fn(make_authorization_middleware(
+                source.clone(),
+            )))
+            // Stands in for the auth middleware, which runs first
+            .layer(middleware::from_fn(|mut req: Request, next: Next| {
+                req.extensions_mut()
+                    .insert(Arc::new(AuthContext::new("ops-1")));
+                next.run(req)
+            }));
+
+        for request in 1..=2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get("/payouts/approve")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            // Two distinct permissions and the level, per request
+            assert_eq!(
+                source.permission_lookups.load(Ordering::SeqCst),
+                2 * request
+            );
+            assert_eq!(source.level_lookups.load(Ordering::SeqCst), request);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Reading bodies into memory without losing them
+//!
+//! Middleware that rewrites or inspects bodies needs them whole, but must
+//! not swallow a body it gives up on.  [`buffer_body`] hands such a body
+//! back, replaying what it had already read.
+
+use axum::body::{Body, Bytes, HttpBody};
+use futures::{Stream, StreamExt, stream};
+
+/// What [`buffer_body`] made of a body
+pub(crate) enum BufferedBody {
+    /// The whole body
+    Complete(Bytes),
+    /// The body is longer than the limit, returned as it would have been
+    TooLarge(Body),
+    /// Reading failed; the returned body yields what was read, then the
+    /// error
+    Failed(Body),
+}
+
+/// Read `body` into memory if it is no longer than `limit` bytes
+///
+/// Works on bodies of unknown length (chunked requests, streams) too: they
+/// are read until they end or pass the limit.
+pub(crate) async fn buffer_body(body: Body, limit: usize) -> BufferedBody {
+    if body.size_hint().lower() > limit as u64 {
+        return BufferedBody::TooLarge(body);
+    }
+
+    let mut rest = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut len = 0;
+    while let Some(chunk) = rest.next().await {
+        match chunk {
+            Ok(chunk) => {
+                len += chunk.len();
+                chunks.push(chunk);
+                if len > limit {
+                    return BufferedBody::TooLarge(replay(chunks, rest));
+                }
+            }
+            Err(e) => return BufferedBody::Failed(replay(chunks, stream::once(async { Err(e) }))),
+        }
+    }
+    match chunks.len() {
+        1 => BufferedBody::Complete(chunks.remove(0)),
+        _ => BufferedBody::Complete(Bytes::from(chunks.concat())),
+    }
+}
+
+/// Body yielding `chunks`, then whatever `rest` yields
+fn replay<S>(chunks: Vec<Bytes>, rest: S) -> Body
+where
+    S: Stream<Item = Result<Bytes, axum::Error>> + Send + 'static,
+{
+    Body::from_stream(stream::iter(chunks.into_iter().map(Ok)).chain(rest))
+}
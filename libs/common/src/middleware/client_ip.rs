@@ -0,0 +1,254 @@
+//! Client IP resolution behind trusted proxies
+//!
+//! `X-Forwarded-For` is written by the client first and appended to by each
+//! proxy on the way in, so only the entries added by our own proxies can be
+//! believed.  [`TrustedProxies`] says which those are: either how many proxy
+//! hops sit in front of the service, or the networks they live in.  The
+//! client is the rightmost address that isn't a trusted proxy; anything to
+//! its left was supplied by the client and is ignored.
+//!
+//! [`client_ip_middleware`] resolves the address once per request and
+//! stores it as a [`ClientIp`] extension, which rate limiting, request
+//! logging and handlers (`Extension<ClientIp>`) read instead of parsing the
+//! header themselves.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use axum::extract::{ConnectInfo, Request};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use ipnet::IpNet;
+
+/// Header proxies append the address they received a request from to
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Proxies whose `X-Forwarded-For` entries are believed
+///
+/// Parsed from a hop count (`"2"`) or a comma-separated list of networks
+/// and addresses (`"10.0.0.0/8, 192.0.2.10"`).  The default trusts no
+/// proxy: `X-Forwarded-For` is ignored and the peer address is the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustedProxies {
+    /// This many proxies sit in front of the service, each appending one
+    /// entry
+    Hops(usize),
+    /// Proxies are the peers and entries within these networks
+    Networks(Vec<IpNet>),
+}
+
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        Self::Hops(0)
+    }
+}
+
+impl TrustedProxies {
+    /// Address of the client that sent a request with `headers`, received
+    /// from `peer` when the connection address is known
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        let forwarded = forwarded_for(headers);
+        match self {
+            Self::Hops(0) => peer,
+            // The first proxy appends the client, each later one its
+            // predecessor, so the client is `hops` entries from the right
+            Self::Hops(hops) => match forwarded.len().checked_sub(*hops) {
+                Some(client) => forwarded[client],
+                // Fewer entries than proxies: the request didn't come
+                // through all of them, so none of the entries can be ours
+                None => peer,
+            },
+            Self::Networks(networks) => {
+                let trusted = |ip: &IpAddr| networks.iter().any(|net| net.contains(ip));
+                if let Some(peer) = peer
+                    && !trusted(&peer)
+                {
+                    return Some(peer);
+                }
+                for entry in forwarded.iter().rev() {
+                    match entry {
+                        Some(ip) if trusted(ip) => continue,
+                        // An entry that isn't an address wasn't written by
+                        // a proxy of ours; nothing left of it is reliable
+                        _ => return *entry,
+                    }
+                }
+                forwarded.first().copied().flatten().or(peer)
+            }
+        }
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Self::default());
+        }
+        if let Ok(hops) = s.parse() {
+            return Ok(Self::Hops(hops));
+        }
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| {
+                        format!(
+                            "invalid trusted proxy '{entry}': expected a hop count, \
+                             network or address"
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self::Networks)
+    }
+}
+
+impl fmt::Display for TrustedProxies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hops(hops) => write!(f, "{hops}"),
+            Self::Networks(networks) => {
+                let networks: Vec<_> = networks.iter().map(IpNet::to_string).collect();
+                f.write_str(&networks.join(","))
+            }
+        }
+    }
+}
+
+/// `X-Forwarded-For` entries of every such header, left to right; entries
+/// that aren't addresses are `None`
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.trim().parse().ok())
+        .collect()
+}
+
+/// Resolved address of the client, stored as a request extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Middleware storing the request's [`ClientIp`] when it can be resolved
+pub async fn client_ip_middleware(
+    mut req: Request,
+    next: Next,
+    trusted: TrustedProxies,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = trusted.client_ip(req.headers(), peer) {
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(req).await
+}
+
+/// Create client IP middleware
+pub fn make_client_ip_middleware(
+    trusted: TrustedProxies,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| {
+        let trusted = trusted.clone();
+        Box::pin(client_ip_middleware(req, next, trusted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn forwarded(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR_HEADER, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for_behind_one_trusted_hop() {
+        // The client sent "6.6.6.6"; the load balancer appended its real address
+        let headers = forwarded("6.6.6.6, 203.0.113.7");
+        let peer = Some(ip("10.0.0.1"));
+
+        assert_eq!(
+            TrustedProxies::Hops(1).client_ip(&headers, peer),
+            Some(ip("203.0.113.7"))
+        );
+        let networks: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(networks.client_ip(&headers, peer), Some(ip("203.0.113.7")));
+        // Trusting nothing ignores the header entirely
+        assert_eq!(TrustedProxies::default().client_ip(&headers, peer), peer);
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for_behind_two_trusted_hops() {
+        // CDN appended the client, the load balancer appended the CDN
+        let headers = forwarded("6.6.6.6, 203.0.113.7, 10.1.2.3");
+        let peer = Some(ip("10.0.0.1"));
+
+        assert_eq!(
+            TrustedProxies::Hops(2).client_ip(&headers, peer),
+            Some(ip("203.0.113.7"))
+        );
+        let networks: TrustedProxies = "10.0.0.0/8, 192.0.2.10".parse().unwrap();
+        assert_eq!(networks.client_ip(&headers, peer), Some(ip("203.0.113.7")));
+        // Trusting only one hop would take the CDN for the client
+        assert_eq!(
+            TrustedProxies::Hops(1).client_ip(&headers, peer),
+            Some(ip("10.1.2.3"))
+        );
+        // A peer outside the trusted networks forged the whole header
+        assert_eq!(
+            networks.client_ip(&headers, Some(ip("198.51.100.9"))),
+            Some(ip("198.51.100.9"))
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for_shorter_than_the_trusted_hops_falls_back_to_peer() {
+        // Sent straight to the load balancer, skipping the CDN
+        let headers = forwarded("6.6.6.6");
+        let peer = Some(ip("10.0.0.1"));
+
+        assert_eq!(TrustedProxies::Hops(2).client_ip(&headers, peer), peer);
+        assert_eq!(
+            TrustedProxies::Hops(2).client_ip(&HeaderMap::new(), peer),
+            peer
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxies_parse() {
+        assert_eq!("".parse(), Ok(TrustedProxies::Hops(0)));
+        assert_eq!("2".parse(), Ok(TrustedProxies::Hops(2)));
+        let networks: TrustedProxies = "10.0.0.0/8,192.0.2.10".parse().unwrap();
+        assert_eq!(networks.to_string(), "10.0.0.0/8,192.0.2.10/32");
+        assert!(
+            "10.0.0.0/8,proxy.internal"
+                .parse::<TrustedProxies>()
+                .is_err()
+        );
+    }
+}
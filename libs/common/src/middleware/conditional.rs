@@ -0,0 +1,143 @@
+//! Conditional GET middleware
+//!
+//! Answers `If-None-Match` requests with `304 Not Modified` when the
+//! handler's response carries a matching `ETag`, saving polling clients
+//! from re-downloading unchanged resources.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::http::etag::if_none_match;
+
+/// Headers a `304` must repeat from the `200` it replaces
+const PRESERVED_HEADERS: [header::HeaderName; 4] = [
+    header::ETAG,
+    header::CACHE_CONTROL,
+    header::VARY,
+    header::EXPIRES,
+];
+
+/// Middleware that turns matching conditional GETs into `304 Not Modified`
+///
+/// Handlers opt in by setting an `ETag`, typically by returning
+/// [`ETagged`](crate::http::etag::ETagged).
+///
+/// # Example
+///
+/// ```ignore
+/// use axum::{Router, middleware};
+/// use common::middleware::conditional_get_middleware;
+///
+/// let app = Router::new()
+///     .route("/orders/{id}", get(get_order))
+///     .layer(middleware::from_fn(conditional_get_middleware));
+/// ```
+pub async fn conditional_get_middleware(req: Request, next: Next) -> Response {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return next.run(req).await;
+    }
+
+    let condition = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+
+    let response = next.run(req).await;
+
+    let Some(condition) = condition else {
+        return response;
+    };
+    if !response.status().is_success() {
+        return response;
+    }
+    let matched = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|etag| if_none_match(&condition, etag));
+    if !matched {
+        return response;
+    }
+
+    let mut not_modified = Response::new(Body::empty());
+    *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in PRESERVED_HEADERS {
+        if let Some(value) = response.headers().get(&name) {
+            not_modified.headers_mut().insert(name, value.clone());
+        }
+    }
+    not_modified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{etag::ETagged, response::ApiResponse};
+    use crate::testing::body_bytes;
+    use axum::{Router, extract::State, middleware, routing::get};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    async fn order_status(State(status): State<Arc<Mutex<&'static str>>>) -> ETagged<String> {
+        let status = status.lock().unwrap().to_string();
+        ETagged::new(ApiResponse::success("order status", status))
+    }
+
+    async fn get_order(app: &Router, if_none_match: Option<&str>) -> Response {
+        let mut req = Request::get("/orders/1");
+        if let Some(tag) = if_none_match {
+            req = req.header(header::IF_NONE_MATCH, tag);
+        }
+        app.clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn etag_of(response: &Response) -> String {
+        response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_matching_if_none_match_returns_304() {
+        let status = Arc::new(Mutex::new("pending"));
+        let app = Router::new()
+            .route("/orders/1", get(order_status))
+            .with_state(status)
+            .layer(middleware::from_fn(conditional_get_middleware));
+
+        let first = get_order(&app, None).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = etag_of(&first);
+
+        let cached = get_order(&app, Some(&etag)).await;
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(etag_of(&cached), etag);
+        assert!(body_bytes(cached).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_if_none_match_returns_200_with_new_etag() {
+        let status = Arc::new(Mutex::new("pending"));
+        let app = Router::new()
+            .route("/orders/1", get(order_status))
+            .with_state(status.clone())
+            .layer(middleware::from_fn(conditional_get_middleware));
+
+        let etag = etag_of(&get_order(&app, None).await);
+        *status.lock().unwrap() = "shipped";
+
+        let fresh = get_order(&app, Some(&etag)).await;
+        assert_eq!(fresh.status(), StatusCode::OK);
+        assert_ne!(etag_of(&fresh), etag);
+    }
+}
@@ -0,0 +1,417 @@
+//! Signed session cookie authentication
+//!
+//! Browser clients can authenticate with a signed, `HttpOnly` session cookie
+//! instead of a bearer token.  [`CookieAuthConfig::issue`] builds the cookies
+//! to set on login: the session cookie itself and a readable CSRF cookie
+//! whose token is also bound into the signed session.
+//!
+//! [`cookie_auth_middleware`] validates the session cookie and inserts an
+//! [`AuthContext`] for handlers, which read it with the
+//! [`Authenticated`](crate::extractors::Authenticated) extractor.  Requests
+//! carrying a bearer token are left to bearer auth.  Because browsers attach
+//! cookies to cross-site requests, state-changing methods authenticated by
+//! cookie must echo the CSRF token in the [`CSRF_HEADER`] header.
+
+use std::sync::Arc;
+
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, Method, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use error::http::ApiError;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tracing::debug;
+
+use super::auth_context::AuthContext;
+use crate::security::{CookieSigner, CsrfGenerator, CsrfToken, CsrfValidator};
+
+/// Default session cookie name
+pub const SESSION_COOKIE: &str = "tf_session";
+
+/// Default CSRF cookie name
+pub const CSRF_COOKIE: &str = "tf_csrf";
+
+/// Header state-changing cookie-authenticated requests must echo the CSRF
+/// token in
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// `SameSite` cookie attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameSite {
+    Strict,
+    #[default]
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// Why a session cookie was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieAuthError {
+    /// Bad signature or unreadable payload
+    Invalid,
+    /// Signed session has expired
+    Expired,
+    /// Missing or mismatched CSRF token on a state-changing request
+    CsrfMismatch,
+}
+
+impl IntoResponse for CookieAuthError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Invalid => ApiError::unauthorized("Invalid session").into_response(),
+            Self::Expired => ApiError::unauthorized("Session expired").into_response(),
+            Self::CsrfMismatch => {
+                ApiError::forbidden("Missing or invalid CSRF token").into_response()
+            }
+        }
+    }
+}
+
+/// Signed session payload
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionClaims {
+    sub: String,
+    exp: i64,
+    csrf: String,
+}
+
+/// Cookies to set on a successful login
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    /// `Set-Cookie` value for the `HttpOnly` session cookie
+    pub session_cookie: String,
+    /// `Set-Cookie` value for the script-readable CSRF cookie
+    pub csrf_cookie: String,
+    /// CSRF token bound to the session
+    pub csrf_token: CsrfToken,
+}
+
+impl IssuedSession {
+    /// Append both `Set-Cookie` headers to a response
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for cookie in [&self.session_cookie, &self.csrf_cookie] {
+            if let Ok(value) = HeaderValue::from_str(cookie) {
+                headers.append(header::SET_COOKIE, value);
+            }
+        }
+    }
+}
+
+/// Cookie session settings
+#[derive(Debug, Clone)]
+pub struct CookieAuthConfig {
+    signer: CookieSigner,
+    pub cookie_name: String,
+    pub csrf_cookie_name: String,
+    pub ttl: Duration,
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub path: String,
+}
+
+impl CookieAuthConfig {
+    /// Settings signing sessions with `secret` (at least 32 random bytes)
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            signer: CookieSigner::new(secret),
+            cookie_name: SESSION_COOKIE.to_string(),
+            csrf_cookie_name: CSRF_COOKIE.to_string(),
+            ttl: Duration::hours(12),
+            secure: true,
+            same_site: SameSite::Lax,
+            path: "/".to_string(),
+        }
+    }
+
+    pub fn with_cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Allow cookies over plain HTTP (local development only)
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Session and CSRF cookies for `user_id`
+    pub fn issue(&self, user_id: &str) -> IssuedSession {
+        self.issue_at(user_id, OffsetDateTime::now_utc())
+    }
+
+    fn issue_at(&self, user_id: &str, now: OffsetDateTime) -> IssuedSession {
+        let csrf_token = CsrfGenerator::generate();
+        let claims = SessionClaims {
+            sub: user_id.to_string(),
+            exp: (now + self.ttl).unix_timestamp(),
+            csrf: csrf_token.as_str().to_string(),
+        };
+        let payload =
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).expect("claims serialize"));
+        let max_age = self.ttl.whole_seconds();
+
+        IssuedSession {
+            session_cookie: self.cookie(
+                &self.cookie_name,
+                &self.signer.sign(&payload),
+                max_age,
+                true,
+            ),
+            csrf_cookie: self.cookie(&self.csrf_cookie_name, csrf_token.as_str(), max_age, false),
+            csrf_token,
+        }
+    }
+
+    /// `Set-Cookie` values that clear both cookies, for logout
+    pub fn clear(&self) -> [String; 2] {
+        [
+            self.cookie(&self.cookie_name, "", 0, true),
+            self.cookie(&self.csrf_cookie_name, "", 0, false),
+        ]
+    }
+
+    fn cookie(&self, name: &str, value: &str, max_age: i64, http_only: bool) -> String {
+        let mut cookie = format!(
+            "{name}={value}; Path={}; Max-Age={max_age}; SameSite={}",
+            self.path,
+            self.same_site.as_str()
+        );
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Authenticate a request by its session cookie
+    ///
+    /// `Ok(None)` when there is no session cookie.  State-changing methods
+    /// must carry the session's CSRF token in [`CSRF_HEADER`].
+    pub fn authenticate(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> Result<Option<AuthContext>, CookieAuthError> {
+        self.authenticate_at(method, headers, OffsetDateTime::now_utc())
+    }
+
+    fn authenticate_at(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+        now: OffsetDateTime,
+    ) -> Result<Option<AuthContext>, CookieAuthError> {
+        let Some(cookie) = find_cookie(headers, &self.cookie_name) else {
+            return Ok(None);
+        };
+
+        let payload = self.signer.verify(cookie).ok_or(CookieAuthError::Invalid)?;
+        let claims: SessionClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(CookieAuthError::Invalid)?;
+        if claims.exp <= now.unix_timestamp() {
+            return Err(CookieAuthError::Expired);
+        }
+
+        if !is_safe_method(method) {
+            let echoed = headers
+                .get(CSRF_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            if !CsrfValidator::new(claims.csrf).verify(echoed) {
+                return Err(CookieAuthError::CsrfMismatch);
+            }
+        }
+
+        Ok(Some(AuthContext::new(claims.sub)))
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Value of cookie `name` from the `Cookie` headers
+fn find_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Middleware authenticating requests by signed session cookie
+pub async fn cookie_auth_middleware(
+    mut req: Request,
+    next: Next,
+    config: Arc<CookieAuthConfig>,
+) -> Response {
+    // Bearer tokens take precedence; cookies are for browser sessions
+    let has_bearer = req.headers().contains_key(header::AUTHORIZATION);
+    if has_bearer || req.extensions().get::<Arc<AuthContext>>().is_some() {
+        return next.run(req).await;
+    }
+
+    match config.authenticate(req.method(), req.headers()) {
+        Ok(Some(context)) => {
+            req.extensions_mut().insert(Arc::new(context));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            debug!(path = %req.uri().path(), reason = ?e, "rejected session cookie");
+            return e.into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// Create cookie auth middleware
+pub fn make_cookie_auth_middleware(
+    config: CookieAuthConfig,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    let config = Arc::new(config);
+    move |req: Request, next: Next| {
+        let config = config.clone();
+        Box::pin(cookie_auth_middleware(req, next, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractors::Authenticated;
+    use crate::testing::body_text;
+    use axum::{
+        Router,
+        body::Body,
+        http::StatusCode,
+        middleware,
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+
+    fn config() -> CookieAuthConfig {
+        CookieAuthConfig::new("0123456789abcdef0123456789abcdef")
+    }
+
+    fn app() -> Router {
+        async fn whoami(Authenticated(context): Authenticated) -> String {
+            context.user_id.clone()
+        }
+        Router::new()
+            .route("/me", get(whoami))
+            .route("/orders", post(whoami))
+            .layer(middleware::from_fn(make_cookie_auth_middleware(config())))
+    }
+
+    /// `name=value` from a `Set-Cookie` value
+    fn pair(set_cookie: &str) -> &str {
+        set_cookie.split(';').next().unwrap()
+    }
+
+    async fn send(request: axum::http::request::Builder) -> (StatusCode, String) {
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        (response.status(), body_text(response).await)
+    }
+
+    #[test]
+    fn test_issued_session_cookie_is_http_only_and_signed() {
+        let issued = config().issue("user-42");
+        assert!(issued.session_cookie.starts_with("tf_session="));
+        assert!(issued.session_cookie.contains("; HttpOnly"));
+        assert!(issued.session_cookie.contains("; Secure"));
+        assert!(issued.session_cookie.contains("SameSite=Lax"));
+        assert!(!issued.csrf_cookie.contains("HttpOnly"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            pair(&issued.session_cookie).parse().unwrap(),
+        );
+        let later = OffsetDateTime::now_utc() + Duration::hours(13);
+        assert_eq!(
+            config()
+                .authenticate_at(&Method::GET, &headers, later)
+                .unwrap_err(),
+            CookieAuthError::Expired
+        );
+    }
+
+    #[tokio::test]
+    async fn test_valid_cookie_authenticates_and_tampered_cookie_is_rejected() {
+        let issued = config().issue("user-42");
+        let session = pair(&issued.session_cookie).to_string();
+
+        let (status, body) = send(Request::get("/me").header(header::COOKIE, &session)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "user-42");
+
+        let forged = config().issue("admin");
+        let forged_payload = pair(&forged.session_cookie)
+            .trim_start_matches("tf_session=")
+            .split('.')
+            .next()
+            .unwrap()
+            .to_string();
+        let signature = session.rsplit('.').next().unwrap();
+        let tampered = format!("tf_session={forged_payload}.{signature}");
+        let (status, _) = send(Request::get("/me").header(header::COOKIE, tampered)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(Request::get("/me")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_state_changing_cookie_requests_require_csrf_token() {
+        let issued = config().issue("user-42");
+        let session = pair(&issued.session_cookie).to_string();
+
+        let (status, _) = send(Request::post("/orders").header(header::COOKIE, &session)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = send(
+            Request::post("/orders")
+                .header(header::COOKIE, &session)
+                .header(CSRF_HEADER, issued.csrf_token.as_str()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "user-42");
+    }
+}
@@ -1,11 +1,23 @@
 //! Cross-Origin Resource Sharing (CORS) middleware
 //!
 //! Handles CORS policy enforcement and preflight requests.
+//!
+//! Preflights (`OPTIONS` with `Origin` and `Access-Control-Request-Method`)
+//! are answered by the middleware itself with `204 No Content`, so they never
+//! reach authentication, rate limiting or handlers; install it outside those
+//! layers.  The response carries `Access-Control-Max-Age` from
+//! [`CorsPolicy::max_age`], letting browsers cache the preflight instead of
+//! repeating it before every request.
 
 use axum::extract::Request;
-use axum::http::StatusCode;
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    VARY,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
 use std::collections::HashSet;
 
 /// CORS policy configuration
@@ -19,7 +31,8 @@ pub struct CorsPolicy {
     pub allowed_methods: HashSet<String>,
     /// Allowed headers
     pub allowed_headers: HashSet<String>,
-    /// Maximum age for preflight cache (seconds)
+    /// How long browsers may cache a preflight response (seconds), sent as
+    /// `Access-Control-Max-Age`
     pub max_age: u64,
     /// Allow credentials
     pub allow_credentials: bool,
@@ -111,6 +124,47 @@ impl CorsPolicy {
         headers.sort();
         headers.join(", ")
     }
+
+    /// Set the headers granting `origin` access, which must be allowed
+    fn apply_origin_headers(&self, headers: &mut HeaderMap, origin: &str) {
+        let allow_origin = if self.allow_all && !self.allow_credentials {
+            HeaderValue::from_static("*")
+        } else {
+            match HeaderValue::from_str(origin) {
+                Ok(value) => value,
+                Err(_) => return,
+            }
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        headers.append(VARY, HeaderValue::from_static("origin"));
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    /// Answer a preflight from `origin` asking for `method`
+    fn preflight_response(&self, origin: &str, method: &str) -> Result<Response, StatusCode> {
+        if !self.is_origin_allowed(origin) || !self.is_method_allowed(method) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        self.apply_origin_headers(headers, origin);
+        for (name, value) in [
+            (ACCESS_CONTROL_ALLOW_METHODS, self.allowed_methods_str()),
+            (ACCESS_CONTROL_ALLOW_HEADERS, self.allowed_headers_str()),
+            (ACCESS_CONTROL_MAX_AGE, self.max_age.to_string()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        Ok(response)
+    }
 }
 
 impl Default for CorsPolicy {
@@ -119,25 +173,39 @@ impl Default for CorsPolicy {
     }
 }
 
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
 /// Middleware for CORS handling
 pub async fn cors_middleware(
     req: Request,
     next: Next,
     policy: CorsPolicy,
 ) -> Result<Response, StatusCode> {
-    // Check for preflight request
-    if req.method() == axum::http::Method::OPTIONS {
-        // Check origin
-        if let Some(origin) = req.headers().get("origin") {
-            if let Ok(origin_str) = origin.to_str() {
-                if !policy.is_origin_allowed(origin_str) {
-                    return Err(StatusCode::FORBIDDEN);
-                }
-            }
+    let origin = header_str(req.headers(), ORIGIN);
+    let requested_method = header_str(req.headers(), ACCESS_CONTROL_REQUEST_METHOD);
+
+    if req.method() == Method::OPTIONS
+        && let Some(origin) = &origin
+    {
+        // Preflights are answered here, before anything further in runs
+        if let Some(method) = requested_method {
+            return policy.preflight_response(origin, &method);
+        }
+        if !policy.is_origin_allowed(origin) {
+            return Err(StatusCode::FORBIDDEN);
         }
     }
 
-    Ok(next.run(req).await)
+    let mut response = next.run(req).await;
+    if let Some(origin) = origin.filter(|origin| policy.is_origin_allowed(origin)) {
+        policy.apply_origin_headers(response.headers_mut(), &origin);
+    }
+    Ok(response)
 }
 
 /// Create CORS middleware with policy
@@ -211,6 +279,70 @@ mod tests {
         assert!(policy.is_method_allowed("POST"));
     }
 
+    #[tokio::test]
+    async fn test_preflight_returns_max_age_without_reaching_inner_layers() {
+        use axum::{Router, body::Body, middleware, routing::get};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tower::ServiceExt;
+
+        let downstream = Arc::new(AtomicUsize::new(0));
+        let counter = downstream.clone();
+        let app = Router::new()
+            .route("/orders", get(|| async { "orders" }))
+            // Stands in for auth and rate limiting
+            .layer(middleware::from_fn(move |req: Request, next: Next| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                next.run(req)
+            }))
+            .layer(middleware::from_fn(make_cors_middleware(
+                presets::restricted(vec!["https://app.trustflow.ng"]).with_max_age(600),
+            )));
+
+        let preflight = |origin: &str, method: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/orders")
+                .header(ORIGIN, origin)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(preflight("https://app.trustflow.ng", "POST"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.trustflow.ng"
+        );
+        assert_eq!(downstream.load(Ordering::SeqCst), 0);
+
+        let response = app
+            .clone()
+            .oneshot(preflight("https://evil.example", "POST"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(downstream.load(Ordering::SeqCst), 0);
+
+        let request = Request::get("/orders")
+            .header(ORIGIN, "https://app.trustflow.ng")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.trustflow.ng"
+        );
+        assert_eq!(downstream.load(Ordering::SeqCst), 1);
+    }
+
     #[test]
     fn test_cors_preset_development() {
         let policy = presets::development();
@@ -0,0 +1,145 @@
+//! Per-request feature flag overrides
+//!
+//! Trusted callers can override flags for a single request with the
+//! [`FEATURE_OVERRIDES`] header (`flag=on,other=off`).  The middleware puts a
+//! [`FeatureFlags`] into the request extensions for handlers; it carries the
+//! overrides only when the caller's [`AuthContext`] has one of the trusted
+//! scopes, so the header is inert for everyone else.  Install it inside the
+//! authentication middleware so the context is already set.
+
+use std::sync::Arc;
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::{debug, info};
+
+use super::auth_context::AuthContext;
+use crate::feature_flags::{FeatureFlags, FeatureOverrides};
+use crate::http::headers::constants::FEATURE_OVERRIDES;
+
+/// Who may override flags
+#[derive(Debug, Clone)]
+pub struct FeatureOverridePolicy {
+    /// Scopes allowed to override, any of which suffices
+    pub trusted_scopes: Vec<String>,
+}
+
+impl Default for FeatureOverridePolicy {
+    fn default() -> Self {
+        Self {
+            trusted_scopes: vec!["admin".to_string(), "internal".to_string()],
+        }
+    }
+}
+
+impl FeatureOverridePolicy {
+    pub fn is_trusted(&self, context: Option<&AuthContext>) -> bool {
+        context.is_some_and(|context| {
+            self.trusted_scopes
+                .iter()
+                .any(|scope| context.has_scope(scope))
+        })
+    }
+}
+
+/// Middleware exposing request-scoped [`FeatureFlags`]
+pub async fn feature_overrides_middleware(
+    mut req: Request,
+    next: Next,
+    flags: FeatureFlags,
+    policy: Arc<FeatureOverridePolicy>,
+) -> Response {
+    let requested = req
+        .headers()
+        .get(FEATURE_OVERRIDES)
+        .and_then(|value| value.to_str().ok())
+        .map(str::parse::<FeatureOverrides>);
+
+    let scoped = match requested {
+        Some(Ok(overrides)) => {
+            let context = req.extensions().get::<Arc<AuthContext>>();
+            if policy.is_trusted(context.map(Arc::as_ref)) {
+                info!(
+                    user_id = context.map(|c| c.user_id.as_str()),
+                    overrides = ?overrides,
+                    "applying feature flag overrides"
+                );
+                flags.with_overrides(overrides)
+            } else {
+                debug!("ignoring feature flag overrides from untrusted caller");
+                flags
+            }
+        }
+        Some(Err(e)) => {
+            debug!(error = %e, "ignoring malformed feature flag overrides");
+            flags
+        }
+        None => flags,
+    };
+
+    req.extensions_mut().insert(scoped);
+    next.run(req).await
+}
+
+/// Create feature overrides middleware
+pub fn make_feature_overrides_middleware(
+    flags: FeatureFlags,
+    policy: FeatureOverridePolicy,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    let policy = Arc::new(policy);
+    move |req: Request, next: Next| {
+        let flags = flags.clone();
+        let policy = policy.clone();
+        Box::pin(feature_overrides_middleware(req, next, flags, policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::body_text;
+    use axum::{Extension, Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app(caller: Option<AuthContext>) -> Router {
+        let flags = FeatureFlags::new().with_flag("instant_payouts", false);
+        Router::new()
+            .route(
+                "/payouts",
+                get(|Extension(flags): Extension<FeatureFlags>| async move {
+                    flags.is_enabled("instant_payouts").to_string()
+                }),
+            )
+            .layer(middleware::from_fn(make_feature_overrides_middleware(
+                flags,
+                FeatureOverridePolicy::default(),
+            )))
+            // Stands in for the auth middleware, which runs first
+            .layer(middleware::from_fn(move |mut req: Request, next: Next| {
+                if let Some(caller) = caller.clone() {
+                    req.extensions_mut().insert(Arc::new(caller));
+                }
+                next.run(req)
+            }))
+    }
+
+    async fn instant_payouts(caller: Option<AuthContext>) -> String {
+        let request = Request::get("/payouts")
+            .header(FEATURE_OVERRIDES, "instant_payouts=on")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(caller).oneshot(request).await.unwrap();
+        body_text(response).await
+    }
+
+    #[tokio::test]
+    async fn test_admin_override_applies_and_untrusted_override_is_ignored() {
+        let admin = AuthContext::new("ops-1").with_scope("admin");
+        assert_eq!(instant_payouts(Some(admin)).await, "true");
+
+        assert_eq!(instant_payouts(None).await, "false");
+        let buyer = AuthContext::new("buyer-9").with_scope("orders:write");
+        assert_eq!(instant_payouts(Some(buyer)).await, "false");
+    }
+}
@@ -0,0 +1,279 @@
+//! JSON field naming middleware
+//!
+//! DTOs, and the [`ApiResponse`](crate::http::response::ApiResponse) and
+//! `ApiError` envelopes, serialize with their snake_case Rust field names.
+//! Services whose clients expect camelCase set it once on the router:
+//!
+//! ```rust,ignore
+//! use axum::middleware;
+//! use common::middleware::make_field_naming_middleware;
+//! use common::utils::rename::FieldNaming;
+//!
+//! let app = Router::new()
+//!     .route("/wallets/{id}", get(get_wallet))
+//!     .layer(middleware::from_fn(make_field_naming_middleware(FieldNaming::CamelCase)));
+//! ```
+//!
+//! Keys of JSON responses are rewritten to the chosen naming, and keys of
+//! JSON request bodies back to snake_case, so extractors keep working with
+//! the structs as written.  Only keys change; values such as error codes
+//! are sent as they are.  Responses whose keys are data rather than field
+//! names (a map keyed by currency, say) opt out with [`PreserveFieldNames`];
+//! so do requests carrying it, put there by a layer outside this one (a
+//! webhook receiver that must see the sender's payload as sent, say).
+//!
+//! A JSON request body is read whole, chunked ones included; one over
+//! [`MAX_RENAMED_BODY_BYTES`] is refused with `413`, and one that fails to
+//! arrive with `400`.  A response body over the limit is passed on as is.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::buffer::{BufferedBody, buffer_body};
+use crate::utils::rename::FieldNaming;
+
+/// Largest JSON body whose keys are renamed
+pub const MAX_RENAMED_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Request or response extension keeping the body's keys as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreserveFieldNames;
+
+/// Middleware spelling JSON keys with `naming` on the wire
+pub async fn field_naming_middleware(req: Request, next: Next, naming: FieldNaming) -> Response {
+    if naming == FieldNaming::SnakeCase {
+        return next.run(req).await;
+    }
+
+    let req = if is_json(req.headers()) && req.extensions().get::<PreserveFieldNames>().is_none() {
+        let (mut parts, body) = req.into_parts();
+        let body = match buffer_body(body, MAX_RENAMED_BODY_BYTES).await {
+            BufferedBody::Complete(bytes) => {
+                rename_body(&mut parts.headers, bytes, FieldNaming::SnakeCase)
+            }
+            BufferedBody::TooLarge(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+            BufferedBody::Failed(_) => return StatusCode::BAD_REQUEST.into_response(),
+        };
+        Request::from_parts(parts, body)
+    } else {
+        req
+    };
+
+    let response = next.run(req).await;
+    if !is_json(response.headers()) || response.extensions().get::<PreserveFieldNames>().is_some() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match buffer_body(body, MAX_RENAMED_BODY_BYTES).await {
+        BufferedBody::Complete(bytes) => rename_body(&mut parts.headers, bytes, naming),
+        BufferedBody::TooLarge(body) | BufferedBody::Failed(body) => body,
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Create field naming middleware with `naming`
+pub fn make_field_naming_middleware(
+    naming: FieldNaming,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| Box::pin(field_naming_middleware(req, next, naming))
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim)
+        .is_some_and(|mime| mime == "application/json" || mime.ends_with("+json"))
+}
+
+/// `bytes` with its keys renamed, `Content-Length` updated to match
+///
+/// Bodies that aren't valid JSON are passed on as they are, so whatever
+/// reads them reports the problem.
+fn rename_body(headers: &mut HeaderMap, bytes: Bytes, naming: FieldNaming) -> Body {
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Body::from(bytes);
+    };
+    naming.rename_keys(&mut value);
+    let renamed = Bytes::from(serde_json::to_vec(&value).unwrap_or_else(|_| bytes.to_vec()));
+    headers.remove(header::TRANSFER_ENCODING);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(renamed.len()));
+    Body::from(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::pagination::{PaginatedResponse, Pagination};
+    use crate::http::response::ApiResponse;
+    use crate::testing::{body_bytes, body_text};
+    use axum::{
+        Json, Router,
+        http::StatusCode,
+        middleware::from_fn,
+        routing::{get, post},
+    };
+    use error::http::ApiError;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    #[derive(Serialize, Deserialize)]
+    struct Wallet {
+        wallet_id: String,
+        available_balance: i64,
+    }
+
+    fn app() -> Router {
+        let wallet = || Wallet {
+            wallet_id: "w_1".into(),
+            available_balance: 1_500,
+        };
+        Router::new()
+            .route(
+                "/wallets",
+                get(move || async move {
+                    let page = Pagination::new(1, 20, 1);
+                    ApiResponse::success(
+                        "Items fetched",
+                        PaginatedResponse::new(vec![wallet()], page),
+                    )
+                }),
+            )
+            .route(
+                "/wallets/rename",
+                post(|Json(wallet): Json<Wallet>| async move {
+                    ApiResponse::success("Renamed", wallet)
+                }),
+            )
+            .route(
+                "/wallets/missing",
+                get(|| async { ApiError::not_found("wallet w_9 not found") }),
+            )
+            .layer(from_fn(make_field_naming_middleware(
+                FieldNaming::CamelCase,
+            )))
+    }
+
+    async fn call(request: Request) -> (StatusCode, Value) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let length: usize = response.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = body_bytes(response).await;
+        assert_eq!(body.len(), length);
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_response_fields_are_camel_cased() {
+        let (status, body) = call(Request::get("/wallets").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let item = &body["data"]["items"][0];
+        assert_eq!(item["walletId"], "w_1");
+        assert_eq!(item["availableBalance"], 1_500);
+        assert!(item.get("wallet_id").is_none());
+        assert_eq!(body["data"]["pagination"]["totalPages"], 1);
+
+        // Clients send camelCase too
+        let (status, body) = call(
+            Request::post("/wallets/rename")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"walletId":"w_2","availableBalance":7}"#))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["walletId"], "w_2");
+
+        // Error envelopes follow the same convention; values are untouched
+        let (status, body) = call(
+            Request::get("/wallets/missing")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].get("errorType").is_some(), "{body}");
+        assert!(body["error"].get("error_type").is_none(), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_request_bodies_without_a_length_are_renamed_or_refused() {
+        let chunked = |chunks: Vec<Result<&'static str, std::io::Error>>| {
+            Request::post("/wallets/rename")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from_stream(futures::stream::iter(chunks)))
+                .unwrap()
+        };
+
+        let (status, body) = call(chunked(vec![
+            Ok(r#"{"walletId":"w_3","#),
+            Ok(r#""availableBalance":9}"#),
+        ]))
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["walletId"], "w_3");
+
+        let response = app()
+            .oneshot(chunked(vec![
+                Ok(r#"{"walletId":"w_3","#),
+                Err(std::io::Error::other("connection reset")),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let padding = " ".repeat(MAX_RENAMED_BODY_BYTES);
+        let oversized = Request::post("/wallets/rename")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(futures::stream::iter([
+                Ok::<_, std::io::Error>(r#"{"walletId":"w_3","#.to_string()),
+                Ok(padding),
+            ])))
+            .unwrap();
+        let response = app().oneshot(oversized).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_requests_can_keep_their_field_names() {
+        let app = Router::new()
+            .route(
+                "/webhooks/psp",
+                // Answers with the keys it was given, as text
+                post(|Json(payload): Json<Value>| async move {
+                    let keys: Vec<String> = payload.as_object().unwrap().keys().cloned().collect();
+                    keys.join(",")
+                }),
+            )
+            .layer(from_fn(make_field_naming_middleware(
+                FieldNaming::CamelCase,
+            )))
+            // Stands in for a layer marking the webhook routes
+            .layer(from_fn(|mut req: Request, next: Next| {
+                req.extensions_mut().insert(PreserveFieldNames);
+                next.run(req)
+            }));
+
+        let response = app
+            .oneshot(
+                Request::post("/webhooks/psp")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"eventType":"charge.succeeded"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(body_text(response).await, "eventType");
+    }
+}
@@ -2,15 +2,107 @@
 //!
 //! Ensures that duplicate requests with the same idempotency key
 //! return cached responses instead of executing repeatedly.
+//!
+//! Each route decides whose keys may collide through
+//! [`IdempotencyConfig::scope`]: escrow payments use
+//! [`IdempotencyScope::PerUser`], so two users picking the same key don't
+//! get each other's responses, while some admin operations are
+//! [`IdempotencyScope::Global`].  The scope is part of the storage key
+//! (see [`IdempotencyConfig::storage_key`]), which has the same shape as
+//! this repo's Redis keys.
+//!
+//! Only successful (`2xx`) responses are stored.  While the first request
+//! with a key is running, the key holds a pending marker, so a duplicate
+//! sent meanwhile gets `409 Conflict` instead of running the handler again.
+//!
+//! ```rust,ignore
+//! let payments = Router::new()
+//!     .route("/escrows/{id}/pay", post(pay))
+//!     .layer(from_fn(make_idempotency_middleware(
+//!         store.clone(),
+//!         IdempotencyConfig::new(IdempotencyScope::PerUser),
+//!     )));
+//! ```
 
+use super::auth_context::AuthContext;
+use crate::http::headers::constants::{API_KEY, IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED};
+use axum::body::{Body, HttpBody};
 use axum::extract::Request;
 use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
+/// Whose requests share an idempotency key namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdempotencyScope {
+    /// Keys are per authenticated user
+    #[default]
+    PerUser,
+    /// Keys are per API key (`x-api-key` header)
+    PerApiKey,
+    /// One namespace for all callers
+    Global,
+}
+
+/// Idempotency settings of a route
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    pub scope: IdempotencyScope,
+    /// Namespace of the storage keys
+    pub prefix: String,
+    /// Responses larger than this, or of unknown length, are passed
+    /// through without being stored
+    pub max_body_bytes: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self::new(IdempotencyScope::default())
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn new(scope: IdempotencyScope) -> Self {
+        Self {
+            scope,
+            prefix: "idempotency".to_string(),
+            max_body_bytes: 1024 * 1024,
+        }
+    }
+
+    /// Use `prefix` as the storage key namespace
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Storage key of `key` sent with `req`, e.g.
+    /// `idempotency:user:42:7f3c...`
+    ///
+    /// `None` when the scope needs a caller the request doesn't identify.
+    /// API keys are hashed so they never appear in storage keys.
+    pub fn storage_key(&self, req: &Request, key: &IdempotencyKey) -> Option<String> {
+        let owner = match self.scope {
+            IdempotencyScope::PerUser => {
+                let context = req.extensions().get::<Arc<AuthContext>>()?;
+                format!("user:{}", context.user_id)
+            }
+            IdempotencyScope::PerApiKey => {
+                let api_key = req.headers().get(API_KEY)?.as_bytes();
+                format!("api_key:{}", hex::encode(Sha256::digest(api_key)))
+            }
+            IdempotencyScope::Global => "global".to_string(),
+        };
+        Some(format!("{}:{owner}:{}", self.prefix, key.as_str()))
+    }
+}
+
 /// Idempotency key for deduplication
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct IdempotencyKey(String);
@@ -32,17 +124,46 @@ impl IdempotencyKey {
 pub struct IdempotentRecord {
     /// Status code from original response
     pub status_code: u16,
+    /// Content type of the original response
+    pub content_type: Option<String>,
     /// Response body (serialized)
     pub body: Vec<u8>,
     /// Created timestamp (seconds since epoch)
     pub created_at: u64,
 }
 
+/// What a request found when it claimed its idempotency key
+#[derive(Debug, Clone)]
+pub enum IdempotencyClaim {
+    /// The key is now held by the caller
+    Claimed,
+    /// Another request with the key is still running
+    InProgress,
+    /// The response recorded for the key
+    Completed(IdempotentRecord),
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    /// Claimed at this time (seconds since epoch), no response yet
+    Pending(u64),
+    Completed(IdempotentRecord),
+}
+
+impl Entry {
+    fn created_at(&self) -> u64 {
+        match self {
+            Entry::Pending(claimed_at) => *claimed_at,
+            Entry::Completed(record) => record.created_at,
+        }
+    }
+}
+
 /// Idempotency store for tracking requests
 #[derive(Debug, Clone)]
 pub struct IdempotencyStore {
-    /// Map of idempotency key -> response record
-    store: Arc<RwLock<HashMap<String, IdempotentRecord>>>,
+    /// Map of idempotency key -> pending marker or response record
+    store: Arc<RwLock<HashMap<String, Entry>>>,
     /// TTL for records (seconds)
     ttl: u64,
 }
@@ -61,16 +182,46 @@ impl IdempotencyStore {
         Self::new(3600)
     }
 
+    fn is_live(&self, entry: &Entry) -> bool {
+        now_secs().saturating_sub(entry.created_at()) < self.ttl
+    }
+
+    /// Claim `key` unless it already holds a live pending marker or
+    /// response, like Redis `SET NX`; markers expire with the TTL so a
+    /// crashed request doesn't hold its key forever
+    pub async fn claim(&self, key: &IdempotencyKey) -> IdempotencyClaim {
+        let mut store = self.store.write().await;
+        match store.get(&key.0).filter(|entry| self.is_live(entry)) {
+            Some(Entry::Pending(_)) => IdempotencyClaim::InProgress,
+            Some(Entry::Completed(record)) => IdempotencyClaim::Completed(record.clone()),
+            None => {
+                store.insert(key.0.clone(), Entry::Pending(now_secs()));
+                IdempotencyClaim::Claimed
+            }
+        }
+    }
+
+    /// Drop the pending marker of `key`, so the request can be retried
+    pub async fn release(&self, key: &IdempotencyKey) {
+        let mut store = self.store.write().await;
+        if let Some(Entry::Pending(_)) = store.get(&key.0) {
+            store.remove(&key.0);
+        }
+    }
+
     /// Store response for key
     pub async fn store(&self, key: IdempotencyKey, record: IdempotentRecord) {
         let mut store = self.store.write().await;
-        store.insert(key.0, record);
+        store.insert(key.0, Entry::Completed(record));
     }
 
-    /// Retrieve cached response
+    /// Retrieve cached response, unless it has outlived the TTL
     pub async fn get(&self, key: &IdempotencyKey) -> Option<IdempotentRecord> {
         let store = self.store.read().await;
-        store.get(&key.0).cloned()
+        match store.get(&key.0).filter(|entry| self.is_live(entry)) {
+            Some(Entry::Completed(record)) => Some(record.clone()),
+            _ => None,
+        }
     }
 
     /// Check if key exists
@@ -92,31 +243,286 @@ impl IdempotencyStore {
     }
 }
 
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn replay(record: IdempotentRecord) -> Response {
+    let mut response = Response::builder()
+        .status(record.status_code)
+        .header(IDEMPOTENT_REPLAYED, "true");
+    if let Some(content_type) = &record.content_type {
+        response = response.header(CONTENT_TYPE, content_type);
+    }
+    response
+        .body(Body::from(record.body))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
 /// Middleware for idempotency handling
-pub async fn idempotency_middleware(req: Request, next: Next) -> Result<Response, StatusCode> {
-    // In production, this would:
-    // 1. Extract idempotency-key header
-    // 2. Check store for existing response
-    // 3. Return cached response if found
-    // 4. Otherwise, execute request and cache response
-
-    if let Some(idempotency_key) = req.headers().get("idempotency-key") {
-        if let Ok(key_str) = idempotency_key.to_str() {
-            // Would check store here
-            let _key = IdempotencyKey::new(key_str);
-        }
+///
+/// Requests with an `idempotency-key` header are answered from the store
+/// when the same key was seen in the same scope, or get `409` while that
+/// request is still running; otherwise the response is stored if it is a
+/// `2xx` no larger than [`IdempotencyConfig::max_body_bytes`].  Other
+/// responses are passed through and the key released, so the client may
+/// retry.  Requests without the header pass through.  A key sent where the
+/// scope's caller can't be identified is rejected with `401`.
+pub async fn idempotency_middleware(
+    req: Request,
+    next: Next,
+    store: IdempotencyStore,
+    config: IdempotencyConfig,
+) -> Result<Response, StatusCode> {
+    let Some(key) = req
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(IdempotencyKey::new)
+    else {
+        return Ok(next.run(req).await);
+    };
+    let storage_key = config
+        .storage_key(&req, &key)
+        .map(IdempotencyKey::new)
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    match store.claim(&storage_key).await {
+        IdempotencyClaim::Claimed => {}
+        IdempotencyClaim::InProgress => return Err(StatusCode::CONFLICT),
+        IdempotencyClaim::Completed(record) => return Ok(replay(record)),
     }
 
-    Ok(next.run(req).await)
+    let response = next.run(req).await;
+    let fits = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|len| len <= config.max_body_bytes as u64);
+    if !response.status().is_success() || !fits {
+        store.release(&storage_key).await;
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, config.max_body_bytes).await {
+        Ok(body) => body,
+        Err(_) => {
+            store.release(&storage_key).await;
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    store
+        .store(
+            storage_key,
+            IdempotentRecord {
+                status_code: parts.status.as_u16(),
+                content_type: parts
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                body: body.to_vec(),
+                created_at: now_secs(),
+            },
+        )
+        .await;
+    Ok(Response::from_parts(parts, Body::from(body)))
 }
 
 /// Create idempotency middleware with store
 pub fn make_idempotency_middleware(
     store: IdempotencyStore,
+    config: IdempotencyConfig,
 ) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Result<Response, StatusCode>> + Clone
 {
     move |req: Request, next: Next| {
-        let _store = store.clone();
-        Box::pin(idempotency_middleware(req, next))
+        Box::pin(idempotency_middleware(
+            req,
+            next,
+            store.clone(),
+            config.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::body_text;
+    use axum::{Router, middleware::from_fn, routing::post};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    fn app(config: IdempotencyConfig, payments: Arc<AtomicUsize>) -> Router {
+        Router::new()
+            .route(
+                "/escrows/esc_1/pay",
+                post(move || async move {
+                    let n = payments.fetch_add(1, Ordering::SeqCst) + 1;
+                    (StatusCode::CREATED, format!("payment {n}"))
+                }),
+            )
+            .layer(from_fn(make_idempotency_middleware(
+                IdempotencyStore::default_ttl(),
+                config,
+            )))
+            // Stands in for the auth middleware
+            .layer(from_fn(|mut req: Request, next: Next| {
+                let user = req.headers().get("x-test-user").cloned();
+                if let Some(user) = user {
+                    let context = AuthContext::new(user.to_str().unwrap());
+                    req.extensions_mut().insert(Arc::new(context));
+                }
+                next.run(req)
+            }))
+    }
+
+    async fn pay(app: &Router, user: &str, key: &str) -> (StatusCode, String) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/escrows/esc_1/pay")
+                    .header("x-test-user", user)
+                    .header(IDEMPOTENCY_KEY, key)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        (response.status(), body_text(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_same_key_from_two_users_does_not_collide_per_user() {
+        let payments = Arc::new(AtomicUsize::new(0));
+        let app = app(
+            IdempotencyConfig::new(IdempotencyScope::PerUser),
+            payments.clone(),
+        );
+
+        assert_eq!(pay(&app, "ada", "key-1").await.1, "payment 1");
+        assert_eq!(pay(&app, "bola", "key-1").await.1, "payment 2");
+        // A retry by the same user is replayed
+        assert_eq!(
+            pay(&app, "ada", "key-1").await,
+            (StatusCode::CREATED, "payment 1".to_string())
+        );
+        assert_eq!(payments.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_global_scope_shares_keys_across_users() {
+        let payments = Arc::new(AtomicUsize::new(0));
+        let app = app(
+            IdempotencyConfig::new(IdempotencyScope::Global),
+            payments.clone(),
+        );
+
+        assert_eq!(pay(&app, "ops-1", "reindex").await.1, "payment 1");
+        assert_eq!(pay(&app, "ops-2", "reindex").await.1, "payment 1");
+        assert_eq!(payments.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_only_successful_responses_that_fit_are_stored() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        let app = Router::new()
+            .route(
+                "/escrows/esc_1/pay",
+                post(move || async move {
+                    match handler_calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => (StatusCode::TOO_MANY_REQUESTS, "slow down".to_string()),
+                        1 => (StatusCode::UNPROCESSABLE_ENTITY, "bad amount".to_string()),
+                        2 => (StatusCode::OK, "x".repeat(64)),
+                        _ => (StatusCode::CREATED, "paid".to_string()),
+                    }
+                }),
+            )
+            .layer(from_fn(make_idempotency_middleware(
+                IdempotencyStore::default_ttl(),
+                IdempotencyConfig {
+                    max_body_bytes: 16,
+                    ..IdempotencyConfig::new(IdempotencyScope::Global)
+                },
+            )));
+
+        assert_eq!(
+            pay(&app, "ada", "key-1").await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            pay(&app, "ada", "key-1").await.0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        // Too large to store, but still delivered whole
+        assert_eq!(
+            pay(&app, "ada", "key-1").await,
+            (StatusCode::OK, "x".repeat(64))
+        );
+        assert_eq!(pay(&app, "ada", "key-1").await.1, "paid");
+        assert_eq!(pay(&app, "ada", "key-1").await.1, "paid");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_of_a_running_request_is_rejected() {
+        let payments = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(tokio::sync::Notify::new());
+        let (started_tx, mut started) = tokio::sync::mpsc::unbounded_channel();
+        let handler_payments = payments.clone();
+        let handler_release = release.clone();
+        let app = Router::new()
+            .route(
+                "/escrows/esc_1/pay",
+                post(move || async move {
+                    started_tx.send(()).unwrap();
+                    handler_release.notified().await;
+                    let n = handler_payments.fetch_add(1, Ordering::SeqCst) + 1;
+                    (StatusCode::CREATED, format!("payment {n}"))
+                }),
+            )
+            .layer(from_fn(make_idempotency_middleware(
+                IdempotencyStore::default_ttl(),
+                IdempotencyConfig::new(IdempotencyScope::Global),
+            )));
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { pay(&app, "ada", "key-1").await }
+        });
+        started.recv().await.unwrap();
+
+        assert_eq!(pay(&app, "ada", "key-1").await.0, StatusCode::CONFLICT);
+        release.notify_one();
+        assert_eq!(first.await.unwrap().1, "payment 1");
+        assert_eq!(pay(&app, "ada", "key-1").await.1, "payment 1");
+        assert_eq!(payments.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_storage_key_includes_scope() {
+        let key = IdempotencyKey::new("key-1");
+        let mut req = Request::new(Body::empty());
+        req.headers_mut()
+            .insert("x-api-key", "tf_live_secret".parse().unwrap());
+
+        let per_user = IdempotencyConfig::new(IdempotencyScope::PerUser);
+        assert_eq!(per_user.storage_key(&req, &key), None);
+        req.extensions_mut()
+            .insert(Arc::new(AuthContext::new("42")));
+        assert_eq!(
+            per_user.storage_key(&req, &key).as_deref(),
+            Some("idempotency:user:42:key-1")
+        );
+
+        let per_api_key = IdempotencyConfig::new(IdempotencyScope::PerApiKey)
+            .storage_key(&req, &key)
+            .unwrap();
+        assert!(per_api_key.starts_with("idempotency:api_key:"));
+        assert!(!per_api_key.contains("tf_live_secret"));
     }
 }
@@ -0,0 +1,275 @@
+//! Priority-aware load shedding middleware
+//!
+//! Under overload, low-priority traffic (analytics, exports) is rejected
+//! with `503 Service Unavailable` so high-priority routes (auth, health)
+//! keep their capacity.  The service counts as overloaded while the number
+//! of in-flight requests reaches the configured ceiling, or while the
+//! smoothed request latency exceeds the configured threshold.
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use error::http::ApiError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Seconds shed clients are told to wait before retrying
+const SHED_RETRY_AFTER_SECS: u64 = 1;
+
+/// Priority of a route when the service is overloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequestPriority {
+    /// Always admitted
+    #[default]
+    High,
+    /// Shed first under overload
+    Low,
+}
+
+/// Load shedding configuration
+#[derive(Debug, Clone)]
+pub struct LoadShedConfig {
+    /// In-flight requests at which the service is overloaded
+    pub max_in_flight: usize,
+    /// Smoothed latency above which the service is overloaded
+    pub latency_threshold: Option<Duration>,
+    /// Priority of routes without an explicit entry
+    pub default_priority: RequestPriority,
+    /// Path-prefix priorities, first match wins
+    pub route_priorities: Vec<(String, RequestPriority)>,
+}
+
+impl LoadShedConfig {
+    /// Create new load shed config
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            latency_threshold: None,
+            default_priority: RequestPriority::High,
+            route_priorities: Vec::new(),
+        }
+    }
+
+    /// Also treat the service as overloaded when latency exceeds `threshold`
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Set the priority of unlisted routes
+    pub fn with_default_priority(mut self, priority: RequestPriority) -> Self {
+        self.default_priority = priority;
+        self
+    }
+
+    /// Add path-specific priority
+    pub fn add_route_priority(
+        mut self,
+        path: impl Into<String>,
+        priority: RequestPriority,
+    ) -> Self {
+        self.route_priorities.push((path.into(), priority));
+        self
+    }
+
+    /// Get priority for path
+    pub fn priority_for(&self, path: &str) -> RequestPriority {
+        self.route_priorities
+            .iter()
+            .find(|(pattern, _)| path.starts_with(pattern.as_str()))
+            .map(|(_, priority)| *priority)
+            .unwrap_or(self.default_priority)
+    }
+}
+
+impl Default for LoadShedConfig {
+    fn default() -> Self {
+        Self::new(512)
+    }
+}
+
+/// Shared load tracker consulted by [`load_shed_middleware`]
+#[derive(Debug, Clone)]
+pub struct LoadShedder {
+    config: Arc<LoadShedConfig>,
+    in_flight: Arc<AtomicUsize>,
+    /// Exponentially weighted moving average of latency, in microseconds
+    latency_micros: Arc<AtomicU64>,
+}
+
+impl LoadShedder {
+    /// Create new load shedder
+    pub fn new(config: LoadShedConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            latency_micros: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn config(&self) -> &LoadShedConfig {
+        &self.config
+    }
+
+    /// Requests currently being handled
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Smoothed request latency
+    pub fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_micros.load(Ordering::Relaxed))
+    }
+
+    /// Whether low-priority requests should currently be shed
+    pub fn is_overloaded(&self) -> bool {
+        self.in_flight() >= self.config.max_in_flight
+            || self
+                .config
+                .latency_threshold
+                .is_some_and(|threshold| self.latency() > threshold)
+    }
+
+    /// Count a request as in flight until the guard is dropped
+    pub fn begin(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Fold a completed request's latency into the moving average
+    pub fn record_latency(&self, latency: Duration) {
+        let sample = latency.as_micros().min(u64::MAX as u128) as u64;
+        let mut avg = self.latency_micros.load(Ordering::Relaxed);
+        loop {
+            let next = if avg == 0 {
+                sample
+            } else {
+                (avg * 4 + sample) / 5
+            };
+            match self.latency_micros.compare_exchange_weak(
+                avg,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => avg = current,
+            }
+        }
+    }
+}
+
+/// Marks one in-flight request; decrements the count on drop so cancelled
+/// or panicking handlers don't leak capacity
+#[derive(Debug)]
+pub struct InFlightGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Middleware for priority-aware load shedding
+pub async fn load_shed_middleware(req: Request, next: Next, shedder: LoadShedder) -> Response {
+    let priority = shedder.config.priority_for(req.uri().path());
+    if priority == RequestPriority::Low && shedder.is_overloaded() {
+        warn!(
+            path = %req.uri().path(),
+            in_flight = shedder.in_flight(),
+            latency_ms = shedder.latency().as_millis() as u64,
+            "shedding low-priority request"
+        );
+        return ApiError::service_unavailable_with_retry(
+            "Service is under heavy load, please retry shortly",
+            SHED_RETRY_AFTER_SECS,
+        )
+        .into_response();
+    }
+
+    let _guard = shedder.begin();
+    let started = Instant::now();
+    let response = next.run(req).await;
+    shedder.record_latency(started.elapsed());
+    response
+}
+
+/// Create load shed middleware
+pub fn make_load_shed_middleware(
+    shedder: LoadShedder,
+) -> impl Fn(Request, Next) -> futures::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| {
+        let shedder = shedder.clone();
+        Box::pin(load_shed_middleware(req, next, shedder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app(shedder: LoadShedder) -> Router {
+        Router::new()
+            .route("/api/v1/auth/login", get(|| async { "ok" }))
+            .route("/api/v1/analytics/events", get(|| async { "ok" }))
+            .layer(middleware::from_fn(make_load_shed_middleware(shedder)))
+    }
+
+    async fn status(app: &Router, path: &str) -> StatusCode {
+        app.clone()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_overload_sheds_low_priority_routes_only() {
+        let shedder = LoadShedder::new(
+            LoadShedConfig::new(2).add_route_priority("/api/v1/analytics", RequestPriority::Low),
+        );
+        let app = app(shedder.clone());
+        assert_eq!(
+            status(&app, "/api/v1/analytics/events").await,
+            StatusCode::OK
+        );
+
+        // Simulate two long-running requests occupying the service
+        let busy = [shedder.begin(), shedder.begin()];
+        assert!(shedder.is_overloaded());
+        assert_eq!(
+            status(&app, "/api/v1/analytics/events").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status(&app, "/api/v1/auth/login").await, StatusCode::OK);
+
+        drop(busy);
+        assert_eq!(shedder.in_flight(), 0);
+        assert_eq!(
+            status(&app, "/api/v1/analytics/events").await,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_high_latency_counts_as_overload() {
+        let shedder = LoadShedder::new(
+            LoadShedConfig::new(100).with_latency_threshold(Duration::from_millis(200)),
+        );
+        shedder.record_latency(Duration::from_millis(50));
+        assert!(!shedder.is_overloaded());
+
+        for _ in 0..10 {
+            shedder.record_latency(Duration::from_secs(2));
+        }
+        assert!(shedder.is_overloaded());
+    }
+}
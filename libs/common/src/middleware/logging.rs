@@ -1,13 +1,108 @@
 //! Request/response logging middleware
 //!
 //! Logs HTTP requests and responses with structured tracing for observability.
+//!
+//! Response bodies can be captured too, for every response or only for
+//! errors.  Bodies of responses carrying a [`LogRedaction`] (see
+//! [`RedactedJson`](super::redaction::RedactedJson)) are masked before they
+//! are logged; the client always gets them unchanged.
+//!
+//! Services can declare [`CorrelationField`]s, such as the tenant or API
+//! version, taken from each request.  The request then runs inside a
+//! `request` span carrying them, so every log line it produces, the
+//! handler's included, has the same context.
 
+use super::buffer::{BufferedBody, buffer_body};
+use super::client_ip::ClientIp;
+use super::redaction::LogRedaction;
+use axum::body::{Body, HttpBody};
 use axum::extract::Request;
 use axum::http::StatusCode;
 use axum::middleware::Next;
 use axum::response::Response;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
 use std::time::Instant;
+use tracing::Instrument;
+
+/// Takes the value of a correlation field from a request
+pub type FieldExtractor = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Context field added to every log line of a request
+#[derive(Clone)]
+pub struct CorrelationField {
+    /// Name in the logs, e.g. `tenant_id`
+    pub name: String,
+    extractor: FieldExtractor,
+}
+
+impl CorrelationField {
+    /// Field whose value `extractor` takes from the request
+    pub fn new(
+        name: impl Into<String>,
+        extractor: impl Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            extractor: Arc::new(extractor),
+        }
+    }
+
+    /// Field taken from the request header `header`
+    pub fn header(name: impl Into<String>, header: &'static str) -> Self {
+        Self::new(name, move |req| {
+            req.headers()
+                .get(header)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string())
+        })
+    }
+
+    /// Value of the field for `req`
+    pub fn extract(&self, req: &Request) -> Option<String> {
+        (self.extractor)(req)
+    }
+}
+
+impl fmt::Debug for CorrelationField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CorrelationField")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Correlation fields of one request, logged as `name=value` pairs; `-`
+/// stands for a value the request didn't have
+///
+/// Values come from the client, so any that isn't a plain token is quoted
+/// and escaped: a header can't forge another pair or a new log line.
+struct Correlation(Vec<(String, Option<String>)>);
+
+impl fmt::Display for Correlation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match value.as_deref() {
+                None => write!(f, "{name}=-")?,
+                Some(value) if is_plain(value) => write!(f, "{name}={value}")?,
+                Some(value) => write!(f, "{name}={value:?}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `value` can be logged unquoted
+fn is_plain(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/'))
+}
 
 /// Request logging configuration
 #[derive(Debug, Clone)]
@@ -18,8 +113,14 @@ pub struct LoggingConfig {
     pub log_body: bool,
     /// Log response body
     pub log_response_body: bool,
+    /// Log the body of error (4xx/5xx) responses
+    pub log_error_body: bool,
+    /// Larger response bodies are not captured
+    pub max_logged_body_bytes: usize,
     /// Paths to exclude from logging
     pub exclude_paths: Vec<String>,
+    /// Context fields added to every log line of a request
+    pub extra_fields: Vec<CorrelationField>,
 }
 
 impl LoggingConfig {
@@ -29,7 +130,10 @@ impl LoggingConfig {
             log_headers: true,
             log_body: false,
             log_response_body: false,
+            log_error_body: false,
+            max_logged_body_bytes: 16 * 1024,
             exclude_paths: vec!["/health".to_string(), "/metrics".to_string()],
+            extra_fields: Vec::new(),
         }
     }
 
@@ -45,12 +149,24 @@ impl LoggingConfig {
         self
     }
 
+    /// Enable body logging for error responses only
+    pub fn with_error_body(mut self) -> Self {
+        self.log_error_body = true;
+        self
+    }
+
     /// Add path to exclude
     pub fn exclude_path(mut self, path: impl Into<String>) -> Self {
         self.exclude_paths.push(path.into());
         self
     }
 
+    /// Add `field` to every log line of a request
+    pub fn with_extra_field(mut self, field: CorrelationField) -> Self {
+        self.extra_fields.push(field);
+        self
+    }
+
     /// Check if path should be logged
     pub fn should_log(&self, path: &str) -> bool {
         !self
@@ -58,6 +174,12 @@ impl LoggingConfig {
             .iter()
             .any(|excluded| path.starts_with(excluded))
     }
+
+    /// Check if the body of a response with `status` should be logged
+    pub fn should_log_body(&self, status: StatusCode) -> bool {
+        self.log_response_body
+            || (self.log_error_body && (status.is_client_error() || status.is_server_error()))
+    }
 }
 
 impl Default for LoggingConfig {
@@ -105,6 +227,26 @@ pub async fn logging_middleware(
         return Ok(next.run(req).await);
     }
 
+    if config.extra_fields.is_empty() {
+        return log_request(req, next, config, path).await;
+    }
+    let correlation = Correlation(
+        config
+            .extra_fields
+            .iter()
+            .map(|field| (field.name.clone(), field.extract(&req)))
+            .collect(),
+    );
+    let span = tracing::info_span!("request", correlation = %correlation);
+    log_request(req, next, config, path).instrument(span).await
+}
+
+async fn log_request(
+    req: Request,
+    next: Next,
+    config: LoggingConfig,
+    path: String,
+) -> Result<Response, StatusCode> {
     let method = req.method().to_string();
     let start = Instant::now();
 
@@ -118,10 +260,9 @@ pub async fn logging_middleware(
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string()),
         client_ip: req
-            .headers()
-            .get("x-forwarded-for")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string()),
+            .extensions()
+            .get::<ClientIp>()
+            .map(ClientIp::to_string),
         user_agent: req
             .headers()
             .get("user-agent")
@@ -138,15 +279,29 @@ pub async fn logging_middleware(
         "HTTP request started"
     );
 
-    let response = next.run(req).await;
+    let mut response = next.run(req).await;
     let elapsed = start.elapsed();
 
     let response_log = ResponseLog {
         status_code: response.status().as_u16(),
         duration_ms: elapsed.as_millis() as u64,
-        size_bytes: 0, // Would calculate actual size in production
+        size_bytes: response.body().size_hint().exact().unwrap_or(0) as usize,
     };
 
+    if config.should_log_body(response.status()) {
+        let body;
+        (response, body) = capture_body(response, config.max_logged_body_bytes).await;
+        if let Some(body) = body {
+            tracing::info!(
+                method = %request_log.method,
+                path = %request_log.path,
+                status = response_log.status_code,
+                body = %body,
+                "HTTP response body"
+            );
+        }
+    }
+
     // Log response
     #[cfg(feature = "logging")]
     tracing::info!(
@@ -160,6 +315,37 @@ pub async fn logging_middleware(
     Ok(response)
 }
 
+/// Buffer the body of `response` for logging, redacted if the response
+/// asks for it
+///
+/// Bodies of unknown length (streams) or over `max_bytes` are left alone,
+/// and one that fails to read is passed on as it was, error included: the
+/// client gets the same response either way.
+async fn capture_body(response: Response, max_bytes: usize) -> (Response, Option<String>) {
+    let fits = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|upper| upper <= max_bytes as u64);
+    if !fits {
+        return (response, None);
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match buffer_body(body, max_bytes).await {
+        BufferedBody::Complete(bytes) => bytes,
+        BufferedBody::TooLarge(body) | BufferedBody::Failed(body) => {
+            tracing::warn!("failed to read response body for logging");
+            return (Response::from_parts(parts, body), None);
+        }
+    };
+    let logged = match parts.extensions.get::<LogRedaction>() {
+        Some(redaction) => redaction.redact_body(&bytes),
+        None => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    (Response::from_parts(parts, Body::from(bytes)), Some(logged))
+}
+
 /// Create logging middleware with config
 pub fn make_logging_middleware(
     config: LoggingConfig,
@@ -174,6 +360,7 @@ pub fn make_logging_middleware(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{Logs, body_json};
 
     #[test]
     fn test_logging_config_default() {
@@ -209,4 +396,173 @@ mod tests {
         assert_eq!(log.method, "GET");
         assert_eq!(log.path, "/api/users");
     }
+
+    #[derive(Serialize, crate::middleware::Redact)]
+    #[serde(rename_all = "camelCase")]
+    struct PayoutAccount {
+        bank_name: String,
+        #[redact]
+        account_number: String,
+    }
+
+    #[tokio::test]
+    async fn test_redacted_fields_are_masked_in_logs_only() {
+        use crate::middleware::RedactedJson;
+        use axum::{Router, middleware::from_fn, routing::get};
+        use tower::ServiceExt;
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let account = || PayoutAccount {
+            bank_name: "First Bank".to_string(),
+            account_number: "0123456789".to_string(),
+        };
+        let app =
+            Router::new()
+                .route(
+                    "/payout-account",
+                    get(move || async move { RedactedJson(account()) }),
+                )
+                .route(
+                    "/payout-account/verify",
+                    get(move || async move {
+                        (StatusCode::UNPROCESSABLE_ENTITY, RedactedJson(account()))
+                    }),
+                )
+                .layer(from_fn(make_logging_middleware(
+                    LoggingConfig::new().with_error_body(),
+                )));
+
+        for (path, status) in [
+            ("/payout-account", StatusCode::OK),
+            ("/payout-account/verify", StatusCode::UNPROCESSABLE_ENTITY),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+            let sent = body_json(response).await;
+            assert_eq!(sent["accountNumber"], "0123456789");
+        }
+
+        let output = logs.contents();
+        // Only the error response's body was captured, and masked
+        assert_eq!(output.matches("HTTP response body").count(), 1, "{output}");
+        assert!(output.contains(r#""accountNumber":"***""#), "{output}");
+        assert!(output.contains("First Bank"), "{output}");
+        assert!(!output.contains("0123456789"), "{output}");
+    }
+
+    #[cfg(feature = "logging")]
+    #[tokio::test]
+    async fn test_extra_fields_appear_on_every_log_line_of_a_request() {
+        use axum::{Router, middleware::from_fn, routing::get};
+        use tower::ServiceExt;
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = LoggingConfig::new()
+            .with_error_body()
+            .with_extra_field(CorrelationField::header("tenant_id", "x-tenant-id"))
+            .with_extra_field(CorrelationField::new("api_version", |req| {
+                req.uri().path().split('/').nth(1).map(str::to_string)
+            }));
+        let app = Router::new()
+            .route(
+                "/v2/wallets",
+                get(|| async {
+                    tracing::info!("listing wallets");
+                    (StatusCode::CONFLICT, "wallet frozen")
+                }),
+            )
+            .layer(from_fn(make_logging_middleware(config)));
+
+        let response = app
+            .oneshot(
+                Request::get("/v2/wallets")
+                    .header("x-tenant-id", "acme")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let output = logs.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        // started, the handler's own line, response body, completed
+        assert_eq!(lines.len(), 4, "{output}");
+        for line in lines {
+            assert!(line.contains("tenant_id=acme api_version=v2"), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_body_that_fails_to_read_reaches_the_client_unchanged() {
+        use futures::StreamExt;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        /// Promises 64 bytes, then fails after the first chunk
+        struct Interrupted(bool);
+
+        impl HttpBody for Interrupted {
+            type Data = axum::body::Bytes;
+            type Error = std::io::Error;
+
+            fn poll_frame(
+                mut self: Pin<&mut Self>,
+                _cx: &mut Context<'_>,
+            ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Poll::Ready(Some(Err(std::io::Error::other("upstream reset"))));
+                }
+                let chunk = axum::body::Bytes::from_static(b"partial");
+                Poll::Ready(Some(Ok(http_body::Frame::data(chunk))))
+            }
+
+            fn size_hint(&self) -> http_body::SizeHint {
+                http_body::SizeHint::with_exact(64)
+            }
+        }
+
+        let response = Response::new(Body::new(Interrupted(false)));
+        let (response, logged) = capture_body(response, 1024).await;
+        assert_eq!(logged, None);
+
+        let mut body = response.into_body().into_data_stream();
+        assert_eq!(body.next().await.unwrap().unwrap(), "partial");
+        let err = body.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("upstream reset"), "{err}");
+    }
+
+    #[test]
+    fn test_correlation_values_cannot_forge_fields() {
+        let correlation = Correlation(vec![
+            (
+                "tenant_id".to_string(),
+                Some("acme api_version=v9\nforged".to_string()),
+            ),
+            ("api_version".to_string(), Some("v2".to_string())),
+            ("user_id".to_string(), None),
+        ]);
+        assert_eq!(
+            correlation.to_string(),
+            r#"tenant_id="acme api_version=v9\nforged" api_version=v2 user_id=-"#
+        );
+    }
 }
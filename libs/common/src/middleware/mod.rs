@@ -7,28 +7,52 @@
 //!
 //! - **tracking**: Unified middleware for request_id, correlation_id, idempotency_key
 //! - **auth_context**: Extract and manage authentication context from bearer tokens
+//! - **authorization**: Request-scoped, memoized permission and verification-level checks
 //! - **body_limit**: Enforce request body size limits
+//! - **client_ip**: Client address from `X-Forwarded-For` behind trusted proxies
 //! - **compression**: Automatic response compression (gzip, deflate, brotli)
+//! - **conditional**: Conditional GET handling (`If-None-Match` → `304 Not Modified`)
+//! - **cookie_auth**: Signed session cookie authentication with mandatory CSRF checks
 //! - **cors**: Cross-Origin Resource Sharing (CORS) policy enforcement
+//! - **field_naming**: Single switch for the JSON field naming (e.g. camelCase) of every body
+//! - **feature_overrides**: Per-request feature flag overrides for trusted callers
 //! - **idempotency**: Idempotent request handling with deduplication
+//! - **load_shed**: Priority-aware load shedding under overload
 //! - **logging**: Request/response logging with structured tracing
 //! - **metrics**: Performance metrics collection and reporting
 //! - **rate_limit**: Request rate limiting with sliding window algorithm
 //! - **recovery**: Graceful error recovery and panic handling
+//! - **redaction**: Masking of `#[redact]` DTO fields in logged response bodies
 //! - **retry**: Automatic retry logic with exponential backoff
 //! - **timeout**: Request timeout enforcement
 
 #[cfg(feature = "http")]
 pub mod auth_context;
 #[cfg(feature = "http")]
+pub mod authorization;
+#[cfg(feature = "http")]
 pub mod body_limit;
 #[cfg(feature = "http")]
+mod buffer;
+#[cfg(feature = "http")]
+pub mod client_ip;
+#[cfg(feature = "http")]
 pub mod compression;
 #[cfg(feature = "http")]
+pub mod conditional;
+#[cfg(feature = "http")]
+pub mod cookie_auth;
+#[cfg(feature = "http")]
 pub mod cors;
 #[cfg(feature = "http")]
+pub mod feature_overrides;
+#[cfg(feature = "http")]
+pub mod field_naming;
+#[cfg(feature = "http")]
 pub mod idempotency;
 #[cfg(feature = "http")]
+pub mod load_shed;
+#[cfg(feature = "http")]
 pub mod logging;
 #[cfg(feature = "http")]
 pub mod metrics;
@@ -37,6 +61,8 @@ pub mod rate_limit;
 #[cfg(feature = "http")]
 pub mod recovery;
 #[cfg(feature = "http")]
+pub mod redaction;
+#[cfg(feature = "http")]
 pub mod retry;
 #[cfg(feature = "http")]
 pub mod timeout;
@@ -49,14 +75,28 @@ compile_error!("middleware module requires the 'http' feature to be enabled");
 #[cfg(feature = "http")]
 pub use auth_context::*;
 #[cfg(feature = "http")]
+pub use authorization::*;
+#[cfg(feature = "http")]
 pub use body_limit::*;
 #[cfg(feature = "http")]
+pub use client_ip::*;
+#[cfg(feature = "http")]
 pub use compression::*;
 #[cfg(feature = "http")]
+pub use conditional::*;
+#[cfg(feature = "http")]
+pub use cookie_auth::*;
+#[cfg(feature = "http")]
 pub use cors::*;
 #[cfg(feature = "http")]
+pub use feature_overrides::*;
+#[cfg(feature = "http")]
+pub use field_naming::*;
+#[cfg(feature = "http")]
 pub use idempotency::*;
 #[cfg(feature = "http")]
+pub use load_shed::*;
+#[cfg(feature = "http")]
 pub use logging::*;
 #[cfg(feature = "http")]
 pub use metrics::*;
@@ -65,6 +105,8 @@ pub use rate_limit::*;
 #[cfg(feature = "http")]
 pub use recovery::*;
 #[cfg(feature = "http")]
+pub use redaction::*;
+#[cfg(feature = "http")]
 pub use retry::*;
 #[cfg(feature = "http")]
 pub use timeout::*;
@@ -76,7 +118,10 @@ pub use tracking::*;
 pub mod prelude {
     //! Import common middleware items with `use common::middleware::prelude::*;`
     pub use super::{
-        auth_context::*, body_limit::*, compression::*, cors::*, idempotency::*, logging::*,
-        metrics::*, rate_limit::*, recovery::*, retry::*, timeout::*, tracking::*,
+        auth_context::*, authorization::*, body_limit::*, client_ip::*,
+        compression::*, conditional::*, cookie_auth::*, cors::*,
+        feature_overrides::*, field_naming::*, idempotency::*, load_shed::*,
+        logging::*, metrics::*, rate_limit::*, recovery::*, redaction::*,
+        retry::*, timeout::*, tracking::*,
     };
 }
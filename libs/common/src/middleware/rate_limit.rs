@@ -2,16 +2,36 @@
 //!
 //! Implements token bucket and sliding window rate limiting algorithms
 //! to prevent abuse and ensure fair resource usage.
+//!
+//! Anonymous requests are limited per client IP and authenticated requests
+//! per user.  The client IP is the [`ClientIp`] resolved by
+//! [`client_ip_middleware`](super::client_ip::client_ip_middleware), which
+//! must run first.  When a client signs in mid-session, the user's bucket starts
+//! from what the anonymous session had left rather than a full budget, so
+//! logging in is not a way to reset the limit.
+//!
+//! With [`RateLimiter::with_retry_tokens`], every `503` carrying a
+//! `Retry-After` header also gets a signed [`RETRY_TOKEN_HEADER`].  A retry
+//! from the same client presenting that token within the advised window is
+//! admitted once without being counted, so clients backing off from an
+//! outage aren't penalised a second time by the limiter.
 
 use axum::extract::Request;
-use axum::http::StatusCode;
+use axum::http::{HeaderValue, StatusCode, header};
 use axum::middleware::Next;
 use axum::response::Response;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
+use super::auth_context::AuthContext;
+use super::client_ip::ClientIp;
+use crate::security::CookieSigner;
+
+/// Header carrying a retry token on `503` responses, echoed back on retry
+pub const RETRY_TOKEN_HEADER: &str = "x-retry-token";
+
 /// Rate limit key (typically IP address or user ID)
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RateLimitKey(String);
@@ -21,6 +41,16 @@ impl RateLimitKey {
     pub fn new(key: impl Into<String>) -> Self {
         Self(key.into())
     }
+
+    /// Key for an unauthenticated client
+    pub fn anonymous(ip: &str) -> Self {
+        Self(format!("ip:{ip}"))
+    }
+
+    /// Key for an authenticated user
+    pub fn user(user_id: &str) -> Self {
+        Self(format!("user:{user_id}"))
+    }
 }
 
 /// Token bucket for rate limiting
@@ -82,6 +112,9 @@ pub struct RateLimiterConfig {
     pub burst_size: u64,
     /// Cleanup interval for expired entries
     pub cleanup_interval: Duration,
+    /// Start a newly authenticated user's bucket from the anonymous
+    /// bucket of the same client
+    pub carry_anonymous_usage: bool,
 }
 
 impl RateLimiterConfig {
@@ -91,9 +124,16 @@ impl RateLimiterConfig {
             requests_per_second,
             burst_size,
             cleanup_interval: Duration::from_secs(60),
+            carry_anonymous_usage: true,
         }
     }
 
+    /// Enable or disable carrying anonymous usage into the user bucket
+    pub fn with_anonymous_carryover(mut self, enabled: bool) -> Self {
+        self.carry_anonymous_usage = enabled;
+        self
+    }
+
     /// Standard: 100 requests per second
     pub fn standard() -> Self {
         Self::new(100, 120)
@@ -110,11 +150,78 @@ impl RateLimiterConfig {
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Issues and redeems signed tokens exempting a client's retry from rate
+/// limiting
+///
+/// A token is bound to the client key it was issued to and expires
+/// `Retry-After` plus `grace` after issue.  Each token is redeemable once
+/// per instance; the signature lets any instance sharing the secret accept it.
+#[derive(Debug, Clone)]
+pub struct RetryTokens {
+    signer: CookieSigner,
+    grace: Duration,
+    /// Redeemed tokens and their expiry, in unix seconds
+    redeemed: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl RetryTokens {
+    /// Create a token issuer signing with `secret`
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            signer: CookieSigner::new(secret),
+            grace: Duration::from_secs(30),
+            redeemed: Arc::default(),
+        }
+    }
+
+    /// How long past `Retry-After` a token stays valid (default 30s)
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Token for `client` told to retry after `retry_after`
+    pub fn issue(&self, client: &RateLimitKey, retry_after: Duration) -> String {
+        let expires = unix_now() + retry_after.as_secs() + self.grace.as_secs();
+        self.signer.sign(&format!("{expires}:{}", client.0))
+    }
+
+    /// Consume `token` for `client`, returning whether it grants an exemption
+    pub fn redeem(&self, token: &str, client: &RateLimitKey) -> bool {
+        let Some((expires, bound)) = self
+            .signer
+            .verify(token)
+            .and_then(|payload| payload.split_once(':'))
+        else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<u64>() else {
+            return false;
+        };
+        let now = unix_now();
+        if bound != client.0 || expires < now {
+            return false;
+        }
+
+        let mut redeemed = self.redeemed.lock().unwrap();
+        redeemed.retain(|_, expiry| *expiry >= now);
+        redeemed.insert(token.to_string(), expires).is_none()
+    }
+}
+
 /// Rate limiter store
 #[derive(Debug, Clone)]
 pub struct RateLimiter {
     config: RateLimiterConfig,
     buckets: Arc<RwLock<HashMap<String, TokenBucket>>>,
+    retry_tokens: Option<RetryTokens>,
 }
 
 impl RateLimiter {
@@ -123,9 +230,20 @@ impl RateLimiter {
         Self {
             config,
             buckets: Arc::new(RwLock::new(HashMap::new())),
+            retry_tokens: None,
         }
     }
 
+    /// Issue retry tokens on `503` responses and exempt retries presenting one
+    pub fn with_retry_tokens(mut self, tokens: RetryTokens) -> Self {
+        self.retry_tokens = Some(tokens);
+        self
+    }
+
+    pub fn retry_tokens(&self) -> Option<&RetryTokens> {
+        self.retry_tokens.as_ref()
+    }
+
     /// Check if request is allowed
     pub async fn is_allowed(&self, key: &RateLimitKey) -> bool {
         let mut buckets = self.buckets.write().await;
@@ -139,6 +257,38 @@ impl RateLimiter {
         bucket.try_consume(1)
     }
 
+    /// Check a request from the client identified by `anonymous`, counting
+    /// it against `user`'s bucket once the client is authenticated
+    ///
+    /// The first time a user's bucket is created, it inherits the tokens
+    /// left in the anonymous bucket (when carryover is enabled).  Each
+    /// request is counted in exactly one bucket.
+    pub async fn is_allowed_as(&self, anonymous: &RateLimitKey, user: Option<&str>) -> bool {
+        let Some(user) = user else {
+            return self.is_allowed(anonymous).await;
+        };
+
+        let user_key = RateLimitKey::user(user);
+        let mut buckets = self.buckets.write().await;
+        if !buckets.contains_key(&user_key.0) {
+            let mut bucket = TokenBucket::new(
+                self.config.burst_size,
+                self.config.requests_per_second as f64,
+            );
+            if self.config.carry_anonymous_usage
+                && let Some(prior) = buckets.get_mut(&anonymous.0)
+            {
+                prior.refill();
+                bucket.tokens = prior.tokens;
+            }
+            buckets.insert(user_key.0.clone(), bucket);
+        }
+
+        buckets
+            .get_mut(&user_key.0)
+            .is_some_and(|bucket| bucket.try_consume(1))
+    }
+
     /// Get remaining requests for key
     pub async fn remaining(&self, key: &RateLimitKey) -> u64 {
         let buckets = self.buckets.read().await;
@@ -161,22 +311,43 @@ pub async fn rate_limit_middleware(
     next: Next,
     limiter: RateLimiter,
 ) -> Result<Response, StatusCode> {
-    // Extract rate limit key from request (typically from IP or user ID)
-    let key = if let Some(forwarded_for) = req.headers().get("x-forwarded-for") {
-        if let Ok(ip) = forwarded_for.to_str() {
-            RateLimitKey::new(ip)
-        } else {
-            RateLimitKey::new("unknown")
-        }
-    } else {
-        RateLimitKey::new("unknown")
-    };
-
-    if !limiter.is_allowed(&key).await {
+    // Anonymous clients are keyed by IP, authenticated ones by user ID.
+    // The IP is the one client_ip_middleware resolved behind the trusted
+    // proxies; the raw X-Forwarded-For is client-controlled.
+    let ip = req
+        .extensions()
+        .get::<ClientIp>()
+        .map_or_else(|| "unknown".to_string(), ClientIp::to_string);
+    let key = RateLimitKey::anonymous(&ip);
+    let user = req
+        .extensions()
+        .get::<Arc<AuthContext>>()
+        .map(|context| context.user_id.clone());
+
+    let exempt = limiter.retry_tokens.as_ref().is_some_and(|tokens| {
+        req.headers()
+            .get(RETRY_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|token| tokens.redeem(token, &key))
+    });
+    if !exempt && !limiter.is_allowed_as(&key, user.as_deref()).await {
         return Err(StatusCode::TOO_MANY_REQUESTS);
     }
 
-    Ok(next.run(req).await)
+    let mut response = next.run(req).await;
+    if let Some(tokens) = &limiter.retry_tokens
+        && response.status() == StatusCode::SERVICE_UNAVAILABLE
+        && let Some(retry_after) = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+        && let Ok(token) =
+            HeaderValue::from_str(&tokens.issue(&key, Duration::from_secs(retry_after)))
+    {
+        response.headers_mut().insert(RETRY_TOKEN_HEADER, token);
+    }
+    Ok(response)
 }
 
 /// Create rate limit middleware
@@ -230,6 +401,143 @@ mod tests {
         assert!(!limiter.is_allowed(&key).await); // Should be rate limited
     }
 
+    #[tokio::test]
+    async fn test_sign_in_carries_anonymous_usage_without_double_counting() {
+        let limiter = RateLimiter::new(RateLimiterConfig::new(1, 20));
+        let client = RateLimitKey::anonymous("203.0.113.7");
+
+        for _ in 0..5 {
+            assert!(limiter.is_allowed_as(&client, None).await);
+        }
+
+        // Signing in continues from the 15 tokens left, not a fresh 20
+        let mut authenticated = 0;
+        while limiter.is_allowed_as(&client, Some("user-1")).await {
+            authenticated += 1;
+        }
+        assert_eq!(authenticated, 15);
+        // Authenticated requests were not also charged to the IP bucket
+        assert_eq!(limiter.remaining(&client).await, 15);
+
+        let fresh = RateLimiter::new(RateLimiterConfig::new(1, 20).with_anonymous_carryover(false));
+        for _ in 0..5 {
+            assert!(fresh.is_allowed_as(&client, None).await);
+        }
+        fresh.is_allowed_as(&client, Some("user-1")).await;
+        assert_eq!(fresh.remaining(&RateLimitKey::user("user-1")).await, 19);
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_forwarded_for_does_not_escape_the_limit() {
+        use crate::middleware::client_ip::{TrustedProxies, make_client_ip_middleware};
+        use axum::{Router, body::Body, middleware, routing::get};
+        use tower::ServiceExt;
+
+        let limiter = RateLimiter::new(RateLimiterConfig::new(1, 1));
+        let app = Router::new()
+            .route("/orders", get(|| async { "ok" }))
+            .layer(middleware::from_fn(make_rate_limit_middleware(limiter)))
+            .layer(middleware::from_fn(make_client_ip_middleware(
+                TrustedProxies::Hops(1),
+            )));
+        let status = |forwarded: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::get("/orders")
+                    .header("x-forwarded-for", forwarded)
+                    .body(Body::empty())
+                    .unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+
+        assert_eq!(status("1.1.1.1, 203.0.113.7").await, StatusCode::OK);
+        // A new made-up address on the left is still the same client
+        assert_eq!(
+            status("2.2.2.2, 203.0.113.7").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(status("2.2.2.2, 198.51.100.4").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_retry_token_exempts_retry_within_window() {
+        use axum::{Router, body::Body, middleware, routing::get};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use tower::ServiceExt;
+
+        let unavailable = Arc::new(AtomicBool::new(true));
+        let flag = unavailable.clone();
+        let limiter = RateLimiter::new(RateLimiterConfig::new(1, 1))
+            .with_retry_tokens(RetryTokens::new("0123456789abcdef0123456789abcdef"));
+        let app = Router::new()
+            .route(
+                "/orders",
+                get(move || {
+                    let flag = flag.clone();
+                    async move {
+                        if flag.load(Ordering::SeqCst) {
+                            Response::builder()
+                                .status(StatusCode::SERVICE_UNAVAILABLE)
+                                .header(header::RETRY_AFTER, "2")
+                                .body(Body::empty())
+                                .unwrap()
+                        } else {
+                            Response::new(Body::from("ok"))
+                        }
+                    }
+                }),
+            )
+            .layer(middleware::from_fn(make_rate_limit_middleware(limiter)));
+        let request = |token: Option<&str>| {
+            let mut builder = Request::get("/orders").header("x-forwarded-for", "203.0.113.7");
+            if let Some(token) = token {
+                builder = builder.header(RETRY_TOKEN_HEADER, token);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        // The only token in the bucket is spent on the request that gets a 503
+        let response = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let token = response.headers()[RETRY_TOKEN_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        unavailable.store(false, Ordering::SeqCst);
+        let status = |response: Result<Response, _>| response.unwrap().status();
+        assert_eq!(
+            status(app.clone().oneshot(request(None)).await),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(app.clone().oneshot(request(Some(&token))).await),
+            StatusCode::OK
+        );
+        // A token exempts a single retry
+        assert_eq!(
+            status(app.clone().oneshot(request(Some(&token))).await),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[test]
+    fn test_retry_token_is_bound_to_client_and_expires() {
+        let tokens =
+            RetryTokens::new("0123456789abcdef0123456789abcdef").with_grace(Duration::ZERO);
+        let client = RateLimitKey::anonymous("203.0.113.7");
+        let token = tokens.issue(&client, Duration::from_secs(5));
+        assert!(!tokens.redeem(&token, &RateLimitKey::anonymous("198.51.100.1")));
+        assert!(!tokens.redeem(&format!("9{token}"), &client));
+
+        let expired = tokens
+            .signer
+            .sign(&format!("{}:{}", unix_now() - 1, client.0));
+        assert!(!tokens.redeem(&expired, &client));
+        assert!(tokens.redeem(&token, &client));
+    }
+
     #[tokio::test]
     async fn test_rate_limiter_remaining() {
         let limiter = RateLimiter::new(RateLimiterConfig::new(10, 20));
@@ -0,0 +1,110 @@
+//! Redaction of response bodies in logs
+//!
+//! The logging middleware can capture response bodies (all of them, or only
+//! error responses).  DTOs holding sensitive values opt in to masking by
+//! deriving [`Redact`] and marking those fields `#[redact]`, and handlers
+//! return them as [`RedactedJson`].  The client still gets the full body;
+//! only the copy written to the logs has the marked fields replaced with
+//! [`REDACTED`].
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, Redact)]
+//! struct PayoutAccount {
+//!     bank_name: String,
+//!     #[redact]
+//!     account_number: String,
+//! }
+//!
+//! async fn payout_account() -> RedactedJson<PayoutAccount> { ... }
+//! ```
+//!
+//! Marked names are masked wherever they appear in the body, at any depth,
+//! so a DTO wrapped in an envelope or a list is covered too.
+
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde_json::Value;
+
+pub use common_derive::Redact;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "***";
+
+/// Types with fields that must not be logged
+///
+/// Usually derived; see the module docs.
+pub trait Redact {
+    /// JSON names of the fields to mask
+    fn redacted_fields() -> &'static [&'static str];
+}
+
+impl<T: Redact> Redact for Vec<T> {
+    fn redacted_fields() -> &'static [&'static str] {
+        T::redacted_fields()
+    }
+}
+
+impl<T: Redact> Redact for Option<T> {
+    fn redacted_fields() -> &'static [&'static str] {
+        T::redacted_fields()
+    }
+}
+
+/// Response extension telling the logging middleware what to mask
+#[derive(Debug, Clone, Copy)]
+pub struct LogRedaction {
+    fields: &'static [&'static str],
+}
+
+impl LogRedaction {
+    /// Redaction for bodies serialized from `T`
+    pub fn of<T: Redact>() -> Self {
+        Self {
+            fields: T::redacted_fields(),
+        }
+    }
+
+    /// Mask the redacted fields of `value` in place
+    pub fn apply(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.fields.contains(&key.as_str()) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.apply(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+
+    /// `body` as it may be logged
+    ///
+    /// A body that isn't JSON can't be masked field by field, so none of it
+    /// is logged.
+    pub fn redact_body(&self, body: &[u8]) -> String {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                self.apply(&mut value);
+                value.to_string()
+            }
+            Err(_) => REDACTED.to_string(),
+        }
+    }
+}
+
+/// JSON response whose logged copy is redacted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedactedJson<T>(pub T);
+
+impl<T: Serialize + Redact> IntoResponse for RedactedJson<T> {
+    fn into_response(self) -> Response {
+        let mut response = Json(self.0).into_response();
+        response.extensions_mut().insert(LogRedaction::of::<T>());
+        response
+    }
+}
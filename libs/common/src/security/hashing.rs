@@ -3,6 +3,16 @@
 //! Provides secure password hashing and verification using industry-standard algorithms.
 //! This module requires no external dependencies for basic hashing,
 //! but argon2 feature can be enabled for Argon2 support.
+//!
+//! Wrap a hasher in [`TimedHasher`] to record how long each hash and verify
+//! takes as the `password_hash_duration_seconds` histogram (labelled by
+//! `operation` and `algorithm`) on the installed metrics recorder, which is
+//! what `infrastructure::observability::MetricsExporter` serves.  Argon2
+//! cost has to keep pace with hardware: durations drifting low mean the
+//! parameters have become weak, drifting high means logins are becoming a
+//! denial-of-service vector.
+
+use std::time::Instant;
 
 use crate::value_objects::security::PasswordHash;
 
@@ -105,6 +115,104 @@ impl PasswordHasher for HmacSha256Hasher {
     }
 }
 
+/// Argon2id hasher producing PHC strings (`$argon2id$v=19$...`)
+#[cfg(feature = "argon2")]
+pub struct Argon2Hasher {
+    argon2: argon2::Argon2<'static>,
+}
+
+#[cfg(feature = "argon2")]
+impl Default for Argon2Hasher {
+    /// OWASP-recommended parameters (19 MiB, 2 iterations, 1 lane)
+    fn default() -> Self {
+        Self {
+            argon2: argon2::Argon2::default(),
+        }
+    }
+}
+
+#[cfg(feature = "argon2")]
+impl Argon2Hasher {
+    /// Hasher with explicit memory (KiB), iteration and parallelism costs
+    pub fn with_params(m_cost: u32, t_cost: u32, p_cost: u32) -> HashResult<Self> {
+        let params = argon2::Params::new(m_cost, t_cost, p_cost, None)
+            .map_err(|e| HashError::HashingError(e.to_string()))?;
+        Ok(Self {
+            argon2: argon2::Argon2::new(
+                argon2::Algorithm::Argon2id,
+                argon2::Version::V0x13,
+                params,
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "argon2")]
+impl PasswordHasher for Argon2Hasher {
+    fn hash(&self, password: impl AsRef<[u8]>) -> HashResult<PasswordHash> {
+        use password_hash::{PasswordHasher as _, SaltString, rand_core::OsRng};
+
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2
+            .hash_password(password.as_ref(), &salt)
+            .map(|hash| PasswordHash::new(hash.to_string()))
+            .map_err(|e| HashError::HashingError(e.to_string()))
+    }
+
+    fn verify(&self, password: impl AsRef<[u8]>, hash: &PasswordHash) -> HashResult<bool> {
+        use password_hash::{PasswordHash as PhcHash, PasswordVerifier as _};
+
+        let parsed = PhcHash::new(hash.as_str()).map_err(|_| HashError::InvalidHashFormat)?;
+        match self.argon2.verify_password(password.as_ref(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(HashError::HashingError(e.to_string())),
+        }
+    }
+}
+
+/// Hasher that records the duration of every hash and verify
+pub struct TimedHasher<H> {
+    inner: H,
+    algorithm: &'static str,
+}
+
+impl<H: PasswordHasher> TimedHasher<H> {
+    /// Time `inner`, labelling samples with `algorithm` (e.g. `"argon2id"`)
+    pub fn new(inner: H, algorithm: &'static str) -> Self {
+        Self { inner, algorithm }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    fn record(&self, operation: &'static str, started: Instant) {
+        metrics::histogram!(
+            "password_hash_duration_seconds",
+            "operation" => operation,
+            "algorithm" => self.algorithm
+        )
+        .record(started.elapsed().as_secs_f64());
+    }
+}
+
+impl<H: PasswordHasher> PasswordHasher for TimedHasher<H> {
+    fn hash(&self, password: impl AsRef<[u8]>) -> HashResult<PasswordHash> {
+        let started = Instant::now();
+        let result = self.inner.hash(password);
+        self.record("hash", started);
+        result
+    }
+
+    fn verify(&self, password: impl AsRef<[u8]>, hash: &PasswordHash) -> HashResult<bool> {
+        let started = Instant::now();
+        let result = self.inner.verify(password, hash);
+        self.record("verify", started);
+        result
+    }
+}
+
 /// Password strength validator
 pub struct PasswordStrength;
 
@@ -185,6 +293,39 @@ mod tests {
         assert!(hasher.verify(password, &hash).unwrap());
     }
 
+    #[test]
+    fn test_timed_hasher_records_hash_and_verify_samples() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let hasher = TimedHasher::new(Sha256Hasher, "sha256");
+        let recorder = PrometheusBuilder::new().build_recorder();
+        metrics::with_local_recorder(&recorder, || {
+            let hash = hasher.hash("MyPassword123!").unwrap();
+            assert!(hasher.verify("MyPassword123!", &hash).unwrap());
+            assert!(!hasher.verify("wrong", &hash).unwrap());
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(rendered.contains(
+            r#"password_hash_duration_seconds_count{operation="hash",algorithm="sha256"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"password_hash_duration_seconds_count{operation="verify",algorithm="sha256"} 2"#
+        ));
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn test_argon2_hash() {
+        // Minimal cost keeps the test fast
+        let hasher = Argon2Hasher::with_params(8, 1, 1).unwrap();
+        let hash = hasher.hash("MyPassword123!").unwrap();
+        assert!(hash.as_str().starts_with("$argon2id$"));
+        assert!(hasher.verify("MyPassword123!", &hash).unwrap());
+        assert!(!hasher.verify("wrong", &hash).unwrap());
+        assert!(hasher.verify("MyPassword123!", &PasswordHash::new("nope")).is_err());
+    }
+
     #[test]
     fn test_password_strength() {
         assert!(PasswordStrength::has_uppercase("Hello"));
@@ -6,8 +6,11 @@
 //! ## Organization
 //!
 //! - `hashing` - Password hashing and strength validation
+//! - `jwt` - JWT issuance and verification, including certificate-bound tokens
 //! - `csrf` - CSRF token generation and validation
 //! - `secrets` - Cryptographically secure random generation
+//! - `signed_cookie` - HMAC-signed cookie values
+//! - `signed_url` - Time-limited HMAC-signed URLs
 //!
 //! ## Quick Start
 //!
@@ -26,11 +29,22 @@
 
 pub mod csrf;
 pub mod hashing;
+pub mod jwt;
 pub mod secrets;
+pub mod signed_cookie;
+pub mod signed_url;
 
 pub use csrf::{CsrfGenerator, CsrfToken, CsrfValidator};
-pub use hashing::{HmacSha256Hasher, PasswordHasher, PasswordStrength, Sha256Hasher};
+pub use hashing::{HmacSha256Hasher, PasswordHasher, PasswordStrength, Sha256Hasher, TimedHasher};
+#[cfg(feature = "argon2")]
+pub use hashing::Argon2Hasher;
+pub use jwt::{
+    Actor, Confirmation, DEFAULT_MAX_TOKEN_BYTES, DEFAULT_WARN_TOKEN_BYTES, JwtClaims, JwtService,
+    TokenType, cert_thumbprint,
+};
 pub use secrets::{RandomGenerator, SecretGenerator, SecretError, SecretResult};
+pub use signed_cookie::CookieSigner;
+pub use signed_url::{SignedUrl, SignedUrlClaims};
 
 /// Prelude module for convenient importing
 pub mod prelude {
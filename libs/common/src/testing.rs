@@ -0,0 +1,52 @@
+//! Test helpers shared by the workspace crates
+//!
+//! Compiled for this crate's own tests and, through the `testing` feature,
+//! for the dev-dependencies of the others.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// In-memory log sink for a `tracing_subscriber::fmt` writer
+///
+/// Clones share the buffer, so hand one to `with_writer(move || logs.clone())`
+/// and read what was written from another.
+#[derive(Clone, Default)]
+pub struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The whole body of `response`
+#[cfg(feature = "http")]
+pub async fn body_bytes(response: impl axum::response::IntoResponse) -> axum::body::Bytes {
+    axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+        .await
+        .unwrap()
+}
+
+/// The body of `response` as UTF-8 text
+#[cfg(feature = "http")]
+pub async fn body_text(response: impl axum::response::IntoResponse) -> String {
+    String::from_utf8(body_bytes(response).await.to_vec()).unwrap()
+}
+
+/// The body of `response` parsed as JSON
+#[cfg(feature = "http")]
+pub async fn body_json(response: impl axum::response::IntoResponse) -> serde_json::Value {
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
@@ -22,6 +22,20 @@ impl Clock {
     }
 }
 
+/// Source of the current time
+///
+/// Code that depends on what time it is takes one of these instead of
+/// calling [`Clock::now`] directly, so tests can pin the time.
+pub trait TimeSource: Send + Sync {
+    fn now(&self) -> Timestamp;
+}
+
+impl TimeSource for Clock {
+    fn now(&self) -> Timestamp {
+        Clock::now()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -12,12 +12,12 @@ mod clock;
 mod intervals;
 mod utils;
 
-pub use clock::Clock;
+pub use clock::{Clock, TimeSource};
 pub use intervals::{Interval, RateWindow};
 pub use utils::{Elapsed, TimeUtils};
 
 /// Prelude for time module
 pub mod prelude {
     //! Import common time items with `use common::time::prelude::*;`
-    pub use super::{Clock, Elapsed, Interval, RateWindow, TimeUtils};
+    pub use super::{Clock, Elapsed, Interval, RateWindow, TimeSource, TimeUtils};
 }
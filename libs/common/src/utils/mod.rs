@@ -7,7 +7,7 @@
 //! - `strings` - String manipulation and formatting utilities
 //! - `encoding` - JSON (basic), Base64, and Hex encoding utilities
 //! - `json` - JSON construction and response builders
-//! - `serde` - Serialization helpers and rename utilities
+//! - `serde` - Serialization helpers, rename utilities and string-encoded amounts
 
 pub mod encoding;
 pub mod json;
@@ -16,7 +16,7 @@ pub mod strings;
 
 pub use encoding::{Base64Utils, HexUtils, JsonUtils};
 pub use json::{JsonBuilder, JsonResponse};
-pub use serde::{OptionalField, compact, rename};
+pub use serde::{OptionalField, amount_string, compact, rename};
 pub use strings::StringUtils;
 
 /// Prelude module for convenient importing
@@ -24,6 +24,6 @@ pub mod prelude {
     //! Import common utilities with `use common::utils::prelude::*;`
     pub use super::{
         Base64Utils, HexUtils, JsonBuilder, JsonResponse, JsonUtils, OptionalField, StringUtils,
-        compact, rename,
+        amount_string, compact, rename,
     };
 }
@@ -72,6 +72,96 @@ pub mod compact {
     }
 }
 
+/// Amounts as JSON strings, so values beyond 2^53 survive JavaScript clients
+///
+/// Serializes any `Display` amount (minor-unit integers, decimals) as a
+/// string.  Deserialization accepts both `"1050"` and `1050`; prefer strings
+/// for fractional values, since JSON numbers with a fraction pass through
+/// `f64`.
+///
+/// ```rust
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Payout {
+///     #[serde(with = "common::utils::serde::amount_string")]
+///     amount: i64,
+/// }
+///
+/// let payout: Payout = serde_json::from_str(r#"{"amount": 1050}"#).unwrap();
+/// assert_eq!(serde_json::to_string(&payout).unwrap(), r#"{"amount":"1050"}"#);
+/// ```
+pub mod amount_string {
+    use serde::{Deserializer, Serializer, de};
+    use std::fmt::{self, Display};
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+
+    /// Serialize the amount as a string
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display,
+        S: Serializer,
+    {
+        serializer.collect_str(value)
+    }
+
+    /// Deserialize the amount from a string or a number
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(AmountVisitor(PhantomData))
+    }
+
+    struct AmountVisitor<T>(PhantomData<T>);
+
+    impl<T> AmountVisitor<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        fn parse<E: de::Error>(raw: &str) -> Result<T, E> {
+            raw.trim()
+                .parse()
+                .map_err(|e| E::custom(format!("invalid amount '{raw}': {e}")))
+        }
+    }
+
+    impl<T> de::Visitor<'_> for AmountVisitor<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an amount as a string or number")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            Self::parse(v)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+            Self::parse(&v.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+            Self::parse(&v.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+            if !v.is_finite() {
+                return Err(E::custom("amount must be finite"));
+            }
+            Self::parse(&v.to_string())
+        }
+    }
+}
+
 /// Rename rules for different serialization styles
 pub mod rename {
     /// Convert to snake_case
@@ -134,6 +224,50 @@ pub mod rename {
     pub fn to_screaming_snake_case(s: &str) -> String {
         s.to_uppercase()
     }
+
+    /// How field names of JSON bodies are spelled on the wire
+    ///
+    /// Structs keep their snake_case field names; services pick one naming
+    /// for every body they exchange and have keys rewritten to it (see
+    /// `middleware::field_naming`) instead of attributing each DTO.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum FieldNaming {
+        /// `user_id`, as the structs are written
+        #[default]
+        SnakeCase,
+        /// `userId`
+        CamelCase,
+    }
+
+    impl FieldNaming {
+        /// `key` spelled with this naming
+        pub fn apply(self, key: &str) -> String {
+            match self {
+                Self::SnakeCase => to_snake_case(key),
+                Self::CamelCase => to_camel_case(key),
+            }
+        }
+
+        /// Rename the keys of every object in `value`, recursively; values
+        /// are left alone
+        pub fn rename_keys(self, value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Object(map) => {
+                    *map = std::mem::take(map)
+                        .into_iter()
+                        .map(|(key, mut value)| {
+                            self.rename_keys(&mut value);
+                            (self.apply(&key), value)
+                        })
+                        .collect();
+                }
+                serde_json::Value::Array(items) => {
+                    items.iter_mut().for_each(|item| self.rename_keys(item));
+                }
+                _ => {}
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -147,6 +281,27 @@ mod tests {
         assert_eq!(field.as_ref(), Some(&"test".to_string()));
     }
 
+    #[test]
+    fn test_large_amount_round_trips_without_precision_loss() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Escrow {
+            #[serde(with = "amount_string")]
+            amount: i64,
+        }
+
+        // 2^53 + 1 is the first integer an f64 cannot represent
+        let escrow = Escrow {
+            amount: 9_007_199_254_740_993,
+        };
+        let json = serde_json::to_string(&escrow).unwrap();
+        assert_eq!(json, r#"{"amount":"9007199254740993"}"#);
+        assert_eq!(serde_json::from_str::<Escrow>(&json).unwrap(), escrow);
+
+        let from_number: Escrow = serde_json::from_str(r#"{"amount": 9007199254740993}"#).unwrap();
+        assert_eq!(from_number, escrow);
+        assert!(serde_json::from_str::<Escrow>(r#"{"amount": "12.5"}"#).is_err());
+    }
+
     #[test]
     fn test_rename_snake_case() {
         assert_eq!(rename::to_snake_case("firstName"), "first_name");
@@ -159,6 +314,24 @@ mod tests {
         assert_eq!(rename::to_camel_case("user_id"), "userId");
     }
 
+    #[test]
+    fn test_field_naming_renames_nested_keys_only() {
+        let mut value = serde_json::json!({
+            "user_id": "u_1",
+            "payout_accounts": [{ "bank_name": "first_bank" }],
+        });
+        rename::FieldNaming::CamelCase.rename_keys(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "userId": "u_1",
+                "payoutAccounts": [{ "bankName": "first_bank" }],
+            })
+        );
+        rename::FieldNaming::SnakeCase.rename_keys(&mut value);
+        assert_eq!(value["payout_accounts"][0]["bank_name"], "first_bank");
+    }
+
     #[test]
     fn test_rename_pascal_case() {
         assert_eq!(rename::to_pascal_case("first_name"), "FirstName");
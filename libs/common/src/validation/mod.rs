@@ -7,6 +7,7 @@
 //!
 //! - `rules` - Basic validation rules (StringRules, EmailRules, PhoneRules, etc.)
 //! - `request` - Request-level validation traits and builders
+//! - `normalize` - Input clean-up applied before validation (emails, phones)
 //!
 //! ## Quick Start
 //!
@@ -21,9 +22,11 @@
 //! }
 //! ```
 
+pub mod normalize;
 pub mod request;
 pub mod rules;
 
+pub use normalize::{Normalize, normalize_email, normalize_phone};
 pub use request::{RequestValidator, Validate, ValidateWith, ValidationBuilder};
 pub use rules::{
     EmailRules, NumberRules, PhoneRules, StringRules, ValidationError, ValidationErrors,
@@ -35,7 +38,7 @@ pub mod prelude {
     //! Import common validation items with `use common::validation::prelude::*;`
     pub use super::rules::*;
     pub use super::{
-        EmailRules, NumberRules, PhoneRules, RequestValidator, StringRules, Validate,
+        EmailRules, Normalize, NumberRules, PhoneRules, RequestValidator, StringRules, Validate,
         ValidateWith, ValidationBuilder, ValidationErrors, ValidationResult,
     };
 }
@@ -0,0 +1,94 @@
+//! Request normalization
+//!
+//! Cleans up client input before it is validated, so that values which mean
+//! the same thing are stored the same way.  `" Ada@Example.COM "` and
+//! `"ada@example.com"` must not become two accounts.
+//!
+//! Request DTOs implement [`Normalize`] by normalizing each field;
+//! `extractors::ValidatedJson` calls it right after deserializing and before
+//! [`Validate`](super::Validate).
+
+use crate::value_objects::contact::{EmailAddress, PhoneNumber};
+
+/// Types that can put their values into canonical form
+pub trait Normalize {
+    /// Rewrite `self` in place into its canonical form
+    fn normalize(&mut self);
+}
+
+/// Trimmed and lowercased email address
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Phone number without the spaces, dashes, dots and parentheses people
+/// type into it; a leading `00` becomes `+`
+pub fn normalize_phone(phone: &str) -> String {
+    let digits: String = phone
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    match digits.strip_prefix("00") {
+        Some(rest) => format!("+{rest}"),
+        None => digits,
+    }
+}
+
+impl Normalize for String {
+    fn normalize(&mut self) {
+        let trimmed = self.trim();
+        if trimmed.len() != self.len() {
+            *self = trimmed.to_string();
+        }
+    }
+}
+
+impl Normalize for EmailAddress {
+    fn normalize(&mut self) {
+        self.0 = normalize_email(&self.0);
+    }
+}
+
+impl Normalize for PhoneNumber {
+    fn normalize(&mut self) {
+        self.0 = normalize_phone(&self.0);
+    }
+}
+
+impl<T: Normalize> Normalize for Option<T> {
+    fn normalize(&mut self) {
+        if let Some(value) = self {
+            value.normalize();
+        }
+    }
+}
+
+impl<T: Normalize> Normalize for Vec<T> {
+    fn normalize(&mut self) {
+        self.iter_mut().for_each(Normalize::normalize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_emails_and_phones() {
+        let mut email = EmailAddress(" Ada.Lovelace@Example.COM\n".into());
+        email.normalize();
+        assert_eq!(email.as_str(), "ada.lovelace@example.com");
+
+        let mut phones = vec![
+            PhoneNumber("+234 (801) 234-5678".into()),
+            PhoneNumber("00234.801.234.5678".into()),
+        ];
+        phones.normalize();
+        assert!(phones.iter().all(|p| p.as_str() == "+2348012345678"));
+
+        let mut name = Some("  Ada ".to_string());
+        name.normalize();
+        assert_eq!(name.as_deref(), Some("Ada"));
+    }
+}
@@ -8,7 +8,8 @@
 //!
 //! - `identity` - User and resource identifiers (UserId, ResourceId, DeviceId)
 //! - `contact` - Contact information (EmailAddress, PhoneNumber)
-//! - `security` - Security-related objects (PasswordHash, Secret, ApiKey)
+//! - `money` - Monetary amounts in minor units (Money)
+//! - `security` - Security-related objects (PasswordHash, Secret, ApiKey, Scope)
 //! - `network` - Network identifiers (Url, IpAddress, UserAgent)
 //! - `pagination_vo` - Query pagination and sorting (Pagination, Sort, SearchParams)
 //! - `timestamps` - Time-related objects (Timestamp, Duration, TimeRange)
@@ -26,6 +27,7 @@
 pub mod contact;
 pub mod core;
 pub mod identity;
+pub mod money;
 pub mod network;
 pub mod pagination_vo;
 pub mod security;
@@ -37,6 +39,8 @@ pub mod ulid;
 
 // Re-export all tracking types from unified tracking module
 pub use contact::{EmailAddress, PhoneNumber};
-pub use security::{ApiKey, PasswordHash, Secret};
+pub use money::Money;
+pub use security::{ApiKey, PasswordHash, Scope, Secret};
 pub use timestamps::{Duration, TimeRange, Timestamp};
 pub use tracking::{CorrelationId, IdempotencyKey, RequestId, TrackingContext};
+pub use ulid::Ulid;
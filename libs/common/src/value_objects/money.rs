@@ -0,0 +1,121 @@
+//! Money value object
+//!
+//! Amounts are held in minor units (kobo, cents) to avoid floating point
+//! arithmetic, and serialized as strings so large values survive clients
+//! that parse JSON numbers as `f64`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Monetary amount in the minor units of an ISO 4217 currency
+///
+/// # Example
+///
+/// ```rust
+/// use common::value_objects::Money;
+///
+/// let price = Money::new(250_000, "NGN").unwrap();
+/// let json = serde_json::to_string(&price).unwrap();
+/// assert_eq!(json, r#"{"amount":"250000","currency":"NGN"}"#);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "MoneyFields")]
+pub struct Money {
+    /// Amount in minor units
+    #[serde(with = "crate::utils::serde::amount_string")]
+    amount: i64,
+    currency: String,
+}
+
+/// `Money` as it appears on the wire, before validation
+#[derive(Deserialize)]
+struct MoneyFields {
+    #[serde(with = "crate::utils::serde::amount_string")]
+    amount: i64,
+    currency: String,
+}
+
+impl Money {
+    /// Create a new amount, validating the currency code
+    ///
+    /// Returns `Self` if valid, or an error string if invalid.
+    pub fn new(amount: i64, currency: impl Into<String>) -> Result<Self, String> {
+        let currency = currency.into().to_ascii_uppercase();
+        if currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic()) {
+            Ok(Self { amount, currency })
+        } else {
+            Err(format!("Invalid currency code: {}", currency))
+        }
+    }
+
+    /// Amount in minor units
+    pub fn amount(&self) -> i64 {
+        self.amount
+    }
+
+    /// ISO 4217 currency code
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount == 0
+    }
+
+    /// Sum of two amounts; `None` on overflow or a currency mismatch
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(Self {
+            amount: self.amount.checked_add(other.amount)?,
+            currency: self.currency.clone(),
+        })
+    }
+}
+
+impl TryFrom<MoneyFields> for Money {
+    type Error = String;
+
+    fn try_from(fields: MoneyFields) -> Result<Self, Self::Error> {
+        Self::new(fields.amount, fields.currency)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_money_round_trips_large_amounts_as_strings() {
+        let escrow = Money::new(9_007_199_254_740_993, "ngn").unwrap();
+        let json = serde_json::to_string(&escrow).unwrap();
+        assert_eq!(json, r#"{"amount":"9007199254740993","currency":"NGN"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), escrow);
+    }
+
+    #[test]
+    fn test_money_deserialization_validates_currency() {
+        let err =
+            serde_json::from_str::<Money>(r#"{"amount":"100","currency":"NAIRA"}"#).unwrap_err();
+        assert!(err.to_string().contains("Invalid currency code"), "{err}");
+
+        let money: Money = serde_json::from_str(r#"{"amount":"100","currency":"ngn"}"#).unwrap();
+        assert_eq!(money.currency(), "NGN");
+    }
+
+    #[test]
+    fn test_money_validation_and_addition() {
+        assert!(Money::new(100, "NAIRA").is_err());
+        let a = Money::new(100, "NGN").unwrap();
+        let b = Money::new(50, "NGN").unwrap();
+        assert_eq!(a.checked_add(&b).unwrap().amount(), 150);
+        assert!(a.checked_add(&Money::new(50, "USD").unwrap()).is_none());
+    }
+}
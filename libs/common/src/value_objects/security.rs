@@ -1,9 +1,11 @@
 //! Security-related value objects
 //!
-//! This module contains value objects for security-sensitive data like password hashes and secrets.
+//! This module contains value objects for security-sensitive data like password hashes and secrets,
+//! and the scopes granted to API keys and access tokens.
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
 /// Password hash wrapper for secure storage
 ///
@@ -123,6 +125,88 @@ impl fmt::Display for ApiKey {
     }
 }
 
+/// Scope granted to an API key or access token
+///
+/// A `resource:action` pair such as `orders:write`, in lowercase ASCII
+/// letters, digits, `_`, `-` and `.`.  Scopes match exactly: holding
+/// `orders:write` does not imply `orders:read`.
+///
+/// # Example
+///
+/// ```rust
+/// use common::value_objects::Scope;
+///
+/// let scope = Scope::new("orders:write").unwrap();
+/// assert_eq!(scope.resource(), "orders");
+/// assert_eq!(scope.action(), "write");
+/// assert!(Scope::new("orders").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Scope(String);
+
+impl Scope {
+    /// Create a scope, validating its `resource:action` form
+    pub fn new(scope: impl Into<String>) -> Result<Self, String> {
+        let scope = scope.into();
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && part.bytes().all(|b| {
+                    b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'_' | b'-' | b'.')
+                })
+        };
+        match scope.split_once(':') {
+            Some((resource, action)) if valid_part(resource) && valid_part(action) => {
+                Ok(Self(scope))
+            }
+            _ => Err(format!("Invalid scope '{scope}': expected 'resource:action'")),
+        }
+    }
+
+    /// Resource the scope grants access to, e.g. `orders`
+    pub fn resource(&self) -> &str {
+        self.0.split_once(':').map_or(&self.0, |(resource, _)| resource)
+    }
+
+    /// Action the scope allows on its resource, e.g. `write`
+    pub fn action(&self) -> &str {
+        self.0.split_once(':').map_or("", |(_, action)| action)
+    }
+
+    /// Get the scope as string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(scope: String) -> Result<Self, Self::Error> {
+        Self::new(scope)
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.0
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,4 +237,20 @@ mod tests {
         let long_key = ApiKey::new("a".repeat(35));
         assert!(long_key.is_valid());
     }
+
+    #[test]
+    fn test_scope_validation() {
+        let scope: Scope = "orders:write".parse().unwrap();
+        assert_eq!((scope.resource(), scope.action()), ("orders", "write"));
+        assert_eq!(scope.to_string(), "orders:write");
+
+        for invalid in ["", "orders", ":write", "orders:", "Orders:write", "orders:write all"] {
+            assert!(Scope::new(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(
+            serde_json::from_str::<Scope>(r#""orders:read""#).unwrap(),
+            Scope::new("orders:read").unwrap()
+        );
+        assert!(serde_json::from_str::<Scope>(r#""orders""#).is_err());
+    }
 }
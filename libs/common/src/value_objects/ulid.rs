@@ -0,0 +1,102 @@
+//! ULID identifiers
+//!
+//! Resources are keyed by ULIDs: 26-character, lexicographically sortable
+//! identifiers in Crockford base32.  [`Ulid`] parses them case-insensitively
+//! and always displays the canonical uppercase form, so a malformed ID is
+//! rejected where it enters (a path segment or a JSON field) rather than
+//! deep inside a lookup.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// ULID value object
+///
+/// # Example
+///
+/// ```rust
+/// use common::value_objects::Ulid;
+///
+/// let id: Ulid = "01arz3ndektsv4rrffq69g5fav".parse().unwrap();
+/// assert_eq!(id.to_string(), "01ARZ3NDEKTSV4RRFFQ69G5FAV");
+/// assert!("not-a-ulid".parse::<Ulid>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Ulid(pub ulid::Ulid);
+
+impl Ulid {
+    /// Generate a new ULID for the current time
+    pub fn new() -> Self {
+        Self(ulid::Ulid::new())
+    }
+
+    /// Parse a ULID, failing with a message naming the rejected value
+    pub fn parse(s: &str) -> Result<Self, String> {
+        ulid::Ulid::from_string(s)
+            .map(Self)
+            .map_err(|e| format!("Invalid ULID '{s}': {e}"))
+    }
+
+    /// Milliseconds since the Unix epoch encoded in the ULID
+    pub fn timestamp_ms(&self) -> u64 {
+        self.0.timestamp_ms()
+    }
+}
+
+impl Default for Ulid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for Ulid {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl From<Ulid> for String {
+    fn from(id: Ulid) -> Self {
+        id.to_string()
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ulid_round_trip() {
+        let id = Ulid::new();
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{id}\""));
+        assert_eq!(serde_json::from_str::<Ulid>(&json).unwrap(), id);
+
+        for invalid in [
+            "",
+            "01ARZ3NDEKTSV4RRFFQ69G5FA",
+            "01ARZ3NDEKTSV4RRFFQ69G5FAU!",
+            "01ARZ3NDEKTSV4RRFFQ69G5FAU",
+        ] {
+            let err = Ulid::parse(invalid).unwrap_err();
+            assert!(err.contains(invalid), "{err}");
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! `#[derive(Config)]` for typed configuration sections
+//!
+//! Generates an inherent `from_loader(&ConfigLoader) -> ConfigResult<Self>`
+//! from per-field attributes:
+//!
+//! - `#[config(key = "REDIS_URL")]` reads a required key
+//! - `#[config(key = "REDIS_MAX_CONNECTIONS", default = 50)]` falls back to
+//!   `default` when the key is missing; a string literal default is parsed
+//!   like a configured value, so `default = "app"` works for `String` fields
+//! - `#[config(key = "REDIS_COMMAND_TIMEOUT", default = "5s", with = ConfigDuration::into_time)]`
+//!   converts the raw value with a function before storing it; durations
+//!   are read as `config::core::duration::ConfigDuration` so they can be
+//!   written as `30s` or `1h30m`
+//! - `#[config(key = "REDIS_REQUIRE_TLS", default_for = secure_by_default)]`
+//!   falls back to `secure_by_default(&environment)` when the key is missing,
+//!   for defaults that differ between development and production
+//! - `#[config(nested)]` loads the field with its own `from_loader`
+//! - `#[config(skip)]` uses the field's `Default`
+//!
+//! `Option<T>` fields without a default are `None` when the key is missing.
+//! A struct-level `#[config(validate = Self::validate_for)]` runs
+//! `validate_for(&self, &environment)` on the loaded section, so
+//! `from_loader` only returns sections valid for the loader's `APP_ENV`.
+//! Errors are wrapped in `ConfigError::Field` so they name both the struct
+//! field and the key.
+//!
+//! Use the re-export `config::Config` rather than depending on this crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Expr, ExprLit, Field, Fields, GenericArgument, Lit, LitStr, Path,
+    PathArguments, Type, parse_macro_input,
+};
+
+#[proc_macro_derive(Config, attributes(config))]
+pub fn derive_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    key: Option<LitStr>,
+    default: Option<Expr>,
+    default_for: Option<Path>,
+    with: Option<Path>,
+    nested: bool,
+    skip: bool,
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("config")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("key") {
+                    attrs.key = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default") {
+                    attrs.default = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default_for") {
+                    attrs.default_for = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("with") {
+                    attrs.with = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("nested") {
+                    attrs.nested = true;
+                } else if meta.path.is_ident("skip") {
+                    attrs.skip = true;
+                } else {
+                    return Err(meta.error(
+                        "unknown config attribute; expected `key`, `default`, `default_for`, `with`, `nested` or `skip`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(attrs)
+    }
+}
+
+/// Struct-level `#[config(...)]` attributes
+#[derive(Default)]
+struct StructAttrs {
+    validate: Option<Path>,
+}
+
+impl StructAttrs {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+        for attr in input.attrs.iter().filter(|a| a.path().is_ident("config")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("validate") {
+                    attrs.validate = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown config attribute; expected `validate`"))
+                }
+            })?;
+        }
+        Ok(attrs)
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "#[derive(Config)] requires a struct with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "#[derive(Config)] only supports structs",
+            ));
+        }
+    };
+
+    let initializers = fields
+        .iter()
+        .map(|field| expand_field(name, field))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let validate = StructAttrs::parse(input)?.validate.map(|validate| {
+        quote! {
+            #validate(&__section, &__loader.environment())?;
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Load this section from configuration sources
+            pub fn from_loader(
+                __loader: &::config::loader::ConfigLoader,
+            ) -> ::config::core::error::ConfigResult<Self> {
+                let __section = Self {
+                    #(#initializers,)*
+                };
+                #validate
+                ::std::result::Result::Ok(__section)
+            }
+        }
+    })
+}
+
+fn expand_field(owner: &syn::Ident, field: &Field) -> syn::Result<TokenStream2> {
+    let ident = field.ident.as_ref().expect("named field");
+    let ty = &field.ty;
+    let attrs = FieldAttrs::parse(field)?;
+
+    if attrs.skip {
+        return Ok(quote!(#ident: ::std::default::Default::default()));
+    }
+    if attrs.nested {
+        return Ok(quote!(#ident: <#ty>::from_loader(__loader)?));
+    }
+
+    let key = attrs.key.ok_or_else(|| {
+        syn::Error::new_spanned(
+            ident,
+            "config field needs `#[config(key = \"...\")]`, `nested` or `skip`",
+        )
+    })?;
+    let label = format!("{owner}.{ident}");
+    let wrap = quote! {
+        |err| ::config::core::error::ConfigError::field(#label, err)
+    };
+
+    let optional = option_inner(ty);
+    if optional.is_some() && attrs.default.is_some() {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`Option` fields are `None` when missing and cannot have a default",
+        ));
+    }
+
+    if attrs.default_for.is_some() && (attrs.default.is_some() || optional.is_some()) {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`default_for` cannot be combined with `default` or an `Option` field",
+        ));
+    }
+
+    let missing = match (&attrs.default, optional) {
+        _ if attrs.default_for.is_some() => {
+            let default_for = &attrs.default_for;
+            Some(quote!(#default_for(&__loader.environment())))
+        }
+        (
+            Some(Expr::Lit(ExprLit {
+                lit: Lit::Str(raw), ..
+            })),
+            _,
+        ) => Some(quote! {
+            ::config::derive::parse_default(#key, #raw).map_err(#wrap)?
+        }),
+        (Some(default), _) => Some(quote!(#default)),
+        (None, Some(_)) => Some(quote!(::std::option::Option::None)),
+        (None, None) => None,
+    };
+    let found = if optional.is_some() {
+        quote!(::std::option::Option::Some(value))
+    } else {
+        quote!(value)
+    };
+    let missing_arm = missing.map(|fallback| {
+        quote! {
+            ::std::result::Result::Err(err) if err.is_missing() => #fallback,
+        }
+    });
+    // Without a conversion the raw value is the field type (or its `Option`
+    // inner type), which pins the type `get` parses to
+    let annotation = if attrs.with.is_none() {
+        quote!(: #ty)
+    } else {
+        quote!()
+    };
+    let convert = match (&attrs.with, optional) {
+        (Some(with), Some(_)) => quote!(raw.map(#with)),
+        (Some(with), None) => quote!(#with(raw)),
+        (None, _) => quote!(raw),
+    };
+
+    Ok(quote! {
+        #ident: {
+            let raw #annotation = match __loader.get(#key) {
+                ::std::result::Result::Ok(value) => #found,
+                #missing_arm
+                ::std::result::Result::Err(err) => return ::std::result::Result::Err((#wrap)(err)),
+            };
+            #convert
+        }
+    })
+}
+
+/// `T` when `ty` is spelled `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
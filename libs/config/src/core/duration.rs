@@ -0,0 +1,231 @@
+//! Human-friendly durations in configuration
+//!
+//! Timeouts and TTLs are written the way people say them: `30s`, `5m`,
+//! `2h`, or combined as `1h30m`.  Units are `ms`, `s`, `m`, `h` and `d`.
+//! A bare number is seconds, which is what unit-less keys such as
+//! `SHUTDOWN_TIMEOUT` have always meant, so existing values keep working.
+//! Keys named after another unit, like `TIMEOUT_HTTP_MS`, read [`Millis`],
+//! [`Minutes`] or [`Days`] instead, whose bare numbers are in that unit.
+//!
+//! Fields read a [`ConfigDuration`] and convert it to the duration type
+//! they store:
+//!
+//! ```rust,ignore
+//! #[config(key = "SHUTDOWN_TIMEOUT", default = "30s", with = ConfigDuration::into_std)]
+//! pub shutdown_timeout: std::time::Duration,
+//! #[config(key = "TIMEOUT_HTTP_MS", default = "10s", with = Millis::into_std)]
+//! pub http_timeout: std::time::Duration,
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Units a duration may be written in, with their length in milliseconds
+const UNITS: &[(&str, u64)] = &[
+    ("ms", 1),
+    ("s", 1_000),
+    ("m", 60_000),
+    ("h", 3_600_000),
+    ("d", 86_400_000),
+];
+
+/// Duration parsed from a configuration value such as `"1h30m"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct ConfigDuration(pub Duration);
+
+impl ConfigDuration {
+    pub fn into_std(self) -> Duration {
+        self.0
+    }
+
+    /// As a `time::Duration`, saturating at its maximum
+    pub fn into_time(self) -> time::Duration {
+        let seconds = i64::try_from(self.0.as_secs()).unwrap_or(i64::MAX);
+        time::Duration::new(seconds, self.0.subsec_nanos() as i32)
+    }
+}
+
+impl From<ConfigDuration> for Duration {
+    fn from(duration: ConfigDuration) -> Self {
+        duration.0
+    }
+}
+
+impl FromStr for ConfigDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, 1_000).map(Self)
+    }
+}
+
+/// [`ConfigDuration`] whose bare numbers are `UNIT_MILLIS` milliseconds
+/// each rather than seconds; use it through [`Millis`], [`Minutes`] or
+/// [`Days`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct BareUnitDuration<const UNIT_MILLIS: u64>(pub ConfigDuration);
+
+/// Bare numbers are milliseconds, for `*_MS` keys
+pub type Millis = BareUnitDuration<1>;
+/// Bare numbers are minutes, for `*_MINUTES` keys
+pub type Minutes = BareUnitDuration<60_000>;
+/// Bare numbers are days, for `*_DAYS` keys
+pub type Days = BareUnitDuration<86_400_000>;
+
+impl<const UNIT_MILLIS: u64> BareUnitDuration<UNIT_MILLIS> {
+    pub fn into_std(self) -> Duration {
+        self.0.into_std()
+    }
+
+    /// As a `time::Duration`, saturating at its maximum
+    pub fn into_time(self) -> time::Duration {
+        self.0.into_time()
+    }
+}
+
+impl<const UNIT_MILLIS: u64> FromStr for BareUnitDuration<UNIT_MILLIS> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, UNIT_MILLIS).map(|duration| Self(ConfigDuration(duration)))
+    }
+}
+
+/// Parse `s`, reading a bare number as that many `bare_unit_millis`
+fn parse(s: &str, bare_unit_millis: u64) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration '{s}': expected a number followed by a unit \
+             (ms, s, m, h, d), e.g. \"30s\" or \"1h30m\""
+        )
+    };
+    let too_long = || format!("duration '{s}' is too long");
+
+    let trimmed = s.trim();
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+    if trimmed.bytes().all(|b| b.is_ascii_digit()) {
+        let amount: u64 = trimmed.parse().map_err(|_| too_long())?;
+        let millis = amount.checked_mul(bare_unit_millis).ok_or_else(too_long)?;
+        return Ok(Duration::from_millis(millis));
+    }
+
+    let mut rest = trimmed;
+    let mut millis: u64 = 0;
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        let amount: u64 = rest[..digits].parse().map_err(|_| too_long())?;
+        rest = &rest[digits..];
+        // The whole suffix must be a unit: `5mins` isn't five minutes
+        let unit_len = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+        let (suffix, unit_millis) = UNITS
+            .iter()
+            .find(|(suffix, _)| *suffix == &rest[..unit_len])
+            .ok_or_else(invalid)?;
+        rest = &rest[suffix.len()..];
+        millis = amount
+            .checked_mul(*unit_millis)
+            .and_then(|part| millis.checked_add(part))
+            .ok_or_else(too_long)?;
+    }
+    Ok(Duration::from_millis(millis))
+}
+
+/// Largest units first, e.g. `1h30m`; zero is `0s`
+impl fmt::Display for ConfigDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut millis = self.0.as_millis();
+        if millis == 0 {
+            return f.write_str("0s");
+        }
+        for (suffix, unit_millis) in UNITS.iter().rev() {
+            let unit_millis = u128::from(*unit_millis);
+            if millis >= unit_millis {
+                write!(f, "{}{suffix}", millis / unit_millis)?;
+                millis %= unit_millis;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::ConfigLoader;
+    use crate::sources::dotenv::DotenvLayerBuilder;
+
+    fn parse(s: &str) -> Result<Duration, String> {
+        s.parse::<ConfigDuration>().map(ConfigDuration::into_std)
+    }
+
+    #[test]
+    fn test_human_friendly_durations_parse() {
+        assert_eq!(parse("1h30m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse("2d"), Ok(Duration::from_secs(2 * 86_400)));
+        assert_eq!(parse(" 5m "), Ok(Duration::from_secs(300)));
+        // Unit-less values keep their old meaning
+        assert_eq!(parse("45"), Ok(Duration::from_secs(45)));
+
+        // ...including keys whose unit is not seconds
+        let millis = |s: &str| s.parse::<Millis>().map(Millis::into_std);
+        assert_eq!(millis("250"), Ok(Duration::from_millis(250)));
+        assert_eq!(millis("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(
+            "15".parse::<Minutes>().unwrap().into_time(),
+            time::Duration::minutes(15)
+        );
+        assert_eq!(
+            "7".parse::<Days>().unwrap().into_time(),
+            time::Duration::days(7)
+        );
+        assert_eq!(
+            "12h".parse::<Days>().unwrap().into_time(),
+            time::Duration::hours(12)
+        );
+        assert!(
+            "99999999999999999"
+                .parse::<Days>()
+                .unwrap_err()
+                .contains("too long")
+        );
+
+        let duration: ConfigDuration = "1h30m".parse().unwrap();
+        assert_eq!(duration.to_string(), "1h30m");
+        assert_eq!(duration.into_time(), time::Duration::minutes(90));
+    }
+
+    #[test]
+    fn test_malformed_durations_are_rejected() {
+        let err = parse("5 potatoes").unwrap_err();
+        assert!(err.contains("'5 potatoes'"), "{err}");
+        assert!(err.contains("1h30m"), "{err}");
+
+        // Through the loader the error also names the key
+        let loader = ConfigLoader::new().with_service_env(
+            DotenvLayerBuilder::new()
+                .with_override("SHUTDOWN_TIMEOUT", "5 potatoes")
+                .build(),
+        );
+        let err = loader
+            .get::<ConfigDuration>("SHUTDOWN_TIMEOUT")
+            .unwrap_err();
+        assert!(err.to_string().contains("SHUTDOWN_TIMEOUT"), "{err}");
+
+        for invalid in ["", "m", "5mins", "1.5h", "-5s", "5s 3m", "h1"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+        assert!(
+            parse("99999999999999999999d")
+                .unwrap_err()
+                .contains("too long")
+        );
+    }
+}
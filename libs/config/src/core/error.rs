@@ -51,6 +51,13 @@ pub enum ConfigError {
 
     #[error("Feature '{feature}' is not enabled")]
     FeatureNotEnabled { feature: String },
+
+    #[error("Invalid configuration for field `{field}`: {source}")]
+    Field {
+        field: String,
+        #[source]
+        source: Box<ConfigError>,
+    },
 }
 
 impl ConfigError {
@@ -113,9 +120,21 @@ impl ConfigError {
         Self::Source(message.into())
     }
 
+    /// Attribute an error to the struct field being loaded
+    pub fn field(field: impl Into<String>, source: ConfigError) -> Self {
+        Self::Field {
+            field: field.into(),
+            source: Box::new(source),
+        }
+    }
+
     /// Check if this is a missing key error
     pub fn is_missing(&self) -> bool {
-        matches!(self, Self::Missing { .. })
+        match self {
+            Self::Missing { .. } => true,
+            Self::Field { source, .. } => source.is_missing(),
+            _ => false,
+        }
     }
 
     /// Check if this is a validation error
@@ -2,9 +2,13 @@
 //!
 //! This module provides the foundation for all configuration handling:
 //! - Environment types
+//! - Human-friendly durations (`30s`, `1h30m`)
 //! - Error types
 //! - Settings trait for generic configuration
+//! - Secret redaction for logging configuration values
 
+pub mod duration;
 pub mod environment;
 pub mod error;
+pub mod redact;
 pub mod settings;
@@ -0,0 +1,140 @@
+//! Runtime support for `#[derive(Config)]`
+//!
+//! See [`Config`](crate::Config) for the attributes the derive understands.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::core::error::{ConfigError, ConfigResult};
+
+/// Parse a string literal default the way a configured value would be
+#[doc(hidden)]
+pub fn parse_default<T>(key: &str, raw: &str) -> ConfigResult<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    raw.parse().map_err(|e: T::Err| {
+        ConfigError::invalid_value(key, format!("invalid default '{raw}': {e}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+    use crate::core::environment::Environment;
+    use crate::core::error::{ConfigError, ConfigResult};
+    use crate::loader::ConfigLoader;
+    use crate::sources::dotenv::DotenvLayerBuilder;
+    use std::time::Duration;
+
+    #[derive(Debug, Config)]
+    struct PoolSection {
+        #[config(key = "POOL_MAX", default = 10)]
+        max: u32,
+        #[config(key = "POOL_IDLE_SECS", default = 60, with = Duration::from_secs)]
+        idle: Duration,
+    }
+
+    #[derive(Debug, Config)]
+    struct ServiceSection {
+        #[config(key = "SERVICE_URL")]
+        url: String,
+        #[config(key = "SERVICE_NAME", default = "escrow")]
+        name: String,
+        #[config(key = "SERVICE_REGION")]
+        region: Option<String>,
+        #[config(nested)]
+        pool: PoolSection,
+        #[config(skip)]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Config)]
+    #[config(validate = TlsSection::validate_for)]
+    struct TlsSection {
+        #[config(key = "TLS_REQUIRED", default_for = Environment::is_production)]
+        required: bool,
+        #[config(key = "TLS_ENABLED", default = false)]
+        enabled: bool,
+    }
+
+    impl TlsSection {
+        fn validate_for(&self, environment: &Environment) -> ConfigResult<()> {
+            if self.required && !self.enabled {
+                return Err(ConfigError::validation(format!(
+                    "TLS_ENABLED must be set in {environment}"
+                )));
+            }
+            Ok(())
+        }
+    }
+
+    fn loader(vars: &[(&str, &str)]) -> ConfigLoader {
+        let env = vars
+            .iter()
+            .fold(DotenvLayerBuilder::new(), |builder, (key, value)| {
+                builder.with_override(*key, *value)
+            })
+            .build();
+        ConfigLoader::new().with_service_env(env)
+    }
+
+    #[test]
+    fn test_derived_config_loads_values_and_defaults() {
+        let section = ServiceSection::from_loader(&loader(&[
+            ("SERVICE_URL", "http://escrow:8080"),
+            ("POOL_IDLE_SECS", "5"),
+        ]))
+        .unwrap();
+
+        assert_eq!(section.url, "http://escrow:8080");
+        assert_eq!(section.name, "escrow");
+        assert_eq!(section.region, None);
+        assert_eq!(section.pool.max, 10);
+        assert_eq!(section.pool.idle, Duration::from_secs(5));
+        assert!(section.tags.is_empty());
+    }
+
+    #[test]
+    fn test_derived_config_errors_name_field_and_key() {
+        let err = ServiceSection::from_loader(&loader(&[])).unwrap_err();
+        assert!(err.is_missing());
+        let message = err.to_string();
+        assert!(message.contains("ServiceSection.url"), "{message}");
+        assert!(message.contains("SERVICE_URL"), "{message}");
+
+        let err = ServiceSection::from_loader(&loader(&[
+            ("SERVICE_URL", "http://escrow:8080"),
+            ("POOL_MAX", "lots"),
+        ]))
+        .unwrap_err();
+        assert!(matches!(&err, ConfigError::Field { field, .. } if field == "PoolSection.max"));
+        assert!(err.to_string().contains("POOL_MAX"));
+    }
+
+    #[test]
+    fn test_environment_defaults_and_validation() {
+        let dev = TlsSection::from_loader(&loader(&[])).unwrap();
+        assert!(!dev.required);
+
+        let err = TlsSection::from_loader(&loader(&[("APP_ENV", "production")])).unwrap_err();
+        assert!(err.is_validation());
+        assert!(err.to_string().contains("Production"), "{err}");
+
+        let prod = TlsSection::from_loader(&loader(&[
+            ("APP_ENV", "production"),
+            ("TLS_ENABLED", "true"),
+        ]))
+        .unwrap();
+        assert!(prod.required && prod.enabled);
+
+        // An explicit value wins over the environment's default
+        let opted_out = TlsSection::from_loader(&loader(&[
+            ("APP_ENV", "production"),
+            ("TLS_REQUIRED", "false"),
+        ]))
+        .unwrap();
+        assert!(!opted_out.required);
+    }
+}
@@ -3,7 +3,13 @@
 //! A modular, world-class configuration library for Rust applications.
 //! Provides configuration types and loading mechanisms for databases, Redis, JWT, servers, and more.
 
+// Lets `#[derive(Config)]` output, which names `::config`, compile here too
+extern crate self as config;
+
 pub mod core;
 
+pub mod derive;
 pub mod loader;
 pub mod sources;
+
+pub use config_derive::Config;
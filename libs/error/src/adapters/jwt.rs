@@ -1,13 +1,16 @@
 //! JWT adapter error conversions
 
-use crate::core::AppError;
+use jsonwebtoken::errors::ErrorKind;
+
+use crate::core::{AppError, AuthErrorCode};
 
 /// Convert from jsonwebtoken::errors::Error
 impl From<jsonwebtoken::errors::Error> for AppError {
     fn from(e: jsonwebtoken::errors::Error) -> Self {
-        Self::auth(
-            e.to_string(),
-            crate::http::error_code::AuthErrorCode::TokenInvalid,
-        )
+        let code = match e.kind() {
+            ErrorKind::ExpiredSignature => AuthErrorCode::TokenExpired,
+            _ => AuthErrorCode::TokenInvalid,
+        };
+        Self::auth(e.to_string(), code)
     }
 }
@@ -1,10 +1,59 @@
 //! SQLx adapter error conversions
 
 use crate::core::AppError;
+use crate::core::kinds::DatabaseError;
+
+/// SQLSTATE for a write attempted in a read-only transaction
+const READ_ONLY_SQL_TRANSACTION: &str = "25006";
 
 /// Convert from sqlx::Error
+///
+/// Writes refused because the database is in read-only maintenance mode
+/// become `ServiceUnavailable` rather than a generic database error.  Other
+/// database errors keep their SQLSTATE, which decides whether they are
+/// retryable.  Failures that never reached the database (I/O, TLS, a pool
+/// timeout or a closed pool) have no SQLSTATE; they are infrastructure
+/// errors, which are retryable.
 impl From<sqlx::Error> for AppError {
     fn from(e: sqlx::Error) -> Self {
-        Self::database(e.to_string())
+        if matches!(
+            e,
+            sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_)
+                | sqlx::Error::Protocol(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed
+        ) {
+            return Self::infrastructure("database", e.to_string());
+        }
+        let code = e
+            .as_database_error()
+            .and_then(|db| db.code())
+            .map(|code| code.into_owned());
+        match code {
+            Some(code) if code == READ_ONLY_SQL_TRANSACTION => {
+                Self::service_unavailable("database (read-only maintenance)", None)
+            }
+            Some(code) => Self::DatabaseError(DatabaseError::with_code(e.to_string(), code)),
+            None => Self::database(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_without_sqlstate_are_retryable_unless_about_the_query() {
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset by peer");
+        assert!(AppError::from(sqlx::Error::Io(reset)).is_retryable());
+        assert!(AppError::from(sqlx::Error::PoolTimedOut).is_retryable());
+        assert!(AppError::from(sqlx::Error::PoolClosed).is_retryable());
+        assert!(AppError::from(sqlx::Error::Tls("handshake failed".into())).is_retryable());
+
+        assert!(!AppError::from(sqlx::Error::RowNotFound).is_retryable());
+        assert!(!AppError::from(sqlx::Error::ColumnNotFound("amount".into())).is_retryable());
     }
 }
@@ -119,6 +119,45 @@ impl AppError {
         Self::InfrastructureError(InfrastructureError::infrastructure(component, message))
     }
 
+    /// Create a service unavailable error (503), optionally hinting when to retry
+    pub fn service_unavailable(component: impl Into<String>, retry_after: Option<u64>) -> Self {
+        Self::InfrastructureError(InfrastructureError::unavailable(component, retry_after))
+    }
+
+    /// Seconds the client should wait before retrying, if known
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::RateLimitError(InfrastructureError::RateLimit {
+                retry_after_seconds,
+                ..
+            }) => Some(*retry_after_seconds),
+            Self::InfrastructureError(InfrastructureError::Unavailable {
+                retry_after_seconds,
+                ..
+            }) => *retry_after_seconds,
+            _ => None,
+        }
+    }
+
+    /// Whether the same operation may succeed if tried again
+    ///
+    /// Rate limits, unavailable infrastructure, 5xx/429 answers (or no
+    /// answer) from external services, transient database failures
+    /// (serialization failures, deadlocks, lost connections) and internal
+    /// errors, which say nothing about the request, are retryable.
+    /// Everything else describes the request itself, and retrying it only
+    /// fails the same way again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimitError(_) | Self::InfrastructureError(_) | Self::InternalError(_) => true,
+            Self::ExternalServiceError(e) => {
+                e.status_code.is_none_or(|status| status >= 500 || status == 429)
+            }
+            Self::DatabaseError(e) => e.code.as_deref().is_some_and(is_transient_sqlstate),
+            _ => false,
+        }
+    }
+
     /// Create an internal error
     pub fn internal(message: impl Into<String>) -> Self {
         Self::InternalError(InternalError::new(message))
@@ -155,6 +194,15 @@ impl AppError {
     }
 }
 
+/// SQLSTATEs of failures that a retry can get past: serialization failure,
+/// deadlock, connection exceptions (class 08), server shutdown and too
+/// many connections
+fn is_transient_sqlstate(code: &str) -> bool {
+    code.starts_with("08")
+        || matches!(code, "40001" | "40P01" | "57P01" | "57P02" | "57P03" | "53300")
+        || code.starts_with("08")
+}
+
 // Core From implementations that don't require HTTP features
 // These are safe to implement here as they don't depend on web frameworks
 
@@ -185,3 +233,32 @@ impl From<time::error::Parse> for AppError {
         Self::validation(format!("Invalid date/time format: {}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_errors_are_retryable_and_validation_is_not() {
+        assert!(AppError::service_unavailable("broker", Some(5)).is_retryable());
+        assert!(AppError::rate_limit("publish", 1).is_retryable());
+        assert!(AppError::external("broker", "connection reset").is_retryable());
+        assert!(AppError::ExternalServiceError(ExternalServiceError::with_status(
+            "psp", "bad gateway", 502
+        ))
+        .is_retryable());
+        assert!(AppError::DatabaseError(DatabaseError::with_code("deadlock", "40P01")).is_retryable());
+        assert!(AppError::DatabaseError(DatabaseError::with_code("connection failure", "08006"))
+            .is_retryable());
+        assert!(AppError::internal("publisher task panicked").is_retryable());
+
+        assert!(!AppError::validation("amount must be positive").is_retryable());
+        assert!(!AppError::ExternalServiceError(ExternalServiceError::with_status(
+            "psp", "card declined", 402
+        ))
+        .is_retryable());
+        assert!(!AppError::DatabaseError(DatabaseError::with_code("duplicate key", "23505"))
+            .is_retryable());
+        assert!(!AppError::not_found("order", "o-1").is_retryable());
+    }
+}
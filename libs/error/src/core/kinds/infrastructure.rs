@@ -14,6 +14,10 @@ pub enum InfrastructureError {
         action: String,
         retry_after_seconds: u64,
     },
+    Unavailable {
+        component: String,
+        retry_after_seconds: Option<u64>,
+    },
 }
 
 impl InfrastructureError {
@@ -30,6 +34,13 @@ impl InfrastructureError {
             retry_after_seconds: retry_after,
         }
     }
+
+    pub fn unavailable(component: impl Into<String>, retry_after: Option<u64>) -> Self {
+        Self::Unavailable {
+            component: component.into(),
+            retry_after_seconds: retry_after,
+        }
+    }
 }
 
 impl std::fmt::Display for InfrastructureError {
@@ -48,6 +59,22 @@ impl std::fmt::Display for InfrastructureError {
                     action, retry_after_seconds
                 )
             }
+            Self::Unavailable {
+                component,
+                retry_after_seconds: Some(retry_after_seconds),
+            } => {
+                write!(
+                    f,
+                    "{} is temporarily unavailable, retry after {} seconds",
+                    component, retry_after_seconds
+                )
+            }
+            Self::Unavailable {
+                component,
+                retry_after_seconds: None,
+            } => {
+                write!(f, "{} is temporarily unavailable", component)
+            }
         }
     }
 }
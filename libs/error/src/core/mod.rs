@@ -7,8 +7,10 @@ pub mod app_error;
 pub mod codes;
 pub mod context;
 pub mod kinds;
+pub mod service;
 
 // Re-export core types for convenience
 pub use app_error::{AppError, AppResult};
 pub use codes::{auth_error::AuthErrorCode};
 pub use context::{ContextualError, ErrorContext};
+pub use service::{ServiceError, ServiceResult};
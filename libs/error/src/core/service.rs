@@ -0,0 +1,122 @@
+//! Service error pattern
+//!
+//! Each service keeps its own error enum, describing what can go wrong in its
+//! own terms, and maps it onto [`AppError`] in one place, so that handlers
+//! can `?` any service error into an API response the same way.  A service
+//! error:
+//!
+//! 1. implements `From<ItsError> for AppError`, choosing the kind
+//!    (not found, conflict, business rule, ...) for each variant,
+//! 2. implements [`ServiceError`], giving each variant a stable code that
+//!    clients can match on (`ORDER_NOT_FOUND`) and saying which variants
+//!    are worth retrying, and
+//! 3. invokes [`service_error!`](crate::service_error), which adds the
+//!    conversion to `ApiError` (with the `http` feature).
+//!
+//! ```rust
+//! use error::{AppError, ServiceError, ServiceResult};
+//!
+//! #[derive(Debug, thiserror::Error)]
+//! pub enum PayoutError {
+//!     #[error("payout {0} not found")]
+//!     NotFound(String),
+//!     #[error("payout already settled")]
+//!     AlreadySettled,
+//!     #[error("payment provider unavailable")]
+//!     ProviderUnavailable,
+//! }
+//!
+//! impl ServiceError for PayoutError {
+//!     fn code(&self) -> &'static str {
+//!         match self {
+//!             Self::NotFound(_) => "PAYOUT_NOT_FOUND",
+//!             Self::AlreadySettled => "PAYOUT_ALREADY_SETTLED",
+//!             Self::ProviderUnavailable => "PAYOUT_PROVIDER_UNAVAILABLE",
+//!         }
+//!     }
+//!
+//!     fn is_retryable(&self) -> bool {
+//!         matches!(self, Self::ProviderUnavailable)
+//!     }
+//! }
+//!
+//! impl From<PayoutError> for AppError {
+//!     fn from(e: PayoutError) -> Self {
+//!         let code = e.code();
+//!         match e {
+//!             PayoutError::NotFound(id) => AppError::not_found("payout", id),
+//!             PayoutError::AlreadySettled => AppError::business(e.to_string(), code),
+//!             PayoutError::ProviderUnavailable => {
+//!                 AppError::service_unavailable("payment provider", None)
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! error::service_error!(PayoutError);
+//!
+//! fn settle(id: &str) -> ServiceResult<(), PayoutError> {
+//!     Err(PayoutError::NotFound(id.to_string()))
+//! }
+//! ```
+
+use super::AppError;
+
+/// Result of a service operation; the error defaults to [`AppError`]
+pub type ServiceResult<T = (), E = AppError> = Result<T, E>;
+
+/// Error returned by a service's application layer
+pub trait ServiceError: std::error::Error + Into<AppError> {
+    /// Stable machine-readable code of this error, e.g. `ORDER_NOT_FOUND`
+    ///
+    /// Codes are part of the API: clients match on them, so they must not
+    /// change once published.
+    fn code(&self) -> &'static str;
+
+    /// Whether the operation may succeed if tried again
+    ///
+    /// Defaults to terminal.  Errors with transient variants (a dependency
+    /// timing out, a lock conflict) override this per variant, and should
+    /// agree with [`AppError::is_retryable`] of the error they convert into.
+    fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+/// Convert a [`ServiceError`] into an `ApiError` carrying its stable code
+/// as `details.service_code`
+#[cfg(feature = "http")]
+pub fn into_api_error<E: ServiceError>(error: E) -> crate::http::ApiError {
+    let code = error.code();
+    crate::http::ApiError::from(error.into()).with_service_code(code)
+}
+
+/// Implement `From<$error> for ApiError` for a [`ServiceError`]
+///
+/// Invoke it next to the error's `From<$error> for AppError`.
+#[cfg(feature = "http")]
+#[macro_export]
+macro_rules! service_error {
+    ($error:ty) => {
+        impl ::core::convert::From<$error> for $crate::http::ApiError {
+            fn from(error: $error) -> Self {
+                $crate::core::service::into_api_error(error)
+            }
+        }
+    };
+}
+
+/// Implement `From<$error> for ApiError` for a [`ServiceError`]
+///
+/// Without the `http` feature there is no `ApiError`, and this only checks
+/// that `$error` is a [`ServiceError`].
+#[cfg(not(feature = "http"))]
+#[macro_export]
+macro_rules! service_error {
+    ($error:ty) => {
+        const _: fn() = || {
+            fn is_service_error<E: $crate::ServiceError>() {}
+            is_service_error::<$error>();
+        };
+    };
+}
@@ -6,7 +6,7 @@
 use axum::{
     Json,
     body::Body,
-    http::{Response, StatusCode},
+    http::{HeaderValue, Response, StatusCode, header::RETRY_AFTER},
     response::IntoResponse,
 };
 use serde::Serialize;
@@ -15,6 +15,7 @@ use serde_json::Value;
 use crate::core::AuthErrorCode;
 
 use super::error_code::ErrorCode;
+use super::rate_limit::RateLimitInfo;
 
 /// Represents a field-level validation error
 #[derive(Debug, Serialize, Clone)]
@@ -54,6 +55,10 @@ pub struct ApiError {
     /// HTTP status code (not serialized to JSON, used for HTTP response)
     #[serde(skip)]
     pub status_code: Option<StatusCode>,
+
+    /// Value for the `Retry-After` header (not serialized to JSON)
+    #[serde(skip)]
+    pub retry_after_seconds: Option<u64>,
 }
 
 impl ApiError {
@@ -64,6 +69,7 @@ impl ApiError {
             message: message.into(),
             details: None,
             status_code: None,
+            retry_after_seconds: None,
         }
     }
 
@@ -84,12 +90,32 @@ impl ApiError {
         self
     }
 
+    /// Attach a service's stable error code as `details.service_code`
+    ///
+    /// Existing details that aren't an object move under `details.details`.
+    pub fn with_service_code(mut self, code: &str) -> Self {
+        let mut details = match self.details.take() {
+            None | Some(Value::Null) => serde_json::Map::new(),
+            Some(Value::Object(map)) => map,
+            Some(other) => serde_json::Map::from_iter([("details".to_string(), other)]),
+        };
+        details.insert("service_code".to_string(), Value::from(code));
+        self.details = Some(Value::Object(details));
+        self
+    }
+
     /// Set the HTTP status code for this error
     pub fn with_status(mut self, status: StatusCode) -> Self {
         self.status_code = Some(status);
         self
     }
 
+    /// Send a `Retry-After` header with the response
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after_seconds = Some(seconds);
+        self
+    }
+
     /// Set the HTTP status code from an ErrorCode
     pub fn with_status_code(mut self, code: ErrorCode) -> Self {
         self.status_code = Some(
@@ -156,9 +182,19 @@ impl ApiError {
             .with_status(StatusCode::UNPROCESSABLE_ENTITY)
     }
 
-    /// 429 Too Many Requests (rate limiting)
-    pub fn rate_limited(message: impl Into<String>) -> Self {
-        Self::rate_limited_with_retry(message, 60)
+    /// 429 Too Many Requests with the exceeded quota
+    ///
+    /// `details` carries the quota as `rate_limit` next to
+    /// `retry_after_seconds`, which clients that predate it still read.
+    pub fn rate_limited(message: impl Into<String>, info: RateLimitInfo) -> Self {
+        let retry_after_seconds = info.retry_after_seconds(time::OffsetDateTime::now_utc());
+        Self::new(ErrorCode::RateLimited, message)
+            .with_details(serde_json::json!({
+                "retry_after_seconds": retry_after_seconds,
+                "rate_limit": info,
+            }))
+            .with_status(StatusCode::TOO_MANY_REQUESTS)
+            .with_retry_after(retry_after_seconds)
     }
 
     /// 429 Too Many Requests with retry information
@@ -166,6 +202,7 @@ impl ApiError {
         Self::new(ErrorCode::RateLimited, message)
             .with_details(serde_json::json!({ "retry_after_seconds": retry_after_seconds }))
             .with_status(StatusCode::TOO_MANY_REQUESTS)
+            .with_retry_after(retry_after_seconds)
     }
 
     /// 500 Internal Server Error
@@ -190,6 +227,16 @@ impl ApiError {
             .with_status(StatusCode::SERVICE_UNAVAILABLE)
     }
 
+    /// 503 Service Unavailable with retry information
+    pub fn service_unavailable_with_retry(
+        message: impl Into<String>,
+        retry_after_seconds: u64,
+    ) -> Self {
+        Self::service_unavailable(message)
+            .with_details(serde_json::json!({ "retry_after_seconds": retry_after_seconds }))
+            .with_retry_after(retry_after_seconds)
+    }
+
     /// 502 Bad Gateway
     pub fn bad_gateway(message: impl Into<String>) -> Self {
         Self::new(ErrorCode::BadGateway, message).with_status(StatusCode::BAD_GATEWAY)
@@ -239,6 +286,7 @@ impl IntoResponse for ApiError {
         let status = self
             .status_code
             .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let retry_after = self.retry_after_seconds;
 
         // Create a simple error response using the local ApiError
         #[derive(serde::Serialize)]
@@ -252,7 +300,13 @@ impl IntoResponse for ApiError {
             error: self,
         };
 
-        (status, Json(response)).into_response()
+        let mut response = (status, Json(response)).into_response();
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
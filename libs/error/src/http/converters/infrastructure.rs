@@ -13,6 +13,17 @@ impl From<InfrastructureError> for ApiError {
                 action,
                 retry_after_seconds,
             } => ApiError::rate_limited_with_retry(action, retry_after_seconds),
+            InfrastructureError::Unavailable {
+                component,
+                retry_after_seconds: Some(retry_after_seconds),
+            } => ApiError::service_unavailable_with_retry(
+                format!("{} is temporarily unavailable", component),
+                retry_after_seconds,
+            ),
+            InfrastructureError::Unavailable {
+                component,
+                retry_after_seconds: None,
+            } => ApiError::service_unavailable(format!("{} is temporarily unavailable", component)),
         }
     }
 }
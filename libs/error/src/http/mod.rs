@@ -10,6 +10,7 @@
 //! - `ApiResult` - Result type for API handlers
 //! - `ErrorCode` - Standard HTTP error codes
 //! - `AuthErrorCode` - Authentication/authorization specific error codes
+//! - `RateLimitInfo` - Quota details sent with `429` responses
 //!
 //! ## Usage
 //!
@@ -24,8 +25,10 @@
 pub mod api_error;
 pub mod converters;
 pub mod error_code;
+pub mod rate_limit;
 
 pub use api_error::{ApiError, ApiResult, FieldError};
 pub use error_code::ErrorCode;
+pub use rate_limit::RateLimitInfo;
 // re-export domain auth codes for HTTP users
 pub use crate::core::codes::auth_error::AuthErrorCode;
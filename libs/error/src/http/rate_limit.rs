@@ -0,0 +1,102 @@
+//! Quota information for `429` responses
+//!
+//! [`ApiError::rate_limited`] puts a [`RateLimitInfo`] under
+//! `details.rate_limit`, so clients can show how many requests they have
+//! left and when the quota resets instead of only how long to wait.
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+#[cfg(doc)]
+use super::ApiError;
+
+/// State of the quota a request exceeded
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// Requests allowed per window
+    pub limit: u64,
+
+    /// Requests left in the current window
+    pub remaining: u64,
+
+    /// When the window resets
+    #[serde(with = "time::serde::rfc3339")]
+    pub reset_at: OffsetDateTime,
+
+    /// What the quota is counted against, e.g. `user`, `ip` or `api_key`
+    pub scope: String,
+}
+
+impl RateLimitInfo {
+    pub fn new(
+        limit: u64,
+        remaining: u64,
+        reset_at: OffsetDateTime,
+        scope: impl Into<String>,
+    ) -> Self {
+        Self {
+            limit,
+            remaining,
+            reset_at,
+            scope: scope.into(),
+        }
+    }
+
+    /// Whole seconds from `now` until the reset, at least one
+    pub fn retry_after_seconds(&self, now: OffsetDateTime) -> u64 {
+        let wait = self.reset_at - now;
+        let seconds = wait.whole_seconds() + i64::from(wait.subsec_nanoseconds() > 0);
+        seconds.max(1) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::ApiError;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use time::Duration;
+
+    #[tokio::test]
+    async fn test_rate_limited_details_carry_quota() {
+        let reset_at = OffsetDateTime::now_utc() + Duration::seconds(30);
+        let info = RateLimitInfo::new(100, 0, reset_at, "user");
+        let response = ApiError::rate_limited("Too many requests", info).into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((29..=30).contains(&retry_after), "{retry_after}");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let details = &body["error"]["details"];
+        assert_eq!(details["retry_after_seconds"], retry_after);
+        assert_eq!(
+            details["rate_limit"],
+            serde_json::json!({
+                "limit": 100,
+                "remaining": 0,
+                "reset_at": reset_at.format(&time::format_description::well_known::Rfc3339).unwrap(),
+                "scope": "user",
+            })
+        );
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        let now = OffsetDateTime::UNIX_EPOCH;
+        let info = |reset_at| RateLimitInfo::new(10, 0, reset_at, "ip");
+        assert_eq!(
+            info(now + Duration::milliseconds(1_500)).retry_after_seconds(now),
+            2
+        );
+        assert_eq!(info(now + Duration::seconds(3)).retry_after_seconds(now), 3);
+        assert_eq!(info(now - Duration::seconds(3)).retry_after_seconds(now), 1);
+    }
+}
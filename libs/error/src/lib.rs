@@ -8,6 +8,7 @@
 //! - `AppError` - Standard application error enum for domain errors
 //! - `ErrorContext` - Context information for error logging
 //! - `ContextualError` - Error with context attached
+//! - `ServiceError` / `ServiceResult` - Per-service error enums and how they map onto `AppError`
 //!
 //! ## HTTP Features (behind `http` flag)
 //!
@@ -33,4 +34,4 @@ pub mod core;
 #[cfg(feature = "http")]
 pub mod http;
 
-pub use core::{AppError, AppResult};
+pub use core::{AppError, AppResult, ServiceError, ServiceResult};
@@ -0,0 +1,383 @@
+//! Audit trail with pluggable sinks
+//!
+//! Compliance asks for audit entries to survive outside the service's own
+//! database, where an operator with table access could rewrite them.  An
+//! [`AuditLog`] writes every entry to each configured [`AuditSink`]:
+//!
+//! - [`PgAuditSink`] appends to the `audit_entries` table;
+//! - [`ObjectStoreAuditSink`] stores each entry as its own JSON object
+//!   under a fresh key and never rewrites it.  Pointed at a bucket with a
+//!   retention lock (S3 Object Lock in compliance mode), the copy is
+//!   immutable even to the service itself.
+//!
+//! ```rust,ignore
+//! let audit = AuditLog::new()
+//!     .with_sink(Arc::new(PgAuditSink::new(pool)))
+//!     .with_sink(Arc::new(ObjectStoreAuditSink::new(worm_bucket, "audit/identity")));
+//! audit.record(&AuditEntry::new("user.suspended").by(admin_id).on("user", user_id)).await?;
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use error::AppError;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+/// Schema of the audit table, for services that don't manage it with their
+/// own migrations
+#[cfg(feature = "database")]
+pub const AUDIT_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS audit_entries (
+    id UUID PRIMARY KEY,
+    actor_id TEXT,
+    action TEXT NOT NULL,
+    resource_type TEXT,
+    resource_id TEXT,
+    details JSONB NOT NULL DEFAULT '{}',
+    occurred_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS audit_entries_occurred_idx ON audit_entries (occurred_at DESC);
+"#;
+
+/// One audited action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    /// Who acted; `None` for the system itself
+    pub actor_id: Option<String>,
+    pub action: String,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub details: serde_json::Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+}
+
+impl AuditEntry {
+    /// Entry for `action`, happening now
+    pub fn new(action: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            actor_id: None,
+            action: action.into(),
+            resource_type: None,
+            resource_id: None,
+            details: serde_json::Value::Object(Default::default()),
+            occurred_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Set who acted
+    pub fn by(mut self, actor_id: impl Into<String>) -> Self {
+        self.actor_id = Some(actor_id.into());
+        self
+    }
+
+    /// Set the resource acted on
+    pub fn on(mut self, resource_type: impl Into<String>, resource_id: impl Into<String>) -> Self {
+        self.resource_type = Some(resource_type.into());
+        self.resource_id = Some(resource_id.into());
+        self
+    }
+
+    /// Attach details, e.g. the old and new values
+    pub fn with_details<T: Serialize>(mut self, details: &T) -> Result<Self, AppError> {
+        self.details = serde_json::to_value(details)
+            .map_err(|e| AppError::internal(format!("audit details not serializable: {e}")))?;
+        Ok(self)
+    }
+}
+
+/// Destination of audit entries
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    /// Name used in logs and errors
+    fn name(&self) -> &str;
+
+    /// Append `entry`; sinks never update or delete entries
+    async fn write(&self, entry: &AuditEntry) -> Result<(), AppError>;
+}
+
+/// Writes each entry to every configured sink
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    sinks: Vec<Arc<dyn AuditSink>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also write entries to `sink`
+    pub fn with_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn sinks(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Write `entry` to all sinks concurrently
+    ///
+    /// Fails if any sink failed, naming them; the entry may still have
+    /// reached the others.  Callers that must not act unaudited should
+    /// treat an error as "not recorded".
+    pub async fn record(&self, entry: &AuditEntry) -> Result<(), AppError> {
+        let results = join_all(self.sinks.iter().map(|sink| sink.write(entry))).await;
+        let failed: Vec<String> = self
+            .sinks
+            .iter()
+            .zip(results)
+            .filter_map(|(sink, result)| {
+                let e = result.err()?;
+                error!(sink = sink.name(), entry_id = %entry.id, action = %entry.action, error = %e, "audit write failed");
+                Some(format!("{}: {e}", sink.name()))
+            })
+            .collect();
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::infrastructure("audit", failed.join("; ")))
+        }
+    }
+}
+
+/// Appends entries to the `audit_entries` table
+#[cfg(feature = "database")]
+#[derive(Clone)]
+pub struct PgAuditSink {
+    pool: crate::database::DbPool,
+}
+
+#[cfg(feature = "database")]
+impl PgAuditSink {
+    pub fn new(pool: crate::database::DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the audit table if it doesn't exist
+    pub async fn ensure_schema(&self) -> Result<(), AppError> {
+        sqlx::raw_sql(AUDIT_SCHEMA)
+            .execute(self.pool.pool())
+            .await
+            .map_err(|e| AppError::infrastructure("audit", e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl AuditSink for PgAuditSink {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn write(&self, entry: &AuditEntry) -> Result<(), AppError> {
+        self.pool.ensure_writable()?;
+        sqlx::query(
+            "INSERT INTO audit_entries \
+             (id, actor_id, action, resource_type, resource_id, details, occurred_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(entry.id)
+        .bind(&entry.actor_id)
+        .bind(&entry.action)
+        .bind(&entry.resource_type)
+        .bind(&entry.resource_id)
+        .bind(&entry.details)
+        .bind(entry.occurred_at)
+        .execute(self.pool.pool())
+        .await
+        .map_err(|e| AppError::infrastructure("audit", e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Stores each entry as a JSON object in an object store
+///
+/// Keys are `{prefix}/{yyyy}/{mm}/{dd}/{occurred_at}-{id}.json`, so an
+/// entry never replaces another and listings sort by time.
+#[cfg(feature = "storage")]
+#[derive(Clone)]
+pub struct ObjectStoreAuditSink {
+    store: Arc<dyn crate::storage::ObjectStore>,
+    prefix: String,
+}
+
+#[cfg(feature = "storage")]
+impl ObjectStoreAuditSink {
+    pub fn new(store: Arc<dyn crate::storage::ObjectStore>, prefix: impl Into<String>) -> Self {
+        Self {
+            store,
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Object key of `entry`
+    pub fn key(&self, entry: &AuditEntry) -> String {
+        let at = entry.occurred_at.to_offset(time::UtcOffset::UTC);
+        format!(
+            "{}/{:04}/{:02}/{:02}/{}-{}.json",
+            self.prefix,
+            at.year(),
+            u8::from(at.month()),
+            at.day(),
+            at.unix_timestamp_nanos(),
+            entry.id
+        )
+    }
+}
+
+#[cfg(feature = "storage")]
+#[async_trait]
+impl AuditSink for ObjectStoreAuditSink {
+    fn name(&self) -> &str {
+        "object_store"
+    }
+
+    async fn write(&self, entry: &AuditEntry) -> Result<(), AppError> {
+        let body = serde_json::to_vec(entry)
+            .map_err(|e| AppError::internal(format!("audit entry not serializable: {e}")))?;
+        let body = futures_util::stream::once(async move { Ok(bytes::Bytes::from(body)) });
+        self.store
+            .put_stream(&self.key(entry), "application/json", Box::pin(body))
+            .await
+            .map_err(|e| AppError::infrastructure("audit", e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// In-memory sink for tests and local development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryAuditSink {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl InMemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AuditSink for InMemoryAuditSink {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    async fn write(&self, entry: &AuditEntry) -> Result<(), AppError> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Sink that is always down
+    struct FailingSink;
+
+    #[async_trait]
+    impl AuditSink for FailingSink {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn write(&self, _entry: &AuditEntry) -> Result<(), AppError> {
+            Err(AppError::external("worm", "bucket unreachable"))
+        }
+    }
+
+    fn suspension() -> AuditEntry {
+        AuditEntry::new("user.suspended")
+            .by("admin-1")
+            .on("user", "user-42")
+            .with_details(&json!({ "reason": "chargeback fraud" }))
+            .unwrap()
+    }
+
+    #[cfg(feature = "storage")]
+    #[tokio::test]
+    async fn test_entry_is_written_to_every_configured_sink() {
+        use crate::storage::{InMemoryObjectStore, ObjectStore};
+
+        let database = InMemoryAuditSink::new();
+        let bucket = Arc::new(InMemoryObjectStore::new());
+        let worm = ObjectStoreAuditSink::new(bucket.clone(), "audit/identity/");
+        let audit = AuditLog::new()
+            .with_sink(Arc::new(database.clone()))
+            .with_sink(Arc::new(worm.clone()));
+        assert_eq!(audit.sinks(), 2);
+
+        let entry = suspension();
+        audit.record(&entry).await.unwrap();
+
+        assert_eq!(database.entries(), std::slice::from_ref(&entry));
+        let key = worm.key(&entry);
+        assert!(key.starts_with("audit/identity/"), "{key}");
+        assert!(key.ends_with(&format!("-{}.json", entry.id)), "{key}");
+        let stored: AuditEntry = serde_json::from_slice(&bucket.get(&key).await.unwrap()).unwrap();
+        assert_eq!(stored, entry);
+        assert_eq!(bucket.content_type(&key).as_deref(), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_sink_is_reported_without_blocking_the_others() {
+        let database = InMemoryAuditSink::new();
+        let audit = AuditLog::new()
+            .with_sink(Arc::new(database.clone()))
+            .with_sink(Arc::new(FailingSink));
+
+        let err = audit.record(&suspension()).await.unwrap_err();
+        assert!(err.to_string().contains("failing"), "{err}");
+        assert_eq!(database.entries().len(), 1);
+    }
+
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_pg_sink_appends_entries() {
+        use crate::database::{DatabaseConfig, DbPool};
+
+        let config = DatabaseConfig {
+            url: std::env::var("DATABASE_URL").unwrap(),
+            ..Default::default()
+        };
+        let pool = DbPool::new(&config).await.unwrap();
+        let sink = PgAuditSink::new(pool.clone());
+        sink.ensure_schema().await.unwrap();
+
+        let entry = suspension();
+        sink.write(&entry).await.unwrap();
+        // Entries are append-only; the same id can't be written twice
+        assert!(sink.write(&entry).await.is_err());
+
+        let (action, details): (String, serde_json::Value) =
+            sqlx::query_as("SELECT action, details FROM audit_entries WHERE id = $1")
+                .bind(entry.id)
+                .fetch_one(pool.pool())
+                .await
+                .unwrap();
+        assert_eq!(action, "user.suspended");
+        assert_eq!(details, entry.details);
+
+        sqlx::query("DELETE FROM audit_entries WHERE id = $1")
+            .bind(entry.id)
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+}
@@ -1,5 +1,9 @@
 //! Shared configuration exports for infrastructure wiring.
 
+pub mod reload;
+
+pub use reload::{ConfigChange, ConfigReloader};
+
 pub use config::core::error::{ConfigError, ConfigResult};
 pub use config::loader::ConfigLoader;
 pub use config::sources::dotenv::DotenvSource;
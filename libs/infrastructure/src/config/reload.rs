@@ -0,0 +1,232 @@
+//! Explicit configuration reload on `SIGHUP`
+//!
+//! Operators who prefer an explicit trigger over file watching can send
+//! `SIGHUP` to the process.  The handler re-runs the config loader, builds a
+//! fresh typed config and swaps it in atomically; readers holding the old
+//! `Arc` keep a consistent snapshot until they drop it.
+//!
+//! Every reload logs which keys changed.  Values are passed through
+//! [`redact_value`] first, so secrets never reach the logs.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use config::core::error::{ConfigError, ConfigResult};
+use config::core::redact::redact_value;
+use config::loader::ConfigLoader;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+
+type LoaderFn = dyn Fn() -> ConfigResult<ConfigLoader> + Send + Sync;
+type BuildFn<T> = dyn Fn(&ConfigLoader) -> ConfigResult<T> + Send + Sync;
+
+/// A single key that changed during a reload (values already redacted)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Holds the active config and knows how to rebuild it
+pub struct ConfigReloader<T> {
+    current: RwLock<Arc<T>>,
+    load: Box<LoaderFn>,
+    build: Box<BuildFn<T>>,
+}
+
+impl<T> ConfigReloader<T>
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    /// Load the initial config.
+    ///
+    /// `load` re-reads the sources (files are read again on every reload) and
+    /// `build` turns them into the typed config, typically `T::from_loader`.
+    pub fn new<L, B>(load: L, build: B) -> ConfigResult<Self>
+    where
+        L: Fn() -> ConfigResult<ConfigLoader> + Send + Sync + 'static,
+        B: Fn(&ConfigLoader) -> ConfigResult<T> + Send + Sync + 'static,
+    {
+        let initial = build(&load()?)?;
+        Ok(Self {
+            current: RwLock::new(Arc::new(initial)),
+            load: Box::new(load),
+            build: Box::new(build),
+        })
+    }
+
+    /// Snapshot of the active config
+    pub fn current(&self) -> Arc<T> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Rebuild the config and swap it in.
+    ///
+    /// This is exactly what the `SIGHUP` handler runs.  If loading or
+    /// validation fails the active config is left untouched.
+    pub fn reload(&self) -> ConfigResult<Vec<ConfigChange>> {
+        let next = (self.build)(&(self.load)()?)?;
+        let previous = self.current();
+
+        let changes = diff(&flatten(&*previous)?, &flatten(&next)?);
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(next);
+
+        if changes.is_empty() {
+            info!("configuration reloaded, no changes");
+        }
+        for change in &changes {
+            info!(
+                key = %change.key,
+                old = change.old.as_deref().unwrap_or("<unset>"),
+                new = change.new.as_deref().unwrap_or("<unset>"),
+                "configuration value changed"
+            );
+        }
+
+        Ok(changes)
+    }
+
+    /// Reload whenever the process receives `SIGHUP`.
+    ///
+    /// Failed reloads are logged and the previous config stays active.
+    #[cfg(unix)]
+    pub fn spawn_sighup_handler(self: Arc<Self>) -> std::io::Result<tokio::task::JoinHandle<()>> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                info!("SIGHUP received, reloading configuration");
+                if let Err(e) = self.reload() {
+                    warn!(error = %e, "configuration reload failed, keeping previous config");
+                }
+            }
+        }))
+    }
+}
+
+fn flatten<T: Serialize>(config: &T) -> ConfigResult<BTreeMap<String, String>> {
+    let value = serde_json::to_value(config)
+        .map_err(|e| ConfigError::source(format!("config is not serializable: {e}")))?;
+
+    let mut out = BTreeMap::new();
+    flatten_into(String::new(), &value, &mut out);
+    Ok(out)
+}
+
+fn flatten_into(prefix: String, value: &Value, out: &mut BTreeMap<String, String>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_into(join(key), value, out);
+            }
+        }
+        Value::String(s) => {
+            out.insert(prefix, s.clone());
+        }
+        other => {
+            out.insert(prefix, other.to_string());
+        }
+    }
+}
+
+fn diff(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<ConfigChange> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| ConfigChange {
+            key: key.clone(),
+            old: old.get(key).map(|v| redact_value(key, v)),
+            new: new.get(key).map(|v| redact_value(key, v)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::core::redact::REDACTED;
+    use config::sources::dotenv::{DotenvLayerBuilder, DotenvSource};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Serialize)]
+    struct TestConfig {
+        max_connections: u32,
+        api_secret: String,
+    }
+
+    fn build(loader: &ConfigLoader) -> ConfigResult<TestConfig> {
+        Ok(TestConfig {
+            max_connections: loader.get_or("MAX_CONNECTIONS", 10u32)?,
+            api_secret: loader.get_or("API_SECRET", String::new())?,
+        })
+    }
+
+    fn source(max_connections: &str, secret: &str) -> DotenvSource {
+        DotenvLayerBuilder::new()
+            .with_override("MAX_CONNECTIONS", max_connections)
+            .with_override("API_SECRET", secret)
+            .build()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reload_swaps_config_and_redacts_secrets() {
+        let env = Arc::new(Mutex::new(source("10", "old-secret")));
+        let handle = env.clone();
+        let reloader = ConfigReloader::new(
+            move || Ok(ConfigLoader::new().with_service_env(handle.lock().unwrap().clone())),
+            build,
+        )
+        .unwrap();
+        assert_eq!(reloader.current().max_connections, 10);
+
+        *env.lock().unwrap() = source("25", "new-secret");
+        let changes = reloader.reload().unwrap();
+
+        assert_eq!(reloader.current().max_connections, 25);
+        assert_eq!(reloader.current().api_secret, "new-secret");
+
+        let pool = changes.iter().find(|c| c.key == "max_connections").unwrap();
+        assert_eq!(pool.old.as_deref(), Some("10"));
+        assert_eq!(pool.new.as_deref(), Some("25"));
+
+        let secret = changes.iter().find(|c| c.key == "api_secret").unwrap();
+        assert_eq!(secret.old.as_deref(), Some(REDACTED));
+        assert_eq!(secret.new.as_deref(), Some(REDACTED));
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_previous_config() {
+        let env = Arc::new(Mutex::new(source("10", "s")));
+        let handle = env.clone();
+        let reloader = ConfigReloader::new(
+            move || Ok(ConfigLoader::new().with_service_env(handle.lock().unwrap().clone())),
+            build,
+        )
+        .unwrap();
+
+        *env.lock().unwrap() = source("not-a-number", "s");
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.current().max_connections, 10);
+    }
+}
@@ -0,0 +1,393 @@
+//! Application-level encryption for sensitive columns
+//!
+//! Document numbers and other PII are encrypted before they leave the process,
+//! on top of whatever encryption the database applies at rest.  Columns are
+//! declared as `BYTEA` and mapped to [`EncryptedField<T>`], which encrypts on
+//! encode and decrypts on decode with AES-256-GCM.
+//!
+//! Every ciphertext carries the id of the key that produced it, so keys can
+//! be rotated KMS-style: add a new key, make it active, and old rows keep
+//! decrypting with their original key until they are rewritten.
+//!
+//! Stored layout: `[version][key id len][key id][12-byte nonce][ciphertext+tag]`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Encode, Postgres, Type};
+use thiserror::Error;
+
+use config::core::error::{ConfigError, ConfigResult};
+use config::loader::ConfigLoader;
+
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+
+static GLOBAL_CIPHER: OnceLock<FieldCipher> = OnceLock::new();
+
+/// Field encryption errors
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EncryptionError {
+    #[error("field encryption is not configured")]
+    NotConfigured,
+
+    #[error("unknown encryption key id '{0}'")]
+    UnknownKey(String),
+
+    #[error("invalid encryption key '{key_id}': {reason}")]
+    InvalidKey { key_id: String, reason: String },
+
+    #[error("malformed ciphertext: {0}")]
+    Malformed(String),
+
+    #[error("encryption failed")]
+    Encrypt,
+
+    #[error("decryption failed (wrong key or tampered data)")]
+    Decrypt,
+
+    #[error("field serialization failed: {0}")]
+    Serialization(String),
+}
+
+/// Key material for field encryption
+#[derive(Clone)]
+pub struct FieldEncryptionConfig {
+    /// Key id used for new ciphertexts
+    pub active_key_id: String,
+    /// Base64-encoded 256-bit keys by id
+    pub keys: HashMap<String, String>,
+}
+
+impl fmt::Debug for FieldEncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryptionConfig")
+            .field("active_key_id", &self.active_key_id)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl FieldEncryptionConfig {
+    /// Load from `FIELD_ENCRYPTION_KEYS` (`id:base64,id:base64`) and
+    /// `FIELD_ENCRYPTION_ACTIVE_KEY`
+    pub fn from_loader(loader: &ConfigLoader) -> ConfigResult<Self> {
+        let raw: String = loader.get("FIELD_ENCRYPTION_KEYS")?;
+        let mut keys = HashMap::new();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, key) = entry.split_once(':').ok_or_else(|| {
+                ConfigError::invalid_value("FIELD_ENCRYPTION_KEYS", "expected id:base64 pairs")
+            })?;
+            keys.insert(id.trim().to_string(), key.trim().to_string());
+        }
+
+        let active_key_id: String = loader.get("FIELD_ENCRYPTION_ACTIVE_KEY")?;
+        let config = Self {
+            active_key_id,
+            keys,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validate configuration invariants
+    pub fn validate(&self) -> ConfigResult<()> {
+        if !self.keys.contains_key(&self.active_key_id) {
+            return Err(ConfigError::validation(
+                "FIELD_ENCRYPTION_ACTIVE_KEY must reference a key in FIELD_ENCRYPTION_KEYS",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// AES-256-GCM keyring used to encrypt and decrypt field values
+#[derive(Clone)]
+pub struct FieldCipher {
+    active_key_id: String,
+    ciphers: HashMap<String, Aes256Gcm>,
+}
+
+impl fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("active_key_id", &self.active_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FieldCipher {
+    /// Build the keyring from config
+    pub fn from_config(config: &FieldEncryptionConfig) -> Result<Self, EncryptionError> {
+        let mut ciphers = HashMap::new();
+        for (key_id, encoded) in &config.keys {
+            let bytes = BASE64
+                .decode(encoded)
+                .map_err(|e| EncryptionError::InvalidKey {
+                    key_id: key_id.clone(),
+                    reason: e.to_string(),
+                })?;
+            if bytes.len() != 32 || key_id.len() > u8::MAX as usize {
+                return Err(EncryptionError::InvalidKey {
+                    key_id: key_id.clone(),
+                    reason: "expected a 32-byte key".to_string(),
+                });
+            }
+            let key = Key::<Aes256Gcm>::from_slice(&bytes);
+            ciphers.insert(key_id.clone(), Aes256Gcm::new(key));
+        }
+
+        if !ciphers.contains_key(&config.active_key_id) {
+            return Err(EncryptionError::UnknownKey(config.active_key_id.clone()));
+        }
+
+        Ok(Self {
+            active_key_id: config.active_key_id.clone(),
+            ciphers,
+        })
+    }
+
+    /// Install the process-wide cipher used by [`EncryptedField`].
+    ///
+    /// Call once during bootstrap; later calls are ignored and return `false`.
+    pub fn install_global(self) -> bool {
+        GLOBAL_CIPHER.set(self).is_ok()
+    }
+
+    /// The process-wide cipher, if installed
+    pub fn global() -> Result<&'static FieldCipher, EncryptionError> {
+        GLOBAL_CIPHER.get().ok_or(EncryptionError::NotConfigured)
+    }
+
+    /// Key id used for new ciphertexts
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    /// Encrypt with the active key
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let cipher = &self.ciphers[&self.active_key_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| EncryptionError::Encrypt)?;
+
+        let key_id = self.active_key_id.as_bytes();
+        let mut out = Vec::with_capacity(2 + key_id.len() + NONCE_LEN + ciphertext.len());
+        out.push(FORMAT_VERSION);
+        out.push(key_id.len() as u8);
+        out.extend_from_slice(key_id);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Decrypt with whichever key produced the ciphertext
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let (key_id, nonce, ciphertext) = split(data)?;
+        let cipher = self
+            .ciphers
+            .get(key_id)
+            .ok_or_else(|| EncryptionError::UnknownKey(key_id.to_string()))?;
+
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::Decrypt)
+    }
+
+    /// Whether a stored ciphertext was produced by a retired key
+    pub fn needs_rotation(&self, data: &[u8]) -> Result<bool, EncryptionError> {
+        let (key_id, _, _) = split(data)?;
+        Ok(key_id != self.active_key_id)
+    }
+}
+
+fn split(data: &[u8]) -> Result<(&str, &[u8], &[u8]), EncryptionError> {
+    let (&version, rest) = data
+        .split_first()
+        .ok_or_else(|| EncryptionError::Malformed("empty value".to_string()))?;
+    if version != FORMAT_VERSION {
+        return Err(EncryptionError::Malformed(format!(
+            "unsupported format version {version}"
+        )));
+    }
+
+    let (&id_len, rest) = rest
+        .split_first()
+        .ok_or_else(|| EncryptionError::Malformed("missing key id".to_string()))?;
+    let id_len = id_len as usize;
+    if rest.len() < id_len + NONCE_LEN {
+        return Err(EncryptionError::Malformed("truncated value".to_string()));
+    }
+
+    let (key_id, rest) = rest.split_at(id_len);
+    let key_id = std::str::from_utf8(key_id)
+        .map_err(|_| EncryptionError::Malformed("key id is not utf-8".to_string()))?;
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    Ok((key_id, nonce, ciphertext))
+}
+
+/// A value stored encrypted in a `BYTEA` column.
+///
+/// The plaintext is JSON-serialized, so any serde type works.  `Debug` never
+/// prints the value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedField<T>(T);
+
+impl<T> EncryptedField<T> {
+    /// Wrap a plaintext value
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Borrow the plaintext value
+    pub fn value(&self) -> &T {
+        &self.0
+    }
+
+    /// Take the plaintext value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> EncryptedField<T> {
+    /// Encrypt for storage with the given cipher
+    pub fn to_ciphertext(&self, cipher: &FieldCipher) -> Result<Vec<u8>, EncryptionError> {
+        let plaintext = serde_json::to_vec(&self.0)
+            .map_err(|e| EncryptionError::Serialization(e.to_string()))?;
+        cipher.encrypt(&plaintext)
+    }
+
+    /// Decrypt a stored value with the given cipher
+    pub fn from_ciphertext(cipher: &FieldCipher, data: &[u8]) -> Result<Self, EncryptionError> {
+        let plaintext = cipher.decrypt(data)?;
+        serde_json::from_slice(&plaintext)
+            .map(Self)
+            .map_err(|e| EncryptionError::Serialization(e.to_string()))
+    }
+}
+
+impl<T> From<T> for EncryptedField<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for EncryptedField<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptedField(***)")
+    }
+}
+
+impl<T> Type<Postgres> for EncryptedField<T> {
+    fn type_info() -> PgTypeInfo {
+        <Vec<u8> as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <Vec<u8> as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Encode<'_, Postgres> for EncryptedField<T> {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        let ciphertext = self.to_ciphertext(FieldCipher::global()?)?;
+        <Vec<u8> as Encode<Postgres>>::encode(ciphertext, buf)
+    }
+}
+
+impl<'r, T: Serialize + DeserializeOwned> Decode<'r, Postgres> for EncryptedField<T> {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let data = <&[u8] as Decode<Postgres>>::decode(value)?;
+        Ok(Self::from_ciphertext(FieldCipher::global()?, data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(active: &str) -> FieldEncryptionConfig {
+        FieldEncryptionConfig {
+            active_key_id: active.to_string(),
+            keys: HashMap::from([
+                ("v1".to_string(), BASE64.encode([1u8; 32])),
+                ("v2".to_string(), BASE64.encode([2u8; 32])),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_hides_plaintext() {
+        let cipher = FieldCipher::from_config(&config("v1")).unwrap();
+        let field = EncryptedField::new("A12345678".to_string());
+
+        let stored = field.to_ciphertext(&cipher).unwrap();
+        assert!(
+            !stored.windows(9).any(|w| w == b"A12345678"),
+            "stored bytes must not contain the plaintext"
+        );
+
+        let decoded = EncryptedField::<String>::from_ciphertext(&cipher, &stored).unwrap();
+        assert_eq!(decoded.value(), "A12345678");
+    }
+
+    #[test]
+    fn test_rotated_keyring_still_decrypts_old_values() {
+        let old = FieldCipher::from_config(&config("v1")).unwrap();
+        let stored = EncryptedField::new(42u64).to_ciphertext(&old).unwrap();
+
+        let rotated = FieldCipher::from_config(&config("v2")).unwrap();
+        assert!(rotated.needs_rotation(&stored).unwrap());
+        let decoded = EncryptedField::<u64>::from_ciphertext(&rotated, &stored).unwrap();
+        assert_eq!(decoded.into_inner(), 42);
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let cipher = FieldCipher::from_config(&config("v1")).unwrap();
+        let mut stored = EncryptedField::new("secret".to_string())
+            .to_ciphertext(&cipher)
+            .unwrap();
+        let last = stored.len() - 1;
+        stored[last] ^= 0xff;
+
+        assert_eq!(cipher.decrypt(&stored), Err(EncryptionError::Decrypt));
+    }
+
+    #[test]
+    fn test_debug_does_not_leak_value() {
+        let field = EncryptedField::new("A12345678");
+        assert_eq!(format!("{field:?}"), "EncryptedField(***)");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_round_trip_through_postgres() {
+        FieldCipher::from_config(&config("v1"))
+            .unwrap()
+            .install_global();
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+
+        let (stored, raw): (EncryptedField<String>, Vec<u8>) =
+            sqlx::query_as("SELECT $1::bytea, $1::bytea")
+                .bind(EncryptedField::new("A12345678".to_string()))
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+        assert_eq!(stored.value(), "A12345678");
+        assert!(!raw.windows(9).any(|w| w == b"A12345678"));
+    }
+}
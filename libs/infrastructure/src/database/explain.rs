@@ -0,0 +1,159 @@
+//! Development-only `EXPLAIN` for slow queries
+//!
+//! When `DATABASE_EXPLAIN_SLOW_QUERIES` is on and the app runs in
+//! `Environment::Development`, queries run through [`DbPool::observe_query`]
+//! that exceed the slow threshold are re-planned with `EXPLAIN` and the plan
+//! is logged.  The query is never executed a second time (no `ANALYZE`).
+//!
+//! Outside development the explainer cannot be constructed, so production
+//! pools never issue extra statements.
+//!
+//! [`DbPool::observe_query`]: super::DbPool::observe_query
+
+use std::time::Duration as StdDuration;
+
+use config::core::environment::Environment;
+use sqlx::PgPool;
+use tracing::{debug, warn};
+
+use crate::database::config::LoggingConfig;
+
+/// Logs the query plan of slow queries
+#[derive(Debug, Clone)]
+pub struct SlowQueryExplainer {
+    threshold: StdDuration,
+}
+
+impl SlowQueryExplainer {
+    /// Build the explainer if enabled in config *and* running in development
+    pub fn for_environment(logging: &LoggingConfig, environment: &Environment) -> Option<Self> {
+        if !logging.explain_slow_queries || !environment.is_development() {
+            return None;
+        }
+
+        let threshold = logging.slow_query_threshold.unsigned_abs();
+        Some(Self { threshold })
+    }
+
+    /// Slow query threshold
+    pub fn threshold(&self) -> StdDuration {
+        self.threshold
+    }
+
+    /// Whether a query that took `elapsed` counts as slow
+    pub fn is_slow(&self, elapsed: StdDuration) -> bool {
+        elapsed >= self.threshold
+    }
+
+    /// Run `EXPLAIN` for `sql` and log the plan.
+    ///
+    /// Returns the plan text, or `None` if the statement could not be planned
+    /// (planning failures are logged, never propagated).
+    pub async fn explain(&self, pool: &PgPool, sql: &str, elapsed: StdDuration) -> Option<String> {
+        let rows: Vec<(String,)> = match sqlx::query_as(&explain_statement(sql))
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                debug!(error = %e, sql, "could not explain slow query");
+                return None;
+            }
+        };
+
+        let plan = rows
+            .into_iter()
+            .map(|(line,)| line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            threshold_ms = self.threshold.as_millis() as u64,
+            sql,
+            plan = %plan,
+            "slow query"
+        );
+        Some(plan)
+    }
+}
+
+/// `EXPLAIN` statement for `sql`.
+///
+/// Parameterised queries are planned with `GENERIC_PLAN` (PostgreSQL 16+)
+/// since the bound values are not available here.
+fn explain_statement(sql: &str) -> String {
+    let sql = sql.trim().trim_end_matches(';');
+    if sql.contains('$') {
+        format!("EXPLAIN (GENERIC_PLAN) {sql}")
+    } else {
+        format!("EXPLAIN {sql}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{DatabaseConfig, DbPool};
+
+    fn logging(enabled: bool) -> LoggingConfig {
+        LoggingConfig {
+            explain_slow_queries: enabled,
+            slow_query_threshold: time::Duration::milliseconds(10),
+            ..LoggingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_never_enabled_outside_development() {
+        assert!(
+            SlowQueryExplainer::for_environment(&logging(true), &Environment::Production).is_none()
+        );
+        assert!(
+            SlowQueryExplainer::for_environment(&logging(true), &Environment::Staging).is_none()
+        );
+        assert!(
+            SlowQueryExplainer::for_environment(&logging(false), &Environment::Development)
+                .is_none()
+        );
+
+        let explainer =
+            SlowQueryExplainer::for_environment(&logging(true), &Environment::Development).unwrap();
+        assert!(explainer.is_slow(StdDuration::from_millis(10)));
+        assert!(!explainer.is_slow(StdDuration::from_millis(9)));
+    }
+
+    #[test]
+    fn test_explain_statement() {
+        assert_eq!(explain_statement("SELECT 1;"), "EXPLAIN SELECT 1");
+        assert_eq!(
+            explain_statement("SELECT * FROM users WHERE id = $1"),
+            "EXPLAIN (GENERIC_PLAN) SELECT * FROM users WHERE id = $1"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_slow_query_in_development_logs_plan() {
+        let config = DatabaseConfig {
+            url: std::env::var("DATABASE_URL").unwrap(),
+            logging: logging(true),
+            ..DatabaseConfig::default()
+        };
+        let pool = DbPool::new(&config)
+            .await
+            .unwrap()
+            .with_slow_query_explain(&config.logging, &Environment::Development);
+
+        let sql = "SELECT pg_sleep(0.05)";
+        let started = std::time::Instant::now();
+        pool.observe_query(sql, sqlx::query(sql).execute(pool.pool()))
+            .await
+            .unwrap();
+
+        let plan = pool
+            .explain_if_slow(sql, started.elapsed())
+            .await
+            .expect("slow query should have been explained");
+        assert!(plan.contains("Result"), "unexpected plan: {plan}");
+    }
+}
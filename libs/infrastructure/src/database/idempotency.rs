@@ -0,0 +1,408 @@
+//! Idempotent service operations
+//!
+//! The idempotency middleware replays HTTP responses, but operations are
+//! also reached from consumers, jobs and other services, where there is no
+//! request to replay.  [`IdempotencyGuard`] makes the operation itself
+//! idempotent: `escrow.release` keyed by the business key (say the release
+//! request id) runs once, its result is recorded, and any later call with
+//! the same key gets that result back without running it again.
+//!
+//! ```rust,ignore
+//! pub async fn release(&self, escrow_id: &str, request_id: &str) -> AppResult<Release> {
+//!     self.idempotency
+//!         .run("escrow.release", request_id, || self.do_release(escrow_id))
+//!         .await
+//! }
+//! ```
+//!
+//! A failed run records nothing, so the caller may retry it.  A call made
+//! while another with the same key is still running gets a `Conflict`
+//! rather than running twice; a claim left behind by a crashed instance is
+//! taken over once it is older than the guard's claim timeout.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use error::AppError;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use super::DbPool;
+
+/// How long a claim may run before another call can take it over, unless
+/// configured otherwise
+pub const DEFAULT_CLAIM_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Schema of the idempotency table, for services that don't manage it with
+/// their own migrations
+pub const IDEMPOTENCY_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    operation TEXT NOT NULL,
+    key TEXT NOT NULL,
+    outcome JSONB,
+    claimed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    completed_at TIMESTAMPTZ,
+    PRIMARY KEY (operation, key)
+);
+"#;
+
+/// What an earlier call with the same key left behind
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriorCall {
+    /// Still running (or crashed less than the claim timeout ago)
+    InProgress,
+    /// Finished with this result
+    Completed(Value),
+}
+
+/// Storage of idempotency keys and the results recorded for them
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim `key` of `operation`, taking over claims older than
+    /// `claim_timeout`; `None` when the caller now holds the claim
+    async fn claim(
+        &self,
+        operation: &str,
+        key: &str,
+        claim_timeout: Duration,
+    ) -> Result<Option<PriorCall>, AppError>;
+
+    /// Record the result of the claimed call
+    async fn complete(&self, operation: &str, key: &str, outcome: &Value) -> Result<(), AppError>;
+
+    /// Drop the claim of a call that failed, so it can be retried
+    async fn release(&self, operation: &str, key: &str) -> Result<(), AppError>;
+}
+
+/// Runs service operations at most once per idempotency key
+#[derive(Clone)]
+pub struct IdempotencyGuard {
+    store: Arc<dyn IdempotencyStore>,
+    claim_timeout: Duration,
+}
+
+impl IdempotencyGuard {
+    pub fn new(store: Arc<dyn IdempotencyStore>) -> Self {
+        Self {
+            store,
+            claim_timeout: DEFAULT_CLAIM_TIMEOUT,
+        }
+    }
+
+    /// Let a claim be taken over after `claim_timeout` (five minutes by
+    /// default); it should exceed how long the slowest operation runs
+    pub fn with_claim_timeout(mut self, claim_timeout: Duration) -> Self {
+        self.claim_timeout = claim_timeout;
+        self
+    }
+
+    /// Run `operation` once for `key`, or return the result of the call
+    /// that already ran it
+    pub async fn run<T, F, Fut>(&self, operation: &str, key: &str, f: F) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        match self.store.claim(operation, key, self.claim_timeout).await? {
+            None => {}
+            Some(PriorCall::Completed(outcome)) => {
+                debug!(operation, key, "replaying result of idempotent operation");
+                return serde_json::from_value(outcome).map_err(|e| {
+                    AppError::internal(format!("recorded result of {operation} unreadable: {e}"))
+                });
+            }
+            Some(PriorCall::InProgress) => {
+                return Err(AppError::conflict(format!(
+                    "{operation} with idempotency key {key} is already in progress"
+                )));
+            }
+        }
+
+        let result = f().await;
+        match &result {
+            Ok(value) => {
+                let outcome = serde_json::to_value(value).map_err(|e| {
+                    AppError::internal(format!("result of {operation} not serializable: {e}"))
+                })?;
+                self.store.complete(operation, key, &outcome).await?;
+            }
+            Err(_) => {
+                if let Err(e) = self.store.release(operation, key).await {
+                    warn!(operation, key, error = %e, "failed to release idempotency claim");
+                }
+            }
+        }
+        result
+    }
+}
+
+fn store_error(e: sqlx::Error) -> AppError {
+    AppError::infrastructure("idempotency", e.to_string())
+}
+
+/// PostgreSQL idempotency table
+#[derive(Clone)]
+pub struct PgIdempotencyStore {
+    pool: DbPool,
+}
+
+impl PgIdempotencyStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the idempotency table if it doesn't exist
+    pub async fn ensure_schema(&self) -> Result<(), AppError> {
+        sqlx::raw_sql(IDEMPOTENCY_SCHEMA)
+            .execute(self.pool.pool())
+            .await
+            .map_err(store_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for PgIdempotencyStore {
+    async fn claim(
+        &self,
+        operation: &str,
+        key: &str,
+        claim_timeout: Duration,
+    ) -> Result<Option<PriorCall>, AppError> {
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys (operation, key) VALUES ($1, $2) \
+             ON CONFLICT (operation, key) DO UPDATE SET claimed_at = now() \
+             WHERE idempotency_keys.completed_at IS NULL \
+             AND idempotency_keys.claimed_at < now() - make_interval(secs => $3) \
+             RETURNING 1",
+        )
+        .bind(operation)
+        .bind(key)
+        .bind(claim_timeout.as_secs_f64())
+        .fetch_optional(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+
+        let (outcome,): (Option<Value>,) = sqlx::query_as(
+            "SELECT outcome FROM idempotency_keys WHERE operation = $1 AND key = $2",
+        )
+        .bind(operation)
+        .bind(key)
+        .fetch_one(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        Ok(Some(
+            outcome.map_or(PriorCall::InProgress, PriorCall::Completed),
+        ))
+    }
+
+    async fn complete(&self, operation: &str, key: &str, outcome: &Value) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET outcome = $3, completed_at = now() \
+             WHERE operation = $1 AND key = $2",
+        )
+        .bind(operation)
+        .bind(key)
+        .bind(outcome)
+        .execute(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        Ok(())
+    }
+
+    async fn release(&self, operation: &str, key: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "DELETE FROM idempotency_keys \
+             WHERE operation = $1 AND key = $2 AND completed_at IS NULL",
+        )
+        .bind(operation)
+        .bind(key)
+        .execute(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredCall {
+    claimed_at: Instant,
+    outcome: Option<Value>,
+}
+
+/// In-memory idempotency keys for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryIdempotencyStore {
+    /// Calls by `(operation, key)`
+    calls: Arc<Mutex<HashMap<(String, String), StoredCall>>>,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn claim(
+        &self,
+        operation: &str,
+        key: &str,
+        claim_timeout: Duration,
+    ) -> Result<Option<PriorCall>, AppError> {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        let id = (operation.to_string(), key.to_string());
+        if let Some(call) = calls.get(&id) {
+            if let Some(outcome) = &call.outcome {
+                return Ok(Some(PriorCall::Completed(outcome.clone())));
+            }
+            if call.claimed_at.elapsed() < claim_timeout {
+                return Ok(Some(PriorCall::InProgress));
+            }
+        }
+        calls.insert(
+            id,
+            StoredCall {
+                claimed_at: Instant::now(),
+                outcome: None,
+            },
+        );
+        Ok(None)
+    }
+
+    async fn complete(&self, operation: &str, key: &str, outcome: &Value) -> Result<(), AppError> {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(call) = calls.get_mut(&(operation.to_string(), key.to_string())) {
+            call.outcome = Some(outcome.clone());
+        }
+        Ok(())
+    }
+
+    async fn release(&self, operation: &str, key: &str) -> Result<(), AppError> {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        let id = (operation.to_string(), key.to_string());
+        if calls.get(&id).is_some_and(|call| call.outcome.is_none()) {
+            calls.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Release {
+        escrow_id: String,
+        transfer: usize,
+    }
+
+    /// Counts transfers, failing the first `failures` of them
+    #[derive(Default)]
+    struct Ledger {
+        transfers: AtomicUsize,
+        failures: AtomicUsize,
+    }
+
+    impl Ledger {
+        async fn release(&self, escrow_id: &str) -> Result<Release, AppError> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(AppError::external("payments", "transfer declined"));
+            }
+            Ok(Release {
+                escrow_id: escrow_id.to_string(),
+                transfer: self.transfers.fetch_add(1, Ordering::SeqCst) + 1,
+            })
+        }
+    }
+
+    async fn exercise(guard: IdempotencyGuard, key: &str) {
+        let ledger = Ledger::default();
+        let release = || guard.run("escrow.release", key, || ledger.release("esc_1"));
+
+        let first = release().await.unwrap();
+        let replayed = release().await.unwrap();
+        assert_eq!(first, replayed);
+        assert_eq!(ledger.transfers.load(Ordering::SeqCst), 1);
+
+        // Another key is another call
+        let other = guard
+            .run("escrow.release", &format!("{key}-other"), || {
+                ledger.release("esc_2")
+            })
+            .await
+            .unwrap();
+        assert_eq!(other.transfer, 2);
+    }
+
+    #[tokio::test]
+    async fn test_guarded_operation_runs_once_per_key() {
+        let guard = IdempotencyGuard::new(Arc::new(InMemoryIdempotencyStore::new()));
+        exercise(guard, "release-req-1").await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_call_can_be_retried_and_running_call_conflicts() {
+        let store = Arc::new(InMemoryIdempotencyStore::new());
+        let guard = IdempotencyGuard::new(store.clone());
+        let ledger = Ledger {
+            failures: AtomicUsize::new(1),
+            ..Default::default()
+        };
+
+        assert!(
+            guard
+                .run("escrow.release", "k", || ledger.release("esc_1"))
+                .await
+                .is_err()
+        );
+        let retried = guard
+            .run("escrow.release", "k", || ledger.release("esc_1"))
+            .await;
+        assert_eq!(retried.unwrap().transfer, 1);
+
+        // A claim still held by another call
+        store
+            .claim("escrow.release", "busy", DEFAULT_CLAIM_TIMEOUT)
+            .await
+            .unwrap();
+        let err = guard
+            .run("escrow.release", "busy", || ledger.release("esc_2"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already in progress"), "{err}");
+        // ...unless it is older than the claim timeout
+        let patient = guard.clone().with_claim_timeout(Duration::ZERO);
+        let taken_over = patient
+            .run("escrow.release", "busy", || ledger.release("esc_2"))
+            .await;
+        assert_eq!(taken_over.unwrap().transfer, 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_pg_store_runs_once_per_key() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let store = PgIdempotencyStore::new(DbPool::from_pool(pool));
+        store.ensure_schema().await.unwrap();
+        let key = format!("release-{}", uuid::Uuid::new_v4());
+        exercise(IdempotencyGuard::new(Arc::new(store)), &key).await;
+    }
+}
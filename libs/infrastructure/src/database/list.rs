@@ -0,0 +1,511 @@
+//! Paginated list endpoints
+//!
+//! Most services have several `GET /payments?status=settled&page=2&sort=-amount`
+//! endpoints that differ only in the entity they list and the filters they
+//! accept.  [`list_handler`] is that endpoint: it validates `page`,
+//! `per_page` and `sort`, turns the filter DTO into a [`Filter`] through
+//! [`ListFilter::apply`], fetches the page from the repository in the
+//! router state and answers with the standard
+//! `ApiResponse<PaginatedResponse<_>>` envelope, `next`/`prev` links
+//! included.
+//!
+//! ```rust,ignore
+//! #[derive(Deserialize)]
+//! struct PaymentFilter {
+//!     status: Option<String>,
+//!     min_amount: Option<i64>,
+//! }
+//!
+//! impl ListFilter for PaymentFilter {
+//!     const SORTABLE: &'static [&'static str] = &["created_at", "amount"];
+//!     const DEFAULT_SORT: &'static str = "-created_at";
+//!
+//!     fn apply(self, mut filter: Filter) -> Result<Filter, AppError> {
+//!         if let Some(status) = self.status {
+//!             filter = filter.eq("status", status);
+//!         }
+//!         if let Some(min_amount) = self.min_amount {
+//!             filter = filter.gte("amount", min_amount);
+//!         }
+//!         Ok(filter)
+//!     }
+//! }
+//!
+//! let app = Router::new()
+//!     .route("/payments", get(list_handler::<Payment, PaymentFilter, RepositoryContext>))
+//!     .with_state(repo);
+//! ```
+//!
+//! Every sort ends with [`ListFilter::TIE_BREAKER`], the ULID primary key
+//! unless the collection says otherwise, so rows sharing a sort value come
+//! back in the same order on every request and no row is skipped or
+//! repeated between pages.
+//!
+//! The filter DTO is read from the same query string as the pagination
+//! parameters, so it shouldn't have fields named `page`, `per_page` or
+//! `sort`.
+//!
+//! List parameters such as `?ids=pay_1,pay_2` are read with
+//! [`CommaSeparated`] and applied with [`Filter::is_in`].  A request with
+//! more values than [`ListFilter::MAX_IN_VALUES`] is rejected with `422`
+//! before any query runs.
+
+use async_trait::async_trait;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{OriginalUri, Query, State};
+use axum::http::Uri;
+use common::http::pagination::{PaginatedResponse, Pagination as PageInfo};
+use common::http::response::{ApiResponse, ApiResult};
+use common::value_objects::pagination_vo::Pagination;
+use error::AppError;
+use error::http::ApiError;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+use sqlx::FromRow;
+use sqlx::postgres::PgRow;
+use url::form_urlencoded;
+
+use super::repository::{DEFAULT_MAX_IN_VALUES, Entity, Filter, RepositoryExt};
+
+/// Page size when the request doesn't give one
+pub const DEFAULT_PER_PAGE: u32 = 20;
+/// Largest page size a request may ask for
+pub const MAX_PER_PAGE: u32 = 100;
+
+/// Pagination and sorting parameters of a list request
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListParams {
+    /// 1-indexed page number, 1 when missing
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+    /// Comma-separated columns, each prefixed with `-` for descending order
+    pub sort: Option<String>,
+}
+
+impl ListParams {
+    /// Requested page, rejecting page 0 and page sizes outside
+    /// `1..=MAX_PER_PAGE`
+    pub fn pagination(&self) -> Result<Pagination, AppError> {
+        let page = self.page.unwrap_or(1);
+        if page == 0 {
+            return Err(AppError::validation_with_field("page starts at 1", "page"));
+        }
+        let per_page = self.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(AppError::validation_with_field(
+                format!("per_page must be between 1 and {MAX_PER_PAGE}"),
+                "per_page",
+            ));
+        }
+        Ok(Pagination::new(page, per_page))
+    }
+
+    /// `filter` ordered as requested, or by `F::DEFAULT_SORT`, then by
+    /// `F::TIE_BREAKER` unless already sorted by it
+    pub fn sort<F: ListFilter>(&self, mut filter: Filter) -> Result<Filter, AppError> {
+        let sort = self.sort.as_deref().unwrap_or(F::DEFAULT_SORT);
+        let mut tie_broken = false;
+        for key in sort.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+            let (column, descending) = match key.strip_prefix('-') {
+                Some(column) => (column, true),
+                None => (key, false),
+            };
+            if !F::SORTABLE.contains(&column) {
+                return Err(AppError::validation_with_field(
+                    format!(
+                        "cannot sort by '{column}', expected one of: {}",
+                        F::SORTABLE.join(", ")
+                    ),
+                    "sort",
+                ));
+            }
+            tie_broken |= column == F::TIE_BREAKER;
+            filter = if descending {
+                filter.order_by_desc(column)
+            } else {
+                filter.order_by(column)
+            };
+        }
+        if !tie_broken {
+            filter = filter.order_by(F::TIE_BREAKER);
+        }
+        Ok(filter)
+    }
+}
+
+/// Comma-separated query parameter, e.g. `ids=1,2,3`
+///
+/// Blank items are skipped; an item that doesn't parse rejects the request
+/// with `400`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommaSeparated<T>(pub Vec<T>);
+
+impl<'de, T> Deserialize<'de> for CommaSeparated<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse()
+                    .map_err(|e| D::Error::custom(format!("invalid list item '{item}': {e}")))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Query-string filters of a list endpoint
+pub trait ListFilter: DeserializeOwned + Send + 'static {
+    /// Columns clients may sort by
+    const SORTABLE: &'static [&'static str];
+    /// Order used when the request has no `sort`, in the same syntax
+    const DEFAULT_SORT: &'static str;
+    /// Unique column ordering rows the sort leaves tied
+    const TIE_BREAKER: &'static str = "id";
+    /// Values each [`Filter::is_in`] condition accepts
+    const MAX_IN_VALUES: usize = DEFAULT_MAX_IN_VALUES;
+
+    /// Add this DTO's conditions to `filter`
+    fn apply(self, filter: Filter) -> Result<Filter, AppError>;
+}
+
+/// Source of the pages [`list_handler`] serves
+///
+/// Implemented by every [`RepositoryExt`], so a `DbPool` or a tenant-scoped
+/// `RepositoryContext` can be the router state directly.
+#[async_trait]
+pub trait ListRepository<E>: Send + Sync {
+    /// Rows matching `filter` on `page`, and how many match on all pages
+    async fn fetch_page(&self, filter: Filter, page: Pagination)
+    -> Result<(Vec<E>, u64), AppError>;
+}
+
+#[async_trait]
+impl<R, E> ListRepository<E> for R
+where
+    R: RepositoryExt + Send + Sync,
+    E: Entity + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    async fn fetch_page(
+        &self,
+        filter: Filter,
+        page: Pagination,
+    ) -> Result<(Vec<E>, u64), AppError> {
+        RepositoryExt::fetch_page(self, filter, page).await
+    }
+}
+
+/// `GET` handler listing `E` through the repository `R`, filtered by `F`
+pub async fn list_handler<E, F, R>(
+    State(repo): State<R>,
+    OriginalUri(uri): OriginalUri,
+    params: Result<Query<ListParams>, QueryRejection>,
+    dto: Result<Query<F>, QueryRejection>,
+) -> ApiResult<PaginatedResponse<E>>
+where
+    E: Serialize + Send + 'static,
+    F: ListFilter,
+    R: ListRepository<E> + Clone + 'static,
+{
+    let Query(params) = params.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    let Query(dto) = dto.map_err(|e| ApiError::bad_request(e.body_text()))?;
+
+    let page = params.pagination()?;
+    let filter = Filter::new().max_in_values(F::MAX_IN_VALUES);
+    let filter = params.sort::<F>(dto.apply(filter)?)?;
+    let (items, total) = repo.fetch_page(filter, page).await?;
+
+    let mut info = PageInfo::new(page.page().into(), page.limit().into(), total);
+    if u64::from(page.page()) < info.total_pages {
+        info = info.with_next(page_link(&uri, page.page() + 1));
+    }
+    if page.page() > 1 {
+        info = info.with_prev(page_link(&uri, page.page() - 1));
+    }
+    Ok(ApiResponse::success(
+        "Items fetched",
+        PaginatedResponse::new(items, info),
+    ))
+}
+
+/// `uri` pointing at `page` instead, other parameters kept
+fn page_link(uri: &Uri, page: u32) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    if let Some(current) = uri.query() {
+        query.extend_pairs(
+            form_urlencoded::parse(current.as_bytes()).filter(|(key, _)| key != "page"),
+        );
+    }
+    query.append_pair("page", &page.to_string());
+    format!("{}?{}", uri.path(), query.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{EntityQuery, select_query};
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use common::testing::body_json;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+    struct Payment {
+        id: String,
+        status: String,
+        amount: i64,
+    }
+
+    impl Entity for Payment {
+        const TABLE: &'static str = "payments";
+        const COLUMNS: &'static [&'static str] = &["id", "status", "amount"];
+
+        fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+            query.bind(&self.id).bind(&self.status).bind(self.amount)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct PaymentFilter {
+        status: Option<String>,
+        min_amount: Option<i64>,
+        ids: Option<CommaSeparated<String>>,
+    }
+
+    impl ListFilter for PaymentFilter {
+        const SORTABLE: &'static [&'static str] = &["id", "amount"];
+        const DEFAULT_SORT: &'static str = "id";
+        const MAX_IN_VALUES: usize = 3;
+
+        fn apply(self, mut filter: Filter) -> Result<Filter, AppError> {
+            if let Some(CommaSeparated(ids)) = self.ids {
+                filter = filter.is_in("id", ids)?;
+            }
+            if let Some(status) = self.status {
+                filter = filter.eq("status", status);
+            }
+            if let Some(min_amount) = self.min_amount {
+                filter = filter.gte("amount", min_amount);
+            }
+            Ok(filter)
+        }
+    }
+
+    /// Serves pages of fixed rows and records the query it was asked for
+    #[derive(Clone)]
+    struct Ledger {
+        rows: Vec<Payment>,
+        queries: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl ListRepository<Payment> for Ledger {
+        async fn fetch_page(
+            &self,
+            filter: Filter,
+            page: Pagination,
+        ) -> Result<(Vec<Payment>, u64), AppError> {
+            let sql = select_query::<Payment>(filter)?.sql().to_string();
+            self.queries.lock().unwrap().push(sql);
+            let items = self
+                .rows
+                .iter()
+                .skip(page.offset() as usize)
+                .take(page.limit() as usize)
+                .cloned()
+                .collect();
+            Ok((items, self.rows.len() as u64))
+        }
+    }
+
+    fn ledger() -> Ledger {
+        Ledger {
+            rows: (1..=5)
+                .map(|i| Payment {
+                    id: format!("pay_{i}"),
+                    status: "settled".to_string(),
+                    amount: i * 1_000,
+                })
+                .collect(),
+            queries: Arc::default(),
+        }
+    }
+
+    async fn get_json(ledger: &Ledger, uri: &str) -> (StatusCode, Value) {
+        let app = Router::new()
+            .route(
+                "/payments",
+                get(list_handler::<Payment, PaymentFilter, Ledger>),
+            )
+            .with_state(ledger.clone());
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        (response.status(), body_json(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_list_handler_paginates_filters_and_sorts() {
+        let ledger = ledger();
+
+        let (status, body) = get_json(
+            &ledger,
+            "/payments?status=settled&min_amount=500&sort=-amount&page=2&per_page=2",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let data = &body["data"];
+        let ids: Vec<&str> = data["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["pay_3", "pay_4"]);
+        assert_eq!(data["pagination"]["page"], 2);
+        assert_eq!(data["pagination"]["total"], 5);
+        assert_eq!(data["pagination"]["total_pages"], 3);
+        assert_eq!(
+            data["pagination"]["next"],
+            "/payments?status=settled&min_amount=500&sort=-amount&per_page=2&page=3"
+        );
+        assert_eq!(
+            data["pagination"]["prev"],
+            "/payments?status=settled&min_amount=500&sort=-amount&per_page=2&page=1"
+        );
+        assert_eq!(
+            ledger.queries.lock().unwrap()[0],
+            "SELECT \"id\", \"status\", \"amount\" FROM \"payments\" \
+             WHERE \"status\" = $1 AND \"amount\" >= $2 ORDER BY \"amount\" DESC, \"id\""
+        );
+
+        let (status, body) = get_json(&ledger, "/payments").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["items"].as_array().unwrap().len(), 5);
+        assert!(body["data"]["pagination"].get("next").is_none());
+        assert!(ledger.queries.lock().unwrap()[1].ends_with("ORDER BY \"id\""));
+
+        for (invalid, expected) in [
+            ("/payments?page=0", StatusCode::UNPROCESSABLE_ENTITY),
+            ("/payments?per_page=500", StatusCode::UNPROCESSABLE_ENTITY),
+            ("/payments?sort=status", StatusCode::UNPROCESSABLE_ENTITY),
+            ("/payments?min_amount=lots", StatusCode::BAD_REQUEST),
+        ] {
+            let (status, body) = get_json(&ledger, invalid).await;
+            assert_eq!(status, expected, "{invalid}: {body}");
+        }
+        assert_eq!(ledger.queries.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_in_filter_with_too_many_values_is_rejected() {
+        let ledger = ledger();
+
+        let (status, body) = get_json(&ledger, "/payments?ids=pay_1,%20pay_2,,pay_3").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            ledger.queries.lock().unwrap()[0],
+            "SELECT \"id\", \"status\", \"amount\" FROM \"payments\" \
+             WHERE \"id\" = ANY ($1) ORDER BY \"id\""
+        );
+
+        let (status, body) = get_json(&ledger, "/payments?ids=pay_1,pay_2,pay_3,pay_4").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+        assert!(body.to_string().contains("at most 3 values"), "{body}");
+        assert_eq!(ledger.queries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sort_ends_with_the_id_tie_breaker() {
+        let order_by = |sort: Option<&str>| {
+            let params = ListParams {
+                sort: sort.map(str::to_string),
+                ..ListParams::default()
+            };
+            let filter = params.sort::<PaymentFilter>(Filter::new()).unwrap();
+            let sql = select_query::<Payment>(filter).unwrap().sql().to_string();
+            sql.split_once(" ORDER BY ").unwrap().1.to_string()
+        };
+
+        assert_eq!(order_by(Some("-amount")), "\"amount\" DESC, \"id\"");
+        // Already unique: nothing to break
+        assert_eq!(order_by(None), "\"id\"");
+        assert_eq!(order_by(Some("amount,-id")), "\"amount\", \"id\" DESC");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_duplicate_sort_values_are_ordered_by_the_id_tie_breaker() {
+        use crate::database::DbPool;
+        use crate::database::scratch::scratch_pool;
+
+        let (pool, schema) = scratch_pool("list_test").await;
+        sqlx::query(
+            "CREATE TABLE payments (id TEXT PRIMARY KEY, status TEXT NOT NULL, amount BIGINT NOT NULL)",
+        )
+        .execute(pool.pool())
+        .await
+        .unwrap();
+        // Inserted out of id order, every amount shared by two rows
+        for (id, amount) in [
+            ("01J00000000000000000000005", 2_000),
+            ("01J00000000000000000000002", 1_000),
+            ("01J00000000000000000000006", 2_000),
+            ("01J00000000000000000000001", 1_000),
+            ("01J00000000000000000000004", 3_000),
+            ("01J00000000000000000000003", 3_000),
+        ] {
+            sqlx::query("INSERT INTO payments (id, status, amount) VALUES ($1, 'settled', $2)")
+                .bind(id)
+                .bind(amount)
+                .execute(pool.pool())
+                .await
+                .unwrap();
+        }
+
+        let app = Router::new()
+            .route("/payments", get(list_handler::<Payment, PaymentFilter, DbPool>))
+            .with_state(pool.clone());
+        let mut ids = Vec::new();
+        for page in 1..=3 {
+            let uri = format!("/payments?sort=-amount&per_page=2&page={page}");
+            let response = app
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = body_json(response).await;
+            for item in body["data"]["items"].as_array().unwrap() {
+                ids.push(item["id"].as_str().unwrap().to_string());
+            }
+        }
+        assert_eq!(
+            ids,
+            [
+                "01J00000000000000000000003",
+                "01J00000000000000000000004",
+                "01J00000000000000000000005",
+                "01J00000000000000000000006",
+                "01J00000000000000000000001",
+                "01J00000000000000000000002",
+            ]
+        );
+
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+}
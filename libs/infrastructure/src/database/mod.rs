@@ -1,10 +1,51 @@
 //! Database infrastructure shared by all domains.
 
 pub mod config;
+pub mod encryption;
+pub mod explain;
+pub mod idempotency;
+#[cfg(feature = "http")]
+pub mod list;
+pub mod outbox;
 pub mod pool;
+pub mod query_budget;
+#[cfg(feature = "redis")]
+pub mod read_only;
+pub mod reconnect;
+pub mod repository;
+#[cfg(feature = "http")]
+pub mod request_tx;
+// Test helpers - for tests here and, with `testing`, in other crates
+#[cfg(any(test, feature = "testing"))]
+pub mod scratch;
+pub mod tenant;
 
 pub use config::DatabaseConfig;
-pub use pool::{DbPool, DbPoolError};
+pub use explain::SlowQueryExplainer;
+pub use idempotency::{
+    IdempotencyGuard, IdempotencyStore, InMemoryIdempotencyStore, PgIdempotencyStore, PriorCall,
+};
+#[cfg(feature = "http")]
+pub use list::{CommaSeparated, ListFilter, ListParams, ListRepository, list_handler};
+pub use encryption::{EncryptedField, EncryptionError, FieldCipher, FieldEncryptionConfig};
+pub use outbox::{
+    InMemoryOutboxStore, NewOutboxMessage, OutboxMessage, OutboxPublisher, OutboxRelay,
+    OutboxStore, PgOutboxStore, RelayPass,
+};
+pub use pool::{DbPool, DbPoolError, read_only_error};
+pub use reconnect::ReconnectThrottle;
+#[cfg(feature = "http")]
+pub use query_budget::{make_query_budget_middleware, query_budget_middleware};
+pub use query_budget::{BudgetEnforcement, QueryBudget, count_queries, record_query};
+#[cfg(feature = "redis")]
+pub use read_only::{read_only_flag_key, spawn_read_only_sync};
+pub use repository::{
+    BatchMode, BatchReport, ChunkReport, DEFAULT_MAX_IN_VALUES, Entity, EntityQuery, Filter,
+    RepositoryExt, UpsertOutcome, upsert_in,
+};
+pub use tenant::RepositoryContext;
+#[cfg(feature = "http")]
+pub use request_tx::{Tx, make_transactional_middleware, transactional_middleware};
 
 use std::path::Path;
 
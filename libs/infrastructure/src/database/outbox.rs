@@ -0,0 +1,865 @@
+//! Transactional outbox relay
+//!
+//! Services write integration events to the `outbox` table in the same
+//! transaction as the state change ([`PgOutboxStore::enqueue`]); the
+//! [`OutboxRelay`] publishes them afterwards.  On startup the relay first
+//! drains everything left pending (e.g. after a crash) and only then starts
+//! polling.
+//!
+//! Messages of one aggregate are published in insertion order: when one
+//! fails, later messages of the same aggregate wait for the next pass.  A
+//! message that fails `max_attempts` times is dead-lettered so it can't
+//! block its aggregate forever; one whose error isn't
+//! [retryable](AppError::is_retryable) is dead-lettered on the first
+//! failure.  Run a single relay per outbox table; two relays would publish
+//! concurrently and could reorder an aggregate.
+//!
+//! Messages keep the correlation and causation ids of the event envelope
+//! they were written for, so the published event stays in its chain.  Each
+//! pass sets [`OUTBOX_PENDING_EVENTS`] to the backlog left behind, so a relay
+//! that falls behind (or a broker refusing messages) shows up on the metrics
+//! endpoint.
+//!
+//! A message can be published more than once: a publish may succeed at the
+//! broker yet fail to be marked, or be retried after a timeout.  Its
+//! [`OutboxMessage::event_id`] is derived from the aggregate and the row's
+//! sequence number rather than generated, so every attempt publishes the
+//! same id and consumers can dedupe on it.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::events::{EventEnvelope, stable_event_id};
+use error::AppError;
+use serde::Serialize;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use super::DbPool;
+use crate::event_schema::EventSchemaRegistry;
+
+/// Gauge of outbox messages not yet published, set after every relay pass
+pub const OUTBOX_PENDING_EVENTS: &str = "outbox_pending_events";
+
+/// Schema of the outbox table, for services that don't manage it with
+/// their own migrations
+pub const OUTBOX_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS outbox (
+    id BIGSERIAL PRIMARY KEY,
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    correlation_id TEXT,
+    causation_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    published_at TIMESTAMPTZ,
+    dead_lettered_at TIMESTAMPTZ
+);
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS correlation_id TEXT;
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS causation_id TEXT;
+CREATE INDEX IF NOT EXISTS outbox_pending_idx ON outbox (id)
+    WHERE published_at IS NULL AND dead_lettered_at IS NULL;
+"#;
+
+/// Event to be written to the outbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewOutboxMessage {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    /// Originating request of the event's chain
+    pub correlation_id: Option<String>,
+    /// Request or event that caused this one
+    pub causation_id: Option<String>,
+}
+
+impl NewOutboxMessage {
+    pub fn new<T: Serialize>(
+        aggregate_type: impl Into<String>,
+        aggregate_id: impl Into<String>,
+        event_type: impl Into<String>,
+        payload: &T,
+    ) -> Result<Self, AppError> {
+        Ok(Self {
+            aggregate_type: aggregate_type.into(),
+            aggregate_id: aggregate_id.into(),
+            event_type: event_type.into(),
+            payload: serde_json::to_value(payload)
+                .map_err(|e| AppError::internal(format!("outbox payload not serializable: {e}")))?,
+            correlation_id: None,
+            causation_id: None,
+        })
+    }
+
+    /// Carry the correlation and causation ids of the event's envelope
+    pub fn with_trace_ids(
+        mut self,
+        correlation_id: impl Into<String>,
+        causation_id: Option<String>,
+    ) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self.causation_id = causation_id;
+        self
+    }
+}
+
+/// Event stored in the outbox
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub correlation_id: Option<String>,
+    pub causation_id: Option<String>,
+    pub created_at: OffsetDateTime,
+    /// Failed publish attempts so far
+    pub attempts: i32,
+}
+
+impl OutboxMessage {
+    /// Id of the published event, the same on every publish attempt
+    pub fn event_id(&self) -> String {
+        stable_event_id(&self.aggregate_type, &self.aggregate_id, self.id)
+    }
+
+    /// Envelope to publish the message in, stamped as published now
+    pub fn envelope(&self) -> EventEnvelope {
+        let event_id = self.event_id();
+        EventEnvelope {
+            correlation_id: self.correlation_id.clone().unwrap_or_else(|| event_id.clone()),
+            causation_id: self.causation_id.clone(),
+            event_id,
+            event_type: self.event_type.clone(),
+            occurred_at: self.created_at,
+            published_at: None,
+            payload: self.payload.clone(),
+        }
+        .published()
+    }
+}
+
+/// Outbox persistence
+#[async_trait]
+pub trait OutboxStore: Send + Sync {
+    /// Up to `limit` unpublished, live messages in insertion order
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxMessage>, AppError>;
+
+    async fn mark_published(&self, id: i64) -> Result<(), AppError>;
+
+    /// Record a failed attempt, returning the total number of attempts
+    async fn record_failure(&self, id: i64, error: &str) -> Result<i32, AppError>;
+
+    /// Stop retrying a message
+    async fn dead_letter(&self, id: i64, error: &str) -> Result<(), AppError>;
+
+    /// Number of unpublished, live messages
+    async fn pending_count(&self) -> Result<u64, AppError>;
+}
+
+/// Destination of outbox messages (message broker, event bus)
+///
+/// Implementations publish [`OutboxMessage::envelope`], whose id stays the
+/// same when a message is retried.
+#[async_trait]
+pub trait OutboxPublisher: Send + Sync {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), AppError>;
+}
+
+/// Outcome of one relay pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayPass {
+    pub published: usize,
+    /// Failed and left for a later pass
+    pub failed: usize,
+    pub dead_lettered: usize,
+}
+
+impl RelayPass {
+    fn absorb(&mut self, other: RelayPass) {
+        self.published += other.published;
+        self.failed += other.failed;
+        self.dead_lettered += other.dead_lettered;
+    }
+}
+
+/// Publishes outbox messages, draining the backlog on startup
+#[derive(Clone)]
+pub struct OutboxRelay {
+    store: Arc<dyn OutboxStore>,
+    publisher: Arc<dyn OutboxPublisher>,
+    batch_size: usize,
+    poll_interval: Duration,
+    max_attempts: i32,
+}
+
+impl OutboxRelay {
+    pub fn new(store: Arc<dyn OutboxStore>, publisher: Arc<dyn OutboxPublisher>) -> Self {
+        Self {
+            store,
+            publisher,
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+            max_attempts: 5,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Dead-letter a message after this many failed attempts
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Publish one batch of pending messages
+    pub async fn relay_pending(&self) -> Result<RelayPass, AppError> {
+        self.relay_batch(&mut HashSet::new()).await
+    }
+
+    /// Publish one batch, skipping the aggregates in `blocked` and adding
+    /// those whose message failed
+    async fn relay_batch(
+        &self,
+        blocked: &mut HashSet<(String, String)>,
+    ) -> Result<RelayPass, AppError> {
+        let mut pass = RelayPass::default();
+
+        for message in self.store.pending(self.batch_size).await? {
+            let aggregate = (message.aggregate_type.clone(), message.aggregate_id.clone());
+            if blocked.contains(&aggregate) {
+                continue;
+            }
+
+            let Err(e) = self.publisher.publish(&message).await else {
+                self.store.mark_published(message.id).await?;
+                pass.published += 1;
+                continue;
+            };
+
+            let reason = e.to_string();
+            let attempts = self.store.record_failure(message.id, &reason).await?;
+            if attempts >= self.max_attempts || !e.is_retryable() {
+                warn!(
+                    id = message.id,
+                    aggregate_type = %message.aggregate_type,
+                    aggregate_id = %message.aggregate_id,
+                    event_type = %message.event_type,
+                    attempts,
+                    error = %reason,
+                    "dead-lettering outbox message"
+                );
+                self.store.dead_letter(message.id, &reason).await?;
+                pass.dead_lettered += 1;
+            } else {
+                // Keep the aggregate's later messages behind this one
+                blocked.insert(aggregate);
+                pass.failed += 1;
+            }
+        }
+
+        // The backlog is only reported; failing to count it doesn't undo the
+        // pass, and the gauge keeps its last value until the next one
+        #[cfg(feature = "metrics")]
+        match self.store.pending_count().await {
+            Ok(pending) => metrics::gauge!(OUTBOX_PENDING_EVENTS).set(pending as f64),
+            Err(e) => warn!(error = %e, "failed to count pending outbox messages"),
+        }
+        Ok(pass)
+    }
+
+    /// Drain the backlog, stopping once a pass makes no progress
+    ///
+    /// A message that fails is tried once here: its aggregate is held back
+    /// for the rest of the catch-up, so back-to-back passes don't spend its
+    /// attempts within milliseconds.  It is left to the polling loop, which
+    /// retries it every poll interval until it is dead-lettered.
+    pub async fn catch_up(&self) -> Result<RelayPass, AppError> {
+        let mut total = RelayPass::default();
+        let mut held = HashSet::new();
+        loop {
+            let pass = self.relay_batch(&mut held).await?;
+            total.absorb(pass);
+            if pass.published + pass.dead_lettered == 0 {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Catch up, then poll until the task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            match self.catch_up().await {
+                Ok(pass) => info!(
+                    published = pass.published,
+                    dead_lettered = pass.dead_lettered,
+                    "outbox backlog drained"
+                ),
+                Err(e) => error!(error = %e, "outbox catch-up failed"),
+            }
+
+            let mut ticker = tokio::time::interval(self.poll_interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.relay_pending().await {
+                    error!(error = %e, "outbox relay pass failed");
+                }
+            }
+        })
+    }
+}
+
+fn store_error(e: sqlx::Error) -> AppError {
+    AppError::infrastructure("outbox", e.to_string())
+}
+
+/// PostgreSQL outbox table
+#[derive(Clone)]
+pub struct PgOutboxStore {
+    pool: DbPool,
+}
+
+impl PgOutboxStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the outbox table if it doesn't exist
+    pub async fn ensure_schema(&self) -> Result<(), AppError> {
+        sqlx::raw_sql(OUTBOX_SCHEMA)
+            .execute(self.pool.pool())
+            .await
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    /// Write `message` on `conn`, typically inside the transaction making
+    /// the state change it describes
+    pub async fn enqueue(
+        conn: &mut PgConnection,
+        message: &NewOutboxMessage,
+    ) -> Result<i64, AppError> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO outbox \
+             (aggregate_type, aggregate_id, event_type, payload, correlation_id, causation_id) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        )
+        .bind(&message.aggregate_type)
+        .bind(&message.aggregate_id)
+        .bind(&message.event_type)
+        .bind(&message.payload)
+        .bind(&message.correlation_id)
+        .bind(&message.causation_id)
+        .fetch_one(conn)
+        .await
+        .map_err(store_error)?;
+        Ok(id)
+    }
+
+    /// [`Self::enqueue`] after checking the payload against the event
+    /// type's registered schema
+    pub async fn enqueue_checked(
+        conn: &mut PgConnection,
+        message: &NewOutboxMessage,
+        schemas: &EventSchemaRegistry,
+    ) -> Result<i64, AppError> {
+        schemas.validate(&message.event_type, &message.payload)?;
+        Self::enqueue(conn, message).await
+    }
+}
+
+#[async_trait]
+impl OutboxStore for PgOutboxStore {
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxMessage>, AppError> {
+        sqlx::query_as(
+            "SELECT id, aggregate_type, aggregate_id, event_type, payload, correlation_id, \
+             causation_id, created_at, attempts \
+             FROM outbox WHERE published_at IS NULL AND dead_lettered_at IS NULL \
+             ORDER BY id LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(self.pool.pool())
+        .await
+        .map_err(store_error)
+    }
+
+    async fn mark_published(&self, id: i64) -> Result<(), AppError> {
+        sqlx::query("UPDATE outbox SET published_at = now() WHERE id = $1")
+            .bind(id)
+            .execute(self.pool.pool())
+            .await
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    async fn record_failure(&self, id: i64, error: &str) -> Result<i32, AppError> {
+        let (attempts,): (i32,) = sqlx::query_as(
+            "UPDATE outbox SET attempts = attempts + 1, last_error = $2 \
+             WHERE id = $1 RETURNING attempts",
+        )
+        .bind(id)
+        .bind(error)
+        .fetch_one(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        Ok(attempts)
+    }
+
+    async fn dead_letter(&self, id: i64, error: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE outbox SET dead_lettered_at = now(), last_error = $2 WHERE id = $1")
+            .bind(id)
+            .bind(error)
+            .execute(self.pool.pool())
+            .await
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    async fn pending_count(&self) -> Result<u64, AppError> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT count(*) FROM outbox WHERE published_at IS NULL AND dead_lettered_at IS NULL",
+        )
+        .fetch_one(self.pool.pool())
+        .await
+        .map_err(store_error)?;
+        Ok(count as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredMessage {
+    message: OutboxMessage,
+    published: bool,
+    dead_lettered: bool,
+}
+
+/// In-memory outbox for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryOutboxStore {
+    messages: Arc<Mutex<Vec<StoredMessage>>>,
+}
+
+impl InMemoryOutboxStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enqueue(&self, message: NewOutboxMessage) -> i64 {
+        let mut messages = self.messages.lock().unwrap();
+        let id = messages.len() as i64 + 1;
+        messages.push(StoredMessage {
+            message: OutboxMessage {
+                id,
+                aggregate_type: message.aggregate_type,
+                aggregate_id: message.aggregate_id,
+                event_type: message.event_type,
+                payload: message.payload,
+                correlation_id: message.correlation_id,
+                causation_id: message.causation_id,
+                created_at: OffsetDateTime::now_utc(),
+                attempts: 0,
+            },
+            published: false,
+            dead_lettered: false,
+        });
+        id
+    }
+
+    pub fn dead_lettered(&self) -> Vec<OutboxMessage> {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stored| stored.dead_lettered)
+            .map(|stored| stored.message.clone())
+            .collect()
+    }
+
+    fn update<T>(
+        &self,
+        id: i64,
+        apply: impl FnOnce(&mut StoredMessage) -> T,
+    ) -> Result<T, AppError> {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|stored| stored.message.id == id)
+            .map(apply)
+            .ok_or_else(|| AppError::not_found("outbox message", id.to_string()))
+    }
+}
+
+#[async_trait]
+impl OutboxStore for InMemoryOutboxStore {
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxMessage>, AppError> {
+        Ok(self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stored| !stored.published && !stored.dead_lettered)
+            .take(limit)
+            .map(|stored| stored.message.clone())
+            .collect())
+    }
+
+    async fn mark_published(&self, id: i64) -> Result<(), AppError> {
+        self.update(id, |stored| stored.published = true)
+    }
+
+    async fn record_failure(&self, id: i64, _error: &str) -> Result<i32, AppError> {
+        self.update(id, |stored| {
+            stored.message.attempts += 1;
+            stored.message.attempts
+        })
+    }
+
+    async fn dead_letter(&self, id: i64, _error: &str) -> Result<(), AppError> {
+        self.update(id, |stored| stored.dead_lettered = true)
+    }
+
+    async fn pending_count(&self) -> Result<u64, AppError> {
+        Ok(self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stored| !stored.published && !stored.dead_lettered)
+            .count() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Publisher recording what it was given, optionally failing chosen events
+    #[derive(Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<OutboxMessage>>,
+        failures: Mutex<HashMap<String, usize>>,
+    }
+
+    impl RecordingPublisher {
+        /// Fail `event_type` the next `times` attempts
+        fn failing(self, event_type: &str, times: usize) -> Self {
+            self.failures
+                .lock()
+                .unwrap()
+                .insert(event_type.to_string(), times);
+            self
+        }
+
+        fn event_types(&self) -> Vec<String> {
+            self.published
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|message| message.event_type.clone())
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl OutboxPublisher for RecordingPublisher {
+        async fn publish(&self, message: &OutboxMessage) -> Result<(), AppError> {
+            if let Some(remaining) = self.failures.lock().unwrap().get_mut(&message.event_type)
+                && *remaining > 0
+            {
+                *remaining -= 1;
+                return Err(AppError::external("broker", "publish rejected"));
+            }
+            self.published.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+    }
+
+    fn event(aggregate_id: &str, event_type: &str) -> NewOutboxMessage {
+        NewOutboxMessage::new(
+            "order",
+            aggregate_id,
+            event_type,
+            &json!({ "id": aggregate_id }),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pending_rows_from_before_startup_are_published_on_boot() {
+        let store = InMemoryOutboxStore::new();
+        for (order, event_type) in [
+            ("o-1", "OrderPlaced"),
+            ("o-2", "OrderPlaced"),
+            ("o-1", "OrderPaid"),
+            ("o-1", "OrderShipped"),
+        ] {
+            store.enqueue(event(order, event_type));
+        }
+        let publisher = Arc::new(RecordingPublisher::default());
+
+        // Polling is far away, so anything published came from catch-up
+        let relay = OutboxRelay::new(Arc::new(store.clone()), publisher.clone())
+            .with_batch_size(2)
+            .with_poll_interval(Duration::from_secs(3600));
+        let handle = relay.spawn();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while publisher.event_types().len() < 4 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("backlog was not drained on startup");
+        handle.abort();
+
+        assert_eq!(
+            publisher.event_types(),
+            ["OrderPlaced", "OrderPlaced", "OrderPaid", "OrderShipped"]
+        );
+        assert!(store.pending(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failures_hold_back_the_aggregate_then_dead_letter() {
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        store.enqueue(event("o-1", "OrderPaid"));
+        store.enqueue(event("o-2", "OrderCancelled"));
+        let publisher = Arc::new(RecordingPublisher::default().failing("OrderPlaced", usize::MAX));
+        let relay =
+            OutboxRelay::new(Arc::new(store.clone()), publisher.clone()).with_max_attempts(3);
+
+        // o-1 is blocked behind its failing first event; o-2 is unaffected
+        let pass = relay.relay_pending().await.unwrap();
+        assert_eq!(
+            pass,
+            RelayPass {
+                published: 1,
+                failed: 1,
+                dead_lettered: 0
+            }
+        );
+        assert_eq!(publisher.event_types(), ["OrderCancelled"]);
+
+        relay.relay_pending().await.unwrap();
+        let pass = relay.relay_pending().await.unwrap();
+        assert_eq!(pass.dead_lettered, 1);
+        assert_eq!(publisher.event_types(), ["OrderCancelled", "OrderPaid"]);
+        let dead = store.dead_lettered();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].event_type, "OrderPlaced");
+        assert_eq!(dead[0].attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_tries_a_failing_message_once() {
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        for event_type in ["OrderPlaced", "OrderPaid", "OrderShipped"] {
+            store.enqueue(event("o-2", event_type));
+        }
+        let publisher = Arc::new(RecordingPublisher::default().failing("OrderPlaced", 1));
+        let relay = OutboxRelay::new(Arc::new(store.clone()), publisher.clone())
+            .with_batch_size(2)
+            .with_max_attempts(2);
+
+        // o-2 keeps the catch-up going for three passes, yet o-1's event
+        // is attempted once and left for polling rather than dead-lettered
+        let total = relay.catch_up().await.unwrap();
+        assert_eq!(total.failed, 1);
+        assert_eq!(total.dead_lettered, 0);
+        let pending = store.pending(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+
+        assert_eq!(relay.relay_pending().await.unwrap().published, 1);
+        assert!(store.dead_lettered().is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_relay_pass_reports_the_pending_backlog() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        store.enqueue(event("o-1", "OrderPaid"));
+        store.enqueue(event("o-2", "OrderPlaced"));
+        let publisher = Arc::new(RecordingPublisher::default().failing("OrderPaid", usize::MAX));
+        let relay = OutboxRelay::new(Arc::new(store.clone()), publisher);
+
+        relay.relay_pending().await.unwrap();
+        let rendered = handle.render();
+        assert!(rendered.contains("outbox_pending_events 1"), "{rendered}");
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_failing_to_count_the_backlog_does_not_fail_the_pass() {
+        /// Store whose backlog count is down
+        struct Uncountable(InMemoryOutboxStore);
+
+        #[async_trait]
+        impl OutboxStore for Uncountable {
+            async fn pending(&self, limit: usize) -> Result<Vec<OutboxMessage>, AppError> {
+                self.0.pending(limit).await
+            }
+
+            async fn mark_published(&self, id: i64) -> Result<(), AppError> {
+                self.0.mark_published(id).await
+            }
+
+            async fn record_failure(&self, id: i64, error: &str) -> Result<i32, AppError> {
+                self.0.record_failure(id, error).await
+            }
+
+            async fn dead_letter(&self, id: i64, error: &str) -> Result<(), AppError> {
+                self.0.dead_letter(id, error).await
+            }
+
+            async fn pending_count(&self) -> Result<u64, AppError> {
+                Err(AppError::service_unavailable("database", None))
+            }
+        }
+
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        let publisher = Arc::new(RecordingPublisher::default());
+        let relay = OutboxRelay::new(Arc::new(Uncountable(store.clone())), publisher.clone());
+
+        assert_eq!(relay.relay_pending().await.unwrap().published, 1);
+        assert_eq!(publisher.event_types(), ["OrderPlaced"]);
+    }
+
+    #[tokio::test]
+    async fn test_terminal_publish_errors_dead_letter_without_retrying() {
+        struct RejectingPublisher;
+
+        #[async_trait]
+        impl OutboxPublisher for RejectingPublisher {
+            async fn publish(&self, message: &OutboxMessage) -> Result<(), AppError> {
+                Err(AppError::validation(format!(
+                    "{} does not match its schema",
+                    message.event_type
+                )))
+            }
+        }
+
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        let relay = OutboxRelay::new(Arc::new(store.clone()), Arc::new(RejectingPublisher))
+            .with_max_attempts(5);
+
+        let pass = relay.relay_pending().await.unwrap();
+        assert_eq!(pass.dead_lettered, 1);
+        assert_eq!(store.dead_lettered()[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_republishing_a_row_reuses_its_event_id() {
+        let store = InMemoryOutboxStore::new();
+        store.enqueue(event("o-1", "OrderPlaced"));
+        store.enqueue(event("o-1", "OrderPaid"));
+        // The broker takes OrderPlaced but the relay sees a failure, so the
+        // row is published again on the next pass
+        let publisher = Arc::new(RecordingPublisher::default().failing("OrderPlaced", 1));
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+
+        struct EnvelopeTap {
+            inner: Arc<RecordingPublisher>,
+            attempts: Arc<Mutex<Vec<EventEnvelope>>>,
+        }
+
+        #[async_trait]
+        impl OutboxPublisher for EnvelopeTap {
+            async fn publish(&self, message: &OutboxMessage) -> Result<(), AppError> {
+                self.attempts.lock().unwrap().push(message.envelope());
+                self.inner.publish(message).await
+            }
+        }
+
+        let relay = OutboxRelay::new(
+            Arc::new(store.clone()),
+            Arc::new(EnvelopeTap {
+                inner: publisher.clone(),
+                attempts: attempts.clone(),
+            }),
+        );
+        assert_eq!(relay.relay_pending().await.unwrap().failed, 1);
+        assert_eq!(relay.relay_pending().await.unwrap().published, 2);
+
+        let attempts = attempts.lock().unwrap().clone();
+        let placed: Vec<_> = attempts
+            .iter()
+            .filter(|envelope| envelope.event_type == "OrderPlaced")
+            .collect();
+        assert_eq!(placed.len(), 2);
+        assert_eq!(placed[0].event_id, placed[1].event_id);
+        assert_eq!(placed[0].event_id, stable_event_id("order", "o-1", 1));
+
+        let paid = attempts
+            .iter()
+            .find(|envelope| envelope.event_type == "OrderPaid")
+            .unwrap();
+        assert_ne!(paid.event_id, placed[0].event_id);
+        assert_eq!(publisher.event_types(), ["OrderPlaced", "OrderPaid"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_pg_store_round_trip() {
+        let config = super::super::DatabaseConfig {
+            url: std::env::var("DATABASE_URL").unwrap(),
+            ..Default::default()
+        };
+        let pool = DbPool::new(&config).await.unwrap();
+        let store = PgOutboxStore::new(pool.clone());
+        store.ensure_schema().await.unwrap();
+
+        let aggregate_id = format!("outbox-test-{}", std::process::id());
+        let mut tx = pool.pool().begin().await.unwrap();
+        let placed =
+            event(&aggregate_id, "OrderPlaced").with_trace_ids("corr-1", Some("req-1".to_string()));
+        let id = PgOutboxStore::enqueue(&mut tx, &placed).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let pending = store.pending(1000).await.unwrap();
+        let message = pending.iter().find(|message| message.id == id).unwrap();
+        assert_eq!(message.correlation_id.as_deref(), Some("corr-1"));
+        assert_eq!(message.causation_id.as_deref(), Some("req-1"));
+        assert_eq!(store.record_failure(id, "broker down").await.unwrap(), 1);
+        store.mark_published(id).await.unwrap();
+        let pending = store.pending(1000).await.unwrap();
+        assert!(pending.iter().all(|message| message.id != id));
+
+        sqlx::query("DELETE FROM outbox WHERE aggregate_id = $1")
+            .bind(&aggregate_id)
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+}
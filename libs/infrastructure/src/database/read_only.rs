@@ -0,0 +1,82 @@
+//! Runtime read-only switch shared through Redis
+//!
+//! Operators set one Redis key (`SET app:maintenance:db_read_only 1`) and
+//! every instance running [`spawn_read_only_sync`] follows within one poll
+//! interval.  Deleting the key returns each pool to the mode it was
+//! configured with; while Redis is unreachable the current mode is kept.
+
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use super::DbPool;
+use crate::redis::{RedisKey, RedisPool};
+
+/// Key holding the read-only flag under `prefix`
+pub fn read_only_flag_key(prefix: &str) -> RedisKey {
+    RedisKey::with_prefix(prefix, ["maintenance", "db_read_only"])
+}
+
+/// Interpret a flag value; `None` for values that aren't a clear on/off
+pub fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Poll `key` every `interval` and apply it to `pool`
+pub fn spawn_read_only_sync(
+    pool: DbPool,
+    redis: RedisPool,
+    key: RedisKey,
+    interval: Duration,
+) -> JoinHandle<()> {
+    let configured = pool.is_read_only();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let value: Result<Option<String>, _> = async {
+                let mut conn = redis.connection().await?;
+                let value = redis::cmd("GET")
+                    .arg(key.as_str())
+                    .query_async(&mut conn)
+                    .await?;
+                Ok::<_, crate::redis::RedisError>(value)
+            }
+            .await;
+
+            match value {
+                Ok(None) => pool.set_read_only(configured),
+                Ok(Some(value)) => match parse_flag(&value) {
+                    Some(read_only) => pool.set_read_only(read_only),
+                    None => warn!(
+                        key = key.as_str(),
+                        value, "ignoring unrecognised read-only flag"
+                    ),
+                },
+                Err(e) => warn!(error = %e, "failed to read database read-only flag"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_values_and_key() {
+        assert_eq!(parse_flag("1"), Some(true));
+        assert_eq!(parse_flag(" ON "), Some(true));
+        assert_eq!(parse_flag("false"), Some(false));
+        assert_eq!(parse_flag("maybe"), None);
+        assert_eq!(
+            read_only_flag_key("app").as_str(),
+            "app:maintenance:db_read_only"
+        );
+    }
+}
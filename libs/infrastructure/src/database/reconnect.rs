@@ -0,0 +1,118 @@
+//! Pacing of new database connections
+//!
+//! When PostgreSQL comes back after an outage every replica finds its pool
+//! empty at the same moment, and each waiting request opens a connection at
+//! once: hundreds of handshakes land on a database that has only just
+//! recovered.  [`ReconnectThrottle`] lets a pool open `burst` connections
+//! straight away and then one per `interval`, each wait stretched by a
+//! random jitter so replicas that recovered together drift apart.
+//!
+//! [`DbPool`](super::DbPool) consults the throttle from its `after_connect`
+//! hook, i.e. only for connections it opens, however they were asked for.
+//! A warm pool never waits.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Share of the interval added at random to each wait
+const JITTER_RATIO: f64 = 0.5;
+
+/// Token bucket for connections opened by a pool
+#[derive(Debug, Clone)]
+pub struct ReconnectThrottle {
+    burst: u32,
+    interval: Duration,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Connections that may open without waiting; negative while callers
+    /// are queued for future slots
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl ReconnectThrottle {
+    /// Allow `burst` connections at once, then one per `interval`
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        Self {
+            burst,
+            interval,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: f64::from(burst),
+                refilled_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Wait until a new connection may be opened
+    pub async fn admit(&self) {
+        let Some(wait) = self.reserve() else {
+            return;
+        };
+        let jitter = self.interval.mul_f64(JITTER_RATIO * fastrand::f64());
+        debug!(
+            wait_ms = (wait + jitter).as_millis() as u64,
+            "pacing new database connection"
+        );
+        tokio::time::sleep(wait + jitter).await;
+    }
+
+    /// Take a token, returning how long to wait for it when there was none
+    fn reserve(&self) -> Option<Duration> {
+        if self.interval.is_zero() {
+            return None;
+        }
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let refilled = now.duration_since(bucket.refilled_at).as_secs_f64()
+            / self.interval.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refilled).min(f64::from(self.burst));
+        bucket.refilled_at = now;
+
+        bucket.tokens -= 1.0;
+        (bucket.tokens < 0.0).then(|| self.interval.mul_f64(-bucket.tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reconnects_after_the_burst_are_spread_out() {
+        let interval = Duration::from_millis(20);
+        let throttle = ReconnectThrottle::new(3, interval);
+        let started = Instant::now();
+
+        // Ten requests find the pool empty at the same moment
+        let mut admitted: Vec<Duration> =
+            futures_util::future::join_all((0..10).map(|_| {
+                let throttle = throttle.clone();
+                async move {
+                    throttle.admit().await;
+                    started.elapsed()
+                }
+            }))
+            .await;
+        admitted.sort();
+
+        assert!(admitted[2] < interval / 2, "{admitted:?}");
+        // Every later connection waits for its own slot: no two share one
+        for (i, at) in admitted.iter().enumerate().skip(3) {
+            let slot = interval * (i as u32 - 2);
+            assert!(*at >= slot, "connection {i} opened at {at:?}, before {slot:?}");
+        }
+        assert!(admitted[9] < interval * 7 + Duration::from_millis(200), "{admitted:?}");
+
+        // Once the bucket refills, a lone reconnect goes straight through
+        tokio::time::sleep(interval * 5).await;
+        let before = Instant::now();
+        throttle.admit().await;
+        assert!(before.elapsed() < interval / 2);
+    }
+}
@@ -0,0 +1,920 @@
+//! Repository helpers shared by domain repositories
+//!
+//! [`RepositoryExt::upsert`] writes an [`Entity`] with
+//! `INSERT ... ON CONFLICT (...) DO UPDATE SET ...` and reports whether the
+//! row was inserted or updated, which is what seeds and idempotent event
+//! consumers need: replaying the same write converges instead of failing on
+//! a unique violation.
+//!
+//! ```rust,ignore
+//! impl Entity for Currency {
+//!     const TABLE: &'static str = "currencies";
+//!     const COLUMNS: &'static [&'static str] = &["code", "name", "decimals"];
+//!
+//!     fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+//!         query.bind(&self.code).bind(&self.name).bind(self.decimals)
+//!     }
+//! }
+//!
+//! pool.upsert(&ngn, &["code"], &["name", "decimals"]).await?;
+//! ```
+//!
+//! [`RepositoryExt::stream`] reads the rows matching a [`Filter`] one at a
+//! time instead of collecting them, for exports over tables too large to
+//! hold in memory:
+//!
+//! ```rust,ignore
+//! let rows = pool.stream::<Payment>(Filter::new().gte("created_at", since).order_by("id"));
+//! Ndjson(rows)
+//! ```
+//!
+//! [`RepositoryExt::fetch_page`] reads one page of a [`Filter`]'s rows
+//! along with how many rows match in total, for paginated list endpoints
+//! (see `list::list_handler`).
+//!
+//! [`RepositoryExt::insert_many`] writes a batch with multi-row `INSERT`s.
+//! [`BatchMode::AllOrNothing`] runs the whole batch in one transaction, so
+//! one bad row rolls everything back; [`BatchMode::BestEffort`] commits
+//! each chunk on its own and reports which chunks failed:
+//!
+//! ```rust,ignore
+//! let report = pool.insert_many(&rates, BatchMode::BestEffort { chunk_size: 500 }).await?;
+//! for chunk in report.failed() {
+//!     warn!(rows = ?chunk.rows, error = ?chunk.result, "rate chunk rejected");
+//! }
+//! ```
+
+use std::ops::Range;
+
+use async_trait::async_trait;
+use common::value_objects::pagination_vo::Pagination;
+use error::AppError;
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{Encode, Executor, FromRow, PgConnection, Postgres, QueryBuilder, Row, Type};
+use tokio::sync::mpsc;
+
+use super::DbPool;
+
+/// Query an [`Entity`] binds its column values onto
+pub type EntityQuery<'q> = Query<'q, Postgres, PgArguments>;
+
+/// Row type that can be written generically
+pub trait Entity: Send + Sync {
+    /// Table name, optionally schema-qualified (`billing.invoices`)
+    const TABLE: &'static str;
+    /// Column names, in the order [`Entity::bind`] binds their values
+    const COLUMNS: &'static [&'static str];
+
+    /// Column holding the owning tenant, for tenant-scoped tables
+    ///
+    /// Reads through a [`RepositoryContext`](super::RepositoryContext) are
+    /// then filtered to the context's tenant, and writes checked against it.
+    const TENANT_COLUMN: Option<&'static str> = None;
+
+    /// Bind one value per column of [`Entity::COLUMNS`], in order
+    fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q>;
+
+    /// Tenant this row belongs to, for tenant-scoped tables
+    fn tenant_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// What an upsert did to the row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Inserted,
+    Updated,
+    /// The row existed and there were no columns to update
+    Unchanged,
+}
+
+/// How [`RepositoryExt::insert_many`] treats a batch when some rows fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    /// One transaction for the whole batch; any failure rolls it all back
+    AllOrNothing,
+    /// Each chunk of `chunk_size` rows commits in its own transaction;
+    /// failed chunks are reported and the rest are kept
+    BestEffort { chunk_size: usize },
+}
+
+/// Outcome of one chunk of a best-effort batch
+#[derive(Debug)]
+pub struct ChunkReport {
+    /// Positions of the chunk's rows in the batch
+    pub rows: Range<usize>,
+    /// Rows inserted, or why the chunk was rolled back
+    pub result: Result<u64, AppError>,
+}
+
+/// Outcome of [`RepositoryExt::insert_many`]
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Rows committed
+    pub inserted: u64,
+    /// One entry per chunk, in batch order; a single entry covering every
+    /// row for [`BatchMode::AllOrNothing`]
+    pub chunks: Vec<ChunkReport>,
+}
+
+impl BatchReport {
+    /// Chunks that were rolled back
+    pub fn failed(&self) -> impl Iterator<Item = &ChunkReport> {
+        self.chunks.iter().filter(|chunk| chunk.result.is_err())
+    }
+
+    /// Whether every row was committed
+    pub fn is_complete(&self) -> bool {
+        self.failed().next().is_none()
+    }
+}
+
+/// Postgres accepts at most this many bind parameters per statement
+const MAX_BIND_PARAMS: usize = u16::MAX as usize;
+
+/// Values one [`Filter::is_in`] condition accepts unless the filter is
+/// given another limit
+pub const DEFAULT_MAX_IN_VALUES: usize = 100;
+
+/// Rows streamed ahead of the consumer before the query waits for it
+const STREAM_BUFFER: usize = 64;
+
+type BindFn = Box<dyn Fn(&mut QueryBuilder<'static, Postgres>) + Send>;
+
+/// Conditions, ordering, limit and offset of a read
+///
+/// Conditions are `AND`ed together; column names are checked against the
+/// entity's [`Entity::COLUMNS`] when the query is built.
+#[derive(Default)]
+pub struct Filter {
+    conditions: Vec<(String, &'static str, BindFn)>,
+    order_by: Vec<(String, bool)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    max_in_values: Option<usize>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows whose `column` equals `value`
+    pub fn eq<T>(self, column: &str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.compare(column, "=", value)
+    }
+
+    /// Rows whose `column` is greater than `value`
+    pub fn gt<T>(self, column: &str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.compare(column, ">", value)
+    }
+
+    /// Rows whose `column` is at least `value`
+    pub fn gte<T>(self, column: &str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.compare(column, ">=", value)
+    }
+
+    /// Rows whose `column` is less than `value`
+    pub fn lt<T>(self, column: &str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.compare(column, "<", value)
+    }
+
+    /// Rows whose `column` is at most `value`
+    pub fn lte<T>(self, column: &str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.compare(column, "<=", value)
+    }
+
+    /// Rows whose `column` is one of `values`
+    ///
+    /// Values usually come straight from a query string, and a long list
+    /// makes an expensive query, so more than the filter's limit (see
+    /// [`Filter::max_in_values`]) is rejected as a validation error on
+    /// `column`.
+    pub fn is_in<T>(mut self, column: &str, values: Vec<T>) -> Result<Self, AppError>
+    where
+        Vec<T>: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        let max = self.max_in_values.unwrap_or(DEFAULT_MAX_IN_VALUES);
+        if values.len() > max {
+            return Err(AppError::validation_with_field(
+                format!("at most {max} values may be given, got {}", values.len()),
+                column,
+            ));
+        }
+        self.conditions.push((
+            column.to_string(),
+            "= ANY",
+            Box::new(move |query: &mut QueryBuilder<'static, Postgres>| {
+                query.push("(").push_bind(values.clone()).push(")");
+            }),
+        ));
+        Ok(self)
+    }
+
+    /// Accept at most `max` values per [`Filter::is_in`] condition
+    /// ([`DEFAULT_MAX_IN_VALUES`] by default)
+    pub fn max_in_values(mut self, max: usize) -> Self {
+        self.max_in_values = Some(max);
+        self
+    }
+
+    /// Order by `column`, ascending
+    pub fn order_by(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), false));
+        self
+    }
+
+    /// Order by `column`, descending
+    pub fn order_by_desc(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), true));
+        self
+    }
+
+    /// Stop after `limit` rows
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the first `offset` rows
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Restrict to rows of `tenant_id`, ahead of the other conditions
+    pub(crate) fn scoped_to(mut self, column: &str, tenant_id: String) -> Self {
+        let tenant = Self::new().eq(column, tenant_id);
+        self.conditions.splice(0..0, tenant.conditions);
+        self
+    }
+
+    fn compare<T>(mut self, column: &str, op: &'static str, value: T) -> Self
+    where
+        T: for<'q> Encode<'q, Postgres> + Type<Postgres> + Clone + Send + 'static,
+    {
+        self.conditions.push((
+            column.to_string(),
+            op,
+            Box::new(move |query: &mut QueryBuilder<'static, Postgres>| {
+                query.push_bind(value.clone());
+            }),
+        ));
+        self
+    }
+}
+
+/// Generic read and write operations on top of a pool
+#[async_trait]
+pub trait RepositoryExt {
+    /// Insert `entity`, or update `update_columns` of the row that conflicts
+    /// on `conflict_columns`
+    ///
+    /// `conflict_columns` must match a unique index or constraint.  With no
+    /// `update_columns` an existing row is left as is.
+    async fn upsert<E: Entity>(
+        &self,
+        entity: &E,
+        conflict_columns: &[&str],
+        update_columns: &[&str],
+    ) -> Result<UpsertOutcome, AppError>;
+
+    /// Rows of `E` matching `filter`, fetched as the stream is polled
+    ///
+    /// At most a few dozen rows are buffered ahead of the consumer; dropping
+    /// the stream cancels the query.  An invalid filter is reported as the
+    /// first item.
+    fn stream<E>(&self, filter: Filter) -> BoxStream<'static, Result<E, AppError>>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Unpin + 'static;
+
+    /// Rows of `E` matching `filter` on `page`, and how many rows match on
+    /// all pages
+    ///
+    /// The page replaces any limit and offset set on `filter`.
+    async fn fetch_page<E>(
+        &self,
+        filter: Filter,
+        page: Pagination,
+    ) -> Result<(Vec<E>, u64), AppError>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static;
+
+    /// Insert `entities` as `mode` says
+    ///
+    /// In [`BatchMode::AllOrNothing`] a failing row fails the call and
+    /// nothing is written.  In [`BatchMode::BestEffort`] chunk failures are
+    /// reported in the [`BatchReport`]; only failing to reach the database
+    /// at all is an error.
+    async fn insert_many<E: Entity>(
+        &self,
+        entities: &[E],
+        mode: BatchMode,
+    ) -> Result<BatchReport, AppError>;
+}
+
+#[async_trait]
+impl RepositoryExt for DbPool {
+    async fn upsert<E: Entity>(
+        &self,
+        entity: &E,
+        conflict_columns: &[&str],
+        update_columns: &[&str],
+    ) -> Result<UpsertOutcome, AppError> {
+        self.ensure_writable()?;
+        let sql = upsert_sql::<E>(conflict_columns, update_columns)?;
+        self.timed(run_upsert(self.pool(), &sql, entity)).await
+    }
+
+    fn stream<E>(&self, filter: Filter) -> BoxStream<'static, Result<E, AppError>>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Unpin + 'static,
+    {
+        let mut query = match select_query::<E>(filter) {
+            Ok(query) => query,
+            Err(e) => return stream::once(async { Err(e) }).boxed(),
+        };
+        super::query_budget::record_query();
+
+        // The query runs on its own task so the stream can own it; the
+        // bounded channel keeps it from reading ahead of the consumer
+        let pool = self.pool().clone();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut rows = query.build_query_as::<E>().fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row.map_err(AppError::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+        )
+        .boxed()
+    }
+
+    async fn fetch_page<E>(
+        &self,
+        filter: Filter,
+        page: Pagination,
+    ) -> Result<(Vec<E>, u64), AppError>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+    {
+        let mut count = count_query::<E>(&filter)?;
+        let mut select = select_query::<E>(
+            filter
+                .limit(i64::from(page.limit()))
+                .offset(page.offset() as i64),
+        )?;
+
+        super::query_budget::record_query();
+        let total: i64 = self
+            .timed(count.build_query_scalar().fetch_one(self.pool()))
+            .await?;
+        super::query_budget::record_query();
+        let rows = self
+            .timed(select.build_query_as::<E>().fetch_all(self.pool()))
+            .await?;
+        Ok((rows, total.max(0) as u64))
+    }
+
+    async fn insert_many<E: Entity>(
+        &self,
+        entities: &[E],
+        mode: BatchMode,
+    ) -> Result<BatchReport, AppError> {
+        self.ensure_writable()?;
+        // Statements are split further so none exceeds the bind limit
+        let statement_rows = (MAX_BIND_PARAMS / E::COLUMNS.len().max(1)).max(1);
+        let mut report = BatchReport::default();
+
+        match mode {
+            BatchMode::AllOrNothing => {
+                let mut tx = self.begin().await?;
+                for rows in entities.chunks(statement_rows) {
+                    report.inserted += self.timed(insert_rows(&mut tx, rows)).await?;
+                }
+                tx.commit().await?;
+                report.chunks.push(ChunkReport {
+                    rows: 0..entities.len(),
+                    result: Ok(report.inserted),
+                });
+            }
+            BatchMode::BestEffort { chunk_size } => {
+                let chunk_size = chunk_size.max(1);
+                for (i, chunk) in entities.chunks(chunk_size).enumerate() {
+                    let start = i * chunk_size;
+                    let mut tx = self.begin().await?;
+                    let mut result = Ok(0);
+                    for rows in chunk.chunks(statement_rows) {
+                        match self.timed(insert_rows(&mut tx, rows)).await {
+                            Ok(inserted) => result = result.map(|n| n + inserted),
+                            Err(e) => {
+                                result = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                    let result = match result {
+                        Ok(inserted) => tx.commit().await.map(|_| inserted).map_err(AppError::from),
+                        // Dropping the transaction rolls the chunk back
+                        Err(e) => Err(e),
+                    };
+                    if let Ok(inserted) = &result {
+                        report.inserted += inserted;
+                    }
+                    report.chunks.push(ChunkReport {
+                        rows: start..start + chunk.len(),
+                        result,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Insert `rows` with one multi-row `INSERT`
+async fn insert_rows<E: Entity>(conn: &mut PgConnection, rows: &[E]) -> Result<u64, AppError> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    let sql = insert_sql::<E>(rows.len());
+    let mut query = sqlx::query(&sql);
+    for row in rows {
+        query = row.bind(query);
+    }
+    super::query_budget::record_query();
+    Ok(query.execute(conn).await?.rows_affected())
+}
+
+/// `INSERT` of `rows` rows of `E`'s columns
+pub(crate) fn insert_sql<E: Entity>(rows: usize) -> String {
+    let columns: Vec<String> = E::COLUMNS.iter().map(|c| quote_ident(c)).collect();
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let placeholders: Vec<String> = (1..=columns.len())
+                .map(|column| format!("${}", row * columns.len() + column))
+                .collect();
+            format!("({})", placeholders.join(", "))
+        })
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        quote_ident(E::TABLE),
+        columns.join(", "),
+        values.join(", ")
+    )
+}
+
+/// `SELECT` of `E`'s columns with `filter` applied
+pub(crate) fn select_query<E: Entity>(
+    filter: Filter,
+) -> Result<QueryBuilder<'static, Postgres>, AppError> {
+    check_columns::<E>(&filter)?;
+
+    let columns: Vec<String> = E::COLUMNS.iter().map(|c| quote_ident(c)).collect();
+    let mut query = QueryBuilder::new(format!(
+        "SELECT {} FROM {}",
+        columns.join(", "),
+        quote_ident(E::TABLE)
+    ));
+    push_conditions(&mut query, &filter);
+    if !filter.order_by.is_empty() {
+        let order: Vec<String> = filter
+            .order_by
+            .iter()
+            .map(|(column, desc)| {
+                format!(
+                    "{}{}",
+                    quote_ident(column),
+                    if *desc { " DESC" } else { "" }
+                )
+            })
+            .collect();
+        query.push(format!(" ORDER BY {}", order.join(", ")));
+    }
+    if let Some(limit) = filter.limit {
+        query.push(" LIMIT ").push_bind(limit);
+    }
+    if let Some(offset) = filter.offset {
+        query.push(" OFFSET ").push_bind(offset);
+    }
+    Ok(query)
+}
+
+/// `SELECT COUNT(*)` of the rows matching `filter`'s conditions
+pub(crate) fn count_query<E: Entity>(
+    filter: &Filter,
+) -> Result<QueryBuilder<'static, Postgres>, AppError> {
+    check_columns::<E>(filter)?;
+
+    let mut query = QueryBuilder::new(format!("SELECT COUNT(*) FROM {}", quote_ident(E::TABLE)));
+    push_conditions(&mut query, filter);
+    Ok(query)
+}
+
+fn check_columns<E: Entity>(filter: &Filter) -> Result<(), AppError> {
+    if let Some(unknown) = filter
+        .conditions
+        .iter()
+        .map(|(column, _, _)| column)
+        .chain(filter.order_by.iter().map(|(column, _)| column))
+        .find(|column| !E::COLUMNS.contains(&column.as_str()))
+    {
+        return Err(AppError::internal(format!(
+            "{} has no column '{unknown}'",
+            E::TABLE
+        )));
+    }
+    Ok(())
+}
+
+fn push_conditions(query: &mut QueryBuilder<'static, Postgres>, filter: &Filter) {
+    for (i, (column, op, bind)) in filter.conditions.iter().enumerate() {
+        query.push(if i == 0 { " WHERE " } else { " AND " });
+        query.push(format!("{} {op} ", quote_ident(column)));
+        bind(query);
+    }
+}
+
+/// [`RepositoryExt::upsert`] inside an open transaction or connection
+pub async fn upsert_in<E: Entity>(
+    conn: &mut PgConnection,
+    entity: &E,
+    conflict_columns: &[&str],
+    update_columns: &[&str],
+) -> Result<UpsertOutcome, AppError> {
+    let sql = upsert_sql::<E>(conflict_columns, update_columns)?;
+    run_upsert(conn, &sql, entity).await
+}
+
+pub(crate) async fn run_upsert<'c, X, E>(
+    executor: X,
+    sql: &str,
+    entity: &E,
+) -> Result<UpsertOutcome, AppError>
+where
+    X: Executor<'c, Database = Postgres>,
+    E: Entity,
+{
+    super::query_budget::record_query();
+    let row = entity
+        .bind(sqlx::query(sql))
+        .fetch_optional(executor)
+        .await?;
+    Ok(outcome(row.map(|row| row.get::<bool, _>("inserted"))))
+}
+
+fn outcome(inserted: Option<bool>) -> UpsertOutcome {
+    match inserted {
+        Some(true) => UpsertOutcome::Inserted,
+        Some(false) => UpsertOutcome::Updated,
+        None => UpsertOutcome::Unchanged,
+    }
+}
+
+/// Double-quote an identifier, schema part included
+fn quote_ident(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Build the upsert statement, rejecting columns the entity doesn't have
+pub(crate) fn upsert_sql<E: Entity>(
+    conflict_columns: &[&str],
+    update_columns: &[&str],
+) -> Result<String, AppError> {
+    if conflict_columns.is_empty() {
+        return Err(AppError::internal(format!(
+            "upsert into {} needs at least one conflict column",
+            E::TABLE
+        )));
+    }
+    if let Some(unknown) = conflict_columns
+        .iter()
+        .chain(update_columns)
+        .find(|column| !E::COLUMNS.contains(column))
+    {
+        return Err(AppError::internal(format!(
+            "{} has no column '{unknown}'",
+            E::TABLE
+        )));
+    }
+
+    let columns: Vec<String> = E::COLUMNS.iter().map(|c| quote_ident(c)).collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("${i}")).collect();
+    let conflict: Vec<String> = conflict_columns.iter().map(|c| quote_ident(c)).collect();
+    let action = if update_columns.is_empty() {
+        "DO NOTHING".to_string()
+    } else {
+        let assignments: Vec<String> = update_columns
+            .iter()
+            .map(|c| {
+                let column = quote_ident(c);
+                format!("{column} = EXCLUDED.{column}")
+            })
+            .collect();
+        match E::TENANT_COLUMN {
+            // A conflicting row of another tenant is left alone
+            Some(tenant) => {
+                let tenant = quote_ident(tenant);
+                format!(
+                    "DO UPDATE SET {} WHERE {}.{tenant} = EXCLUDED.{tenant}",
+                    assignments.join(", "),
+                    quote_ident(E::TABLE)
+                )
+            }
+            None => format!("DO UPDATE SET {}", assignments.join(", ")),
+        }
+    };
+
+    // `xmax` is zero only for a freshly inserted row version
+    Ok(format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) {action} RETURNING (xmax = 0) AS inserted",
+        quote_ident(E::TABLE),
+        columns.join(", "),
+        placeholders.join(", "),
+        conflict.join(", "),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::scratch::scratch_pool;
+
+    #[derive(Debug, PartialEq, sqlx::FromRow)]
+    struct Currency {
+        code: String,
+        name: String,
+        decimals: i32,
+    }
+
+    impl Entity for Currency {
+        const TABLE: &'static str = "currencies";
+        const COLUMNS: &'static [&'static str] = &["code", "name", "decimals"];
+
+        fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+            query.bind(&self.code).bind(&self.name).bind(self.decimals)
+        }
+    }
+
+    #[test]
+    fn test_upsert_sql_quotes_and_validates_columns() {
+        assert_eq!(
+            upsert_sql::<Currency>(&["code"], &["name", "decimals"]).unwrap(),
+            "INSERT INTO \"currencies\" (\"code\", \"name\", \"decimals\") VALUES ($1, $2, $3) \
+             ON CONFLICT (\"code\") DO UPDATE SET \"name\" = EXCLUDED.\"name\", \
+             \"decimals\" = EXCLUDED.\"decimals\" RETURNING (xmax = 0) AS inserted"
+        );
+        assert!(
+            upsert_sql::<Currency>(&["code"], &[])
+                .unwrap()
+                .contains("DO NOTHING")
+        );
+        assert!(upsert_sql::<Currency>(&[], &["name"]).is_err());
+        assert!(upsert_sql::<Currency>(&["code"], &["name; DROP TABLE x"]).is_err());
+        assert_eq!(quote_ident("billing.invoices"), "\"billing\".\"invoices\"");
+    }
+
+    #[test]
+    fn test_insert_sql_numbers_placeholders_per_row() {
+        assert_eq!(
+            insert_sql::<Currency>(2),
+            "INSERT INTO \"currencies\" (\"code\", \"name\", \"decimals\") \
+             VALUES ($1, $2, $3), ($4, $5, $6)"
+        );
+    }
+
+    #[test]
+    fn test_select_query_applies_filter() {
+        let query = select_query::<Currency>(
+            Filter::new()
+                .eq("name", "Naira".to_string())
+                .gte("decimals", 2)
+                .order_by_desc("code")
+                .limit(100),
+        )
+        .unwrap();
+        assert_eq!(
+            query.sql(),
+            "SELECT \"code\", \"name\", \"decimals\" FROM \"currencies\" \
+             WHERE \"name\" = $1 AND \"decimals\" >= $2 ORDER BY \"code\" DESC LIMIT $3"
+        );
+        assert!(select_query::<Currency>(Filter::new().order_by("rate")).is_err());
+
+        let filter = Filter::new()
+            .eq("name", "Naira".to_string())
+            .order_by("code")
+            .limit(20)
+            .offset(40);
+        assert_eq!(
+            count_query::<Currency>(&filter).unwrap().sql(),
+            "SELECT COUNT(*) FROM \"currencies\" WHERE \"name\" = $1"
+        );
+        assert!(
+            select_query::<Currency>(filter)
+                .unwrap()
+                .sql()
+                .ends_with("ORDER BY \"code\" LIMIT $2 OFFSET $3")
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_second_upsert_updates_instead_of_failing() {
+        let (pool, schema) = scratch_pool("upsert_test").await;
+        sqlx::query(
+            "CREATE TABLE currencies (code TEXT PRIMARY KEY, name TEXT NOT NULL, decimals INT NOT NULL)",
+        )
+        .execute(pool.pool())
+        .await
+        .unwrap();
+
+        let mut naira = Currency {
+            code: "NGN".into(),
+            name: "Naira".into(),
+            decimals: 2,
+        };
+        let update = ["name", "decimals"];
+        assert_eq!(
+            pool.upsert(&naira, &["code"], &update).await.unwrap(),
+            UpsertOutcome::Inserted
+        );
+
+        naira.name = "Nigerian naira".into();
+        assert_eq!(
+            pool.upsert(&naira, &["code"], &update).await.unwrap(),
+            UpsertOutcome::Updated
+        );
+        let mut tx = pool.begin().await.unwrap();
+        assert_eq!(
+            upsert_in(&mut tx, &naira, &["code"], &[]).await.unwrap(),
+            UpsertOutcome::Unchanged
+        );
+        tx.commit().await.unwrap();
+
+        let (name, rows): (String, i64) =
+            sqlx::query_as("SELECT MAX(name), COUNT(*) FROM currencies")
+                .fetch_one(pool.pool())
+                .await
+                .unwrap();
+        assert_eq!((name.as_str(), rows), ("Nigerian naira", 1));
+
+        let dollar = Currency {
+            code: "USD".into(),
+            name: "US dollar".into(),
+            decimals: 2,
+        };
+        pool.upsert(&dollar, &["code"], &[]).await.unwrap();
+        let streamed: Vec<Currency> = pool
+            .stream::<Currency>(Filter::new().gte("decimals", 2).order_by("code"))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(streamed, vec![naira, dollar]);
+
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_fetch_page_is_bounded_by_the_database_timeout() {
+        let (pool, schema) = scratch_pool("timeout_test").await;
+        let pool = pool.with_timeouts(&crate::resilience::TimeoutProfiles {
+            database: std::time::Duration::from_millis(300),
+            ..Default::default()
+        });
+        sqlx::query(
+            "CREATE VIEW slow_currencies AS \
+             SELECT v.* FROM (VALUES ('NGN', 'Naira', 2)) AS v(code, name, decimals) \
+             CROSS JOIN pg_sleep(2)",
+        )
+        .execute(pool.pool())
+        .await
+        .unwrap();
+        struct SlowCurrency(Currency);
+
+        impl<'r> FromRow<'r, PgRow> for SlowCurrency {
+            fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+                Currency::from_row(row).map(SlowCurrency)
+            }
+        }
+
+        impl Entity for SlowCurrency {
+            const TABLE: &'static str = "slow_currencies";
+            const COLUMNS: &'static [&'static str] = Currency::COLUMNS;
+
+            fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+                self.0.bind(query)
+            }
+        }
+
+        let err = pool
+            .fetch_page::<SlowCurrency>(Filter::new(), Pagination::new(1, 20))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("timed out after 300ms"), "{err}");
+
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_all_or_nothing_rolls_back_while_best_effort_keeps_good_chunks() {
+        let (pool, schema) = scratch_pool("batch_test").await;
+        // Its own table, so it can run alongside the upsert test
+        struct Rate(Currency);
+
+        impl Entity for Rate {
+            const TABLE: &'static str = "batch_currencies";
+            const COLUMNS: &'static [&'static str] = Currency::COLUMNS;
+
+            fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+                self.0.bind(query)
+            }
+        }
+
+        sqlx::query(
+            "CREATE TABLE batch_currencies (code TEXT PRIMARY KEY, name TEXT NOT NULL, \
+             decimals INT NOT NULL CHECK (decimals >= 0))",
+        )
+        .execute(pool.pool())
+        .await
+        .unwrap();
+        let count = || async {
+            let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM batch_currencies")
+                .fetch_one(pool.pool())
+                .await
+                .unwrap();
+            rows
+        };
+
+        // The fourth row breaks the CHECK constraint
+        let batch: Vec<Rate> = ["NGN", "USD", "EUR", "BAD", "GBP", "KES"]
+            .into_iter()
+            .map(|code| {
+                Rate(Currency {
+                    code: code.into(),
+                    name: code.into(),
+                    decimals: if code == "BAD" { -1 } else { 2 },
+                })
+            })
+            .collect();
+
+        assert!(
+            pool.insert_many(&batch, BatchMode::AllOrNothing)
+                .await
+                .is_err()
+        );
+        assert_eq!(count().await, 0);
+
+        let report = pool
+            .insert_many(&batch, BatchMode::BestEffort { chunk_size: 2 })
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 4);
+        assert!(!report.is_complete());
+        let failed: Vec<_> = report.failed().map(|chunk| chunk.rows.clone()).collect();
+        assert_eq!(failed, vec![Range { start: 2, end: 4 }]);
+        assert_eq!(count().await, 4);
+
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+    }
+}
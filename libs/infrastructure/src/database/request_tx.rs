@@ -0,0 +1,207 @@
+//! Request-scoped transactions
+//!
+//! Routes wrapped in [`transactional_middleware`] run inside a single
+//! database transaction: it is opened before the handler runs, handed to the
+//! handler through the [`Tx`] extractor, committed when the response is 2xx
+//! and rolled back otherwise.  If the handler panics the transaction is
+//! dropped while unwinding, which rolls it back as well.
+//!
+//! ```rust,ignore
+//! let app = Router::new()
+//!     .route("/orders", post(create_order))
+//!     .layer(from_fn(make_transactional_middleware(pool.clone())));
+//!
+//! async fn create_order(mut tx: Tx) -> Result<ApiResponse, ApiError> {
+//!     sqlx::query("INSERT INTO orders ...").execute(&mut *tx).await?;
+//!     sqlx::query("INSERT INTO order_events ...").execute(&mut *tx).await?;
+//!     Ok(ApiResponse::success_message("created"))
+//! }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{StatusCode, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use error::http::ApiError;
+use sqlx::{PgConnection, Postgres, Transaction};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::{error, warn};
+
+use super::DbPool;
+
+type Slot = Arc<Mutex<Option<Transaction<'static, Postgres>>>>;
+
+/// Transaction slot stored in the request extensions
+#[derive(Clone)]
+struct TxSlot(Slot);
+
+/// Extractor for the request's transaction
+pub struct Tx(OwnedMutexGuard<Option<Transaction<'static, Postgres>>>);
+
+impl Deref for Tx {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("transaction already finished")
+    }
+}
+
+impl DerefMut for Tx {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("transaction already finished")
+    }
+}
+
+impl<S> FromRequestParts<S> for Tx
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let slot = parts
+            .extensions
+            .get::<TxSlot>()
+            .cloned()
+            .ok_or_else(|| ApiError::internal("transactional middleware is not installed"))?;
+
+        let guard = slot
+            .0
+            .try_lock_owned()
+            .map_err(|_| ApiError::internal("request transaction is already in use"))?;
+        if guard.is_none() {
+            return Err(ApiError::internal("request transaction already finished"));
+        }
+        Ok(Self(guard))
+    }
+}
+
+/// Run the request inside a transaction, committing on 2xx
+pub async fn transactional_middleware(mut req: Request, next: Next, pool: DbPool) -> Response {
+    let tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!(error = %e, "failed to begin request transaction");
+            return ApiError::service_unavailable("database unavailable").into_response();
+        }
+    };
+
+    let slot: Slot = Arc::new(Mutex::new(Some(tx)));
+    req.extensions_mut().insert(TxSlot(slot.clone()));
+
+    let response = next.run(req).await;
+
+    let Some(tx) = slot.lock().await.take() else {
+        return response;
+    };
+
+    if response.status().is_success() {
+        if let Err(e) = tx.commit().await {
+            error!(error = %e, "failed to commit request transaction");
+            return ApiError::internal("failed to commit transaction").into_response();
+        }
+    } else if let Err(e) = tx.rollback().await {
+        warn!(error = %e, status = %response.status(), "failed to roll back request transaction");
+    }
+
+    response
+}
+
+/// Create transactional middleware for `pool`
+pub fn make_transactional_middleware(
+    pool: DbPool,
+) -> impl Fn(Request, Next) -> futures_util::future::BoxFuture<'static, Result<Response, StatusCode>>
++ Clone {
+    move |req: Request, next: Next| {
+        let pool = pool.clone();
+        Box::pin(async move { Ok(transactional_middleware(req, next, pool).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DatabaseConfig;
+    use axum::{Router, body::Body, middleware::from_fn, routing::post};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_tx_without_middleware_is_rejected() {
+        let app = Router::new().route("/", post(|_tx: Tx| async { StatusCode::OK }));
+        let response = app
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_error_response_rolls_back_writes() {
+        let config = DatabaseConfig {
+            url: std::env::var("DATABASE_URL").unwrap(),
+            ..DatabaseConfig::default()
+        };
+        let pool = DbPool::new(&config).await.unwrap();
+        let table = format!("request_tx_test_{}", std::process::id());
+        sqlx::query(&format!("CREATE TABLE {table} (name TEXT NOT NULL)"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+
+        let insert = format!("INSERT INTO {table} (name) VALUES ($1)");
+        let ok_insert = insert.clone();
+        let app = Router::new()
+            .route(
+                "/ok",
+                post(move |mut tx: Tx| async move {
+                    sqlx::query(&ok_insert)
+                        .bind("kept")
+                        .execute(&mut *tx)
+                        .await
+                        .unwrap();
+                    StatusCode::CREATED
+                }),
+            )
+            .route(
+                "/fail",
+                post(move |mut tx: Tx| async move {
+                    sqlx::query(&insert)
+                        .bind("discarded")
+                        .execute(&mut *tx)
+                        .await
+                        .unwrap();
+                    ApiError::conflict("second write failed")
+                }),
+            )
+            .layer(from_fn(make_transactional_middleware(pool.clone())));
+
+        for (path, status) in [
+            ("/ok", StatusCode::CREATED),
+            ("/fail", StatusCode::CONFLICT),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::post(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        let names: Vec<(String,)> = sqlx::query_as(&format!("SELECT name FROM {table}"))
+            .fetch_all(pool.pool())
+            .await
+            .unwrap();
+        sqlx::query(&format!("DROP TABLE {table}"))
+            .execute(pool.pool())
+            .await
+            .unwrap();
+
+        assert_eq!(names, vec![("kept".to_string(),)]);
+    }
+}
@@ -0,0 +1,40 @@
+//! Throwaway schemas for tests against a real PostgreSQL
+
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use super::DbPool;
+
+/// Pool on `DATABASE_URL` whose connections resolve unqualified names in a
+/// new schema `{prefix}_{pid}`
+///
+/// Test entities have fixed table names; this keeps their tables away from
+/// real ones and from a concurrent run.  Drop the returned schema with
+/// `CASCADE` at the end of the test.
+pub async fn scratch_pool(prefix: &str) -> (DbPool, String) {
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let schema = format!("{prefix}_{}", std::process::id());
+
+    let admin = PgPool::connect(&url).await.unwrap();
+    for ddl in [
+        format!("DROP SCHEMA IF EXISTS {schema} CASCADE"),
+        format!("CREATE SCHEMA {schema}"),
+    ] {
+        sqlx::query(&ddl).execute(&admin).await.unwrap();
+    }
+    admin.close().await;
+
+    let search_path = format!("SET search_path TO {schema}");
+    let pool = PgPoolOptions::new()
+        .after_connect(move |conn, _| {
+            let search_path = search_path.clone();
+            Box::pin(async move {
+                sqlx::Executor::execute(conn, search_path.as_str())
+                    .await
+                    .map(|_| ())
+            })
+        })
+        .connect(&url)
+        .await
+        .unwrap();
+    (DbPool::from_pool(pool), schema)
+}
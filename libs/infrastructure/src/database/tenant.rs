@@ -0,0 +1,271 @@
+//! Tenant-scoped repository access
+//!
+//! Tables shared by several tenants name their tenant column through
+//! [`Entity::TENANT_COLUMN`].  Going through a [`RepositoryContext`] scoped
+//! with [`RepositoryContext::with_tenant`] (usually the caller's tenant, see
+//! `RepositoryContext::for_caller`), every read of such an entity gets
+//! `WHERE tenant_id = $1` added and every write is checked to belong to that
+//! tenant, so a handler can't return or overwrite another tenant's rows by
+//! forgetting a condition.
+//!
+//! A context without a tenant refuses tenant-scoped entities outright.
+//! Jobs that really do work across tenants say so with
+//! [`RepositoryContext::all_tenants`].
+//!
+//! ```rust,ignore
+//! let repo = RepositoryContext::for_caller(db.clone(), &auth);
+//! let invoices = repo.stream::<Invoice>(Filter::new().eq("status", "open"));
+//! ```
+
+use async_trait::async_trait;
+use common::value_objects::pagination_vo::Pagination;
+use error::AppError;
+use error::core::AuthErrorCode;
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use sqlx::FromRow;
+use sqlx::postgres::PgRow;
+
+use super::DbPool;
+use super::repository::{
+    BatchMode, BatchReport, Entity, Filter, RepositoryExt, UpsertOutcome, run_upsert, upsert_sql,
+};
+
+/// Which tenants' rows a context may touch
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TenantScope {
+    /// No tenant set; tenant-scoped entities are refused
+    Unscoped,
+    Tenant(String),
+    /// Explicit cross-tenant access
+    AllTenants,
+}
+
+/// Repository access on behalf of one tenant
+#[derive(Clone)]
+pub struct RepositoryContext {
+    pool: DbPool,
+    scope: TenantScope,
+}
+
+impl RepositoryContext {
+    /// Context without a tenant, for tables that aren't tenant-scoped
+    pub fn new(pool: DbPool) -> Self {
+        Self {
+            pool,
+            scope: TenantScope::Unscoped,
+        }
+    }
+
+    /// Context of the authenticated caller, scoped to their tenant if they
+    /// have one
+    #[cfg(feature = "http")]
+    pub fn for_caller(pool: DbPool, caller: &common::middleware::AuthContext) -> Self {
+        match &caller.tenant_id {
+            Some(tenant_id) => Self::new(pool).with_tenant(tenant_id.clone()),
+            None => Self::new(pool),
+        }
+    }
+
+    /// Scope all subsequent queries to `tenant_id`
+    pub fn with_tenant(mut self, tenant_id: impl Into<String>) -> Self {
+        self.scope = TenantScope::Tenant(tenant_id.into());
+        self
+    }
+
+    /// Allow reading and writing the rows of every tenant
+    ///
+    /// For background jobs and internal tooling only; never derive this
+    /// from a request.
+    pub fn all_tenants(mut self) -> Self {
+        self.scope = TenantScope::AllTenants;
+        self
+    }
+
+    /// Tenant queries are scoped to, if any
+    pub fn tenant_id(&self) -> Option<&str> {
+        match &self.scope {
+            TenantScope::Tenant(tenant_id) => Some(tenant_id),
+            _ => None,
+        }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// `filter` restricted to this context's tenant, when `E` is
+    /// tenant-scoped
+    fn scoped<E: Entity>(&self, filter: Filter) -> Result<Filter, AppError> {
+        let Some(column) = E::TENANT_COLUMN else {
+            return Ok(filter);
+        };
+        match &self.scope {
+            TenantScope::Tenant(tenant_id) => Ok(filter.scoped_to(column, tenant_id.clone())),
+            TenantScope::AllTenants => Ok(filter),
+            TenantScope::Unscoped => Err(missing_tenant::<E>()),
+        }
+    }
+
+    /// Fail unless this context may write `entity`
+    fn check_write<E: Entity>(&self, entity: &E) -> Result<(), AppError> {
+        if E::TENANT_COLUMN.is_none() {
+            return Ok(());
+        }
+        match (&self.scope, entity.tenant_id()) {
+            (TenantScope::Unscoped, _) => Err(missing_tenant::<E>()),
+            (_, None) => Err(AppError::internal(format!(
+                "{} row has no tenant",
+                E::TABLE
+            ))),
+            (TenantScope::Tenant(scope), Some(tenant_id)) if scope != tenant_id => {
+                Err(AppError::authz(
+                    format!("{} row belongs to another tenant", E::TABLE),
+                    AuthErrorCode::InsufficientPermissions,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn missing_tenant<E: Entity>() -> AppError {
+    AppError::internal(format!(
+        "{} is tenant-scoped but the repository context has no tenant",
+        E::TABLE
+    ))
+}
+
+#[async_trait]
+impl RepositoryExt for RepositoryContext {
+    async fn upsert<E: Entity>(
+        &self,
+        entity: &E,
+        conflict_columns: &[&str],
+        update_columns: &[&str],
+    ) -> Result<UpsertOutcome, AppError> {
+        self.check_write(entity)?;
+        self.pool.ensure_writable()?;
+        let sql = upsert_sql::<E>(conflict_columns, update_columns)?;
+        run_upsert(self.pool.pool(), &sql, entity).await
+    }
+
+    fn stream<E>(&self, filter: Filter) -> BoxStream<'static, Result<E, AppError>>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Unpin + 'static,
+    {
+        match self.scoped::<E>(filter) {
+            Ok(filter) => self.pool.stream(filter),
+            Err(e) => stream::once(async { Err(e) }).boxed(),
+        }
+    }
+
+    async fn fetch_page<E>(
+        &self,
+        filter: Filter,
+        page: Pagination,
+    ) -> Result<(Vec<E>, u64), AppError>
+    where
+        E: Entity + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+    {
+        let filter = self.scoped::<E>(filter)?;
+        self.pool.fetch_page(filter, page).await
+    }
+
+    async fn insert_many<E: Entity>(
+        &self,
+        entities: &[E],
+        mode: BatchMode,
+    ) -> Result<BatchReport, AppError> {
+        for entity in entities {
+            self.check_write(entity)?;
+        }
+        self.pool.insert_many(entities, mode).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{EntityQuery, select_query};
+    use sqlx::postgres::PgPoolOptions;
+
+    #[derive(Debug, sqlx::FromRow)]
+    struct Invoice {
+        id: String,
+        tenant_id: String,
+        status: String,
+    }
+
+    impl Entity for Invoice {
+        const TABLE: &'static str = "invoices";
+        const COLUMNS: &'static [&'static str] = &["id", "tenant_id", "status"];
+        const TENANT_COLUMN: Option<&'static str> = Some("tenant_id");
+
+        fn bind<'q>(&'q self, query: EntityQuery<'q>) -> EntityQuery<'q> {
+            query
+                .bind(&self.id)
+                .bind(&self.tenant_id)
+                .bind(&self.status)
+        }
+
+        fn tenant_id(&self) -> Option<&str> {
+            Some(&self.tenant_id)
+        }
+    }
+
+    fn pool() -> DbPool {
+        DbPool::from_pool(
+            PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/trustflow_test")
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_queries_are_filtered_by_tenant() {
+        let repo = RepositoryContext::new(pool()).with_tenant("acme");
+
+        let filter = repo
+            .scoped::<Invoice>(Filter::new().eq("status", "open".to_string()))
+            .unwrap();
+        assert_eq!(
+            select_query::<Invoice>(filter).unwrap().sql(),
+            "SELECT \"id\", \"tenant_id\", \"status\" FROM \"invoices\" \
+             WHERE \"tenant_id\" = $1 AND \"status\" = $2"
+        );
+        assert!(
+            upsert_sql::<Invoice>(&["id"], &["status"])
+                .unwrap()
+                .contains("WHERE \"invoices\".\"tenant_id\" = EXCLUDED.\"tenant_id\"")
+        );
+
+        let own = Invoice {
+            id: "inv_1".into(),
+            tenant_id: "acme".into(),
+            status: "open".into(),
+        };
+        let foreign = Invoice {
+            tenant_id: "globex".into(),
+            ..own
+        };
+        assert!(matches!(
+            repo.upsert(&foreign, &["id"], &["status"]).await,
+            Err(AppError::AuthorizationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unscoped_context_refuses_tenant_scoped_entities() {
+        let repo = RepositoryContext::new(pool());
+
+        let mut rows = repo.stream::<Invoice>(Filter::new());
+        let err = rows.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("has no tenant"), "{err}");
+        assert!(rows.next().await.is_none());
+
+        let all = repo.all_tenants();
+        assert!(all.scoped::<Invoice>(Filter::new()).is_ok());
+        assert_eq!(all.tenant_id(), None);
+    }
+}
@@ -0,0 +1,428 @@
+//! HTTP client that routes requests to discovered service instances
+//!
+//! Besides the weights instances register with, the client keeps its own
+//! view of each instance's health.  An instance whose request failed is
+//! left out of selection until its cooldown elapses, and instances that
+//! answer slower than their peers get proportionally less traffic.  Both
+//! are local to the client: discovery only drops instances once their
+//! health check fails, which takes far longer than one failed request.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::{Method, RequestBuilder, Response, header::HeaderMap};
+
+use super::{DiscoveryError, ServiceDiscovery, ServiceInstance};
+
+/// Request header pinning a request to the canary or stable pool
+///
+/// `always`/`true`/`1` routes to a canary, `never`/`false`/`0` to a stable
+/// instance; anything else falls back to weighted selection.
+pub const CANARY_HEADER: &str = "x-canary";
+
+/// Per-request canary routing preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CanaryPreference {
+    /// Weighted selection across every instance
+    #[default]
+    Auto,
+    /// Only canary instances
+    Always,
+    /// Only stable instances
+    Never,
+}
+
+impl CanaryPreference {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "always" | "true" | "1" => Self::Always,
+            "never" | "false" | "0" => Self::Never,
+            _ => Self::Auto,
+        }
+    }
+
+    /// Preference carried by the [`CANARY_HEADER`] header, if any
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(CANARY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+
+    fn admits(self, instance: &ServiceInstance) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Always => instance.is_canary(),
+            Self::Never => !instance.is_canary(),
+        }
+    }
+}
+
+/// How long a failed instance is skipped unless configured otherwise
+pub const DEFAULT_FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Weight of the latest sample in an instance's average latency
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// What the client observed of one instance
+#[derive(Debug, Clone, Copy, Default)]
+struct InstanceHealth {
+    /// Skipped until then after a failure
+    cooldown_until: Option<Instant>,
+    /// Exponentially weighted average of successful requests
+    latency: Option<Duration>,
+}
+
+/// Discovery-aware HTTP client with weighted canary routing and local
+/// health tracking
+///
+/// Cheap to clone; clones share health tracking.
+#[derive(Clone)]
+pub struct DiscoveryHttpClient {
+    discovery: Arc<dyn ServiceDiscovery>,
+    http: reqwest::Client,
+    rng: Arc<Mutex<fastrand::Rng>>,
+    cooldown: Duration,
+    /// By instance id
+    health: Arc<Mutex<HashMap<String, InstanceHealth>>>,
+}
+
+impl DiscoveryHttpClient {
+    pub fn new(discovery: Arc<dyn ServiceDiscovery>) -> Self {
+        Self {
+            discovery,
+            http: reqwest::Client::new(),
+            rng: Arc::new(Mutex::new(fastrand::Rng::new())),
+            cooldown: DEFAULT_FAILURE_COOLDOWN,
+            health: Arc::default(),
+        }
+    }
+
+    /// Skip an instance for `cooldown` after a request to it failed
+    pub fn with_failure_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Use a preconfigured HTTP client (timeouts, TLS)
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Seed instance selection, for reproducible tests
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = fastrand::Rng::with_seed(seed);
+        self
+    }
+
+    /// Record that a request to `instance` succeeded after `latency`
+    pub fn record_success(&self, instance: &ServiceInstance, latency: Duration) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = health.entry(instance.id.clone()).or_default();
+        entry.cooldown_until = None;
+        entry.latency = Some(match entry.latency {
+            Some(average) => {
+                average.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => latency,
+        });
+    }
+
+    /// Record that a request to `instance` failed, skipping it for the
+    /// failure cooldown
+    pub fn record_failure(&self, instance: &ServiceInstance) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health
+            .entry(instance.id.clone())
+            .or_default()
+            .cooldown_until = Some(Instant::now() + self.cooldown);
+    }
+
+    /// Whether `instance` is skipped after a recent failure
+    pub fn is_cooling_down(&self, instance: &ServiceInstance) -> bool {
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health
+            .get(&instance.id)
+            .and_then(|h| h.cooldown_until)
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// Pick an instance of `service` honoring `preference`
+    ///
+    /// A pinned preference with no matching instance falls back to the
+    /// whole pool rather than failing the request.  Instances cooling down
+    /// after a failure are skipped, unless every instance is.
+    pub async fn select_instance(
+        &self,
+        service: &str,
+        preference: CanaryPreference,
+    ) -> Result<ServiceInstance, DiscoveryError> {
+        let instances = self.discovery.instances(service).await?;
+        let pinned: Vec<&ServiceInstance> = instances
+            .iter()
+            .filter(|instance| preference.admits(instance))
+            .collect();
+        let mut pool = if pinned.is_empty() {
+            instances.iter().collect()
+        } else {
+            pinned
+        };
+        if pool.iter().any(|instance| !self.is_cooling_down(instance)) {
+            pool.retain(|instance| !self.is_cooling_down(instance));
+        }
+        self.pick_weighted(&pool)
+            .cloned()
+            .ok_or_else(|| DiscoveryError::NoInstances(service.to_string()))
+    }
+
+    /// Build a request for `path` on an instance of `service`, honoring the
+    /// caller's [`CANARY_HEADER`]
+    pub async fn request(
+        &self,
+        method: Method,
+        service: &str,
+        path: &str,
+        incoming: &HeaderMap,
+    ) -> Result<RequestBuilder, DiscoveryError> {
+        let preference = CanaryPreference::from_headers(incoming);
+        let instance = self.select_instance(service, preference).await?;
+        Ok(self.request_to(&instance, method, path))
+    }
+
+    /// Send a body-less request for `path` to an instance of `service` and
+    /// record how it went
+    ///
+    /// Connection errors and `5xx` responses count as failures of the
+    /// instance; other responses are returned as they are.
+    pub async fn send(
+        &self,
+        method: Method,
+        service: &str,
+        path: &str,
+        incoming: &HeaderMap,
+    ) -> Result<Response, DiscoveryError> {
+        let preference = CanaryPreference::from_headers(incoming);
+        let instance = self.select_instance(service, preference).await?;
+        let started = Instant::now();
+        match self.request_to(&instance, method, path).send().await {
+            Ok(response) if !response.status().is_server_error() => {
+                self.record_success(&instance, started.elapsed());
+                Ok(response)
+            }
+            Ok(response) => {
+                self.record_failure(&instance);
+                Ok(response)
+            }
+            Err(e) => {
+                self.record_failure(&instance);
+                Err(DiscoveryError::Request {
+                    instance: instance.id,
+                    message: e.to_string(),
+                })
+            }
+        }
+    }
+
+    fn request_to(&self, instance: &ServiceInstance, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/{}", instance.base_url(), path.trim_start_matches('/'));
+        self.http.request(method, url)
+    }
+
+    /// Routing weight of `instance`, scaled down by how much slower than
+    /// the fastest instance of `pool` it has been answering
+    ///
+    /// Instances without latency samples yet keep their full weight, so
+    /// they get traffic to be measured on.
+    fn effective_weight(
+        instance: &ServiceInstance,
+        latency: Option<Duration>,
+        fastest: Option<Duration>,
+    ) -> u64 {
+        let weight = u64::from(instance.weight()) * 1_000;
+        match (latency, fastest) {
+            (Some(latency), Some(fastest)) if latency > fastest => {
+                let scaled = weight as f64 * fastest.as_secs_f64() / latency.as_secs_f64();
+                // A slow instance still gets a trickle, or it would never
+                // be measured again
+                (scaled as u64).max(1)
+            }
+            _ => weight,
+        }
+    }
+
+    fn pick_weighted<'a>(&self, pool: &[&'a ServiceInstance]) -> Option<&'a ServiceInstance> {
+        let latencies: Vec<Option<Duration>> = {
+            let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+            pool.iter()
+                .map(|instance| health.get(&instance.id).and_then(|h| h.latency))
+                .collect()
+        };
+        let fastest = latencies.iter().flatten().min().copied();
+        let weights: Vec<u64> = pool
+            .iter()
+            .zip(&latencies)
+            .map(|(instance, latency)| {
+                if instance.weight() == 0 {
+                    0
+                } else {
+                    Self::effective_weight(instance, *latency, fastest)
+                }
+            })
+            .collect();
+        let total: u64 = weights.iter().sum();
+        if total == 0 {
+            // Every instance opted out via weight 0; spread evenly instead
+            let index = self.rng.lock().unwrap().usize(..pool.len().max(1));
+            return pool.get(index).copied();
+        }
+        let mut roll = self.rng.lock().unwrap().u64(..total);
+        pool.iter().zip(weights).find_map(|(instance, weight)| {
+            if roll < weight {
+                Some(*instance)
+            } else {
+                roll -= weight;
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::{CANARY_TAG, StaticDiscovery, WEIGHT_META_KEY};
+
+    fn client() -> DiscoveryHttpClient {
+        let discovery = StaticDiscovery::new()
+            .with_instance(
+                "order",
+                ServiceInstance::new("order-stable", "10.0.0.1", 8080)
+                    .with_meta(WEIGHT_META_KEY, "90"),
+            )
+            .with_instance(
+                "order",
+                ServiceInstance::new("order-canary", "10.0.0.2", 8080)
+                    .with_tag(CANARY_TAG)
+                    .with_meta(WEIGHT_META_KEY, "10"),
+            );
+        DiscoveryHttpClient::new(Arc::new(discovery)).with_seed(7)
+    }
+
+    #[tokio::test]
+    async fn test_traffic_is_split_by_instance_weight() {
+        let client = client();
+        let mut canary = 0;
+        for _ in 0..10_000 {
+            let instance = client
+                .select_instance("order", CanaryPreference::Auto)
+                .await
+                .unwrap();
+            if instance.is_canary() {
+                canary += 1;
+            }
+        }
+        // 10% expected; allow for sampling noise
+        assert!((800..=1200).contains(&canary), "canary got {canary}");
+    }
+
+    #[tokio::test]
+    async fn test_canary_header_pins_the_pool() {
+        let client = client();
+        let mut headers = HeaderMap::new();
+        headers.insert(CANARY_HEADER, "always".parse().unwrap());
+        for _ in 0..50 {
+            let preference = CanaryPreference::from_headers(&headers);
+            let instance = client.select_instance("order", preference).await.unwrap();
+            assert_eq!(instance.id, "order-canary");
+        }
+
+        let request = client
+            .request(Method::GET, "order", "/orders/1", &headers)
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.url().as_str(), "http://10.0.0.2:8080/orders/1");
+
+        for _ in 0..50 {
+            let instance = client
+                .select_instance("order", CanaryPreference::Never)
+                .await
+                .unwrap();
+            assert_eq!(instance.id, "order-stable");
+        }
+
+        assert!(matches!(
+            client
+                .select_instance("payments", CanaryPreference::Auto)
+                .await,
+            Err(DiscoveryError::NoInstances(_))
+        ));
+    }
+
+    fn pair() -> DiscoveryHttpClient {
+        let discovery = StaticDiscovery::new()
+            .with_instance("ledger", ServiceInstance::new("ledger-a", "10.0.1.1", 8080))
+            .with_instance("ledger", ServiceInstance::new("ledger-b", "10.0.1.2", 8080));
+        DiscoveryHttpClient::new(Arc::new(discovery)).with_seed(11)
+    }
+
+    async fn pick(client: &DiscoveryHttpClient) -> String {
+        client
+            .select_instance("ledger", CanaryPreference::Auto)
+            .await
+            .unwrap()
+            .id
+    }
+
+    #[tokio::test]
+    async fn test_failed_instance_is_skipped_until_its_cooldown_elapses() {
+        let client = pair().with_failure_cooldown(Duration::from_millis(50));
+        let failed = ServiceInstance::new("ledger-a", "10.0.1.1", 8080);
+
+        client.record_failure(&failed);
+        assert!(client.is_cooling_down(&failed));
+        for _ in 0..100 {
+            assert_eq!(pick(&client).await, "ledger-b");
+        }
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!client.is_cooling_down(&failed));
+        let mut picked_a = false;
+        for _ in 0..100 {
+            picked_a |= pick(&client).await == "ledger-a";
+        }
+        assert!(picked_a);
+
+        // With every instance cooling down the whole pool is used again
+        client.record_failure(&failed);
+        client.record_failure(&ServiceInstance::new("ledger-b", "10.0.1.2", 8080));
+        assert!(
+            client
+                .select_instance("ledger", CanaryPreference::Auto)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slower_instances_get_less_traffic() {
+        let client = pair();
+        let a = ServiceInstance::new("ledger-a", "10.0.1.1", 8080);
+        let b = ServiceInstance::new("ledger-b", "10.0.1.2", 8080);
+        client.record_success(&a, Duration::from_millis(10));
+        client.record_success(&b, Duration::from_millis(40));
+
+        let mut fast = 0;
+        for _ in 0..10_000 {
+            if pick(&client).await == "ledger-a" {
+                fast += 1;
+            }
+        }
+        // 4:1 expected; allow for sampling noise
+        assert!((7_600..=8_400).contains(&fast), "fast instance got {fast}");
+    }
+}
@@ -0,0 +1,125 @@
+//! Consul-backed service discovery
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{DiscoveryError, ServiceDiscovery, ServiceInstance};
+
+/// Reads passing instances from Consul's `/v1/health/service` endpoint
+#[derive(Clone)]
+pub struct ConsulDiscovery {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ConsulDiscovery {
+    /// Discovery against the Consul agent at `base_url`, e.g. `http://localhost:8500`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HealthEntry {
+    node: ConsulNode,
+    service: ConsulService,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulNode {
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulService {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(default)]
+    address: String,
+    port: u16,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    meta: Option<HashMap<String, String>>,
+}
+
+impl From<HealthEntry> for ServiceInstance {
+    fn from(entry: HealthEntry) -> Self {
+        let service = entry.service;
+        // Services registered without an address inherit the node's
+        let address = if service.address.is_empty() {
+            entry.node.address
+        } else {
+            service.address
+        };
+        Self {
+            id: service.id,
+            address,
+            port: service.port,
+            tags: service.tags.unwrap_or_default(),
+            meta: service.meta.unwrap_or_default(),
+        }
+    }
+}
+
+fn parse_health_entries(body: &[u8]) -> Result<Vec<ServiceInstance>, DiscoveryError> {
+    let entries: Vec<HealthEntry> = serde_json::from_slice(body)
+        .map_err(|e| DiscoveryError::Backend(format!("invalid consul response: {e}")))?;
+    Ok(entries.into_iter().map(ServiceInstance::from).collect())
+}
+
+#[async_trait]
+impl ServiceDiscovery for ConsulDiscovery {
+    async fn instances(&self, service: &str) -> Result<Vec<ServiceInstance>, DiscoveryError> {
+        let url = format!("{}/v1/health/service/{service}", self.base_url);
+        let response = self
+            .client
+            .get(url)
+            .query(&[("passing", "true")])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| DiscoveryError::Backend(e.to_string()))?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| DiscoveryError::Backend(e.to_string()))?;
+        parse_health_entries(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_entries_map_to_instances() {
+        let body = br#"[
+            {
+                "Node": { "Address": "10.0.0.5" },
+                "Service": {
+                    "ID": "order-canary-1",
+                    "Service": "order",
+                    "Tags": ["canary"],
+                    "Address": "",
+                    "Port": 8080,
+                    "Meta": { "weight": "10" }
+                }
+            }
+        ]"#;
+
+        let instances = parse_health_entries(body).unwrap();
+        assert_eq!(instances[0].id, "order-canary-1");
+        assert_eq!(instances[0].base_url(), "http://10.0.0.5:8080");
+        assert!(instances[0].is_canary());
+        assert_eq!(instances[0].weight(), 10);
+    }
+}
@@ -0,0 +1,137 @@
+//! Service discovery and discovery-aware HTTP routing
+//!
+//! [`ServiceDiscovery`] resolves a service name to its healthy instances;
+//! [`ConsulDiscovery`] reads them from Consul's health API.
+//! [`DiscoveryHttpClient`] picks one instance per request, weighted by the
+//! `weight` metadata each instance registers with, so a canary tagged
+//! `canary` with weight 10 next to a stable instance with weight 90 receives
+//! roughly 10% of traffic.  Callers can pin a request to either pool with
+//! the [`CANARY_HEADER`] header.  The client also skips instances that just
+//! failed and favours those answering faster.
+
+pub mod client;
+pub mod consul;
+
+pub use client::{CANARY_HEADER, CanaryPreference, DEFAULT_FAILURE_COOLDOWN, DiscoveryHttpClient};
+pub use consul::ConsulDiscovery;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use error::AppError;
+use thiserror::Error;
+
+/// Consul tag marking canary instances
+pub const CANARY_TAG: &str = "canary";
+
+/// Metadata key holding an instance's routing weight
+pub const WEIGHT_META_KEY: &str = "weight";
+
+/// Weight of instances that don't register one
+pub const DEFAULT_WEIGHT: u32 = 100;
+
+/// Service discovery errors
+#[derive(Debug, Error)]
+pub enum DiscoveryError {
+    #[error("no healthy instances of '{0}'")]
+    NoInstances(String),
+
+    #[error("discovery backend error: {0}")]
+    Backend(String),
+
+    #[error("request to instance '{instance}' failed: {message}")]
+    Request { instance: String, message: String },
+}
+
+impl From<DiscoveryError> for AppError {
+    fn from(err: DiscoveryError) -> Self {
+        match err {
+            DiscoveryError::NoInstances(service) => AppError::service_unavailable(service, None),
+            DiscoveryError::Backend(message) => AppError::external("service discovery", message),
+            DiscoveryError::Request { instance, message } => AppError::external(instance, message),
+        }
+    }
+}
+
+/// A healthy, addressable instance of a service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceInstance {
+    pub id: String,
+    pub address: String,
+    pub port: u16,
+    pub tags: Vec<String>,
+    pub meta: HashMap<String, String>,
+}
+
+impl ServiceInstance {
+    pub fn new(id: impl Into<String>, address: impl Into<String>, port: u16) -> Self {
+        Self {
+            id: id.into(),
+            address: address.into(),
+            port,
+            tags: Vec::new(),
+            meta: HashMap::new(),
+        }
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn with_meta(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_canary(&self) -> bool {
+        self.tags.iter().any(|tag| tag == CANARY_TAG)
+    }
+
+    /// Routing weight from the `weight` metadata, [`DEFAULT_WEIGHT`] if unset
+    /// or unparseable
+    pub fn weight(&self) -> u32 {
+        self.meta
+            .get(WEIGHT_META_KEY)
+            .and_then(|weight| weight.parse().ok())
+            .unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    /// Base URL of the instance
+    pub fn base_url(&self) -> String {
+        format!("http://{}:{}", self.address, self.port)
+    }
+}
+
+/// Resolves service names to healthy instances
+#[async_trait]
+pub trait ServiceDiscovery: Send + Sync {
+    async fn instances(&self, service: &str) -> Result<Vec<ServiceInstance>, DiscoveryError>;
+}
+
+/// Fixed instance list, for tests and local development
+#[derive(Debug, Clone, Default)]
+pub struct StaticDiscovery {
+    services: HashMap<String, Vec<ServiceInstance>>,
+}
+
+impl StaticDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_instance(mut self, service: impl Into<String>, instance: ServiceInstance) -> Self {
+        self.services
+            .entry(service.into())
+            .or_default()
+            .push(instance);
+        self
+    }
+}
+
+#[async_trait]
+impl ServiceDiscovery for StaticDiscovery {
+    async fn instances(&self, service: &str) -> Result<Vec<ServiceInstance>, DiscoveryError> {
+        Ok(self.services.get(service).cloned().unwrap_or_default())
+    }
+}
@@ -0,0 +1,14 @@
+//! Outbound email delivery
+//!
+//! [`EmailProvider`] hides the transport behind `send(EmailMessage)` so the
+//! MFA, verification and notification flows don't care whether mail leaves
+//! through an SMTP relay or SendGrid.  [`MockEmailProvider`] records messages
+//! for tests.
+
+pub mod provider;
+pub mod sendgrid;
+pub mod smtp;
+
+pub use provider::{EmailAttachment, EmailError, EmailMessage, EmailProvider, MockEmailProvider};
+pub use sendgrid::SendGridEmailProvider;
+pub use smtp::{SmtpConfig, SmtpEmailProvider};
@@ -0,0 +1,227 @@
+//! Email message model, provider trait and in-memory mock
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use error::AppError;
+use lettre::message::Mailbox;
+use thiserror::Error;
+
+/// Email delivery errors
+#[derive(Debug, Error)]
+pub enum EmailError {
+    #[error("invalid email message: {0}")]
+    InvalidMessage(String),
+
+    #[error("{provider} delivery failed: {message}")]
+    Delivery {
+        provider: &'static str,
+        message: String,
+    },
+}
+
+impl EmailError {
+    pub(crate) fn delivery(provider: &'static str, message: impl Into<String>) -> Self {
+        Self::Delivery {
+            provider,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<EmailError> for AppError {
+    fn from(err: EmailError) -> Self {
+        match err {
+            EmailError::InvalidMessage(message) => AppError::validation(message),
+            EmailError::Delivery { provider, message } => AppError::external(provider, message),
+        }
+    }
+}
+
+/// File attached to an email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Bytes,
+}
+
+/// Outbound email
+///
+/// Addresses accept either `user@example.com` or `Name <user@example.com>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub text_body: Option<String>,
+    pub html_body: Option<String>,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+impl EmailMessage {
+    pub fn new(from: impl Into<String>, to: impl Into<String>, subject: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            to: vec![to.into()],
+            reply_to: None,
+            subject: subject.into(),
+            text_body: None,
+            html_body: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Add another recipient
+    pub fn with_recipient(mut self, to: impl Into<String>) -> Self {
+        self.to.push(to.into());
+        self
+    }
+
+    pub fn with_reply_to(mut self, reply_to: impl Into<String>) -> Self {
+        self.reply_to = Some(reply_to.into());
+        self
+    }
+
+    pub fn with_text(mut self, body: impl Into<String>) -> Self {
+        self.text_body = Some(body.into());
+        self
+    }
+
+    pub fn with_html(mut self, body: impl Into<String>) -> Self {
+        self.html_body = Some(body.into());
+        self
+    }
+
+    pub fn with_attachment(
+        mut self,
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        content: impl Into<Bytes>,
+    ) -> Self {
+        self.attachments.push(EmailAttachment {
+            filename: filename.into(),
+            content_type: content_type.into(),
+            content: content.into(),
+        });
+        self
+    }
+
+    /// Check addresses parse and there is a body to send
+    pub fn validate(&self) -> Result<(), EmailError> {
+        if self.to.is_empty() {
+            return Err(EmailError::InvalidMessage("no recipients".into()));
+        }
+        if self.text_body.is_none() && self.html_body.is_none() {
+            return Err(EmailError::InvalidMessage(
+                "a text or HTML body is required".into(),
+            ));
+        }
+        parse_mailbox(&self.from)?;
+        for address in self.to.iter().chain(&self.reply_to) {
+            parse_mailbox(address)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn parse_mailbox(address: &str) -> Result<Mailbox, EmailError> {
+    Mailbox::from_str(address)
+        .map_err(|e| EmailError::InvalidMessage(format!("invalid address '{address}': {e}")))
+}
+
+/// Outbound email delivery
+#[async_trait]
+pub trait EmailProvider: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), EmailError>;
+}
+
+/// Provider that records messages instead of sending them
+#[derive(Debug, Clone, Default)]
+pub struct MockEmailProvider {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+    failure: Option<String>,
+}
+
+impl MockEmailProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provider whose every send fails with `message`
+    pub fn failing(message: impl Into<String>) -> Self {
+        Self {
+            failure: Some(message.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Messages sent so far, oldest first
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl EmailProvider for MockEmailProvider {
+    async fn send(&self, message: EmailMessage) -> Result<(), EmailError> {
+        message.validate()?;
+        if let Some(failure) = &self.failure {
+            return Err(EmailError::delivery("mock", failure.clone()));
+        }
+        self.sent.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_provider_records_message_contents() {
+        let provider = MockEmailProvider::new();
+        let message = EmailMessage::new(
+            "TrustFlow <no-reply@trustflow.io>",
+            "ada@example.com",
+            "Verify your email",
+        )
+        .with_reply_to("support@trustflow.io")
+        .with_text("Your code is 123456")
+        .with_html("<p>Your code is <b>123456</b></p>")
+        .with_attachment("terms.pdf", "application/pdf", &b"%PDF-1.7"[..]);
+
+        provider.send(message.clone()).await.unwrap();
+
+        let sent = provider.sent();
+        assert_eq!(sent, vec![message]);
+        assert_eq!(sent[0].to, ["ada@example.com"]);
+        assert_eq!(sent[0].reply_to.as_deref(), Some("support@trustflow.io"));
+        assert_eq!(sent[0].attachments[0].filename, "terms.pdf");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_messages_and_failures_map_to_app_errors() {
+        let provider = MockEmailProvider::new();
+        let no_body = EmailMessage::new("no-reply@trustflow.io", "ada@example.com", "Hi");
+        let err = provider.send(no_body).await.unwrap_err();
+        assert!(matches!(AppError::from(err), AppError::ValidationError(_)));
+
+        let bad_address =
+            EmailMessage::new("no-reply@trustflow.io", "not an address", "Hi").with_text("hello");
+        assert!(provider.send(bad_address).await.is_err());
+        assert!(provider.sent().is_empty());
+
+        let failing = MockEmailProvider::failing("mailbox unavailable");
+        let message =
+            EmailMessage::new("no-reply@trustflow.io", "ada@example.com", "Hi").with_text("hello");
+        let err = failing.send(message).await.unwrap_err();
+        assert!(matches!(
+            AppError::from(err),
+            AppError::ExternalServiceError(_)
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+//! SendGrid email provider (v3 Mail Send API)
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use lettre::message::Mailbox;
+use serde_json::{Value, json};
+
+use super::provider::{EmailError, EmailMessage, EmailProvider, parse_mailbox};
+
+const PROVIDER: &str = "sendgrid";
+
+/// Default Mail Send endpoint
+pub const SENDGRID_ENDPOINT: &str = "https://api.sendgrid.com/v3/mail/send";
+
+/// Sends email through the SendGrid HTTP API
+#[derive(Clone)]
+pub struct SendGridEmailProvider {
+    client: reqwest::Client,
+    api_key: String,
+    endpoint: String,
+}
+
+impl SendGridEmailProvider {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            endpoint: SENDGRID_ENDPOINT.to_string(),
+        }
+    }
+
+    /// Override the API endpoint, e.g. to point at a stub server
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+}
+
+fn address(mailbox: &Mailbox) -> Value {
+    match &mailbox.name {
+        Some(name) => json!({ "email": mailbox.email.to_string(), "name": name }),
+        None => json!({ "email": mailbox.email.to_string() }),
+    }
+}
+
+/// Mail Send request body for `message`
+pub fn sendgrid_payload(message: &EmailMessage) -> Result<Value, EmailError> {
+    let to = message
+        .to
+        .iter()
+        .map(|to| parse_mailbox(to).map(|mailbox| address(&mailbox)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // SendGrid requires text/plain to precede text/html
+    let mut content = Vec::new();
+    if let Some(text) = &message.text_body {
+        content.push(json!({ "type": "text/plain", "value": text }));
+    }
+    if let Some(html) = &message.html_body {
+        content.push(json!({ "type": "text/html", "value": html }));
+    }
+
+    let mut payload = json!({
+        "personalizations": [{ "to": to }],
+        "from": address(&parse_mailbox(&message.from)?),
+        "subject": message.subject,
+        "content": content,
+    });
+    if let Some(reply_to) = &message.reply_to {
+        payload["reply_to"] = address(&parse_mailbox(reply_to)?);
+    }
+    if !message.attachments.is_empty() {
+        payload["attachments"] = message
+            .attachments
+            .iter()
+            .map(|attachment| {
+                json!({
+                    "content": STANDARD.encode(&attachment.content),
+                    "filename": attachment.filename,
+                    "type": attachment.content_type,
+                    "disposition": "attachment",
+                })
+            })
+            .collect();
+    }
+    Ok(payload)
+}
+
+#[async_trait]
+impl EmailProvider for SendGridEmailProvider {
+    async fn send(&self, message: EmailMessage) -> Result<(), EmailError> {
+        message.validate()?;
+        let payload = sendgrid_payload(&message)?;
+
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| EmailError::delivery(PROVIDER, e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(EmailError::delivery(
+            PROVIDER,
+            format!("unexpected status {status}: {body}"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_includes_reply_to_bodies_and_attachments() {
+        let message = EmailMessage::new(
+            "TrustFlow <no-reply@trustflow.io>",
+            "ada@example.com",
+            "Receipt",
+        )
+        .with_reply_to("support@trustflow.io")
+        .with_html("<p>Thanks</p>")
+        .with_text("Thanks")
+        .with_attachment("receipt.txt", "text/plain", &b"paid"[..]);
+
+        let payload = sendgrid_payload(&message).unwrap();
+        assert_eq!(payload["from"]["name"], "TrustFlow");
+        assert_eq!(payload["from"]["email"], "no-reply@trustflow.io");
+        assert_eq!(
+            payload["personalizations"][0]["to"][0]["email"],
+            "ada@example.com"
+        );
+        assert_eq!(payload["reply_to"]["email"], "support@trustflow.io");
+        assert_eq!(payload["content"][0]["type"], "text/plain");
+        assert_eq!(payload["content"][1]["type"], "text/html");
+        assert_eq!(payload["attachments"][0]["content"], "cGFpZA==");
+    }
+}
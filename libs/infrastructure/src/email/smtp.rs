@@ -0,0 +1,173 @@
+//! SMTP email provider backed by `lettre`
+
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Attachment, MultiPart, SinglePart, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+
+use super::provider::{EmailError, EmailMessage, EmailProvider, parse_mailbox};
+
+const PROVIDER: &str = "smtp";
+
+/// SMTP relay settings
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Upgrade the connection with STARTTLS; disable only for local relays
+    pub starttls: bool,
+}
+
+impl SmtpConfig {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            username: None,
+            password: None,
+            starttls: true,
+        }
+    }
+
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Plain-text connection, e.g. to a local mail catcher
+    pub fn insecure(mut self) -> Self {
+        self.starttls = false;
+        self
+    }
+}
+
+/// Sends email through an SMTP relay
+#[derive(Clone)]
+pub struct SmtpEmailProvider {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpEmailProvider {
+    pub fn new(config: &SmtpConfig) -> Result<Self, EmailError> {
+        let mut builder = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                .map_err(|e| EmailError::delivery(PROVIDER, e.to_string()))?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+        }
+        .port(config.port);
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+enum Body {
+    Single(SinglePart),
+    Alternative(MultiPart),
+}
+
+/// Build the MIME message: text/HTML alternatives, wrapped in
+/// `multipart/mixed` when there are attachments
+fn build_message(message: &EmailMessage) -> Result<Message, EmailError> {
+    let mut builder = Message::builder()
+        .from(parse_mailbox(&message.from)?)
+        .subject(&message.subject);
+    for to in &message.to {
+        builder = builder.to(parse_mailbox(to)?);
+    }
+    if let Some(reply_to) = &message.reply_to {
+        builder = builder.reply_to(parse_mailbox(reply_to)?);
+    }
+
+    let text = message.text_body.clone().map(SinglePart::plain);
+    let html = message.html_body.clone().map(SinglePart::html);
+    let body = match (text, html) {
+        (Some(text), Some(html)) => {
+            Body::Alternative(MultiPart::alternative().singlepart(text).singlepart(html))
+        }
+        (Some(part), None) | (None, Some(part)) => Body::Single(part),
+        (None, None) => {
+            return Err(EmailError::InvalidMessage(
+                "a text or HTML body is required".into(),
+            ));
+        }
+    };
+
+    let email = if message.attachments.is_empty() {
+        match body {
+            Body::Alternative(alternative) => builder.multipart(alternative),
+            Body::Single(part) => builder.singlepart(part),
+        }
+    } else {
+        let mut mixed = match body {
+            Body::Alternative(alternative) => MultiPart::mixed().multipart(alternative),
+            Body::Single(part) => MultiPart::mixed().singlepart(part),
+        };
+        for attachment in &message.attachments {
+            let content_type = ContentType::parse(&attachment.content_type).map_err(|e| {
+                EmailError::InvalidMessage(format!(
+                    "invalid content type '{}': {e}",
+                    attachment.content_type
+                ))
+            })?;
+            mixed = mixed.singlepart(
+                Attachment::new(attachment.filename.clone())
+                    .body(attachment.content.to_vec(), content_type),
+            );
+        }
+        builder.multipart(mixed)
+    };
+    email.map_err(|e| EmailError::InvalidMessage(e.to_string()))
+}
+
+#[async_trait]
+impl EmailProvider for SmtpEmailProvider {
+    async fn send(&self, message: EmailMessage) -> Result<(), EmailError> {
+        message.validate()?;
+        let email = build_message(&message)?;
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| EmailError::delivery(PROVIDER, e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_with_attachment_is_mixed_with_alternative_bodies() {
+        let message = EmailMessage::new("no-reply@trustflow.io", "ada@example.com", "Receipt")
+            .with_reply_to("support@trustflow.io")
+            .with_text("Thanks")
+            .with_html("<p>Thanks</p>")
+            .with_attachment("receipt.txt", "text/plain", &b"paid"[..]);
+
+        let raw = String::from_utf8(build_message(&message).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Reply-To: support@trustflow.io"));
+        assert!(raw.contains("multipart/mixed"));
+        assert!(raw.contains("multipart/alternative"));
+        assert!(raw.contains("filename=\"receipt.txt\""));
+
+        let plain =
+            EmailMessage::new("no-reply@trustflow.io", "ada@example.com", "Hi").with_text("hello");
+        let raw = String::from_utf8(build_message(&plain).unwrap().formatted()).unwrap();
+        assert!(!raw.contains("multipart"));
+    }
+}
@@ -0,0 +1,349 @@
+//! Domain event schema registry
+//!
+//! Producers register the JSON Schema of each event type with its version;
+//! payloads are then checked against it before they are written to the
+//! outbox (`PgOutboxStore::enqueue_checked`), and new versions of a schema
+//! are checked against the previous one.  A
+//! version is compatible when consumers written against the previous one
+//! keep working: every field they could rely on (required before) is still
+//! required, and no field changes its type.
+//!
+//! In development and test, mismatches are errors, so a breaking change
+//! fails the test suite instead of the consumers after deploy.  Elsewhere
+//! they are only logged: a schema that's stricter than the code shouldn't
+//! stop production traffic.
+//!
+//! Only the JSON Schema keywords the event schemas use are understood:
+//! `type`, `enum`, `properties`, `required`, `additionalProperties: false`
+//! and `items`.  Other keywords are ignored.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use config::core::environment::Environment;
+use error::AppError;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+/// What to do with a payload or schema change that doesn't fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaEnforcement {
+    /// Fail the publish or registration
+    Reject,
+    /// Log and carry on
+    Warn,
+}
+
+impl SchemaEnforcement {
+    /// [`Reject`](Self::Reject) in development and testing, [`Warn`](Self::Warn)
+    /// elsewhere
+    pub fn for_environment(environment: &Environment) -> Self {
+        if environment.allows_debug() {
+            Self::Reject
+        } else {
+            Self::Warn
+        }
+    }
+}
+
+/// Registered schema of one event type
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSchema {
+    pub version: u32,
+    pub schema: Value,
+}
+
+/// Schemas of the event types a service publishes
+#[derive(Debug)]
+pub struct EventSchemaRegistry {
+    schemas: RwLock<HashMap<String, EventSchema>>,
+    enforcement: SchemaEnforcement,
+}
+
+impl EventSchemaRegistry {
+    pub fn new(enforcement: SchemaEnforcement) -> Self {
+        Self {
+            schemas: RwLock::new(HashMap::new()),
+            enforcement,
+        }
+    }
+
+    pub fn for_environment(environment: &Environment) -> Self {
+        Self::new(SchemaEnforcement::for_environment(environment))
+    }
+
+    /// Register `schema` as `version` of `event_type`
+    ///
+    /// A version must be newer than the registered one and compatible with
+    /// it (see the module docs).
+    pub fn register(
+        &self,
+        event_type: impl Into<String>,
+        version: u32,
+        schema: Value,
+    ) -> Result<(), AppError> {
+        let event_type = event_type.into();
+        let mut schemas = self.schemas.write().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(current) = schemas.get(&event_type) {
+            if version <= current.version {
+                return Err(AppError::validation(format!(
+                    "{event_type} schema v{version} is not newer than registered v{}",
+                    current.version
+                )));
+            }
+            let mut breaks = Vec::new();
+            compatibility_breaks(&current.schema, &schema, "", &mut breaks);
+            if !breaks.is_empty() {
+                self.enforce(format!(
+                    "{event_type} schema v{version} is incompatible with v{}: {}",
+                    current.version,
+                    breaks.join("; ")
+                ))?;
+            }
+        }
+
+        schemas.insert(event_type, EventSchema { version, schema });
+        Ok(())
+    }
+
+    /// Registered schema of `event_type`
+    pub fn schema(&self, event_type: &str) -> Option<EventSchema> {
+        self.schemas
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(event_type)
+            .cloned()
+    }
+
+    /// Check `payload` against the schema of `event_type`
+    ///
+    /// Event types without a registered schema pass.
+    pub fn validate(&self, event_type: &str, payload: &Value) -> Result<(), AppError> {
+        let Some(registered) = self.schema(event_type) else {
+            return Ok(());
+        };
+
+        let mut errors = Vec::new();
+        validate_value(&registered.schema, payload, "", &mut errors);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        self.enforce(format!(
+            "{event_type} payload does not match schema v{}: {}",
+            registered.version,
+            errors.join("; ")
+        ))
+    }
+
+    fn enforce(&self, message: String) -> Result<(), AppError> {
+        match self.enforcement {
+            SchemaEnforcement::Reject => Err(AppError::validation(message)),
+            SchemaEnforcement::Warn => {
+                warn!("{message}");
+                Ok(())
+            }
+        }
+    }
+}
+
+fn pointer(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn properties(schema: &Value) -> Option<&Map<String, Value>> {
+    schema.get("properties").and_then(Value::as_object)
+}
+
+fn required(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|fields| fields.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    match schema.get("type") {
+        Some(Value::String(expected)) if !type_matches(expected, value) => {
+            errors.push(format!("{} must be {expected}", pointer(path)));
+            return;
+        }
+        Some(Value::Array(types))
+            if !types
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|expected| type_matches(expected, value)) =>
+        {
+            errors.push(format!("{} has the wrong type", pointer(path)));
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        errors.push(format!(
+            "{} is not one of the allowed values",
+            pointer(path)
+        ));
+    }
+
+    if let Some(object) = value.as_object() {
+        for field in required(schema) {
+            if !object.contains_key(field) {
+                errors.push(format!("{path}/{field} is required"));
+            }
+        }
+        let declared = properties(schema);
+        for (field, field_value) in object {
+            match declared.and_then(|declared| declared.get(field)) {
+                Some(field_schema) => validate_value(
+                    field_schema,
+                    field_value,
+                    &format!("{path}/{field}"),
+                    errors,
+                ),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{path}/{field} is not allowed"));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            validate_value(items, item, &format!("{path}/{index}"), errors);
+        }
+    }
+}
+
+/// Changes from `old` to `new` that break consumers of `old`
+fn compatibility_breaks(old: &Value, new: &Value, path: &str, breaks: &mut Vec<String>) {
+    if let (Some(old_type), Some(new_type)) = (old.get("type"), new.get("type"))
+        && old_type != new_type
+    {
+        breaks.push(format!(
+            "{} changed type from {old_type} to {new_type}",
+            pointer(path)
+        ));
+        return;
+    }
+
+    let still_required = required(new);
+    for field in required(old) {
+        if !still_required.contains(&field) {
+            breaks.push(format!("{path}/{field} is no longer required"));
+        }
+    }
+
+    if let (Some(old_properties), Some(new_properties)) = (properties(old), properties(new)) {
+        for (field, old_schema) in old_properties {
+            if let Some(new_schema) = new_properties.get(field) {
+                compatibility_breaks(old_schema, new_schema, &format!("{path}/{field}"), breaks);
+            }
+        }
+    }
+    if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+        compatibility_breaks(old_items, new_items, &format!("{path}/items"), breaks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn escrow_funded_v1() -> Value {
+        json!({
+            "type": "object",
+            "required": ["escrow_id", "amount_minor", "currency"],
+            "properties": {
+                "escrow_id": { "type": "string" },
+                "amount_minor": { "type": "integer" },
+                "currency": { "type": "string", "enum": ["NGN", "USD"] },
+                "milestones": { "type": "array", "items": { "type": "string" } }
+            }
+        })
+    }
+
+    #[test]
+    fn test_payload_missing_required_field_fails_validation() {
+        let registry = EventSchemaRegistry::for_environment(&Environment::Testing);
+        registry
+            .register("escrow.funded", 1, escrow_funded_v1())
+            .unwrap();
+
+        let valid = json!({ "escrow_id": "esc_1", "amount_minor": 250_000, "currency": "NGN" });
+        assert!(registry.validate("escrow.funded", &valid).is_ok());
+
+        let missing = json!({ "escrow_id": "esc_1", "currency": "NGN" });
+        let err = registry
+            .validate("escrow.funded", &missing)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/amount_minor is required"), "{err}");
+
+        let wrong = json!({
+            "escrow_id": "esc_1", "amount_minor": "250000", "currency": "GBP",
+            "milestones": ["design", 2]
+        });
+        let err = registry
+            .validate("escrow.funded", &wrong)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/amount_minor must be integer"), "{err}");
+        assert!(err.contains("/currency is not one of"), "{err}");
+        assert!(err.contains("/milestones/1 must be string"), "{err}");
+
+        // Production only logs
+        let lenient = EventSchemaRegistry::for_environment(&Environment::Production);
+        lenient
+            .register("escrow.funded", 1, escrow_funded_v1())
+            .unwrap();
+        assert!(lenient.validate("escrow.funded", &missing).is_ok());
+    }
+
+    #[test]
+    fn test_incompatible_schema_versions_are_rejected() {
+        let registry = EventSchemaRegistry::new(SchemaEnforcement::Reject);
+        registry
+            .register("escrow.funded", 1, escrow_funded_v1())
+            .unwrap();
+
+        let mut dropped_currency = escrow_funded_v1();
+        dropped_currency["required"] = json!(["escrow_id", "amount_minor"]);
+        assert!(
+            registry
+                .register("escrow.funded", 2, dropped_currency)
+                .is_err()
+        );
+
+        let mut retyped = escrow_funded_v1();
+        retyped["properties"]["amount_minor"] = json!({ "type": "string" });
+        assert!(registry.register("escrow.funded", 2, retyped).is_err());
+
+        let mut added_field = escrow_funded_v1();
+        added_field["properties"]["funded_by"] = json!({ "type": "string" });
+        registry
+            .register("escrow.funded", 2, added_field.clone())
+            .unwrap();
+        assert_eq!(registry.schema("escrow.funded").unwrap().version, 2);
+        assert!(registry.register("escrow.funded", 2, added_field).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! Readiness checks
+//!
+//! Services register named [`HealthCheck`]s (database, Redis, "migrations
+//! applied", "payment provider reachable", ...) in a [`HealthRegistry`].
+//! The `/readyz` handler runs them all concurrently and reports per-check
+//! results plus an overall status: any `Down` check makes the service not
+//! ready.
+//!
+//! Circuit breakers guarding downstream calls can be registered too, with
+//! [`HealthRegistry::register_circuit_breaker`].  An open breaker on a
+//! [`Criticality::Critical`] dependency degrades readiness; breakers on
+//! non-critical dependencies are only listed in the report.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::resilience::{CircuitBreaker, CircuitBreakerState};
+
+/// Status reported by a single check, and the aggregate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Degraded,
+    Down,
+}
+
+impl HealthStatus {
+    /// Whether traffic should be routed to the service
+    pub fn is_ready(&self) -> bool {
+        !matches!(self, Self::Down)
+    }
+}
+
+/// A named readiness probe
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> HealthStatus;
+}
+
+/// Result of one check within a report
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: HealthStatus,
+    pub latency_ms: u64,
+}
+
+/// How much a dependency behind a circuit breaker matters for readiness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criticality {
+    /// An open breaker degrades readiness
+    Critical,
+    /// An open breaker is reported but doesn't affect readiness
+    NonCritical,
+}
+
+/// State of one registered circuit breaker within a report
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerResult {
+    pub name: String,
+    pub state: CircuitBreakerState,
+    pub criticality: Criticality,
+}
+
+impl BreakerResult {
+    /// Readiness impact of this breaker
+    pub fn status(&self) -> HealthStatus {
+        match (self.criticality, self.state) {
+            (Criticality::Critical, CircuitBreakerState::Open) => HealthStatus::Degraded,
+            _ => HealthStatus::Up,
+        }
+    }
+}
+
+/// Aggregated readiness report
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub status: HealthStatus,
+    pub checks: Vec<CheckResult>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub circuit_breakers: Vec<BreakerResult>,
+}
+
+/// Registry of readiness checks
+#[derive(Clone)]
+pub struct HealthRegistry {
+    checks: Vec<(String, Arc<dyn HealthCheck>)>,
+    breakers: Vec<(String, CircuitBreaker, Criticality)>,
+    timeout: Duration,
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthRegistry {
+    /// Empty registry with a 2 second per-check timeout
+    pub fn new() -> Self {
+        Self {
+            checks: Vec::new(),
+            breakers: Vec::new(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Checks that take longer than `timeout` are reported as `Down`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Register a check under `name`
+    pub fn register(&mut self, name: impl Into<String>, check: Box<dyn HealthCheck>) -> &mut Self {
+        self.checks.push((name.into(), Arc::from(check)));
+        self
+    }
+
+    /// Report `breaker` under `name`; see [`Criticality`] for its effect on
+    /// readiness
+    pub fn register_circuit_breaker(
+        &mut self,
+        name: impl Into<String>,
+        breaker: CircuitBreaker,
+        criticality: Criticality,
+    ) -> &mut Self {
+        self.breakers.push((name.into(), breaker, criticality));
+        self
+    }
+
+    /// Names of all registered checks
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.checks.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Run every check concurrently and aggregate the results
+    pub async fn check_all(&self) -> ReadinessReport {
+        let runs = self.checks.iter().map(|(name, check)| async move {
+            let started = Instant::now();
+            let status = match tokio::time::timeout(self.timeout, check.check()).await {
+                Ok(status) => status,
+                Err(_) => {
+                    warn!(check = %name, "health check timed out");
+                    HealthStatus::Down
+                }
+            };
+
+            CheckResult {
+                name: name.clone(),
+                status,
+                latency_ms: started.elapsed().as_millis() as u64,
+            }
+        });
+
+        let checks = join_all(runs).await;
+        let circuit_breakers: Vec<BreakerResult> = self
+            .breakers
+            .iter()
+            .map(|(name, breaker, criticality)| BreakerResult {
+                name: name.clone(),
+                state: breaker.state(),
+                criticality: *criticality,
+            })
+            .collect();
+        let status = checks
+            .iter()
+            .map(|c| c.status)
+            .chain(circuit_breakers.iter().map(BreakerResult::status))
+            .max()
+            .unwrap_or(HealthStatus::Up);
+
+        ReadinessReport {
+            status,
+            checks,
+            circuit_breakers,
+        }
+    }
+}
+
+/// `SELECT 1` against the shared pool
+#[cfg(feature = "database")]
+pub struct DatabaseHealthCheck {
+    pool: crate::database::DbPool,
+}
+
+#[cfg(feature = "database")]
+impl DatabaseHealthCheck {
+    pub fn new(pool: crate::database::DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl HealthCheck for DatabaseHealthCheck {
+    async fn check(&self) -> HealthStatus {
+        match sqlx::query("SELECT 1").execute(self.pool.pool()).await {
+            Ok(_) => HealthStatus::Up,
+            Err(e) => {
+                warn!(error = %e, "database health check failed");
+                HealthStatus::Down
+            }
+        }
+    }
+}
+
+/// `PING` against Redis
+#[cfg(feature = "redis")]
+pub struct RedisHealthCheck {
+    pool: crate::redis::RedisPool,
+}
+
+#[cfg(feature = "redis")]
+impl RedisHealthCheck {
+    pub fn new(pool: crate::redis::RedisPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl HealthCheck for RedisHealthCheck {
+    async fn check(&self) -> HealthStatus {
+        let ping = async {
+            let mut conn = self.pool.connection().await?;
+            redis::cmd("PING")
+                .query_async::<String>(&mut conn)
+                .await
+                .map_err(crate::redis::RedisError::from)
+        };
+
+        match ping.await {
+            Ok(_) => HealthStatus::Up,
+            Err(e) => {
+                warn!(error = %e, "redis health check failed");
+                HealthStatus::Down
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(HealthStatus);
+
+    #[async_trait]
+    impl HealthCheck for Fixed {
+        async fn check(&self) -> HealthStatus {
+            self.0
+        }
+    }
+
+    struct Hangs;
+
+    #[async_trait]
+    impl HealthCheck for Hangs {
+        async fn check(&self) -> HealthStatus {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_check_makes_service_not_ready() {
+        let mut registry = HealthRegistry::new();
+        registry
+            .register("database", Box::new(Fixed(HealthStatus::Up)))
+            .register("migrations", Box::new(Fixed(HealthStatus::Down)));
+
+        let report = registry.check_all().await;
+
+        assert_eq!(report.status, HealthStatus::Down);
+        assert!(!report.status.is_ready());
+        assert_eq!(report.checks.len(), 2);
+        assert_eq!(report.checks[1].name, "migrations");
+        assert_eq!(report.checks[1].status, HealthStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_status() {
+        assert_eq!(
+            HealthRegistry::new().check_all().await.status,
+            HealthStatus::Up
+        );
+
+        let mut registry = HealthRegistry::new();
+        registry
+            .register("a", Box::new(Fixed(HealthStatus::Up)))
+            .register("b", Box::new(Fixed(HealthStatus::Degraded)));
+        let report = registry.check_all().await;
+        assert_eq!(report.status, HealthStatus::Degraded);
+        assert!(report.status.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_open_critical_breaker_degrades_readiness() {
+        use crate::resilience::CircuitBreakerConfig;
+
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            ..CircuitBreakerConfig::default()
+        };
+        let trip = |breaker: &CircuitBreaker| {
+            let breaker = breaker.clone();
+            async move {
+                let _ = breaker
+                    .call(|| async { Err::<(), _>("connection refused".into()) })
+                    .await;
+            }
+        };
+        let payments = CircuitBreaker::new(config.clone());
+        let recommendations = CircuitBreaker::new(config);
+        let mut registry = HealthRegistry::new();
+        registry
+            .register("database", Box::new(Fixed(HealthStatus::Up)))
+            .register_circuit_breaker("payments", payments.clone(), Criticality::Critical)
+            .register_circuit_breaker(
+                "recommendations",
+                recommendations.clone(),
+                Criticality::NonCritical,
+            );
+
+        assert_eq!(registry.check_all().await.status, HealthStatus::Up);
+
+        trip(&recommendations).await;
+        let report = registry.check_all().await;
+        assert_eq!(report.status, HealthStatus::Up);
+        assert_eq!(report.circuit_breakers[1].state, CircuitBreakerState::Open);
+
+        trip(&payments).await;
+        let report = registry.check_all().await;
+        assert_eq!(report.status, HealthStatus::Degraded);
+        assert!(report.status.is_ready());
+
+        payments.reset();
+        assert_eq!(registry.check_all().await.status, HealthStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn test_slow_check_times_out_as_down() {
+        let mut registry = HealthRegistry::new().with_timeout(Duration::from_millis(10));
+        registry.register("provider", Box::new(Hangs));
+
+        assert_eq!(registry.check_all().await.status, HealthStatus::Down);
+    }
+}
@@ -2,19 +2,48 @@
 //!
 //! Provides a generic, typed HTTP client wrapper around `reqwest` with
 //! built-in resilience and observability.
+//!
+//! Every request attempt is reported to the client's [`HttpClientObserver`]s.
+//! With the `metrics` feature, [`HttpClient::new`] installs a
+//! [`LatencyObserver`], which records the
+//! `http_client_request_duration_seconds` histogram labelled by target host,
+//! method and status, so p50/p95/p99 of downstream calls can be graphed per
+//! service.
+//!
+//! [`HttpClientConfig::for_dependency`] takes the request timeout from the
+//! dependency's class in [`TimeoutProfiles`], so a KYC provider gets longer
+//! than an internal service.
+//!
+//! Clients calling user-supplied URLs (webhooks, image fetches) should set
+//! [`HttpClientConfig::block_private_ips`].  Every host is then resolved by
+//! the client itself and the request refused if any address it resolves to
+//! is loopback, private, link-local or otherwise internal (see
+//! [`is_internal_ip`]).  The check runs for each connection, so redirects
+//! are covered too, and IP literals in the URL or a redirect target are
+//! checked before anything is sent.  Proxies from the environment are not
+//! used, as they would resolve the host out of the client's sight.
+//!
+//! When a `429` or `503` carries `Retry-After`, in seconds or as an HTTP
+//! date, the retry policy waits as advised instead of its own backoff, up
+//! to [`RetryConfig::max_retry_after`](crate::resilience::RetryConfig).
 
-use std::time::Duration;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant, SystemTime};
 
-use once_cell::sync::Lazy;
-use reqwest::{Client as ReqwestClient, Method, RequestBuilder, Response};
-use serde::de::DeserializeOwned;
+use common::value_objects::network::is_internal_ip;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client as ReqwestClient, Method, RequestBuilder, Response, StatusCode, redirect};
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
 
-use crate::resilience::{with_timeout, RetryPolicy};
-use crate::observability::metrics::MetricsExporter;
-use crate::error::AppError;
+use crate::AppError;
+use crate::resilience::{DependencyClass, RetryPolicy, TimeoutProfiles};
 
-static GLOBAL_HTTP_CLIENT: Lazy<ReqwestClient> = Lazy::new(|| {
+static GLOBAL_HTTP_CLIENT: LazyLock<ReqwestClient> = LazyLock::new(|| {
     ReqwestClient::builder()
         .timeout(Duration::from_secs(30))
         .build()
@@ -22,11 +51,13 @@ static GLOBAL_HTTP_CLIENT: Lazy<ReqwestClient> = Lazy::new(|| {
 });
 
 /// HTTP client wrapper configuration
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct HttpClientConfig {
     pub base_url: String,
     pub timeout: Duration,
-    pub retry_policy: Option<RetryPolicy>,
+    pub retry_policy: Option<Arc<RetryPolicy>>,
+    /// Refuse to connect to internal addresses, for SSRF protection
+    pub block_private_ips: bool,
 }
 
 impl Default for HttpClientConfig {
@@ -35,8 +66,176 @@ impl Default for HttpClientConfig {
             base_url: String::new(),
             timeout: Duration::from_secs(10),
             retry_policy: None,
+            block_private_ips: false,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// Configuration for calls to `base_url`, a dependency of `class`
+    pub fn for_dependency(
+        base_url: impl Into<String>,
+        class: DependencyClass,
+        profiles: &TimeoutProfiles,
+    ) -> Self {
+        Self {
+            base_url: base_url.into(),
+            timeout: profiles.timeout_for(class),
+            ..Self::default()
+        }
+    }
+}
+
+/// Requests reqwest follows at most by default
+const MAX_REDIRECTS: usize = 10;
+
+/// A request target that is an internal address
+#[derive(Debug, Error)]
+#[error("{host} resolves to internal address {ip}")]
+pub struct InternalAddressBlocked {
+    pub host: String,
+    pub ip: IpAddr,
+}
+
+/// Check an IP literal host of `url`; names are checked on resolution
+fn check_target(url: &url::Url) -> Result<(), InternalAddressBlocked> {
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        _ => return Ok(()),
+    };
+    if is_internal_ip(ip) {
+        return Err(InternalAddressBlocked {
+            host: ip.to_string(),
+            ip,
+        });
+    }
+    Ok(())
+}
+
+/// Resolver refusing names with any internal address
+struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            // One internal address is enough to refuse: the connector may
+            // pick any of them
+            if let Some(addr) = addrs.iter().find(|addr| is_internal_ip(addr.ip())) {
+                return Err(Box::new(InternalAddressBlocked {
+                    host,
+                    ip: addr.ip(),
+                }) as _);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// reqwest's default redirect limit, also refusing internal IP literals
+fn public_only_redirects() -> redirect::Policy {
+    redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match check_target(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(blocked) => attempt.error(blocked),
+        }
+    })
+}
+
+/// The [`InternalAddressBlocked`] somewhere in the source chain of `error`
+fn blocked_address(error: &reqwest::Error) -> Option<&InternalAddressBlocked> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if let Some(blocked) = error.downcast_ref::<InternalAddressBlocked>() {
+            return Some(blocked);
         }
+        source = error.source();
     }
+    None
+}
+
+/// Delay advised by a `Retry-After` value received at `now`
+///
+/// Accepts delay-seconds (`120`) and HTTP dates
+/// (`Wed, 21 Oct 2015 07:28:00 GMT`); a date in the past means no delay.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// A failed attempt, with the delay the server advised before the next
+struct AttemptError {
+    error: reqwest::Error,
+    retry_after: Option<Duration>,
+}
+
+impl From<reqwest::Error> for AttemptError {
+    fn from(error: reqwest::Error) -> Self {
+        Self {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+impl fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// One finished request attempt, as seen by observers
+#[derive(Debug, Clone)]
+pub struct HttpCall<'a> {
+    /// Target host, e.g. `wallet.service.consul`
+    pub host: &'a str,
+    pub method: &'a Method,
+    /// Response status; `None` when no response was received
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+}
+
+/// Hook notified after every request attempt, retries included
+pub trait HttpClientObserver: Send + Sync {
+    fn on_call(&self, call: &HttpCall<'_>);
+}
+
+/// Feeds request latencies into the metrics recorder
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyObserver;
+
+#[cfg(feature = "metrics")]
+impl HttpClientObserver for LatencyObserver {
+    fn on_call(&self, call: &HttpCall<'_>) {
+        let status = call
+            .status
+            .map_or_else(|| "error".to_string(), |s| s.as_u16().to_string());
+        metrics::histogram!(
+            crate::observability::metrics::HTTP_CLIENT_REQUEST_DURATION_SECONDS,
+            "host" => call.host.to_string(),
+            "method" => call.method.as_str().to_string(),
+            "status" => status,
+        )
+        .record(call.elapsed.as_secs_f64());
+    }
+}
+
+fn default_observers() -> Vec<Arc<dyn HttpClientObserver>> {
+    #[cfg(feature = "metrics")]
+    return vec![Arc::new(LatencyObserver)];
+    #[cfg(not(feature = "metrics"))]
+    Vec::new()
 }
 
 /// Generic HTTP client
@@ -44,71 +243,155 @@ impl Default for HttpClientConfig {
 pub struct HttpClient {
     client: ReqwestClient,
     config: HttpClientConfig,
+    observers: Vec<Arc<dyn HttpClientObserver>>,
 }
 
 impl HttpClient {
+    /// # Panics
+    ///
+    /// With [`HttpClientConfig::block_private_ips`] set, panics if the
+    /// client can't be built rather than falling back to one without the
+    /// address checks.
     pub fn new(config: HttpClientConfig) -> Self {
-        let client = ReqwestClient::builder()
-            .timeout(config.timeout)
-            .build()
-            .unwrap_or_else(|_| GLOBAL_HTTP_CLIENT.clone());
+        let builder = ReqwestClient::builder().timeout(config.timeout);
+        let client = if config.block_private_ips {
+            // A proxy from HTTP(S)_PROXY/ALL_PROXY would resolve the host
+            // itself, out of reach of the resolver
+            builder
+                .no_proxy()
+                .dns_resolver(Arc::new(PublicOnlyResolver))
+                .redirect(public_only_redirects())
+                .build()
+                .expect("failed to build http client blocking private addresses")
+        } else {
+            builder
+                .build()
+                .unwrap_or_else(|_| GLOBAL_HTTP_CLIENT.clone())
+        };
+
+        Self {
+            client,
+            config,
+            observers: default_observers(),
+        }
+    }
 
-        Self { client, config }
+    /// Also notify `observer` of every request attempt
+    pub fn with_observer(mut self, observer: Arc<dyn HttpClientObserver>) -> Self {
+        self.observers.push(observer);
+        self
     }
 
-    fn request(&self, method: Method, path: &str) -> RequestBuilder {
-        let url = if self.config.base_url.is_empty() {
+    fn url(&self, path: &str) -> String {
+        if self.config.base_url.is_empty() {
             path.to_string()
         } else {
             format!("{}{}", self.config.base_url, path)
+        }
+    }
+
+    /// Send one attempt and report it to the observers
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        request: RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let started = Instant::now();
+        let result = request.send().await;
+        let elapsed = started.elapsed();
+
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string());
+        let call = HttpCall {
+            host: &host,
+            method: &method,
+            status: result.as_ref().ok().map(Response::status),
+            elapsed,
         };
-        self.client.request(method, &url)
+        for observer in &self.observers {
+            observer.on_call(&call);
+        }
+        result
     }
 
-    /// Perform GET request and deserialize JSON response
-    pub async fn get<T>(&self, path: &str) -> Result<T, AppError>
+    async fn execute<T, F>(&self, method: Method, path: &str, build: F) -> Result<T, AppError>
     where
-        T: DeserializeOwned + Send + 'static,
+        T: DeserializeOwned,
+        F: Fn(RequestBuilder) -> RequestBuilder,
     {
+        let url = self.url(path);
+        if self.config.block_private_ips {
+            let parsed = url::Url::parse(&url)
+                .map_err(|e| AppError::validation(format!("invalid URL {url}: {e}")))?;
+            check_target(&parsed).map_err(|blocked| {
+                AppError::validation(format!("request to {url} blocked: {blocked}"))
+            })?;
+        }
         let op = || async {
-            let resp = self.request(Method::GET, path).send().await?;
+            let request = build(self.client.request(method.clone(), &url));
+            let resp = self.send(method.clone(), &url, request).await?;
             Self::handle_response(resp).await
         };
 
-        if let Some(policy) = &self.config.retry_policy {
-            policy.execute(op).await.map_err(|e| AppError::external(e.to_string()))
-        } else {
-            op().await.map_err(|e| AppError::external(e.to_string()))
-        }
+        let result = match &self.config.retry_policy {
+            Some(policy) => {
+                policy
+                    .execute_with_retry_after(op, |e: &AttemptError| e.retry_after)
+                    .await
+            }
+            None => op().await,
+        };
+        result.map_err(|AttemptError { error: e, .. }| {
+            if let Some(blocked) = blocked_address(&e) {
+                return AppError::validation(format!("request to {url} blocked: {blocked}"));
+            }
+            let message = if e.is_timeout() {
+                format!("timed out after {}ms", self.config.timeout.as_millis())
+            } else {
+                e.to_string()
+            };
+            AppError::external(url.clone(), message)
+        })
+    }
+
+    /// Perform GET request and deserialize JSON response
+    pub async fn get<T>(&self, path: &str) -> Result<T, AppError>
+    where
+        T: DeserializeOwned,
+    {
+        self.execute(Method::GET, path, |request| request).await
     }
 
     /// Perform POST with JSON body
     pub async fn post<B, T>(&self, path: &str, body: &B) -> Result<T, AppError>
     where
         B: Serialize + ?Sized,
-        T: DeserializeOwned + Send + 'static,
+        T: DeserializeOwned,
     {
-        let op = || async {
-            let resp = self
-                .request(Method::POST, path)
-                .json(body)
-                .send()
-                .await?;
-            Self::handle_response(resp).await
-        };
-
-        if let Some(policy) = &self.config.retry_policy {
-            policy.execute(op).await.map_err(|e| AppError::external(e.to_string()))
-        } else {
-            op().await.map_err(|e| AppError::external(e.to_string()))
-        }
+        self.execute(Method::POST, path, |request| request.json(body))
+            .await
     }
 
-    async fn handle_response<T>(resp: Response) -> Result<T, reqwest::Error>
+    async fn handle_response<T>(resp: Response) -> Result<T, AttemptError>
     where
-        T: DeserializeOwned + Send + 'static,
+        T: DeserializeOwned,
     {
-        resp.error_for_status_ref()?;
+        if let Err(error) = resp.error_for_status_ref() {
+            let throttled = matches!(
+                resp.status(),
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+            );
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .filter(|_| throttled)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, SystemTime::now()));
+            return Err(AttemptError { error, retry_after });
+        }
         let body = resp.json::<T>().await?;
         Ok(body)
     }
@@ -117,30 +400,215 @@ impl HttpClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::resilience::RetryConfig;
     use serde::Deserialize;
-    use httpmock::MockServer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
 
     #[derive(Debug, Deserialize)]
     struct TestResponse {
         hello: String,
     }
 
+    /// Serve `body` with status 200 to every connection on a local port
+    async fn serve(body: &'static str) -> String {
+        serve_after(Duration::ZERO, body).await
+    }
+
+    /// Like [`serve`], answering each request after `delay`
+    async fn serve_after(delay: Duration, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                tokio::time::sleep(delay).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    /// Answer the n-th connection with the n-th raw response, repeating the
+    /// last one; returns the base URL and when each request arrived
+    async fn serve_sequence(
+        responses: Vec<&'static str>,
+    ) -> (String, Arc<std::sync::Mutex<Vec<Instant>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let arrivals = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = arrivals.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let n = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(Instant::now());
+                    seen.len()
+                };
+                let response = responses[(n - 1).min(responses.len() - 1)];
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{addr}"), arrivals)
+    }
+
+    fn client(base_url: String) -> HttpClient {
+        HttpClient::new(HttpClientConfig {
+            base_url,
+            timeout: Duration::from_secs(1),
+            ..HttpClientConfig::default()
+        })
+    }
+
     #[tokio::test]
     async fn test_get() {
-        let server = MockServer::start();
-        let mock = server.mock(|when, then| {
-            when.method(GET).path("/ping");
-            then.status(200).json_body_obj(&TestResponse { hello: "world".into() });
-        });
+        let base_url = serve(r#"{"hello":"world"}"#).await;
+
+        let resp: TestResponse = client(base_url).get("/ping").await.unwrap();
+        assert_eq!(resp.hello, "world");
+    }
+
+    #[test]
+    fn test_retry_after_accepts_seconds_and_http_dates() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(parse_retry_after("2", now), Some(Duration::from_secs(2)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
 
+    #[tokio::test]
+    async fn test_retry_waits_as_long_as_retry_after_advises() {
+        let (base_url, arrivals) = serve_sequence(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nretry-after: 2\r\n\
+             content-length: 0\r\nconnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+             content-length: 17\r\nconnection: close\r\n\r\n{\"hello\":\"world\"}",
+        ])
+        .await;
         let client = HttpClient::new(HttpClientConfig {
-            base_url: server.url(""),
+            base_url,
             timeout: Duration::from_secs(1),
-            retry_policy: None,
+            retry_policy: Some(Arc::new(RetryPolicy::new(RetryConfig {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(10),
+                ..RetryConfig::default()
+            }))),
+            ..HttpClientConfig::default()
         });
 
         let resp: TestResponse = client.get("/ping").await.unwrap();
         assert_eq!(resp.hello, "world");
-        mock.assert();
+
+        let arrivals = arrivals.lock().unwrap();
+        assert_eq!(arrivals.len(), 2);
+        let waited = arrivals[1] - arrivals[0];
+        assert!(
+            (Duration::from_millis(1_900)..Duration::from_secs(3)).contains(&waited),
+            "retried after {waited:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_private_ips_are_blocked_when_enabled() {
+        let base_url = serve(r#"{"hello":"world"}"#).await;
+        let port = base_url.rsplit(':').next().unwrap();
+        let guarded = |base_url: String| {
+            HttpClient::new(HttpClientConfig {
+                base_url,
+                block_private_ips: true,
+                ..HttpClientConfig::default()
+            })
+        };
+
+        // A name resolving to a loopback address
+        let err = guarded(format!("http://localhost:{port}"))
+            .get::<TestResponse>("/ping")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::ValidationError(_)), "{err:?}");
+        assert!(
+            err.to_string().contains("resolves to internal address"),
+            "{err}"
+        );
+
+        // IP literals, including the cloud metadata endpoint
+        for base_url in [base_url.clone(), "http://169.254.169.254".to_string()] {
+            let err = guarded(base_url)
+                .get::<TestResponse>("/latest/meta-data")
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("internal address"), "{err}");
+        }
+
+        // Not enabled by default
+        let resp: TestResponse = client(base_url).get("/ping").await.unwrap();
+        assert_eq!(resp.hello, "world");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_comes_from_the_dependency_class() {
+        let base_url = serve_after(Duration::from_millis(200), r#"{"hello":"world"}"#).await;
+        let profiles = TimeoutProfiles {
+            http: Duration::from_millis(50),
+            kyc: Duration::from_secs(2),
+            ..TimeoutProfiles::default()
+        };
+
+        let internal = HttpClient::new(HttpClientConfig::for_dependency(
+            base_url.clone(),
+            DependencyClass::Http,
+            &profiles,
+        ));
+        let err = internal.get::<TestResponse>("/ping").await.unwrap_err();
+        assert!(err.to_string().contains("timed out after 50ms"), "{err}");
+
+        let kyc = HttpClient::new(HttpClientConfig::for_dependency(
+            base_url,
+            DependencyClass::Kyc,
+            &profiles,
+        ));
+        let resp: TestResponse = kyc.get("/ping").await.unwrap();
+        assert_eq!(resp.hello, "world");
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_call_records_latency_by_host_and_method() {
+        use crate::observability::metrics::prometheus_builder;
+
+        let base_url = serve(r#"{"hello":"world"}"#).await;
+        let recorder = prometheus_builder().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let _: TestResponse = client(base_url)
+            .post("/greetings", &serde_json::json!({}))
+            .await
+            .unwrap();
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains(
+                r#"http_client_request_duration_seconds_count{host="127.0.0.1",method="POST",status="200"} 1"#
+            ),
+            "{rendered}"
+        );
+        // Tuned buckets rather than the exporter's summary quantiles
+        assert!(rendered.contains(r#"le="0.025""#), "{rendered}");
     }
 }
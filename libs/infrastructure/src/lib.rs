@@ -4,10 +4,24 @@
 //! - `database`: one PostgreSQL pool configuration and builder
 //! - `redis`: one Redis connection manager configuration and builder
 //! - `config`: thin re-exports of shared configuration loader utilities
+//! - `audit`: audit trail written to the database and external append-only sinks
+//! - `health`: readiness check registry
+//! - `event_schema`: domain event schema registry and compatibility checks
+//! - `resilience`: circuit breaker, retry, timeout and bulkhead helpers
+//! - `storage`: object store for uploaded documents
+//! - `email`: outbound email providers (SMTP, SendGrid)
+//! - `discovery`: Consul service discovery and canary-aware routing
+//! - `http_clients`: typed inter-service HTTP client with latency metrics
+//! - `observability`: log subscriber setup, Prometheus and OTLP metrics exporters
+//! - `server`: HTTP server bootstrap with a bounded graceful shutdown
 
 pub use error::{AppError, AppResult};
 
+pub mod audit;
 pub mod config;
+pub mod event_schema;
+pub mod health;
+pub mod resilience;
 
 #[cfg(feature = "database")]
 pub mod database;
@@ -15,6 +29,24 @@ pub mod database;
 #[cfg(feature = "redis")]
 pub mod redis;
 
+#[cfg(feature = "storage")]
+pub mod storage;
+
+#[cfg(feature = "email")]
+pub mod email;
+
+#[cfg(feature = "discovery")]
+pub mod discovery;
+
+#[cfg(feature = "http-client")]
+pub mod http_clients;
+
+#[cfg(feature = "metrics")]
+pub mod observability;
+
+#[cfg(feature = "http")]
+pub mod server;
+
 #[cfg(feature = "database")]
 pub use database::{DatabaseConfig, DbPool, DbPoolError};
 
@@ -0,0 +1,219 @@
+//! Observability configuration
+//!
+//! One [`ObservabilityConfig`] per service, loaded like any other config
+//! section and handed to [`init`](super::init):
+//!
+//! | Key | Default | |
+//! |-----|---------|-|
+//! | `SERVICE_NAME` | `trustflow` | `service` field of JSON logs, `service.name` in OTLP |
+//! | `SERVICE_VERSION` | `unknown` | `service.version` in OTLP |
+//! | `LOG_FORMAT` | `text` | `text` or `json` |
+//! | `LOG_LEVEL` | `info` | filter directives; `RUST_LOG` overrides them |
+//! | `OTEL_EXPORTER_OTLP_ENDPOINT` | unset | collector metrics and traces are pushed to |
+//! | `OTEL_EXPORT_INTERVAL_SECS` | `60s` | time between OTLP pushes; bare numbers are seconds |
+//! | `METRICS_PORT` | `9090` | port the Prometheus endpoint is served on |
+//! | `TRACE_SAMPLE_RATIO` | `1.0` | share of traces kept, clamped to `0..=1` |
+
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use config::Config;
+use config::core::duration::ConfigDuration;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines, for development
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" | "pretty" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format '{other}', expected text or json"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// Logging, tracing and metrics settings of a service
+#[derive(Debug, Clone, Config)]
+pub struct ObservabilityConfig {
+    #[config(key = "SERVICE_NAME", default = "trustflow")]
+    pub service_name: String,
+    #[config(key = "SERVICE_VERSION", default = "unknown")]
+    pub service_version: String,
+    #[config(key = "LOG_FORMAT", default = "text")]
+    pub log_format: LogFormat,
+    /// `EnvFilter` directives, e.g. `info,sqlx=warn`
+    #[config(key = "LOG_LEVEL", default = "info")]
+    pub log_level: String,
+    /// OTLP/HTTP collector base URL; metrics and traces aren't pushed when unset
+    #[config(key = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+    #[config(key = "OTEL_EXPORT_INTERVAL_SECS", default = "60s", with = ConfigDuration::into_std)]
+    pub otlp_interval: Duration,
+    #[config(key = "METRICS_PORT", default = 9090)]
+    pub metrics_port: u16,
+    #[config(key = "TRACE_SAMPLE_RATIO", default = 1.0, with = clamp_ratio)]
+    pub trace_sample_ratio: f64,
+}
+
+impl Default for ObservabilityConfig {
+    fn default() -> Self {
+        Self {
+            service_name: "trustflow".to_string(),
+            service_version: "unknown".to_string(),
+            log_format: LogFormat::Text,
+            log_level: "info".to_string(),
+            otlp_endpoint: None,
+            otlp_interval: Duration::from_secs(60),
+            metrics_port: 9090,
+            trace_sample_ratio: 1.0,
+        }
+    }
+}
+
+impl ObservabilityConfig {
+    /// Address the Prometheus endpoint listens on
+    pub fn metrics_addr(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.metrics_port))
+    }
+
+    /// OTLP export settings, if a collector is configured
+    #[cfg(feature = "otlp")]
+    pub fn otlp(&self) -> Option<super::OtlpConfig> {
+        let endpoint = self.otlp_endpoint.as_deref()?;
+        Some(
+            super::OtlpConfig::new(endpoint, &self.service_name)
+                .with_service_version(&self.service_version)
+                .with_interval(self.otlp_interval),
+        )
+    }
+
+    /// Whether the trace `trace_id` is kept
+    ///
+    /// The decision depends only on the id, so every service sampling the
+    /// same trace at the same ratio agrees on it.
+    pub fn should_sample(&self, trace_id: &str) -> bool {
+        keeps_trace(trace_id, self.trace_sample_ratio)
+    }
+}
+
+/// Whether `trace_id` falls in the kept share `ratio` of traces
+pub(super) fn keeps_trace(trace_id: &str, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    // FNV-1a with a SplitMix64 finalizer: stable across builds and
+    // evenly spread even for ids differing only in their last digits
+    let mut hash = trace_id
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    (hash as f64 / u64::MAX as f64) < ratio
+}
+
+fn clamp_ratio(ratio: f64) -> f64 {
+    if ratio.is_nan() {
+        1.0
+    } else {
+        ratio.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::loader::ConfigLoader;
+    use config::sources::dotenv::DotenvLayerBuilder;
+
+    fn loader(vars: &[(&str, &str)]) -> ConfigLoader {
+        let env = vars
+            .iter()
+            .fold(DotenvLayerBuilder::new(), |builder, (key, value)| {
+                builder.with_override(*key, *value)
+            })
+            .build();
+        ConfigLoader::new().with_service_env(env)
+    }
+
+    #[test]
+    fn test_config_from_loader_derives_settings() {
+        let config = ObservabilityConfig::from_loader(&loader(&[
+            ("SERVICE_NAME", "escrow-service"),
+            ("SERVICE_VERSION", "1.4.2"),
+            ("LOG_FORMAT", "JSON"),
+            ("LOG_LEVEL", "info,sqlx=warn"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://otel-collector:4318"),
+            ("OTEL_EXPORT_INTERVAL_SECS", "15"),
+            ("METRICS_PORT", "9464"),
+            ("TRACE_SAMPLE_RATIO", "1.5"),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_level, "info,sqlx=warn");
+        assert_eq!(config.metrics_addr(), "0.0.0.0:9464".parse().unwrap());
+        assert_eq!(config.trace_sample_ratio, 1.0);
+        assert!(config.should_sample("4bf92f3577b34da6a3ce929d0e0e4736"));
+
+        #[cfg(feature = "otlp")]
+        {
+            let otlp = config.otlp().expect("collector configured");
+            assert_eq!(otlp.endpoint, "http://otel-collector:4318");
+            assert_eq!(otlp.service_name, "escrow-service");
+            assert_eq!(otlp.service_version.as_deref(), Some("1.4.2"));
+            assert_eq!(otlp.interval, Duration::from_secs(15));
+        }
+
+        let defaults = ObservabilityConfig::from_loader(&loader(&[])).unwrap();
+        assert_eq!(defaults.log_format, LogFormat::Text);
+        assert_eq!(defaults.metrics_addr(), "0.0.0.0:9090".parse().unwrap());
+        assert_eq!(defaults.otlp_endpoint, None);
+
+        let err = ObservabilityConfig::from_loader(&loader(&[("LOG_FORMAT", "xml")]));
+        assert!(err.unwrap_err().to_string().contains("log_format"));
+    }
+
+    #[test]
+    fn test_sample_ratio_keeps_roughly_that_share_of_traces() {
+        let config = ObservabilityConfig {
+            trace_sample_ratio: 0.25,
+            ..ObservabilityConfig::default()
+        };
+        let kept = (0..10_000)
+            .filter(|i| config.should_sample(&format!("{i:032x}")))
+            .count();
+        assert!((2_000..3_000).contains(&kept), "kept {kept} of 10000");
+
+        let none = ObservabilityConfig {
+            trace_sample_ratio: 0.0,
+            ..ObservabilityConfig::default()
+        };
+        assert!(!none.should_sample("4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+}
@@ -0,0 +1,22 @@
+//! Degradation counter
+//!
+//! Fallback paths keep requests succeeding while a dependency misbehaves: a
+//! cache read that fails falls through to the source, a rate limiter without
+//! Redis decides locally, an OTP goes out by email when SMS fails.  Users
+//! see no errors, so error-rate alerts stay quiet while the platform runs
+//! degraded.  Each fallback taken calls [`record_degradation`], which counts
+//! it in [`DEGRADATIONS_TOTAL`] labelled by `component` and `reason`, so
+//! alerts can fire on e.g.
+//! `sum by (component) (rate(degradations_total[5m])) > 1`.
+
+/// Counter of fallbacks taken, labelled by `component` and `reason`
+pub const DEGRADATIONS_TOTAL: &str = "degradations_total";
+
+/// Count one fallback taken by `component` because of `reason`
+///
+/// Both labels are static so the series stay few; put keys, ids and error
+/// messages in the log line instead.
+pub fn record_degradation(component: &'static str, reason: &'static str) {
+    metrics::counter!(DEGRADATIONS_TOTAL, "component" => component, "reason" => reason)
+        .increment(1);
+}
@@ -1,31 +1,203 @@
 //! Logging helpers
 //!
-//! Wraps `tracing-subscriber` setup and provides convenience initialization.
+//! Builds the `tracing` subscriber described by an [`ObservabilityConfig`]:
+//! an `EnvFilter` from `LOG_LEVEL` (or `RUST_LOG`, which wins) and a fmt
+//! layer writing either human-readable text or one JSON object per line.
+//!
+//! JSON lines carry `timestamp`, `level`, `target`, `service`, the event's
+//! fields (including `message`) and `spans`, the fields of every span the
+//! event happened in, outermost first.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use super::config::{LogFormat, ObservabilityConfig};
+
+/// Filter of `config`, overridden by `RUST_LOG` when that is set
+pub fn env_filter(config: &ObservabilityConfig) -> Result<EnvFilter, Box<dyn std::error::Error>> {
+    match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) if !directives.trim().is_empty() => Ok(EnvFilter::try_new(directives)?),
+        _ => Ok(EnvFilter::try_new(&config.log_level)?),
+    }
+}
+
+/// Subscriber logging to `make_writer` as configured by `config`
+pub fn subscriber<W>(
+    config: &ObservabilityConfig,
+    make_writer: W,
+) -> Result<impl Subscriber + Send + Sync + for<'a> LookupSpan<'a>, Box<dyn std::error::Error>>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let fmt_layer = match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_writer(make_writer)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat {
+                service: config.service_name.clone(),
+            })
+            .with_writer(make_writer)
+            .boxed(),
+    };
+
+    Ok(Registry::default().with(fmt_layer.with_filter(env_filter(config)?)))
+}
+
+/// Writes events as single-line JSON objects
+struct JsonFormat {
+    service: String,
+}
 
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, fmt, Registry};
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".into(),
+            OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .map_err(|_| fmt::Error)?
+                .into(),
+        );
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), metadata.target().into());
+        line.insert("service".into(), self.service.clone().into());
 
-/// Initialize logging with environment variable support.
-///
-/// `RUST_LOG` environment variable is respected for log level filtering.
-pub fn init_logging() {
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        line.extend(fields.0);
 
-    let fmt_layer = fmt::layer()
-        .with_target(false)
-        .with_ansi(true);
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope
+                .from_root()
+                .map(|span| {
+                    let mut fields = span
+                        .extensions()
+                        .get::<FormattedFields<JsonFields>>()
+                        .and_then(|f| serde_json::from_str::<Map<String, Value>>(f).ok())
+                        .unwrap_or_default();
+                    fields.insert("name".into(), span.name().into());
+                    Value::Object(fields)
+                })
+                .collect();
+            line.insert("spans".into(), spans.into());
+        }
 
-    let subscriber = Registry::default().with(env_filter).with(fmt_layer);
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
 
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Failed to set global default subscriber");
+/// Stores span fields as a JSON object, for [`JsonFormat`] to pick up
+struct JsonFields;
+
+impl<'w> FormatFields<'w> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'w mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::testing::Logs;
+
     #[test]
-    fn test_init_logging() {
-        init_logging();
-        tracing::info!("logging initialized");
+    fn test_json_format_writes_event_and_span_fields() {
+        let config = ObservabilityConfig {
+            service_name: "escrow-service".to_string(),
+            log_format: LogFormat::Json,
+            log_level: "info".to_string(),
+            ..ObservabilityConfig::default()
+        };
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = subscriber(&config, move || writer.clone()).unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "req-1");
+            let _entered = span.enter();
+            tracing::info!(escrow_id = "esc_1", amount = 2500, "escrow funded");
+            tracing::debug!("filtered out");
+        });
+
+        let output = logs.contents();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1, "{output}");
+        let line = &lines[0];
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["service"], "escrow-service");
+        assert_eq!(line["message"], "escrow funded");
+        assert_eq!(line["escrow_id"], "esc_1");
+        assert_eq!(line["amount"], 2500);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["request_id"], "req-1");
     }
 }
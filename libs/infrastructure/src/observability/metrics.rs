@@ -1,13 +1,48 @@
 //! Metrics helpers
 //!
 //! Sets up global metrics registry and exposes exporter types.
+//!
+//! Metrics are always served for Prometheus to scrape.  With the `otlp`
+//! feature they can also be pushed to an OpenTelemetry collector; see
+//! [`init_metrics_with_otlp`].
+
+use common::events::EVENT_CONSUME_LAG_SECONDS;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+#[cfg(feature = "otlp")]
+use super::otlp::{OtlpConfig, OtlpRecorder};
+#[cfg(feature = "otlp")]
+use metrics_util::layers::{Fanout, FanoutBuilder};
 
-use metrics::{Gauge, Histogram, Key, Recorder, Unit};
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+/// Histogram of outbound HTTP request durations, labelled by `host`,
+/// `method` and `status`
+pub const HTTP_CLIENT_REQUEST_DURATION_SECONDS: &str = "http_client_request_duration_seconds";
+
+/// Counter of pub/sub messages dropped for slow subscribers, labelled by
+/// `channel` and `policy`
+pub const REDIS_PUBSUB_DROPPED_MESSAGES_TOTAL: &str = "redis_pubsub_dropped_messages_total";
+
+/// Buckets for inter-service call latencies, in seconds
+///
+/// Dense between 5ms and 250ms, where healthy calls inside the cluster land,
+/// with a tail up to the default 10s client timeout.
+pub const HTTP_CLIENT_LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.15, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Buckets for event consume lag, in seconds
+///
+/// From well under a second for a consumer keeping up to several minutes
+/// for one that has fallen behind.
+pub const EVENT_LAG_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0,
+];
 
 /// Holds exporter handle so that metrics can be scraped
 pub struct MetricsExporter {
     handle: PrometheusHandle,
+    #[cfg(feature = "otlp")]
+    otlp: Option<OtlpRecorder>,
 }
 
 impl MetricsExporter {
@@ -15,9 +50,42 @@ impl MetricsExporter {
     pub fn handle(&self) -> &PrometheusHandle {
         &self.handle
     }
+
+    /// OTLP exporter, if metrics are also pushed to a collector
+    #[cfg(feature = "otlp")]
+    pub fn otlp(&self) -> Option<&OtlpRecorder> {
+        self.otlp.as_ref()
+    }
+
+    /// Push the current metrics to the OTLP collector, e.g. on shutdown
+    ///
+    /// Does nothing when OTLP export isn't configured.
+    #[cfg(feature = "otlp")]
+    pub async fn flush(&self) -> error::AppResult<()> {
+        match &self.otlp {
+            Some(otlp) => otlp.flush().await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Prometheus builder with the bucket layouts of this crate's histograms
+pub fn prometheus_builder() -> PrometheusBuilder {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(HTTP_CLIENT_REQUEST_DURATION_SECONDS.to_string()),
+            HTTP_CLIENT_LATENCY_BUCKETS,
+        )
+        .and_then(|builder| {
+            builder.set_buckets_for_metric(
+                Matcher::Full(EVENT_CONSUME_LAG_SECONDS.to_string()),
+                EVENT_LAG_BUCKETS,
+            )
+        })
+        .expect("bucket list is not empty")
 }
 
-/// Initialize global metrics recorder and return exporter handle. 
+/// Initialize global metrics recorder and return exporter handle.
 ///
 /// Usage:
 /// ````rust
@@ -25,11 +93,62 @@ impl MetricsExporter {
 /// // mount exporter.handle().render() to HTTP endpoint
 /// ````
 pub fn init_metrics() -> Result<MetricsExporter, Box<dyn std::error::Error>> {
-    let builder = PrometheusBuilder::new();
-    let handle = builder
-        .install()?;
+    let handle = prometheus_builder().install_recorder()?;
 
-    Ok(MetricsExporter { handle })
+    Ok(MetricsExporter {
+        handle,
+        #[cfg(feature = "otlp")]
+        otlp: None,
+    })
+}
+
+/// Recorder feeding both the Prometheus endpoint and an OTLP collector,
+/// without installing it
+///
+/// Nothing runs Prometheus upkeep or pushes to the collector; use
+/// [`init_metrics_with_otlp`] outside of tests.
+#[cfg(feature = "otlp")]
+pub fn metrics_recorder_with_otlp(
+    config: OtlpConfig,
+) -> Result<(MetricsExporter, Fanout), Box<dyn std::error::Error>> {
+    let prometheus = prometheus_builder().build_recorder();
+    let otlp = OtlpRecorder::new(config)?;
+    let exporter = MetricsExporter {
+        handle: prometheus.handle(),
+        otlp: Some(otlp.clone()),
+    };
+    let recorder = FanoutBuilder::default()
+        .add_recorder(prometheus)
+        .add_recorder(otlp)
+        .build();
+    Ok((exporter, recorder))
+}
+
+/// Like [`init_metrics`], also pushing every metric to the OTLP collector
+/// described by `config` once per `config.interval`
+///
+/// Must be called inside a Tokio runtime.  Flush the exporter on shutdown
+/// so the last interval isn't lost.
+#[cfg(feature = "otlp")]
+pub fn init_metrics_with_otlp(
+    config: OtlpConfig,
+) -> Result<MetricsExporter, Box<dyn std::error::Error>> {
+    let (exporter, recorder) = metrics_recorder_with_otlp(config)?;
+    metrics::set_global_recorder(recorder)?;
+
+    // `install_recorder` would otherwise run the Prometheus upkeep
+    let handle = exporter.handle.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(5));
+        loop {
+            ticker.tick().await;
+            handle.run_upkeep();
+        }
+    });
+    if let Some(otlp) = &exporter.otlp {
+        otlp.spawn();
+    }
+    Ok(exporter)
 }
 
 #[cfg(test)]
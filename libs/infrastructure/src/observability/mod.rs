@@ -1,11 +1,72 @@
 //! Observability utilities for logging, tracing, and metrics
 //!
 //! Provides a standardized way to initialize and configure observability across services.
+//!
+//! Services load an [`ObservabilityConfig`] and call [`init`] once at
+//! startup, which installs the log subscriber, the tracer provider spans are
+//! sampled and exported through (see [`traces`]) and the metrics recorder
+//! (Prometheus, plus OTLP with the `otlp` feature when a collector is
+//! configured).
+//!
+//! ```rust,ignore
+//! let config = ObservabilityConfig::from_loader(&loader)?;
+//! let exporter = observability::init(&config)?;
+//! // serve exporter.handle().render() on config.metrics_addr()
+//! ```
 
+pub mod config;
+pub mod degradation;
 pub mod logging;
-pub mod tracing;
 pub mod metrics;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod traces;
+
+pub use config::{LogFormat, ObservabilityConfig};
+pub use degradation::record_degradation;
+pub use metrics::{MetricsExporter, init_metrics, prometheus_builder};
+#[cfg(feature = "otlp")]
+pub use metrics::{init_metrics_with_otlp, metrics_recorder_with_otlp};
+#[cfg(feature = "otlp")]
+pub use otlp::{OtlpConfig, OtlpRecorder};
+pub use traces::{RatioSampler, tracer_provider};
+
+use opentelemetry::trace::TracerProvider as _;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Install the global log subscriber, tracer provider and metrics recorder
+/// for `config`
+///
+/// Call once, inside the Tokio runtime (OTLP pushes run on it).
+pub fn init(config: &ObservabilityConfig) -> Result<MetricsExporter, Box<dyn std::error::Error>> {
+    let provider = tracer_provider(config)?;
+    let spans =
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(config.service_name.clone()));
+    opentelemetry::global::set_tracer_provider(provider);
+    tracing::subscriber::set_global_default(
+        logging::subscriber(config, std::io::stdout)?.with(spans),
+    )?;
+
+    #[cfg(feature = "otlp")]
+    let exporter = match config.otlp() {
+        Some(otlp) => init_metrics_with_otlp(otlp)?,
+        None => init_metrics()?,
+    };
+    #[cfg(not(feature = "otlp"))]
+    let exporter = {
+        if config.otlp_endpoint.is_some() {
+            tracing::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set but the otlp feature is disabled");
+        }
+        init_metrics()?
+    };
 
-pub use logging::init_logging;
-pub use tracing::init_tracing;
-pub use metrics::{init_metrics, MetricsExporter};
+    tracing::info!(
+        service = %config.service_name,
+        version = %config.service_version,
+        log_format = %config.log_format,
+        otlp_endpoint = config.otlp_endpoint.as_deref().unwrap_or("none"),
+        trace_sample_ratio = config.trace_sample_ratio,
+        "observability initialized"
+    );
+    Ok(exporter)
+}
@@ -0,0 +1,487 @@
+//! OTLP metrics export
+//!
+//! Pushes the metrics recorded through the `metrics` facade to an
+//! OpenTelemetry collector, using OTLP/HTTP with the JSON encoding
+//! (`POST {endpoint}/v1/metrics`).  [`OtlpRecorder`] keeps its own copy of
+//! every counter, gauge and histogram; it is installed next to the
+//! Prometheus recorder (see
+//! [`init_metrics_with_otlp`](super::init_metrics_with_otlp)), so each metric
+//! is registered once and shows up on both the scrape endpoint and the
+//! collector.
+//!
+//! Counters and histograms are exported with cumulative temporality, the
+//! same as Prometheus scrapes them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use error::{AppError, AppResult};
+use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use metrics_util::registry::{AtomicStorage, Registry};
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+
+use super::metrics::{HTTP_CLIENT_LATENCY_BUCKETS, HTTP_CLIENT_REQUEST_DURATION_SECONDS};
+
+/// Bucket bounds for histograms without configured ones, in seconds
+///
+/// The Prometheus client defaults.
+pub const DEFAULT_OTLP_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Where and how often metrics are pushed
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Collector base URL, e.g. `http://otel-collector:4318`
+    pub endpoint: String,
+    /// `service.name` resource attribute
+    pub service_name: String,
+    /// `service.version` resource attribute
+    pub service_version: Option<String>,
+    /// Time between pushes
+    pub interval: Duration,
+    /// Timeout of a single push
+    pub timeout: Duration,
+    /// Histogram bucket bounds by metric name
+    pub buckets: HashMap<String, Vec<f64>>,
+}
+
+impl OtlpConfig {
+    pub fn new(endpoint: impl Into<String>, service_name: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            service_name: service_name.into(),
+            service_version: None,
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            buckets: HashMap::from([(
+                HTTP_CLIENT_REQUEST_DURATION_SECONDS.to_string(),
+                HTTP_CLIENT_LATENCY_BUCKETS.to_vec(),
+            )]),
+        }
+    }
+
+    pub fn with_service_version(mut self, version: impl Into<String>) -> Self {
+        self.service_version = Some(version.into());
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Export histogram `name` with bucket bounds `bounds`
+    pub fn with_buckets(mut self, name: impl Into<String>, bounds: &[f64]) -> Self {
+        self.buckets.insert(name.into(), bounds.to_vec());
+        self
+    }
+
+    fn metrics_url(&self) -> String {
+        format!("{}/v1/metrics", self.endpoint.trim_end_matches('/'))
+    }
+}
+
+/// Accumulated samples of one histogram
+#[derive(Debug, Clone)]
+struct HistogramPoint {
+    bounds: Vec<f64>,
+    /// One count per bound, plus the overflow bucket
+    bucket_counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl HistogramPoint {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            bucket_counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn record(&mut self, sample: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| sample <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.bucket_counts[bucket] += 1;
+        self.count += 1;
+        self.sum += sample;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Descriptions {
+    units: HashMap<String, Unit>,
+    texts: HashMap<String, SharedString>,
+}
+
+struct Inner {
+    config: OtlpConfig,
+    client: reqwest::Client,
+    registry: Registry<Key, AtomicStorage>,
+    descriptions: Mutex<Descriptions>,
+    histograms: Mutex<HashMap<Key, HistogramPoint>>,
+    started_at: OffsetDateTime,
+}
+
+/// Recorder keeping metrics for export over OTLP
+#[derive(Clone)]
+pub struct OtlpRecorder {
+    inner: Arc<Inner>,
+}
+
+impl OtlpRecorder {
+    pub fn new(config: OtlpConfig) -> AppResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AppError::infrastructure("otlp", e.to_string()))?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                config,
+                client,
+                registry: Registry::atomic(),
+                descriptions: Mutex::default(),
+                histograms: Mutex::default(),
+                started_at: OffsetDateTime::now_utc(),
+            }),
+        })
+    }
+
+    pub fn config(&self) -> &OtlpConfig {
+        &self.inner.config
+    }
+
+    /// Push the current value of every metric to the collector
+    pub async fn flush(&self) -> AppResult<()> {
+        let body = self.export_request(OffsetDateTime::now_utc());
+        let response = self
+            .inner
+            .client
+            .post(self.inner.config.metrics_url())
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AppError::infrastructure("otlp", e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(AppError::infrastructure(
+                "otlp",
+                format!("collector answered {}", response.status()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Flush every `interval` until the task is aborted
+    pub fn spawn(&self) -> tokio::task::JoinHandle<()> {
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(recorder.inner.config.interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = recorder.flush().await {
+                    tracing::warn!(error = %e, "OTLP metrics export failed");
+                }
+            }
+        })
+    }
+
+    /// `ExportMetricsServiceRequest` with the metrics as of `now`
+    fn export_request(&self, now: OffsetDateTime) -> Value {
+        let inner = &self.inner;
+        let start = unix_nanos(inner.started_at);
+        let now = unix_nanos(now);
+        let mut metrics: HashMap<String, (&'static str, Vec<Value>)> = HashMap::new();
+
+        inner.registry.visit_counters(|key, counter| {
+            let value = counter.load(std::sync::atomic::Ordering::Acquire);
+            metrics
+                .entry(key.name().to_string())
+                .or_insert(("sum", Vec::new()))
+                .1
+                .push(json!({
+                    "attributes": attributes(key),
+                    "startTimeUnixNano": start,
+                    "timeUnixNano": now,
+                    "asInt": value.to_string(),
+                }));
+        });
+        inner.registry.visit_gauges(|key, gauge| {
+            let value = f64::from_bits(gauge.load(std::sync::atomic::Ordering::Acquire));
+            metrics
+                .entry(key.name().to_string())
+                .or_insert(("gauge", Vec::new()))
+                .1
+                .push(json!({
+                    "attributes": attributes(key),
+                    "timeUnixNano": now,
+                    "asDouble": value,
+                }));
+        });
+
+        let mut histograms = inner.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        inner.registry.visit_histograms(|key, bucket| {
+            let point = histograms.entry(key.clone()).or_insert_with(|| {
+                HistogramPoint::new(
+                    inner
+                        .config
+                        .buckets
+                        .get(key.name())
+                        .map(Vec::as_slice)
+                        .unwrap_or(DEFAULT_OTLP_BUCKETS),
+                )
+            });
+            bucket.clear_with(|samples| samples.iter().for_each(|s| point.record(*s)));
+        });
+        for (key, point) in histograms.iter() {
+            let mut data_point = json!({
+                "attributes": attributes(key),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "count": point.count.to_string(),
+                "sum": point.sum,
+                "bucketCounts": point.bucket_counts.iter().map(u64::to_string).collect::<Vec<_>>(),
+                "explicitBounds": point.bounds,
+            });
+            if point.count > 0 {
+                data_point["min"] = json!(point.min);
+                data_point["max"] = json!(point.max);
+            }
+            metrics
+                .entry(key.name().to_string())
+                .or_insert(("histogram", Vec::new()))
+                .1
+                .push(data_point);
+        }
+        drop(histograms);
+
+        let descriptions = inner.descriptions.lock().unwrap_or_else(|e| e.into_inner());
+        let mut metrics: Vec<Value> = metrics
+            .into_iter()
+            .map(|(name, (kind, data_points))| {
+                let data = match kind {
+                    "sum" => json!({
+                        "dataPoints": data_points,
+                        "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                        "isMonotonic": true,
+                    }),
+                    "histogram" => json!({
+                        "dataPoints": data_points,
+                        "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    }),
+                    _ => json!({ "dataPoints": data_points }),
+                };
+                let mut metric = json!({ "name": name });
+                metric[kind] = data;
+                if let Some(text) = descriptions.texts.get(&name) {
+                    metric["description"] = json!(text.as_ref());
+                }
+                if let Some(unit) = descriptions.units.get(&name) {
+                    metric["unit"] = json!(unit.as_canonical_label());
+                }
+                metric
+            })
+            .collect();
+        metrics.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+        let mut resource = vec![string_attribute("service.name", &inner.config.service_name)];
+        if let Some(version) = &inner.config.service_version {
+            resource.push(string_attribute("service.version", version));
+        }
+        json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": resource },
+                "scopeMetrics": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "metrics": metrics,
+                }],
+            }],
+        })
+    }
+
+    fn describe(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        let mut descriptions = self
+            .inner
+            .descriptions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(unit) = unit {
+            descriptions.units.insert(key.as_str().to_string(), unit);
+        }
+        descriptions
+            .texts
+            .insert(key.as_str().to_string(), description);
+    }
+}
+
+/// `AGGREGATION_TEMPORALITY_CUMULATIVE` in the OTLP protocol
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        self.inner
+            .registry
+            .get_or_create_counter(key, |counter| Counter::from_arc(counter.clone()))
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        self.inner
+            .registry
+            .get_or_create_gauge(key, |gauge| Gauge::from_arc(gauge.clone()))
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        self.inner
+            .registry
+            .get_or_create_histogram(key, |histogram| Histogram::from_arc(histogram.clone()))
+    }
+}
+
+/// OTLP encodes 64-bit integers as JSON strings
+fn unix_nanos(at: OffsetDateTime) -> String {
+    at.unix_timestamp_nanos().to_string()
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn attributes(key: &Key) -> Vec<Value> {
+    key.labels()
+        .map(|label| string_attribute(label.key(), label.value()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observability::metrics_recorder_with_otlp;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Collector accepting OTLP pushes, forwarding each request's path and
+    /// body to the returned channel
+    async fn mock_collector() -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head_len, body_len) = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let body_len = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        break (end + 4, body_len);
+                    }
+                };
+                while request.len() < head_len + body_len {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let head = String::from_utf8_lossy(&request[..head_len]).to_string();
+                let path = head
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let body = serde_json::from_slice(&request[head_len..]).unwrap();
+                let _ = tx.send((path, body));
+                let _ = socket
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}",
+                    )
+                    .await;
+            }
+        });
+        (format!("http://{addr}"), rx)
+    }
+
+    fn find_metric<'a>(body: &'a Value, name: &str) -> Option<&'a Value> {
+        body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .as_array()?
+            .iter()
+            .find(|metric| metric["name"] == name)
+    }
+
+    #[tokio::test]
+    async fn test_flush_exports_registered_metrics_over_otlp() {
+        let (endpoint, mut pushes) = mock_collector().await;
+        let (exporter, recorder) =
+            metrics_recorder_with_otlp(OtlpConfig::new(endpoint, "escrow-service")).unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("escrow_funded_total", "currency" => "NGN").increment(3);
+            metrics::histogram!(HTTP_CLIENT_REQUEST_DURATION_SECONDS, "host" => "payments")
+                .record(0.03);
+        });
+        exporter.flush().await.unwrap();
+
+        let (path, body) = pushes.recv().await.unwrap();
+        assert_eq!(path, "/v1/metrics");
+        assert_eq!(
+            body["resourceMetrics"][0]["resource"]["attributes"][0],
+            string_attribute("service.name", "escrow-service")
+        );
+
+        let funded = find_metric(&body, "escrow_funded_total").expect("counter exported");
+        let point = &funded["sum"]["dataPoints"][0];
+        assert_eq!(point["asInt"], "3");
+        assert_eq!(point["attributes"][0], string_attribute("currency", "NGN"));
+        assert_eq!(funded["sum"]["isMonotonic"], true);
+
+        let latency =
+            find_metric(&body, HTTP_CLIENT_REQUEST_DURATION_SECONDS).expect("histogram exported");
+        let point = &latency["histogram"]["dataPoints"][0];
+        assert_eq!(point["count"], "1");
+        assert_eq!(point["explicitBounds"][0], HTTP_CLIENT_LATENCY_BUCKETS[0]);
+        // 0.03s lands in the (0.025, 0.05] bucket
+        assert_eq!(point["bucketCounts"][3], "1");
+
+        // The same metrics are still scraped by Prometheus
+        let rendered = exporter.handle().render();
+        assert!(rendered.contains("escrow_funded_total{currency=\"NGN\"} 3"));
+    }
+}
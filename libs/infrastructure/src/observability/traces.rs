@@ -0,0 +1,143 @@
+//! Trace sampling and export
+//!
+//! [`tracer_provider`] builds the OpenTelemetry tracer provider that
+//! [`init`](super::init) bridges `tracing` spans into.  A new trace is kept
+//! with probability `TRACE_SAMPLE_RATIO`: [`RatioSampler`] decides from the
+//! trace id alone (see [`ObservabilityConfig::should_sample`]), so every
+//! service sampling at the same ratio agrees on a trace.  Spans with a
+//! parent, local or propagated from another service, follow the parent's
+//! decision.  With the `otlp` feature and a collector configured, kept spans
+//! are pushed over OTLP/HTTP to `{endpoint}/v1/traces`.
+
+use opentelemetry::trace::{
+    Link, SamplingDecision, SamplingResult, SpanKind, TraceContextExt, TraceId, TraceState,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider, ShouldSample};
+
+use super::config::{ObservabilityConfig, keeps_trace};
+
+/// Keeps the share `ratio` of new traces, chosen by trace id
+#[derive(Debug, Clone, Copy)]
+pub struct RatioSampler {
+    ratio: f64,
+}
+
+impl RatioSampler {
+    pub fn new(ratio: f64) -> Self {
+        Self { ratio }
+    }
+}
+
+impl ShouldSample for RatioSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        _name: &str,
+        _span_kind: &SpanKind,
+        _attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let decision = if keeps_trace(&trace_id.to_string(), self.ratio) {
+            SamplingDecision::RecordAndSample
+        } else {
+            SamplingDecision::Drop
+        };
+        SamplingResult {
+            decision,
+            attributes: Vec::new(),
+            trace_state: parent_context
+                .map(|cx| cx.span().span_context().trace_state().clone())
+                .unwrap_or_else(TraceState::default),
+        }
+    }
+}
+
+/// Tracer provider sampling as `config` says, exporting to the OTLP
+/// collector when one is configured
+pub fn tracer_provider(
+    config: &ObservabilityConfig,
+) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    let resource = Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .with_attribute(KeyValue::new(
+            "service.version",
+            config.service_version.clone(),
+        ))
+        .build();
+    let builder = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(RatioSampler::new(
+            config.trace_sample_ratio,
+        ))))
+        .with_resource(resource);
+
+    #[cfg(feature = "otlp")]
+    let builder = match config.otlp_endpoint.as_deref() {
+        Some(endpoint) => {
+            use opentelemetry_otlp::{Protocol, WithExportConfig};
+
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpJson)
+                .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+                .build()?;
+            builder.with_batch_exporter(exporter)
+        }
+        None => builder,
+    };
+
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Whether a root span and its child are sampled at `ratio`
+    fn sampled(ratio: f64) -> (bool, bool) {
+        let config = ObservabilityConfig {
+            trace_sample_ratio: ratio,
+            ..ObservabilityConfig::default()
+        };
+        let provider = tracer_provider(&config).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("request");
+            let child = root.in_scope(|| tracing::info_span!("query"));
+            let is_sampled =
+                |span: &tracing::Span| span.context().span().span_context().is_sampled();
+            (is_sampled(&root), is_sampled(&child))
+        })
+    }
+
+    #[test]
+    fn test_provider_samples_at_the_configured_ratio() {
+        assert_eq!(sampled(1.0), (true, true));
+        assert_eq!(sampled(0.0), (false, false));
+    }
+
+    #[test]
+    fn test_ratio_sampler_agrees_with_should_sample() {
+        let config = ObservabilityConfig {
+            trace_sample_ratio: 0.25,
+            ..ObservabilityConfig::default()
+        };
+        let sampler = RatioSampler::new(config.trace_sample_ratio);
+        for i in 1..1_000_u128 {
+            let trace_id = TraceId::from(i * 0x9e37_79b9_7f4a_7c15);
+            let result =
+                sampler.should_sample(None, trace_id, "request", &SpanKind::Server, &[], &[]);
+            assert_eq!(
+                result.decision == SamplingDecision::RecordAndSample,
+                config.should_sample(&trace_id.to_string()),
+            );
+        }
+    }
+}
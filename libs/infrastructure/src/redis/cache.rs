@@ -2,6 +2,31 @@
 //!
 //! Provides a generic cache interface for storing and retrieving serializable data.
 //!
+//! Entries can also be tagged (`user:42`, `catalog`) and dropped as a group
+//! with [`RedisCache::invalidate_tag`], so callers don't have to remember every
+//! cache key derived from an entity.  Each tag is a set of the keys carrying it.
+//!
+//! Oversized values are kept in check with
+//! [`RedisCache::with_max_value_size`], which rejects anything larger, and
+//! [`RedisCache::with_compression_threshold`], which gzips values above the
+//! threshold.  Compressed entries are recognised by the gzip magic bytes
+//! (JSON never starts with them) and decompressed transparently on read.
+//!
+//! [`RedisCache::get_or_fetch`] is the read-through path: concurrent misses
+//! for the same key share a single fetch (see [`SingleFlight`]), so an
+//! expiring hot entry doesn't send every waiting request to the database.
+//!
+//! In cluster mode a value and its tag sets live in different slots, so
+//! tagging and invalidation fall back to per-slot commands instead of one
+//! atomic script.
+//!
+//! Every read is counted as a hit, miss or error.  [`RedisCache::stats`]
+//! returns the running totals for one cache (shared by its clones), and with
+//! the `metrics` feature each read also increments
+//! `cache_requests_total{namespace, result}` on the installed recorder, so the
+//! hit ratio per prefix shows up on the
+//! [`MetricsExporter`](crate::observability::MetricsExporter) endpoint.
+//!
 //! ## Feature Flags
 //!
 //! - `redis`: Enables Redis support (enabled by default with `full` feature)
@@ -12,13 +37,36 @@ use async_trait::async_trait;
 #[cfg(feature = "redis")]
 use serde::{Serialize, de::DeserializeOwned};
 
+#[cfg(feature = "redis")]
+use std::borrow::Cow;
+#[cfg(feature = "redis")]
+use std::future::Future;
+#[cfg(feature = "redis")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "redis")]
+use std::sync::Arc;
+
+#[cfg(feature = "redis")]
+use std::sync::atomic::{AtomicU64, Ordering};
 #[cfg(feature = "redis")]
 use std::time::Duration;
 
 #[cfg(feature = "redis")]
-use super::{RedisError, RedisPool};
+use error::AppError;
+#[cfg(feature = "redis")]
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+#[cfg(feature = "redis")]
+use redis::AsyncCommands;
+#[cfg(feature = "redis")]
+use tracing::warn;
+
+#[cfg(feature = "redis")]
+use super::{RedisError, RedisPool, pool::RedisConnection};
 #[cfg(feature = "redis")]
 use crate::redis::key::RedisKey;
+#[cfg(feature = "redis")]
+use crate::resilience::SingleFlight;
 
 /// Cache trait for generic caching operations
 #[cfg(feature = "redis")]
@@ -27,7 +75,7 @@ pub trait Cache: Send + Sync {
     /// Get a value from cache
     async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, RedisError>;
     /// Set a value in cache with TTL
-    async fn set<T: Serialize>(
+    async fn set<T: Serialize + Sync>(
         &self,
         key: &str,
         value: &T,
@@ -50,12 +98,122 @@ pub trait Cache: Send + Sync {
     async fn delete_many(&self, keys: &[&str]) -> Result<u64, RedisError>;
 }
 
+/// Writes the value and records its key in every tag set.  Tag sets live at
+/// least as long as their longest-lived member so invalidation never misses
+/// a key that is still cached.
+#[cfg(feature = "redis")]
+const SET_TAGGED_SCRIPT: &str = r#"
+local ttl = tonumber(ARGV[2])
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ttl)
+for i = 2, #KEYS do
+    redis.call('SADD', KEYS[i], KEYS[1])
+    if redis.call('TTL', KEYS[i]) < ttl then
+        redis.call('EXPIRE', KEYS[i], ttl)
+    end
+end
+"#;
+
+/// Cluster-mode counterpart of [`SET_TAGGED_SCRIPT`] for one tag set, which
+/// usually lives in a different slot from the value
+#[cfg(feature = "redis")]
+const ADD_TO_TAG_SCRIPT: &str = r#"
+local ttl = tonumber(ARGV[2])
+redis.call('SADD', KEYS[1], ARGV[1])
+if redis.call('TTL', KEYS[1]) < ttl then
+    redis.call('EXPIRE', KEYS[1], ttl)
+end
+"#;
+
+/// Deletes every key in the tag set and the set itself, returning the number
+/// of keys that were tagged
+#[cfg(feature = "redis")]
+const INVALIDATE_TAG_SCRIPT: &str = r#"
+local keys = redis.call('SMEMBERS', KEYS[1])
+for i = 1, #keys, 500 do
+    redis.call('DEL', unpack(keys, i, math.min(i + 499, #keys)))
+end
+redis.call('DEL', KEYS[1])
+return #keys
+"#;
+
+/// Leading bytes of every gzip stream
+#[cfg(feature = "redis")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Result of a single cache read
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheOutcome {
+    Hit,
+    Miss,
+    Error,
+}
+
+#[cfg(feature = "redis")]
+impl CacheOutcome {
+    fn of<T>(result: &Result<Option<T>, RedisError>) -> Self {
+        match result {
+            Ok(Some(_)) => Self::Hit,
+            Ok(None) => Self::Miss,
+            Err(_) => Self::Error,
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hit => "hit",
+            Self::Miss => "miss",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// Running read totals shared by every clone of a cache
+#[cfg(feature = "redis")]
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Snapshot of a cache's read outcomes
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub errors: u64,
+}
+
+#[cfg(feature = "redis")]
+impl CacheStats {
+    /// Total reads, including failed ones
+    pub fn requests(&self) -> u64 {
+        self.hits + self.misses + self.errors
+    }
+
+    /// Share of reads served from the cache, `None` before the first read
+    pub fn hit_ratio(&self) -> Option<f64> {
+        match self.requests() {
+            0 => None,
+            total => Some(self.hits as f64 / total as f64),
+        }
+    }
+}
+
 /// Redis cache implementation
 #[cfg(feature = "redis")]
 #[derive(Clone)]
 pub struct RedisCache {
     pool: RedisPool,
     prefix: String,
+    max_value_size: Option<usize>,
+    compression_threshold: Option<usize>,
+    /// Serialized results of in-flight read-through fetches, by key
+    fetches: SingleFlight<String, Result<Vec<u8>, AppError>>,
+    counters: Arc<CacheCounters>,
 }
 
 #[cfg(feature = "redis")]
@@ -65,9 +223,25 @@ impl RedisCache {
         Self {
             pool,
             prefix: prefix.into(),
+            max_value_size: None,
+            compression_threshold: None,
+            fetches: SingleFlight::new(),
+            counters: Arc::default(),
         }
     }
 
+    /// Reject values larger than `bytes` once encoded (after compression)
+    pub fn with_max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = Some(bytes);
+        self
+    }
+
+    /// Gzip serialized values larger than `bytes`
+    pub fn with_compression_threshold(mut self, bytes: usize) -> Self {
+        self.compression_threshold = Some(bytes);
+        self
+    }
+
     /// Access the prefix that was supplied at construction.
     ///
     /// Other components (e.g. OTP) can use this in combination with
@@ -77,43 +251,251 @@ impl RedisCache {
         &self.prefix
     }
 
+    /// Hits, misses and errors recorded by this cache so far
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            errors: self.counters.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count one read in the local totals and, with the `metrics` feature, in
+    /// `cache_requests_total` labelled by this cache's prefix
+    fn record(&self, outcome: CacheOutcome) {
+        let counter = match outcome {
+            CacheOutcome::Hit => &self.counters.hits,
+            CacheOutcome::Miss => &self.counters.misses,
+            CacheOutcome::Error => &self.counters.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "metrics")]
+        metrics::counter!(
+            "cache_requests_total",
+            "namespace" => self.prefix.clone(),
+            "result" => outcome.as_str()
+        )
+        .increment(1);
+    }
+
     /// Get prefixed key using `RedisKey` builder
     fn key(&self, key: &str) -> RedisKey {
         RedisKey::cache(&self.prefix, key)
     }
+
+    fn tag_key(&self, tag: &str) -> RedisKey {
+        RedisKey::cache_tag(&self.prefix, tag)
+    }
+
+    /// Serialize `value` into the bytes stored under `key`, compressing and
+    /// enforcing the size limit as configured
+    fn encode<T: Serialize>(&self, key: &str, value: &T) -> Result<Vec<u8>, RedisError> {
+        let mut data = serde_json::to_vec(value)
+            .map_err(|e| RedisError::Command(format!("value not serializable: {e}")))?;
+
+        if self
+            .compression_threshold
+            .is_some_and(|threshold| data.len() > threshold)
+        {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&data)
+                .and_then(|_| encoder.finish())
+                .map(|compressed| data = compressed)
+                .map_err(|e| RedisError::Command(format!("failed to compress value: {e}")))?;
+        }
+
+        if let Some(limit) = self.max_value_size
+            && data.len() > limit
+        {
+            warn!(
+                key,
+                size = data.len(),
+                limit,
+                "rejecting oversized cache value"
+            );
+            return Err(RedisError::ValueTooLarge {
+                key: key.to_string(),
+                size: data.len(),
+                limit,
+            });
+        }
+        Ok(data)
+    }
+
+    /// Inverse of [`Self::encode`]
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, RedisError> {
+        let json = if data.starts_with(&GZIP_MAGIC) {
+            let mut json = Vec::new();
+            GzDecoder::new(data)
+                .read_to_end(&mut json)
+                .map_err(|e| RedisError::Command(format!("invalid compressed value: {e}")))?;
+            Cow::Owned(json)
+        } else {
+            Cow::Borrowed(data)
+        };
+        serde_json::from_slice(&json)
+            .map_err(|e| RedisError::Command(format!("invalid cached value: {e}")))
+    }
+
+    /// Set a value with TTL and associate it with `tags`
+    pub async fn set_tagged<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Duration,
+        tags: &[&str],
+    ) -> Result<(), RedisError> {
+        let data = self.encode(key, value)?;
+        if self.pool.is_cluster() {
+            return self.set_tagged_cluster(key, data, ttl, tags).await;
+        }
+
+        let script = redis::Script::new(SET_TAGGED_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation.key(self.key(key).as_str());
+        for tag in tags {
+            invocation.key(self.tag_key(tag).as_str());
+        }
+        invocation.arg(data).arg(ttl.as_secs().max(1));
+
+        let mut conn = self.pool.connection().await?;
+        invocation.invoke_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Tag sets and values hash to different slots, so cluster mode can't
+    /// write them in one script.  Tags are recorded before the value so an
+    /// interrupted write never leaves a cached value invalidation can't see.
+    async fn set_tagged_cluster(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        ttl: Duration,
+        tags: &[&str],
+    ) -> Result<(), RedisError> {
+        let cache_key = self.key(key);
+        let ttl = ttl.as_secs().max(1);
+        let mut conn = self.pool.connection().await?;
+        for tag in tags {
+            redis::Script::new(ADD_TO_TAG_SCRIPT)
+                .key(self.tag_key(tag).as_str())
+                .arg(cache_key.as_str())
+                .arg(ttl)
+                .invoke_async::<()>(&mut conn)
+                .await?;
+        }
+        redis::cmd("SET")
+            .arg(cache_key.as_str())
+            .arg(data)
+            .arg("EX")
+            .arg(ttl)
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Read `key`, running `fetch` and caching its result on a miss
+    ///
+    /// Concurrent misses for the same key await one shared `fetch`.  Cache
+    /// failures are logged and fall through to `fetch`, so Redis trouble
+    /// degrades reads instead of failing them.
+    pub async fn get_or_fetch<T, F, Fut>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        fetch: F,
+    ) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned + Sync,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        match self.get(key).await {
+            Ok(Some(hit)) => return Ok(hit),
+            Ok(None) => {}
+            Err(e) => {
+                warn!(key, error = %e, "cache read failed, fetching from source");
+                #[cfg(feature = "metrics")]
+                crate::observability::record_degradation("cache", "read_failed");
+            }
+        }
+
+        let data = self
+            .fetches
+            .run(key.to_string(), || async {
+                let value = fetch().await?;
+                if let Err(e) = self.set(key, &value, ttl).await {
+                    warn!(key, error = %e, "failed to cache fetched value");
+                }
+                serde_json::to_vec(&value)
+                    .map_err(|e| AppError::internal(format!("value not serializable: {e}")))
+            })
+            .await?;
+        Ok(Self::decode(&data)?)
+    }
+
+    /// Delete every entry carrying `tag`, returning how many were tagged
+    pub async fn invalidate_tag(&self, tag: &str) -> Result<u64, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        if self.pool.is_cluster() {
+            return self.invalidate_tag_cluster(&mut conn, tag).await;
+        }
+        let invalidated: u64 = redis::Script::new(INVALIDATE_TAG_SCRIPT)
+            .key(self.tag_key(tag).as_str())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(invalidated)
+    }
+
+    /// Tagged keys span many slots, so cluster mode deletes them with `DEL`
+    /// (which the cluster client splits per slot) instead of a script.  Only
+    /// the members read are removed from the set, keeping keys tagged
+    /// concurrently.
+    async fn invalidate_tag_cluster(
+        &self,
+        conn: &mut RedisConnection,
+        tag: &str,
+    ) -> Result<u64, RedisError> {
+        let tag_key = self.tag_key(tag);
+        let members: Vec<String> = conn.smembers(tag_key.as_str()).await?;
+        for chunk in members.chunks(500) {
+            let _: u64 = conn.del(chunk).await?;
+            let _: u64 = conn.srem(tag_key.as_str(), chunk).await?;
+        }
+        Ok(members.len() as u64)
+    }
 }
 
 #[cfg(feature = "redis")]
 #[async_trait]
 impl Cache for RedisCache {
     async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let result = async {
+            let mut conn = self.pool.connection().await?;
 
-        let data: Option<String> = redis::cmd("GET")
-            .arg(self.key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            let data: Option<Vec<u8>> = redis::cmd("GET")
+                .arg(self.key(key).as_str())
+                .query_async(&mut conn)
+                .await?;
 
-        match data {
-            Some(json) => {
-                let value = serde_json::from_str(&json)
-                    .map_err(|e| RedisError::deserialization("JSON", e.to_string()))?;
-                Ok(Some(value))
-            }
-            None => Ok(None),
+            data.map(|bytes| Self::decode(&bytes)).transpose()
         }
+        .await;
+
+        self.record(CacheOutcome::of(&result));
+        result
     }
 
-    async fn set<T: Serialize>(
+    async fn set<T: Serialize + Sync>(
         &self,
         key: &str,
         value: &T,
         ttl: Duration,
     ) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let data = serde_json::to_string(value)
-            .map_err(|e| RedisError::serialization("JSON", e.to_string()))?;
+        let data = self.encode(key, value)?;
+        let mut conn = self.pool.connection().await?;
 
         let mut cmd = redis::cmd("SET");
         cmd.arg(self.key(key).as_str())
@@ -121,45 +503,40 @@ impl Cache for RedisCache {
             .arg("EX")
             .arg(ttl.as_secs());
 
-        cmd.query_async::<_, String>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        cmd.query_async::<String>(&mut conn).await?;
 
         Ok(())
     }
 
     async fn delete(&self, key: &str) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         redis::cmd("DEL")
             .arg(self.key(key).as_str())
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn exists(&self, key: &str) -> Result<bool, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let result: u64 = redis::cmd("EXISTS")
             .arg(self.key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         Ok(result > 0)
     }
 
     async fn ttl(&self, key: &str) -> Result<Option<i64>, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let ttl: i64 = redis::cmd("TTL")
             .arg(self.key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         if ttl == -2 {
             Ok(None) // Key doesn't exist
@@ -169,14 +546,13 @@ impl Cache for RedisCache {
     }
 
     async fn increment(&self, key: &str, amount: i64) -> Result<i64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let result: i64 = redis::cmd("INCRBY")
             .arg(self.key(key).as_str())
             .arg(amount)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         Ok(result)
     }
@@ -185,31 +561,36 @@ impl Cache for RedisCache {
         &self,
         keys: &[&str],
     ) -> Result<Vec<Option<T>>, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let data = async {
+            let mut conn = self.pool.connection().await?;
 
-        let mut cmd = redis::cmd("MGET");
-        for key in keys {
-            cmd.arg(self.key(key).as_str());
+            let mut cmd = redis::cmd("MGET");
+            for key in keys {
+                cmd.arg(self.key(key).as_str());
+            }
+
+            let data: Vec<Option<Vec<u8>>> = cmd.query_async(&mut conn).await?;
+            Ok::<_, RedisError>(data)
         }
+        .await;
 
-        let data: Vec<Option<String>> = cmd
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
-
-        let mut results = Vec::new();
-        for item in data {
-            match item {
-                Some(json) => {
-                    let value = serde_json::from_str(&json)
-                        .map_err(|e| RedisError::deserialization("JSON", e.to_string()))?;
-                    results.push(Some(value));
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                for _ in keys {
+                    self.record(CacheOutcome::Error);
                 }
-                None => results.push(None),
+                return Err(e);
             }
-        }
-
-        Ok(results)
+        };
+
+        data.into_iter()
+            .map(|item| {
+                let value = item.map(|bytes| Self::decode(&bytes)).transpose();
+                self.record(CacheOutcome::of(&value));
+                value
+            })
+            .collect()
     }
 
     async fn delete_many(&self, keys: &[&str]) -> Result<u64, RedisError> {
@@ -217,18 +598,176 @@ impl Cache for RedisCache {
             return Ok(0);
         }
 
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let mut cmd = redis::cmd("DEL");
         for key in keys {
             cmd.arg(self.key(key).as_str());
         }
 
-        let deleted: u64 = cmd
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        let deleted: u64 = cmd.query_async(&mut conn).await?;
 
         Ok(deleted)
     }
 }
+
+#[cfg(all(test, feature = "redis"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_keys_are_namespaced() {
+        let pool = RedisPool::lazy("redis://localhost:6379").unwrap();
+        let cache = RedisCache::new(pool, "app");
+        assert_eq!(cache.key("profile:42").as_str(), "app:cache:profile:42");
+        assert_eq!(cache.tag_key("user:42").as_str(), "app:cache_tag:user:42");
+    }
+
+    #[test]
+    fn test_value_over_threshold_is_compressed_and_round_trips() {
+        let pool = RedisPool::lazy("redis://localhost:6379").unwrap();
+        let cache = RedisCache::new(pool, "app").with_compression_threshold(256);
+        let catalog: Vec<String> = (0..200).map(|i| format!("product-{i}")).collect();
+
+        let stored = cache.encode("catalog", &catalog).unwrap();
+        assert!(stored.starts_with(&GZIP_MAGIC));
+        assert!(stored.len() < serde_json::to_vec(&catalog).unwrap().len());
+        assert_eq!(RedisCache::decode::<Vec<String>>(&stored).unwrap(), catalog);
+
+        let small = cache.encode("name", &"trustflow").unwrap();
+        assert_eq!(small, b"\"trustflow\"");
+        assert_eq!(RedisCache::decode::<String>(&small).unwrap(), "trustflow");
+    }
+
+    #[test]
+    fn test_value_over_max_size_is_rejected() {
+        let pool = RedisPool::lazy("redis://localhost:6379").unwrap();
+        let cache = RedisCache::new(pool, "app").with_max_value_size(16);
+
+        let err = cache.encode("blob", &"x".repeat(64)).unwrap_err();
+        assert!(matches!(
+            err,
+            RedisError::ValueTooLarge {
+                size: 66,
+                limit: 16,
+                ..
+            }
+        ));
+        assert!(cache.encode("blob", &"small").is_ok());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_hit_and_miss_increment_their_counters() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let namespace = format!("catalog_{}", std::process::id());
+        let cache = RedisCache::new(RedisPool::new(&url).await.unwrap(), &namespace);
+        let recorder = PrometheusBuilder::new().build_recorder();
+        // The test runtime polls everything on this thread
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        cache
+            .set("item:42", &"Vintage camera", Duration::from_secs(60))
+            .await
+            .unwrap();
+        let hit: Option<String> = cache.get("item:42").await.unwrap();
+        assert_eq!(hit.as_deref(), Some("Vintage camera"));
+        assert_eq!(cache.get::<String>("item:43").await.unwrap(), None);
+        let items: Vec<Option<String>> = cache
+            .get_many(&["item:42", "item:43", "item:44"])
+            .await
+            .unwrap();
+        assert_eq!(items, [Some("Vintage camera".to_string()), None, None]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.errors), (2, 3, 0));
+        assert_eq!(stats.hit_ratio(), Some(2.0 / 5.0));
+
+        let rendered = recorder.handle().render();
+        let line = |result, count| {
+            format!(r#"cache_requests_total{{namespace="{namespace}",result="{result}"}} {count}"#)
+        };
+        assert!(rendered.contains(&line("hit", 2)), "{rendered}");
+        assert!(rendered.contains(&line("miss", 3)), "{rendered}");
+
+        cache.delete("item:42").await.unwrap();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_failed_reads_increment_the_error_counter() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        // Nothing listens on port 1
+        let cache = RedisCache::new(RedisPool::lazy("redis://127.0.0.1:1").unwrap(), "catalog");
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        assert!(cache.get::<String>("item:42").await.is_err());
+        assert!(cache.get_many::<String>(&["item:42", "item:43"]).await.is_err());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.errors), (0, 0, 3));
+        assert_eq!(stats.hit_ratio(), Some(0.0));
+        assert!(
+            recorder
+                .handle()
+                .render()
+                .contains(r#"cache_requests_total{namespace="catalog",result="error"} 3"#)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_invalidate_tag_removes_all_tagged_keys() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let cache = RedisCache::new(RedisPool::new(&url).await.unwrap(), "test");
+        let tag = format!("user:{}", std::process::id());
+        let ttl = Duration::from_secs(60);
+
+        cache
+            .set_tagged("profile:full", &"full", ttl, &[&tag])
+            .await
+            .unwrap();
+        cache
+            .set_tagged("profile:summary", &"summary", ttl, &[&tag, "profiles"])
+            .await
+            .unwrap();
+        cache.set("unrelated", &"kept", ttl).await.unwrap();
+
+        assert_eq!(cache.invalidate_tag(&tag).await.unwrap(), 2);
+        assert!(!cache.exists("profile:full").await.unwrap());
+        assert!(!cache.exists("profile:summary").await.unwrap());
+        assert!(cache.exists("unrelated").await.unwrap());
+        assert_eq!(cache.invalidate_tag(&tag).await.unwrap(), 0);
+
+        cache.delete("unrelated").await.unwrap();
+        cache.invalidate_tag("profiles").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch_coalesces_concurrent_misses() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Nothing listens on port 1: every cache call fails and reads fall
+        // through to the fetch, which must still run only once
+        let cache = RedisCache::new(RedisPool::lazy("redis://127.0.0.1:1").unwrap(), "app");
+        let fetches = AtomicUsize::new(0);
+
+        let reads = (0..20).map(|_| {
+            cache.get_or_fetch("catalog:item:42", Duration::from_secs(60), || async {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok("Vintage camera".to_string())
+            })
+        });
+        for result in futures_util::future::join_all(reads).await {
+            assert_eq!(result.unwrap(), "Vintage camera");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.stats().errors, 20);
+    }
+}
@@ -6,67 +6,107 @@
 //!
 //! This design avoids premature microservice complexity while remaining
 //! future-ready for extraction into separate services if needed.
+//!
+//! Staging and production default to requiring a password and TLS
+//! (`rediss://`) on every Redis URL; `REDIS_REQUIRE_AUTH` and
+//! `REDIS_REQUIRE_TLS` override that either way.  `from_loader` validates
+//! the section against the loader's `APP_ENV`.
 
+use config::Config;
+use config::core::duration::{ConfigDuration, Days, Millis, Minutes};
+use config::core::environment::Environment;
 use config::core::error::{ConfigError, ConfigResult};
-use config::loader::ConfigLoader;
 use serde::{Deserialize, Serialize};
 use time::Duration;
 use url::Url;
 
+/// Most connections a pool may be configured with, Redis' default
+/// `maxclients`
+const MAX_POOL_CONNECTIONS: u32 = 10_000;
+
 /// Redis configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Config)]
+#[config(validate = RedisConfig::validate_for)]
 pub struct RedisConfig {
     /// Redis connection URL
+    #[config(key = "REDIS_URL", default = "redis://localhost:6379")]
     pub url: String,
+    /// Connect to a Redis Cluster instead of a single node
+    #[config(key = "REDIS_CLUSTER", default = false)]
+    pub cluster: bool,
+    /// Comma-separated cluster seed node URLs; `url` is used when empty
+    #[config(key = "REDIS_CLUSTER_NODES", default = "", with = split_nodes)]
+    pub cluster_nodes: Vec<String>,
     /// Key prefix for all Redis keys
+    #[config(key = "REDIS_KEY_PREFIX", default = "app")]
     pub key_prefix: String,
     /// Maximum number of connections in the pool
+    #[config(key = "REDIS_MAX_CONNECTIONS", default = 50)]
     pub max_connections: u32,
     /// Connection timeout
+    #[config(key = "REDIS_CONNECTION_TIMEOUT", default = "10s", with = ConfigDuration::into_time)]
     pub connection_timeout: Duration,
     /// Command timeout
+    #[config(key = "REDIS_COMMAND_TIMEOUT", default = "5s", with = ConfigDuration::into_time)]
     pub command_timeout: Duration,
     /// Connection retry delay
+    #[config(key = "REDIS_RETRY_DELAY", default = "100ms", with = Millis::into_time)]
     pub retry_delay: Duration,
+    /// Refuse Redis URLs without a password
+    #[config(key = "REDIS_REQUIRE_AUTH", default_for = secure_by_default)]
+    pub require_auth: bool,
+    /// Refuse Redis URLs not using TLS (`rediss://`)
+    #[config(key = "REDIS_REQUIRE_TLS", default_for = secure_by_default)]
+    pub require_tls: bool,
     /// Domain-specific settings
+    #[config(nested)]
     pub domains: RedisDomainsConfig,
 }
 
 /// Domain-specific Redis configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Config)]
 pub struct RedisDomainsConfig {
+    #[config(nested)]
     pub session: SessionConfig,
+    #[config(nested)]
     pub rate_limit: RateLimitConfig,
+    #[config(nested)]
     pub cache: CacheConfig,
 }
 
 /// Session behavior configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Config)]
 pub struct SessionConfig {
     /// Default session TTL
+    #[config(key = "SESSION_TTL_DAYS", default = "7d", with = Days::into_time)]
     pub ttl: Duration,
 
     /// Maximum active sessions per user
+    #[config(key = "MAX_SESSIONS_PER_USER", default = 5)]
     pub max_sessions_per_user: u32,
 
     /// Refresh threshold (percentage of TTL)
+    #[config(key = "SESSION_REFRESH_THRESHOLD", default = 0.8)]
     pub refresh_threshold: f32,
 }
 
 /// Rate limiting behavior configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Config)]
 pub struct RateLimitConfig {
     /// Default window duration
+    #[config(key = "RATE_LIMIT_WINDOW_MINUTES", default = "15m", with = Minutes::into_time)]
     pub window: Duration,
 
     /// Default requests allowed per window
+    #[config(key = "RATE_LIMIT_DEFAULT_LIMIT", default = 100)]
     pub default_limit: u64,
 }
 
 /// Cache behavior configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Config)]
 pub struct CacheConfig {
     /// Default cache TTL
+    #[config(key = "CACHE_TTL_MINUTES", default = "10m", with = Minutes::into_time)]
     pub ttl: Duration,
 }
 
@@ -74,11 +114,15 @@ impl Default for RedisConfig {
     fn default() -> Self {
         Self {
             url: "redis://localhost:6379".to_string(),
+            cluster: false,
+            cluster_nodes: Vec::new(),
             key_prefix: "app".to_string(),
             max_connections: 50,
             connection_timeout: Duration::seconds(10),
             command_timeout: Duration::seconds(5),
             retry_delay: Duration::milliseconds(100),
+            require_auth: false,
+            require_tls: false,
             domains: RedisDomainsConfig::default(),
         }
     }
@@ -121,45 +165,18 @@ impl Default for CacheConfig {
     }
 }
 impl RedisConfig {
-    /// Create configuration from a ConfigLoader
-    ///
-    /// This is the primary way to create a RedisConfig using the config crate's
-    /// configuration loading system. It supports loading from:
-    /// - Shared .env file (libs/infrastructure/.env)
-    /// - Service-specific .env file
-    pub fn from_loader(loader: &ConfigLoader) -> ConfigResult<Self> {
-        Ok(Self {
-            url: loader.get_or("REDIS_URL", "redis://localhost:6379".to_string())?,
-            key_prefix: loader.get_or("REDIS_KEY_PREFIX", "app".to_string())?,
-            max_connections: loader.get_or("REDIS_MAX_CONNECTIONS", 50u32)?,
-            connection_timeout: Duration::seconds(
-                loader.get_or("REDIS_CONNECTION_TIMEOUT", 10i64)?,
-            ),
-            command_timeout: Duration::seconds(loader.get_or("REDIS_COMMAND_TIMEOUT", 5i64)?),
-            retry_delay: Duration::milliseconds(loader.get_or("REDIS_RETRY_DELAY", 100i64)?),
-            domains: RedisDomainsConfig {
-                session: SessionConfig {
-                    ttl: Duration::days(loader.get_or("SESSION_TTL_DAYS", 7i64)?),
-                    max_sessions_per_user: loader.get_or("MAX_SESSIONS_PER_USER", 5u32)?,
-                    refresh_threshold: loader.get_or("SESSION_REFRESH_THRESHOLD", 0.8f32)?,
-                },
-                rate_limit: RateLimitConfig {
-                    window: Duration::minutes(loader.get_or("RATE_LIMIT_WINDOW_MINUTES", 15i64)?),
-                    default_limit: loader.get_or("RATE_LIMIT_DEFAULT_LIMIT", 100u64)?,
-                },
-                cache: CacheConfig {
-                    ttl: Duration::minutes(loader.get_or("CACHE_TTL_MINUTES", 10i64)?),
-                },
-            },
-        })
-    }
-
     /// Validate configuration invariants
     pub fn validate(&self) -> ConfigResult<()> {
         if self.url.trim().is_empty() {
             return Err(ConfigError::validation("REDIS_URL cannot be empty"));
         }
 
+        if self.max_connections == 0 || self.max_connections > MAX_POOL_CONNECTIONS {
+            return Err(ConfigError::validation(format!(
+                "REDIS_MAX_CONNECTIONS must be between 1 and {MAX_POOL_CONNECTIONS}"
+            )));
+        }
+
         if self.domains.session.refresh_threshold <= 0.0
             || self.domains.session.refresh_threshold > 1.0
         {
@@ -171,6 +188,48 @@ impl RedisConfig {
         Ok(())
     }
 
+    /// Validate for `environment`, enforcing `require_auth` and
+    /// `require_tls` on every URL that will be connected to
+    pub fn validate_for(&self, environment: &Environment) -> ConfigResult<()> {
+        self.validate()?;
+
+        let urls = if self.cluster {
+            self.cluster_seeds()
+        } else {
+            vec![self.url.clone()]
+        };
+        for url in &urls {
+            let parsed = Url::parse(url).map_err(|_| {
+                ConfigError::validation(format!("Invalid Redis URL '{}'", redact_url(url)))
+            })?;
+            if self.require_auth && parsed.password().is_none_or(str::is_empty) {
+                return Err(ConfigError::validation(format!(
+                    "Redis URL '{}' has no password, which {environment} requires \
+                     (REDIS_REQUIRE_AUTH)",
+                    redact_url(url)
+                )));
+            }
+            if self.require_tls && parsed.scheme() != "rediss" {
+                return Err(ConfigError::validation(format!(
+                    "Redis URL '{}' doesn't use TLS (rediss://), which {environment} requires \
+                     (REDIS_REQUIRE_TLS)",
+                    redact_url(url)
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Seed nodes for cluster mode
+    pub fn cluster_seeds(&self) -> Vec<String> {
+        if self.cluster_nodes.is_empty() {
+            vec![self.url.clone()]
+        } else {
+            self.cluster_nodes.clone()
+        }
+    }
+
     /// Build a fully namespaced Redis key
     pub fn key(&self, domain: &str, key: &str) -> String {
         format!("{}:{}:{}", self.key_prefix, domain, key)
@@ -197,34 +256,97 @@ impl RedisConfig {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//
-//     #[test]
-//     fn test_redis_config_defaults() {
-//         let config = RedisConfig::default();
-//         assert!(!config.url.is_empty());
-//         assert!(!config.key_prefix.is_empty());
-//     }
-//
-//     #[test]
-//     fn test_redis_config_url_parsing() {
-//         let config = RedisConfig {
-//             url: "redis://localhost:6379".to_string(),
-//             ..Default::default()
-//         };
-//
-//         assert_eq!(config.host(), Some("localhost"));
-//         assert_eq!(config.port(), Some(6379));
-//     }
-//
-//     #[test]
-//     fn test_redis_config_validation() {
-//         let mut config = RedisConfig::default();
-//         assert!(config.validate().is_ok());
-//
-//         config.url = "".to_string();
-//         assert!(config.validate().is_err());
-//     }
-// }
+/// Whether `environment` requires Redis auth and TLS unless configured
+/// otherwise
+fn secure_by_default(environment: &Environment) -> bool {
+    !environment.allows_debug()
+}
+
+/// `url` with any password masked, for error messages
+fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("****"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+fn split_nodes(nodes: String) -> Vec<String> {
+    nodes
+        .split(',')
+        .map(str::trim)
+        .filter(|node| !node.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::loader::ConfigLoader;
+    use config::sources::dotenv::DotenvLayerBuilder;
+
+    fn loader(vars: &[(&str, &str)]) -> ConfigLoader {
+        let env = vars
+            .iter()
+            .fold(DotenvLayerBuilder::new(), |builder, (key, value)| {
+                builder.with_override(*key, *value)
+            })
+            .build();
+        ConfigLoader::new().with_service_env(env)
+    }
+
+    #[test]
+    fn test_redis_config_url_parsing() {
+        let config = RedisConfig {
+            url: "redis://localhost:6379".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.host().as_deref(), Some("localhost"));
+        assert_eq!(config.port(), Some(6379));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_production_requires_password_and_tls() {
+        let development = RedisConfig::from_loader(&loader(&[
+            ("APP_ENV", "development"),
+            ("REDIS_URL", "redis://localhost:6379"),
+        ]))
+        .unwrap();
+        assert!(!development.require_auth && !development.require_tls);
+
+        let err = RedisConfig::from_loader(&loader(&[
+            ("APP_ENV", "production"),
+            ("REDIS_URL", "rediss://redis.internal:6380"),
+        ]))
+        .unwrap_err();
+        assert!(err.is_validation());
+        assert!(err.to_string().contains("no password"), "{err}");
+
+        let err = RedisConfig::from_loader(&loader(&[
+            ("APP_ENV", "production"),
+            ("REDIS_URL", "redis://:s3cret@redis.internal:6379"),
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("TLS"), "{err}");
+        assert!(!err.to_string().contains("s3cret"), "{err}");
+
+        let production = RedisConfig::from_loader(&loader(&[
+            ("APP_ENV", "production"),
+            ("REDIS_URL", "rediss://:s3cret@redis.internal:6380"),
+        ]))
+        .unwrap();
+        assert!(production.require_auth && production.is_tls());
+
+        let err = RedisConfig::from_loader(&loader(&[("REDIS_MAX_CONNECTIONS", "0")]));
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("REDIS_MAX_CONNECTIONS")
+        );
+    }
+}
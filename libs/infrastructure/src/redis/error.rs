@@ -1,5 +1,6 @@
 //! Redis error types.
 
+use error::AppError;
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -12,6 +13,13 @@ pub enum RedisError {
 
     #[error("redis configuration error: {0}")]
     Configuration(String),
+
+    #[error("redis value for '{key}' is {size} bytes, over the {limit} byte limit")]
+    ValueTooLarge {
+        key: String,
+        size: usize,
+        limit: usize,
+    },
 }
 
 impl From<redis::RedisError> for RedisError {
@@ -19,3 +27,9 @@ impl From<redis::RedisError> for RedisError {
         Self::Command(value.to_string())
     }
 }
+
+impl From<RedisError> for AppError {
+    fn from(value: RedisError) -> Self {
+        AppError::infrastructure("redis", value.to_string())
+    }
+}
@@ -0,0 +1,284 @@
+//! Replayable event history backed by Redis Streams
+//!
+//! Pub/sub is fire-and-forget: a consumer that is disconnected misses every
+//! event published in the meantime.  [`EventLog`] appends each event to a
+//! stream (`XADD`) instead, so consumers can remember the id of the last
+//! event they handled and resume from it (`XRANGE`) after a restart.
+//!
+//! Consumer groups (`XREADGROUP`/`XACK`) are exposed for workers that share
+//! the load of a stream and need at-least-once delivery.
+
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use redis::streams::{StreamId, StreamRangeReply, StreamReadOptions, StreamReadReply};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use super::{RedisError, RedisPool};
+use crate::redis::key::RedisKey;
+
+const TYPE_FIELD: &str = "type";
+const PAYLOAD_FIELD: &str = "payload";
+
+/// Cursor that reads a stream from its first entry
+pub const START: &str = "0";
+
+/// A single entry read back from the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedEvent {
+    /// Stream entry id, usable as a cursor
+    pub id: String,
+    pub event_type: String,
+    pub payload: Value,
+}
+
+impl LoggedEvent {
+    /// Deserialize the payload into a concrete event type
+    pub fn payload_as<T: DeserializeOwned>(&self) -> Result<T, RedisError> {
+        serde_json::from_value(self.payload.clone())
+            .map_err(|e| RedisError::Command(format!("invalid event payload: {e}")))
+    }
+
+    fn from_stream_id(entry: StreamId) -> Result<Self, RedisError> {
+        let event_type: String = entry
+            .get(TYPE_FIELD)
+            .ok_or_else(|| RedisError::Command(format!("event {} has no type", entry.id)))?;
+        let raw: String = entry
+            .get(PAYLOAD_FIELD)
+            .ok_or_else(|| RedisError::Command(format!("event {} has no payload", entry.id)))?;
+        let payload = serde_json::from_str(&raw)
+            .map_err(|e| RedisError::Command(format!("invalid event payload: {e}")))?;
+
+        Ok(Self {
+            id: entry.id,
+            event_type,
+            payload,
+        })
+    }
+}
+
+/// A page of events read from a cursor
+#[derive(Debug, Clone, Default)]
+pub struct EventPage {
+    pub events: Vec<LoggedEvent>,
+    /// Cursor to pass to the next `read_from` call; `None` when no events
+    /// were returned (the caller's cursor is still current)
+    pub next_cursor: Option<String>,
+}
+
+/// Append-only event log stored in a Redis stream
+#[derive(Clone)]
+pub struct EventLog {
+    pool: RedisPool,
+    key: RedisKey,
+    max_len: Option<usize>,
+}
+
+impl EventLog {
+    /// Event log for `stream` under the given key prefix
+    pub fn new(pool: RedisPool, prefix: impl AsRef<str>, stream: impl AsRef<str>) -> Self {
+        Self {
+            pool,
+            key: RedisKey::with_prefix(prefix, ["events", stream.as_ref()]),
+            max_len: None,
+        }
+    }
+
+    /// Keep roughly the last `max_len` events (`MAXLEN ~`)
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Key of the underlying stream
+    pub fn key(&self) -> &RedisKey {
+        &self.key
+    }
+
+    /// Append an event, returning its id
+    pub async fn append<T: Serialize>(
+        &self,
+        event_type: &str,
+        payload: &T,
+    ) -> Result<String, RedisError> {
+        let payload = serde_json::to_string(payload)
+            .map_err(|e| RedisError::Command(format!("event payload not serializable: {e}")))?;
+        let fields = [(TYPE_FIELD, event_type), (PAYLOAD_FIELD, payload.as_str())];
+
+        let mut conn = self.pool.connection().await?;
+        let id: String = match self.max_len {
+            Some(max_len) => {
+                conn.xadd_maxlen(
+                    self.key.as_str(),
+                    redis::streams::StreamMaxlen::Approx(max_len),
+                    "*",
+                    &fields,
+                )
+                .await?
+            }
+            None => conn.xadd(self.key.as_str(), "*", &fields).await?,
+        };
+        Ok(id)
+    }
+
+    /// Read up to `count` events strictly after `cursor`.
+    ///
+    /// Pass [`START`] to read from the beginning of the log.
+    pub async fn read_from(&self, cursor: &str, count: usize) -> Result<EventPage, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let reply: StreamRangeReply = conn
+            .xrange_count(self.key.as_str(), exclusive_start(cursor), "+", count)
+            .await?;
+
+        let events = reply
+            .ids
+            .into_iter()
+            .map(LoggedEvent::from_stream_id)
+            .collect::<Result<Vec<_>, _>>()?;
+        let next_cursor = events.last().map(|e| e.id.clone());
+
+        Ok(EventPage {
+            events,
+            next_cursor,
+        })
+    }
+
+    /// Create a consumer group starting at `start_id` (`$` for new events
+    /// only, [`START`] for the full history).  Existing groups are left as is.
+    pub async fn create_group(&self, group: &str, start_id: &str) -> Result<(), RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let created: redis::RedisResult<()> = conn
+            .xgroup_create_mkstream(self.key.as_str(), group, start_id)
+            .await;
+
+        match created {
+            Ok(()) => Ok(()),
+            Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read events not yet delivered to any consumer in `group`.
+    ///
+    /// Delivered events stay pending until acknowledged with [`ack`].
+    ///
+    /// [`ack`]: EventLog::ack
+    pub async fn read_group(
+        &self,
+        group: &str,
+        consumer: &str,
+        count: usize,
+        block: Option<Duration>,
+    ) -> Result<Vec<LoggedEvent>, RedisError> {
+        let mut options = StreamReadOptions::default()
+            .group(group, consumer)
+            .count(count);
+        if let Some(block) = block {
+            options = options.block(block.as_millis() as usize);
+        }
+
+        let mut conn = self.pool.connection().await?;
+        let reply: Option<StreamReadReply> = conn
+            .xread_options(&[self.key.as_str()], &[">"], &options)
+            .await?;
+
+        reply
+            .into_iter()
+            .flat_map(|reply| reply.keys)
+            .flat_map(|key| key.ids)
+            .map(LoggedEvent::from_stream_id)
+            .collect()
+    }
+
+    /// Acknowledge processed events for `group`
+    pub async fn ack(&self, group: &str, ids: &[&str]) -> Result<u64, RedisError> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.pool.connection().await?;
+        let acked: u64 = conn.xack(self.key.as_str(), group, ids).await?;
+        Ok(acked)
+    }
+
+    /// Number of events currently retained
+    pub async fn retained(&self) -> Result<u64, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let len: u64 = conn.xlen(self.key.as_str()).await?;
+        Ok(len)
+    }
+}
+
+/// `XRANGE` start that excludes `cursor` itself
+fn exclusive_start(cursor: &str) -> String {
+    if cursor == START || cursor == "-" {
+        "-".to_string()
+    } else {
+        format!("({cursor}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct OrderPlaced {
+        order_id: u32,
+    }
+
+    #[test]
+    fn test_exclusive_start() {
+        assert_eq!(exclusive_start(START), "-");
+        assert_eq!(exclusive_start("1700000000000-3"), "(1700000000000-3");
+    }
+
+    #[test]
+    fn test_stream_key_is_namespaced() {
+        let pool = RedisPool::lazy("redis://localhost:6379").unwrap();
+        let log = EventLog::new(pool, "app", "orders");
+        assert_eq!(log.key().as_str(), "app:events:orders");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_append_and_replay_from_cursor() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let pool = RedisPool::new(&url).await.unwrap();
+        let stream = format!("orders-{}", std::process::id());
+        let log = EventLog::new(pool.clone(), "test", &stream);
+
+        let mut ids = Vec::new();
+        for order_id in 0..5 {
+            ids.push(
+                log.append("order.placed", &OrderPlaced { order_id })
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let page = log.read_from(&ids[1], 2).await.unwrap();
+        let replayed: Vec<u32> = page
+            .events
+            .iter()
+            .map(|e| e.payload_as::<OrderPlaced>().unwrap().order_id)
+            .collect();
+        assert_eq!(replayed, vec![2, 3]);
+        assert_eq!(page.next_cursor.as_deref(), Some(ids[3].as_str()));
+
+        let rest = log.read_from(&ids[3], 10).await.unwrap();
+        assert_eq!(rest.events.len(), 1);
+        assert_eq!(rest.events[0].event_type, "order.placed");
+
+        log.create_group("workers", START).await.unwrap();
+        log.create_group("workers", START).await.unwrap();
+        let delivered = log.read_group("workers", "w1", 10, None).await.unwrap();
+        assert_eq!(delivered.len(), 5);
+        let delivered_ids: Vec<&str> = delivered.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(log.ack("workers", &delivered_ids).await.unwrap(), 5);
+
+        let mut conn = pool.connection().await.unwrap();
+        let _: () = conn.del(log.key().as_str()).await.unwrap();
+    }
+}
@@ -22,7 +22,7 @@ impl RedisKey {
     pub fn from_parts(parts: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
         let joined = parts
             .into_iter()
-            .map(|p| p.as_ref())
+            .map(|p| p.as_ref().to_string())
             .filter(|s| !s.is_empty())
             .collect::<Vec<_>>()
             .join(":");
@@ -41,6 +41,11 @@ impl RedisKey {
         Self::with_prefix(prefix, ["cache", key.as_ref()])
     }
 
+    /// Set of cache keys carrying a tag
+    pub fn cache_tag(prefix: impl AsRef<str>, tag: impl AsRef<str>) -> Self {
+        Self::with_prefix(prefix, ["cache_tag", tag.as_ref()])
+    }
+
     /// Session key (single session)
     pub fn session(prefix: impl AsRef<str>, session_id: impl AsRef<str>) -> Self {
         Self::with_prefix(prefix, ["session", session_id.as_ref()])
@@ -61,6 +66,20 @@ impl RedisKey {
         Self::with_prefix(prefix, ["lock", resource.as_ref()])
     }
 
+    /// Wrap `part` in a hash tag (`{part}`)
+    ///
+    /// Redis Cluster hashes only the tagged part of a key, so keys sharing a
+    /// tag live in the same slot and can be used together in scripts,
+    /// transactions and multi-key commands.
+    pub fn hash_tag(part: impl AsRef<str>) -> String {
+        format!("{{{}}}", part.as_ref())
+    }
+
+    /// Cluster hash slot that owns this key
+    pub fn slot(&self) -> u16 {
+        redis::cluster_routing::get_slot(self.0.as_bytes())
+    }
+
     /// Return the inner string representation
     pub fn as_str(&self) -> &str {
         &self.0
@@ -117,6 +136,22 @@ mod tests {
 
         let l = RedisKey::lock("app", "resource");
         assert_eq!(l.as_str(), "app:lock:resource");
+
+        let t = RedisKey::cache_tag("app", "user:42");
+        assert_eq!(t.as_str(), "app:cache_tag:user:42");
+    }
+
+    #[test]
+    fn hash_tagged_keys_share_a_slot() {
+        // Reference slot from the Redis Cluster specification
+        assert_eq!(RedisKey::from("foo").slot(), 12182);
+
+        let tag = RedisKey::hash_tag("user:42");
+        let a = RedisKey::with_prefix("app", [tag.as_str(), "sessions"]);
+        let b = RedisKey::with_prefix("app", [tag.as_str(), "profile"]);
+        assert_eq!(a.as_str(), "app:{user:42}:sessions");
+        assert_eq!(a.slot(), b.slot());
+        assert_eq!(a.slot(), RedisKey::from("user:42").slot());
     }
 
     #[test]
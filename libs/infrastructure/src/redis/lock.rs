@@ -1,46 +1,139 @@
 //! Distributed lock implementation for Redis
 //!
-//! Provides distributed locking capabilities using Redis SETNX for
-//! coordinating access to shared resources across multiple instances.
+//! Provides distributed locking capabilities using Redis for coordinating
+//! access to shared resources across multiple instances.
 //!
-//! ## Feature Flags
+//! Instances' clocks drift apart, so no decision here uses the local clock.
+//! Expiry is enforced by Redis itself (`PX`), leases record when they were
+//! taken and when they lapse in Redis server time (`TIME`), and
+//! [`DistributedLock::is_held`] asks Redis rather than comparing against
+//! `now()`.  A holder that stalls past its TTL (a GC pause, a frozen VM)
+//! can still wake up believing it holds the lock, so every lease also
+//! carries a fencing token: a number that grows with each acquisition of
+//! the resource.  Storage guarded by the lock should reject writes carrying
+//! a token lower than the highest it has seen.
 //!
-//! - `redis`: Enables Redis support (enabled by default with `full` feature)
+//! ```rust,ignore
+//! if let Some(mut lease) = lock.acquire("payout:batch-42", Duration::from_secs(30)).await? {
+//!     payouts.run(&batch, lease.fencing_token).await?;
+//!     lock.extend(&mut lease, Duration::from_secs(30)).await?;
+//!     lock.release(&lease).await?;
+//! }
+//! ```
 
-#[cfg(feature = "redis")]
-use async_trait::async_trait;
-
-#[cfg(feature = "redis")]
+use std::collections::HashMap;
+use std::sync::Mutex;
 use std::time::Duration;
 
-#[cfg(feature = "redis")]
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
 use super::{RedisError, RedisPool};
-#[cfg(feature = "redis")]
 use crate::redis::key::RedisKey;
 
+/// Take the lock if free: bump the fencing counter and store it as the
+/// lock's value.  Returns `{fencing token or 0, TIME seconds, microseconds}`.
+const ACQUIRE_SCRIPT: &str = r#"
+local now = redis.call('TIME')
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return {0, now[1], now[2]}
+end
+local fence = redis.call('INCR', KEYS[2])
+redis.call('SET', KEYS[1], fence, 'PX', ARGV[1])
+return {fence, now[1], now[2]}
+"#;
+
+/// Reset the TTL if the lock still holds our token.  Returns
+/// `{1 or 0, TIME seconds, microseconds}`.
+const EXTEND_SCRIPT: &str = r#"
+local now = redis.call('TIME')
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return {1, now[1], now[2]}
+end
+return {0, now[1], now[2]}
+"#;
+
+/// Delete the lock if it still holds our token
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Milliseconds left on the lock if it holds our token, else -1
+const HELD_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PTTL', KEYS[1])
+end
+return -1
+"#;
+
+/// A held lock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockLease {
+    pub resource: String,
+    /// Grows with every acquisition of `resource`; pass it along with
+    /// writes so stale holders can be rejected
+    pub fencing_token: u64,
+    /// When the lease was taken or last extended, in Redis server time
+    pub acquired_at: OffsetDateTime,
+    /// When the lease lapses, in Redis server time
+    pub expires_at: OffsetDateTime,
+}
+
+impl LockLease {
+    fn new(resource: &str, fencing_token: u64, server_now: OffsetDateTime, ttl: Duration) -> Self {
+        Self {
+            resource: resource.to_string(),
+            fencing_token,
+            acquired_at: server_now,
+            expires_at: server_now + ttl,
+        }
+    }
+
+    /// Whether the lease is still safe to act on at Redis time `server_now`
+    ///
+    /// Stops a little before `expires_at` (1% of the TTL plus 2ms), leaving
+    /// room for the drift between Redis' clock and its expiry timer.
+    pub fn is_valid_at(&self, server_now: OffsetDateTime) -> bool {
+        let ttl = self.expires_at - self.acquired_at;
+        let margin = ttl / 100 + time::Duration::milliseconds(2);
+        server_now + margin < self.expires_at
+    }
+}
+
 /// Distributed lock trait
-#[cfg(feature = "redis")]
 #[async_trait]
 pub trait DistributedLock: Send + Sync {
-    /// Acquire a lock with the given key and TTL
-    async fn acquire(&self, key: &str, ttl: Duration) -> Result<bool, RedisError>;
+    /// Take the lock on `resource` for `ttl`, or `None` if it is held
+    async fn acquire(&self, resource: &str, ttl: Duration)
+    -> Result<Option<LockLease>, RedisError>;
+
+    /// Push the expiry of a lease still held to `ttl` from now
+    ///
+    /// Returns `false`, leaving `lease` as it was, if the lock expired or
+    /// was taken over in the meantime.
+    async fn extend(&self, lease: &mut LockLease, ttl: Duration) -> Result<bool, RedisError>;
 
-    /// Release a lock with the given key
-    async fn release(&self, key: &str) -> Result<bool, RedisError>;
+    /// Release the lock if `lease` still holds it
+    async fn release(&self, lease: &LockLease) -> Result<bool, RedisError>;
 
-    /// Check if a lock exists
-    async fn exists(&self, key: &str) -> Result<bool, RedisError>;
+    /// Whether `lease` still holds the lock, as far as Redis is concerned
+    async fn is_held(&self, lease: &LockLease) -> Result<bool, RedisError>;
+
+    /// Redis server time
+    async fn server_time(&self) -> Result<OffsetDateTime, RedisError>;
 }
 
 /// Redis-based distributed lock implementation
-#[cfg(feature = "redis")]
 #[derive(Clone)]
 pub struct RedisLock {
     pool: RedisPool,
     prefix: String,
 }
 
-#[cfg(feature = "redis")]
 impl RedisLock {
     /// Create a new Redis lock instance
     pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
@@ -51,56 +144,298 @@ impl RedisLock {
     }
 
     /// Get the lock key
+    ///
+    /// The resource is hash-tagged so the lock and its fencing counter share
+    /// a cluster slot.
     fn lock_key(&self, resource: &str) -> RedisKey {
-        RedisKey::lock(&self.prefix, resource)
+        RedisKey::lock(&self.prefix, RedisKey::hash_tag(resource))
+    }
+
+    /// Key of the fencing counter of `resource`
+    fn fence_key(&self, resource: &str) -> RedisKey {
+        RedisKey::with_prefix(
+            &self.prefix,
+            ["lock", RedisKey::hash_tag(resource).as_str(), "fence"],
+        )
+    }
+
+    /// Latest fencing token handed out for `resource`, 0 if none was
+    ///
+    /// A lease whose token is lower has been superseded.
+    pub async fn current_fencing_token(&self, resource: &str) -> Result<u64, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let fence: Option<u64> = redis::cmd("GET")
+            .arg(self.fence_key(resource).as_str())
+            .query_async(&mut conn)
+            .await?;
+        Ok(fence.unwrap_or(0))
     }
 }
 
-#[cfg(feature = "redis")]
 #[async_trait]
 impl DistributedLock for RedisLock {
-    async fn acquire(&self, key: &str, ttl: Duration) -> Result<bool, RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let lock_key = self.lock_key(key).as_str();
-
-        // Use SETNX with PX for atomic lock acquisition
-        let result: Option<String> = redis::cmd("SET")
-            .arg(lock_key)
-            .arg("locked")
-            .arg("NX")
-            .arg("PX")
-            .arg(ttl.as_millis() as u64)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("set", e.to_string()))?;
-
-        Ok(result.is_some())
-    }
-
-    async fn release(&self, key: &str) -> Result<bool, RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let lock_key = self.lock_key(key).as_str();
-
-        // Use DEL to release the lock
-        let deleted: u64 = redis::cmd("DEL")
-            .arg(lock_key)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("del", e.to_string()))?;
+    async fn acquire(
+        &self,
+        resource: &str,
+        ttl: Duration,
+    ) -> Result<Option<LockLease>, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let (fence, secs, micros): (u64, i64, i64) = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(self.lock_key(resource).as_str())
+            .key(self.fence_key(resource).as_str())
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
+            .await?;
+
+        if fence == 0 {
+            return Ok(None);
+        }
+        Ok(Some(LockLease::new(
+            resource,
+            fence,
+            server_time(secs, micros)?,
+            ttl,
+        )))
+    }
+
+    async fn extend(&self, lease: &mut LockLease, ttl: Duration) -> Result<bool, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let (extended, secs, micros): (u64, i64, i64) = redis::Script::new(EXTEND_SCRIPT)
+            .key(self.lock_key(&lease.resource).as_str())
+            .arg(lease.fencing_token)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
+            .await?;
+
+        if extended == 0 {
+            return Ok(false);
+        }
+        *lease = LockLease::new(
+            &lease.resource,
+            lease.fencing_token,
+            server_time(secs, micros)?,
+            ttl,
+        );
+        Ok(true)
+    }
 
+    async fn release(&self, lease: &LockLease) -> Result<bool, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let deleted: u64 = redis::Script::new(RELEASE_SCRIPT)
+            .key(self.lock_key(&lease.resource).as_str())
+            .arg(lease.fencing_token)
+            .invoke_async(&mut conn)
+            .await?;
         Ok(deleted > 0)
     }
 
-    async fn exists(&self, key: &str) -> Result<bool, RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let lock_key = self.lock_key(key).as_str();
+    async fn is_held(&self, lease: &LockLease) -> Result<bool, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let remaining_ms: i64 = redis::Script::new(HELD_SCRIPT)
+            .key(self.lock_key(&lease.resource).as_str())
+            .arg(lease.fencing_token)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(remaining_ms > 0)
+    }
+
+    async fn server_time(&self) -> Result<OffsetDateTime, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let (secs, micros): (i64, i64) = redis::cmd("TIME").query_async(&mut conn).await?;
+        server_time(secs, micros)
+    }
+}
+
+fn ttl_millis(ttl: Duration) -> u64 {
+    (ttl.as_millis() as u64).max(1)
+}
+
+/// Instant of a `TIME` reply
+fn server_time(secs: i64, micros: i64) -> Result<OffsetDateTime, RedisError> {
+    OffsetDateTime::from_unix_timestamp_nanos(
+        i128::from(secs) * 1_000_000_000 + i128::from(micros) * 1_000,
+    )
+    .map_err(|e| RedisError::Command(format!("invalid TIME reply: {e}")))
+}
+
+/// In-process lock with its own clock, standing in for Redis in tests
+///
+/// The clock plays the part of Redis server time and only moves when
+/// [`InMemoryLock::advance`] is called.
+pub struct InMemoryLock {
+    state: Mutex<InMemoryState>,
+}
+
+struct InMemoryState {
+    now: OffsetDateTime,
+    /// Holder's fencing token and expiry, by resource
+    held: HashMap<String, (u64, OffsetDateTime)>,
+    fences: HashMap<String, u64>,
+}
+
+impl InMemoryLock {
+    /// Lock whose clock starts at `now`
+    pub fn new(now: OffsetDateTime) -> Self {
+        Self {
+            state: Mutex::new(InMemoryState {
+                now,
+                held: HashMap::new(),
+                fences: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.now += by;
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, InMemoryState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = state.now;
+        state.held.retain(|_, (_, expires_at)| *expires_at > now);
+        state
+    }
+
+    fn holds(state: &InMemoryState, lease: &LockLease) -> bool {
+        state
+            .held
+            .get(&lease.resource)
+            .is_some_and(|(fence, _)| *fence == lease.fencing_token)
+    }
+}
+
+#[async_trait]
+impl DistributedLock for InMemoryLock {
+    async fn acquire(
+        &self,
+        resource: &str,
+        ttl: Duration,
+    ) -> Result<Option<LockLease>, RedisError> {
+        let mut state = self.lock_state();
+        if state.held.contains_key(resource) {
+            return Ok(None);
+        }
+        let now = state.now;
+        let fence = state.fences.entry(resource.to_string()).or_default();
+        *fence += 1;
+        let lease = LockLease::new(resource, *fence, now, ttl);
+        state.held.insert(
+            resource.to_string(),
+            (lease.fencing_token, lease.expires_at),
+        );
+        Ok(Some(lease))
+    }
+
+    async fn extend(&self, lease: &mut LockLease, ttl: Duration) -> Result<bool, RedisError> {
+        let mut state = self.lock_state();
+        if !Self::holds(&state, lease) {
+            return Ok(false);
+        }
+        *lease = LockLease::new(&lease.resource, lease.fencing_token, state.now, ttl);
+        state.held.insert(
+            lease.resource.clone(),
+            (lease.fencing_token, lease.expires_at),
+        );
+        Ok(true)
+    }
+
+    async fn release(&self, lease: &LockLease) -> Result<bool, RedisError> {
+        let mut state = self.lock_state();
+        if !Self::holds(&state, lease) {
+            return Ok(false);
+        }
+        state.held.remove(&lease.resource);
+        Ok(true)
+    }
+
+    async fn is_held(&self, lease: &LockLease) -> Result<bool, RedisError> {
+        Ok(Self::holds(&self.lock_state(), lease))
+    }
+
+    async fn server_time(&self) -> Result<OffsetDateTime, RedisError> {
+        Ok(self.lock_state().now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[tokio::test]
+    async fn test_lock_follows_server_time_despite_local_clock_skew() {
+        // The "server" is ten minutes behind this machine's clock
+        let local_now = OffsetDateTime::now_utc();
+        let lock = InMemoryLock::new(local_now - Duration::from_secs(600));
+        let ttl = Duration::from_secs(30);
+
+        let mut lease = lock.acquire("payout:batch-42", ttl).await.unwrap().unwrap();
+        // Judged by the local clock the lease lapsed long ago; by server
+        // time it has 30s left
+        assert!(!lease.is_valid_at(local_now));
+        assert!(lease.is_valid_at(lock.server_time().await.unwrap()));
+        assert!(lock.is_held(&lease).await.unwrap());
+        assert!(
+            lock.acquire("payout:batch-42", ttl)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        lock.advance(Duration::from_secs(20));
+        assert!(lock.extend(&mut lease, ttl).await.unwrap());
+        lock.advance(Duration::from_secs(20));
+        assert!(lock.is_held(&lease).await.unwrap());
+
+        // Past the extended TTL another instance takes over with a higher
+        // fencing token, and the stale holder can no longer extend or release
+        lock.advance(Duration::from_secs(11));
+        assert!(!lock.is_held(&lease).await.unwrap());
+        let next = lock.acquire("payout:batch-42", ttl).await.unwrap().unwrap();
+        assert!(next.fencing_token > lease.fencing_token);
+        assert!(!lock.extend(&mut lease, ttl).await.unwrap());
+        assert!(!lock.release(&lease).await.unwrap());
+        assert!(lock.release(&next).await.unwrap());
+    }
+
+    #[test]
+    fn test_lease_validity_leaves_drift_margin() {
+        let acquired_at = datetime!(2024-03-10 12:00:00 UTC);
+        let lease = LockLease::new("r", 1, acquired_at, Duration::from_secs(10));
+        assert!(lease.is_valid_at(acquired_at + Duration::from_millis(9_800)));
+        // 1% of 10s plus 2ms before expiry it is no longer safe
+        assert!(!lease.is_valid_at(acquired_at + Duration::from_millis(9_900)));
+        assert_eq!(
+            server_time(1_710_072_000, 250_000).unwrap(),
+            datetime!(2024-03-10 12:00:00.25 UTC)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_redis_lock_fencing_and_server_time() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let lock = RedisLock::new(RedisPool::new(&url).await.unwrap(), "test");
+        let resource = format!("lock-{}", std::process::id());
+        let ttl = Duration::from_millis(200);
 
-        let exists: u64 = redis::cmd("EXISTS")
-            .arg(lock_key)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("exists", e.to_string()))?;
+        let mut lease = lock.acquire(&resource, ttl).await.unwrap().unwrap();
+        let server_now = lock.server_time().await.unwrap();
+        assert!(lease.is_valid_at(server_now));
+        assert!(lock.acquire(&resource, ttl).await.unwrap().is_none());
+        assert!(lock.extend(&mut lease, ttl).await.unwrap());
 
-        Ok(exists > 0)
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!lock.is_held(&lease).await.unwrap());
+        let next = lock.acquire(&resource, ttl).await.unwrap().unwrap();
+        assert_eq!(
+            lock.current_fencing_token(&resource).await.unwrap(),
+            next.fencing_token
+        );
+        assert!(next.fencing_token > lease.fencing_token);
+        assert!(!lock.release(&lease).await.unwrap());
+        assert!(lock.release(&next).await.unwrap());
     }
 }
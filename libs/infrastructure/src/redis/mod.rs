@@ -1,9 +1,31 @@
 //! Redis infrastructure shared by all domains.
 
+pub mod cache;
 pub mod config;
 pub mod error;
+pub mod event_log;
+pub mod hyperloglog;
+pub mod key;
+pub mod lock;
 pub mod pool;
+pub mod pubsub;
+pub mod quota;
+pub mod rate_limiter;
+pub mod semaphore;
+pub mod session;
 
+pub use cache::{Cache, CacheStats, RedisCache};
 pub use config::RedisConfig;
 pub use error::RedisError;
-pub use pool::RedisPool;
+pub use event_log::{EventLog, EventPage, LoggedEvent};
+pub use hyperloglog::HyperLogLogCounter;
+pub use key::RedisKey;
+pub use lock::{DistributedLock, InMemoryLock, LockLease, RedisLock};
+pub use pool::{RedisConnection, RedisPool};
+pub use pubsub::{RedisPubSub, SlowConsumerPolicy, SubscriberConfig, Subscription};
+pub use quota::{ApiKeyQuota, DailyReset, QuotaStatus};
+pub use rate_limiter::{
+    FallbackRateLimiter, OutagePolicy, RateLimiter, RedisFixedWindowRateLimiter, RedisRateLimiter,
+};
+pub use semaphore::{DistributedSemaphore, InMemorySemaphore, RedisSemaphore, SemaphorePermit};
+pub use session::{InMemorySessionStore, RedisSessionStore, SessionData, SessionStore};
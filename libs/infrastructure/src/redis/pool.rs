@@ -1,16 +1,109 @@
 //! Redis client wrapper.
+//!
+//! A pool talks either to a single node or to a Redis Cluster.  In cluster
+//! mode commands are routed to the node owning each key's hash slot, and
+//! multi-key commands the client knows how to split (`MGET`, `DEL`,
+//! `EXISTS`) are fanned out per slot.  Scripts, transactions and other
+//! multi-key commands must keep their keys in one slot; build those keys
+//! with [`RedisKey::hash_tag`](super::RedisKey::hash_tag).
+//!
+//! Connecting, and every command sent over a [`RedisConnection`], is bounded
+//! by the Redis timeout of the pool's [`TimeoutProfiles`].
 
-use redis::{Client, aio::MultiplexedConnection};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::{
+    Client, Cmd, Pipeline, RedisFuture, Value,
+    aio::{ConnectionLike, MultiplexedConnection},
+    cluster::ClusterClient,
+    cluster_async::ClusterConnection,
+};
+use tokio::sync::OnceCell;
 
 use crate::redis::{RedisConfig, error::RedisError};
+use crate::resilience::timeout::run_with_timeout;
+use crate::resilience::{DependencyClass, TimeoutProfiles};
+
+#[derive(Clone)]
+enum Backend {
+    Single(Client),
+    Cluster {
+        client: ClusterClient,
+        /// Cluster connections track topology and multiplex internally, so
+        /// one is shared rather than reconnecting to every node per call
+        connection: Arc<OnceCell<ClusterConnection>>,
+    },
+}
 
 #[derive(Clone)]
 pub struct RedisPool {
-    client: Client,
+    backend: Backend,
+    timeout: Duration,
+}
+
+#[derive(Clone)]
+enum Connection {
+    Single(MultiplexedConnection),
+    Cluster(ClusterConnection),
+}
+
+/// Connection to a single node or a cluster
+#[derive(Clone)]
+pub struct RedisConnection {
+    connection: Connection,
+    /// Longest a command may wait for its reply
+    timeout: Duration,
+}
+
+/// Await `request` for at most `timeout`
+async fn bounded<T>(timeout: Duration, request: RedisFuture<'_, T>) -> redis::RedisResult<T> {
+    run_with_timeout(DependencyClass::Redis, timeout, request)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e.to_string()))?
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let request = match &mut self.connection {
+            Connection::Single(conn) => conn.req_packed_command(cmd),
+            Connection::Cluster(conn) => conn.req_packed_command(cmd),
+        };
+        Box::pin(bounded(self.timeout, request))
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let request = match &mut self.connection {
+            Connection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            Connection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+        };
+        Box::pin(bounded(self.timeout, request))
+    }
+
+    fn get_db(&self) -> i64 {
+        match &self.connection {
+            Connection::Single(conn) => conn.get_db(),
+            Connection::Cluster(conn) => conn.get_db(),
+        }
+    }
 }
 
 impl RedisPool {
     pub async fn new(redis_url: &str) -> Result<Self, RedisError> {
+        let pool = Self::lazy(redis_url)?;
+        pool.ping().await?;
+        Ok(pool)
+    }
+
+    /// Build a pool without contacting the server; connections are opened
+    /// on first use.
+    pub fn lazy(redis_url: &str) -> Result<Self, RedisError> {
         if redis_url.trim().is_empty() {
             return Err(RedisError::Configuration(
                 "REDIS_URL cannot be empty".to_string(),
@@ -18,32 +111,145 @@ impl RedisPool {
         }
 
         let client = Client::open(redis_url).map_err(|e| RedisError::Connection(e.to_string()))?;
-        let mut conn = client
-            .get_multiplexed_async_connection()
+        Ok(Self {
+            backend: Backend::Single(client),
+            timeout: TimeoutProfiles::default().redis,
+        })
+    }
+
+    /// Connect to a Redis Cluster through any of its `nodes`
+    pub async fn cluster(nodes: &[String]) -> Result<Self, RedisError> {
+        let pool = Self::lazy_cluster(nodes)?;
+        pool.ping().await?;
+        Ok(pool)
+    }
+
+    /// Cluster counterpart of [`Self::lazy`]; the slot map is fetched on
+    /// first use.
+    pub fn lazy_cluster(nodes: &[String]) -> Result<Self, RedisError> {
+        if nodes.iter().all(|node| node.trim().is_empty()) {
+            return Err(RedisError::Configuration(
+                "REDIS_CLUSTER_NODES cannot be empty".to_string(),
+            ));
+        }
+
+        let client = ClusterClient::new(nodes.iter().map(String::as_str).collect::<Vec<_>>())
+            .map_err(|e| RedisError::Connection(e.to_string()))?;
+        Ok(Self {
+            backend: Backend::Cluster {
+                client,
+                connection: Arc::new(OnceCell::new()),
+            },
+            timeout: TimeoutProfiles::default().redis,
+        })
+    }
+
+    pub async fn from_config(config: &RedisConfig) -> Result<Self, RedisError> {
+        config
+            .validate()
+            .map_err(|e| RedisError::Configuration(e.to_string()))?;
+        if config.cluster {
+            Self::cluster(&config.cluster_seeds()).await
+        } else {
+            Self::new(&config.url).await
+        }
+    }
+
+    /// Bound connecting and commands by the Redis timeout of `profiles`
+    pub fn with_timeouts(mut self, profiles: &TimeoutProfiles) -> Self {
+        self.timeout = profiles.timeout_for(DependencyClass::Redis);
+        self
+    }
+
+    pub fn is_cluster(&self) -> bool {
+        matches!(self.backend, Backend::Cluster { .. })
+    }
+
+    pub async fn connection(&self) -> Result<RedisConnection, RedisError> {
+        let connect = async {
+            match &self.backend {
+                Backend::Single(client) => client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .map(Connection::Single),
+                Backend::Cluster { client, connection } => connection
+                    .get_or_try_init(|| client.get_async_connection())
+                    .await
+                    .cloned()
+                    .map(Connection::Cluster),
+            }
+        };
+        let connection = run_with_timeout(DependencyClass::Redis, self.timeout, connect)
             .await
+            .map_err(|e| RedisError::Connection(e.to_string()))?
             .map_err(|e| RedisError::Connection(e.to_string()))?;
+        Ok(RedisConnection {
+            connection,
+            timeout: self.timeout,
+        })
+    }
+
+    /// Single-node client, for features cluster mode doesn't support
+    /// (pub/sub subscriptions)
+    pub fn client(&self) -> Option<&Client> {
+        match &self.backend {
+            Backend::Single(client) => Some(client),
+            Backend::Cluster { .. } => None,
+        }
+    }
 
+    async fn ping(&self) -> Result<(), RedisError> {
+        let mut conn = self.connection().await?;
         redis::cmd("PING")
             .query_async::<String>(&mut conn)
             .await
             .map_err(|e| RedisError::Connection(e.to_string()))?;
-
-        Ok(Self { client })
+        Ok(())
     }
+}
 
-    pub async fn from_config(config: &RedisConfig) -> Result<Self, RedisError> {
-        config.validate().map_err(|e| RedisError::Configuration(e.to_string()))?;
-        Self::new(&config.url).await
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub async fn connection(&self) -> Result<MultiplexedConnection, RedisError> {
-        self.client
-            .get_multiplexed_async_connection()
-            .await
-            .map_err(|e| RedisError::Connection(e.to_string()))
+    #[tokio::test]
+    async fn test_cluster_mode_is_selected_and_fails_fast_when_unreachable() {
+        assert!(RedisPool::lazy_cluster(&[]).is_err());
+
+        let pool = RedisPool::lazy_cluster(&["redis://127.0.0.1:1".to_string()]).unwrap();
+        assert!(pool.is_cluster());
+        assert!(pool.client().is_none());
+        assert!(matches!(
+            pool.connection().await,
+            Err(RedisError::Connection(_))
+        ));
+
+        assert!(!RedisPool::lazy("redis://127.0.0.1:1").unwrap().is_cluster());
     }
 
-    pub fn client(&self) -> &Client {
-        &self.client
+    #[tokio::test]
+    async fn test_unresponsive_server_fails_after_the_redis_timeout() {
+        // Accepts connections but never replies
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let profiles = TimeoutProfiles {
+            redis: Duration::from_millis(50),
+            ..TimeoutProfiles::default()
+        };
+        let pool = RedisPool::lazy(&format!("redis://{addr}"))
+            .unwrap()
+            .with_timeouts(&profiles);
+
+        let started = std::time::Instant::now();
+        let err = pool.ping().await.unwrap_err();
+        assert!(err.to_string().contains("timed out after 50ms"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(1));
     }
 }
@@ -3,30 +3,31 @@
 //! Provides Redis pub/sub functionality for event-driven architectures,
 //! real-time notifications, and distributed messaging.
 //!
-//! ## Feature Flags
-//!
-//! - `redis`: Enables Redis support (enabled by default with `full` feature)
-
-#[cfg(feature = "redis")]
-use async_trait::async_trait;
+//! Each [`Subscription`] has a bounded buffer between the Redis connection
+//! and the consumer.  When the consumer falls behind and the buffer is full,
+//! its [`SlowConsumerPolicy`] decides what gives: the oldest buffered
+//! message, the incoming one, or (with `Block`) the connection, which stops
+//! being read until there is room.  Blocking loses nothing locally but
+//! leaves messages queued in Redis, which disconnects subscribers that go
+//! over its `client-output-buffer-limit`.  Dropped messages are counted per
+//! subscription and in the `redis_pubsub_dropped_messages_total` metric.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-#[cfg(feature = "redis")]
-use serde::{Serialize, de::DeserializeOwned};
+use futures_util::stream::StreamExt;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::warn;
 
-#[cfg(feature = "redis")]
 use super::{RedisError, RedisPool};
-#[cfg(feature = "redis")]
 use crate::redis::key::RedisKey;
 
-#[cfg(feature = "redis")]
-use futures_util::stream::StreamExt;
-#[cfg(feature = "redis")]
-use std::sync::Arc;
-#[cfg(feature = "redis")]
-use tokio::sync::broadcast;
-
 /// Message wrapper for pub/sub messages
-#[cfg(feature = "redis")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PubSubMessage {
     /// Topic/channel name
@@ -39,49 +40,190 @@ pub struct PubSubMessage {
     pub timestamp: i64,
 }
 
-/// Pub/Sub trait for message publishing and subscribing
-#[cfg(feature = "redis")]
-#[async_trait]
-pub trait PubSub: Send + Sync {
-    /// Publish a message to a channel
-    async fn publish(&self, channel: &str, message: &str) -> Result<u64, RedisError>;
+/// What to do with messages for a subscriber whose buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Discard the oldest buffered message to make room
+    DropOldest,
+    /// Discard the incoming message
+    DropNewest,
+    /// Stop reading from Redis until the consumer catches up
+    Block,
+}
 
-    /// Subscribe to a channel
-    async fn subscribe(&self, channel: &str) -> Result<Subscription, RedisError>;
+impl SlowConsumerPolicy {
+    #[cfg(feature = "metrics")]
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::DropOldest => "drop_oldest",
+            Self::DropNewest => "drop_newest",
+            Self::Block => "block",
+        }
+    }
+}
 
-    /// Publish a serialized message to a channel
-    async fn publish_json<T: Serialize>(
-        &self,
-        channel: &str,
-        message: &T,
-    ) -> Result<u64, RedisError>;
+/// Buffering of one subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberConfig {
+    /// Messages buffered for the consumer at most
+    pub capacity: usize,
+    pub policy: SlowConsumerPolicy,
+}
+
+impl Default for SubscriberConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            policy: SlowConsumerPolicy::DropOldest,
+        }
+    }
 }
 
-/// Subscription handle for receiving messages
-#[cfg(feature = "redis")]
-pub struct Subscription {
+/// Bounded queue between the Redis reader and one consumer
+struct Buffer<T> {
+    channel: String,
+    config: SubscriberConfig,
+    queue: Mutex<VecDeque<T>>,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+    readable: Notify,
+    writable: Notify,
+}
+
+impl<T> Buffer<T> {
+    fn new(channel: String, config: SubscriberConfig) -> Self {
+        Self {
+            channel,
+            config: SubscriberConfig {
+                capacity: config.capacity.max(1),
+                ..config
+            },
+            queue: Mutex::new(VecDeque::new()),
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        }
+    }
+
+    fn queue(&self) -> std::sync::MutexGuard<'_, VecDeque<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Buffer `item`, applying the policy when full
+    async fn push(&self, item: T) {
+        loop {
+            {
+                let mut queue = self.queue();
+                if queue.len() < self.config.capacity {
+                    queue.push_back(item);
+                    break;
+                }
+                match self.config.policy {
+                    SlowConsumerPolicy::DropOldest => {
+                        queue.pop_front();
+                        queue.push_back(item);
+                        drop(queue);
+                        self.record_drop();
+                        break;
+                    }
+                    SlowConsumerPolicy::DropNewest => {
+                        drop(queue);
+                        self.record_drop();
+                        return;
+                    }
+                    SlowConsumerPolicy::Block => {}
+                }
+            }
+            self.writable.notified().await;
+        }
+        self.readable.notify_one();
+    }
+
+    fn record_drop(&self) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        // Log the first drop and then every thousandth, not each one
+        if dropped == 1 || dropped.is_multiple_of(1000) {
+            warn!(
+                channel = %self.channel,
+                dropped,
+                "pub/sub subscriber is falling behind, dropping messages"
+            );
+        }
+
+        #[cfg(feature = "metrics")]
+        metrics::counter!(
+            crate::observability::metrics::REDIS_PUBSUB_DROPPED_MESSAGES_TOTAL,
+            "channel" => self.channel.clone(),
+            "policy" => self.config.policy.as_str()
+        )
+        .increment(1);
+    }
+
+    /// Next buffered item; `None` once closed and drained
+    async fn pop(&self) -> Option<T> {
+        loop {
+            if let Some(item) = self.queue().pop_front() {
+                self.writable.notify_one();
+                return Some(item);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            self.readable.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.readable.notify_one();
+    }
+}
+
+/// Messages of one channel, decoded as `T`
+pub struct Subscription<T = String> {
     pub channel: String,
-    pub receiver: broadcast::Receiver<String>,
+    buffer: Arc<Buffer<T>>,
+    reader: JoinHandle<()>,
+}
+
+impl<T> Subscription<T> {
+    /// Next message; `None` once the connection has closed and every
+    /// buffered message was received
+    pub async fn recv(&mut self) -> Option<T> {
+        self.buffer.pop().await
+    }
+
+    /// Messages dropped so far because the consumer was too slow
+    pub fn dropped(&self) -> u64 {
+        self.buffer.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Messages waiting to be received
+    pub fn buffered(&self) -> usize {
+        self.buffer.queue().len()
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
 }
 
 /// Redis Pub/Sub implementation
-#[cfg(feature = "redis")]
 #[derive(Clone)]
 pub struct RedisPubSub {
     pool: RedisPool,
     prefix: String,
-    sender: Arc<broadcast::Sender<String>>,
 }
 
-#[cfg(feature = "redis")]
 impl RedisPubSub {
     /// Create a new Redis pub/sub instance
     pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
-        let (sender, _) = broadcast::channel(1000);
         Self {
             pool,
             prefix: prefix.into(),
-            sender: Arc::new(sender),
         }
     }
 
@@ -92,77 +234,112 @@ impl RedisPubSub {
 
     /// Get prefixed channel name
     fn channel_name(&self, channel: &str) -> RedisKey {
-        RedisKey::from_parts([&self.prefix, "pubsub", channel])
+        RedisKey::from_parts([self.prefix.as_str(), "pubsub", channel])
     }
 
-    /// Subscribe to a channel and return a stream of messages
-    pub async fn subscribe_to_channel(
+    /// Publish a message to a channel, returning the number of receivers
+    pub async fn publish(&self, channel: &str, message: &str) -> Result<u64, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        Ok(conn
+            .publish(self.channel_name(channel).as_str(), message)
+            .await?)
+    }
+
+    /// Publish `message` serialized as JSON
+    pub async fn publish_json<T: Serialize>(
         &self,
         channel: &str,
-    ) -> Result<broadcast::Receiver<String>, RedisError> {
-        let conn = self.pool.get_connection().await?;
-
-        let channel_name = self.channel_name(channel).as_str();
-
-        // Subscribe to Redis channel
-        let mut pubsub = redis::cmd("SUBSCRIBE")
-            .arg(channel_name)
-            .query_async::<_, ()>(conn)
-            .await
-            .map_err(|e| RedisError::command("subscribe", e.to_string()))?;
-
-        let sender = self.sender.clone();
-
-        // Spawn a task to forward Redis messages to the broadcast channel
-        tokio::spawn(async move {
-            while let Some(msg) = pubsub.on_message().next().await {
-                let payload: String = msg.get_payload().unwrap_or_default();
-                let _ = sender.send(payload);
-            }
-        });
+        message: &T,
+    ) -> Result<u64, RedisError> {
+        let payload =
+            serde_json::to_string(message).map_err(|e| RedisError::Command(e.to_string()))?;
+        self.publish(channel, &payload).await
+    }
 
-        Ok(self.sender.subscribe())
+    /// Subscribe to the raw payloads of `channel`
+    pub async fn subscribe(
+        &self,
+        channel: &str,
+        config: SubscriberConfig,
+    ) -> Result<Subscription, RedisError> {
+        self.subscribe_with(channel, config, Ok).await
     }
-}
 
-#[cfg(feature = "redis")]
-#[async_trait]
-impl PubSub for RedisPubSub {
-    async fn publish(&self, channel: &str, message: &str) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let channel_name = self.channel_name(channel).as_str();
+    /// Subscribe to `channel`, decoding each payload from JSON
+    ///
+    /// Payloads that don't decode as `T` are logged and skipped.
+    pub async fn subscribe_json<T>(
+        &self,
+        channel: &str,
+        config: SubscriberConfig,
+    ) -> Result<Subscription<T>, RedisError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.subscribe_with(channel, config, |payload| {
+            serde_json::from_str(&payload).map_err(|e| e.to_string())
+        })
+        .await
+    }
 
-        let result: u64 = redis::cmd("PUBLISH")
-            .arg(channel_name)
-            .arg(message)
-            .query_async(conn)
+    async fn subscribe_with<T>(
+        &self,
+        channel: &str,
+        config: SubscriberConfig,
+        decode: fn(String) -> Result<T, String>,
+    ) -> Result<Subscription<T>, RedisError>
+    where
+        T: Send + 'static,
+    {
+        let client = self.pool.client().ok_or_else(|| {
+            RedisError::Configuration("pub/sub subscriptions need a single-node Redis".into())
+        })?;
+        let mut pubsub = client
+            .get_async_pubsub()
             .await
-            .map_err(|e| RedisError::command("publish", e.to_string()))?;
-
-        Ok(result)
-    }
+            .map_err(|e| RedisError::Connection(e.to_string()))?;
+        pubsub
+            .subscribe(self.channel_name(channel).as_str())
+            .await?;
 
-    async fn subscribe(&self, channel: &str) -> Result<Subscription, RedisError> {
-        let receiver = self.subscribe_to_channel(channel).await?;
+        let buffer = Arc::new(Buffer::new(channel.to_string(), config));
+        let reader = spawn_reader(buffer.clone(), pubsub.into_on_message(), decode);
         Ok(Subscription {
             channel: channel.to_string(),
-            receiver,
+            buffer,
+            reader,
         })
     }
+}
 
-    async fn publish_json<T: Serialize>(
-        &self,
-        channel: &str,
-        message: &T,
-    ) -> Result<u64, RedisError> {
-        let payload = serde_json::to_string(message)
-            .map_err(|e| RedisError::serialization("JSON", e.to_string()))?;
-        self.publish(channel, &payload).await
-    }
+/// Move decoded `messages` into `buffer` until the stream ends
+fn spawn_reader<T, S, F>(buffer: Arc<Buffer<T>>, mut messages: S, decode: F) -> JoinHandle<()>
+where
+    T: Send + 'static,
+    S: futures_util::Stream<Item = redis::Msg> + Unpin + Send + 'static,
+    F: Fn(String) -> Result<T, String> + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!(channel = %buffer.channel, error = %e, "unreadable pub/sub payload");
+                    continue;
+                }
+            };
+            match decode(payload) {
+                Ok(item) => buffer.push(item).await,
+                Err(e) => {
+                    warn!(channel = %buffer.channel, error = %e, "undecodable pub/sub message");
+                }
+            }
+        }
+        buffer.close();
+    })
 }
 
 /// Builder for creating Pub/Sub messages
-#[cfg(feature = "redis")]
 pub struct MessageBuilder {
     topic: String,
     payload: String,
@@ -170,7 +347,6 @@ pub struct MessageBuilder {
     timestamp: i64,
 }
 
-#[cfg(feature = "redis")]
 impl MessageBuilder {
     /// Create a new message builder
     pub fn new(topic: impl Into<String>, payload: impl Into<String>) -> Self {
@@ -178,7 +354,7 @@ impl MessageBuilder {
             topic: topic.into(),
             payload: payload.into(),
             message_id: None,
-            timestamp: chrono::Utc::now().timestamp(),
+            timestamp: time::OffsetDateTime::now_utc().unix_timestamp(),
         }
     }
 
@@ -200,7 +376,6 @@ impl MessageBuilder {
 }
 
 /// Helper to create a new message
-#[cfg(feature = "redis")]
 pub fn message(topic: impl Into<String>, payload: impl Into<String>) -> MessageBuilder {
     MessageBuilder::new(topic, payload)
 }
@@ -208,6 +383,7 @@ pub fn message(topic: impl Into<String>, payload: impl Into<String>) -> MessageB
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn message_builder_creates_message() {
@@ -228,4 +404,107 @@ mod tests {
         assert_eq!(msg.payload, "hello world");
         assert_eq!(msg.message_id, None);
     }
+
+    /// Subscription fed by `payloads` instead of a Redis connection
+    fn subscription<T: DeserializeOwned + Send + 'static>(
+        payloads: Vec<String>,
+        config: SubscriberConfig,
+    ) -> Subscription<T> {
+        let buffer = Arc::new(Buffer::new("orders".to_string(), config));
+        let messages = futures_util::stream::iter(payloads).map(|payload| {
+            redis::Msg::from_value(&redis::Value::Array(vec![
+                redis::Value::BulkString(b"message".to_vec()),
+                redis::Value::BulkString(b"orders".to_vec()),
+                redis::Value::BulkString(payload.into_bytes()),
+            ]))
+            .unwrap()
+        });
+        let reader = spawn_reader(buffer.clone(), messages, |payload| {
+            serde_json::from_str(&payload).map_err(|e| e.to_string())
+        });
+        Subscription {
+            channel: "orders".to_string(),
+            buffer,
+            reader,
+        }
+    }
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_drops_instead_of_buffering_everything() {
+        let payloads: Vec<_> = (0..10_000).map(|n| n.to_string()).collect();
+
+        let mut newest = subscription::<u32>(
+            payloads.clone(),
+            SubscriberConfig {
+                capacity: 100,
+                policy: SlowConsumerPolicy::DropOldest,
+            },
+        );
+        settle().await;
+        assert_eq!(newest.buffered(), 100);
+        assert_eq!(newest.dropped(), 9_900);
+        assert_eq!(newest.recv().await, Some(9_900));
+
+        let mut oldest = subscription::<u32>(
+            payloads.clone(),
+            SubscriberConfig {
+                capacity: 100,
+                policy: SlowConsumerPolicy::DropNewest,
+            },
+        );
+        settle().await;
+        assert_eq!(oldest.dropped(), 9_900);
+        assert_eq!(oldest.recv().await, Some(0));
+
+        // Blocking loses nothing; the reader waits for the consumer
+        let mut blocking = subscription::<u32>(
+            payloads,
+            SubscriberConfig {
+                capacity: 100,
+                policy: SlowConsumerPolicy::Block,
+            },
+        );
+        settle().await;
+        assert_eq!(blocking.buffered(), 100);
+        let mut received = 0;
+        while let Some(n) = blocking.recv().await {
+            assert_eq!(n, received);
+            received += 1;
+        }
+        assert_eq!(received, 10_000);
+        assert_eq!(blocking.dropped(), 0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_dropped_messages_are_counted_in_metrics() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let payloads = (0..30).map(|n| n.to_string()).collect();
+        let subscription = subscription::<u32>(
+            payloads,
+            SubscriberConfig {
+                capacity: 10,
+                policy: SlowConsumerPolicy::DropNewest,
+            },
+        );
+        settle().await;
+        assert_eq!(subscription.dropped(), 20);
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains(
+                r#"redis_pubsub_dropped_messages_total{channel="orders",policy="drop_newest"} 20"#
+            ),
+            "{rendered}"
+        );
+    }
 }
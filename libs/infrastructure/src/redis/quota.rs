@@ -0,0 +1,328 @@
+//! Daily API key quotas with timezone-aligned resets
+//!
+//! A quota that resets at UTC midnight resets mid-afternoon for some users
+//! and in the early morning for others.  [`DailyReset`] fixes the reset to
+//! local midnight in a time zone, globally or per API key, and
+//! [`ApiKeyQuota`] counts usage in one Redis key per local calendar day.
+//!
+//! Zones are IANA names such as `America/New_York`, so the reset stays at
+//! midnight across daylight saving changes; those days are 23 or 25 hours
+//! long.  A fixed offset like `+01:00` is accepted for zones without DST.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use common::time::{Clock, TimeSource};
+use time::{Date, OffsetDateTime, Time, UtcOffset, format_description::FormatItem};
+use time_tz::{Offset, PrimitiveDateTimeExt, TimeZone, Tz, timezones};
+
+use super::hyperloglog::daily_window;
+use super::{RedisError, RedisPool};
+use crate::redis::key::RedisKey;
+
+const OFFSET_FORMAT: &[FormatItem<'static>] =
+    time::macros::format_description!("[offset_hour sign:mandatory]:[offset_minute]");
+
+/// Counters outlive their window briefly so a request racing the reset
+/// never sees a key without expiry
+const EXPIRY_GRACE_SECS: i64 = 60;
+
+/// Where a [`DailyReset`]'s midnight is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Fixed(UtcOffset),
+    Named(&'static Tz),
+}
+
+/// Midnight reset in a time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyReset {
+    zone: Zone,
+}
+
+impl Default for DailyReset {
+    fn default() -> Self {
+        Self::UTC
+    }
+}
+
+impl DailyReset {
+    pub const UTC: Self = Self {
+        zone: Zone::Fixed(UtcOffset::UTC),
+    };
+
+    /// Reset at midnight in a fixed offset
+    pub fn new(offset: UtcOffset) -> Self {
+        Self {
+            zone: Zone::Fixed(offset),
+        }
+    }
+
+    /// Reset at midnight in an IANA zone such as `Europe/London`
+    pub fn in_zone(name: &str) -> Result<Self, RedisError> {
+        timezones::get_by_name(name.trim())
+            .map(|tz| Self {
+                zone: Zone::Named(tz),
+            })
+            .ok_or_else(|| RedisError::Configuration(format!("unknown quota time zone '{name}'")))
+    }
+
+    /// Parse an IANA zone name, or an offset such as `+01:00` or `-05:30`
+    pub fn parse(zone: &str) -> Result<Self, RedisError> {
+        if let Ok(offset) = UtcOffset::parse(zone.trim(), OFFSET_FORMAT) {
+            return Ok(Self::new(offset));
+        }
+        Self::in_zone(zone).map_err(|_| {
+            RedisError::Configuration(format!(
+                "invalid quota time zone '{zone}': expected a name like \
+                 'America/New_York' or an offset like '+01:00'"
+            ))
+        })
+    }
+
+    /// UTC offset in effect at `now`
+    pub fn offset_at(&self, now: OffsetDateTime) -> UtcOffset {
+        match self.zone {
+            Zone::Fixed(offset) => offset,
+            Zone::Named(tz) => tz.get_offset_utc(&now).to_utc(),
+        }
+    }
+
+    /// Local calendar day `now` falls in
+    pub fn window_date(&self, now: OffsetDateTime) -> Date {
+        now.to_offset(self.offset_at(now)).date()
+    }
+
+    /// Start of the current window
+    pub fn window_start(&self, now: OffsetDateTime) -> OffsetDateTime {
+        self.midnight(self.window_date(now))
+    }
+
+    /// Instant the current window ends and usage resets
+    pub fn next_reset(&self, now: OffsetDateTime) -> OffsetDateTime {
+        let date = self.window_date(now);
+        self.midnight(date.next_day().unwrap_or(date))
+    }
+
+    /// First instant of `date` in this zone
+    fn midnight(&self, date: Date) -> OffsetDateTime {
+        let local = date.with_time(Time::MIDNIGHT);
+        match self.zone {
+            Zone::Fixed(offset) => local.assume_offset(offset),
+            Zone::Named(tz) => local
+                .assume_timezone(tz)
+                .take_first()
+                // Clocks jumped past midnight: the day starts at the jump,
+                // which is midnight read in the offset before it
+                .unwrap_or_else(|| {
+                    let before = local.assume_utc() - time::Duration::DAY;
+                    local.assume_offset(tz.get_offset_utc(&before).to_utc())
+                }),
+        }
+    }
+}
+
+/// Result of recording one request against a quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub allowed: bool,
+    pub used: u64,
+    pub limit: u64,
+    pub remaining: u64,
+    pub resets_at: OffsetDateTime,
+}
+
+/// Redis-backed daily request quota per API key
+#[derive(Clone)]
+pub struct ApiKeyQuota {
+    pool: RedisPool,
+    prefix: String,
+    daily_limit: u64,
+    default_reset: DailyReset,
+    key_resets: HashMap<String, DailyReset>,
+    clock: Arc<dyn TimeSource>,
+}
+
+impl ApiKeyQuota {
+    /// Quota of `daily_limit` requests per key, resetting at UTC midnight
+    pub fn new(pool: RedisPool, prefix: impl Into<String>, daily_limit: u64) -> Self {
+        Self {
+            pool,
+            prefix: prefix.into(),
+            daily_limit,
+            default_reset: DailyReset::UTC,
+            key_resets: HashMap::new(),
+            clock: Arc::new(Clock),
+        }
+    }
+
+    /// Reset every key at midnight in `reset`'s zone
+    pub fn with_timezone(mut self, reset: DailyReset) -> Self {
+        self.default_reset = reset;
+        self
+    }
+
+    /// Override the reset for a single key
+    pub fn with_key_timezone(mut self, api_key: impl Into<String>, reset: DailyReset) -> Self {
+        self.key_resets.insert(api_key.into(), reset);
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn TimeSource>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Reset schedule that applies to `api_key`
+    pub fn reset_for(&self, api_key: &str) -> DailyReset {
+        self.key_resets
+            .get(api_key)
+            .copied()
+            .unwrap_or(self.default_reset)
+    }
+
+    /// Counter key for `api_key`'s window containing `now`
+    pub fn window_key(&self, api_key: &str, now: OffsetDateTime) -> RedisKey {
+        let window = daily_window(self.reset_for(api_key).window_date(now));
+        RedisKey::with_prefix(&self.prefix, ["quota", api_key, window.as_str()])
+    }
+
+    /// Record one request for `api_key`
+    pub async fn consume(&self, api_key: &str) -> Result<QuotaStatus, RedisError> {
+        self.consume_at(api_key, self.clock.now().inner()).await
+    }
+
+    /// Record one request for `api_key` as of `now`
+    pub async fn consume_at(
+        &self,
+        api_key: &str,
+        now: OffsetDateTime,
+    ) -> Result<QuotaStatus, RedisError> {
+        let key = self.window_key(api_key, now);
+        let resets_at = self.reset_for(api_key).next_reset(now);
+
+        let mut conn = self.pool.connection().await?;
+        let (used,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(key.as_str(), 1)
+            .expire_at(key.as_str(), resets_at.unix_timestamp() + EXPIRY_GRACE_SECS)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(QuotaStatus {
+            allowed: used <= self.daily_limit,
+            used,
+            limit: self.daily_limit,
+            remaining: self.daily_limit.saturating_sub(used),
+            resets_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::value_objects::timestamps::Timestamp;
+    use time::macros::datetime;
+
+    /// Clock stopped at one instant
+    struct StoppedClock(OffsetDateTime);
+
+    impl TimeSource for StoppedClock {
+        fn now(&self) -> Timestamp {
+            Timestamp::from_datetime(self.0)
+        }
+    }
+
+    fn quota() -> ApiKeyQuota {
+        let pool = RedisPool::lazy("redis://localhost:6379").unwrap();
+        ApiKeyQuota::new(pool, "app", 1000)
+    }
+
+    #[test]
+    fn test_quota_resets_at_configured_zone_midnight() {
+        let lagos = DailyReset::parse("+01:00").unwrap();
+        let quota = quota().with_key_timezone("key-lagos", lagos);
+
+        // 23:30 UTC is already 00:30 the next day in Lagos
+        let now = datetime!(2024-03-10 23:30 UTC);
+        assert_eq!(
+            quota.reset_for("key-lagos").window_start(now),
+            datetime!(2024-03-10 23:00 UTC)
+        );
+        assert_eq!(
+            quota.reset_for("key-lagos").next_reset(now),
+            datetime!(2024-03-11 23:00 UTC)
+        );
+        assert_eq!(
+            quota.window_key("key-lagos", now).as_str(),
+            "app:quota:key-lagos:2024-03-11"
+        );
+
+        // Keys without an override still follow UTC midnight
+        assert_eq!(
+            quota.reset_for("key-utc").next_reset(now),
+            datetime!(2024-03-11 00:00 UTC)
+        );
+        assert_eq!(
+            quota.window_key("key-utc", now).as_str(),
+            "app:quota:key-utc:2024-03-10"
+        );
+    }
+
+    #[test]
+    fn test_global_timezone_and_negative_offsets() {
+        let quota = quota().with_timezone(DailyReset::parse("-05:00").unwrap());
+        let now = datetime!(2024-03-11 03:00 UTC);
+        assert_eq!(
+            quota.reset_for("any").next_reset(now),
+            datetime!(2024-03-11 05:00 UTC)
+        );
+        assert!(DailyReset::parse("Lagos").is_err());
+    }
+
+    #[test]
+    fn test_named_zone_resets_at_local_midnight_across_dst() {
+        let new_york = DailyReset::parse("America/New_York").unwrap();
+
+        // Clocks spring forward on 2024-03-10, a 23-hour day
+        let now = datetime!(2024-03-10 12:00 UTC);
+        assert_eq!(new_york.window_start(now), datetime!(2024-03-10 05:00 UTC));
+        assert_eq!(new_york.next_reset(now), datetime!(2024-03-11 04:00 UTC));
+        // A fixed -05:00 would reset at 01:00 local all summer
+        let fixed = DailyReset::parse("-05:00").unwrap();
+        assert_eq!(fixed.next_reset(now), datetime!(2024-03-11 05:00 UTC));
+
+        // ...and fall back on 2024-11-03, a 25-hour day
+        let now = datetime!(2024-11-03 12:00 UTC);
+        assert_eq!(new_york.window_start(now), datetime!(2024-11-03 04:00 UTC));
+        assert_eq!(new_york.next_reset(now), datetime!(2024-11-04 05:00 UTC));
+
+        // Havana skips midnight itself; that day starts when clocks jump
+        let havana = DailyReset::in_zone("America/Havana").unwrap();
+        assert_eq!(
+            havana.window_start(datetime!(2024-03-10 12:00 UTC)),
+            datetime!(2024-03-10 05:00 UTC)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_consume_counts_within_window() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let now = OffsetDateTime::now_utc().replace_time(Time::from_hms(12, 0, 0).unwrap());
+        let quota = ApiKeyQuota::new(RedisPool::new(&url).await.unwrap(), "test", 2)
+            .with_timezone(DailyReset::in_zone("Europe/Berlin").unwrap())
+            .with_clock(Arc::new(StoppedClock(now)));
+        let api_key = format!("key-{}", std::process::id());
+
+        assert!(quota.consume(&api_key).await.unwrap().allowed);
+        let second = quota.consume(&api_key).await.unwrap();
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.resets_at, quota.reset_for(&api_key).next_reset(now));
+        assert!(!quota.consume(&api_key).await.unwrap().allowed);
+    }
+}
@@ -2,6 +2,29 @@
 //!
 //! Provides distributed rate limiting using Redis as the backing store.
 //!
+//! ## Redis outages
+//!
+//! A bare limiter returns an error when Redis can't be reached.
+//! [`RateLimiter::with_fallback`] wraps it with an [`OutagePolicy`] that
+//! decides instead:
+//!
+//! - [`OutagePolicy::FailClosed`] rejects every request until Redis is back;
+//!   use it for sensitive operations such as login or OTP verification.
+//! - [`OutagePolicy::FailOpen`] keeps admitting requests, approximating the
+//!   limit with an in-memory fixed window on this instance.  With several
+//!   instances the effective limit is multiplied, which is an acceptable
+//!   trade for non-sensitive endpoints.
+//!
+//! ```rust,no_run
+//! use infrastructure::redis::{OutagePolicy, RateLimiter, RedisPool, RedisRateLimiter};
+//!
+//! # fn build(pool: RedisPool) {
+//! let limiter = RedisRateLimiter::new(pool, "app");
+//! let login = limiter.clone().with_fallback(OutagePolicy::FailClosed);
+//! let search = limiter.with_fallback(OutagePolicy::FailOpen);
+//! # }
+//! ```
+//!
 //! ## Feature Flags
 //!
 //! - `redis`: Enables Redis support (enabled by default with `full` feature)
@@ -10,13 +33,74 @@
 use async_trait::async_trait;
 
 #[cfg(feature = "redis")]
-use std::time::Duration;
+use std::collections::HashMap;
+
+#[cfg(feature = "redis")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "redis")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "redis")]
+use tracing::warn;
 
 #[cfg(feature = "redis")]
 use super::{RedisError, RedisPool};
 #[cfg(feature = "redis")]
 use crate::redis::key::RedisKey;
 
+/// Sliding window over a sorted set of request timestamps (milliseconds)
+#[cfg(feature = "redis")]
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local key = KEYS[1]
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+
+-- Remove old entries outside the window
+redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window * 1000)
+
+-- Count current requests
+local count = redis.call('ZCARD', key)
+
+if count < limit then
+    -- Add new request
+    redis.call('ZADD', key, now, now .. ':' .. math.random(1000000))
+    redis.call('EXPIRE', key, window)
+    return {1, limit - count - 1}
+else
+    return {0, 0}
+end
+"#;
+
+/// Remaining requests in the sliding window, without recording one
+#[cfg(feature = "redis")]
+const SLIDING_REMAINING_SCRIPT: &str = r#"
+local key = KEYS[1]
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+
+-- Remove old entries
+redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window * 1000)
+
+local count = redis.call('ZCARD', key)
+
+if count < limit then
+    return limit - count
+else
+    return 0
+end
+"#;
+
+#[cfg(feature = "redis")]
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
 /// Rate limiter trait for distributed rate limiting
 #[cfg(feature = "redis")]
 #[async_trait]
@@ -36,6 +120,14 @@ pub trait RateLimiter: Send + Sync {
     async fn current(&self, key: &str) -> Result<u64, RedisError>;
     /// Get TTL for a key
     async fn ttl(&self, key: &str) -> Result<i64, RedisError>;
+
+    /// Decide by `policy` instead of failing while Redis is unavailable
+    fn with_fallback(self, policy: OutagePolicy) -> FallbackRateLimiter<Self>
+    where
+        Self: Sized,
+    {
+        FallbackRateLimiter::new(self, policy)
+    }
 }
 
 /// Redis rate limiter implementation using sliding window algorithm
@@ -71,130 +163,63 @@ impl RateLimiter for RedisRateLimiter {
         limit: u64,
         window: Duration,
     ) -> Result<(bool, u64), RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let prefixed_key = self.rate_limit_key(key);
+        let mut conn = self.pool.connection().await?;
 
         // Use sliding window with Lua script for atomicity
-        let lua_script = r#"
-            local key = KEYS[1]
-            local limit = tonumber(ARGV[1])
-            local window = tonumber(ARGV[2])
-            local now = tonumber(ARGV[3])
-            
-            -- Remove old entries outside the window
-            redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window * 1000)
-            
-            -- Count current requests
-            local count = redis.call('ZCARD', key)
-            
-            if count < limit then
-                -- Add new request
-                redis.call('ZADD', key, now, now .. ':' .. math.random(1000000))
-                redis.call('EXPIRE', key, window)
-                return {1, limit - count - 1}
-            else
-                return {0, 0}
-            end
-        "#;
-
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
-
-        let result: Vec<String> = redis::cmd("EVAL")
-            .arg(lua_script)
-            .arg(1)
-            .arg(prefixed_key.as_str())
+        let (allowed, remaining): (u64, u64) = redis::Script::new(SLIDING_WINDOW_SCRIPT)
+            .key(self.rate_limit_key(key).as_str())
             .arg(limit)
             .arg(window.as_secs())
-            .arg(now)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
-
-        if result.len() >= 2 {
-            let allowed: u64 = result[0].parse().unwrap_or(0);
-            let remaining: u64 = result[1].parse().unwrap_or(0);
-            Ok((allowed == 1, remaining))
-        } else {
-            Ok((false, 0))
-        }
+            .arg(now_millis())
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok((allowed == 1, remaining))
     }
 
     async fn remaining(&self, key: &str, limit: u64, window: Duration) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let prefixed_key = self.rate_limit_key(key);
-
-        let lua_script = r#"
-            local key = KEYS[1]
-            local limit = tonumber(ARGV[1])
-            local window = tonumber(ARGV[2])
-            local now = tonumber(ARGV[3])
-            
-            -- Remove old entries
-            redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window * 1000)
-            
-            local count = redis.call('ZCARD', key)
-            
-            if count < limit then
-                return limit - count
-            else
-                return 0
-            end
-        "#;
-
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
+        let mut conn = self.pool.connection().await?;
 
-        let remaining: u64 = redis::cmd("EVAL")
-            .arg(lua_script)
-            .arg(1)
-            .arg(prefixed_key.as_str())
+        let remaining: u64 = redis::Script::new(SLIDING_REMAINING_SCRIPT)
+            .key(self.rate_limit_key(key).as_str())
             .arg(limit)
             .arg(window.as_secs())
-            .arg(now)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(now_millis())
+            .invoke_async(&mut conn)
+            .await?;
 
         Ok(remaining)
     }
 
     async fn reset(&self, key: &str) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         redis::cmd("DEL")
             .arg(self.rate_limit_key(key).as_str())
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn current(&self, key: &str) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let count: u64 = redis::cmd("ZCARD")
             .arg(self.rate_limit_key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         Ok(count)
     }
 
     async fn ttl(&self, key: &str) -> Result<i64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let ttl: i64 = redis::cmd("TTL")
             .arg(self.rate_limit_key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         Ok(ttl)
     }
@@ -228,6 +253,28 @@ impl RedisFixedWindowRateLimiter {
             / window;
         RedisKey::from_parts([&self.prefix, "ratelimit", key, &window_id.to_string()])
     }
+
+    /// Keys of every window recorded for `key`
+    async fn window_keys(&self, key: &str) -> Result<Vec<String>, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let pattern = RedisKey::from_parts([&self.prefix, "ratelimit", key, "*"]);
+
+        let mut cursor = 0u64;
+        let mut keys = Vec::new();
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern.as_str())
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
 }
 
 #[cfg(feature = "redis")]
@@ -239,80 +286,308 @@ impl RateLimiter for RedisFixedWindowRateLimiter {
         limit: u64,
         window: Duration,
     ) -> Result<(bool, u64), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
         let prefixed_key = self.rate_limit_key(key, window.as_secs());
 
         let count: u64 = redis::cmd("INCR")
-            .arg(&prefixed_key)
-            .query_async(conn.clone())
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(prefixed_key.as_str())
+            .query_async(&mut conn)
+            .await?;
 
         if count == 1 {
             // First request, set expiry
-            let mut cmd = redis::cmd("EXPIRE");
-            cmd.arg(&prefixed_key).arg(window.as_secs());
-            cmd.query_async::<_, bool>(conn)
-                .await
-                .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            redis::cmd("EXPIRE")
+                .arg(prefixed_key.as_str())
+                .arg(window.as_secs())
+                .query_async::<bool>(&mut conn)
+                .await?;
         }
 
-        let remaining = if count > limit { 0 } else { limit - count + 1 };
-        Ok((count <= limit, remaining))
+        Ok((count <= limit, limit.saturating_sub(count)))
     }
 
     async fn remaining(&self, key: &str, limit: u64, window: Duration) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
         let prefixed_key = self.rate_limit_key(key, window.as_secs());
 
-        let count: u64 = redis::cmd("GET")
-            .arg(&prefixed_key)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        let count: Option<u64> = redis::cmd("GET")
+            .arg(prefixed_key.as_str())
+            .query_async(&mut conn)
+            .await?;
 
-        let remaining = if count >= limit { 0 } else { limit - count };
-        Ok(remaining)
+        Ok(limit.saturating_sub(count.unwrap_or(0)))
     }
 
     async fn reset(&self, key: &str) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        // For fixed window, we can't easily know all window keys, so scan
+        // for them
+        let keys = self.window_keys(key).await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
 
-        // For fixed window, we can't easily know all window keys
-        // This is a limitation of the fixed window algorithm
-        let pattern = format!("{}:ratelimit:{}:*", self.prefix, key);
+        let mut conn = self.pool.connection().await?;
         redis::cmd("DEL")
-            .arg(pattern)
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(&keys)
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn current(&self, key: &str) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
         let prefixed_key = self.rate_limit_key(key, 60); // Default to 60s window
 
-        let count: u64 = redis::cmd("GET")
-            .arg(&prefixed_key)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        let count: Option<u64> = redis::cmd("GET")
+            .arg(prefixed_key.as_str())
+            .query_async(&mut conn)
+            .await?;
 
-        Ok(count)
+        Ok(count.unwrap_or(0))
     }
 
     async fn ttl(&self, key: &str) -> Result<i64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
+        let prefixed_key = self.rate_limit_key(key, 60); // Default to 60s window
 
-        let pattern = format!("{}:ratelimit:{}:*", self.prefix, key);
         let ttl: i64 = redis::cmd("TTL")
-            .arg(pattern)
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(prefixed_key.as_str())
+            .query_async(&mut conn)
+            .await?;
 
         Ok(ttl)
     }
 }
+
+/// What a [`FallbackRateLimiter`] does while Redis is unavailable
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutagePolicy {
+    /// Reject every request
+    FailClosed,
+    /// Admit requests within an approximate, per-instance in-memory limit
+    FailOpen,
+}
+
+/// Fixed window counter for one key in the local fallback
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone, Copy)]
+struct LocalWindow {
+    started: Instant,
+    window: Duration,
+    count: u64,
+}
+
+#[cfg(feature = "redis")]
+impl LocalWindow {
+    fn expired(&self, now: Instant) -> bool {
+        now.duration_since(self.started) >= self.window
+    }
+}
+
+/// Rate limiter that applies an [`OutagePolicy`] when the wrapped limiter
+/// fails, see [`RateLimiter::with_fallback`]
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct FallbackRateLimiter<L> {
+    inner: L,
+    policy: OutagePolicy,
+    local: Arc<Mutex<HashMap<String, LocalWindow>>>,
+}
+
+#[cfg(feature = "redis")]
+impl<L: RateLimiter> FallbackRateLimiter<L> {
+    /// Wrap `inner`, deciding by `policy` while it fails
+    pub fn new(inner: L, policy: OutagePolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            local: Arc::default(),
+        }
+    }
+
+    pub fn policy(&self) -> OutagePolicy {
+        self.policy
+    }
+
+    /// Count a request against the local window, returning whether it fits
+    /// and how many remain
+    fn local_is_allowed(&self, key: &str, limit: u64, window: Duration) -> (bool, u64) {
+        let now = Instant::now();
+        let mut local = self.local.lock().unwrap();
+        // Drop finished windows so the map doesn't grow for the whole outage
+        local.retain(|_, w| !w.expired(now));
+
+        let entry = local.entry(key.to_string()).or_insert(LocalWindow {
+            started: now,
+            window,
+            count: 0,
+        });
+        if entry.count >= limit {
+            return (false, 0);
+        }
+        entry.count += 1;
+        (true, limit - entry.count)
+    }
+
+    fn local_window(&self, key: &str) -> Option<LocalWindow> {
+        let now = Instant::now();
+        self.local
+            .lock()
+            .unwrap()
+            .get(key)
+            .copied()
+            .filter(|w| !w.expired(now))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl<L: RateLimiter> RateLimiter for FallbackRateLimiter<L> {
+    async fn is_allowed(
+        &self,
+        key: &str,
+        limit: u64,
+        window: Duration,
+    ) -> Result<(bool, u64), RedisError> {
+        match self.inner.is_allowed(key, limit, window).await {
+            Ok(decision) => Ok(decision),
+            Err(e) => {
+                warn!(key, error = %e, policy = ?self.policy, "rate limiter unavailable, using fallback");
+                #[cfg(feature = "metrics")]
+                crate::observability::record_degradation(
+                    "rate_limiter",
+                    match self.policy {
+                        OutagePolicy::FailClosed => "fail_closed",
+                        OutagePolicy::FailOpen => "fail_open",
+                    },
+                );
+                Ok(match self.policy {
+                    OutagePolicy::FailClosed => (false, 0),
+                    OutagePolicy::FailOpen => self.local_is_allowed(key, limit, window),
+                })
+            }
+        }
+    }
+
+    async fn remaining(&self, key: &str, limit: u64, window: Duration) -> Result<u64, RedisError> {
+        match self.inner.remaining(key, limit, window).await {
+            Ok(remaining) => Ok(remaining),
+            Err(_) => Ok(match self.policy {
+                OutagePolicy::FailClosed => 0,
+                OutagePolicy::FailOpen => {
+                    limit.saturating_sub(self.local_window(key).map_or(0, |w| w.count))
+                }
+            }),
+        }
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), RedisError> {
+        self.local.lock().unwrap().remove(key);
+        self.inner.reset(key).await
+    }
+
+    async fn current(&self, key: &str) -> Result<u64, RedisError> {
+        match self.inner.current(key).await {
+            Ok(count) => Ok(count),
+            Err(_) => Ok(self.local_window(key).map_or(0, |w| w.count)),
+        }
+    }
+
+    async fn ttl(&self, key: &str) -> Result<i64, RedisError> {
+        match self.inner.ttl(key).await {
+            Ok(ttl) => Ok(ttl),
+            // -2 matches Redis for a key that doesn't exist
+            Err(_) => Ok(self.local_window(key).map_or(-2, |w| {
+                w.window
+                    .saturating_sub(w.started.elapsed())
+                    .as_secs()
+                    .try_into()
+                    .unwrap_or(i64::MAX)
+            })),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod tests {
+    use super::*;
+
+    /// Nothing listens on port 1, so every Redis call fails
+    fn redis_down() -> RedisRateLimiter {
+        RedisRateLimiter::new(RedisPool::lazy("redis://127.0.0.1:1").unwrap(), "app")
+    }
+
+    #[tokio::test]
+    async fn test_redis_down_fails_closed_or_open_by_policy() {
+        let window = Duration::from_secs(60);
+        assert!(
+            redis_down()
+                .is_allowed("login:ip:1", 3, window)
+                .await
+                .is_err()
+        );
+
+        let login = redis_down().with_fallback(OutagePolicy::FailClosed);
+        assert_eq!(
+            login.is_allowed("login:ip:1", 3, window).await.unwrap(),
+            (false, 0)
+        );
+
+        let search = redis_down().with_fallback(OutagePolicy::FailOpen);
+        for expected_remaining in [2, 1, 0] {
+            assert_eq!(
+                search.is_allowed("search:ip:1", 3, window).await.unwrap(),
+                (true, expected_remaining)
+            );
+        }
+        // The local fallback still approximates the limit
+        assert_eq!(
+            search.is_allowed("search:ip:1", 3, window).await.unwrap(),
+            (false, 0)
+        );
+        assert!(search.is_allowed("search:ip:2", 3, window).await.unwrap().0);
+        assert_eq!(search.current("search:ip:1").await.unwrap(), 3);
+        assert_eq!(search.remaining("search:ip:1", 3, window).await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_fallback_is_counted_as_a_degradation() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let limiter = redis_down().with_fallback(OutagePolicy::FailOpen);
+        for _ in 0..3 {
+            assert!(
+                limiter
+                    .is_allowed("search:ip:1", 10, Duration::from_secs(60))
+                    .await
+                    .unwrap()
+                    .0
+            );
+        }
+
+        let rendered = handle.render();
+        assert!(
+            rendered
+                .contains(r#"degradations_total{component="rate_limiter",reason="fail_open"} 3"#),
+            "{rendered}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_window_resets_after_it_elapses() {
+        let limiter = redis_down().with_fallback(OutagePolicy::FailOpen);
+        let window = Duration::from_millis(50);
+
+        assert!(limiter.is_allowed("k", 1, window).await.unwrap().0);
+        assert!(!limiter.is_allowed("k", 1, window).await.unwrap().0);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(limiter.is_allowed("k", 1, window).await.unwrap().0);
+    }
+}
@@ -0,0 +1,244 @@
+//! Distributed counting semaphore on Redis
+//!
+//! Caps how many holders share a key across every instance, e.g. the
+//! requests a single user has in flight.  Each permit is a member of a
+//! sorted set scored by when its lease lapses in Redis server time, so
+//! permits of a crashed instance, or of a request that was cancelled before
+//! releasing, free themselves once the lease runs out.
+//!
+//! ```rust,ignore
+//! if let Some(permit) = semaphore.acquire("user:42", 4, Duration::from_secs(60)).await? {
+//!     handle(request).await;
+//!     semaphore.release(&permit).await?;
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use common::security::RandomGenerator;
+
+use super::{RedisError, RedisPool};
+use crate::redis::key::RedisKey;
+
+/// Drop lapsed permits, then add `ARGV[3]` if fewer than `ARGV[1]` are
+/// held.  Returns 1 when the permit was granted.
+const ACQUIRE_SCRIPT: &str = r#"
+local now = redis.call('TIME')
+local now_ms = now[1] * 1000 + math.floor(now[2] / 1000)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now_ms)
+if redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('ZADD', KEYS[1], now_ms + tonumber(ARGV[2]), ARGV[3])
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Permits currently held, after dropping lapsed ones
+const HELD_SCRIPT: &str = r#"
+local now = redis.call('TIME')
+local now_ms = now[1] * 1000 + math.floor(now[2] / 1000)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now_ms)
+return redis.call('ZCARD', KEYS[1])
+"#;
+
+/// One slot of a semaphore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemaphorePermit {
+    pub key: String,
+    /// Identifies this permit among the key's holders
+    pub holder: String,
+}
+
+impl SemaphorePermit {
+    fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            holder: RandomGenerator::hex(32),
+        }
+    }
+}
+
+/// Distributed semaphore trait
+#[async_trait]
+pub trait DistributedSemaphore: Send + Sync {
+    /// Take one of `limit` permits on `key` for at most `lease`, or `None`
+    /// if all are held
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: u64,
+        lease: Duration,
+    ) -> Result<Option<SemaphorePermit>, RedisError>;
+
+    /// Give a permit back; `false` if its lease had already lapsed
+    async fn release(&self, permit: &SemaphorePermit) -> Result<bool, RedisError>;
+
+    /// Permits currently held on `key`
+    async fn held(&self, key: &str) -> Result<u64, RedisError>;
+}
+
+/// Redis-based distributed semaphore
+#[derive(Clone)]
+pub struct RedisSemaphore {
+    pool: RedisPool,
+    prefix: String,
+}
+
+impl RedisSemaphore {
+    /// Create a new Redis semaphore
+    pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
+        Self {
+            pool,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn semaphore_key(&self, key: &str) -> RedisKey {
+        RedisKey::with_prefix(&self.prefix, ["semaphore", key])
+    }
+}
+
+#[async_trait]
+impl DistributedSemaphore for RedisSemaphore {
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: u64,
+        lease: Duration,
+    ) -> Result<Option<SemaphorePermit>, RedisError> {
+        let permit = SemaphorePermit::new(key);
+        let mut conn = self.pool.connection().await?;
+        let granted: u64 = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(self.semaphore_key(key).as_str())
+            .arg(limit)
+            .arg((lease.as_millis() as u64).max(1))
+            .arg(&permit.holder)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok((granted == 1).then_some(permit))
+    }
+
+    async fn release(&self, permit: &SemaphorePermit) -> Result<bool, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let removed: u64 = redis::cmd("ZREM")
+            .arg(self.semaphore_key(&permit.key).as_str())
+            .arg(&permit.holder)
+            .query_async(&mut conn)
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn held(&self, key: &str) -> Result<u64, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let held: u64 = redis::Script::new(HELD_SCRIPT)
+            .key(self.semaphore_key(key).as_str())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(held)
+    }
+}
+
+/// In-process semaphore, standing in for Redis in tests and single-instance
+/// deployments
+#[derive(Default)]
+pub struct InMemorySemaphore {
+    /// Lease expiry of each holder, by key
+    held: Mutex<HashMap<String, HashMap<String, Instant>>>,
+}
+
+impl InMemorySemaphore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live_holders<'a>(
+        held: &'a mut HashMap<String, HashMap<String, Instant>>,
+        key: &str,
+    ) -> &'a mut HashMap<String, Instant> {
+        let now = Instant::now();
+        let holders = held.entry(key.to_string()).or_default();
+        holders.retain(|_, expires_at| *expires_at > now);
+        holders
+    }
+}
+
+#[async_trait]
+impl DistributedSemaphore for InMemorySemaphore {
+    async fn acquire(
+        &self,
+        key: &str,
+        limit: u64,
+        lease: Duration,
+    ) -> Result<Option<SemaphorePermit>, RedisError> {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        let holders = Self::live_holders(&mut held, key);
+        if holders.len() as u64 >= limit {
+            return Ok(None);
+        }
+        let permit = SemaphorePermit::new(key);
+        holders.insert(permit.holder.clone(), Instant::now() + lease);
+        Ok(Some(permit))
+    }
+
+    async fn release(&self, permit: &SemaphorePermit) -> Result<bool, RedisError> {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        let holders = Self::live_holders(&mut held, &permit.key);
+        let released = holders.remove(&permit.holder).is_some();
+        if holders.is_empty() {
+            held.remove(&permit.key);
+        }
+        Ok(released)
+    }
+
+    async fn held(&self, key: &str) -> Result<u64, RedisError> {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(Self::live_holders(&mut held, key).len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_permits_are_capped_per_key_and_lapse_with_their_lease() {
+        let semaphore = InMemorySemaphore::new();
+        let lease = Duration::from_secs(60);
+
+        let first = semaphore.acquire("user:1", 2, lease).await.unwrap().unwrap();
+        let _second = semaphore.acquire("user:1", 2, lease).await.unwrap().unwrap();
+        assert!(semaphore.acquire("user:1", 2, lease).await.unwrap().is_none());
+        assert!(semaphore.acquire("user:2", 2, lease).await.unwrap().is_some());
+
+        assert!(semaphore.release(&first).await.unwrap());
+        assert!(!semaphore.release(&first).await.unwrap());
+        assert_eq!(semaphore.held("user:1").await.unwrap(), 1);
+
+        let short = Duration::from_millis(20);
+        semaphore.acquire("user:3", 1, short).await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(semaphore.acquire("user:3", 1, short).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_redis_semaphore_caps_holders() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let semaphore = RedisSemaphore::new(RedisPool::new(&url).await.unwrap(), "test");
+        let key = format!("semaphore-{}", std::process::id());
+        let lease = Duration::from_millis(200);
+
+        let first = semaphore.acquire(&key, 2, lease).await.unwrap().unwrap();
+        semaphore.acquire(&key, 2, lease).await.unwrap().unwrap();
+        assert!(semaphore.acquire(&key, 2, lease).await.unwrap().is_none());
+        assert!(semaphore.release(&first).await.unwrap());
+        assert_eq!(semaphore.held(&key).await.unwrap(), 1);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(semaphore.held(&key).await.unwrap(), 0);
+    }
+}
@@ -2,27 +2,51 @@
 //!
 //! Provides session management using Redis as the backing store.
 //!
+//! A session id that exists before the user authenticates (handed out to an
+//! anonymous visitor, or planted by an attacker) must not survive the
+//! authentication, or whoever knew it shares the authenticated session.
+//! After every privilege change (login, step-up, role change) call
+//! [`SessionStore::rotate`]: the session's data moves to a fresh id and the
+//! old id stops resolving.
+//!
+//! ```rust,ignore
+//! let session_id = match sessions.rotate(&session_id).await? {
+//!     Some(rotated) => rotated,
+//!     None => return Err(AuthError::SessionExpired),
+//! };
+//! set_session_cookie(&mut response, &session_id);
+//! ```
+//!
 //! ## Feature Flags
 //!
 //! - `redis`: Enables Redis support (enabled by default with `full` feature)
 
 #[cfg(feature = "redis")]
-use async_trait::async_trait;
-
+use std::collections::HashMap;
 #[cfg(feature = "redis")]
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::sync::Mutex;
+#[cfg(feature = "redis")]
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "redis")]
-use std::time::Duration;
+use async_trait::async_trait;
+#[cfg(feature = "redis")]
+use common::security::SecretGenerator;
+#[cfg(feature = "redis")]
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "redis")]
 use super::{RedisError, RedisPool};
 #[cfg(feature = "redis")]
 use crate::redis::key::RedisKey;
 
+/// How long a session stays alive after activity
+#[cfg(feature = "redis")]
+const SESSION_IDLE_TTL: Duration = Duration::from_secs(86400);
+
 /// Session data structure
 #[cfg(feature = "redis")]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionData {
     pub user_id: String,
     pub email: String,
@@ -35,6 +59,12 @@ pub struct SessionData {
     pub last_activity: String,
 }
 
+/// Fresh, unguessable session id
+#[cfg(feature = "redis")]
+fn new_session_id() -> String {
+    SecretGenerator::token().expose().to_string()
+}
+
 /// Session store trait
 #[cfg(feature = "redis")]
 #[async_trait]
@@ -56,6 +86,12 @@ pub trait SessionStore: Send + Sync {
     async fn delete_user_sessions(&self, user_id: &str) -> Result<u64, RedisError>;
     /// Get all sessions for a user
     async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<SessionData>, RedisError>;
+    /// Move a session to a new id, keeping its data and remaining TTL, and
+    /// invalidate `old_session_id`
+    ///
+    /// Returns the new id, or `None` when `old_session_id` doesn't exist
+    /// (expired, deleted, or already rotated by a concurrent request).
+    async fn rotate(&self, old_session_id: &str) -> Result<Option<String>, RedisError>;
 }
 
 /// Redis session store implementation
@@ -87,6 +123,18 @@ impl RedisSessionStore {
     }
 }
 
+#[cfg(feature = "redis")]
+fn decode(json: &str) -> Result<SessionData, RedisError> {
+    serde_json::from_str(json)
+        .map_err(|e| RedisError::Command(format!("invalid session data: {e}")))
+}
+
+#[cfg(feature = "redis")]
+fn encode(session: &SessionData) -> Result<String, RedisError> {
+    serde_json::to_string(session)
+        .map_err(|e| RedisError::Command(format!("failed to encode session: {e}")))
+}
+
 #[cfg(feature = "redis")]
 #[async_trait]
 impl SessionStore for RedisSessionStore {
@@ -96,130 +144,107 @@ impl SessionStore for RedisSessionStore {
         session: &SessionData,
         ttl: Duration,
     ) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
-        let data = serde_json::to_string(session)
-            .map_err(|e| RedisError::serialization("JSON", e.to_string()))?;
+        let mut conn = self.pool.connection().await?;
+        let data = encode(session)?;
 
         // Save session data
-        let mut cmd = redis::cmd("SET");
-        cmd.arg(self.session_key(key).as_str())
+        redis::cmd("SET")
+            .arg(self.session_key(key).as_str())
             .arg(data)
             .arg("EX")
-            .arg(ttl.as_secs());
-
-        cmd.query_async::<_, String>(conn.clone())
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(ttl.as_secs().max(1))
+            .query_async::<()>(&mut conn)
+            .await?;
 
         // Add to user's session set
-        let mut cmd = redis::cmd("SADD");
-        cmd.arg(self.user_sessions_key(&session.user_id).as_str())
-            .arg(key);
-
-        cmd.query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        redis::cmd("SADD")
+            .arg(self.user_sessions_key(&session.user_id).as_str())
+            .arg(key)
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn get_session(&self, key: &str) -> Result<Option<SessionData>, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         let data: Option<String> = redis::cmd("GET")
             .arg(self.session_key(key).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
-        match data {
-            Some(json) => {
-                let session = serde_json::from_str(&json)
-                    .map_err(|e| RedisError::deserialization("JSON", e.to_string()))?;
-                Ok(Some(session))
-            }
-            None => Ok(None),
-        }
+        data.as_deref().map(decode).transpose()
     }
 
     async fn delete_session(&self, key: &str) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         // Get session data first to remove from user sessions set
-        let session = self.get_session(key).await?;
-
-        if let Some(s) = session {
-            let mut cmd = redis::cmd("SREM");
-            cmd.arg(self.user_sessions_key(&s.user_id).as_str())
-                .arg(key);
-
-            cmd.query_async::<_, u64>(conn.clone())
-                .await
-                .map_err(|e| RedisError::command("redis", e.to_string()))?;
+        if let Some(session) = self.get_session(key).await? {
+            redis::cmd("SREM")
+                .arg(self.user_sessions_key(&session.user_id).as_str())
+                .arg(key)
+                .query_async::<u64>(&mut conn)
+                .await?;
         }
 
         // Delete session data
         redis::cmd("DEL")
             .arg(self.session_key(key).as_str())
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn update_activity(&self, key: &str) -> Result<(), RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         redis::cmd("EXPIRE")
             .arg(self.session_key(key).as_str())
-            .arg(86400) // 24 hours TTL
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .arg(SESSION_IDLE_TTL.as_secs())
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(())
     }
 
     async fn delete_user_sessions(&self, user_id: &str) -> Result<u64, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         // Get all session IDs for this user
         let session_ids: Vec<String> = redis::cmd("SMEMBERS")
             .arg(self.user_sessions_key(user_id).as_str())
-            .query_async(conn.clone())
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         let mut deleted_count = 0;
         for session_id in &session_ids {
             redis::cmd("DEL")
                 .arg(self.session_key(session_id).as_str())
-                .query_async::<_, u64>(conn.clone())
-                .await
-                .map_err(|e| RedisError::command("redis", e.to_string()))?;
+                .query_async::<u64>(&mut conn)
+                .await?;
             deleted_count += 1;
         }
 
         // Delete the user's session set
         redis::cmd("DEL")
             .arg(self.user_sessions_key(user_id).as_str())
-            .query_async::<_, u64>(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async::<u64>(&mut conn)
+            .await?;
 
         Ok(deleted_count)
     }
 
     async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<SessionData>, RedisError> {
-        let conn = self.pool.get_connection().await?;
+        let mut conn = self.pool.connection().await?;
 
         // Get all session IDs for this user
         let session_ids: Vec<String> = redis::cmd("SMEMBERS")
             .arg(self.user_sessions_key(user_id).as_str())
-            .query_async(conn)
-            .await
-            .map_err(|e| RedisError::command("redis", e.to_string()))?;
+            .query_async(&mut conn)
+            .await?;
 
         let mut sessions = Vec::new();
         for session_id in session_ids {
@@ -230,26 +255,168 @@ impl SessionStore for RedisSessionStore {
 
         Ok(sessions)
     }
+
+    async fn rotate(&self, old_session_id: &str) -> Result<Option<String>, RedisError> {
+        let mut conn = self.pool.connection().await?;
+        let old_key = self.session_key(old_session_id);
+
+        let ttl_ms: i64 = redis::cmd("PTTL")
+            .arg(old_key.as_str())
+            .query_async(&mut conn)
+            .await?;
+        // GETDEL lets exactly one of several concurrent rotations take the
+        // session; the old id is gone before the new one exists
+        let data: Option<String> = redis::cmd("GETDEL")
+            .arg(old_key.as_str())
+            .query_async(&mut conn)
+            .await?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        let mut session = decode(&data)?;
+        let new_session_id = new_session_id();
+        session.session_id = new_session_id.clone();
+        let mut set = redis::cmd("SET");
+        set.arg(self.session_key(&new_session_id).as_str())
+            .arg(encode(&session)?);
+        if ttl_ms > 0 {
+            set.arg("PX").arg(ttl_ms);
+        }
+        set.query_async::<()>(&mut conn).await?;
+
+        let user_sessions = self.user_sessions_key(&session.user_id);
+        redis::cmd("SREM")
+            .arg(user_sessions.as_str())
+            .arg(old_session_id)
+            .query_async::<u64>(&mut conn)
+            .await?;
+        redis::cmd("SADD")
+            .arg(user_sessions.as_str())
+            .arg(&new_session_id)
+            .query_async::<u64>(&mut conn)
+            .await?;
+
+        Ok(Some(new_session_id))
+    }
+}
+
+/// A stored session and when it lapses
+#[cfg(feature = "redis")]
+struct StoredSession {
+    data: SessionData,
+    expires_at: Instant,
+}
+
+/// In-process session store, standing in for Redis in tests and
+/// single-instance deployments
+#[cfg(feature = "redis")]
+#[derive(Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+}
+
+#[cfg(feature = "redis")]
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live_sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, StoredSession>> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        sessions.retain(|_, stored| stored.expires_at > now);
+        sessions
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn save_session(
+        &self,
+        key: &str,
+        session: &SessionData,
+        ttl: Duration,
+    ) -> Result<(), RedisError> {
+        self.live_sessions().insert(
+            key.to_string(),
+            StoredSession {
+                data: session.clone(),
+                expires_at: Instant::now() + ttl,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_session(&self, key: &str) -> Result<Option<SessionData>, RedisError> {
+        Ok(self
+            .live_sessions()
+            .get(key)
+            .map(|stored| stored.data.clone()))
+    }
+
+    async fn delete_session(&self, key: &str) -> Result<(), RedisError> {
+        self.live_sessions().remove(key);
+        Ok(())
+    }
+
+    async fn update_activity(&self, key: &str) -> Result<(), RedisError> {
+        if let Some(stored) = self.live_sessions().get_mut(key) {
+            stored.expires_at = Instant::now() + SESSION_IDLE_TTL;
+        }
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &str) -> Result<u64, RedisError> {
+        let mut sessions = self.live_sessions();
+        let before = sessions.len();
+        sessions.retain(|_, stored| stored.data.user_id != user_id);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<SessionData>, RedisError> {
+        Ok(self
+            .live_sessions()
+            .values()
+            .filter(|stored| stored.data.user_id == user_id)
+            .map(|stored| stored.data.clone())
+            .collect())
+    }
+
+    async fn rotate(&self, old_session_id: &str) -> Result<Option<String>, RedisError> {
+        let mut sessions = self.live_sessions();
+        let Some(mut stored) = sessions.remove(old_session_id) else {
+            return Ok(None);
+        };
+        let new_session_id = new_session_id();
+        stored.data.session_id = new_session_id.clone();
+        sessions.insert(new_session_id.clone(), stored);
+        Ok(Some(new_session_id))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
 
-    #[tokio::test]
-    async fn test_session_data_serde() {
-        let session = SessionData {
+    fn session(session_id: &str) -> SessionData {
+        SessionData {
             user_id: "user-123".to_string(),
             email: "test@example.com".to_string(),
             role: "BUYER".to_string(),
-            session_id: "sess-456".to_string(),
+            session_id: session_id.to_string(),
             device_id: "device-789".to_string(),
             user_agent: "Mozilla/5.0".to_string(),
             ip_address: "192.168.1.1".to_string(),
             created_at: "2024-01-01T00:00:00Z".to_string(),
             last_activity: "2024-01-01T00:00:00Z".to_string(),
-        };
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_data_serde() {
+        let session = session("sess-456");
 
         let json = serde_json::to_string(&session).unwrap();
         let decoded: SessionData = serde_json::from_str(&json).unwrap();
@@ -258,4 +425,50 @@ mod tests {
         assert_eq!(session.email, decoded.email);
         assert_eq!(session.role, decoded.role);
     }
+
+    #[tokio::test]
+    async fn test_rotation_invalidates_the_old_id_and_keeps_the_data() {
+        let store = InMemorySessionStore::new();
+        store
+            .save_session("sess-456", &session("sess-456"), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        let rotated = store.rotate("sess-456").await.unwrap().unwrap();
+        assert_ne!(rotated, "sess-456");
+        assert!(store.get_session("sess-456").await.unwrap().is_none());
+        let moved = store.get_session(&rotated).await.unwrap().unwrap();
+        assert_eq!(
+            moved,
+            SessionData {
+                session_id: rotated.clone(),
+                ..session("sess-456")
+            }
+        );
+        assert_eq!(store.get_user_sessions("user-123").await.unwrap(), [moved]);
+
+        // The old id can't be rotated again, e.g. by whoever planted it
+        assert!(store.rotate("sess-456").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_redis_rotation_moves_the_session() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let store = RedisSessionStore::new(RedisPool::new(&url).await.unwrap(), "test");
+        let old = format!("sess-{}", std::process::id());
+        store
+            .save_session(&old, &session(&old), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        let rotated = store.rotate(&old).await.unwrap().unwrap();
+        assert!(store.get_session(&old).await.unwrap().is_none());
+        let moved = store.get_session(&rotated).await.unwrap().unwrap();
+        assert_eq!(moved.session_id, rotated);
+        assert_eq!(moved.device_id, "device-789");
+        assert!(store.rotate(&old).await.unwrap().is_none());
+
+        store.delete_user_sessions("user-123").await.unwrap();
+    }
 }
@@ -1,12 +1,38 @@
 //! Bulkhead pattern implementation for resource isolation
 //!
 //! Limits concurrent executions to prevent resource exhaustion.
+//!
+//! [`Bulkhead`] caps concurrency within one process.  [`PerUserConcurrency`]
+//! caps the requests each user has in flight across all instances, using a
+//! [`DistributedSemaphore`] keyed by user id, and answers `429` past the cap.
+//! Rate limiting bounds how often a user calls; this bounds how much of the
+//! service they hold at once.
+//!
+//! [`Bulkhead::call`] waits for a permit; [`Bulkhead::try_call`] gives up
+//! at once when every permit is in use.  Both run in a `resilience.bulkhead`
+//! span recording the `outcome`: `success`, or `rejected` when no permit was
+//! granted.
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
 use thiserror::Error;
 use tokio::sync::Semaphore;
-use tracing::warn;
+use tracing::{Instrument, Span, field, info_span, warn};
+
+#[cfg(all(feature = "http", feature = "redis"))]
+use crate::redis::DistributedSemaphore;
+#[cfg(all(feature = "http", feature = "redis"))]
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+#[cfg(all(feature = "http", feature = "redis"))]
+use common::middleware::AuthContext;
+#[cfg(all(feature = "http", feature = "redis"))]
+use error::http::ApiError;
+#[cfg(all(feature = "http", feature = "redis"))]
+use std::time::Duration;
 
 /// Bulkhead error
 #[derive(Debug, Error, Clone)]
@@ -68,28 +94,43 @@ impl Bulkhead {
         F: FnOnce() -> Fut,
         Fut: std::future::Future<Output = T>,
     {
+        let span = self.span();
         // Try to acquire permit
         let permit = self.semaphore.acquire().await;
         match permit {
             Ok(_permit) => {
-                let current = self.current_count.fetch_add(1, Ordering::AcqRel) + 1;
-                
-                let result = f().await;
-                
+                self.current_count.fetch_add(1, Ordering::AcqRel);
+
+                let result = f().instrument(span.clone()).await;
+
                 self.current_count.fetch_sub(1, Ordering::AcqRel);
+                span.record("outcome", "success");
                 Ok(result)
             }
-            Err(_) => {
-                let current = self.current_count.load(Ordering::Acquire);
-                warn!(
-                    "Bulkhead rejected: maximum {} concurrent requests, {} in use",
-                    self.max_concurrent, current
-                );
-                Err(BulkheadError::Rejected {
-                    max: self.max_concurrent,
-                    current,
-                })
-            }
+            Err(_) => Err(self.reject(&span)),
+        }
+    }
+
+    fn span(&self) -> Span {
+        info_span!(
+            "resilience.bulkhead",
+            max_concurrent = self.max_concurrent,
+            outcome = field::Empty,
+        )
+    }
+
+    fn reject(&self, span: &Span) -> BulkheadError {
+        let current = self.current_count.load(Ordering::Acquire);
+        span.in_scope(|| {
+            warn!(
+                "Bulkhead rejected: maximum {} concurrent requests, {} in use",
+                self.max_concurrent, current
+            );
+        });
+        span.record("outcome", "rejected");
+        BulkheadError::Rejected {
+            max: self.max_concurrent,
+            current,
         }
     }
 
@@ -98,32 +139,123 @@ impl Bulkhead {
     where
         F: FnOnce() -> T,
     {
+        let span = self.span();
         match self.semaphore.try_acquire() {
             Ok(_permit) => {
                 self.current_count.fetch_add(1, Ordering::AcqRel);
-                let result = f();
+                let result = span.in_scope(f);
                 self.current_count.fetch_sub(1, Ordering::AcqRel);
+                span.record("outcome", "success");
                 Ok(result)
             }
-            Err(_) => {
-                let current = self.current_count.load(Ordering::Acquire);
-                warn!(
-                    "Bulkhead rejected: maximum {} concurrent requests, {} in use",
-                    self.max_concurrent, current
-                );
-                Err(BulkheadError::Rejected {
-                    max: self.max_concurrent,
-                    current,
-                })
-            }
+            Err(_) => Err(self.reject(&span)),
         }
     }
 }
 
+/// Per-user cap on in-flight requests, shared across instances
+///
+/// A permit's lease bounds how long a request that never released it (a
+/// crashed instance, a cancelled handler) keeps counting against its user.
+#[cfg(all(feature = "http", feature = "redis"))]
+#[derive(Clone)]
+pub struct PerUserConcurrency {
+    semaphore: Arc<dyn DistributedSemaphore>,
+    max_in_flight: u64,
+    lease: Duration,
+}
+
+#[cfg(all(feature = "http", feature = "redis"))]
+impl PerUserConcurrency {
+    /// Allow each user `max_in_flight` simultaneous requests
+    pub fn new(semaphore: Arc<dyn DistributedSemaphore>, max_in_flight: u64) -> Self {
+        Self {
+            semaphore,
+            max_in_flight,
+            lease: Duration::from_secs(60),
+        }
+    }
+
+    /// Longest a request holds its permit if it never releases it
+    /// (default 60s); keep it above the request timeout
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    pub fn max_in_flight(&self) -> u64 {
+        self.max_in_flight
+    }
+
+    fn key(user_id: &str) -> String {
+        format!("user:{user_id}")
+    }
+}
+
+/// Middleware rejecting a user's requests with `429` while they already
+/// have the maximum in flight
+///
+/// Unauthenticated requests pass through.  If the semaphore is unreachable
+/// requests are admitted, leaving rate limiting as the remaining guard.
+#[cfg(all(feature = "http", feature = "redis"))]
+pub async fn per_user_concurrency_middleware(
+    req: Request,
+    next: Next,
+    limit: PerUserConcurrency,
+) -> Response {
+    let Some(user_id) = req
+        .extensions()
+        .get::<Arc<AuthContext>>()
+        .map(|context| context.user_id.clone())
+    else {
+        return next.run(req).await;
+    };
+
+    let key = PerUserConcurrency::key(&user_id);
+    let permit = match limit
+        .semaphore
+        .acquire(&key, limit.max_in_flight, limit.lease)
+        .await
+    {
+        Ok(Some(permit)) => Some(permit),
+        Ok(None) => {
+            warn!(
+                user_id,
+                max = limit.max_in_flight,
+                "per-user concurrency limit reached"
+            );
+            return ApiError::rate_limited_with_retry("Too many concurrent requests", 1)
+                .into_response();
+        }
+        Err(e) => {
+            warn!(user_id, error = %e, "concurrency limiter unavailable, admitting request");
+            None
+        }
+    };
+
+    let response = next.run(req).await;
+    if let Some(permit) = permit
+        && let Err(e) = limit.semaphore.release(&permit).await
+    {
+        warn!(user_id, error = %e, "failed to release concurrency permit");
+    }
+    response
+}
+
+/// Create per-user concurrency middleware
+#[cfg(all(feature = "http", feature = "redis"))]
+pub fn make_per_user_concurrency_middleware(
+    limit: PerUserConcurrency,
+) -> impl Fn(Request, Next) -> futures_util::future::BoxFuture<'static, Response> + Clone {
+    move |req: Request, next: Next| {
+        let limit = limit.clone();
+        Box::pin(per_user_concurrency_middleware(req, next, limit))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::atomic::AtomicBool;
     use std::time::Duration;
 
     #[tokio::test]
@@ -140,15 +272,21 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_bulkhead_rejects_beyond_limit() {
+    async fn test_bulkhead_waits_beyond_limit() {
         let bulkhead = Bulkhead::new(BulkheadConfig {
             max_concurrent: 1,
         });
 
-        let _guard = bulkhead.semaphore.acquire().await.unwrap();
-        let result = bulkhead.call(|| async { 1 }).await;
+        let guard = bulkhead.semaphore.acquire().await.unwrap();
+        let mut call = Box::pin(bulkhead.call(|| async { 1 }));
+        let waited = tokio::time::timeout(Duration::from_millis(20), &mut call).await;
+        assert!(waited.is_err(), "call ran while every permit was held");
 
-        assert!(result.is_err());
+        drop(guard);
+        assert_eq!(call.await.unwrap(), 1);
+
+        bulkhead.semaphore.close();
+        assert!(bulkhead.call(|| async { 1 }).await.is_err());
     }
 
     #[test]
@@ -162,4 +300,72 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[cfg(all(feature = "http", feature = "redis"))]
+    #[tokio::test]
+    async fn test_user_over_concurrency_cap_gets_429_while_others_are_unaffected() {
+        use crate::redis::InMemorySemaphore;
+        use axum::{Router, body::Body, http::StatusCode, middleware, routing::get};
+        use tower::ServiceExt;
+
+        let semaphore: Arc<dyn DistributedSemaphore> = Arc::new(InMemorySemaphore::new());
+        // Held requests wait here until the test lets them finish
+        let gate = Arc::new(Semaphore::new(0));
+        let handler_gate = gate.clone();
+        let app = Router::new()
+            .route(
+                "/orders",
+                get(move |req: Request| {
+                    let gate = handler_gate.clone();
+                    async move {
+                        if req.headers().contains_key("x-hold") {
+                            gate.acquire().await.unwrap().forget();
+                        }
+                        "ok"
+                    }
+                }),
+            )
+            .layer(middleware::from_fn(make_per_user_concurrency_middleware(
+                PerUserConcurrency::new(semaphore.clone(), 2),
+            )))
+            .layer(middleware::from_fn(|mut req: Request, next: Next| async move {
+                let user = req.headers()["x-user"].to_str().unwrap().to_string();
+                req.extensions_mut().insert(Arc::new(AuthContext::new(user)));
+                next.run(req).await
+            }));
+        let request = |user: &str, hold: bool| {
+            let mut builder = Request::get("/orders").header("x-user", user);
+            if hold {
+                builder = builder.header("x-hold", "1");
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let held: Vec<_> = (0..2)
+            .map(|_| tokio::spawn(app.clone().oneshot(request("user-1", true))))
+            .collect();
+        while semaphore.held("user:user-1").await.unwrap() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        let status = |response: Result<Response, _>| response.unwrap().status();
+        assert_eq!(
+            status(app.clone().oneshot(request("user-1", false)).await),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(app.clone().oneshot(request("user-2", false)).await),
+            StatusCode::OK
+        );
+
+        gate.add_permits(2);
+        for request in held {
+            assert_eq!(status(request.await.unwrap()), StatusCode::OK);
+        }
+        assert_eq!(semaphore.held("user:user-1").await.unwrap(), 0);
+        assert_eq!(
+            status(app.clone().oneshot(request("user-1", false)).await),
+            StatusCode::OK
+        );
+    }
 }
@@ -2,15 +2,22 @@
 //!
 //! Prevents cascading failures by intercepting calls and tracking their state.
 //! Transitions between three states: Closed, Open, and Half-Open.
+//!
+//! Each call runs in a `resilience.circuit_breaker` span recording the
+//! breaker's `state` and the call's `outcome`: `success`, `failure`, or
+//! `circuit_open` when the call was refused without being made.
 
+use error::AppError;
+use serde::Serialize;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
-use tracing::{info, warn};
+use tracing::{Instrument, Span, field, info, info_span, warn};
 
 /// Circuit breaker state
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CircuitBreakerState {
     /// Normal state, requests pass through
     Closed,
@@ -33,12 +40,33 @@ impl std::fmt::Display for CircuitBreakerState {
 /// Circuit breaker error
 #[derive(Debug, Error, Clone)]
 pub enum CircuitBreakerError {
-    #[error("Circuit breaker is open")]
-    Open,
+    /// Calls are blocked; `retry_after` is the time left until the breaker
+    /// lets a trial call through
+    #[error("Circuit breaker is open, retry after {}s", retry_after_seconds(*.retry_after))]
+    Open { retry_after: Duration },
     #[error("Failed to execute: {0}")]
     ExecutionError(String),
 }
 
+/// Whole seconds to advertise in `Retry-After` (rounded up, at least 1)
+fn retry_after_seconds(remaining: Duration) -> u64 {
+    remaining.as_millis().div_ceil(1000).max(1) as u64
+}
+
+impl From<CircuitBreakerError> for AppError {
+    fn from(err: CircuitBreakerError) -> Self {
+        match err {
+            CircuitBreakerError::Open { retry_after } => AppError::service_unavailable(
+                "upstream service",
+                Some(retry_after_seconds(retry_after)),
+            ),
+            CircuitBreakerError::ExecutionError(message) => {
+                AppError::infrastructure("circuit_breaker", message)
+            }
+        }
+    }
+}
+
 /// Circuit breaker configuration
 #[derive(Debug, Clone)]
 pub struct CircuitBreakerConfig {
@@ -63,6 +91,8 @@ impl Default for CircuitBreakerConfig {
 /// Circuit breaker implementation
 #[derive(Clone)]
 pub struct CircuitBreaker {
+    /// Reported as the component of degradation metrics
+    name: &'static str,
     config: Arc<CircuitBreakerConfig>,
     state: Arc<AtomicU32>, // 0=Closed, 1=Open, 2=HalfOpen
     failures: Arc<AtomicU64>,
@@ -74,6 +104,7 @@ impl CircuitBreaker {
     /// Create a new circuit breaker
     pub fn new(config: CircuitBreakerConfig) -> Self {
         Self {
+            name: "circuit_breaker",
             config: Arc::new(config),
             state: Arc::new(AtomicU32::new(0)), // Closed
             failures: Arc::new(AtomicU64::new(0)),
@@ -82,6 +113,12 @@ impl CircuitBreaker {
         }
     }
 
+    /// Name the breaker after the dependency it protects, e.g. `kyc_provider`
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
     /// Get current state
     pub fn state(&self) -> CircuitBreakerState {
         match self.state.load(Ordering::Acquire) {
@@ -102,6 +139,17 @@ impl CircuitBreaker {
         self.successes.load(Ordering::Acquire)
     }
 
+    /// Time left until an open breaker allows a trial call (`None` unless open)
+    pub fn time_until_half_open(&self) -> Option<Duration> {
+        if self.state() != CircuitBreakerState::Open {
+            return None;
+        }
+        let elapsed = Duration::from_millis(
+            now_millis().saturating_sub(self.last_failure_time.load(Ordering::Acquire)),
+        );
+        Some(self.config.timeout.saturating_sub(elapsed))
+    }
+
     /// Execute a function with circuit breaker protection
     pub async fn call<F, Fut, T>(&self, f: F) -> Result<T, CircuitBreakerError>
     where
@@ -109,21 +157,40 @@ impl CircuitBreaker {
         Fut: std::future::Future<Output = Result<T, Box<dyn std::error::Error + Send + Sync>>>,
     {
         let state = self.state();
+        let span = info_span!(
+            "resilience.circuit_breaker",
+            breaker = self.name,
+            state = %state,
+            outcome = field::Empty,
+        );
 
-        match state {
-            CircuitBreakerState::Open => {
-                if self.should_attempt_reset() {
-                    self.transition_to_half_open();
+        async move {
+            match state {
+                CircuitBreakerState::Open => {
+                    if self.should_attempt_reset() {
+                        self.transition_to_half_open();
+                        Span::current().record("state", field::display(self.state()));
+                        self.execute_call(f).await
+                    } else {
+                        let retry_after = self.time_until_half_open().unwrap_or_default();
+                        warn!(
+                            breaker = self.name,
+                            retry_after_ms = retry_after.as_millis() as u64,
+                            "Circuit breaker is open"
+                        );
+                        #[cfg(feature = "metrics")]
+                        crate::observability::record_degradation(self.name, "circuit_open");
+                        Span::current().record("outcome", "circuit_open");
+                        Err(CircuitBreakerError::Open { retry_after })
+                    }
+                }
+                CircuitBreakerState::Closed | CircuitBreakerState::HalfOpen => {
                     self.execute_call(f).await
-                } else {
-                    warn!("Circuit breaker is open");
-                    Err(CircuitBreakerError::Open)
                 }
             }
-            CircuitBreakerState::Closed | CircuitBreakerState::HalfOpen => {
-                self.execute_call(f).await
-            }
         }
+        .instrument(span)
+        .await
     }
 
     async fn execute_call<F, Fut, T>(&self, f: F) -> Result<T, CircuitBreakerError>
@@ -134,10 +201,12 @@ impl CircuitBreaker {
         match f().await {
             Ok(result) => {
                 self.record_success();
+                Span::current().record("outcome", "success");
                 Ok(result)
             }
             Err(e) => {
                 self.record_failure();
+                Span::current().record("outcome", "failure");
                 Err(CircuitBreakerError::ExecutionError(e.to_string()))
             }
         }
@@ -165,11 +234,8 @@ impl CircuitBreaker {
         match state {
             CircuitBreakerState::Closed => {
                 let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-                self.last_failure_time.store(now, Ordering::Release);
+                self.last_failure_time
+                    .store(now_millis(), Ordering::Release);
 
                 if failures >= self.config.failure_threshold as u64 {
                     self.transition_to_open();
@@ -184,11 +250,7 @@ impl CircuitBreaker {
 
     fn should_attempt_reset(&self) -> bool {
         let last_failure = self.last_failure_time.load(Ordering::Acquire);
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let elapsed = Duration::from_secs(now - last_failure);
+        let elapsed = Duration::from_millis(now_millis().saturating_sub(last_failure));
         elapsed >= self.config.timeout
     }
 
@@ -201,11 +263,8 @@ impl CircuitBreaker {
 
     fn transition_to_open(&self) {
         self.state.store(1, Ordering::Release);
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        self.last_failure_time.store(now, Ordering::Release);
+        self.last_failure_time
+            .store(now_millis(), Ordering::Release);
         warn!("Circuit breaker transitioned to Open");
     }
 
@@ -221,6 +280,13 @@ impl CircuitBreaker {
     }
 }
 
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -245,12 +311,10 @@ mod tests {
 
         // Trigger failures
         for _ in 0..2 {
-            let _ = cb
+            let _: Result<(), _> = cb
                 .call(|| async {
-                    Err(
-                        Box::new(std::io::Error::new(std::io::ErrorKind::Other, "test"))
-                            as Box<dyn std::error::Error + Send + Sync>,
-                    )
+                    Err(Box::new(std::io::Error::other("test"))
+                        as Box<dyn std::error::Error + Send + Sync>)
                 })
                 .await;
         }
@@ -266,4 +330,36 @@ mod tests {
         // Block until timeout
         assert_eq!(cb.state(), CircuitBreakerState::Open);
     }
+
+    #[tokio::test]
+    async fn test_open_breaker_retry_hint_matches_remaining_window() {
+        let cb = CircuitBreaker::new(CircuitBreakerConfig {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        });
+        assert_eq!(cb.time_until_half_open(), None);
+        cb.transition_to_open();
+
+        let err = cb.call(|| async { Ok(()) }).await.unwrap_err();
+        let CircuitBreakerError::Open { retry_after } = err.clone() else {
+            panic!("expected open breaker, got {err:?}");
+        };
+        assert!(retry_after <= Duration::from_secs(30));
+        assert!(retry_after > Duration::from_secs(29));
+
+        let app_error = AppError::from(err);
+        assert_eq!(app_error.retry_after(), Some(30));
+
+        #[cfg(feature = "http")]
+        {
+            use axum::response::IntoResponse;
+
+            let response = error::http::ApiError::from(app_error).into_response();
+            assert_eq!(
+                response.status(),
+                axum::http::StatusCode::SERVICE_UNAVAILABLE
+            );
+            assert_eq!(response.headers()["retry-after"], "30");
+        }
+    }
 }
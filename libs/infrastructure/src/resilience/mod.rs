@@ -4,11 +4,12 @@
 //! - Circuit Breaker: Prevent cascading failures
 //! - Retry: Intelligent retry with exponential backoff
 //! - Timeout: Request timeout enforcement
-//! - Bulkhead: Resource isolation
+//! - Bulkhead: Resource isolation, including per-user in-flight request caps
+//! - Single flight: Coalesce identical concurrent reads into one fetch
 //!
 //! ## Example
 //!
-//! ```rust,no_run
+//! ```rust,ignore
 //! use infrastructure::resilience::{CircuitBreaker, CircuitBreakerConfig};
 //!
 //! let cb = CircuitBreaker::new(CircuitBreakerConfig::default());
@@ -21,9 +22,13 @@
 pub mod bulkhead;
 pub mod circuit_breaker;
 pub mod retry;
+pub mod single_flight;
 pub mod timeout;
 
 pub use bulkhead::{Bulkhead, BulkheadConfig};
+#[cfg(all(feature = "http", feature = "redis"))]
+pub use bulkhead::{PerUserConcurrency, make_per_user_concurrency_middleware, per_user_concurrency_middleware};
 pub use circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitBreakerState};
 pub use retry::{ExponentialBackoff, RetryConfig, RetryPolicy};
-pub use timeout::TimeoutError;
+pub use single_flight::SingleFlight;
+pub use timeout::{DependencyClass, TimeoutError, TimeoutProfiles};
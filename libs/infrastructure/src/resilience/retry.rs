@@ -1,11 +1,18 @@
 //! Retry policy implementation with exponential backoff
 //!
 //! Provides configurable retry strategies for transient failures.
+//!
+//! Failures can carry the delay the server advised (an HTTP `Retry-After`);
+//! [`RetryPolicy::execute_with_retry_after`] waits that long instead of its
+//! own backoff, up to [`RetryConfig::max_retry_after`].
+//!
+//! Each execution runs in a `resilience.retry` span recording how many
+//! `retries` it took and its `outcome` (`success` or `exhausted`), so a
+//! trace shows a slow call was slow because it was retried.
 
 use std::future::Future;
 use std::time::Duration;
-use thiserror::Error;
-use tracing::{warn, debug};
+use tracing::{Instrument, Span, debug, field, info_span, warn};
 
 /// Retry configuration
 #[derive(Debug, Clone)]
@@ -18,6 +25,8 @@ pub struct RetryConfig {
     pub max_backoff: Duration,
     /// Backoff multiplier
     pub multiplier: f64,
+    /// Longest server-advised delay honored; longer advice is cut to this
+    pub max_retry_after: Duration,
 }
 
 impl Default for RetryConfig {
@@ -27,6 +36,7 @@ impl Default for RetryConfig {
             initial_backoff: Duration::from_millis(100),
             max_backoff: Duration::from_secs(30),
             multiplier: 2.0,
+            max_retry_after: Duration::from_secs(60),
         }
     }
 }
@@ -43,40 +53,72 @@ impl RetryPolicy {
     }
 
     /// Execute with retry
-    pub async fn execute<F, Fut, T, E>(&self, mut f: F) -> Result<T, E>
+    pub async fn execute<F, Fut, T, E>(&self, f: F) -> Result<T, E>
     where
         F: FnMut() -> Fut,
         Fut: Future<Output = Result<T, E>>,
         E: std::fmt::Display,
     {
-        let mut backoff = self.config.initial_backoff;
-        let mut attempt = 0;
-
-        loop {
-            match f().await {
-                Ok(result) => {
-                    if attempt > 0 {
-                        debug!("Operation succeeded after {} retries", attempt);
-                    }
-                    return Ok(result);
-                }
-                Err(e) => {
-                    attempt += 1;
-                    if attempt > self.config.max_retries {
-                        warn!("Operation failed after {} attempts: {}", attempt, e);
-                        return Err(e);
+        self.execute_with_retry_after(f, |_| None).await
+    }
+
+    /// Execute with retry, waiting the delay `retry_after` finds in a
+    /// failure rather than the backoff when there is one
+    pub async fn execute_with_retry_after<F, Fut, T, E, R>(
+        &self,
+        mut f: F,
+        retry_after: R,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+        R: Fn(&E) -> Option<Duration>,
+    {
+        let span = info_span!(
+            "resilience.retry",
+            max_retries = self.config.max_retries,
+            retries = field::Empty,
+            outcome = field::Empty,
+        );
+        async move {
+            let mut backoff = self.config.initial_backoff;
+            let mut attempt = 0;
+
+            loop {
+                match f().await {
+                    Ok(result) => {
+                        if attempt > 0 {
+                            debug!("Operation succeeded after {} retries", attempt);
+                        }
+                        record_outcome(attempt, "success");
+                        return Ok(result);
                     }
+                    Err(e) => {
+                        if attempt == self.config.max_retries {
+                            warn!("Operation failed after {} attempts: {}", attempt + 1, e);
+                            record_outcome(attempt, "exhausted");
+                            return Err(e);
+                        }
+                        attempt += 1;
 
-                    warn!(
-                        "Operation failed (attempt {}/{}), retrying in {:?}: {}",
-                        attempt, self.config.max_retries, backoff, e
-                    );
+                        let delay = match retry_after(&e) {
+                            Some(advised) => advised.min(self.config.max_retry_after),
+                            None => backoff,
+                        };
+                        warn!(
+                            "Operation failed (attempt {}/{}), retrying in {:?}: {}",
+                            attempt, self.config.max_retries, delay, e
+                        );
 
-                    tokio::time::sleep(backoff).await;
-                    backoff = self.calculate_backoff(backoff);
+                        tokio::time::sleep(delay).await;
+                        backoff = self.calculate_backoff(backoff);
+                    }
                 }
             }
         }
+        .instrument(span)
+        .await
     }
 
     fn calculate_backoff(&self, current: Duration) -> Duration {
@@ -85,6 +127,13 @@ impl RetryPolicy {
     }
 }
 
+/// Record on the current `resilience.retry` span how the execution ended
+fn record_outcome(retries: u32, outcome: &'static str) {
+    let span = Span::current();
+    span.record("retries", retries);
+    span.record("outcome", outcome);
+}
+
 /// Exponential backoff calculation
 pub struct ExponentialBackoff {
     config: RetryConfig,
@@ -107,8 +156,10 @@ impl ExponentialBackoff {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::atomic::{AtomicU32, Ordering};
+    use common::testing::Logs;
     use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tracing_subscriber::fmt::format::FmtSpan;
 
     #[tokio::test]
     async fn test_retry_on_success() {
@@ -146,6 +197,71 @@ mod tests {
         assert_eq!(attempts.load(Ordering::SeqCst), 2);
     }
 
+    #[tokio::test]
+    async fn test_advised_delay_replaces_backoff_up_to_the_cap() {
+        let retry = RetryPolicy::new(RetryConfig {
+            max_retries: 1,
+            initial_backoff: Duration::from_secs(10),
+            max_retry_after: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let started = std::time::Instant::now();
+        let result: Result<i32, &str> = retry
+            .execute_with_retry_after(
+                || async { Err("throttled") },
+                |_| Some(Duration::from_secs(3600)),
+            )
+            .await;
+        assert_eq!(result, Err("throttled"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_span_records_retry_count_and_outcome() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let retry = RetryPolicy::new(RetryConfig {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let attempts = AtomicU32::new(0);
+        let result: Result<i32, &str> = retry
+            .execute(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move { if attempt < 2 { Err("transient error") } else { Ok(42) } }
+            })
+            .await;
+        assert_eq!(result, Ok(42));
+
+        let exhausted: Result<i32, &str> = RetryPolicy::new(RetryConfig {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .execute(|| async { Err("down") })
+        .await;
+        assert_eq!(exhausted, Err("down"));
+
+        let output = logs.contents();
+        let closed: Vec<&str> = output
+            .lines()
+            .filter(|line| line.contains("resilience.retry") && line.contains("close"))
+            .collect();
+        assert_eq!(closed.len(), 2, "{output}");
+        assert!(closed[0].contains("retries=2"), "{output}");
+        assert!(closed[0].contains("outcome=\"success\""), "{output}");
+        assert!(closed[1].contains("retries=1"), "{output}");
+        assert!(closed[1].contains("outcome=\"exhausted\""), "{output}");
+    }
+
     #[test]
     fn test_exponential_backoff() {
         let eb = ExponentialBackoff::new(RetryConfig {
@@ -0,0 +1,121 @@
+//! Request coalescing ("single flight") for identical concurrent reads
+//!
+//! When many callers miss the cache for the same key at once, only the first
+//! runs the fetch; the rest await its result instead of stampeding the
+//! database.  Entries live only while a fetch is in flight, so later callers
+//! always see fresh data.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent computations that share a key
+pub struct SingleFlight<K, V> {
+    inflight: Arc<Mutex<HashMap<K, Arc<OnceCell<V>>>>>,
+}
+
+impl<K, V> Clone for SingleFlight<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inflight: self.inflight.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            inflight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `fetch` for `key`, or join the fetch already running for it
+    ///
+    /// If the leading caller is cancelled, one of the waiters takes over the
+    /// fetch.  Errors are shared with the callers that were waiting, but not
+    /// remembered afterwards.
+    pub async fn run<F, Fut>(&self, key: K, fetch: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let cell = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let value = cell.get_or_init(fetch).await.clone();
+
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            inflight.remove(&key);
+        }
+        value
+    }
+
+    /// Keys with a fetch currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.inflight.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_identical_reads_share_one_fetch() {
+        let flight = SingleFlight::<String, Result<String, String>>::new();
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let reads = (0..50).map(|_| {
+            let flight = flight.clone();
+            let fetches = fetches.clone();
+            tokio::spawn(async move {
+                flight
+                    .run("catalog:item:42".to_string(), || async move {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok("Vintage camera".to_string())
+                    })
+                    .await
+            })
+        });
+        let results = futures_util::future::join_all(reads).await;
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(
+            results
+                .into_iter()
+                .all(|r| r.unwrap() == Ok("Vintage camera".to_string()))
+        );
+        assert_eq!(flight.in_flight(), 0);
+
+        // Once settled, the next read fetches again
+        flight
+            .run("catalog:item:42".to_string(), || async {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Ok("Vintage camera".to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
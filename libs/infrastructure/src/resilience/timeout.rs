@@ -1,19 +1,150 @@
 //! Timeout implementation for async operations
 //!
 //! Provides enforced timeouts for operations that may hang or take too long.
+//!
+//! Dependencies don't all deserve the same patience: a Redis lookup that
+//! hasn't answered in a second won't, while a KYC provider running document
+//! checks routinely takes tens of seconds.  [`TimeoutProfiles`] holds one
+//! timeout per [`DependencyClass`], loaded once from configuration and handed
+//! to `DbPool`, `RedisPool` and `HttpClientConfig`, which apply it to every
+//! call they make.
+//!
+//! Timed calls run in a `resilience.timeout` span recording the
+//! `timeout_ms` applied and the `outcome`, `success` or `timed_out`.
 
 use std::future::Future;
 use std::time::Duration;
+
+use config::Config;
+use config::core::duration::Millis;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::warn;
+use tracing::{Instrument, Span, field, info_span, warn};
 
 /// Timeout error
-#[derive(Debug, Error, Clone)]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum TimeoutError {
     #[error("Operation timed out after {duration_ms}ms")]
     Exceeded { duration_ms: u64 },
 }
 
+/// Kind of dependency a call goes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyClass {
+    Database,
+    Redis,
+    /// Other services and third-party HTTP APIs
+    Http,
+    /// Identity verification (KYC) providers
+    Kyc,
+}
+
+impl DependencyClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Database => "database",
+            Self::Redis => "redis",
+            Self::Http => "http",
+            Self::Kyc => "kyc",
+        }
+    }
+}
+
+/// Timeout of each class of dependency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Config)]
+pub struct TimeoutProfiles {
+    #[config(key = "TIMEOUT_DATABASE_MS", default = "5s", with = Millis::into_std)]
+    pub database: Duration,
+    #[config(key = "TIMEOUT_REDIS_MS", default = "1s", with = Millis::into_std)]
+    pub redis: Duration,
+    #[config(key = "TIMEOUT_HTTP_MS", default = "10s", with = Millis::into_std)]
+    pub http: Duration,
+    #[config(key = "TIMEOUT_KYC_MS", default = "30s", with = Millis::into_std)]
+    pub kyc: Duration,
+}
+
+impl Default for TimeoutProfiles {
+    fn default() -> Self {
+        Self {
+            database: Duration::from_secs(5),
+            redis: Duration::from_secs(1),
+            http: Duration::from_secs(10),
+            kyc: Duration::from_secs(30),
+        }
+    }
+}
+
+impl TimeoutProfiles {
+    /// Timeout of calls to `class`
+    pub fn timeout_for(&self, class: DependencyClass) -> Duration {
+        match class {
+            DependencyClass::Database => self.database,
+            DependencyClass::Redis => self.redis,
+            DependencyClass::Http => self.http,
+            DependencyClass::Kyc => self.kyc,
+        }
+    }
+
+    /// Await `future`, a call to `class`, for at most that class's timeout
+    pub async fn run<F: Future>(
+        &self,
+        class: DependencyClass,
+        future: F,
+    ) -> Result<F::Output, TimeoutError> {
+        run_with_timeout(class, self.timeout_for(class), future).await
+    }
+}
+
+/// Await `future`, a call to `class`, for at most `timeout`
+pub(crate) async fn run_with_timeout<F: Future>(
+    class: DependencyClass,
+    timeout: Duration,
+    future: F,
+) -> Result<F::Output, TimeoutError> {
+    let span = timeout_span(timeout);
+    span.record("dependency", class.as_str());
+    async move {
+        let result = tokio::time::timeout(timeout, future).await;
+        record_outcome(result.is_ok());
+        result.map_err(|_| {
+            let duration_ms = timeout.as_millis() as u64;
+            warn!(dependency = class.as_str(), duration_ms, "dependency call timed out");
+            TimeoutError::Exceeded { duration_ms }
+        })
+    }
+    .instrument(span)
+    .await
+}
+
+/// Span a timed call runs in
+fn timeout_span(timeout: Duration) -> Span {
+    info_span!(
+        "resilience.timeout",
+        timeout_ms = timeout.as_millis() as u64,
+        dependency = field::Empty,
+        outcome = field::Empty,
+    )
+}
+
+/// Await `future` for at most `timeout` in a `resilience.timeout` span
+async fn timed<F: Future>(
+    timeout: Duration,
+    future: F,
+) -> Result<F::Output, tokio::time::error::Elapsed> {
+    async move {
+        let result = tokio::time::timeout(timeout, future).await;
+        record_outcome(result.is_ok());
+        result
+    }
+    .instrument(timeout_span(timeout))
+    .await
+}
+
+fn record_outcome(completed: bool) {
+    let outcome = if completed { "success" } else { "timed_out" };
+    Span::current().record("outcome", outcome);
+}
+
 /// Execute an operation with a timeout
 pub async fn with_timeout<F, Fut, T>(
     duration: Duration,
@@ -23,7 +154,7 @@ where
     F: FnOnce() -> Fut,
     Fut: Future<Output = T>,
 {
-    match tokio::time::timeout(duration, operation()).await {
+    match timed(duration, operation()).await {
         Ok(result) => Ok(result),
         Err(_) => {
             warn!(
@@ -47,7 +178,7 @@ where
     F: FnOnce() -> Fut,
     Fut: Future<Output = T>,
 {
-    match tokio::time::timeout(duration, operation()).await {
+    match timed(duration, operation()).await {
         Ok(result) => result,
         Err(_) => {
             warn!(
@@ -68,7 +199,7 @@ where
     F: FnOnce() -> Fut,
     Fut: Future<Output = Result<T, E>>,
 {
-    match tokio::time::timeout(duration, operation()).await {
+    match timed(duration, operation()).await {
         Ok(Ok(result)) => Ok(result),
         Ok(Err(_)) => Err(TimeoutError::Exceeded {
             duration_ms: duration.as_millis() as u64,
@@ -113,4 +244,46 @@ mod tests {
         }, 0).await;
         assert_eq!(result, 0);
     }
+
+    #[tokio::test]
+    async fn test_each_dependency_class_has_its_own_timeout() {
+        let profiles = TimeoutProfiles {
+            database: Duration::from_millis(200),
+            redis: Duration::from_millis(20),
+            http: Duration::from_millis(100),
+            kyc: Duration::from_millis(400),
+        };
+        let call = || tokio::time::sleep(Duration::from_millis(60));
+
+        assert_eq!(
+            profiles.run(DependencyClass::Redis, call()).await,
+            Err(TimeoutError::Exceeded { duration_ms: 20 })
+        );
+        assert_eq!(profiles.run(DependencyClass::Http, call()).await, Ok(()));
+        assert_eq!(profiles.run(DependencyClass::Database, call()).await, Ok(()));
+
+        let slow_call = || tokio::time::sleep(Duration::from_millis(300));
+        assert_eq!(
+            profiles.run(DependencyClass::Database, slow_call()).await,
+            Err(TimeoutError::Exceeded { duration_ms: 200 })
+        );
+        assert_eq!(profiles.run(DependencyClass::Kyc, slow_call()).await, Ok(()));
+    }
+
+    #[test]
+    fn test_profiles_read_bare_numbers_as_milliseconds() {
+        use config::loader::ConfigLoader;
+        use config::sources::dotenv::DotenvLayerBuilder;
+
+        let env = DotenvLayerBuilder::new()
+            .with_override("TIMEOUT_HTTP_MS", "2500")
+            .with_override("TIMEOUT_KYC_MS", "1m")
+            .build();
+        let profiles = TimeoutProfiles::from_loader(&ConfigLoader::new().with_service_env(env))
+            .unwrap();
+
+        assert_eq!(profiles.http, Duration::from_millis(2500));
+        assert_eq!(profiles.kyc, Duration::from_secs(60));
+        assert_eq!(profiles.database, TimeoutProfiles::default().database);
+    }
 }
@@ -0,0 +1,570 @@
+//! HTTP server bootstrap
+//!
+//! [`serve`] runs a router until the shutdown signal fires, then stops
+//! accepting connections and lets in-flight requests finish, but only for
+//! `SHUTDOWN_TIMEOUT`.  A request that never completes (a stuck upstream
+//! call, a client holding a stream open) would otherwise keep the old
+//! instance alive until the orchestrator kills it, stalling the deploy.
+//! When the timeout is hit the number of requests still running is logged
+//! and `serve` returns; their connections are dropped with the runtime
+//! when `main` returns.
+//!
+//! The rest of [`ServerConfig`] shapes the server itself: `TCP_NODELAY` on
+//! accepted sockets, a cap on open connections (further clients wait in the
+//! accept backlog), HTTP keep-alive, and the number of runtime worker
+//! threads, which [`ServerConfig::runtime`] applies before `main` starts any
+//! async work.
+//!
+//! `trusted_proxies` says which `X-Forwarded-For` entries to believe; every
+//! request gets the resolved
+//! [`ClientIp`](common::middleware::client_ip::ClientIp) extension before it reaches the
+//! router's own middleware.
+//!
+//! `max_concurrent_requests` is a bulkhead around the whole router: past
+//! that many requests in flight, new ones are answered `503` with a
+//! `Retry-After` by the outermost layer, before any middleware or handler
+//! spends work on them.
+//!
+//! ```rust,ignore
+//! let server = ServerConfig::from_loader(&loader)?;
+//! server.runtime()?.block_on(async {
+//!     let listener = server.bind().await?;
+//!     server::serve(listener, app, shutdown_signal(), &server).await
+//! })?;
+//! ```
+
+use std::future::{Future, IntoFuture};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{HeaderValue, header};
+use axum::middleware::{Next, from_fn};
+use axum::response::IntoResponse;
+use axum::serve::{IncomingStream, Listener};
+use common::middleware::client_ip::{TrustedProxies, make_client_ip_middleware};
+use config::Config;
+use config::core::duration::ConfigDuration;
+use error::http::ApiError;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+use tracing::{debug, info, warn};
+
+/// Seconds requests shed over `max_concurrent_requests` are told to wait
+const SHED_RETRY_AFTER_SECS: u64 = 1;
+
+/// Listening address and shutdown behaviour of a service
+#[derive(Debug, Clone, Config)]
+pub struct ServerConfig {
+    #[config(key = "SERVER_ADDRESS", default = "0.0.0.0:8080")]
+    pub address: SocketAddr,
+    /// How long in-flight requests may run after the shutdown signal
+    #[config(key = "SHUTDOWN_TIMEOUT", default = "30s", with = ConfigDuration::into_std)]
+    pub shutdown_timeout: Duration,
+    /// Runtime worker threads; one per CPU core when unset
+    #[config(key = "SERVER_WORKER_THREADS")]
+    pub worker_threads: Option<usize>,
+    /// Keep connections open between requests; when off every response
+    /// carries `Connection: close`
+    #[config(key = "SERVER_KEEP_ALIVE", default = true)]
+    pub keep_alive: bool,
+    /// Connections served at once; unlimited when unset
+    #[config(key = "SERVER_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+    /// Set `TCP_NODELAY` on accepted connections
+    #[config(key = "SERVER_TCP_NODELAY", default = true)]
+    pub tcp_nodelay: bool,
+    /// Requests handled at once; further requests get `503`, unlimited
+    /// when unset
+    #[config(key = "SERVER_MAX_CONCURRENT_REQUESTS")]
+    pub max_concurrent_requests: Option<usize>,
+    /// Proxies in front of the service, as a hop count or a list of
+    /// networks; none by default, so `X-Forwarded-For` is ignored
+    #[config(key = "SERVER_TRUSTED_PROXIES", default = "0")]
+    pub trusted_proxies: TrustedProxies,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            shutdown_timeout: Duration::from_secs(30),
+            worker_threads: None,
+            keep_alive: true,
+            max_connections: None,
+            tcp_nodelay: true,
+            max_concurrent_requests: None,
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Multi-threaded runtime with the configured worker threads
+    pub fn runtime(&self) -> io::Result<Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if let Some(threads) = self.worker_threads {
+            builder.worker_threads(threads);
+        }
+        builder.enable_all().build()
+    }
+
+    /// Listen on the configured address
+    pub async fn bind(&self) -> io::Result<TcpListener> {
+        TcpListener::bind(self.address).await
+    }
+}
+
+/// How a server stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    /// Every in-flight request finished within the timeout
+    Drained,
+    /// The timeout ran out with `in_flight` requests still running
+    TimedOut { in_flight: usize },
+}
+
+/// Serve `app` on `listener` as `config` describes until `signal`
+/// resolves, then drain for at most `config.shutdown_timeout`
+pub async fn serve<F>(
+    listener: TcpListener,
+    app: Router,
+    signal: F,
+    config: &ServerConfig,
+) -> io::Result<ShutdownOutcome>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let drain_timeout = config.shutdown_timeout;
+    let app = if config.keep_alive {
+        app
+    } else {
+        app.layer(from_fn(|request: Request, next: Next| async move {
+            let mut response = next.run(request).await;
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+            response
+        }))
+    };
+    // Expose the peer as the usual `ConnectInfo<SocketAddr>` for the client
+    // IP middleware and handlers
+    let app = app
+        .layer(from_fn(make_client_ip_middleware(
+            config.trusted_proxies.clone(),
+        )))
+        .layer(from_fn(|mut request: Request, next: Next| {
+            if let Some(ConnectInfo(PeerAddr(addr))) = request.extensions().get().copied() {
+                request.extensions_mut().insert(ConnectInfo(addr));
+            }
+            next.run(request)
+        }));
+    let listener = ServerListener {
+        inner: listener,
+        tcp_nodelay: config.tcp_nodelay,
+        connections: config
+            .max_connections
+            .map(|max| Arc::new(Semaphore::new(max))),
+    };
+
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let counter = in_flight.clone();
+    let app = app.layer(from_fn(move |request: Request, next: Next| {
+        let guard = InFlight::enter(&counter);
+        async move {
+            let response = next.run(request).await;
+            drop(guard);
+            response
+        }
+    }));
+    let app = match config.max_concurrent_requests {
+        Some(max) => {
+            let permits = Arc::new(Semaphore::new(max));
+            app.layer(from_fn(move |request: Request, next: Next| {
+                let permit = permits.clone().try_acquire_owned();
+                async move {
+                    let Ok(_permit) = permit else {
+                        warn!(
+                            path = %request.uri().path(),
+                            max_concurrent_requests = max,
+                            "shedding request over the server concurrency cap"
+                        );
+                        return ApiError::service_unavailable_with_retry(
+                            "Service is at capacity, please retry shortly",
+                            SHED_RETRY_AFTER_SECS,
+                        )
+                        .into_response();
+                    };
+                    next.run(request).await
+                }
+            }))
+        }
+        None => app,
+    };
+
+    let (draining_tx, mut draining) = watch::channel(false);
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<PeerAddr>(),
+    )
+        .with_graceful_shutdown(async move {
+            signal.await;
+            info!("shutdown signal received, draining in-flight requests");
+            let _ = draining_tx.send(true);
+        })
+        .into_future();
+    let deadline = async move {
+        if draining.wait_for(|draining| *draining).await.is_err() {
+            // The server stopped on its own; let it report how
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        result = server => {
+            result?;
+            info!("server drained");
+            Ok(ShutdownOutcome::Drained)
+        }
+        () = deadline => {
+            let in_flight = in_flight.load(Ordering::SeqCst);
+            warn!(
+                in_flight,
+                timeout_ms = drain_timeout.as_millis() as u64,
+                "graceful shutdown timed out, closing remaining connections"
+            );
+            Ok(ShutdownOutcome::TimedOut { in_flight })
+        }
+    }
+}
+
+/// Address of the peer of a [`ServerListener`] connection
+#[derive(Debug, Clone, Copy)]
+struct PeerAddr(SocketAddr);
+
+impl Connected<IncomingStream<'_, ServerListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, ServerListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+/// [`TcpListener`] applying the per-connection settings of [`ServerConfig`]
+struct ServerListener {
+    inner: TcpListener,
+    tcp_nodelay: bool,
+    /// Open connection slots when connections are capped
+    connections: Option<Arc<Semaphore>>,
+}
+
+impl Listener for ServerListener {
+    type Io = ServerStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        // Wait for a free slot before accepting, so clients over the cap
+        // queue in the backlog instead of being accepted and stalled
+        let permit = match &self.connections {
+            Some(connections) => Some(
+                connections
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("connection semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let (stream, addr) = Listener::accept(&mut self.inner).await;
+        if self.tcp_nodelay
+            && let Err(e) = stream.set_nodelay(true)
+        {
+            debug!(error = %e, %addr, "failed to set TCP_NODELAY");
+        }
+        (
+            ServerStream {
+                stream,
+                _permit: permit,
+            },
+            addr,
+        )
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+/// Accepted connection holding its slot until closed
+struct ServerStream {
+    stream: TcpStream,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl AsyncRead for ServerStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ServerStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+/// Counts a request as in flight until dropped
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn enter(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Extension;
+    use axum::routing::get;
+    use common::middleware::client_ip::ClientIp;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+
+    async fn stub_server(
+        config: ServerConfig,
+    ) -> (
+        SocketAddr,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<io::Result<ShutdownOutcome>>,
+    ) {
+        let app = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/stuck", get(std::future::pending::<&'static str>))
+            .route(
+                "/ip",
+                get(|Extension(ip): Extension<ClientIp>| async move { ip.to_string() }),
+            );
+        let listener = config.bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, signal) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            serve(
+                listener,
+                app,
+                async {
+                    let _ = signal.await;
+                },
+                &config,
+            )
+            .await
+        });
+        (addr, stop, server)
+    }
+
+    fn local_config(shutdown_timeout: Duration) -> ServerConfig {
+        ServerConfig {
+            address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            shutdown_timeout,
+            ..ServerConfig::default()
+        }
+    }
+
+    async fn send(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_gives_up_on_stuck_request_after_timeout() {
+        let (addr, stop, server) = stub_server(local_config(Duration::from_millis(200))).await;
+        let _stuck = send(addr, "/stuck").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = Instant::now();
+        stop.send(()).unwrap();
+        let outcome = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("shutdown hung on the stuck request")
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome, ShutdownOutcome::TimedOut { in_flight: 1 });
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_without_in_flight_requests_drains() {
+        let (addr, stop, server) = stub_server(local_config(Duration::from_secs(5))).await;
+        let mut stream = send(addr, "/ok").await;
+        let mut response = [0; 64];
+        let read = stream.read(&mut response).await.unwrap();
+        assert!(response[..read].starts_with(b"HTTP/1.1 200"));
+        drop(stream);
+
+        stop.send(()).unwrap();
+        let outcome = tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .expect("shutdown waited for the timeout")
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome, ShutdownOutcome::Drained);
+    }
+
+    #[tokio::test]
+    async fn test_configured_address_and_keep_alive_apply() {
+        let loader = config::loader::ConfigLoader::new().with_service_env(
+            config::sources::dotenv::DotenvLayerBuilder::new()
+                .with_override("SERVER_ADDRESS", "127.0.0.1:0")
+                .with_override("SERVER_KEEP_ALIVE", "false")
+                .with_override("SERVER_MAX_CONNECTIONS", "4")
+                .build(),
+        );
+        let config = ServerConfig::from_loader(&loader).unwrap();
+        assert!(!config.keep_alive);
+        assert_eq!(config.max_connections, Some(4));
+        assert!(config.tcp_nodelay);
+
+        let (addr, stop, server) = stub_server(config).await;
+        assert!(addr.ip().is_loopback());
+
+        let mut stream = send(addr, "/ok").await;
+        let mut response = Vec::new();
+        // The server closes the connection after one response
+        tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut response))
+            .await
+            .expect("connection was kept alive")
+            .unwrap();
+        let response = String::from_utf8(response).unwrap().to_ascii_lowercase();
+        assert!(response.starts_with("http/1.1 200"), "{response}");
+        assert!(response.contains("connection: close"), "{response}");
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_honours_only_trusted_proxies() {
+        let client_ip = |trusted_proxies: &str| {
+            let config = ServerConfig {
+                trusted_proxies: trusted_proxies.parse().unwrap(),
+                ..local_config(Duration::from_millis(100))
+            };
+            async move {
+                let (addr, stop, server) = stub_server(config).await;
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream
+                    .write_all(
+                        b"GET /ip HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                          X-Forwarded-For: 6.6.6.6, 203.0.113.7\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                stop.send(()).unwrap();
+                server.await.unwrap().unwrap();
+                response.rsplit("\r\n").next().unwrap().to_string()
+            }
+        };
+
+        // The loopback peer is the proxy, so its entry names the client
+        assert_eq!(client_ip("127.0.0.0/8").await, "203.0.113.7");
+        // An untrusted peer's header is ignored
+        assert_eq!(client_ip("10.0.0.0/8").await, "127.0.0.1");
+        assert_eq!(client_ip("0").await, "127.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_the_concurrency_cap_are_shed() {
+        let config = ServerConfig {
+            max_concurrent_requests: Some(1),
+            ..local_config(Duration::from_millis(100))
+        };
+        let (addr, stop, server) = stub_server(config).await;
+        let _stuck = send(addr, "/stuck").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let read_head = |mut stream: TcpStream| async move {
+            let mut response = [0; 256];
+            let read = stream.read(&mut response).await.unwrap();
+            String::from_utf8_lossy(&response[..read]).to_ascii_lowercase()
+        };
+        let shed = read_head(send(addr, "/ok").await).await;
+        assert!(shed.starts_with("http/1.1 503"), "{shed}");
+        assert!(shed.contains("retry-after: 1"), "{shed}");
+
+        stop.send(()).unwrap();
+        let outcome = server.await.unwrap().unwrap();
+        assert_eq!(outcome, ShutdownOutcome::TimedOut { in_flight: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_requests_within_the_concurrency_cap_are_served() {
+        let config = ServerConfig {
+            max_concurrent_requests: Some(2),
+            ..local_config(Duration::from_millis(100))
+        };
+        let (addr, stop, server) = stub_server(config).await;
+        let _stuck = send(addr, "/stuck").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        for _ in 0..3 {
+            let mut stream = send(addr, "/ok").await;
+            let mut response = [0; 64];
+            let read = stream.read(&mut response).await.unwrap();
+            assert!(response[..read].starts_with(b"HTTP/1.1 200"));
+        }
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+//! Object storage for uploaded documents and other blobs.
+
+pub mod object_store;
+
+pub use object_store::{
+    ByteStream, InMemoryObjectStore, LocalObjectStore, ObjectStore, StorageError, StoredObject,
+};
@@ -0,0 +1,268 @@
+//! Object store abstraction
+//!
+//! Uploads are written as streams of chunks so large documents never have to
+//! be held in memory.  [`LocalObjectStore`] writes to a directory (dev and
+//! single-node deployments); [`InMemoryObjectStore`] backs tests.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+/// Body of an object being stored
+pub type ByteStream<'a> = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + 'a>>;
+
+/// Storage errors
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("invalid object key '{0}'")]
+    InvalidKey(String),
+
+    #[error("object '{0}' not found")]
+    NotFound(String),
+
+    #[error("storage I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Metadata of a stored object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub key: String,
+    pub content_type: String,
+    pub size: u64,
+}
+
+/// Blob storage backend
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Store `body` under `key`, replacing any existing object.
+    ///
+    /// If the stream yields an error the partial object is discarded.
+    async fn put_stream<'a>(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: ByteStream<'a>,
+    ) -> Result<StoredObject, StorageError>;
+
+    /// Read a whole object
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError>;
+
+    /// Delete an object; deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Reject keys that could escape the store root (`..`, absolute paths)
+fn validate_key(key: &str) -> Result<&Path, StorageError> {
+    let path = Path::new(key);
+    let safe = !key.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if safe {
+        Ok(path)
+    } else {
+        Err(StorageError::InvalidKey(key.to_string()))
+    }
+}
+
+/// Filesystem-backed store rooted at a directory
+#[derive(Debug, Clone)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        Ok(self.root.join(validate_key(key)?))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalObjectStore {
+    async fn put_stream<'a>(
+        &self,
+        key: &str,
+        content_type: &str,
+        mut body: ByteStream<'a>,
+    ) -> Result<StoredObject, StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Write to a temp file and rename so readers never see partial objects
+        let tmp = path.with_extension("partial");
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        let mut size = 0u64;
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    drop(file);
+                    let _ = tokio::fs::remove_file(&tmp).await;
+                    return Err(e.into());
+                }
+            };
+            size += chunk.len() as u64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        tokio::fs::rename(&tmp, &path).await?;
+
+        Ok(StoredObject {
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            size,
+        })
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Bytes::from(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// In-memory store for tests
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryObjectStore {
+    objects: Arc<RwLock<HashMap<String, (String, Bytes)>>>,
+}
+
+impl InMemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Content type of a stored object
+    pub fn content_type(&self, key: &str) -> Option<String> {
+        self.objects
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(key)
+            .map(|(content_type, _)| content_type.clone())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for InMemoryObjectStore {
+    async fn put_stream<'a>(
+        &self,
+        key: &str,
+        content_type: &str,
+        mut body: ByteStream<'a>,
+    ) -> Result<StoredObject, StorageError> {
+        validate_key(key)?;
+        let mut data = Vec::new();
+        while let Some(chunk) = body.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+
+        let size = data.len() as u64;
+        self.objects
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key.to_string(), (content_type.to_string(), data.into()));
+
+        Ok(StoredObject {
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            size,
+        })
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, StorageError> {
+        self.objects
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(key)
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.objects
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn body(chunks: Vec<io::Result<&'static [u8]>>) -> ByteStream<'static> {
+        Box::pin(stream::iter(
+            chunks.into_iter().map(|c| c.map(Bytes::from_static)),
+        ))
+    }
+
+    #[test]
+    fn test_keys_cannot_escape_root() {
+        assert!(validate_key("kyc/user-1/passport.pdf").is_ok());
+        assert!(validate_key("../etc/passwd").is_err());
+        assert!(validate_key("/etc/passwd").is_err());
+        assert!(validate_key("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_store_streams_to_disk() {
+        let root = std::env::temp_dir().join(format!("object-store-{}", std::process::id()));
+        let store = LocalObjectStore::new(&root);
+
+        let stored = store
+            .put_stream(
+                "kyc/doc.pdf",
+                "application/pdf",
+                body(vec![Ok(b"%PDF-"), Ok(b"1.7")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored.size, 8);
+        assert_eq!(&store.get("kyc/doc.pdf").await.unwrap()[..], b"%PDF-1.7");
+
+        let failed = store
+            .put_stream(
+                "kyc/broken.pdf",
+                "application/pdf",
+                body(vec![
+                    Ok(b"%PDF-"),
+                    Err(io::Error::other("client went away")),
+                ]),
+            )
+            .await;
+        assert!(failed.is_err());
+        assert!(matches!(
+            store.get("kyc/broken.pdf").await,
+            Err(StorageError::NotFound(_))
+        ));
+
+        store.delete("kyc/doc.pdf").await.unwrap();
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
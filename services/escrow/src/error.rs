@@ -0,0 +1,62 @@
+//! Escrow service errors
+
+use error::core::AuthErrorCode;
+use error::{AppError, ServiceError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EscrowError {
+    #[error("escrow {0} not found")]
+    NotFound(String),
+
+    #[error("escrow {0} is already funded")]
+    AlreadyFunded(String),
+
+    #[error("escrow {0} is not funded")]
+    NotFunded(String),
+
+    #[error("amount must be positive")]
+    InvalidAmount,
+
+    #[error("release of escrow {0} requires buyer approval")]
+    ReleaseNotApproved(String),
+
+    #[error("escrow {0} is under dispute")]
+    Disputed(String),
+
+    #[error("only a party to escrow {0} can do this")]
+    NotAParty(String),
+}
+
+impl ServiceError for EscrowError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "ESCROW_NOT_FOUND",
+            Self::AlreadyFunded(_) => "ESCROW_ALREADY_FUNDED",
+            Self::NotFunded(_) => "ESCROW_NOT_FUNDED",
+            Self::InvalidAmount => "ESCROW_INVALID_AMOUNT",
+            Self::ReleaseNotApproved(_) => "ESCROW_RELEASE_NOT_APPROVED",
+            Self::Disputed(_) => "ESCROW_DISPUTED",
+            Self::NotAParty(_) => "ESCROW_NOT_A_PARTY",
+        }
+    }
+}
+
+impl From<EscrowError> for AppError {
+    fn from(e: EscrowError) -> Self {
+        let code = e.code();
+        match e {
+            EscrowError::NotFound(id) => AppError::not_found("escrow", id),
+            EscrowError::AlreadyFunded(_) => AppError::conflict(e.to_string()),
+            EscrowError::InvalidAmount => AppError::validation_with_field(e.to_string(), "amount"),
+            EscrowError::NotFunded(_)
+            | EscrowError::ReleaseNotApproved(_)
+            | EscrowError::Disputed(_) => AppError::business(e.to_string(), code),
+            EscrowError::NotAParty(_) => {
+                AppError::authz(e.to_string(), AuthErrorCode::InsufficientPermissions)
+            }
+        }
+    }
+}
+
+error::service_error!(EscrowError);
@@ -1,3 +1,5 @@
+pub mod error;
 pub mod routes;
 
+pub use error::EscrowError;
 pub use routes::router;
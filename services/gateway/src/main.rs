@@ -1,14 +1,46 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use axum::{Router, middleware::from_fn, routing::get};
+use axum::http::StatusCode;
 use common::{
     http::{fallback::handle_404, response::ApiResponse},
-    middleware::{CorsPolicy, TrackingConfig, make_cors_middleware, tracking_middleware},
+    middleware::{
+        CorsPolicy, LoadShedConfig, LoadShedder, RequestPriority, TrackingConfig,
+        make_cors_middleware, make_load_shed_middleware, tracking_middleware,
+    },
 };
-use config::{loader::ConfigLoader, sources::dotenv::DotenvSource};
-use infrastructure::{DatabaseConfig, DbPool, RedisConfig, RedisPool};
-use tracing::info;
-use tracing_subscriber::EnvFilter;
+use config::{
+    Config,
+    core::{error::ConfigResult, redact::redact_value},
+    loader::ConfigLoader,
+    sources::dotenv::DotenvSource,
+};
+use identity::account_deletion::{
+    AccountDeletionService, DEFAULT_ERASURE_INTERVAL, PgAccountEraser, PgDeletionStore,
+    spawn_erasure_job,
+};
+use infrastructure::{
+    DatabaseConfig, DbPool, RedisConfig, RedisPool,
+    config::ConfigReloader,
+    health::{DatabaseHealthCheck, HealthRegistry, ReadinessReport, RedisHealthCheck},
+    observability::{self, MetricsExporter, ObservabilityConfig},
+    redis::RedisSessionStore,
+    resilience::TimeoutProfiles,
+    server::{self, ServerConfig},
+};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Infrastructure settings the gateway can reload on `SIGHUP`
+#[derive(Debug, Clone, Serialize, Config)]
+struct GatewayConfig {
+    #[config(nested)]
+    database: DatabaseConfig,
+    #[config(nested)]
+    redis: RedisConfig,
+    #[config(nested)]
+    timeouts: TimeoutProfiles,
+}
 
 #[derive(Clone)]
 struct AppState {
@@ -16,20 +48,71 @@ struct AppState {
     redis: Arc<RedisPool>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_tracing();
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let loader = load_config()?;
+    let server_config = ServerConfig::from_loader(&loader)?;
+    server_config
+        .runtime()?
+        .block_on(run(loader, server_config))
+}
+
+async fn run(
+    loader: ConfigLoader,
+    server_config: ServerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let observability_config = ObservabilityConfig::from_loader(&loader)?;
+    let metrics = Arc::new(observability::init(&observability_config)?);
+    serve_metrics(metrics.clone(), observability_config.metrics_addr()).await?;
 
-    let loader = ConfigLoader::new().with_service_env(DotenvSource::try_from_file(".env")?);
+    info!(config = ?loader.summary(), "effective configuration");
+    let reloader = Arc::new(ConfigReloader::new(load_config, GatewayConfig::from_loader)?);
 
-    let database_config = DatabaseConfig::from_loader(&loader)?;
-    let redis_config = RedisConfig::from_loader(&loader)?;
+    #[cfg(unix)]
+    if loader.get_or("CONFIG_RELOAD_ON_SIGHUP", false)? {
+        reloader.clone().spawn_sighup_handler()?;
+        info!("config reload on SIGHUP enabled");
+    }
+
+    let active = reloader.current();
+    let database_config = &active.database;
+    let redis_config = &active.redis;
+
+    let db = Arc::new(
+        DbPool::new(database_config)
+            .await?
+            .with_slow_query_explain(&database_config.logging, &loader.environment())
+            .with_timeouts(&active.timeouts),
+    );
+    // Pre-fill the pool so the first requests after a deploy don't each
+    // open a connection
+    db.warmup(database_config.pool.warmup_connections).await?;
+    let redis = Arc::new(
+        RedisPool::from_config(redis_config)
+            .await?
+            .with_timeouts(&active.timeouts),
+    );
+
+    info!(
+        db_url = %redact_value("DATABASE_URL", &database_config.url),
+        "postgres pool initialized"
+    );
+    info!(
+        redis_url = %redact_value("REDIS_URL", &redis_config.url),
+        "redis client initialized"
+    );
 
-    let db = Arc::new(DbPool::new(&database_config).await?);
-    let redis = Arc::new(RedisPool::from_config(&redis_config).await?);
+    // Erase accounts whose deletion grace period has ended
+    let sessions = Arc::new(RedisSessionStore::new((*redis).clone(), "identity"));
+    let deletions = AccountDeletionService::new(
+        Arc::new(PgDeletionStore::new(db.pool().clone())),
+        Arc::new(PgAccountEraser::new(db.pool().clone(), sessions)),
+    );
+    let erasure_job = spawn_erasure_job(deletions, DEFAULT_ERASURE_INTERVAL);
 
-    info!(db_url = %database_config.url, "postgres pool initialized");
-    info!(redis_url = %redis_config.url, "redis client initialized");
+    let mut health = HealthRegistry::new();
+    health
+        .register("database", Box::new(DatabaseHealthCheck::new((*db).clone())))
+        .register("redis", Box::new(RedisHealthCheck::new((*redis).clone())));
 
     let shared_infra = AppState { db, redis };
     let db_ptr = Arc::as_ptr(&shared_infra.db) as usize;
@@ -39,29 +122,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         shared_redis = format_args!("0x{redis_ptr:x}"),
         "shared infrastructure state initialized"
     );
-    let app = build_router();
+    // Preflight caching: short while developing so policy changes show up
+    // quickly, the 2h browsers cap it at elsewhere
+    let default_max_age = if loader.environment().allows_debug() { 60 } else { 7200 };
+    let cors = CorsPolicy::new()
+        .allow_all_origins()
+        .with_max_age(loader.get_or("CORS_MAX_AGE_SECS", default_max_age)?);
+    let app = build_router(Arc::new(health), cors);
 
-    let address = std::env::var("SERVER_ADDRESS").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
-    let addr: SocketAddr = address.parse()?;
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let listener = server_config.bind().await?;
 
-    info!(%addr, "gateway started");
+    info!(addr = %server_config.address, "gateway started");
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    server::serve(listener, app, shutdown_signal(), &server_config).await?;
+
+    erasure_job.abort();
 
     drop(shared_infra);
+    // Push what was recorded since the last OTLP export
+    if let Err(e) = metrics.flush().await {
+        warn!(error = %e, "final metrics export failed");
+    }
 
     Ok(())
 }
 
-fn build_router() -> Router {
-    let cors = make_cors_middleware(CorsPolicy::new().allow_all_origins());
+fn load_config() -> ConfigResult<ConfigLoader> {
+    Ok(ConfigLoader::new().with_service_env(DotenvSource::try_from_file(".env")?))
+}
+
+fn build_router(health: Arc<HealthRegistry>, cors: CorsPolicy) -> Router {
+    // Outside load shedding and everything below it, so preflights are
+    // answered without touching them
+    let cors = make_cors_middleware(cors);
+    // Analytics is shed first when the gateway is overloaded
+    let load_shed = make_load_shed_middleware(LoadShedder::new(
+        LoadShedConfig::default()
+            .with_latency_threshold(std::time::Duration::from_secs(2))
+            .add_route_priority("/api/v1/analytics", RequestPriority::Low),
+    ));
 
     Router::new()
         .route("/", get(live))
-        .route("/health", get(health))
+        .route("/health", get(health_check))
+        .route("/readyz", get(move || readyz(health.clone())))
         .nest("/api/v1/identity", identity::router())
         .nest("/api/v1/order", order::router())
         .nest("/api/v1/escrow", escrow::router())
@@ -75,6 +179,7 @@ fn build_router() -> Router {
         .nest("/api/v1/messaging", messaging::router())
         .nest("/api/v1/analytics", analytics::router())
         .fallback(handle_404)
+        .layer(from_fn(load_shed))
         .layer(from_fn(cors))
         .layer(from_fn(|req, next| {
             Box::pin(tracking_middleware(req, next, TrackingConfig::default()))
@@ -85,13 +190,41 @@ async fn live() -> ApiResponse {
     ApiResponse::success_message("TrustFlow is Live")
 }
 
-async fn health() -> ApiResponse {
+async fn health_check() -> ApiResponse {
     ApiResponse::success_message("gateway healthy")
 }
 
-fn init_tracing() {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+async fn readyz(health: Arc<HealthRegistry>) -> ApiResponse<ReadinessReport> {
+    let report = health.check_all().await;
+    let status = if report.status.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    ApiResponse::success("readiness", report).with_status(status)
+}
+
+/// Serve the Prometheus endpoint on its own port, away from public traffic
+async fn serve_metrics(
+    metrics: Arc<MetricsExporter>,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let app = Router::new().route(
+        "/metrics",
+        get(move || {
+            let metrics = metrics.clone();
+            async move { metrics.handle().render() }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!(%addr, "metrics endpoint started");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!(error = %e, "metrics endpoint stopped");
+        }
+    });
+    Ok(())
 }
 
 async fn shutdown_signal() {
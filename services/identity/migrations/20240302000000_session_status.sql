@@ -0,0 +1,10 @@
+-- Identity Service Database Schema
+-- Migration: 20240302000000_session_status
+-- Description: Session status enum backing SessionStatus, replacing the revoked flag as the source of truth
+
+CREATE TYPE session_status AS ENUM ('ACTIVE', 'EXPIRED', 'REVOKED', 'LOGGED_OUT');
+
+ALTER TABLE sessions ADD COLUMN status session_status NOT NULL DEFAULT 'ACTIVE';
+UPDATE sessions SET status = 'REVOKED' WHERE revoked;
+
+CREATE INDEX idx_sessions_status ON sessions(status);
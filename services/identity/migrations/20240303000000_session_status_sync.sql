@@ -0,0 +1,16 @@
+-- Identity Service Database Schema
+-- Migration: 20240303000000_session_status_sync
+-- Description: Keep sessions.status and the legacy revoked flag in step, whichever one a writer sets
+
+CREATE OR REPLACE FUNCTION sync_session_status()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.revoked AND NEW.status = 'ACTIVE' THEN
+        NEW.status = 'REVOKED';
+    END IF;
+    NEW.revoked = NEW.status <> 'ACTIVE';
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER sync_sessions_status BEFORE INSERT OR UPDATE ON sessions FOR EACH ROW EXECUTE FUNCTION sync_session_status();
@@ -0,0 +1,14 @@
+-- Identity Service Database Schema
+-- Migration: 20240304000000_account_deletions
+-- Description: Pending account erasures, and erased accounts without a phone number
+
+CREATE TABLE account_deletions (
+    user_id UUID PRIMARY KEY REFERENCES users(id),
+    requested_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    erase_after TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX idx_account_deletions_erase_after ON account_deletions(erase_after);
+
+-- Erasure clears the phone number; UNIQUE still holds for the rest
+ALTER TABLE users ALTER COLUMN phone DROP NOT NULL;
@@ -0,0 +1,289 @@
+//! Self-service account endpoints
+//!
+//! Changing the password, resetting it and deleting the account are things
+//! only the account holder may do.  Each handler checks the caller with
+//! [`ensure_not_impersonated`] before touching the account, so staff acting
+//! as the user through an impersonation token get `403` and the account is
+//! left as it was.
+//!
+//! | Method | Path                   | Handler              |
+//! |--------|------------------------|----------------------|
+//! | POST   | `/account/password`    | [`change_password`]  |
+//! | POST   | `/account/deletion`    | [`request_deletion`] |
+//! | POST   | `/auth/password-reset` | [`reset_password`]   |
+
+use std::sync::Arc;
+
+use axum::{Extension, Router, extract::State, routing::post};
+use common::extractors::{Authenticated, JsonExtractor};
+use common::http::response::{ApiResponse, ApiResult};
+use common::middleware::AuthContext;
+use common::security::PasswordHasher;
+use error::AppError;
+use serde::Deserialize;
+
+use crate::account_deletion::{AccountDeletionService, DeletionRequest};
+use crate::impersonation::{SelfOnlyAction, ensure_not_impersonated};
+use crate::password_reset::PasswordResetService;
+
+/// Services behind the account endpoints
+pub struct AccountState<H> {
+    pub passwords: PasswordResetService<H>,
+    pub deletions: AccountDeletionService,
+}
+
+impl<H> Clone for AccountState<H> {
+    fn clone(&self) -> Self {
+        Self {
+            passwords: self.passwords.clone(),
+            deletions: self.deletions.clone(),
+        }
+    }
+}
+
+/// Body of `POST /account/password`
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Body of `POST /auth/password-reset`
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Router for the account endpoints; expects the auth middleware to have
+/// run for the authenticated ones
+pub fn router<H>(state: AccountState<H>) -> Router
+where
+    H: PasswordHasher + 'static,
+{
+    Router::new()
+        .route("/account/password", post(change_password::<H>))
+        .route("/account/deletion", post(request_deletion::<H>))
+        .route("/auth/password-reset", post(reset_password::<H>))
+        .with_state(state)
+}
+
+/// Change the caller's password
+pub async fn change_password<H: PasswordHasher + 'static>(
+    State(state): State<AccountState<H>>,
+    Authenticated(caller): Authenticated,
+    JsonExtractor(request): JsonExtractor<ChangePasswordRequest>,
+) -> ApiResult {
+    ensure_not_impersonated(&caller, SelfOnlyAction::ChangePassword)?;
+    state
+        .passwords
+        .change_password(
+            &caller.user_id,
+            &request.current_password,
+            &request.new_password,
+        )
+        .await
+        .map_err(AppError::from)?;
+    Ok(ApiResponse::success_message("Password changed"))
+}
+
+/// Schedule erasure of the caller's account
+pub async fn request_deletion<H: PasswordHasher + 'static>(
+    State(state): State<AccountState<H>>,
+    Authenticated(caller): Authenticated,
+) -> ApiResult<DeletionRequest> {
+    ensure_not_impersonated(&caller, SelfOnlyAction::DeleteAccount)?;
+    let request = state
+        .deletions
+        .request_deletion(&caller.user_id)
+        .await
+        .map_err(AppError::from)?;
+    Ok(ApiResponse::success("Account deletion scheduled", request))
+}
+
+/// Set a new password with a reset token
+///
+/// The token is what authorizes the reset, so the request needn't be
+/// authenticated; one made with an impersonation token is still refused.
+pub async fn reset_password<H: PasswordHasher + 'static>(
+    State(state): State<AccountState<H>>,
+    caller: Option<Extension<Arc<AuthContext>>>,
+    JsonExtractor(request): JsonExtractor<ResetPasswordRequest>,
+) -> ApiResult {
+    if let Some(Extension(caller)) = caller {
+        ensure_not_impersonated(&caller, SelfOnlyAction::ChangePassword)?;
+    }
+    state
+        .passwords
+        .reset_password(&request.token, &request.new_password)
+        .await
+        .map_err(AppError::from)?;
+    Ok(ApiResponse::success_message("Password reset"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_deletion::DeletionStore;
+    use crate::account_deletion::{
+        AccountEraser, DeletionError, InMemoryDeletionStore, PiiTombstone,
+    };
+    use crate::password_reset::{
+        PasswordResetAccounts, PasswordResetError, ResetAccount, ResetLinkSender,
+    };
+    use crate::verification_token::{
+        InMemoryVerificationTokenStore, TokenPurpose, VerificationTokenService,
+    };
+    use async_trait::async_trait;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use common::security::Sha256Hasher;
+    use common::value_objects::PasswordHash;
+    use std::sync::Mutex;
+    use time::{Duration, OffsetDateTime};
+    use tower::ServiceExt;
+
+    const ADA: &str = "0b9c6a1e-3c1f-4e8a-9d55-2f7a1c4d8e21";
+
+    /// Ada's password hashes, newest first, and everything done to her account
+    #[derive(Default)]
+    struct Account {
+        passwords: Mutex<Vec<PasswordHash>>,
+        changes: Mutex<Vec<&'static str>>,
+    }
+
+    #[async_trait]
+    impl PasswordResetAccounts for Account {
+        async fn find_by_identifier(
+            &self,
+            _identifier: &str,
+        ) -> Result<Option<ResetAccount>, PasswordResetError> {
+            Ok(None)
+        }
+
+        async fn password_history(
+            &self,
+            _user_id: &str,
+            limit: usize,
+        ) -> Result<Vec<PasswordHash>, PasswordResetError> {
+            let passwords = self.passwords.lock().unwrap();
+            Ok(passwords.iter().take(limit).cloned().collect())
+        }
+
+        async fn set_password(
+            &self,
+            _user_id: &str,
+            hash: &PasswordHash,
+        ) -> Result<(), PasswordResetError> {
+            self.passwords.lock().unwrap().insert(0, hash.clone());
+            self.changes.lock().unwrap().push("password");
+            Ok(())
+        }
+
+        async fn revoke_sessions(&self, _user_id: &str) -> Result<(), PasswordResetError> {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl AccountEraser for Account {
+        async fn anonymize(
+            &self,
+            _user_id: &str,
+            _tombstone: &PiiTombstone,
+        ) -> Result<(), DeletionError> {
+            Ok(())
+        }
+
+        async fn revoke_sessions(&self, _user_id: &str) -> Result<(), DeletionError> {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl ResetLinkSender for Account {
+        async fn send(
+            &self,
+            _account: &ResetAccount,
+            _link: &str,
+        ) -> Result<(), PasswordResetError> {
+            Ok(())
+        }
+    }
+
+    fn app() -> (
+        Router,
+        Arc<Account>,
+        Arc<InMemoryDeletionStore>,
+        VerificationTokenService,
+    ) {
+        let account = Arc::new(Account::default());
+        account
+            .passwords
+            .lock()
+            .unwrap()
+            .push(Sha256Hasher.hash("Old-passw0rd").unwrap());
+        let tokens = VerificationTokenService::new(InMemoryVerificationTokenStore::new());
+        let deletions = Arc::new(InMemoryDeletionStore::new());
+        let state = AccountState {
+            passwords: PasswordResetService::new(
+                tokens.clone(),
+                account.clone(),
+                account.clone(),
+                Sha256Hasher,
+                "https://app.trustflow.io/reset-password",
+            ),
+            deletions: AccountDeletionService::new(deletions.clone(), account.clone()),
+        };
+        (router(state), account, deletions, tokens)
+    }
+
+    fn post(uri: &str, caller: Option<AuthContext>, body: &str) -> Request<Body> {
+        let mut request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        if let Some(caller) = caller {
+            request.extensions_mut().insert(Arc::new(caller));
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn test_self_only_actions_are_refused_during_impersonation() {
+        let (app, account, deletions, tokens) = app();
+        let far_future = OffsetDateTime::now_utc() + Duration::days(365);
+        let impersonated = || Some(AuthContext::new(ADA).with_impersonator("support-7"));
+        let token = tokens
+            .issue(ADA, TokenPurpose::PasswordReset, Duration::minutes(30))
+            .await
+            .unwrap()
+            .token;
+        let change = r#"{"current_password":"Old-passw0rd","new_password":"New-passw0rd!"}"#;
+        let reset = format!(r#"{{"token":"{token}","new_password":"Newer-passw0rd!"}}"#);
+
+        for request in [
+            post("/account/password", impersonated(), change),
+            post("/account/deletion", impersonated(), ""),
+            post("/auth/password-reset", impersonated(), &reset),
+        ] {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        assert!(account.changes.lock().unwrap().is_empty());
+        assert!(deletions.due(far_future).await.unwrap().is_empty());
+
+        // The user themselves may do all of it
+        let ada = || Some(AuthContext::new(ADA));
+        for request in [
+            post("/account/password", ada(), change),
+            post("/account/deletion", ada(), ""),
+            post("/auth/password-reset", None, &reset),
+        ] {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(*account.changes.lock().unwrap(), ["password", "password"]);
+        assert_eq!(deletions.due(far_future).await.unwrap().len(), 1);
+    }
+}
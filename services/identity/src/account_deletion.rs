@@ -0,0 +1,697 @@
+//! Account erasure with a cancelable grace period
+//!
+//! `UserStatus::Deleted` only hides an account.  Erasure goes further: a
+//! user asks for deletion, has [`DEFAULT_DELETION_GRACE`] to change their
+//! mind, and afterwards [`AccountDeletionService::run_due`] (driven by
+//! [`spawn_erasure_job`]) replaces their PII with tombstones, revokes every
+//! session and publishes [`DeletionEvent::Erased`].
+//!
+//! The user row itself is kept with its id, so orders, escrows and disputes
+//! that reference it stay intact; only the personal data is gone.
+//!
+//! In production pending deletions live in Postgres ([`PgDeletionStore`]),
+//! so they survive restarts, and [`PgAccountEraser`] erases the account's
+//! rows and drops its sessions from the session store.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use error::AppError;
+use infrastructure::redis::SessionStore;
+use serde::Serialize;
+use sqlx::PgPool;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Time between a deletion request and erasure
+pub const DEFAULT_DELETION_GRACE: Duration = Duration::days(30);
+
+/// How often [`spawn_erasure_job`] looks for accounts due for erasure
+pub const DEFAULT_ERASURE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Domain used for tombstone email addresses; `.invalid` never resolves
+const TOMBSTONE_EMAIL_DOMAIN: &str = "erased.trustflow.invalid";
+
+/// Account deletion errors
+#[derive(Debug, Error)]
+pub enum DeletionError {
+    #[error("no pending deletion for account {0}")]
+    NotScheduled(String),
+
+    #[error("deletion already scheduled for {0}")]
+    AlreadyScheduled(OffsetDateTime),
+
+    #[error("account erasure failed: {0}")]
+    Erasure(String),
+
+    #[error("deletion store error: {0}")]
+    Store(String),
+}
+
+impl From<DeletionError> for AppError {
+    fn from(err: DeletionError) -> Self {
+        match err {
+            DeletionError::NotScheduled(user_id) => {
+                AppError::not_found("account deletion", user_id)
+            }
+            DeletionError::AlreadyScheduled(_) => AppError::conflict(err.to_string()),
+            DeletionError::Erasure(e) => AppError::infrastructure("account_erasure", e),
+            DeletionError::Store(e) => AppError::infrastructure("deletion_store", e),
+        }
+    }
+}
+
+/// A pending account deletion
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeletionRequest {
+    pub user_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub requested_at: OffsetDateTime,
+    /// When the account becomes eligible for erasure
+    #[serde(with = "time::serde::rfc3339")]
+    pub erase_after: OffsetDateTime,
+}
+
+impl DeletionRequest {
+    pub fn is_due(&self, now: OffsetDateTime) -> bool {
+        self.erase_after <= now
+    }
+}
+
+/// Values that replace a user's PII on erasure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiiTombstone {
+    /// Unique per user so unique-email constraints keep holding
+    pub email: String,
+    /// Phone numbers are cleared rather than faked
+    pub phone: Option<String>,
+    pub display_name: String,
+}
+
+impl PiiTombstone {
+    pub fn for_user(user_id: &str) -> Self {
+        Self {
+            email: format!("deleted-{user_id}@{TOMBSTONE_EMAIL_DOMAIN}"),
+            phone: None,
+            display_name: "Deleted user".to_string(),
+        }
+    }
+}
+
+/// Lifecycle events of an account deletion
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeletionEvent {
+    Scheduled {
+        user_id: String,
+        #[serde(with = "time::serde::rfc3339")]
+        erase_after: OffsetDateTime,
+    },
+    Cancelled {
+        user_id: String,
+    },
+    Erased {
+        user_id: String,
+        #[serde(with = "time::serde::rfc3339")]
+        erased_at: OffsetDateTime,
+    },
+}
+
+/// Storage for pending deletions
+#[async_trait]
+pub trait DeletionStore: Send + Sync {
+    /// Record a request; a user may have at most one pending deletion
+    async fn schedule(&self, request: &DeletionRequest) -> Result<(), DeletionError>;
+
+    /// Remove and return the pending request for `user_id`
+    async fn remove(&self, user_id: &str) -> Result<Option<DeletionRequest>, DeletionError>;
+
+    /// Pending requests whose grace period has ended at `now`
+    async fn due(&self, now: OffsetDateTime) -> Result<Vec<DeletionRequest>, DeletionError>;
+}
+
+/// Side effects that erase an account
+#[async_trait]
+pub trait AccountEraser: Send + Sync {
+    /// Overwrite the user's PII with `tombstone`, keeping the row and its id
+    async fn anonymize(&self, user_id: &str, tombstone: &PiiTombstone)
+    -> Result<(), DeletionError>;
+
+    /// Revoke every session and refresh token of the user
+    async fn revoke_sessions(&self, user_id: &str) -> Result<(), DeletionError>;
+}
+
+/// Receives [`DeletionEvent`]s
+#[async_trait]
+pub trait DeletionEventSink: Send + Sync {
+    async fn publish(&self, event: DeletionEvent);
+}
+
+/// Sink that drops every event
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullDeletionEventSink;
+
+#[async_trait]
+impl DeletionEventSink for NullDeletionEventSink {
+    async fn publish(&self, _event: DeletionEvent) {}
+}
+
+/// Schedules, cancels and carries out account erasure
+#[derive(Clone)]
+pub struct AccountDeletionService {
+    store: Arc<dyn DeletionStore>,
+    eraser: Arc<dyn AccountEraser>,
+    events: Arc<dyn DeletionEventSink>,
+    grace: Duration,
+}
+
+impl AccountDeletionService {
+    pub fn new(store: Arc<dyn DeletionStore>, eraser: Arc<dyn AccountEraser>) -> Self {
+        Self {
+            store,
+            eraser,
+            events: Arc::new(NullDeletionEventSink),
+            grace: DEFAULT_DELETION_GRACE,
+        }
+    }
+
+    pub fn with_event_sink(mut self, events: Arc<dyn DeletionEventSink>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn with_grace_period(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Schedule erasure of `user_id` once the grace period has passed
+    pub async fn request_deletion(&self, user_id: &str) -> Result<DeletionRequest, DeletionError> {
+        self.request_deletion_at(user_id, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Schedule erasure of `user_id` as of `now`
+    pub async fn request_deletion_at(
+        &self,
+        user_id: &str,
+        now: OffsetDateTime,
+    ) -> Result<DeletionRequest, DeletionError> {
+        let request = DeletionRequest {
+            user_id: user_id.to_string(),
+            requested_at: now,
+            erase_after: now + self.grace,
+        };
+        self.store.schedule(&request).await?;
+        info!(user_id, erase_after = %request.erase_after, "account deletion scheduled");
+        self.events
+            .publish(DeletionEvent::Scheduled {
+                user_id: request.user_id.clone(),
+                erase_after: request.erase_after,
+            })
+            .await;
+        Ok(request)
+    }
+
+    /// Cancel a pending deletion during its grace period
+    pub async fn cancel_deletion(&self, user_id: &str) -> Result<DeletionRequest, DeletionError> {
+        let request = self
+            .store
+            .remove(user_id)
+            .await?
+            .ok_or_else(|| DeletionError::NotScheduled(user_id.to_string()))?;
+        info!(user_id, "account deletion cancelled");
+        self.events
+            .publish(DeletionEvent::Cancelled {
+                user_id: user_id.to_string(),
+            })
+            .await;
+        Ok(request)
+    }
+
+    /// Erase every account whose grace period has ended, returning their ids
+    ///
+    /// A failed erasure is logged and left pending so the next run retries it.
+    pub async fn run_due(&self, now: OffsetDateTime) -> Result<Vec<String>, DeletionError> {
+        let mut erased = Vec::new();
+        for request in self.store.due(now).await? {
+            match self.erase(&request.user_id, now).await {
+                Ok(()) => erased.push(request.user_id),
+                Err(e) => error!(user_id = %request.user_id, error = %e, "account erasure failed"),
+            }
+        }
+        Ok(erased)
+    }
+
+    async fn erase(&self, user_id: &str, now: OffsetDateTime) -> Result<(), DeletionError> {
+        self.eraser
+            .anonymize(user_id, &PiiTombstone::for_user(user_id))
+            .await?;
+        self.eraser.revoke_sessions(user_id).await?;
+        self.store.remove(user_id).await?;
+        info!(user_id, "account erased");
+        self.events
+            .publish(DeletionEvent::Erased {
+                user_id: user_id.to_string(),
+                erased_at: now,
+            })
+            .await;
+        Ok(())
+    }
+}
+
+/// Run [`AccountDeletionService::run_due`] every `interval`
+pub fn spawn_erasure_job(
+    service: AccountDeletionService,
+    interval: std::time::Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = service.run_due(OffsetDateTime::now_utc()).await {
+                error!(error = %e, "account erasure job failed");
+            }
+        }
+    })
+}
+
+/// In-memory store for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDeletionStore {
+    requests: Arc<Mutex<HashMap<String, DeletionRequest>>>,
+}
+
+impl InMemoryDeletionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DeletionStore for InMemoryDeletionStore {
+    async fn schedule(&self, request: &DeletionRequest) -> Result<(), DeletionError> {
+        let mut requests = self
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(existing) = requests.get(&request.user_id) {
+            return Err(DeletionError::AlreadyScheduled(existing.erase_after));
+        }
+        requests.insert(request.user_id.clone(), request.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, user_id: &str) -> Result<Option<DeletionRequest>, DeletionError> {
+        Ok(self
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(user_id))
+    }
+
+    async fn due(&self, now: OffsetDateTime) -> Result<Vec<DeletionRequest>, DeletionError> {
+        Ok(self
+            .requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .filter(|request| request.is_due(now))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Postgres store over the `account_deletions` table
+#[derive(Debug, Clone)]
+pub struct PgDeletionStore {
+    pool: PgPool,
+}
+
+impl PgDeletionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+type DeletionRow = (String, OffsetDateTime, OffsetDateTime);
+
+fn deletion_from_row((user_id, requested_at, erase_after): DeletionRow) -> DeletionRequest {
+    DeletionRequest {
+        user_id,
+        requested_at,
+        erase_after,
+    }
+}
+
+fn store_error(e: sqlx::Error) -> DeletionError {
+    DeletionError::Store(e.to_string())
+}
+
+#[async_trait]
+impl DeletionStore for PgDeletionStore {
+    async fn schedule(&self, request: &DeletionRequest) -> Result<(), DeletionError> {
+        let inserted = sqlx::query(
+            "INSERT INTO account_deletions (user_id, requested_at, erase_after) \
+             VALUES ($1::uuid, $2, $3) ON CONFLICT (user_id) DO NOTHING",
+        )
+        .bind(&request.user_id)
+        .bind(request.requested_at)
+        .bind(request.erase_after)
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?
+        .rows_affected();
+        if inserted == 1 {
+            return Ok(());
+        }
+        let erase_after: OffsetDateTime = sqlx::query_scalar(
+            "SELECT erase_after FROM account_deletions WHERE user_id = $1::uuid",
+        )
+        .bind(&request.user_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(store_error)?;
+        Err(DeletionError::AlreadyScheduled(erase_after))
+    }
+
+    async fn remove(&self, user_id: &str) -> Result<Option<DeletionRequest>, DeletionError> {
+        let row: Option<DeletionRow> = sqlx::query_as(
+            "DELETE FROM account_deletions WHERE user_id = $1::uuid \
+             RETURNING user_id::text, requested_at, erase_after",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(store_error)?;
+        Ok(row.map(deletion_from_row))
+    }
+
+    async fn due(&self, now: OffsetDateTime) -> Result<Vec<DeletionRequest>, DeletionError> {
+        let rows: Vec<DeletionRow> = sqlx::query_as(
+            "SELECT user_id::text, requested_at, erase_after FROM account_deletions \
+             WHERE erase_after <= $1 ORDER BY erase_after",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(store_error)?;
+        Ok(rows.into_iter().map(deletion_from_row).collect())
+    }
+}
+
+/// Erases accounts in the identity database and the session store
+#[derive(Clone)]
+pub struct PgAccountEraser {
+    pool: PgPool,
+    sessions: Arc<dyn SessionStore>,
+}
+
+impl PgAccountEraser {
+    pub fn new(pool: PgPool, sessions: Arc<dyn SessionStore>) -> Self {
+        Self { pool, sessions }
+    }
+}
+
+#[async_trait]
+impl AccountEraser for PgAccountEraser {
+    async fn anonymize(
+        &self,
+        user_id: &str,
+        tombstone: &PiiTombstone,
+    ) -> Result<(), DeletionError> {
+        let erasure = |e: sqlx::Error| DeletionError::Erasure(e.to_string());
+        let mut tx = self.pool.begin().await.map_err(erasure)?;
+        sqlx::query(
+            "UPDATE users SET email = $2, phone = $3, mfa_enabled = FALSE, mfa_secret = NULL, \
+             metadata = '{}', updated_at = NOW() WHERE id = $1::uuid",
+        )
+        .bind(user_id)
+        .bind(&tombstone.email)
+        .bind(&tombstone.phone)
+        .execute(&mut *tx)
+        .await
+        .map_err(erasure)?;
+        sqlx::query(
+            "UPDATE user_profiles SET first_name = NULL, last_name = NULL, display_name = $2, \
+             avatar_url = NULL, date_of_birth = NULL, gender = NULL, address = NULL, \
+             business_name = NULL, business_registration_number = NULL, tax_id = NULL, \
+             updated_at = NOW() WHERE user_id = $1::uuid",
+        )
+        .bind(user_id)
+        .bind(&tombstone.display_name)
+        .execute(&mut *tx)
+        .await
+        .map_err(erasure)?;
+        tx.commit().await.map_err(erasure)
+    }
+
+    async fn revoke_sessions(&self, user_id: &str) -> Result<(), DeletionError> {
+        sqlx::query(
+            "UPDATE sessions SET status = 'REVOKED' WHERE user_id = $1::uuid AND status = 'ACTIVE'",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DeletionError::Erasure(e.to_string()))?;
+        self.sessions
+            .delete_user_sessions(user_id)
+            .await
+            .map_err(|e| DeletionError::Erasure(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[derive(Default)]
+    struct Recorder {
+        anonymized: Mutex<Vec<(String, PiiTombstone)>>,
+        revoked: Mutex<Vec<String>>,
+        events: Mutex<Vec<DeletionEvent>>,
+    }
+
+    #[async_trait]
+    impl AccountEraser for Recorder {
+        async fn anonymize(
+            &self,
+            user_id: &str,
+            tombstone: &PiiTombstone,
+        ) -> Result<(), DeletionError> {
+            self.anonymized
+                .lock()
+                .unwrap()
+                .push((user_id.to_string(), tombstone.clone()));
+            Ok(())
+        }
+
+        async fn revoke_sessions(&self, user_id: &str) -> Result<(), DeletionError> {
+            self.revoked.lock().unwrap().push(user_id.to_string());
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl DeletionEventSink for Recorder {
+        async fn publish(&self, event: DeletionEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    fn service() -> (AccountDeletionService, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let service =
+            AccountDeletionService::new(Arc::new(InMemoryDeletionStore::new()), recorder.clone())
+                .with_event_sink(recorder.clone())
+                .with_grace_period(Duration::days(14));
+        (service, recorder)
+    }
+
+    #[tokio::test]
+    async fn test_request_schedules_after_grace_period() {
+        let (service, recorder) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+
+        let request = service.request_deletion_at("user-1", now).await.unwrap();
+        assert_eq!(request.erase_after, datetime!(2024-05-15 12:00 UTC));
+        assert!(matches!(
+            service.request_deletion_at("user-1", now).await,
+            Err(DeletionError::AlreadyScheduled(_))
+        ));
+
+        // Nothing is erased during the grace period
+        let erased = service.run_due(now + Duration::days(13)).await.unwrap();
+        assert!(erased.is_empty());
+        assert!(recorder.anonymized.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_deletion_is_never_erased() {
+        let (service, recorder) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        service.request_deletion_at("user-1", now).await.unwrap();
+
+        service.cancel_deletion("user-1").await.unwrap();
+        assert!(matches!(
+            service.cancel_deletion("user-1").await,
+            Err(DeletionError::NotScheduled(_))
+        ));
+
+        assert!(
+            service
+                .run_due(now + Duration::days(30))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(recorder.anonymized.lock().unwrap().is_empty());
+        assert_eq!(
+            recorder.events.lock().unwrap().last(),
+            Some(&DeletionEvent::Cancelled {
+                user_id: "user-1".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_grace_run_anonymizes_revokes_and_emits() {
+        let (service, recorder) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        service.request_deletion_at("user-1", now).await.unwrap();
+        service
+            .request_deletion_at("user-2", now + Duration::days(7))
+            .await
+            .unwrap();
+
+        let run_at = now + Duration::days(15);
+        assert_eq!(service.run_due(run_at).await.unwrap(), ["user-1"]);
+
+        let anonymized = recorder.anonymized.lock().unwrap().clone();
+        assert_eq!(anonymized.len(), 1);
+        assert_eq!(anonymized[0].0, "user-1");
+        assert_eq!(
+            anonymized[0].1.email,
+            "deleted-user-1@erased.trustflow.invalid"
+        );
+        assert_eq!(anonymized[0].1.phone, None);
+        assert_eq!(*recorder.revoked.lock().unwrap(), ["user-1"]);
+        assert_eq!(
+            recorder.events.lock().unwrap().last(),
+            Some(&DeletionEvent::Erased {
+                user_id: "user-1".to_string(),
+                erased_at: run_at,
+            })
+        );
+
+        // Erased accounts are no longer pending
+        assert!(matches!(
+            service.cancel_deletion("user-1").await,
+            Err(DeletionError::NotScheduled(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_postgres_store_and_eraser_survive_a_new_service() {
+        use infrastructure::database::scratch::scratch_pool;
+        use infrastructure::redis::InMemorySessionStore;
+
+        let (pool, schema) = scratch_pool("identity_deletions").await;
+        let pool = pool.pool().clone();
+        for ddl in [
+            format!(
+                "CREATE TABLE {schema}.users (id UUID PRIMARY KEY, email TEXT NOT NULL, \
+                 phone TEXT, mfa_enabled BOOLEAN NOT NULL DEFAULT TRUE, mfa_secret TEXT, \
+                 metadata JSONB, updated_at TIMESTAMPTZ)"
+            ),
+            format!(
+                "CREATE TABLE {schema}.user_profiles (user_id UUID PRIMARY KEY, \
+                 first_name TEXT, last_name TEXT, display_name TEXT, avatar_url TEXT, \
+                 date_of_birth DATE, gender TEXT, address JSONB, business_name TEXT, \
+                 business_registration_number TEXT, tax_id TEXT, updated_at TIMESTAMPTZ)"
+            ),
+            format!(
+                "CREATE TABLE {schema}.sessions (user_id UUID NOT NULL, \
+                 status TEXT NOT NULL DEFAULT 'ACTIVE')"
+            ),
+            format!(
+                "CREATE TABLE {schema}.account_deletions (user_id UUID PRIMARY KEY, \
+                 requested_at TIMESTAMPTZ NOT NULL, erase_after TIMESTAMPTZ NOT NULL)"
+            ),
+        ] {
+            sqlx::query(&ddl).execute(&pool).await.unwrap();
+        }
+        let user_id = "0b9c6a1e-3c1f-4e8a-9d55-2f7a1c4d8e21";
+        sqlx::query(
+            "INSERT INTO users (id, email, phone, mfa_secret) \
+             VALUES ($1::uuid, 'ada@example.com', '+2348012345678', 'JBSWY3DP')",
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO user_profiles (user_id, first_name, display_name) \
+             VALUES ($1::uuid, 'Ada', 'Ada L.')",
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO sessions (user_id) VALUES ($1::uuid)")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let service = || {
+            AccountDeletionService::new(
+                Arc::new(PgDeletionStore::new(pool.clone())),
+                Arc::new(PgAccountEraser::new(
+                    pool.clone(),
+                    Arc::new(InMemorySessionStore::new()),
+                )),
+            )
+        };
+        let now = datetime!(2024-05-01 12:00 UTC);
+        service().request_deletion_at(user_id, now).await.unwrap();
+        assert!(matches!(
+            service().request_deletion_at(user_id, now).await,
+            Err(DeletionError::AlreadyScheduled(at)) if at == now + DEFAULT_DELETION_GRACE
+        ));
+
+        // A service started later still finds the pending erasure
+        let run_at = now + DEFAULT_DELETION_GRACE;
+        assert_eq!(service().run_due(run_at).await.unwrap(), [user_id]);
+        let (email, phone, secret): (String, Option<String>, Option<String>) =
+            sqlx::query_as("SELECT email, phone, mfa_secret FROM users")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(email, PiiTombstone::for_user(user_id).email);
+        assert_eq!((phone, secret), (None, None));
+        let (first_name, display_name): (Option<String>, String) =
+            sqlx::query_as("SELECT first_name, display_name FROM user_profiles")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!((first_name, display_name.as_str()), (None, "Deleted user"));
+        let status: String = sqlx::query_scalar("SELECT status FROM sessions")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "REVOKED");
+        assert!(service().run_due(run_at).await.unwrap().is_empty());
+
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! Admin actions on user accounts
+//!
+//! Staff holding [`SUSPEND_PERMISSION`] can suspend many accounts in one
+//! request.  A bulk suspension keeps going past ids that are malformed,
+//! unknown or belong to deleted accounts and reports each of them, so a
+//! partly successful request answers `207 Multi-Status` with the per-item
+//! outcome (see [`BulkResult`]).
+//!
+//! | Method | Path                        | Handler                |
+//! |--------|-----------------------------|------------------------|
+//! | POST   | `/admin/users/bulk/suspend` | [`bulk_suspend_users`] |
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{Router, extract::State, routing::post};
+use common::extractors::{Authenticated, JsonExtractor};
+use common::http::bulk::BulkResult;
+use common::http::response::ApiResult;
+use common::middleware::PermissionSource;
+use error::core::AuthErrorCode;
+use error::http::ApiError;
+use error::{AppError, AppResult};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::domain::entities::{User, UserId};
+
+/// Permission needed to suspend users
+pub const SUSPEND_PERMISSION: &str = "users:suspend";
+
+/// Most accounts one bulk request may suspend
+pub const MAX_BULK_SUSPEND: usize = 100;
+
+/// Where admin actions load and save accounts
+#[async_trait]
+pub trait AdminUsers: Send + Sync {
+    async fn find(&self, user_id: &UserId) -> AppResult<Option<User>>;
+
+    async fn save(&self, user: &User) -> AppResult<()>;
+}
+
+/// Suspends accounts on behalf of permitted staff
+#[derive(Clone)]
+pub struct UserSuspensionService {
+    users: Arc<dyn AdminUsers>,
+    permissions: Arc<dyn PermissionSource>,
+}
+
+impl UserSuspensionService {
+    pub fn new(users: Arc<dyn AdminUsers>, permissions: Arc<dyn PermissionSource>) -> Self {
+        Self { users, permissions }
+    }
+
+    /// Suspend `user_id` for `reason`; suspending a suspended account again
+    /// only updates the reason
+    pub async fn suspend(&self, user_id: &str, reason: &str) -> AppResult<UserId> {
+        let id: UserId = user_id.parse().map_err(|_| {
+            AppError::validation_with_field(
+                format!("'{user_id}' is not a valid user id"),
+                "user_ids",
+            )
+        })?;
+        let mut user = self
+            .users
+            .find(&id)
+            .await?
+            .filter(|user| !user.is_deleted())
+            .ok_or_else(|| AppError::not_found("user", user_id))?;
+        user.suspend(reason);
+        self.users.save(&user).await?;
+        Ok(id)
+    }
+
+    /// Suspend every account in `user_ids` that can be, reporting the rest
+    pub async fn bulk_suspend(
+        &self,
+        admin_id: &str,
+        user_ids: Vec<String>,
+        reason: &str,
+    ) -> AppResult<BulkResult<UserId>> {
+        if !self
+            .permissions
+            .has_permission(admin_id, SUSPEND_PERMISSION)
+            .await?
+        {
+            warn!(admin_id, "bulk suspension refused");
+            return Err(AppError::authz(
+                format!("{admin_id} may not suspend users"),
+                AuthErrorCode::InsufficientPermissions,
+            ));
+        }
+
+        let result = BulkResult::process(user_ids, |user_id| async move {
+            self.suspend(&user_id, reason).await.map_err(ApiError::from)
+        })
+        .await;
+        warn!(
+            admin_id,
+            suspended = result.succeeded.len(),
+            failed = result.failed.len(),
+            "users suspended"
+        );
+        Ok(result)
+    }
+}
+
+/// Body of `POST /admin/users/bulk/suspend`
+#[derive(Debug, Deserialize)]
+pub struct BulkSuspendRequest {
+    pub user_ids: Vec<String>,
+    pub reason: String,
+}
+
+/// Router for the admin endpoints; expects the auth middleware to have run
+pub fn router(suspensions: UserSuspensionService) -> Router {
+    Router::new()
+        .route("/admin/users/bulk/suspend", post(bulk_suspend_users))
+        .with_state(suspensions)
+}
+
+/// Suspend many accounts, continuing past the ones that fail
+pub async fn bulk_suspend_users(
+    State(suspensions): State<UserSuspensionService>,
+    Authenticated(caller): Authenticated,
+    JsonExtractor(request): JsonExtractor<BulkSuspendRequest>,
+) -> ApiResult<BulkResult<UserId>> {
+    if request.user_ids.is_empty() || request.user_ids.len() > MAX_BULK_SUSPEND {
+        return Err(AppError::validation_with_field(
+            format!("between 1 and {MAX_BULK_SUSPEND} user ids are required"),
+            "user_ids",
+        )
+        .into());
+    }
+    if request.reason.trim().is_empty() {
+        return Err(AppError::validation_with_field("a reason is required", "reason").into());
+    }
+
+    let result = suspensions
+        .bulk_suspend(&caller.user_id, request.user_ids, &request.reason)
+        .await?;
+    Ok(result.into_api_response("Suspend users"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::RoleId;
+    use crate::domain::enums::UserStatus;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use common::middleware::AuthContext;
+    use common::security::{PasswordHasher, Sha256Hasher};
+    use common::testing::body_json;
+    use common::value_objects::EmailAddress;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    const ADMIN: &str = "admin-1";
+
+    #[derive(Default)]
+    struct Users(Mutex<HashMap<UserId, User>>);
+
+    impl Users {
+        fn add(&self, email: &str) -> User {
+            let user = User::new_pending(
+                EmailAddress::new(email).unwrap(),
+                Sha256Hasher.hash("correct horse").unwrap(),
+                RoleId::new(),
+            );
+            self.0.lock().unwrap().insert(user.id, user.clone());
+            user
+        }
+
+        fn get(&self, id: &UserId) -> User {
+            self.0.lock().unwrap()[id].clone()
+        }
+    }
+
+    #[async_trait]
+    impl AdminUsers for Users {
+        async fn find(&self, user_id: &UserId) -> AppResult<Option<User>> {
+            Ok(self.0.lock().unwrap().get(user_id).cloned())
+        }
+
+        async fn save(&self, user: &User) -> AppResult<()> {
+            self.0.lock().unwrap().insert(user.id, user.clone());
+            Ok(())
+        }
+    }
+
+    /// Only [`ADMIN`] may suspend users
+    struct StaffPermissions;
+
+    #[async_trait]
+    impl PermissionSource for StaffPermissions {
+        async fn has_permission(&self, user_id: &str, permission: &str) -> AppResult<bool> {
+            Ok(user_id == ADMIN && permission == SUSPEND_PERMISSION)
+        }
+
+        async fn verification_level(&self, _user_id: &str) -> AppResult<u8> {
+            Ok(0)
+        }
+    }
+
+    fn app() -> (Router, Arc<Users>) {
+        let users = Arc::new(Users::default());
+        let service = UserSuspensionService::new(users.clone(), Arc::new(StaffPermissions));
+        (router(service), users)
+    }
+
+    fn suspend(caller: &str, user_ids: &[String]) -> Request<Body> {
+        let body = serde_json::json!({ "user_ids": user_ids, "reason": "chargeback fraud" });
+        let mut request = Request::post("/admin/users/bulk/suspend")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(Arc::new(AuthContext::new(caller)));
+        request
+    }
+
+    #[tokio::test]
+    async fn test_bulk_suspend_with_one_invalid_id_is_partial_success() {
+        let (app, users) = app();
+        let ada = users.add("ada@example.com");
+        let bob = users.add("bob@example.com");
+        let unknown = UserId::new();
+        let ids = [
+            ada.id.to_string(),
+            "not-a-user-id".to_string(),
+            bob.id.to_string(),
+            unknown.to_string(),
+        ];
+
+        let response = app.oneshot(suspend(ADMIN, &ids)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let body = body_json(response).await;
+        assert_eq!(body["message"], "Suspend users: 2 of 4 succeeded");
+        assert_eq!(
+            body["data"]["succeeded"],
+            serde_json::json!([ada.id.to_string(), bob.id.to_string()])
+        );
+        let failed: Vec<_> = body["data"]["failed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["id"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(failed, ["not-a-user-id".to_string(), unknown.to_string()]);
+
+        for id in [ada.id, bob.id] {
+            let user = users.get(&id);
+            assert_eq!(user.status, UserStatus::Suspended);
+            assert_eq!(user.suspension_reason.as_deref(), Some("chargeback fraud"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bulk_suspend_needs_the_permission() {
+        let (app, users) = app();
+        let ada = users.add("ada@example.com");
+
+        let response = app
+            .oneshot(suspend("support-7", &[ada.id.to_string()]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(users.get(&ada.id).status, UserStatus::Pending);
+    }
+}
@@ -0,0 +1,297 @@
+//! Credential stuffing detection
+//!
+//! A brute-force attack tries many passwords against one account; credential
+//! stuffing replays leaked username/password pairs, so it tries many
+//! *accounts*, each only once or twice, and per-account lockouts never fire.
+//! [`CredentialStuffingDetector`] looks at it from the source's side instead:
+//! it tracks the distinct identifiers that failed to log in from each IP
+//! address (and, when known, each autonomous system, since attacks are
+//! spread over addresses of one hosting provider) in a sliding window.  When
+//! a source goes over its limit it is blocked for a while and a
+//! [`CredentialStuffingDetected`] is returned, which the caller publishes as
+//! `SuspiciousActivityType::CredentialStuffing`.
+//!
+//! State is kept in memory, per instance; behind a load balancer each
+//! instance sees its share of the attempts, so limits should be set with the
+//! number of instances in mind.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+
+/// Where login attempts come from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum AttemptSource {
+    Ip(String),
+    /// Autonomous system number of the client's network
+    Asn(u32),
+}
+
+impl fmt::Display for AttemptSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "ip {ip}"),
+            Self::Asn(asn) => write!(f, "AS{asn}"),
+        }
+    }
+}
+
+/// Limits of the detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CredentialStuffingConfig {
+    /// How far back failed logins are counted
+    pub window: Duration,
+    /// Distinct accounts that may fail from one IP within the window
+    pub max_accounts_per_ip: usize,
+    /// Distinct accounts that may fail from one AS within the window
+    pub max_accounts_per_asn: usize,
+    /// How long a source that went over its limit stays blocked
+    pub block_for: Duration,
+}
+
+impl Default for CredentialStuffingConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::minutes(10),
+            max_accounts_per_ip: 10,
+            max_accounts_per_asn: 100,
+            block_for: Duration::hours(1),
+        }
+    }
+}
+
+/// A source found trying many accounts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CredentialStuffingDetected {
+    pub source: AttemptSource,
+    /// Distinct accounts that failed from the source within the window
+    pub distinct_accounts: usize,
+    #[serde(with = "time::serde::rfc3339")]
+    pub blocked_until: OffsetDateTime,
+}
+
+#[derive(Debug, Default)]
+struct SourceState {
+    /// Failed attempts within the window, oldest first
+    failures: VecDeque<(OffsetDateTime, String)>,
+    blocked_until: Option<OffsetDateTime>,
+}
+
+impl SourceState {
+    fn prune(&mut self, now: OffsetDateTime, window: Duration) {
+        while let Some((at, _)) = self.failures.front() {
+            if *at > now - window {
+                break;
+            }
+            self.failures.pop_front();
+        }
+        if self.blocked_until.is_some_and(|until| until <= now) {
+            self.blocked_until = None;
+        }
+    }
+
+    fn distinct_accounts(&self) -> usize {
+        self.failures
+            .iter()
+            .map(|(_, account)| account.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Tracks failed logins per source and blocks sources that try many accounts
+///
+/// Cheap to clone; clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStuffingDetector {
+    config: CredentialStuffingConfig,
+    sources: Arc<Mutex<HashMap<AttemptSource, SourceState>>>,
+}
+
+impl CredentialStuffingDetector {
+    pub fn new(config: CredentialStuffingConfig) -> Self {
+        Self {
+            config,
+            sources: Arc::default(),
+        }
+    }
+
+    fn sources(ip: &str, asn: Option<u32>) -> impl Iterator<Item = AttemptSource> {
+        std::iter::once(AttemptSource::Ip(ip.to_string())).chain(asn.map(AttemptSource::Asn))
+    }
+
+    fn limit(&self, source: &AttemptSource) -> usize {
+        match source {
+            AttemptSource::Ip(_) => self.config.max_accounts_per_ip,
+            AttemptSource::Asn(_) => self.config.max_accounts_per_asn,
+        }
+    }
+
+    /// Until when logins from `ip` (or its AS) are blocked, if they are
+    pub fn blocked_until(&self, ip: &str, asn: Option<u32>) -> Option<OffsetDateTime> {
+        self.blocked_until_at(ip, asn, OffsetDateTime::now_utc())
+    }
+
+    /// Until when logins from `ip` (or its AS) are blocked as of `now`
+    pub fn blocked_until_at(
+        &self,
+        ip: &str,
+        asn: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Option<OffsetDateTime> {
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        Self::sources(ip, asn)
+            .filter_map(|source| {
+                let state = sources.get_mut(&source)?;
+                state.prune(now, self.config.window);
+                state.blocked_until
+            })
+            .max()
+    }
+
+    /// Record a failed login for `account` from `ip`
+    ///
+    /// Returns the detection when this failure takes a source over its limit.
+    pub fn record_failure(
+        &self,
+        account: &str,
+        ip: &str,
+        asn: Option<u32>,
+    ) -> Option<CredentialStuffingDetected> {
+        self.record_failure_at(account, ip, asn, OffsetDateTime::now_utc())
+    }
+
+    /// Record a failed login for `account` from `ip` at `now`
+    pub fn record_failure_at(
+        &self,
+        account: &str,
+        ip: &str,
+        asn: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Option<CredentialStuffingDetected> {
+        // `Ada@example.com` and `ada@example.com` are the same account
+        let account = account.trim().to_lowercase();
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut detected = None;
+        for source in Self::sources(ip, asn) {
+            let limit = self.limit(&source);
+            let state = sources.entry(source.clone()).or_default();
+            state.prune(now, self.config.window);
+            state.failures.push_back((now, account.clone()));
+
+            let distinct_accounts = state.distinct_accounts();
+            if state.blocked_until.is_some() || distinct_accounts <= limit {
+                continue;
+            }
+            let blocked_until = now + self.config.block_for;
+            state.blocked_until = Some(blocked_until);
+            warn!(
+                %source,
+                distinct_accounts,
+                %blocked_until,
+                "credential stuffing detected, blocking source"
+            );
+            detected.get_or_insert(CredentialStuffingDetected {
+                source,
+                distinct_accounts,
+                blocked_until,
+            });
+        }
+        detected
+    }
+
+    /// Drop state of sources with no recent failures and no block
+    pub fn purge_idle(&self, now: OffsetDateTime) {
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.retain(|_, state| {
+            state.prune(now, self.config.window);
+            !state.failures.is_empty() || state.blocked_until.is_some()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn detector() -> CredentialStuffingDetector {
+        CredentialStuffingDetector::new(CredentialStuffingConfig {
+            window: Duration::minutes(5),
+            max_accounts_per_ip: 5,
+            max_accounts_per_asn: 50,
+            block_for: Duration::minutes(30),
+        })
+    }
+
+    #[test]
+    fn test_many_distinct_accounts_from_one_ip_are_detected_and_blocked() {
+        let detector = detector();
+        let start = datetime!(2024-05-01 12:00 UTC);
+        let ip = "203.0.113.7";
+
+        // One account failing over and over is not stuffing
+        for second in 0..20 {
+            let now = start + Duration::seconds(second);
+            assert_eq!(
+                detector.record_failure_at("Ada@example.com", ip, Some(64500), now),
+                None
+            );
+        }
+
+        let mut detections = Vec::new();
+        for n in 1..=8 {
+            let now = start + Duration::seconds(30 + n);
+            let account = format!("user{n}@example.com");
+            detections.extend(detector.record_failure_at(&account, ip, Some(64500), now));
+        }
+        let detected_at = start + Duration::seconds(35);
+        assert_eq!(
+            detections,
+            [CredentialStuffingDetected {
+                source: AttemptSource::Ip(ip.to_string()),
+                distinct_accounts: 6,
+                blocked_until: detected_at + Duration::minutes(30),
+            }]
+        );
+
+        let now = start + Duration::minutes(10);
+        assert_eq!(
+            detector.blocked_until_at(ip, None, now),
+            Some(detected_at + Duration::minutes(30))
+        );
+        // Other addresses, even in the same AS, are still let through
+        assert_eq!(
+            detector.blocked_until_at("203.0.113.8", Some(64500), now),
+            None
+        );
+        assert_eq!(
+            detector.blocked_until_at(ip, None, detected_at + Duration::minutes(30)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_failures_outside_the_window_are_forgotten() {
+        let detector = detector();
+        let start = datetime!(2024-05-01 12:00 UTC);
+
+        for n in 0..12 {
+            let now = start + Duration::minutes(n);
+            let account = format!("user{n}@example.com");
+            assert_eq!(
+                detector.record_failure_at(&account, "198.51.100.4", None, now),
+                None
+            );
+        }
+
+        detector.purge_idle(start + Duration::hours(1));
+        assert!(detector.sources.lock().unwrap().is_empty());
+    }
+}
@@ -6,10 +6,10 @@
 
 pub mod role;
 pub mod user;
-pub mod verification;
 pub mod session;
+pub mod verification;
 
 pub use role::*;
 pub use user::*;
-pub use verification::*;
 pub use session::*;
+pub use verification::*;
@@ -1,5 +1,4 @@
-use crate::domain::value_objects::Permission;
-use common::Timestamp;
+use common::value_objects::Timestamp;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -27,6 +26,12 @@ impl RoleId {
     }
 }
 
+impl Default for RoleId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Individual permission entry
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Permission {
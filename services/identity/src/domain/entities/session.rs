@@ -1,7 +1,11 @@
+use crate::domain::entities::UserId;
+use crate::domain::enums::SessionStatus;
+use common::value_objects::identity::DeviceId;
+use common::value_objects::network::IpAddress;
+use common::value_objects::Timestamp;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-
 /// Session entity for managing user sessions
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Session {
@@ -14,12 +18,16 @@ pub struct Session {
     pub refresh_token_hash: String,
     pub expires_at: Timestamp,
     pub refresh_expires_at: Timestamp,
+    /// Source of truth for whether the session may be used
+    pub status: SessionStatus,
+    /// Legacy flag, kept in step with `status` for older readers
     pub revoked: bool,
     pub created_at: Timestamp,
     pub last_activity_at: Timestamp,
 }
 
 impl Session {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_id: UserId,
         device_id: DeviceId,
@@ -40,6 +48,7 @@ impl Session {
             refresh_token_hash,
             expires_at,
             refresh_expires_at,
+            status: SessionStatus::Active,
             revoked: false,
             created_at: Timestamp::now(),
             last_activity_at: Timestamp::now(),
@@ -48,11 +57,19 @@ impl Session {
 
     /// Check if session is valid
     pub fn is_valid(&self) -> bool {
-        !self.revoked && self.expires_at.inner() > time::OffsetDateTime::now_utc()
+        self.status == SessionStatus::Active
+            && self.expires_at.inner() > time::OffsetDateTime::now_utc()
     }
 
     /// Revoke session
     pub fn revoke(&mut self) {
+        self.status = SessionStatus::Revoked;
+        self.revoked = true;
+    }
+
+    /// End the session at the user's request
+    pub fn log_out(&mut self) {
+        self.status = SessionStatus::LoggedOut;
         self.revoked = true;
     }
 }
@@ -65,4 +82,10 @@ impl SessionId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
-}
\ No newline at end of file
+}
+
+impl Default for SessionId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
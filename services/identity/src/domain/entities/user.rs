@@ -1,20 +1,25 @@
 use crate::domain::{
     entities::RoleId,
-    enums::{UserRole, UserStatus, VerificationLevel},
+    enums::{UserStatus, VerificationLevel},
 };
-use common::{EmailAddress, PasswordHash, PhoneNumber, Timestamp};
+use common::value_objects::network::Url;
+use common::value_objects::{EmailAddress, PasswordHash, PhoneNumber, Timestamp};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct User {
     pub id: UserId,
     pub email: EmailAddress,
-    pub phone: PhoneNumber,
+    pub phone: Option<PhoneNumber>,
     pub password_hash: PasswordHash,
     pub role: RoleId,
     pub status: UserStatus,
     pub verification_level: VerificationLevel,
+    pub suspension_reason: Option<String>,
+    pub last_login_at: Option<Timestamp>,
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
     pub deleted_at: Option<Timestamp>,
@@ -30,6 +35,8 @@ impl User {
             role,
             status: UserStatus::Pending,
             verification_level: VerificationLevel::Level0,
+            suspension_reason: None,
+            last_login_at: None,
             created_at: Timestamp::now(),
             updated_at: Timestamp::now(),
             deleted_at: None,
@@ -44,7 +51,7 @@ impl User {
     /// Suspend user with reason
     pub fn suspend(&mut self, reason: &str) {
         self.status = UserStatus::Suspended;
-        self.metadata.insert("suspension_reason", reason);
+        self.suspension_reason = Some(reason.to_string());
         self.updated_at = Timestamp::now();
     }
 
@@ -71,8 +78,6 @@ impl User {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-
 /// User ID newtype wrapper for type safety
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct UserId(pub Uuid);
@@ -83,6 +88,26 @@ impl UserId {
     }
 }
 
+impl Default for UserId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for UserId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(Self)
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// User profile entity - extended user information
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UserProfile {
@@ -1,6 +1,22 @@
+use crate::domain::entities::UserId;
+use crate::domain::enums::{
+    DocumentType, VerificationLevel, VerificationMethod, VerificationStatus,
+};
+use common::value_objects::network::Url;
+use common::value_objects::Timestamp;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Free-form key/value details attached to a verification record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata(pub serde_json::Value);
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self(serde_json::Value::Object(serde_json::Map::new()))
+    }
+}
+
 impl Metadata {
     pub fn insert(&mut self, key: &str, value: &str) {
         let value = serde_json::Value::String(value.to_string());
@@ -79,3 +95,9 @@ impl VerificationId {
         Self(Uuid::new_v4())
     }
 }
+
+impl Default for VerificationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
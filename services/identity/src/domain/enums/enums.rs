@@ -1,18 +1,21 @@
 //! Domain enums for Identity Service
 //!
 //! Core enums defining user roles, statuses, verification levels, etc.
+//!
+//! The persisted enums map to the Postgres enum types of the same snake_case
+//! name, created by the service migrations, with SCREAMING_SNAKE_CASE labels.
 
 use serde::{Deserialize, Serialize};
-// use sqlx::Type;
-use strum_macros::{Display, EnumString};
+use sqlx::Type;
 use thiserror::Error;
 
 /// User status enum - defines current account state
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "user_status", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "user_status", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum UserStatus {
     /// Account created, awaiting email/phone verification
+    #[default]
     Pending = 0,
     /// Account active and in good standing
     Active = 1,
@@ -26,12 +29,6 @@ pub enum UserStatus {
     AwaitingApproval = 5,
 }
 
-impl Default for UserStatus {
-    fn default() -> Self {
-        Self::Pending
-    }
-}
-
 impl UserStatus {
     /// Check if user can authenticate
     pub fn can_authenticate(&self) -> bool {
@@ -57,11 +54,12 @@ impl UserStatus {
 }
 
 /// Verification level enum - tiered identity verification
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "verification_level", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "verification_level", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum VerificationLevel {
     /// Level 0: Email/Phone only (basic)
+    #[default]
     Level0 = 0,
     /// Level 1: Basic KYC (Name, Phone, Email verified)
     Level1 = 1,
@@ -73,12 +71,6 @@ pub enum VerificationLevel {
     Level4 = 4,
 }
 
-impl Default for VerificationLevel {
-    fn default() -> Self {
-        Self::Level0
-    }
-}
-
 impl VerificationLevel {
     /// Get next level (if any)
     pub fn next_level(&self) -> Option<Self> {
@@ -133,11 +125,12 @@ impl VerificationLevel {
 }
 
 /// Verification status enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "verification_status", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "verification_status", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum VerificationStatus {
     /// Verification in progress
+    #[default]
     Pending = 0,
     /// Verification approved
     Approved = 1,
@@ -151,12 +144,6 @@ pub enum VerificationStatus {
     Cancelled = 5,
 }
 
-impl Default for VerificationStatus {
-    fn default() -> Self {
-        Self::Pending
-    }
-}
-
 impl VerificationStatus {
     /// Check if verification is successful
     pub fn is_successful(&self) -> bool {
@@ -175,11 +162,12 @@ impl VerificationStatus {
 }
 
 /// Verification method enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "verification_method", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "verification_method", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum VerificationMethod {
     /// Email verification link
+    #[default]
     Email = 1,
     /// SMS OTP verification
     Phone = 2,
@@ -197,18 +185,13 @@ pub enum VerificationMethod {
     Bank = 8,
 }
 
-impl Default for VerificationMethod {
-    fn default() -> Self {
-        Self::Email
-    }
-}
-
 /// Document type enum for Nigerian documents
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "document_type", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "document_type", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum DocumentType {
     /// National Identification Number (NIN)
+    #[default]
     Nin = 1,
     /// Driver's License
     DriversLicense = 2,
@@ -226,12 +209,6 @@ pub enum DocumentType {
     Other = 8,
 }
 
-impl Default for DocumentType {
-    fn default() -> Self {
-        Self::Nin
-    }
-}
-
 impl DocumentType {
     /// Get document name
     pub fn name(&self) -> &'static str {
@@ -266,11 +243,12 @@ impl DocumentType {
 }
 
 /// MFA method enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
-//#[sqlx(type_name = "mfa_method", rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
+#[sqlx(type_name = "mfa_method", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MfaMethod {
     /// Time-based OTP (Google Authenticator, etc.)
+    #[default]
     Totp = 1,
     /// SMS OTP
     Sms = 2,
@@ -282,18 +260,13 @@ pub enum MfaMethod {
     Webauthn = 5,
 }
 
-impl Default for MfaMethod {
-    fn default() -> Self {
-        Self::Totp
-    }
-}
-
 /// Session status enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type, Default)]
 #[sqlx(type_name = "session_status", rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SessionStatus {
     /// Session is active
+    #[default]
     Active = 1,
     /// Session expired
     Expired = 2,
@@ -303,12 +276,6 @@ pub enum SessionStatus {
     LoggedOut = 4,
 }
 
-impl Default for SessionStatus {
-    fn default() -> Self {
-        Self::Active
-    }
-}
-
 /// Login failure reason enum
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum LoginFailureReason {
@@ -368,6 +335,128 @@ pub enum VerificationError {
     MaxAttemptsExceeded,
 }
 
+#[cfg(test)]
+mod db_tests {
+    use super::*;
+    use sqlx::{Connection, PgConnection, Postgres};
+
+    /// `CREATE TYPE` statements from the migrations, so the enums are checked
+    /// against the types the database actually gets
+    fn enum_types() -> impl Iterator<Item = &'static str> {
+        [
+            include_str!("../../../migrations/20240301000000_initial_schema.sql"),
+            include_str!("../../../migrations/20240302000000_session_status.sql"),
+        ]
+        .into_iter()
+        .flat_map(str::lines)
+        .filter(|line| line.starts_with("CREATE TYPE"))
+    }
+
+    async fn round_trip<T>(conn: &mut PgConnection, type_name: &str, values: &[T])
+    where
+        T: for<'q> sqlx::Encode<'q, Postgres>
+            + for<'r> sqlx::Decode<'r, Postgres>
+            + Type<Postgres>
+            + Copy
+            + PartialEq
+            + std::fmt::Debug
+            + Send
+            + Unpin,
+    {
+        sqlx::query(&format!("CREATE TEMP TABLE {type_name}_values (value {type_name} NOT NULL)"))
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        for &value in values {
+            let stored: T = sqlx::query_scalar(&format!(
+                "INSERT INTO {type_name}_values (value) VALUES ($1) RETURNING value"
+            ))
+            .bind(value)
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+            assert_eq!(stored, value, "{type_name}");
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running PostgreSQL instance (DATABASE_URL)"]
+    async fn test_enums_round_trip_through_postgres_enum_columns() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        // Everything below is rolled back when the transaction drops
+        let mut tx = conn.begin().await.unwrap();
+        sqlx::query("CREATE SCHEMA identity_enum_round_trip")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query("SET LOCAL search_path TO identity_enum_round_trip")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        for ddl in enum_types() {
+            sqlx::query(ddl).execute(&mut *tx).await.unwrap();
+        }
+
+        use UserStatus as U;
+        round_trip(
+            &mut tx,
+            "user_status",
+            &[U::Pending, U::Active, U::Suspended, U::Deleted, U::Locked, U::AwaitingApproval],
+        )
+        .await;
+        use VerificationLevel as L;
+        round_trip(
+            &mut tx,
+            "verification_level",
+            &[L::Level0, L::Level1, L::Level2, L::Level3, L::Level4],
+        )
+        .await;
+        use VerificationStatus as S;
+        round_trip(
+            &mut tx,
+            "verification_status",
+            &[S::Pending, S::Approved, S::Rejected, S::Expired, S::ManualReview, S::Cancelled],
+        )
+        .await;
+        use VerificationMethod as V;
+        round_trip(
+            &mut tx,
+            "verification_method",
+            &[
+                V::Email, V::Phone, V::Document, V::Manual, V::Biometric, V::Social, V::Provider,
+                V::Bank,
+            ],
+        )
+        .await;
+        use DocumentType as D;
+        round_trip(
+            &mut tx,
+            "document_type",
+            &[
+                D::Nin, D::DriversLicense, D::InternationalPassport, D::CacCertificate, D::Tin,
+                D::VotersCard, D::Pvc, D::Other,
+            ],
+        )
+        .await;
+        use MfaMethod as M;
+        round_trip(
+            &mut tx,
+            "mfa_method",
+            &[M::Totp, M::Sms, M::Email, M::Push, M::Webauthn],
+        )
+        .await;
+        use SessionStatus as Ss;
+        round_trip(
+            &mut tx,
+            "session_status",
+            &[Ss::Active, Ss::Expired, Ss::Revoked, Ss::LoggedOut],
+        )
+        .await;
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
@@ -0,0 +1,6 @@
+//! Domain enums for Identity Service
+
+#[allow(clippy::module_inception)]
+mod enums;
+
+pub use enums::*;
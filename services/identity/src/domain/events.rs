@@ -4,7 +4,10 @@
 
 use crate::domain::entities::*;
 use crate::domain::enums::*;
-use common::Timestamp;
+use crate::domain::value_objects::GeoLocation;
+use common::value_objects::identity::DeviceId;
+use common::value_objects::network::IpAddress;
+use common::value_objects::{EmailAddress, PhoneNumber, Timestamp};
 use serde::{Deserialize, Serialize};
 
 /// Base event trait
@@ -19,8 +22,8 @@ pub trait DomainEvent: Send + Sync {
 pub struct UserRegisteredEvent {
     pub user_id: UserId,
     pub email: EmailAddress,
-    pub phone: PhoneNumber,
-    pub role: UserRole,
+    pub phone: Option<PhoneNumber>,
+    pub role: RoleId,
     pub timestamp: Timestamp,
 }
 
@@ -64,9 +67,13 @@ impl DomainEvent for EmailVerifiedEvent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserLoggedInEvent {
     pub user_id: UserId,
-    pub session_id: SessionId,
+    /// Id of the session in the session store
+    pub session_id: String,
     pub device_id: DeviceId,
     pub ip_address: IpAddress,
+    /// Where `ip_address` resolved to, if anywhere
+    #[serde(default)]
+    pub location: Option<GeoLocation>,
     pub timestamp: Timestamp,
 }
 
@@ -295,7 +302,7 @@ impl DomainEvent for VerificationRejectedEvent {
 pub struct RoleAssignedEvent {
     pub user_id: UserId,
     pub role_id: RoleId,
-    pub role_name: RoleName,
+    pub role_name: String,
     pub assigned_by: UserId,
     pub timestamp: Timestamp,
 }
@@ -319,7 +326,7 @@ impl DomainEvent for RoleAssignedEvent {
 pub struct RoleRemovedEvent {
     pub user_id: UserId,
     pub role_id: RoleId,
-    pub role_name: RoleName,
+    pub role_name: String,
     pub removed_by: UserId,
     pub timestamp: Timestamp,
 }
@@ -364,7 +371,9 @@ impl DomainEvent for SessionRevokedEvent {
 /// Suspicious activity detected event
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuspiciousActivityEvent {
-    pub user_id: UserId,
+    /// Account the activity targeted; `None` when it came from a source
+    /// trying many accounts
+    pub user_id: Option<UserId>,
     pub activity_type: SuspiciousActivityType,
     pub details: String,
     pub ip_address: IpAddress,
@@ -381,7 +390,10 @@ impl DomainEvent for SuspiciousActivityEvent {
     }
 
     fn aggregate_id(&self) -> String {
-        self.user_id.0.to_string()
+        match &self.user_id {
+            Some(user_id) => user_id.0.to_string(),
+            None => self.ip_address.0.clone(),
+        }
     }
 }
 
@@ -401,6 +413,8 @@ pub enum LogoutReason {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PasswordChangeReason {
     UserInitiated,
+    /// Forgotten password reset through an emailed or texted link
+    Reset,
     SecurityBreach,
     AdminInitiated,
     Expiration,
@@ -451,3 +465,24 @@ impl EventPublisher for NullEventPublisher {
         Ok(())
     }
 }
+
+/// Event publisher keeping `(event_type, aggregate_id)` of every event, for
+/// tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordingPublisher(pub(crate) std::sync::Mutex<Vec<(String, String)>>);
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl EventPublisher for RecordingPublisher {
+    async fn publish(
+        &self,
+        event: &dyn DomainEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.0
+            .lock()
+            .unwrap()
+            .push((event.event_type().to_string(), event.aggregate_id()));
+        Ok(())
+    }
+}
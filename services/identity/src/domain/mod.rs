@@ -5,3 +5,4 @@
 pub mod entities;
 pub mod enums;
 pub mod events;
+pub mod value_objects;
@@ -0,0 +1,74 @@
+//! Geolocation of a client address
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Where an IP address is, as far as the resolver knows
+///
+/// Every part is optional: databases often know the country of an address
+/// but not its city, and never know private or freshly allocated ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 code
+    pub country: Option<String>,
+    /// First-level subdivision, e.g. a state
+    pub region: Option<String>,
+    pub city: Option<String>,
+    /// Autonomous system number of the network
+    pub asn: Option<u32>,
+}
+
+impl GeoLocation {
+    /// Location in `country`, e.g. `"NG"`
+    pub fn country(country: impl Into<String>) -> Self {
+        Self {
+            country: Some(country.into().to_ascii_uppercase()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    pub fn with_city(mut self, city: impl Into<String>) -> Self {
+        self.city = Some(city.into());
+        self
+    }
+
+    pub fn with_asn(mut self, asn: u32) -> Self {
+        self.asn = Some(asn);
+        self
+    }
+
+    /// Whether a login from here, after one from `previous`, counts as an
+    /// unusual location
+    ///
+    /// Only a change of country counts: regions and cities are too coarse
+    /// and too often wrong (mobile carriers route a whole country through a
+    /// few gateways) to score on.  Unknown countries never count.
+    pub fn is_unusual_after(&self, previous: &GeoLocation) -> bool {
+        match (&self.country, &previous.country) {
+            (Some(now), Some(before)) => !now.eq_ignore_ascii_case(before),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for GeoLocation {
+    /// Most specific first, e.g. `Lagos, Lagos, NG`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<&str> = [&self.city, &self.region, &self.country]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if parts.is_empty() {
+            f.write_str("unknown")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Value objects of the identity domain
+
+mod geo;
+
+pub use geo::GeoLocation;
@@ -0,0 +1,227 @@
+//! IP geolocation
+//!
+//! Login events and telemetry carry where the client was, so risk scoring
+//! can notice a login from a new country.  [`GeoIpResolver`] is the
+//! extension point; [`LocalGeoIpDatabase`] answers from a database file
+//! shipped with the service, so no lookup leaves the process, and
+//! [`StaticGeoIpResolver`] answers from a fixed table in tests.
+//!
+//! ```rust,ignore
+//! let geo = LocalGeoIpDatabase::open("/etc/trustflow/geoip-city.csv")?;
+//! let telemetry = LoginTelemetry::stream(pool, "identity", 100_000)
+//!     .with_geo_resolver(Arc::new(geo));
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::Path;
+
+use error::AppError;
+use ipnet::IpNet;
+
+pub use crate::domain::value_objects::GeoLocation;
+
+/// Resolves IP addresses to locations
+pub trait GeoIpResolver: Send + Sync {
+    fn resolve(&self, ip: IpAddr) -> Option<GeoLocation>;
+
+    /// Resolve a textual address; `None` if it isn't one
+    fn resolve_str(&self, ip_address: &str) -> Option<GeoLocation> {
+        self.resolve(ip_address.trim().parse().ok()?)
+    }
+}
+
+/// Resolver that knows no locations
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoGeoIp;
+
+impl GeoIpResolver for NoGeoIp {
+    fn resolve(&self, _ip: IpAddr) -> Option<GeoLocation> {
+        None
+    }
+}
+
+/// Resolver answering from a fixed table of addresses, for tests
+#[derive(Debug, Clone, Default)]
+pub struct StaticGeoIpResolver {
+    locations: HashMap<IpAddr, GeoLocation>,
+}
+
+impl StaticGeoIpResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `ip` to `location`
+    ///
+    /// # Panics
+    ///
+    /// If `ip` is not an IP address.
+    pub fn with(mut self, ip: &str, location: GeoLocation) -> Self {
+        let ip = ip.parse().expect("StaticGeoIpResolver: invalid IP address");
+        self.locations.insert(ip, location);
+        self
+    }
+}
+
+impl GeoIpResolver for StaticGeoIpResolver {
+    fn resolve(&self, ip: IpAddr) -> Option<GeoLocation> {
+        self.locations.get(&ip).cloned()
+    }
+}
+
+/// In-process database of network blocks, loaded from a MaxMind-style CSV
+///
+/// The file has a header row and the columns
+/// `network,country_iso_code,subdivision_1_name,city_name,autonomous_system_number`,
+/// the blocks file of a GeoLite2 City export joined with its locations and
+/// ASN files.  Empty cells mean unknown; cells may be double-quoted.  As in
+/// MaxMind's exports, blocks must not overlap.
+#[derive(Debug, Clone, Default)]
+pub struct LocalGeoIpDatabase {
+    /// Blocks by first address, IPv4 mapped into IPv6: `(last address, location)`
+    blocks: BTreeMap<u128, (u128, GeoLocation)>,
+}
+
+impl LocalGeoIpDatabase {
+    /// Load the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let path = path.as_ref();
+        let csv = std::fs::read_to_string(path).map_err(|e| {
+            AppError::infrastructure("geoip", format!("reading {}: {e}", path.display()))
+        })?;
+        Self::from_csv(&csv)
+    }
+
+    /// Parse a database from CSV text
+    pub fn from_csv(csv: &str) -> Result<Self, AppError> {
+        let mut database = Self::default();
+        for (index, line) in csv.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| {
+                AppError::infrastructure("geoip", format!("line {}: {reason}", index + 1))
+            };
+            let cells = split_csv_line(line);
+            let [network, country, region, city, asn] = cells.as_slice() else {
+                return Err(invalid(format!("expected 5 columns, got {}", cells.len())));
+            };
+            let network: IpNet = network
+                .parse()
+                .map_err(|e| invalid(format!("network {network:?}: {e}")))?;
+            let cell = |value: &String| (!value.is_empty()).then(|| value.clone());
+            let location = GeoLocation {
+                country: cell(country).map(|c| c.to_ascii_uppercase()),
+                region: cell(region),
+                city: cell(city),
+                asn: cell(asn)
+                    .map(|asn| asn.parse())
+                    .transpose()
+                    .map_err(|e| invalid(format!("asn {asn:?}: {e}")))?,
+            };
+            database.insert(network, location);
+        }
+        Ok(database)
+    }
+
+    /// Add a block, replacing one that starts at the same address
+    pub fn insert(&mut self, network: IpNet, location: GeoLocation) {
+        let first = to_u128(network.network());
+        let last = to_u128(network.broadcast());
+        self.blocks.insert(first, (last, location));
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl GeoIpResolver for LocalGeoIpDatabase {
+    fn resolve(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let ip = to_u128(ip);
+        let (_, (last, location)) = self.blocks.range(..=ip).next_back()?;
+        (ip <= *last).then(|| location.clone())
+    }
+}
+
+/// `ip` as a number, with IPv4 in the IPv4-mapped IPv6 range
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Cells of one CSV line; quoted cells may contain commas and `""`
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = "\
+network,country_iso_code,subdivision_1_name,city_name,autonomous_system_number
+102.89.0.0/16,NG,Lagos,Lagos,29465
+81.2.69.0/24,gb,England,London,
+2a02:c7c::/32,GB,\"England\",\"London, City of\",5607
+10.0.0.0/8,,,,
+";
+
+    #[test]
+    fn test_local_database_resolves_known_addresses() {
+        let geo = LocalGeoIpDatabase::from_csv(DATABASE).unwrap();
+        assert_eq!(geo.len(), 4);
+
+        assert_eq!(
+            geo.resolve_str("102.89.1.7"),
+            Some(
+                GeoLocation::country("NG")
+                    .with_region("Lagos")
+                    .with_city("Lagos")
+                    .with_asn(29465)
+            )
+        );
+        assert_eq!(
+            geo.resolve_str("81.2.69.255").unwrap(),
+            GeoLocation::country("GB").with_region("England").with_city("London")
+        );
+        let v6 = geo.resolve_str("2a02:c7c:1234::1").unwrap();
+        assert_eq!(v6.city.as_deref(), Some("London, City of"));
+        assert_eq!(geo.resolve_str("10.1.2.3"), Some(GeoLocation::default()));
+
+        assert_eq!(geo.resolve_str("81.2.70.1"), None);
+        assert_eq!(geo.resolve_str("8.8.8.8"), None);
+        assert_eq!(geo.resolve_str("not an ip"), None);
+    }
+
+    #[test]
+    fn test_malformed_database_names_the_line() {
+        let err = LocalGeoIpDatabase::from_csv("header\n1.2.3.0/24,US\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+        let err = LocalGeoIpDatabase::from_csv("header\n1.2.3/24,US,,,\n").unwrap_err();
+        assert!(err.to_string().contains("network"), "{err}");
+    }
+}
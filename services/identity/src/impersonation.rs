@@ -0,0 +1,295 @@
+//! Support staff acting as a user
+//!
+//! To reproduce a user's problem, staff holding [`IMPERSONATE_PERMISSION`]
+//! can get a short-lived access token for the user's account from
+//! [`ImpersonationService::impersonate`].  The token's `act` claim names the
+//! staff member, so every request made with it shows up as impersonated in
+//! the [`AuthContext`] (and in logs), and actions only the user may take
+//! ([`SelfOnlyAction`]) are refused.  Each impersonation is written to the
+//! audit sink before the token is handed out; if it can't be recorded, no
+//! token is issued.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use common::middleware::{AuthContext, PermissionSource};
+use common::security::JwtService;
+use error::core::AuthErrorCode;
+use error::{AppError, AppResult};
+use serde::Serialize;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+
+/// Permission needed to impersonate users
+pub const IMPERSONATE_PERMISSION: &str = "users:impersonate";
+
+/// Lifetime of impersonation tokens unless configured otherwise
+pub const DEFAULT_IMPERSONATION_TTL: Duration = Duration::minutes(15);
+
+/// Impersonation errors
+#[derive(Debug, Error)]
+pub enum ImpersonationError {
+    #[error("{0} may not impersonate users")]
+    NotPermitted(String),
+
+    #[error("cannot impersonate yourself")]
+    SelfImpersonation,
+
+    #[error("impersonation audit failed: {0}")]
+    Audit(String),
+
+    #[error(transparent)]
+    App(#[from] AppError),
+}
+
+impl From<ImpersonationError> for AppError {
+    fn from(err: ImpersonationError) -> Self {
+        match err {
+            ImpersonationError::NotPermitted(_) => {
+                AppError::authz(err.to_string(), AuthErrorCode::InsufficientPermissions)
+            }
+            ImpersonationError::SelfImpersonation => AppError::validation(err.to_string()),
+            ImpersonationError::Audit(e) => AppError::infrastructure("impersonation_audit", e),
+            ImpersonationError::App(e) => e,
+        }
+    }
+}
+
+/// Actions only the account holder may take, refused during impersonation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfOnlyAction {
+    ChangePassword,
+    ChangeEmail,
+    ManageMfa,
+    DeleteAccount,
+    Impersonate,
+}
+
+impl SelfOnlyAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ChangePassword => "changing the password",
+            Self::ChangeEmail => "changing the email address",
+            Self::ManageMfa => "managing MFA",
+            Self::DeleteAccount => "deleting the account",
+            Self::Impersonate => "impersonating another user",
+        }
+    }
+}
+
+/// Fail with `InsufficientPermissions` if `caller` is impersonating the user
+pub fn ensure_not_impersonated(caller: &AuthContext, action: SelfOnlyAction) -> AppResult<()> {
+    let result = caller.deny_if_impersonated(action.as_str());
+    if result.is_err() {
+        warn!(
+            user_id = %caller.user_id,
+            impersonator = caller.impersonator.as_deref().unwrap_or_default(),
+            action = action.as_str(),
+            "blocked action during impersonation"
+        );
+    }
+    result
+}
+
+/// Audit record of one impersonation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImpersonationRecord {
+    pub admin_id: String,
+    pub target_user_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+/// Where impersonations are recorded
+#[async_trait]
+pub trait ImpersonationAuditSink: Send + Sync {
+    async fn record(&self, record: &ImpersonationRecord) -> Result<(), ImpersonationError>;
+}
+
+/// Token letting an admin act as a user
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpersonationGrant {
+    pub access_token: String,
+    pub admin_id: String,
+    pub target_user_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+/// Issues impersonation tokens to permitted staff
+#[derive(Clone)]
+pub struct ImpersonationService {
+    jwt: JwtService,
+    permissions: Arc<dyn PermissionSource>,
+    audit: Arc<dyn ImpersonationAuditSink>,
+    ttl: Duration,
+}
+
+impl ImpersonationService {
+    pub fn new(
+        jwt: JwtService,
+        permissions: Arc<dyn PermissionSource>,
+        audit: Arc<dyn ImpersonationAuditSink>,
+    ) -> Self {
+        Self {
+            jwt,
+            permissions,
+            audit,
+            ttl: DEFAULT_IMPERSONATION_TTL,
+        }
+    }
+
+    /// Issue tokens valid for `ttl` (capped at the access token lifetime)
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Let `admin_id` act as `target_user_id`
+    pub async fn impersonate(
+        &self,
+        admin_id: &str,
+        target_user_id: &str,
+    ) -> Result<ImpersonationGrant, ImpersonationError> {
+        if admin_id == target_user_id {
+            return Err(ImpersonationError::SelfImpersonation);
+        }
+        if !self
+            .permissions
+            .has_permission(admin_id, IMPERSONATE_PERMISSION)
+            .await?
+        {
+            warn!(admin_id, target_user_id, "impersonation refused");
+            return Err(ImpersonationError::NotPermitted(admin_id.to_string()));
+        }
+
+        let access_token = self.jwt.issue_impersonation(
+            target_user_id,
+            admin_id,
+            Vec::new(),
+            self.ttl.whole_seconds(),
+        )?;
+        let claims = self.jwt.verify_access(&access_token, None)?;
+        let record = ImpersonationRecord {
+            admin_id: admin_id.to_string(),
+            target_user_id: target_user_id.to_string(),
+            started_at: OffsetDateTime::from_unix_timestamp(claims.iat)
+                .map_err(|e| AppError::internal(e.to_string()))?,
+            expires_at: OffsetDateTime::from_unix_timestamp(claims.exp)
+                .map_err(|e| AppError::internal(e.to_string()))?,
+        };
+        self.audit.record(&record).await?;
+        warn!(
+            admin_id,
+            target_user_id,
+            expires_at = %record.expires_at,
+            "impersonation started"
+        );
+
+        Ok(ImpersonationGrant {
+            access_token,
+            admin_id: record.admin_id,
+            target_user_id: record.target_user_id,
+            expires_at: record.expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::core::kinds::AuthError;
+    use std::sync::Mutex;
+
+    struct StaffPermissions;
+
+    #[async_trait]
+    impl PermissionSource for StaffPermissions {
+        async fn has_permission(&self, user_id: &str, permission: &str) -> AppResult<bool> {
+            Ok(user_id == "support-1" && permission == IMPERSONATE_PERMISSION)
+        }
+
+        async fn verification_level(&self, _user_id: &str) -> AppResult<u8> {
+            Ok(0)
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingAudit {
+        records: Mutex<Vec<ImpersonationRecord>>,
+    }
+
+    #[async_trait]
+    impl ImpersonationAuditSink for RecordingAudit {
+        async fn record(&self, record: &ImpersonationRecord) -> Result<(), ImpersonationError> {
+            self.records.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_carries_impersonator_and_blocks_self_only_actions() {
+        let jwt = JwtService::new("test-secret", "trustflow", "web", 3600, 86_400);
+        let audit = Arc::new(RecordingAudit::default());
+        let service =
+            ImpersonationService::new(jwt.clone(), Arc::new(StaffPermissions), audit.clone());
+
+        let grant = service.impersonate("support-1", "user-42").await.unwrap();
+
+        let claims = jwt.verify_access(&grant.access_token, None).unwrap();
+        assert_eq!(claims.sub, "user-42");
+        assert_eq!(claims.impersonator(), Some("support-1"));
+        assert_eq!(
+            claims.exp - claims.iat,
+            DEFAULT_IMPERSONATION_TTL.whole_seconds()
+        );
+
+        let records = audit.records.lock().unwrap().clone();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].admin_id, "support-1");
+        assert_eq!(records[0].target_user_id, "user-42");
+        assert_eq!(records[0].expires_at, grant.expires_at);
+
+        let caller = AuthContext::from_claims(&claims);
+        assert!(caller.is_impersonated());
+        assert_eq!(caller.impersonator.as_deref(), Some("support-1"));
+        for action in [SelfOnlyAction::ChangePassword, SelfOnlyAction::Impersonate] {
+            assert!(
+                matches!(
+                    ensure_not_impersonated(&caller, action),
+                    Err(AppError::AuthorizationError(AuthError::Authorization {
+                        code: AuthErrorCode::InsufficientPermissions,
+                        ..
+                    }))
+                ),
+                "{action:?} should be forbidden during impersonation"
+            );
+        }
+        // The user themselves is unaffected
+        let own = AuthContext::from_claims(
+            &jwt.verify_access(&jwt.issue_access("user-42", Vec::new()).unwrap(), None)
+                .unwrap(),
+        );
+        assert!(ensure_not_impersonated(&own, SelfOnlyAction::ChangePassword).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_staff_without_permission_get_no_token() {
+        let jwt = JwtService::new("test-secret", "trustflow", "web", 3600, 86_400);
+        let audit = Arc::new(RecordingAudit::default());
+        let service = ImpersonationService::new(jwt, Arc::new(StaffPermissions), audit.clone());
+
+        assert!(matches!(
+            service.impersonate("support-2", "user-42").await,
+            Err(ImpersonationError::NotPermitted(_))
+        ));
+        assert!(matches!(
+            service.impersonate("support-1", "support-1").await,
+            Err(ImpersonationError::SelfImpersonation)
+        ));
+        assert!(audit.records.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,511 @@
+//! Invite codes for gated registration
+//!
+//! An invite is either single-use or allows a fixed number of uses, may
+//! expire, and may restrict which role the invited user registers with.
+//! [`InviteCodeService::validate_and_consume`] checks the code, including
+//! the role it is redeemed for, and spends one use in a single atomic step
+//! in the backing store: two registrations racing on the last use of a code
+//! cannot both succeed, and a registration with the wrong role never spends
+//! a use.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use error::AppError;
+use infrastructure::redis::{RedisError, RedisKey, RedisPool};
+use rand::Rng;
+use serde::Serialize;
+use thiserror::Error;
+use time::OffsetDateTime;
+
+/// Characters used in generated codes (no 0/O or 1/I look-alikes)
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 10;
+
+/// Expired invites are kept this long so users get "expired" rather than
+/// "not found"
+const EXPIRED_RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Invite code errors
+#[derive(Debug, Error)]
+pub enum InviteError {
+    #[error("invite code not found")]
+    NotFound,
+
+    #[error("invite code has expired")]
+    Expired,
+
+    #[error("invite code has already been used")]
+    Exhausted,
+
+    #[error("invite code does not allow registering as {0}")]
+    RoleNotPermitted(String),
+
+    #[error("invalid invite: {0}")]
+    Invalid(String),
+
+    #[error("invite store error: {0}")]
+    Store(#[from] RedisError),
+}
+
+impl From<InviteError> for AppError {
+    fn from(err: InviteError) -> Self {
+        match err {
+            InviteError::Store(e) => AppError::infrastructure("invite_store", e.to_string()),
+            other => AppError::validation_with_field(other.to_string(), "invite_code"),
+        }
+    }
+}
+
+/// Parameters for a new invite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InviteSpec {
+    pub max_uses: u32,
+    pub expires_at: Option<OffsetDateTime>,
+    pub role: Option<String>,
+}
+
+impl InviteSpec {
+    /// Invite that can be redeemed once
+    pub fn single_use() -> Self {
+        Self::multi_use(1)
+    }
+
+    /// Invite that can be redeemed `max_uses` times
+    pub fn multi_use(max_uses: u32) -> Self {
+        Self {
+            max_uses,
+            expires_at: None,
+            role: None,
+        }
+    }
+
+    pub fn expires_at(mut self, expires_at: OffsetDateTime) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Only allow registration with `role`
+    pub fn with_role(mut self, role: impl Into<String>) -> Self {
+        self.role = Some(role.into());
+        self
+    }
+}
+
+/// State of an invite code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InviteDetails {
+    pub code: String,
+    pub role: Option<String>,
+    pub max_uses: u32,
+    pub used_count: u32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+impl InviteDetails {
+    /// Whether the invite allows registering with `role`
+    pub fn permits_role(&self, role: &str) -> bool {
+        self.role
+            .as_deref()
+            .is_none_or(|allowed| allowed.eq_ignore_ascii_case(role))
+    }
+
+    pub fn remaining_uses(&self) -> u32 {
+        self.max_uses.saturating_sub(self.used_count)
+    }
+
+    fn check_redeemable(&self, role: &str, now: OffsetDateTime) -> Result<(), InviteError> {
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(InviteError::Expired);
+        }
+        if self.used_count >= self.max_uses {
+            return Err(InviteError::Exhausted);
+        }
+        if !self.permits_role(role) {
+            return Err(InviteError::RoleNotPermitted(role.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Storage for invite codes
+#[async_trait]
+pub trait InviteStore: Send + Sync {
+    /// Store a new invite; existing codes must not be overwritten
+    async fn insert(&self, invite: &InviteDetails) -> Result<(), InviteError>;
+
+    /// Atomically check that `code` is redeemable for `role` at `now` and
+    /// spend one use, returning the updated invite
+    async fn consume(
+        &self,
+        code: &str,
+        role: &str,
+        now: OffsetDateTime,
+    ) -> Result<InviteDetails, InviteError>;
+}
+
+/// Generates and redeems invite codes
+#[derive(Clone)]
+pub struct InviteCodeService {
+    store: Arc<dyn InviteStore>,
+}
+
+impl InviteCodeService {
+    pub fn new(store: impl InviteStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    /// Create and store a new invite code
+    pub async fn generate(&self, spec: InviteSpec) -> Result<InviteDetails, InviteError> {
+        if spec.max_uses == 0 {
+            return Err(InviteError::Invalid("max_uses must be at least 1".into()));
+        }
+        if spec
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc())
+        {
+            return Err(InviteError::Invalid("expiry must be in the future".into()));
+        }
+
+        let invite = InviteDetails {
+            code: generate_code(),
+            role: spec.role,
+            max_uses: spec.max_uses,
+            used_count: 0,
+            expires_at: spec.expires_at,
+        };
+        self.store.insert(&invite).await?;
+        Ok(invite)
+    }
+
+    /// Validate `code` for registering as `role` and spend one use of it
+    pub async fn validate_and_consume(
+        &self,
+        code: &str,
+        role: &str,
+    ) -> Result<InviteDetails, InviteError> {
+        let code = normalize_code(code);
+        if code.is_empty() {
+            return Err(InviteError::NotFound);
+        }
+        self.store
+            .consume(&code, role, OffsetDateTime::now_utc())
+            .await
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Codes are case-insensitive and may be pasted with surrounding whitespace
+fn normalize_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+/// In-memory store for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryInviteStore {
+    invites: Arc<Mutex<HashMap<String, InviteDetails>>>,
+}
+
+impl InMemoryInviteStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl InviteStore for InMemoryInviteStore {
+    async fn insert(&self, invite: &InviteDetails) -> Result<(), InviteError> {
+        let mut invites = self
+            .invites
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if invites.contains_key(&invite.code) {
+            return Err(InviteError::Invalid(format!(
+                "code {} already exists",
+                invite.code
+            )));
+        }
+        invites.insert(invite.code.clone(), invite.clone());
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        code: &str,
+        role: &str,
+        now: OffsetDateTime,
+    ) -> Result<InviteDetails, InviteError> {
+        let mut invites = self
+            .invites
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let invite = invites.get_mut(code).ok_or(InviteError::NotFound)?;
+        invite.check_redeemable(role, now)?;
+        invite.used_count += 1;
+        Ok(invite.clone())
+    }
+}
+
+/// Creates the invite hash unless the code exists, with its expiry, in one
+/// step, so a crash can't leave a half-written invite behind.
+///
+/// `ARGV` is `code, role, max_uses, used_count, expires_at, expire_at`;
+/// `expire_at` is empty for invites that never expire.  Returns 1 if created.
+const INSERT_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'code', ARGV[1], 'role', ARGV[2], 'max_uses', ARGV[3],
+    'used_count', ARGV[4], 'expires_at', ARGV[5])
+if ARGV[6] ~= '' then
+    redis.call('EXPIREAT', KEYS[1], ARGV[6])
+end
+return 1
+"#;
+
+/// Checks and increments the use count in one step.
+///
+/// `ARGV` is `now, role`.  Returns `{status}` or
+/// `{"ok", role, max_uses, used_count, expires_at}`.
+const CONSUME_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return {'not_found'}
+end
+local fields = redis.call('HMGET', KEYS[1], 'role', 'max_uses', 'used_count', 'expires_at')
+local expires_at = fields[4]
+if expires_at ~= '' and tonumber(expires_at) <= tonumber(ARGV[1]) then
+    return {'expired'}
+end
+if tonumber(fields[3]) >= tonumber(fields[2]) then
+    return {'exhausted'}
+end
+if fields[1] ~= '' and string.upper(fields[1]) ~= string.upper(ARGV[2]) then
+    return {'role_not_permitted'}
+end
+local used = redis.call('HINCRBY', KEYS[1], 'used_count', 1)
+return {'ok', fields[1], fields[2], tostring(used), expires_at}
+"#;
+
+/// Redis-backed store; each invite is a hash under `prefix:invite:CODE`
+#[derive(Clone)]
+pub struct RedisInviteStore {
+    pool: RedisPool,
+    prefix: String,
+}
+
+impl RedisInviteStore {
+    pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
+        Self {
+            pool,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, code: &str) -> RedisKey {
+        RedisKey::with_prefix(&self.prefix, ["invite", code])
+    }
+}
+
+#[async_trait]
+impl InviteStore for RedisInviteStore {
+    async fn insert(&self, invite: &InviteDetails) -> Result<(), InviteError> {
+        let key = self.key(&invite.code);
+        let expires_at = invite
+            .expires_at
+            .map(|t| t.unix_timestamp().to_string())
+            .unwrap_or_default();
+        let expire_at = invite
+            .expires_at
+            .map(|t| (t.unix_timestamp() + EXPIRED_RETENTION_SECS).to_string())
+            .unwrap_or_default();
+
+        let mut conn = self.pool.connection().await?;
+        let created: bool = redis::Script::new(INSERT_SCRIPT)
+            .key(key.as_str())
+            .arg(&invite.code)
+            .arg(invite.role.clone().unwrap_or_default())
+            .arg(invite.max_uses)
+            .arg(invite.used_count)
+            .arg(expires_at)
+            .arg(expire_at)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(RedisError::from)?;
+        if !created {
+            return Err(InviteError::Invalid(format!(
+                "code {} already exists",
+                invite.code
+            )));
+        }
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        code: &str,
+        role: &str,
+        now: OffsetDateTime,
+    ) -> Result<InviteDetails, InviteError> {
+        let mut conn = self.pool.connection().await?;
+        let reply: Vec<String> = redis::Script::new(CONSUME_SCRIPT)
+            .key(self.key(code).as_str())
+            .arg(now.unix_timestamp())
+            .arg(role)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(RedisError::from)?;
+
+        match reply.first().map(String::as_str) {
+            Some("ok") if reply.len() == 5 => {
+                let number = |value: &str| {
+                    value
+                        .parse::<u32>()
+                        .map_err(|e| RedisError::Command(format!("corrupt invite {code}: {e}")))
+                };
+                let expires_at = match reply[4].as_str() {
+                    "" => None,
+                    ts => Some(
+                        ts.parse::<i64>()
+                            .ok()
+                            .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+                            .ok_or_else(|| {
+                                RedisError::Command(format!("corrupt invite expiry for {code}"))
+                            })?,
+                    ),
+                };
+                Ok(InviteDetails {
+                    code: code.to_string(),
+                    role: Some(reply[1].clone()).filter(|role| !role.is_empty()),
+                    max_uses: number(&reply[2])?,
+                    used_count: number(&reply[3])?,
+                    expires_at,
+                })
+            }
+            Some("expired") => Err(InviteError::Expired),
+            Some("exhausted") => Err(InviteError::Exhausted),
+            Some("role_not_permitted") => Err(InviteError::RoleNotPermitted(role.to_string())),
+            Some("not_found") => Err(InviteError::NotFound),
+            _ => Err(
+                RedisError::Command(format!("unexpected invite script reply: {reply:?}")).into(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    #[tokio::test]
+    async fn test_single_use_code_is_consumed_once() {
+        let service = InviteCodeService::new(InMemoryInviteStore::new());
+        let invite = service
+            .generate(InviteSpec::single_use().with_role("SELLER"))
+            .await
+            .unwrap();
+        assert_eq!(invite.code.len(), CODE_LENGTH);
+
+        // The wrong role is refused without spending the only use
+        assert!(matches!(
+            service.validate_and_consume(&invite.code, "ADMIN").await,
+            Err(InviteError::RoleNotPermitted(_))
+        ));
+        let redeemed = service
+            .validate_and_consume(&invite.code.to_lowercase(), "seller")
+            .await
+            .unwrap();
+        assert_eq!(redeemed.used_count, 1);
+        assert_eq!(redeemed.remaining_uses(), 0);
+        assert!(redeemed.permits_role("seller"));
+        assert!(!redeemed.permits_role("ADMIN"));
+
+        assert!(matches!(
+            service.validate_and_consume(&invite.code, "SELLER").await,
+            Err(InviteError::Exhausted)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_multi_use_code_allows_max_uses() {
+        let service = InviteCodeService::new(InMemoryInviteStore::new());
+        let invite = service.generate(InviteSpec::multi_use(2)).await.unwrap();
+
+        service
+            .validate_and_consume(&invite.code, "BUYER")
+            .await
+            .unwrap();
+        service
+            .validate_and_consume(&invite.code, "SELLER")
+            .await
+            .unwrap();
+        assert!(
+            service
+                .validate_and_consume(&invite.code, "BUYER")
+                .await
+                .is_err()
+        );
+        assert!(matches!(
+            service.validate_and_consume("NOPE", "BUYER").await,
+            Err(InviteError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_code_is_rejected() {
+        let store = InMemoryInviteStore::new();
+        store
+            .insert(&InviteDetails {
+                code: "EXPIRED234".to_string(),
+                role: None,
+                max_uses: 1,
+                used_count: 0,
+                expires_at: Some(OffsetDateTime::now_utc() - Duration::minutes(1)),
+            })
+            .await
+            .unwrap();
+        let service = InviteCodeService::new(store);
+
+        assert!(matches!(
+            service.validate_and_consume("EXPIRED234", "BUYER").await,
+            Err(InviteError::Expired)
+        ));
+        assert!(
+            service
+                .generate(
+                    InviteSpec::single_use()
+                        .expires_at(OffsetDateTime::now_utc() - Duration::days(1))
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_redis_store_consumes_atomically() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let pool = RedisPool::new(&url).await.unwrap();
+        let service = InviteCodeService::new(RedisInviteStore::new(pool, "test"));
+        let invite = service
+            .generate(
+                InviteSpec::multi_use(3).expires_at(OffsetDateTime::now_utc() + Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+
+        let attempts = (0..10).map(|_| service.validate_and_consume(&invite.code, "BUYER"));
+        let results = futures_util::future::join_all(attempts).await;
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 3);
+    }
+}
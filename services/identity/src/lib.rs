@@ -1,3 +1,18 @@
+pub mod account;
+pub mod account_deletion;
+pub mod admin;
+pub mod credential_stuffing;
+pub mod domain;
+pub mod geo_ip;
+pub mod impersonation;
+pub mod invite;
+pub mod login;
+pub mod login_request;
+pub mod login_telemetry;
+pub mod otp_delivery;
+pub mod password_reset;
+pub mod refresh_token;
 pub mod routes;
+pub mod verification_token;
 
 pub use routes::router;
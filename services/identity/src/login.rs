@@ -0,0 +1,1035 @@
+//! Password login
+//!
+//! [`LoginService::login`] is the path every password login takes: it looks
+//! the account up, checks the password and the account's status, and emits
+//! a [`LoginTelemetryRecord`](crate::login_telemetry::LoginTelemetryRecord)
+//! for the attempt however it ends.  Failures on our side (the account store
+//! or the hasher) are recorded as [`LoginOutcome::Error`], never as bad
+//! credentials, so risk scoring doesn't count an outage against the user.
+//!
+//! Every failed password check also counts towards credential stuffing
+//! detection, keyed by the client's IP and, when the telemetry's geo
+//! resolver knows it, its autonomous system.  Sources caught trying many
+//! accounts are refused before their password is checked, and the detection
+//! is published as `SuspiciousActivityType::CredentialStuffing`.
+//!
+//! A successful login opens a session in the [`SessionStore`].  A session id
+//! the client held before logging in is rotated rather than reused, and so
+//! is the session after a step-up ([`LoginService::step_up`]) or a role
+//! change ([`LoginService::change_session_role`]): an id known before a
+//! privilege change never carries the new privileges.
+//!
+//! Each successful login is published as a `UserLoggedInEvent` carrying
+//! where the client's IP resolved to.  When that is another country than
+//! the account's previous login, `SuspiciousActivityType::UnusualLocation`
+//! is published as well.
+//!
+//! With a [`RefreshTokenSigner`] configured, each login also issues a
+//! refresh token bound to the session and the client's device.  Refreshing
+//! from another device revokes the session and publishes
+//! `SuspiciousActivityType::NewDevice`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use common::security::{PasswordHasher, SecretGenerator};
+use common::value_objects::PasswordHash;
+use common::value_objects::Timestamp;
+use common::value_objects::identity::DeviceId;
+use common::value_objects::network::IpAddress;
+use error::{AppError, http::AuthErrorCode};
+use infrastructure::redis::{RedisError, SessionData, SessionStore};
+use thiserror::Error;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::{info, warn};
+
+use crate::credential_stuffing::{CredentialStuffingDetected, CredentialStuffingDetector};
+use crate::domain::entities::UserId;
+use crate::domain::enums::UserStatus;
+use crate::domain::events::{
+    EventPublisher, NullEventPublisher, SuspiciousActivityEvent, SuspiciousActivityType,
+    UserLoggedInEvent,
+};
+use crate::domain::value_objects::GeoLocation;
+use crate::login_request::LoginCommand;
+use crate::login_telemetry::{
+    LoginAttempt, LoginOutcome, LoginTelemetry, LoginTelemetryRecord, NullLoginTelemetrySink,
+};
+use crate::refresh_token::{DeviceFingerprint, RefreshTokenError, RefreshTokenSigner};
+
+/// How long a session lives without activity unless configured otherwise
+pub const DEFAULT_SESSION_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Login errors
+#[derive(Debug, Error)]
+pub enum LoginError {
+    /// Unknown account or wrong password; the two are not told apart
+    #[error("invalid credentials")]
+    InvalidCredentials,
+
+    #[error("account is locked")]
+    AccountLocked,
+
+    #[error("account is not active")]
+    AccountInactive,
+
+    #[error("second factor required")]
+    MfaRequired,
+
+    /// The client's source is blocked for credential stuffing
+    #[error("too many failed logins from this source, blocked until {until}")]
+    Blocked { until: OffsetDateTime },
+
+    /// The session is unknown: expired, logged out or already rotated
+    #[error("session has expired")]
+    SessionExpired,
+
+    #[error(transparent)]
+    Refresh(#[from] RefreshTokenError),
+
+    #[error("password hashing failed: {0}")]
+    Hashing(String),
+
+    #[error("account store error: {0}")]
+    Store(String),
+
+    #[error("session store error: {0}")]
+    Session(#[from] RedisError),
+}
+
+impl LoginError {
+    /// How the attempt is recorded in telemetry
+    pub fn outcome(&self) -> LoginOutcome {
+        match self {
+            Self::InvalidCredentials => LoginOutcome::InvalidCredentials,
+            Self::AccountLocked => LoginOutcome::AccountLocked,
+            Self::AccountInactive => LoginOutcome::AccountInactive,
+            Self::MfaRequired => LoginOutcome::MfaRequired,
+            Self::Blocked { .. } => LoginOutcome::RateLimited,
+            Self::Refresh(_) => LoginOutcome::InvalidCredentials,
+            Self::SessionExpired | Self::Hashing(_) | Self::Store(_) | Self::Session(_) => {
+                LoginOutcome::Error
+            }
+        }
+    }
+}
+
+impl From<LoginError> for AppError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::InvalidCredentials => {
+                AppError::auth(err.to_string(), AuthErrorCode::InvalidCredentials)
+            }
+            LoginError::AccountLocked => {
+                AppError::auth(err.to_string(), AuthErrorCode::AccountLocked)
+            }
+            LoginError::AccountInactive => {
+                AppError::auth(err.to_string(), AuthErrorCode::AccountInactive)
+            }
+            LoginError::MfaRequired => AppError::auth(err.to_string(), AuthErrorCode::MfaRequired),
+            LoginError::Blocked { until } => {
+                let retry_after = (until - OffsetDateTime::now_utc()).whole_seconds().max(1);
+                AppError::rate_limit("login", retry_after as u64)
+            }
+            LoginError::SessionExpired => {
+                AppError::auth(err.to_string(), AuthErrorCode::SessionExpired)
+            }
+            LoginError::Refresh(e) => e.into(),
+            LoginError::Hashing(e) => AppError::internal(e),
+            LoginError::Store(e) => AppError::infrastructure("login_accounts", e),
+            LoginError::Session(e) => AppError::infrastructure("session_store", e.to_string()),
+        }
+    }
+}
+
+/// Account as the login flow needs it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginAccount {
+    pub user_id: UserId,
+    pub email: String,
+    pub role: String,
+    pub password_hash: PasswordHash,
+    pub status: UserStatus,
+    pub mfa_enabled: bool,
+}
+
+/// Accounts as the login flow needs them
+#[async_trait]
+pub trait LoginAccounts: Send + Sync {
+    /// Account whose email or phone number is `identifier`
+    async fn find_by_identifier(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<LoginAccount>, LoginError>;
+
+    /// Remember that `user_id` logged in at `at`
+    async fn record_login(&self, user_id: &UserId, at: OffsetDateTime) -> Result<(), LoginError>;
+}
+
+/// Where a login request came from
+#[derive(Debug, Clone, Copy)]
+pub struct LoginClient<'a> {
+    pub ip_address: &'a str,
+    pub user_agent: &'a str,
+    /// Session the client held before logging in, e.g. an anonymous cart
+    pub session_id: Option<&'a str>,
+}
+
+/// A successful login
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginSuccess {
+    pub user_id: UserId,
+    pub session_id: String,
+    /// Set when the service has a [`RefreshTokenSigner`]
+    pub refresh_token: Option<String>,
+}
+
+/// Checks passwords and records every attempt
+pub struct LoginService<H> {
+    accounts: Arc<dyn LoginAccounts>,
+    sessions: Arc<dyn SessionStore>,
+    hasher: Arc<H>,
+    telemetry: LoginTelemetry,
+    stuffing: CredentialStuffingDetector,
+    events: Arc<dyn EventPublisher>,
+    session_ttl: std::time::Duration,
+    refresh_tokens: Option<RefreshTokenSigner>,
+}
+
+impl<H> Clone for LoginService<H> {
+    fn clone(&self) -> Self {
+        Self {
+            accounts: self.accounts.clone(),
+            sessions: self.sessions.clone(),
+            hasher: self.hasher.clone(),
+            telemetry: self.telemetry.clone(),
+            stuffing: self.stuffing.clone(),
+            events: self.events.clone(),
+            session_ttl: self.session_ttl,
+            refresh_tokens: self.refresh_tokens.clone(),
+        }
+    }
+}
+
+impl<H: PasswordHasher> LoginService<H> {
+    pub fn new(
+        accounts: Arc<dyn LoginAccounts>,
+        sessions: Arc<dyn SessionStore>,
+        hasher: H,
+    ) -> Self {
+        Self {
+            accounts,
+            sessions,
+            hasher: Arc::new(hasher),
+            telemetry: LoginTelemetry::new(Arc::new(NullLoginTelemetrySink)),
+            stuffing: CredentialStuffingDetector::default(),
+            events: Arc::new(NullEventPublisher),
+            session_ttl: DEFAULT_SESSION_TTL,
+            refresh_tokens: None,
+        }
+    }
+
+    /// Emit a telemetry record per attempt through `telemetry`
+    pub fn with_telemetry(mut self, telemetry: LoginTelemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// Detect credential stuffing with `detector` instead of the default limits
+    pub fn with_credential_stuffing_detector(
+        mut self,
+        detector: CredentialStuffingDetector,
+    ) -> Self {
+        self.stuffing = detector;
+        self
+    }
+
+    /// Publish security events through `events`
+    pub fn with_event_publisher(mut self, events: Arc<dyn EventPublisher>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// How long sessions live without activity
+    pub fn with_session_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Issue device-bound refresh tokens signed by `signer`
+    pub fn with_refresh_tokens(mut self, signer: RefreshTokenSigner) -> Self {
+        self.refresh_tokens = Some(signer);
+        self
+    }
+
+    /// Log in with `command`, sent by `client`
+    pub async fn login(
+        &self,
+        command: &LoginCommand,
+        client: LoginClient<'_>,
+    ) -> Result<LoginSuccess, LoginError> {
+        self.login_at(command, client, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Log in with `command` as of `now`
+    pub async fn login_at(
+        &self,
+        command: &LoginCommand,
+        client: LoginClient<'_>,
+        now: OffsetDateTime,
+    ) -> Result<LoginSuccess, LoginError> {
+        let identifier = command.identifier.as_str().trim();
+        let result = match self
+            .authenticate(identifier, &command.password, client, now)
+            .await
+        {
+            Ok(account) => self.start_session(account, command, client, now).await,
+            Err(e) => Err(e),
+        };
+
+        let outcome = match &result {
+            Ok(_) => LoginOutcome::Success,
+            Err(e) => e.outcome(),
+        };
+        let attempt = LoginAttempt {
+            identifier,
+            ip_address: client.ip_address,
+            device_id: &command.device_id,
+            user_agent: client.user_agent,
+        };
+        let record = self.telemetry.record_at(attempt, outcome, now).await;
+        if let Ok(success) = &result {
+            self.publish_login(success, command, &record, now).await;
+        }
+        result
+    }
+
+    /// Publish a successful login, and its location if that is unusual for
+    /// the account
+    async fn publish_login(
+        &self,
+        success: &LoginSuccess,
+        command: &LoginCommand,
+        record: &LoginTelemetryRecord,
+        now: OffsetDateTime,
+    ) {
+        let logged_in = UserLoggedInEvent {
+            user_id: success.user_id,
+            session_id: success.session_id.clone(),
+            device_id: DeviceId::new(command.device_id.clone()),
+            ip_address: IpAddress(record.ip_address.clone()),
+            location: record.geo.clone(),
+            timestamp: Timestamp(now),
+        };
+        if let Err(e) = self.events.publish(&logged_in).await {
+            warn!(error = %e, "failed to publish login event");
+        }
+
+        if !record.unusual_location {
+            return;
+        }
+        let country = |geo: &Option<GeoLocation>| {
+            geo.as_ref()
+                .and_then(|geo| geo.country.clone())
+                .unwrap_or_default()
+        };
+        let unusual = SuspiciousActivityEvent {
+            user_id: Some(success.user_id),
+            activity_type: SuspiciousActivityType::UnusualLocation,
+            details: format!(
+                "login from {} after a login from {}",
+                country(&record.geo),
+                country(&record.previous_geo)
+            ),
+            ip_address: IpAddress(record.ip_address.clone()),
+            timestamp: Timestamp(now),
+        };
+        if let Err(e) = self.events.publish(&unusual).await {
+            warn!(error = %e, "failed to publish suspicious activity event");
+        }
+    }
+
+    async fn authenticate(
+        &self,
+        identifier: &str,
+        password: &str,
+        client: LoginClient<'_>,
+        now: OffsetDateTime,
+    ) -> Result<LoginAccount, LoginError> {
+        let asn = self
+            .telemetry
+            .geo_resolver()
+            .resolve_str(client.ip_address)
+            .and_then(|geo| geo.asn);
+        if let Some(until) = self.stuffing.blocked_until_at(client.ip_address, asn, now) {
+            return Err(LoginError::Blocked { until });
+        }
+
+        let Some(account) = self.accounts.find_by_identifier(identifier).await? else {
+            self.record_failure(identifier, None, client.ip_address, asn, now)
+                .await;
+            return Err(LoginError::InvalidCredentials);
+        };
+        let matches = self
+            .hasher
+            .verify(password, &account.password_hash)
+            .map_err(|e| LoginError::Hashing(e.to_string()))?;
+        if !matches {
+            self.record_failure(
+                identifier,
+                Some(account.user_id),
+                client.ip_address,
+                asn,
+                now,
+            )
+            .await;
+            return Err(LoginError::InvalidCredentials);
+        }
+
+        // Status is only revealed to someone who knows the password
+        match account.status {
+            UserStatus::Active => {}
+            UserStatus::Locked => return Err(LoginError::AccountLocked),
+            _ => return Err(LoginError::AccountInactive),
+        }
+        if account.mfa_enabled {
+            return Err(LoginError::MfaRequired);
+        }
+
+        Ok(account)
+    }
+
+    /// Open the session of a successful login
+    async fn start_session(
+        &self,
+        account: LoginAccount,
+        command: &LoginCommand,
+        client: LoginClient<'_>,
+        now: OffsetDateTime,
+    ) -> Result<LoginSuccess, LoginError> {
+        // An id handed out before authentication, possibly planted by an
+        // attacker, must not survive it
+        let rotated = match client.session_id {
+            Some(old) => self.sessions.rotate(old).await?,
+            None => None,
+        };
+        let session_id = rotated.unwrap_or_else(|| SecretGenerator::token().expose().to_string());
+        let now_text = now
+            .format(&Rfc3339)
+            .expect("UTC timestamps always format as RFC 3339");
+        let session = SessionData {
+            user_id: account.user_id.to_string(),
+            email: account.email,
+            role: account.role,
+            session_id: session_id.clone(),
+            device_id: command.device_id.clone(),
+            user_agent: client.user_agent.to_string(),
+            ip_address: client.ip_address.to_string(),
+            created_at: now_text.clone(),
+            last_activity: now_text,
+        };
+        self.sessions
+            .save_session(&session_id, &session, self.session_ttl)
+            .await?;
+
+        self.accounts.record_login(&account.user_id, now).await?;
+        info!(user_id = %account.user_id, "user logged in");
+        let device = DeviceFingerprint::new(&command.device_id, client.user_agent);
+        let refresh_token = self
+            .refresh_tokens
+            .as_ref()
+            .map(|signer| signer.issue(&account.user_id.to_string(), &session_id, &device));
+        Ok(LoginSuccess {
+            user_id: account.user_id,
+            session_id,
+            refresh_token,
+        })
+    }
+
+    /// Exchange `refresh_token`, presented by `client` from `device_id`, for
+    /// a new one
+    ///
+    /// A token presented from a device other than the one it was issued to
+    /// is treated as stolen: its session is revoked and the attempt is
+    /// published as suspicious activity.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        device_id: &str,
+        client: LoginClient<'_>,
+    ) -> Result<LoginSuccess, LoginError> {
+        let signer = self
+            .refresh_tokens
+            .as_ref()
+            .ok_or(RefreshTokenError::InvalidSignature)?;
+        let device = DeviceFingerprint::new(device_id, client.user_agent);
+        let claims = match signer.verify(refresh_token, &device) {
+            Ok(claims) => claims,
+            Err(RefreshTokenError::DeviceMismatch {
+                user_id,
+                session_id,
+            }) => {
+                let victim: UserId = user_id.parse().map_err(|_| RefreshTokenError::Malformed)?;
+                self.sessions.delete_session(&session_id).await?;
+                let event = SuspiciousActivityEvent {
+                    user_id: Some(victim),
+                    activity_type: SuspiciousActivityType::NewDevice,
+                    details: format!(
+                        "refresh token for session {session_id} used from another device"
+                    ),
+                    ip_address: IpAddress(client.ip_address.to_string()),
+                    timestamp: Timestamp::now(),
+                };
+                if let Err(e) = self.events.publish(&event).await {
+                    warn!(error = %e, "failed to publish suspicious activity event");
+                }
+                return Err(RefreshTokenError::DeviceMismatch {
+                    user_id,
+                    session_id,
+                }
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // Our own signature is on the claims, so an unreadable user id is a
+        // bug or a leaked key, never something to paper over
+        let user_id: UserId = claims
+            .sub
+            .parse()
+            .map_err(|_| RefreshTokenError::Malformed)?;
+        self.sessions
+            .get_session(&claims.session_id)
+            .await?
+            .filter(|session| session.user_id == claims.sub)
+            .ok_or(LoginError::SessionExpired)?;
+        Ok(LoginSuccess {
+            refresh_token: Some(signer.issue(&claims.sub, &claims.session_id, &device)),
+            user_id,
+            session_id: claims.session_id,
+        })
+    }
+
+    /// Re-check the password of the session's user before a sensitive
+    /// action, returning the session's new id
+    pub async fn step_up(&self, session_id: &str, password: &str) -> Result<String, LoginError> {
+        let session = self
+            .sessions
+            .get_session(session_id)
+            .await?
+            .ok_or(LoginError::SessionExpired)?;
+        let account = self
+            .accounts
+            .find_by_identifier(&session.email)
+            .await?
+            .filter(|account| account.user_id.to_string() == session.user_id)
+            .ok_or(LoginError::SessionExpired)?;
+        let matches = self
+            .hasher
+            .verify(password, &account.password_hash)
+            .map_err(|e| LoginError::Hashing(e.to_string()))?;
+        if !matches {
+            return Err(LoginError::InvalidCredentials);
+        }
+        let rotated = self.rotate(session_id).await?;
+        info!(user_id = %account.user_id, "session stepped up");
+        Ok(rotated)
+    }
+
+    /// Record `role` on a session whose user's role changed, returning the
+    /// session's new id
+    pub async fn change_session_role(
+        &self,
+        session_id: &str,
+        role: &str,
+    ) -> Result<String, LoginError> {
+        let rotated = self.rotate(session_id).await?;
+        let mut session = self
+            .sessions
+            .get_session(&rotated)
+            .await?
+            .ok_or(LoginError::SessionExpired)?;
+        session.role = role.to_string();
+        self.sessions
+            .save_session(&rotated, &session, self.session_ttl)
+            .await?;
+        info!(user_id = %session.user_id, role, "session role changed");
+        Ok(rotated)
+    }
+
+    async fn rotate(&self, session_id: &str) -> Result<String, LoginError> {
+        self.sessions
+            .rotate(session_id)
+            .await?
+            .ok_or(LoginError::SessionExpired)
+    }
+
+    /// Count a failed password check towards credential stuffing detection,
+    /// publishing the source if this failure took it over its limit
+    async fn record_failure(
+        &self,
+        identifier: &str,
+        user_id: Option<UserId>,
+        ip_address: &str,
+        asn: Option<u32>,
+        now: OffsetDateTime,
+    ) {
+        let Some(CredentialStuffingDetected {
+            source,
+            distinct_accounts,
+            blocked_until,
+        }) = self
+            .stuffing
+            .record_failure_at(identifier, ip_address, asn, now)
+        else {
+            return;
+        };
+        let event = SuspiciousActivityEvent {
+            user_id,
+            activity_type: SuspiciousActivityType::CredentialStuffing,
+            details: format!(
+                "{distinct_accounts} accounts failed to log in from {source}; blocked until {blocked_until}"
+            ),
+            ip_address: IpAddress(ip_address.to_string()),
+            timestamp: Timestamp(now),
+        };
+        if let Err(e) = self.events.publish(&event).await {
+            warn!(error = %e, "failed to publish suspicious activity event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_stuffing::CredentialStuffingConfig;
+    use crate::domain::events::RecordingPublisher;
+    use crate::geo_ip::{GeoLocation, StaticGeoIpResolver};
+    use crate::login_request::LoginIdentifier;
+    use crate::login_telemetry::RecordingSink;
+    use common::security::Sha256Hasher;
+    use infrastructure::redis::InMemorySessionStore;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use time::macros::datetime;
+
+    /// Accounts keyed by email; `down` makes every lookup fail
+    #[derive(Default)]
+    struct Accounts {
+        accounts: HashMap<String, LoginAccount>,
+        down: bool,
+        logins: Mutex<Vec<UserId>>,
+    }
+
+    #[async_trait]
+    impl LoginAccounts for Accounts {
+        async fn find_by_identifier(
+            &self,
+            identifier: &str,
+        ) -> Result<Option<LoginAccount>, LoginError> {
+            if self.down {
+                return Err(LoginError::Store("connection refused".into()));
+            }
+            Ok(self.accounts.get(identifier).cloned())
+        }
+
+        async fn record_login(
+            &self,
+            user_id: &UserId,
+            _at: OffsetDateTime,
+        ) -> Result<(), LoginError> {
+            self.logins.lock().unwrap().push(*user_id);
+            Ok(())
+        }
+    }
+
+    fn account(email: &str, status: UserStatus) -> LoginAccount {
+        LoginAccount {
+            user_id: UserId::new(),
+            email: email.to_string(),
+            role: "BUYER".to_string(),
+            password_hash: Sha256Hasher.hash("correct horse").unwrap(),
+            status,
+            mfa_enabled: false,
+        }
+    }
+
+    fn command(identifier: &str, password: &str) -> LoginCommand {
+        LoginCommand {
+            identifier: LoginIdentifier::Email(identifier.to_string()),
+            password: password.to_string(),
+            device_id: "ios-42".to_string(),
+            device_name: None,
+        }
+    }
+
+    const CLIENT: LoginClient<'static> = LoginClient {
+        ip_address: "102.89.1.7",
+        user_agent: "TrustFlow/3.1 iOS",
+        session_id: None,
+    };
+
+    fn service(
+        accounts: Accounts,
+    ) -> (
+        LoginService<Sha256Hasher>,
+        Arc<Accounts>,
+        Arc<InMemorySessionStore>,
+        Arc<RecordingSink>,
+    ) {
+        let accounts = Arc::new(accounts);
+        let sessions = Arc::new(InMemorySessionStore::new());
+        let sink = Arc::new(RecordingSink::default());
+        let service = LoginService::new(accounts.clone(), sessions.clone(), Sha256Hasher)
+            .with_telemetry(LoginTelemetry::new(sink.clone()));
+        (service, accounts, sessions, sink)
+    }
+
+    /// Service knowing only Ada, who is active
+    fn with_ada() -> (
+        LoginService<Sha256Hasher>,
+        LoginAccount,
+        Arc<InMemorySessionStore>,
+    ) {
+        let ada = account("ada@example.com", UserStatus::Active);
+        let (service, _, sessions, _) = service(Accounts {
+            accounts: HashMap::from([("ada@example.com".to_string(), ada.clone())]),
+            ..Default::default()
+        });
+        (service, ada, sessions)
+    }
+
+    #[tokio::test]
+    async fn test_every_login_attempt_emits_telemetry_with_its_outcome() {
+        let ada = account("ada@example.com", UserStatus::Active);
+        let (service, accounts, _, sink) = service(Accounts {
+            accounts: HashMap::from([
+                ("ada@example.com".to_string(), ada.clone()),
+                (
+                    "bob@example.com".to_string(),
+                    account("bob@example.com", UserStatus::Locked),
+                ),
+                (
+                    "eve@example.com".to_string(),
+                    account("eve@example.com", UserStatus::Suspended),
+                ),
+            ]),
+            ..Default::default()
+        });
+        let now = datetime!(2024-03-10 12:00:00 UTC);
+
+        let success = service
+            .login_at(&command(" ada@example.com", "correct horse"), CLIENT, now)
+            .await
+            .unwrap();
+        assert_eq!(success.user_id, ada.user_id);
+        assert_eq!(*accounts.logins.lock().unwrap(), [ada.user_id]);
+
+        for (identifier, password) in [
+            ("ada@example.com", "wrong"),
+            ("nobody@example.com", "correct horse"),
+            ("bob@example.com", "correct horse"),
+            ("eve@example.com", "correct horse"),
+        ] {
+            assert!(
+                service
+                    .login_at(&command(identifier, password), CLIENT, now)
+                    .await
+                    .is_err()
+            );
+        }
+
+        let records = sink.0.lock().unwrap();
+        let outcomes: Vec<LoginOutcome> = records.iter().map(|r| r.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                LoginOutcome::Success,
+                LoginOutcome::InvalidCredentials,
+                LoginOutcome::InvalidCredentials,
+                LoginOutcome::AccountLocked,
+                LoginOutcome::AccountInactive,
+            ]
+        );
+        assert_eq!(records[0].account, "ada@example.com");
+        assert_eq!(records[0].ip_address, "102.89.1.7");
+        assert_eq!(records[0].device_id, "ios-42");
+        assert_eq!(records[0].user_agent, "TrustFlow/3.1 iOS");
+        assert_eq!(records[0].occurred_at, now);
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_is_recorded_as_an_error_not_bad_credentials() {
+        let (service, _, _, sink) = service(Accounts {
+            down: true,
+            ..Default::default()
+        });
+
+        let err = service
+            .login(&command("ada@example.com", "correct horse"), CLIENT)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoginError::Store(_)));
+        assert!(matches!(
+            AppError::from(err),
+            AppError::InfrastructureError(_)
+        ));
+        assert_eq!(sink.0.lock().unwrap()[0].outcome, LoginOutcome::Error);
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_feed_credential_stuffing_detection_by_as() {
+        let ada = account("ada@example.com", UserStatus::Active);
+        let accounts = Arc::new(Accounts {
+            accounts: HashMap::from([("ada@example.com".to_string(), ada.clone())]),
+            ..Default::default()
+        });
+        let sink = Arc::new(RecordingSink::default());
+        let events = Arc::new(RecordingPublisher::default());
+        let geo = StaticGeoIpResolver::new()
+            .with("102.89.1.7", GeoLocation::country("NG").with_asn(29465))
+            .with("102.89.1.8", GeoLocation::country("NG").with_asn(29465));
+        let sessions = Arc::new(InMemorySessionStore::new());
+        let service = LoginService::new(accounts, sessions, Sha256Hasher)
+            .with_telemetry(LoginTelemetry::new(sink.clone()).with_geo_resolver(Arc::new(geo)))
+            .with_credential_stuffing_detector(CredentialStuffingDetector::new(
+                CredentialStuffingConfig {
+                    max_accounts_per_ip: 5,
+                    max_accounts_per_asn: 2,
+                    ..Default::default()
+                },
+            ))
+            .with_event_publisher(events.clone());
+        let now = datetime!(2024-03-10 12:00:00 UTC);
+        let from = |ip_address| LoginClient {
+            ip_address,
+            ..CLIENT
+        };
+
+        // Spread over two addresses of one network, so only the AS is caught
+        for (identifier, ip) in [
+            ("nobody1@example.com", "102.89.1.7"),
+            ("nobody2@example.com", "102.89.1.8"),
+            ("ada@example.com", "102.89.1.7"),
+        ] {
+            let err = service
+                .login_at(&command(identifier, "guess"), from(ip), now)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, LoginError::InvalidCredentials));
+        }
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [(
+                "security.suspicious_activity".to_string(),
+                ada.user_id.to_string()
+            )]
+        );
+
+        // Even the right password is refused from anywhere in the AS
+        let err = service
+            .login_at(
+                &command("ada@example.com", "correct horse"),
+                from("102.89.1.8"),
+                now,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoginError::Blocked { .. }));
+        assert!(matches!(AppError::from(err), AppError::RateLimitError(_)));
+        assert_eq!(
+            sink.0.lock().unwrap().last().unwrap().outcome,
+            LoginOutcome::RateLimited
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_rotates_the_session_held_before_it() {
+        let (service, ada, sessions) = with_ada();
+        let anonymous = SessionData {
+            user_id: String::new(),
+            email: String::new(),
+            role: String::new(),
+            session_id: "planted".to_string(),
+            device_id: "ios-42".to_string(),
+            user_agent: CLIENT.user_agent.to_string(),
+            ip_address: CLIENT.ip_address.to_string(),
+            created_at: String::new(),
+            last_activity: String::new(),
+        };
+        sessions
+            .save_session("planted", &anonymous, DEFAULT_SESSION_TTL)
+            .await
+            .unwrap();
+
+        let client = LoginClient {
+            session_id: Some("planted"),
+            ..CLIENT
+        };
+        let success = service
+            .login(&command("ada@example.com", "correct horse"), client)
+            .await
+            .unwrap();
+
+        assert_ne!(success.session_id, "planted");
+        assert!(sessions.get_session("planted").await.unwrap().is_none());
+        let session = sessions
+            .get_session(&success.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.user_id, ada.user_id.to_string());
+        assert_eq!(session.session_id, success.session_id);
+        assert_eq!(session.email, "ada@example.com");
+        assert_eq!(session.role, "BUYER");
+    }
+
+    #[tokio::test]
+    async fn test_step_up_and_role_change_rotate_the_session() {
+        let (service, _, sessions) = with_ada();
+        let login = service
+            .login(&command("ada@example.com", "correct horse"), CLIENT)
+            .await
+            .unwrap();
+
+        // A wrong password leaves the session where it was
+        assert!(matches!(
+            service.step_up(&login.session_id, "wrong").await,
+            Err(LoginError::InvalidCredentials)
+        ));
+        let stepped_up = service
+            .step_up(&login.session_id, "correct horse")
+            .await
+            .unwrap();
+        assert_ne!(stepped_up, login.session_id);
+        assert!(
+            sessions
+                .get_session(&login.session_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let seller = service
+            .change_session_role(&stepped_up, "SELLER")
+            .await
+            .unwrap();
+        assert!(sessions.get_session(&stepped_up).await.unwrap().is_none());
+        let session = sessions.get_session(&seller).await.unwrap().unwrap();
+        assert_eq!(session.role, "SELLER");
+        assert_eq!(session.session_id, seller);
+
+        // Old ids are dead for every privilege change
+        assert!(matches!(
+            service.change_session_role(&stepped_up, "ADMIN").await,
+            Err(LoginError::SessionExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_tokens_are_bound_to_the_session_and_device() {
+        let (service, ada, sessions) = with_ada();
+        let events = Arc::new(RecordingPublisher::default());
+        let service = service
+            .with_refresh_tokens(RefreshTokenSigner::new("refresh-only-key"))
+            .with_event_publisher(events.clone());
+        let login = service
+            .login(&command("ada@example.com", "correct horse"), CLIENT)
+            .await
+            .unwrap();
+        let token = login.refresh_token.unwrap();
+
+        // An app update changes the user agent's versions, not the device
+        let updated = LoginClient {
+            user_agent: "TrustFlow/3.2 iOS",
+            ..CLIENT
+        };
+        let refreshed = service.refresh(&token, "ios-42", updated).await.unwrap();
+        assert_eq!(refreshed.user_id, ada.user_id);
+        assert_eq!(refreshed.session_id, login.session_id);
+        let logged_in = ("user.logged_in".to_string(), ada.user_id.to_string());
+        assert_eq!(*events.0.lock().unwrap(), std::slice::from_ref(&logged_in));
+
+        let stolen = LoginClient {
+            ip_address: "41.58.7.7",
+            user_agent: "Mozilla/5.0 (X11; Linux x86_64)",
+            session_id: None,
+        };
+        let err = service
+            .refresh(&refreshed.refresh_token.unwrap(), "laptop-1", stolen)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LoginError::Refresh(RefreshTokenError::DeviceMismatch { .. })
+        ));
+        assert!(
+            sessions
+                .get_session(&login.session_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                logged_in,
+                (
+                    "security.suspicious_activity".to_string(),
+                    ada.user_id.to_string()
+                )
+            ]
+        );
+
+        // The revoked session can't be refreshed from the right device either
+        assert!(matches!(
+            service.refresh(&token, "ios-42", CLIENT).await,
+            Err(LoginError::SessionExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_login_from_a_new_country_is_published_as_unusual_location() {
+        let (service, ada, _) = with_ada();
+        let events = Arc::new(RecordingPublisher::default());
+        let geo = StaticGeoIpResolver::new()
+            .with("102.89.1.7", GeoLocation::country("NG"))
+            .with("102.89.1.8", GeoLocation::country("NG"))
+            .with("81.2.69.142", GeoLocation::country("GB"));
+        let sink = Arc::new(RecordingSink::default());
+        let service = service
+            .with_telemetry(LoginTelemetry::new(sink.clone()).with_geo_resolver(Arc::new(geo)))
+            .with_event_publisher(events.clone());
+        let now = datetime!(2024-03-10 12:00:00 UTC);
+
+        for (ip_address, minutes) in [("102.89.1.7", 0), ("102.89.1.8", 5), ("81.2.69.142", 60)] {
+            let client = LoginClient {
+                ip_address,
+                ..CLIENT
+            };
+            service
+                .login_at(
+                    &command("ada@example.com", "correct horse"),
+                    client,
+                    now + time::Duration::minutes(minutes),
+                )
+                .await
+                .unwrap();
+        }
+
+        let logged_in = ("user.logged_in".to_string(), ada.user_id.to_string());
+        let unusual = (
+            "security.suspicious_activity".to_string(),
+            ada.user_id.to_string(),
+        );
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [logged_in.clone(), logged_in.clone(), logged_in, unusual]
+        );
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records[2].geo, Some(GeoLocation::country("GB")));
+        assert!(records[2].unusual_location);
+    }
+}
@@ -0,0 +1,189 @@
+//! Versioned login request bodies
+//!
+//! v1 clients send a free-form `identifier` and a bare `device_id`.  v2 says
+//! which kind of identifier it is and describes the device, so sign-in
+//! notifications can name it.  Both decode into [`LoginCommand`] through
+//! [`common::extractors::Versioned`]:
+//!
+//! ```json
+//! // v1
+//! { "identifier": "ada@example.com", "password": "...", "device_id": "ios-42" }
+//! // v2 (Accept-Version: 2 or /v2/auth/login)
+//! { "login": { "email": "ada@example.com" }, "password": "...",
+//!   "device": { "id": "ios-42", "name": "Ada's iPhone" } }
+//! ```
+
+use common::extractors::{ApiVersion, VersionedRequest, decode_as};
+use serde::{Deserialize, Serialize};
+
+/// How the user identifies themselves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginIdentifier {
+    Email(String),
+    Phone(String),
+    /// v1 identifier, either an email or a phone number
+    Unspecified(String),
+}
+
+impl LoginIdentifier {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Email(value) | Self::Phone(value) | Self::Unspecified(value) => value,
+        }
+    }
+}
+
+/// Login request as the auth service consumes it, whatever version the
+/// client sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginCommand {
+    pub identifier: LoginIdentifier,
+    pub password: String,
+    pub device_id: String,
+    pub device_name: Option<String>,
+}
+
+/// v1 login body
+#[derive(Debug, Deserialize)]
+pub struct LoginRequestV1 {
+    pub identifier: String,
+    pub password: String,
+    pub device_id: String,
+}
+
+/// Device description in a v2 login body
+#[derive(Debug, Deserialize)]
+pub struct LoginDevice {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+/// v2 login body
+#[derive(Debug, Deserialize)]
+pub struct LoginRequestV2 {
+    pub login: LoginIdentifier,
+    pub password: String,
+    pub device: LoginDevice,
+}
+
+impl From<LoginRequestV1> for LoginCommand {
+    fn from(request: LoginRequestV1) -> Self {
+        Self {
+            identifier: LoginIdentifier::Unspecified(request.identifier),
+            password: request.password,
+            device_id: request.device_id,
+            device_name: None,
+        }
+    }
+}
+
+impl From<LoginRequestV2> for LoginCommand {
+    fn from(request: LoginRequestV2) -> Self {
+        Self {
+            identifier: request.login,
+            password: request.password,
+            device_id: request.device.id,
+            device_name: request.device.name,
+        }
+    }
+}
+
+impl VersionedRequest for LoginCommand {
+    const VERSIONS: &'static [ApiVersion] = &[ApiVersion::V1, ApiVersion::V2];
+
+    fn decode(version: ApiVersion, body: &[u8]) -> Result<Self, serde_json::Error> {
+        match version {
+            ApiVersion::V1 => decode_as::<LoginRequestV1, _>(body),
+            _ => decode_as::<LoginRequestV2, _>(body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Json, Router,
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+    };
+    use common::extractors::Versioned;
+    use common::testing::body_bytes;
+    use tower::ServiceExt;
+
+    async fn login(
+        Versioned { version, body }: Versioned<LoginCommand>,
+    ) -> Json<serde_json::Value> {
+        Json(serde_json::json!({
+            "version": version.to_string(),
+            "identifier": body.identifier,
+            "device_id": body.device_id,
+            "device_name": body.device_name,
+        }))
+    }
+
+    async fn call(
+        uri: &str,
+        accept_version: Option<&str>,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let app = Router::new()
+            .route("/auth/login", post(login))
+            .route("/v2/auth/login", post(login));
+        let mut request = Request::post(uri).header("content-type", "application/json");
+        if let Some(version) = accept_version {
+            request = request.header("accept-version", version);
+        }
+        let response = app
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = body_bytes(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_v1_and_v2_bodies_reach_the_same_handler() {
+        let (status, v1) = call(
+            "/auth/login",
+            None,
+            serde_json::json!({
+                "identifier": "ada@example.com",
+                "password": "correct horse",
+                "device_id": "ios-42",
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v1["version"], "v1");
+        assert_eq!(
+            v1["identifier"],
+            serde_json::json!({ "unspecified": "ada@example.com" })
+        );
+        assert_eq!(v1["device_name"], serde_json::Value::Null);
+
+        let v2_body = serde_json::json!({
+            "login": { "email": "ada@example.com" },
+            "password": "correct horse",
+            "device": { "id": "ios-42", "name": "Ada's iPhone" },
+        });
+        for (uri, header) in [("/auth/login", Some("2")), ("/v2/auth/login", None)] {
+            let (status, v2) = call(uri, header, v2_body.clone()).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(v2["version"], "v2");
+            assert_eq!(
+                v2["identifier"],
+                serde_json::json!({ "email": "ada@example.com" })
+            );
+            assert_eq!(v2["device_id"], "ios-42");
+            assert_eq!(v2["device_name"], "Ada's iPhone");
+        }
+
+        // A v2 body sent without asking for v2 is decoded as v1 and rejected
+        let (status, _) = call("/auth/login", None, v2_body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
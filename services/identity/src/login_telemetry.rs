@@ -0,0 +1,368 @@
+//! Login-security telemetry
+//!
+//! Every login attempt, successful or not, is described by a
+//! [`LoginTelemetryRecord`]: where it came from (IP, geolocation, device),
+//! how it ended, and how many attempts the same IP and account made
+//! shortly before.  When the attempt comes from another country than the
+//! account's previous successful login it is flagged as an unusual
+//! location, which the risk service raises as
+//! `SuspiciousActivityType::UnusualLocation`.  [`LoginTelemetry`] sends these to a
+//! [`LoginTelemetrySink`], normally the Redis stream the risk service scores
+//! in real time (see [`LoginTelemetry::stream`]).  This is separate from the
+//! audit log: records are high-volume, short-lived and meant for machines.
+//!
+//! Emitting is best effort.  A sink that fails is logged and never fails
+//! the login.  Velocity counts and last login locations are kept in memory,
+//! per instance, like [`crate::credential_stuffing`].
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use error::AppError;
+use infrastructure::redis::{EventLog, RedisPool};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+
+use crate::geo_ip::{GeoIpResolver, GeoLocation, NoGeoIp};
+
+/// Event type of telemetry records on the stream
+pub const LOGIN_ATTEMPT_EVENT: &str = "login_attempt";
+
+/// How a login attempt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    InvalidCredentials,
+    /// Password accepted, second factor still outstanding
+    MfaRequired,
+    AccountLocked,
+    /// Password accepted, but the account is suspended, deleted or not yet
+    /// activated
+    AccountInactive,
+    /// Refused before the password was checked, e.g. a blocked source
+    RateLimited,
+    /// The attempt failed on our side, e.g. the account store was down
+    Error,
+}
+
+/// A login attempt as the auth service saw it
+#[derive(Debug, Clone, Copy)]
+pub struct LoginAttempt<'a> {
+    pub identifier: &'a str,
+    pub ip_address: &'a str,
+    pub device_id: &'a str,
+    pub user_agent: &'a str,
+}
+
+/// Attempts made within the velocity window, this one included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LoginVelocity {
+    pub window_secs: i64,
+    pub attempts_from_ip: usize,
+    pub attempts_for_account: usize,
+}
+
+/// What the risk service receives for each login attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoginTelemetryRecord {
+    /// Identifier the attempt was made for, lowercased
+    pub account: String,
+    pub ip_address: String,
+    pub geo: Option<GeoLocation>,
+    /// Location of the account's previous successful login, if known
+    pub previous_geo: Option<GeoLocation>,
+    /// `geo` is in another country than `previous_geo`
+    pub unusual_location: bool,
+    pub device_id: String,
+    pub user_agent: String,
+    pub outcome: LoginOutcome,
+    pub velocity: LoginVelocity,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+}
+
+/// Destination of telemetry records
+#[async_trait]
+pub trait LoginTelemetrySink: Send + Sync {
+    async fn emit(&self, record: &LoginTelemetryRecord) -> Result<(), AppError>;
+}
+
+#[async_trait]
+impl LoginTelemetrySink for EventLog {
+    async fn emit(&self, record: &LoginTelemetryRecord) -> Result<(), AppError> {
+        self.append(LOGIN_ATTEMPT_EVENT, record).await?;
+        Ok(())
+    }
+}
+
+/// Sink that drops every record
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullLoginTelemetrySink;
+
+#[async_trait]
+impl LoginTelemetrySink for NullLoginTelemetrySink {
+    async fn emit(&self, _record: &LoginTelemetryRecord) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// Sink that keeps every record, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordingSink(pub(crate) Mutex<Vec<LoginTelemetryRecord>>);
+
+#[cfg(test)]
+#[async_trait]
+impl LoginTelemetrySink for RecordingSink {
+    async fn emit(&self, record: &LoginTelemetryRecord) -> Result<(), AppError> {
+        self.0.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+/// Builds and emits a [`LoginTelemetryRecord`] per login attempt
+///
+/// Cheap to clone; clones share velocity counts.
+#[derive(Clone)]
+pub struct LoginTelemetry {
+    sink: Arc<dyn LoginTelemetrySink>,
+    geo: Arc<dyn GeoIpResolver>,
+    window: Duration,
+    /// Attempt times by `ip:<address>` / `account:<identifier>`, oldest first
+    attempts: Arc<Mutex<HashMap<String, VecDeque<OffsetDateTime>>>>,
+    /// Location of the last successful login, by account
+    last_locations: Arc<Mutex<HashMap<String, GeoLocation>>>,
+}
+
+impl LoginTelemetry {
+    pub fn new(sink: Arc<dyn LoginTelemetrySink>) -> Self {
+        Self {
+            sink,
+            geo: Arc::new(NoGeoIp),
+            window: Duration::minutes(10),
+            attempts: Arc::default(),
+            last_locations: Arc::default(),
+        }
+    }
+
+    /// Telemetry appended to the `login-telemetry` stream, keeping roughly
+    /// the last `max_len` records
+    pub fn stream(pool: RedisPool, prefix: &str, max_len: usize) -> Self {
+        Self::new(Arc::new(
+            EventLog::new(pool, prefix, "login-telemetry").with_max_len(max_len),
+        ))
+    }
+
+    /// Locate attempts through `geo`
+    pub fn with_geo_resolver(mut self, geo: Arc<dyn GeoIpResolver>) -> Self {
+        self.geo = geo;
+        self
+    }
+
+    /// Resolver locating attempts
+    pub fn geo_resolver(&self) -> &Arc<dyn GeoIpResolver> {
+        &self.geo
+    }
+
+    /// Count attempts over `window` for velocity (10 minutes by default)
+    pub fn with_velocity_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Emit the record of `attempt`
+    pub async fn record(
+        &self,
+        attempt: LoginAttempt<'_>,
+        outcome: LoginOutcome,
+    ) -> LoginTelemetryRecord {
+        self.record_at(attempt, outcome, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Emit the record of `attempt`, made at `now`, and return it so the
+    /// caller can act on its geo check
+    pub async fn record_at(
+        &self,
+        attempt: LoginAttempt<'_>,
+        outcome: LoginOutcome,
+        now: OffsetDateTime,
+    ) -> LoginTelemetryRecord {
+        let record = self.build(attempt, outcome, now);
+        if let Err(e) = self.sink.emit(&record).await {
+            warn!(error = %e, outcome = ?record.outcome, "failed to emit login telemetry");
+        }
+        record
+    }
+
+    fn build(
+        &self,
+        attempt: LoginAttempt<'_>,
+        outcome: LoginOutcome,
+        now: OffsetDateTime,
+    ) -> LoginTelemetryRecord {
+        // `Ada@example.com` and `ada@example.com` are the same account
+        let account = attempt.identifier.trim().to_lowercase();
+        let velocity = LoginVelocity {
+            window_secs: self.window.whole_seconds(),
+            attempts_from_ip: self.count(format!("ip:{}", attempt.ip_address), now),
+            attempts_for_account: self.count(format!("account:{account}"), now),
+        };
+        let geo = self.geo.resolve_str(attempt.ip_address);
+        let previous_geo = self.last_location(&account, geo.as_ref(), outcome);
+        let unusual_location = matches!(
+            (&geo, &previous_geo),
+            (Some(geo), Some(previous)) if geo.is_unusual_after(previous)
+        );
+        LoginTelemetryRecord {
+            account,
+            ip_address: attempt.ip_address.to_string(),
+            geo,
+            previous_geo,
+            unusual_location,
+            device_id: attempt.device_id.to_string(),
+            user_agent: attempt.user_agent.to_string(),
+            outcome,
+            velocity,
+            occurred_at: now,
+        }
+    }
+
+    /// Location of the account's previous successful login; remembers
+    /// `geo` if this attempt succeeded
+    fn last_location(
+        &self,
+        account: &str,
+        geo: Option<&GeoLocation>,
+        outcome: LoginOutcome,
+    ) -> Option<GeoLocation> {
+        let mut locations = self.last_locations.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = locations.get(account).cloned();
+        if let (LoginOutcome::Success, Some(geo)) = (outcome, geo)
+            && geo.country.is_some()
+        {
+            locations.insert(account.to_string(), geo.clone());
+        }
+        previous
+    }
+
+    /// Add an attempt at `now` under `key` and count those in the window
+    fn count(&self, key: String, now: OffsetDateTime) -> usize {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let times = attempts.entry(key).or_default();
+        while times.front().is_some_and(|at| *at <= now - self.window) {
+            times.pop_front();
+        }
+        times.push_back(now);
+        times.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn geo() -> Arc<dyn GeoIpResolver> {
+        Arc::new(
+            crate::geo_ip::StaticGeoIpResolver::new()
+                .with(
+                    "102.89.1.7",
+                    GeoLocation::country("NG")
+                        .with_region("Lagos")
+                        .with_city("Lagos")
+                        .with_asn(29465),
+                )
+                .with("81.2.69.160", GeoLocation::country("GB").with_city("London")),
+        )
+    }
+
+    fn attempt(identifier: &str) -> LoginAttempt<'_> {
+        LoginAttempt {
+            identifier,
+            ip_address: "102.89.1.7",
+            device_id: "ios-42",
+            user_agent: "TrustFlow/3.1 iOS",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_emits_telemetry_record() {
+        let sink = Arc::new(RecordingSink::default());
+        let telemetry = LoginTelemetry::new(sink.clone()).with_geo_resolver(geo());
+        let now = datetime!(2024-03-10 12:00:00 UTC);
+
+        telemetry
+            .record_at(
+                attempt("Ada@example.com"),
+                LoginOutcome::InvalidCredentials,
+                now,
+            )
+            .await;
+        telemetry
+            .record_at(
+                attempt("bob@example.com"),
+                LoginOutcome::Success,
+                now + Duration::minutes(1),
+            )
+            .await;
+        // Outside the window the earlier attempts no longer count
+        telemetry
+            .record_at(
+                attempt("ada@example.com"),
+                LoginOutcome::Success,
+                now + Duration::seconds(630),
+            )
+            .await;
+
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        let json = serde_json::to_value(&records[0]).unwrap();
+        assert_eq!(json["account"], "ada@example.com");
+        assert_eq!(json["ip_address"], "102.89.1.7");
+        assert_eq!(json["geo"]["country"], "NG");
+        assert_eq!(json["geo"]["region"], "Lagos");
+        assert_eq!(json["geo"]["asn"], 29465);
+        assert_eq!(json["device_id"], "ios-42");
+        assert_eq!(json["user_agent"], "TrustFlow/3.1 iOS");
+        assert_eq!(json["outcome"], "invalid_credentials");
+        assert_eq!(json["velocity"]["window_secs"], 600);
+        assert_eq!(json["occurred_at"], "2024-03-10T12:00:00Z");
+
+        assert_eq!(records[1].velocity.attempts_from_ip, 2);
+        assert_eq!(records[1].velocity.attempts_for_account, 1);
+        assert_eq!(records[2].velocity.attempts_from_ip, 2);
+        assert_eq!(records[2].velocity.attempts_for_account, 1);
+    }
+
+    #[tokio::test]
+    async fn test_login_from_another_country_is_flagged_as_unusual_location() {
+        let sink = Arc::new(RecordingSink::default());
+        let telemetry = LoginTelemetry::new(sink.clone()).with_geo_resolver(geo());
+        let from = |ip_address| LoginAttempt {
+            ip_address,
+            ..attempt("ada@example.com")
+        };
+
+        telemetry.record(from("102.89.1.7"), LoginOutcome::Success).await;
+        // A failed attempt from abroad is flagged but doesn't move the account
+        telemetry
+            .record(from("81.2.69.160"), LoginOutcome::InvalidCredentials)
+            .await;
+        telemetry.record(from("102.89.1.7"), LoginOutcome::Success).await;
+        telemetry.record(from("81.2.69.160"), LoginOutcome::Success).await;
+        // Unresolvable addresses are never unusual
+        telemetry.record(from("10.0.0.1"), LoginOutcome::Success).await;
+
+        let records = sink.0.lock().unwrap();
+        let flags: Vec<bool> = records.iter().map(|r| r.unusual_location).collect();
+        assert_eq!(flags, [false, true, false, true, false]);
+        assert_eq!(records[0].previous_geo, None);
+        assert_eq!(records[3].previous_geo.as_ref().unwrap().to_string(), "Lagos, Lagos, NG");
+        assert_eq!(records[3].geo.as_ref().unwrap().country.as_deref(), Some("GB"));
+        assert_eq!(records[4].previous_geo.as_ref().unwrap().to_string(), "London, GB");
+    }
+}
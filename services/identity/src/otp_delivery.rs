@@ -0,0 +1,326 @@
+//! OTP delivery over several channels
+//!
+//! SMS gateways drop messages to some networks for minutes at a time, and
+//! mailboxes bounce.  [`OtpDispatcher`] tries the user's channels one after
+//! the other, in the user's preferred order or the dispatcher's default
+//! one, until one accepts the code, and reports every attempt in the
+//! [`OtpDeliveryResult`] so the client can tell the user where to look.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use error::AppError;
+use infrastructure::email::{EmailMessage, EmailProvider};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Channel an OTP can be sent over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtpChannelKind {
+    Sms,
+    Email,
+}
+
+/// OTP delivery errors
+#[derive(Debug, Error)]
+pub enum OtpDeliveryError {
+    #[error("no {0:?} address on file")]
+    NoAddress(OtpChannelKind),
+
+    #[error("{channel:?} delivery failed: {message}")]
+    Channel {
+        channel: OtpChannelKind,
+        message: String,
+    },
+
+    #[error("OTP could not be delivered over any channel")]
+    Undeliverable { attempts: Vec<OtpDeliveryAttempt> },
+}
+
+impl From<OtpDeliveryError> for AppError {
+    fn from(err: OtpDeliveryError) -> Self {
+        match err {
+            OtpDeliveryError::NoAddress(_) => AppError::validation(err.to_string()),
+            OtpDeliveryError::Channel { .. } | OtpDeliveryError::Undeliverable { .. } => {
+                AppError::external("otp_delivery", err.to_string())
+            }
+        }
+    }
+}
+
+/// Where a user can receive OTPs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OtpRecipient {
+    pub user_id: String,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    /// Channels in the order the user prefers; empty for the dispatcher's
+    /// default order
+    pub preferred_channels: Vec<OtpChannelKind>,
+}
+
+/// One delivery attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OtpDeliveryAttempt {
+    pub channel: OtpChannelKind,
+    /// Why the attempt failed; `None` if the code was delivered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of a successful dispatch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OtpDeliveryResult {
+    /// Channel that accepted the code
+    pub delivered_via: OtpChannelKind,
+    /// Every attempt made, in order, the successful one last
+    pub attempts: Vec<OtpDeliveryAttempt>,
+}
+
+impl OtpDeliveryResult {
+    /// Whether a channel before the one that delivered failed
+    pub fn fell_back(&self) -> bool {
+        self.attempts.len() > 1
+    }
+}
+
+/// Sends OTP codes over one channel
+#[async_trait]
+pub trait OtpChannel: Send + Sync {
+    fn kind(&self) -> OtpChannelKind;
+
+    async fn send(&self, recipient: &OtpRecipient, code: &str) -> Result<(), OtpDeliveryError>;
+}
+
+/// Sends OTP codes by email
+pub struct EmailOtpChannel {
+    provider: Arc<dyn EmailProvider>,
+    from: String,
+}
+
+impl EmailOtpChannel {
+    pub fn new(provider: Arc<dyn EmailProvider>, from: impl Into<String>) -> Self {
+        Self {
+            provider,
+            from: from.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl OtpChannel for EmailOtpChannel {
+    fn kind(&self) -> OtpChannelKind {
+        OtpChannelKind::Email
+    }
+
+    async fn send(&self, recipient: &OtpRecipient, code: &str) -> Result<(), OtpDeliveryError> {
+        let email = recipient
+            .email
+            .as_deref()
+            .ok_or(OtpDeliveryError::NoAddress(OtpChannelKind::Email))?;
+        let message = EmailMessage::new(&self.from, email, "Your TrustFlow verification code")
+            .with_text(format!(
+                "Your verification code is {code}. Do not share it with anyone."
+            ));
+        self.provider
+            .send(message)
+            .await
+            .map_err(|e| OtpDeliveryError::Channel {
+                channel: OtpChannelKind::Email,
+                message: e.to_string(),
+            })
+    }
+}
+
+/// Delivers OTP codes, falling back from one channel to the next
+#[derive(Clone)]
+pub struct OtpDispatcher {
+    /// Registered channels, in the default order
+    channels: Vec<Arc<dyn OtpChannel>>,
+}
+
+impl OtpDispatcher {
+    /// Dispatcher trying `channels` in the given order by default
+    pub fn new(channels: Vec<Arc<dyn OtpChannel>>) -> Self {
+        Self { channels }
+    }
+
+    /// Channels to try for `recipient`, in order
+    ///
+    /// The preferred channels come first; registered channels the user
+    /// didn't list follow in the default order.
+    fn order_for(&self, recipient: &OtpRecipient) -> Vec<&Arc<dyn OtpChannel>> {
+        let mut ordered: Vec<_> = recipient
+            .preferred_channels
+            .iter()
+            .filter_map(|kind| self.channels.iter().find(|c| c.kind() == *kind))
+            .collect();
+        for channel in &self.channels {
+            if !ordered.iter().any(|c| c.kind() == channel.kind()) {
+                ordered.push(channel);
+            }
+        }
+        ordered
+    }
+
+    /// Send `code` to `recipient` over the first channel that accepts it
+    pub async fn dispatch(
+        &self,
+        recipient: &OtpRecipient,
+        code: &str,
+    ) -> Result<OtpDeliveryResult, OtpDeliveryError> {
+        let mut attempts = Vec::new();
+        for channel in self.order_for(recipient) {
+            let kind = channel.kind();
+            match channel.send(recipient, code).await {
+                Ok(()) => {
+                    attempts.push(OtpDeliveryAttempt {
+                        channel: kind,
+                        error: None,
+                    });
+                    info!(
+                        user_id = %recipient.user_id,
+                        channel = ?kind,
+                        attempts = attempts.len(),
+                        "OTP delivered"
+                    );
+                    if attempts.len() > 1 {
+                        infrastructure::observability::record_degradation(
+                            "otp_delivery",
+                            "channel_fallback",
+                        );
+                    }
+                    return Ok(OtpDeliveryResult {
+                        delivered_via: kind,
+                        attempts,
+                    });
+                }
+                // Nothing was tried, so there's nothing to report
+                Err(OtpDeliveryError::NoAddress(_)) => {}
+                Err(e) => {
+                    warn!(
+                        user_id = %recipient.user_id,
+                        channel = ?kind,
+                        error = %e,
+                        "OTP delivery failed, trying next channel"
+                    );
+                    attempts.push(OtpDeliveryAttempt {
+                        channel: kind,
+                        error: Some(e.to_string()),
+                    });
+                }
+            }
+        }
+        Err(OtpDeliveryError::Undeliverable { attempts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use infrastructure::email::MockEmailProvider;
+    use std::sync::Mutex;
+
+    /// SMS gateway stand-in
+    #[derive(Default)]
+    struct MockSms {
+        down: bool,
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait]
+    impl OtpChannel for MockSms {
+        fn kind(&self) -> OtpChannelKind {
+            OtpChannelKind::Sms
+        }
+
+        async fn send(&self, recipient: &OtpRecipient, code: &str) -> Result<(), OtpDeliveryError> {
+            let phone = recipient
+                .phone
+                .clone()
+                .ok_or(OtpDeliveryError::NoAddress(OtpChannelKind::Sms))?;
+            if self.down {
+                return Err(OtpDeliveryError::Channel {
+                    channel: OtpChannelKind::Sms,
+                    message: "gateway timeout".to_string(),
+                });
+            }
+            self.sent.lock().unwrap().push((phone, code.to_string()));
+            Ok(())
+        }
+    }
+
+    fn recipient() -> OtpRecipient {
+        OtpRecipient {
+            user_id: "user-1".to_string(),
+            phone: Some("+2348012345678".to_string()),
+            email: Some("ada@example.com".to_string()),
+            preferred_channels: vec![OtpChannelKind::Sms],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_email_when_sms_fails() {
+        let sms = Arc::new(MockSms {
+            down: true,
+            ..MockSms::default()
+        });
+        let email = MockEmailProvider::new();
+        let dispatcher = OtpDispatcher::new(vec![
+            Arc::new(EmailOtpChannel::new(
+                Arc::new(email.clone()),
+                "no-reply@trustflow.io",
+            )),
+            sms.clone(),
+        ]);
+
+        let result = dispatcher.dispatch(&recipient(), "482913").await.unwrap();
+
+        assert_eq!(result.delivered_via, OtpChannelKind::Email);
+        assert!(result.fell_back());
+        assert_eq!(
+            result.attempts,
+            [
+                OtpDeliveryAttempt {
+                    channel: OtpChannelKind::Sms,
+                    error: Some("Sms delivery failed: gateway timeout".to_string()),
+                },
+                OtpDeliveryAttempt {
+                    channel: OtpChannelKind::Email,
+                    error: None,
+                },
+            ]
+        );
+        let sent = email.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, ["ada@example.com"]);
+        assert!(sent[0].text_body.as_deref().unwrap().contains("482913"));
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_when_every_channel_fails() {
+        let dispatcher = OtpDispatcher::new(vec![
+            Arc::new(MockSms::default()),
+            Arc::new(EmailOtpChannel::new(
+                Arc::new(MockEmailProvider::failing("mailbox full")),
+                "no-reply@trustflow.io",
+            )),
+        ]);
+        let no_phone = OtpRecipient {
+            phone: None,
+            preferred_channels: Vec::new(),
+            ..recipient()
+        };
+
+        let Err(OtpDeliveryError::Undeliverable { attempts }) =
+            dispatcher.dispatch(&no_phone, "482913").await
+        else {
+            panic!("expected delivery to fail");
+        };
+        // SMS was skipped for want of a number
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].channel, OtpChannelKind::Email);
+    }
+}
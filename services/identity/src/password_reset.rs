@@ -0,0 +1,785 @@
+//! Forgotten-password reset
+//!
+//! [`PasswordResetService::request_reset`] looks the account up by email or
+//! phone, issues a single-use [`TokenPurpose::PasswordReset`] token and sends
+//! the user a link carrying it.  [`PasswordResetService::reset_password`]
+//! redeems the token, checks the new password against the
+//! [`PasswordPolicy`] and the account's recent passwords, stores it, revokes
+//! every session and publishes [`PasswordResetEvent::Completed`] and a
+//! [`PasswordChangedEvent`].
+//!
+//! Requesting a reset never tells the caller whether the account exists:
+//! unknown identifiers, and failures to issue or send the link, look exactly
+//! like a link that was sent.  That includes how long the answer takes, so
+//! [`PasswordResetService::request_reset`] does the lookup and the sending
+//! in a background task rather than on the request path.
+//!
+//! Signed-in users change their password with
+//! [`PasswordResetService::change_password`], which checks the current
+//! password and then applies the same policy, history and session rules.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use common::security::PasswordHasher;
+use common::value_objects::PasswordHash;
+use common::value_objects::Timestamp;
+use error::{AppError, http::AuthErrorCode};
+use infrastructure::email::{EmailMessage, EmailProvider};
+use serde::Serialize;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
+
+use crate::domain::entities::UserId;
+use crate::domain::events::{
+    EventPublisher, NullEventPublisher, PasswordChangeReason, PasswordChangedEvent,
+};
+use crate::verification_token::{TokenPurpose, VerificationTokenError, VerificationTokenService};
+
+/// How long a reset link stays valid
+pub const DEFAULT_RESET_TOKEN_TTL: Duration = Duration::minutes(30);
+
+/// Password reset errors
+#[derive(Debug, Error)]
+pub enum PasswordResetError {
+    #[error(transparent)]
+    Token(#[from] VerificationTokenError),
+
+    #[error("password does not meet the policy: {0}")]
+    WeakPassword(String),
+
+    #[error("password was used recently")]
+    PasswordReused,
+
+    #[error("current password is incorrect")]
+    WrongPassword,
+
+    #[error("password reset link could not be sent: {0}")]
+    Delivery(String),
+
+    #[error("password hashing failed: {0}")]
+    Hashing(String),
+
+    #[error("account store error: {0}")]
+    Store(String),
+
+    /// The token belongs to something that isn't a user id; only a bug or a
+    /// tampered token store gets here
+    #[error("reset token names an invalid user id: {0}")]
+    InvalidUserId(String),
+}
+
+impl From<PasswordResetError> for AppError {
+    fn from(err: PasswordResetError) -> Self {
+        match err {
+            PasswordResetError::Token(e) => e.into(),
+            PasswordResetError::WeakPassword(_) | PasswordResetError::PasswordReused => {
+                AppError::validation_with_field(err.to_string(), "new_password")
+            }
+            PasswordResetError::WrongPassword => {
+                AppError::auth(err.to_string(), AuthErrorCode::InvalidCredentials)
+            }
+            PasswordResetError::Delivery(e) => AppError::external("password_reset_delivery", e),
+            PasswordResetError::Hashing(e) => AppError::internal(e),
+            PasswordResetError::InvalidUserId(_) => AppError::internal(err.to_string()),
+            PasswordResetError::Store(e) => AppError::infrastructure("password_reset_store", e),
+        }
+    }
+}
+
+/// Rules a new password must follow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_special: bool,
+    /// Number of recent passwords, the current one included, that may not
+    /// be reused
+    pub history_count: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_special: true,
+            history_count: 12,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Check `password` against every rule but history
+    pub fn check(&self, password: &str) -> Result<(), PasswordResetError> {
+        let weak = |reason: &str| Err(PasswordResetError::WeakPassword(reason.to_string()));
+        if password.chars().count() < self.min_length {
+            return weak(&format!("must be at least {} characters", self.min_length));
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            return weak("must contain an uppercase letter");
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            return weak("must contain a lowercase letter");
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return weak("must contain a digit");
+        }
+        if self.require_special && password.chars().all(char::is_alphanumeric) {
+            return weak("must contain a special character");
+        }
+        Ok(())
+    }
+}
+
+/// Account a reset link is sent for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetAccount {
+    pub user_id: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+}
+
+/// Lifecycle events of a password reset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PasswordResetEvent {
+    Requested {
+        user_id: String,
+    },
+    Completed {
+        user_id: String,
+        #[serde(with = "time::serde::rfc3339")]
+        changed_at: OffsetDateTime,
+    },
+}
+
+/// Accounts as the reset flow needs them
+#[async_trait]
+pub trait PasswordResetAccounts: Send + Sync {
+    /// Account whose email or phone number is `identifier`
+    async fn find_by_identifier(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<ResetAccount>, PasswordResetError>;
+
+    /// Up to `limit` password hashes of the user, the current one first
+    async fn password_history(
+        &self,
+        user_id: &str,
+        limit: usize,
+    ) -> Result<Vec<PasswordHash>, PasswordResetError>;
+
+    /// Replace the password, keeping the old hash in the history
+    async fn set_password(
+        &self,
+        user_id: &str,
+        hash: &PasswordHash,
+    ) -> Result<(), PasswordResetError>;
+
+    /// Revoke every session and refresh token of the user
+    async fn revoke_sessions(&self, user_id: &str) -> Result<(), PasswordResetError>;
+}
+
+/// Sends reset links by email, SMS or whatever reaches the user
+#[async_trait]
+pub trait ResetLinkSender: Send + Sync {
+    async fn send(&self, account: &ResetAccount, link: &str) -> Result<(), PasswordResetError>;
+}
+
+/// Sends reset links by email
+pub struct EmailResetLinkSender {
+    provider: Arc<dyn EmailProvider>,
+    from: String,
+}
+
+impl EmailResetLinkSender {
+    pub fn new(provider: Arc<dyn EmailProvider>, from: impl Into<String>) -> Self {
+        Self {
+            provider,
+            from: from.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ResetLinkSender for EmailResetLinkSender {
+    async fn send(&self, account: &ResetAccount, link: &str) -> Result<(), PasswordResetError> {
+        let email = account
+            .email
+            .as_deref()
+            .ok_or_else(|| PasswordResetError::Delivery("no email address on file".into()))?;
+        let message = EmailMessage::new(&self.from, email, "Reset your TrustFlow password")
+            .with_text(format!(
+                "Use this link to choose a new password: {link}\n\n\
+                 It expires shortly and works once. If you didn't ask for it, ignore this email."
+            ));
+        self.provider
+            .send(message)
+            .await
+            .map_err(|e| PasswordResetError::Delivery(e.to_string()))
+    }
+}
+
+/// Receives [`PasswordResetEvent`]s
+#[async_trait]
+pub trait PasswordResetEventSink: Send + Sync {
+    async fn publish(&self, event: PasswordResetEvent);
+}
+
+/// Sink that drops every event
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullPasswordResetEventSink;
+
+#[async_trait]
+impl PasswordResetEventSink for NullPasswordResetEventSink {
+    async fn publish(&self, _event: PasswordResetEvent) {}
+}
+
+/// Issues reset links and resets passwords
+pub struct PasswordResetService<H> {
+    tokens: VerificationTokenService,
+    accounts: Arc<dyn PasswordResetAccounts>,
+    sender: Arc<dyn ResetLinkSender>,
+    hasher: Arc<H>,
+    events: Arc<dyn PasswordResetEventSink>,
+    domain_events: Arc<dyn EventPublisher>,
+    policy: PasswordPolicy,
+    reset_url: String,
+    ttl: Duration,
+}
+
+impl<H> Clone for PasswordResetService<H> {
+    fn clone(&self) -> Self {
+        Self {
+            tokens: self.tokens.clone(),
+            accounts: self.accounts.clone(),
+            sender: self.sender.clone(),
+            hasher: self.hasher.clone(),
+            events: self.events.clone(),
+            domain_events: self.domain_events.clone(),
+            policy: self.policy.clone(),
+            reset_url: self.reset_url.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<H: PasswordHasher> PasswordResetService<H> {
+    /// Service sending links to the page at `reset_url`, which receives the
+    /// token as its `token` query parameter
+    pub fn new(
+        tokens: VerificationTokenService,
+        accounts: Arc<dyn PasswordResetAccounts>,
+        sender: Arc<dyn ResetLinkSender>,
+        hasher: H,
+        reset_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            tokens,
+            accounts,
+            sender,
+            hasher: Arc::new(hasher),
+            events: Arc::new(NullPasswordResetEventSink),
+            domain_events: Arc::new(NullEventPublisher),
+            policy: PasswordPolicy::default(),
+            reset_url: reset_url.into(),
+            ttl: DEFAULT_RESET_TOKEN_TTL,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: PasswordPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_event_sink(mut self, events: Arc<dyn PasswordResetEventSink>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Publish a [`PasswordChangedEvent`] through `events` for every reset
+    pub fn with_event_publisher(mut self, events: Arc<dyn EventPublisher>) -> Self {
+        self.domain_events = events;
+        self
+    }
+
+    /// How long reset links stay valid
+    pub fn with_token_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Send a reset link to the account of `identifier`, if there is one
+    ///
+    /// Returns at once: the work happens in a spawned task, so the time the
+    /// request takes doesn't depend on whether the account exists.
+    pub fn request_reset(&self, identifier: &str)
+    where
+        H: 'static,
+    {
+        let service = self.clone();
+        let identifier = identifier.to_string();
+        tokio::spawn(async move {
+            service
+                .request_reset_at(&identifier, OffsetDateTime::now_utc())
+                .await
+        });
+    }
+
+    /// Send a reset link to the account of `identifier` as of `now`
+    ///
+    /// Deliberately infallible: every failure is logged, and the caller
+    /// answers the same way whether or not the account exists.
+    pub async fn request_reset_at(&self, identifier: &str, now: OffsetDateTime) {
+        if let Err(e) = self.send_link(identifier.trim(), now).await {
+            warn!(error = %e, "password reset link not sent");
+        }
+    }
+
+    async fn send_link(
+        &self,
+        identifier: &str,
+        now: OffsetDateTime,
+    ) -> Result<(), PasswordResetError> {
+        let Some(account) = self.accounts.find_by_identifier(identifier).await? else {
+            info!("password reset requested for an unknown account");
+            return Ok(());
+        };
+        let issued = self
+            .tokens
+            .issue_at(&account.user_id, TokenPurpose::PasswordReset, self.ttl, now)
+            .await?;
+        let separator = if self.reset_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let link = format!("{}{separator}token={}", self.reset_url, issued.token);
+        self.sender.send(&account, &link).await?;
+        info!(user_id = %account.user_id, "password reset link sent");
+        self.events
+            .publish(PasswordResetEvent::Requested {
+                user_id: account.user_id,
+            })
+            .await;
+        Ok(())
+    }
+
+    /// Redeem `token` and set `new_password`, returning the user id
+    pub async fn reset_password(
+        &self,
+        token: &str,
+        new_password: &str,
+    ) -> Result<String, PasswordResetError> {
+        self.reset_password_at(token, new_password, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Redeem `token` as of `now` and set `new_password`
+    ///
+    /// The token is only spent once the password is known to be acceptable,
+    /// so a user who picks a weak or reused password can try again with the
+    /// same link.
+    pub async fn reset_password_at(
+        &self,
+        token: &str,
+        new_password: &str,
+        now: OffsetDateTime,
+    ) -> Result<String, PasswordResetError> {
+        self.policy.check(new_password)?;
+        let user_id = self
+            .tokens
+            .verify_at(token, TokenPurpose::PasswordReset, now)
+            .await?;
+        let changed_user: UserId = user_id
+            .parse()
+            .map_err(|_| PasswordResetError::InvalidUserId(user_id.clone()))?;
+        self.check_history(&user_id, new_password).await?;
+        let hash = self
+            .hasher
+            .hash(new_password)
+            .map_err(|e| PasswordResetError::Hashing(e.to_string()))?;
+
+        // Another request may have redeemed the token in the meantime
+        let user_id = self
+            .tokens
+            .consume_at(token, TokenPurpose::PasswordReset, now)
+            .await?;
+        self.accounts.set_password(&user_id, &hash).await?;
+        self.accounts.revoke_sessions(&user_id).await?;
+        info!(user_id = %user_id, "password reset");
+        self.events
+            .publish(PasswordResetEvent::Completed {
+                user_id: user_id.clone(),
+                changed_at: now,
+            })
+            .await;
+        let changed = PasswordChangedEvent {
+            user_id: changed_user,
+            reason: PasswordChangeReason::Reset,
+            timestamp: Timestamp(now),
+        };
+        if let Err(e) = self.domain_events.publish(&changed).await {
+            warn!(user_id = %user_id, error = %e, "failed to publish password changed event");
+        }
+        Ok(user_id)
+    }
+
+    /// Replace the password of `user_id`, who proved they know
+    /// `current_password`, with `new_password`
+    pub async fn change_password(
+        &self,
+        user_id: &str,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), PasswordResetError> {
+        self.change_password_at(
+            user_id,
+            current_password,
+            new_password,
+            OffsetDateTime::now_utc(),
+        )
+        .await
+    }
+
+    /// Replace the password of `user_id` as of `now`, signing them out
+    /// everywhere
+    pub async fn change_password_at(
+        &self,
+        user_id: &str,
+        current_password: &str,
+        new_password: &str,
+        now: OffsetDateTime,
+    ) -> Result<(), PasswordResetError> {
+        let changed_user: UserId = user_id
+            .parse()
+            .map_err(|_| PasswordResetError::InvalidUserId(user_id.to_string()))?;
+        let current = self.accounts.password_history(user_id, 1).await?;
+        let matches = match current.first() {
+            Some(hash) => self
+                .hasher
+                .verify(current_password, hash)
+                .map_err(|e| PasswordResetError::Hashing(e.to_string()))?,
+            None => false,
+        };
+        if !matches {
+            return Err(PasswordResetError::WrongPassword);
+        }
+        self.policy.check(new_password)?;
+        self.check_history(user_id, new_password).await?;
+        let hash = self
+            .hasher
+            .hash(new_password)
+            .map_err(|e| PasswordResetError::Hashing(e.to_string()))?;
+
+        self.accounts.set_password(user_id, &hash).await?;
+        self.accounts.revoke_sessions(user_id).await?;
+        info!(user_id, "password changed");
+        let changed = PasswordChangedEvent {
+            user_id: changed_user,
+            reason: PasswordChangeReason::UserInitiated,
+            timestamp: Timestamp(now),
+        };
+        if let Err(e) = self.domain_events.publish(&changed).await {
+            warn!(user_id, error = %e, "failed to publish password changed event");
+        }
+        Ok(())
+    }
+
+    async fn check_history(&self, user_id: &str, password: &str) -> Result<(), PasswordResetError> {
+        if self.policy.history_count == 0 {
+            return Ok(());
+        }
+        let history = self
+            .accounts
+            .password_history(user_id, self.policy.history_count)
+            .await?;
+        for hash in &history {
+            match self.hasher.verify(password, hash) {
+                Ok(true) => return Err(PasswordResetError::PasswordReused),
+                Ok(false) => {}
+                // Hashes from an older scheme can't be compared; skip them
+                Err(e) => warn!(user_id, error = %e, "unverifiable password history entry"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verification_token::InMemoryVerificationTokenStore;
+    use common::security::Sha256Hasher;
+    use infrastructure::email::MockEmailProvider;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use time::macros::datetime;
+    use tokio::sync::{Notify, mpsc};
+
+    const USER_ID: &str = "0b9c6a1e-3c1f-4e8a-9d55-2f7a1c4d8e21";
+
+    /// Accounts keyed by email, with password hashes newest first
+    #[derive(Default)]
+    struct Recorder {
+        accounts: HashMap<String, ResetAccount>,
+        passwords: Mutex<HashMap<String, Vec<PasswordHash>>>,
+        revoked: Mutex<Vec<String>>,
+        events: Mutex<Vec<PasswordResetEvent>>,
+    }
+
+    #[async_trait]
+    impl PasswordResetAccounts for Recorder {
+        async fn find_by_identifier(
+            &self,
+            identifier: &str,
+        ) -> Result<Option<ResetAccount>, PasswordResetError> {
+            Ok(self.accounts.get(identifier).cloned())
+        }
+
+        async fn password_history(
+            &self,
+            user_id: &str,
+            limit: usize,
+        ) -> Result<Vec<PasswordHash>, PasswordResetError> {
+            let passwords = self.passwords.lock().unwrap();
+            let history = passwords
+                .get(user_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            Ok(history.iter().take(limit).cloned().collect())
+        }
+
+        async fn set_password(
+            &self,
+            user_id: &str,
+            hash: &PasswordHash,
+        ) -> Result<(), PasswordResetError> {
+            let mut passwords = self.passwords.lock().unwrap();
+            passwords
+                .entry(user_id.to_string())
+                .or_default()
+                .insert(0, hash.clone());
+            Ok(())
+        }
+
+        async fn revoke_sessions(&self, user_id: &str) -> Result<(), PasswordResetError> {
+            self.revoked.lock().unwrap().push(user_id.to_string());
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl PasswordResetEventSink for Recorder {
+        async fn publish(&self, event: PasswordResetEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    fn service() -> (
+        PasswordResetService<Sha256Hasher>,
+        Arc<Recorder>,
+        MockEmailProvider,
+    ) {
+        let ada = ResetAccount {
+            user_id: USER_ID.into(),
+            email: Some("ada@example.com".into()),
+            phone: None,
+        };
+        // Imported before user ids were UUIDs
+        let legacy = ResetAccount {
+            user_id: "legacy-7".into(),
+            email: Some("legacy@example.com".into()),
+            phone: None,
+        };
+        let recorder = Arc::new(Recorder {
+            accounts: HashMap::from([
+                ("ada@example.com".to_string(), ada),
+                ("legacy@example.com".to_string(), legacy),
+            ]),
+            ..Default::default()
+        });
+        let old = Sha256Hasher.hash("Old-passw0rd").unwrap();
+        recorder
+            .passwords
+            .lock()
+            .unwrap()
+            .insert(USER_ID.into(), vec![old]);
+
+        let email = MockEmailProvider::new();
+        let service = PasswordResetService::new(
+            VerificationTokenService::new(InMemoryVerificationTokenStore::new()),
+            recorder.clone(),
+            Arc::new(EmailResetLinkSender::new(
+                Arc::new(email.clone()),
+                "no-reply@trustflow.io",
+            )),
+            Sha256Hasher,
+            "https://app.trustflow.io/reset-password",
+        )
+        .with_event_sink(recorder.clone());
+        (service, recorder, email)
+    }
+
+    /// Token of the reset link in the last email sent
+    fn emailed_token(email: &MockEmailProvider) -> String {
+        let sent = email.sent();
+        let body = sent.last().unwrap().text_body.clone().unwrap();
+        let (_, rest) = body.split_once("?token=").unwrap();
+        rest.split_whitespace().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_valid_reset_sets_password_and_revokes_sessions() {
+        let (service, recorder, email) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        service.request_reset_at(" ada@example.com ", now).await;
+        let token = emailed_token(&email);
+
+        // Policy and history failures leave the link usable
+        assert!(matches!(
+            service.reset_password_at(&token, "short", now).await,
+            Err(PasswordResetError::WeakPassword(_))
+        ));
+        assert!(matches!(
+            service.reset_password_at(&token, "Old-passw0rd", now).await,
+            Err(PasswordResetError::PasswordReused)
+        ));
+
+        let user_id = service
+            .reset_password_at(&token, "New-passw0rd", now + Duration::minutes(5))
+            .await
+            .unwrap();
+        assert_eq!(user_id, USER_ID);
+        let current = recorder.passwords.lock().unwrap()[USER_ID][0].clone();
+        assert!(Sha256Hasher.verify("New-passw0rd", &current).unwrap());
+        assert_eq!(*recorder.revoked.lock().unwrap(), [USER_ID]);
+        assert_eq!(
+            recorder.events.lock().unwrap().last(),
+            Some(&PasswordResetEvent::Completed {
+                user_id: USER_ID.into(),
+                changed_at: now + Duration::minutes(5),
+            })
+        );
+
+        // The link works once
+        assert!(matches!(
+            service
+                .reset_password_at(&token, "Other-passw0rd", now)
+                .await,
+            Err(PasswordResetError::Token(VerificationTokenError::Invalid))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let (service, recorder, email) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        service.request_reset_at("ada@example.com", now).await;
+        let token = emailed_token(&email);
+
+        assert!(matches!(
+            service
+                .reset_password_at(&token, "New-passw0rd", now + DEFAULT_RESET_TOKEN_TTL)
+                .await,
+            Err(PasswordResetError::Token(VerificationTokenError::Expired))
+        ));
+        assert!(recorder.revoked.lock().unwrap().is_empty());
+        assert_eq!(recorder.passwords.lock().unwrap()[USER_ID].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_identifier_does_not_reveal_existence() {
+        let (service, recorder, email) = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+
+        // Same (unit) answer whether or not the account exists; only the
+        // real account gets mail
+        let () = service.request_reset_at("nobody@example.com", now).await;
+        assert!(email.sent().is_empty());
+        assert!(recorder.events.lock().unwrap().is_empty());
+
+        let () = service.request_reset_at("ada@example.com", now).await;
+        assert_eq!(email.sent().len(), 1);
+        assert_eq!(email.sent()[0].to, ["ada@example.com"]);
+    }
+
+    /// Sends once released, reporting each link it sent
+    struct GatedSender {
+        release: Notify,
+        sent: mpsc::UnboundedSender<String>,
+    }
+
+    #[async_trait]
+    impl ResetLinkSender for GatedSender {
+        async fn send(
+            &self,
+            account: &ResetAccount,
+            _link: &str,
+        ) -> Result<(), PasswordResetError> {
+            self.release.notified().await;
+            self.sent.send(account.user_id.clone()).unwrap();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_reset_sends_off_the_request_path() {
+        let (_, recorder, _) = service();
+        let (sent, mut links) = mpsc::unbounded_channel();
+        let sender = Arc::new(GatedSender {
+            release: Notify::new(),
+            sent,
+        });
+        let service = PasswordResetService::new(
+            VerificationTokenService::new(InMemoryVerificationTokenStore::new()),
+            recorder,
+            sender.clone(),
+            Sha256Hasher,
+            "https://app.trustflow.io/reset-password",
+        );
+
+        // Returns while the send for a real account is still blocked
+        service.request_reset("ada@example.com");
+        service.request_reset("nobody@example.com");
+        assert!(links.try_recv().is_err());
+
+        sender.release.notify_one();
+        assert_eq!(links.recv().await.unwrap(), USER_ID);
+    }
+
+    #[tokio::test]
+    async fn test_reset_publishes_password_changed_and_rejects_invalid_user_ids() {
+        use crate::domain::events::RecordingPublisher;
+
+        let (service, recorder, email) = service();
+        let published = Arc::new(RecordingPublisher::default());
+        let service = service.with_event_publisher(published.clone());
+        let now = datetime!(2024-05-01 12:00 UTC);
+
+        service.request_reset_at("legacy@example.com", now).await;
+        let token = emailed_token(&email);
+        assert!(matches!(
+            service.reset_password_at(&token, "New-passw0rd", now).await,
+            Err(PasswordResetError::InvalidUserId(_))
+        ));
+        assert!(recorder.revoked.lock().unwrap().is_empty());
+
+        service.request_reset_at("ada@example.com", now).await;
+        let token = emailed_token(&email);
+        service
+            .reset_password_at(&token, "New-passw0rd", now)
+            .await
+            .unwrap();
+        assert_eq!(
+            *published.0.lock().unwrap(),
+            [("user.password_changed".to_string(), USER_ID.to_string())]
+        );
+    }
+}
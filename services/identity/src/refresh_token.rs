@@ -0,0 +1,278 @@
+//! Device-bound refresh tokens
+//!
+//! A refresh token records a fingerprint of the device it was issued to.
+//! Presenting it from any other device is rejected with
+//! [`RefreshTokenError::DeviceMismatch`], which callers treat as a stolen
+//! token: the session should be revoked and a suspicious-activity event
+//! raised.
+//!
+//! Tokens are `base64url(claims).base64url(hmac-sha256(claims))`; only a
+//! hash of the fingerprint is embedded, never the raw device identifiers.
+//! Sign them with a key of their own: an access-token (JWT) secret leaking
+//! must not also let refresh tokens be forged.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::security::hashing::{
+    hex,
+    hmac::{Hmac, Mac},
+    sha2::{Digest, Sha256},
+};
+use error::{AppError, http::AuthErrorCode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Default refresh token lifetime
+pub const DEFAULT_REFRESH_TTL: Duration = Duration::days(7);
+
+/// Refresh token errors
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RefreshTokenError {
+    #[error("malformed refresh token")]
+    Malformed,
+
+    #[error("invalid refresh token signature")]
+    InvalidSignature,
+
+    #[error("refresh token expired")]
+    Expired,
+
+    #[error("refresh token presented from a different device")]
+    DeviceMismatch { user_id: String, session_id: String },
+}
+
+impl RefreshTokenError {
+    /// Whether the failure indicates a stolen token rather than a stale one
+    pub fn is_suspicious(&self) -> bool {
+        matches!(self, Self::DeviceMismatch { .. })
+    }
+}
+
+impl From<RefreshTokenError> for AppError {
+    fn from(err: RefreshTokenError) -> Self {
+        let code = match err {
+            RefreshTokenError::Expired => AuthErrorCode::TokenExpired,
+            _ => AuthErrorCode::TokenInvalid,
+        };
+        AppError::auth(err.to_string(), code)
+    }
+}
+
+/// Stable fingerprint of the device a token is bound to
+///
+/// Only attributes that survive an app, browser or OS update go into it:
+/// the device id and the user agent with its version numbers stripped.
+/// Hashing the full user agent would log users out, and report them as
+/// token thieves, every time their browser updated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceFingerprint(String);
+
+impl DeviceFingerprint {
+    /// Fingerprint from the client-supplied device id and user agent
+    pub fn new(device_id: &str, user_agent: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(device_id.trim().as_bytes());
+        hasher.update([0]);
+        hasher.update(user_agent_family(user_agent).as_bytes());
+        Self(hex::encode(hasher.finalize()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// `user_agent` without its versions: `TrustFlow/2.1 (Android 14)` and
+/// `TrustFlow/2.2 (Android 15)` are the same family
+fn user_agent_family(user_agent: &str) -> String {
+    user_agent
+        .split_whitespace()
+        .map(|token| {
+            let token = token.split('/').next().unwrap_or_default();
+            token.trim_end_matches(|c: char| {
+                c.is_ascii_digit() || matches!(c, '.' | '_' | ';' | ')')
+            })
+        })
+        .filter(|token| !token.is_empty() && *token != "(")
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Claims carried by a refresh token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshClaims {
+    pub sub: String,
+    pub session_id: String,
+    /// [`DeviceFingerprint`] the token was issued to
+    pub device: String,
+    /// Expiry as a unix timestamp
+    pub exp: i64,
+}
+
+/// Issues and verifies device-bound refresh tokens
+#[derive(Clone)]
+pub struct RefreshTokenSigner {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl RefreshTokenSigner {
+    /// Signer using `secret`, which must not be shared with any other token
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().to_vec(),
+            ttl: DEFAULT_REFRESH_TTL,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Issue a token for `user_id`'s session, bound to `device`
+    pub fn issue(&self, user_id: &str, session_id: &str, device: &DeviceFingerprint) -> String {
+        let claims = RefreshClaims {
+            sub: user_id.to_string(),
+            session_id: session_id.to_string(),
+            device: device.as_str().to_string(),
+            exp: (OffsetDateTime::now_utc() + self.ttl).unix_timestamp(),
+        };
+        let payload = URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(&claims).expect("refresh claims always serialize"));
+        let signature =
+            URL_SAFE_NO_PAD.encode(self.mac(payload.as_bytes()).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// Verify `token` and that it is presented from the device it was issued to
+    pub fn verify(
+        &self,
+        token: &str,
+        presenting: &DeviceFingerprint,
+    ) -> Result<RefreshClaims, RefreshTokenError> {
+        let (payload, signature) = token.split_once('.').ok_or(RefreshTokenError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| RefreshTokenError::Malformed)?;
+        self.mac(payload.as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| RefreshTokenError::InvalidSignature)?;
+
+        let claims: RefreshClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+            .ok_or(RefreshTokenError::Malformed)?;
+
+        if claims.exp <= OffsetDateTime::now_utc().unix_timestamp() {
+            return Err(RefreshTokenError::Expired);
+        }
+        if claims.device != presenting.as_str() {
+            warn!(
+                user_id = %claims.sub,
+                session_id = %claims.session_id,
+                "refresh token presented from a different device"
+            );
+            return Err(RefreshTokenError::DeviceMismatch {
+                user_id: claims.sub,
+                session_id: claims.session_id,
+            });
+        }
+        Ok(claims)
+    }
+
+    fn mac(&self, data: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHONE: (&str, &str) = ("device-phone", "TrustFlow/2.1 (Android 14)");
+    const LAPTOP: (&str, &str) = ("device-laptop", "Mozilla/5.0 (X11; Linux x86_64)");
+
+    #[test]
+    fn test_refresh_from_issuing_device_succeeds() {
+        let signer = RefreshTokenSigner::new("refresh-secret");
+        let phone = DeviceFingerprint::new(PHONE.0, PHONE.1);
+        let token = signer.issue("user-1", "session-1", &phone);
+
+        let claims = signer.verify(&token, &phone).unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.session_id, "session-1");
+    }
+
+    #[test]
+    fn test_refresh_from_mismatched_device_is_rejected() {
+        let signer = RefreshTokenSigner::new("refresh-secret");
+        let token = signer.issue(
+            "user-1",
+            "session-1",
+            &DeviceFingerprint::new(PHONE.0, PHONE.1),
+        );
+
+        let err = signer
+            .verify(&token, &DeviceFingerprint::new(LAPTOP.0, LAPTOP.1))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RefreshTokenError::DeviceMismatch {
+                user_id: "user-1".to_string(),
+                session_id: "session-1".to_string(),
+            }
+        );
+        assert!(err.is_suspicious());
+    }
+
+    #[test]
+    fn test_tampered_and_expired_tokens_are_rejected() {
+        let signer = RefreshTokenSigner::new("refresh-secret");
+        let phone = DeviceFingerprint::new(PHONE.0, PHONE.1);
+
+        let token = signer.issue("user-1", "session-1", &phone);
+        let forged = RefreshTokenSigner::new("other-secret").issue("user-1", "session-1", &phone);
+        let (_, forged_signature) = forged.split_once('.').unwrap();
+        let (payload, _) = token.split_once('.').unwrap();
+        assert_eq!(
+            signer.verify(&format!("{payload}.{forged_signature}"), &phone),
+            Err(RefreshTokenError::InvalidSignature)
+        );
+
+        let expired =
+            signer
+                .clone()
+                .with_ttl(Duration::seconds(-1))
+                .issue("user-1", "session-1", &phone);
+        assert_eq!(
+            signer.verify(&expired, &phone),
+            Err(RefreshTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_survives_app_and_os_updates_but_not_a_new_platform() {
+        let phone = DeviceFingerprint::new(PHONE.0, PHONE.1);
+        assert_eq!(
+            DeviceFingerprint::new(PHONE.0, "TrustFlow/2.2 (Android 15)"),
+            phone
+        );
+        assert_eq!(
+            DeviceFingerprint::new(LAPTOP.0, "Mozilla/5.0 (X11; Linux x86_64)"),
+            DeviceFingerprint::new(LAPTOP.0, "Mozilla/5.1 (X11; Linux x86_64) ")
+        );
+        assert_ne!(
+            DeviceFingerprint::new(PHONE.0, "TrustFlow/2.1 (iOS 17)"),
+            phone
+        );
+        assert_ne!(DeviceFingerprint::new(LAPTOP.0, PHONE.1), phone);
+    }
+}
@@ -0,0 +1,407 @@
+//! Single-use, expiring verification tokens
+//!
+//! Links sent to a user's email or phone (password resets, address
+//! confirmation) carry a random token that proves the user received the
+//! message.  [`VerificationTokenService`] issues them for a purpose and a
+//! subject, usually a user id, and redeems each at most once.
+//!
+//! Only a SHA-256 digest of the token is stored, so a leaked store can't be
+//! used to take over accounts.  The digest covers the purpose as well, so a
+//! token issued for one flow is unknown to every other.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use common::security::RandomGenerator;
+use error::AppError;
+use infrastructure::redis::{RedisError, RedisKey, RedisPool};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+/// Length of issued tokens in hex characters (256 bits)
+const TOKEN_LENGTH: usize = 64;
+
+/// Expired tokens are kept this long so users get "expired" rather than
+/// "invalid"
+const EXPIRED_RETENTION: Duration = Duration::hours(24);
+
+/// What a token may be redeemed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+    PhoneVerification,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PasswordReset => "password_reset",
+            Self::EmailVerification => "email_verification",
+            Self::PhoneVerification => "phone_verification",
+        }
+    }
+}
+
+/// Verification token errors
+#[derive(Debug, Error)]
+pub enum VerificationTokenError {
+    /// Unknown, already used, or issued for another purpose
+    #[error("verification token is invalid")]
+    Invalid,
+
+    #[error("verification token has expired")]
+    Expired,
+
+    #[error("verification token store error: {0}")]
+    Store(#[from] RedisError),
+}
+
+impl From<VerificationTokenError> for AppError {
+    fn from(err: VerificationTokenError) -> Self {
+        match err {
+            VerificationTokenError::Store(e) => {
+                AppError::infrastructure("verification_token_store", e.to_string())
+            }
+            other => AppError::validation_with_field(other.to_string(), "token"),
+        }
+    }
+}
+
+/// A freshly issued token; the plaintext is only ever available here
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuedToken {
+    pub token: String,
+    pub expires_at: OffsetDateTime,
+}
+
+/// What is stored for a token, under its digest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenRecord {
+    pub subject: String,
+    pub purpose: TokenPurpose,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+/// Storage for token records, keyed by token digest
+#[async_trait]
+pub trait VerificationTokenStore: Send + Sync {
+    /// Store `record`; the store may drop it once `retain_until` has passed
+    async fn insert(
+        &self,
+        digest: &str,
+        record: &TokenRecord,
+        retain_until: OffsetDateTime,
+    ) -> Result<(), VerificationTokenError>;
+
+    async fn get(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError>;
+
+    /// Atomically remove and return the record, so it's returned at most once
+    async fn take(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError>;
+}
+
+/// Issues and redeems verification tokens
+#[derive(Clone)]
+pub struct VerificationTokenService {
+    store: Arc<dyn VerificationTokenStore>,
+}
+
+impl VerificationTokenService {
+    pub fn new(store: impl VerificationTokenStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    /// Issue a token for `subject` valid for `ttl`
+    pub async fn issue(
+        &self,
+        subject: &str,
+        purpose: TokenPurpose,
+        ttl: Duration,
+    ) -> Result<IssuedToken, VerificationTokenError> {
+        self.issue_at(subject, purpose, ttl, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Issue a token for `subject` valid for `ttl` from `now`
+    pub async fn issue_at(
+        &self,
+        subject: &str,
+        purpose: TokenPurpose,
+        ttl: Duration,
+        now: OffsetDateTime,
+    ) -> Result<IssuedToken, VerificationTokenError> {
+        let token = RandomGenerator::hex(TOKEN_LENGTH);
+        let record = TokenRecord {
+            subject: subject.to_string(),
+            purpose,
+            expires_at: now + ttl,
+        };
+        self.store
+            .insert(
+                &digest(&token, purpose),
+                &record,
+                record.expires_at + EXPIRED_RETENTION,
+            )
+            .await?;
+        Ok(IssuedToken {
+            token,
+            expires_at: record.expires_at,
+        })
+    }
+
+    /// Subject of a redeemable `token`, leaving it redeemable
+    pub async fn verify_at(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+        now: OffsetDateTime,
+    ) -> Result<String, VerificationTokenError> {
+        let record = self.store.get(&digest(token, purpose)).await?;
+        check(record, now)
+    }
+
+    /// Redeem `token`, returning its subject
+    pub async fn consume(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<String, VerificationTokenError> {
+        self.consume_at(token, purpose, OffsetDateTime::now_utc())
+            .await
+    }
+
+    /// Redeem `token` as of `now`, returning its subject
+    pub async fn consume_at(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+        now: OffsetDateTime,
+    ) -> Result<String, VerificationTokenError> {
+        let record = self.store.take(&digest(token, purpose)).await?;
+        check(record, now)
+    }
+}
+
+fn check(
+    record: Option<TokenRecord>,
+    now: OffsetDateTime,
+) -> Result<String, VerificationTokenError> {
+    let record = record.ok_or(VerificationTokenError::Invalid)?;
+    if record.expires_at <= now {
+        return Err(VerificationTokenError::Expired);
+    }
+    Ok(record.subject)
+}
+
+fn digest(token: &str, purpose: TokenPurpose) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(purpose.as_str());
+    hasher.update(b":");
+    hasher.update(token.trim());
+    hex::encode(hasher.finalize())
+}
+
+/// In-memory store for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryVerificationTokenStore {
+    records: Arc<Mutex<HashMap<String, TokenRecord>>>,
+}
+
+impl InMemoryVerificationTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl VerificationTokenStore for InMemoryVerificationTokenStore {
+    async fn insert(
+        &self,
+        digest: &str,
+        record: &TokenRecord,
+        _retain_until: OffsetDateTime,
+    ) -> Result<(), VerificationTokenError> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(digest.to_string(), record.clone());
+        Ok(())
+    }
+
+    async fn get(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(digest)
+            .cloned())
+    }
+
+    async fn take(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(digest))
+    }
+}
+
+/// Redis-backed store; each record is JSON under `prefix:verification-token:DIGEST`
+#[derive(Clone)]
+pub struct RedisVerificationTokenStore {
+    pool: RedisPool,
+    prefix: String,
+}
+
+impl RedisVerificationTokenStore {
+    pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
+        Self {
+            pool,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, digest: &str) -> RedisKey {
+        RedisKey::with_prefix(&self.prefix, ["verification-token", digest])
+    }
+
+    fn parse(json: Option<String>) -> Result<Option<TokenRecord>, VerificationTokenError> {
+        json.map(|json| {
+            serde_json::from_str(&json)
+                .map_err(|e| RedisError::Command(format!("invalid token record: {e}")).into())
+        })
+        .transpose()
+    }
+}
+
+#[async_trait]
+impl VerificationTokenStore for RedisVerificationTokenStore {
+    async fn insert(
+        &self,
+        digest: &str,
+        record: &TokenRecord,
+        retain_until: OffsetDateTime,
+    ) -> Result<(), VerificationTokenError> {
+        let json = serde_json::to_string(record).map_err(|e| RedisError::Command(e.to_string()))?;
+        let ttl_ms = (retain_until - OffsetDateTime::now_utc())
+            .whole_milliseconds()
+            .max(1);
+        let mut conn = self.pool.connection().await?;
+        redis::cmd("SET")
+            .arg(self.key(digest).as_str())
+            .arg(json)
+            .arg("PX")
+            .arg(ttl_ms as u64)
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(RedisError::from)?;
+        Ok(())
+    }
+
+    async fn get(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError> {
+        let mut conn = self.pool.connection().await?;
+        let json: Option<String> = redis::cmd("GET")
+            .arg(self.key(digest).as_str())
+            .query_async(&mut conn)
+            .await
+            .map_err(RedisError::from)?;
+        Self::parse(json)
+    }
+
+    async fn take(&self, digest: &str) -> Result<Option<TokenRecord>, VerificationTokenError> {
+        let mut conn = self.pool.connection().await?;
+        let json: Option<String> = redis::cmd("GETDEL")
+            .arg(self.key(digest).as_str())
+            .query_async(&mut conn)
+            .await
+            .map_err(RedisError::from)?;
+        Self::parse(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn service() -> VerificationTokenService {
+        VerificationTokenService::new(InMemoryVerificationTokenStore::new())
+    }
+
+    #[tokio::test]
+    async fn test_token_is_redeemable_once_for_its_purpose() {
+        let tokens = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        let issued = tokens
+            .issue_at(
+                "user-1",
+                TokenPurpose::PasswordReset,
+                Duration::minutes(30),
+                now,
+            )
+            .await
+            .unwrap();
+        assert_eq!(issued.token.len(), TOKEN_LENGTH);
+        assert_eq!(issued.expires_at, datetime!(2024-05-01 12:30 UTC));
+
+        assert!(matches!(
+            tokens
+                .consume_at(&issued.token, TokenPurpose::EmailVerification, now)
+                .await,
+            Err(VerificationTokenError::Invalid)
+        ));
+        assert_eq!(
+            tokens
+                .verify_at(&issued.token, TokenPurpose::PasswordReset, now)
+                .await
+                .unwrap(),
+            "user-1"
+        );
+        assert_eq!(
+            tokens
+                .consume_at(&issued.token, TokenPurpose::PasswordReset, now)
+                .await
+                .unwrap(),
+            "user-1"
+        );
+        assert!(matches!(
+            tokens
+                .consume_at(&issued.token, TokenPurpose::PasswordReset, now)
+                .await,
+            Err(VerificationTokenError::Invalid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_rejected() {
+        let tokens = service();
+        let now = datetime!(2024-05-01 12:00 UTC);
+        let issued = tokens
+            .issue_at(
+                "user-1",
+                TokenPurpose::PasswordReset,
+                Duration::minutes(30),
+                now,
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            tokens
+                .consume_at(
+                    &issued.token,
+                    TokenPurpose::PasswordReset,
+                    now + Duration::minutes(30)
+                )
+                .await,
+            Err(VerificationTokenError::Expired)
+        ));
+    }
+}
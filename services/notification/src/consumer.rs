@@ -0,0 +1,128 @@
+//! Domain event consumer
+//!
+//! [`NotificationConsumer`] turns each consumed event into the
+//! notifications its [`EventNotificationRules`] call for and sends them
+//! through the [`NotificationDispatcher`], which skips the ones a
+//! redelivered event already sent.  Every consumed event records its lag
+//! since publishing.
+
+use common::events::EventEnvelope;
+use error::AppError;
+use tracing::debug;
+
+use crate::dispatch::{DispatchOutcome, NotificationDispatcher};
+use crate::rules::EventNotificationRules;
+
+/// Sends the notifications domain events are mapped to
+#[derive(Clone)]
+pub struct NotificationConsumer {
+    rules: EventNotificationRules,
+    dispatcher: NotificationDispatcher,
+}
+
+impl NotificationConsumer {
+    pub fn new(rules: EventNotificationRules, dispatcher: NotificationDispatcher) -> Self {
+        Self { rules, dispatcher }
+    }
+
+    /// Send every notification `event` is mapped to
+    ///
+    /// Stops at the first failed send; the event should then be redelivered,
+    /// and the notifications already sent are skipped as duplicates.
+    pub async fn handle(&self, event: &EventEnvelope) -> Result<Vec<DispatchOutcome>, AppError> {
+        event.record_consumed();
+        let notifications = self.rules.notifications(event)?;
+        if notifications.is_empty() {
+            debug!(event_type = %event.event_type, "no notification rule for event");
+        }
+
+        let mut outcomes = Vec::with_capacity(notifications.len());
+        for notification in &notifications {
+            outcomes.push(self.dispatcher.dispatch(notification).await?);
+        }
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::{InMemoryNotificationDedup, Notification, NotificationChannel};
+    use crate::testing::RecordingSender;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const RULES: &str = r#"{
+        "verification.approved": [{
+            "recipient": "user_id",
+            "channels": ["email", "in_app"],
+            "subject": "You're verified",
+            "body": "Hi {first_name}, your {level} verification was approved."
+        }]
+    }"#;
+
+    #[tokio::test]
+    async fn test_verification_approved_sends_mapped_notifications() {
+        let sender = Arc::new(RecordingSender::default());
+        let consumer = NotificationConsumer::new(
+            EventNotificationRules::from_json(RULES).unwrap(),
+            NotificationDispatcher::new(sender.clone(), Arc::new(InMemoryNotificationDedup::new())),
+        );
+        let event = EventEnvelope::new(
+            "verification.approved",
+            json!({ "user_id": "user-9", "first_name": "Ada", "level": "tier_2" }),
+        );
+
+        let outcomes = consumer.handle(&event).await.unwrap();
+        assert_eq!(outcomes, vec![DispatchOutcome::Sent; 2]);
+        let sent = sender.sent.lock().unwrap().clone();
+        let expected = |channel| Notification {
+            event_id: event.event_id.clone(),
+            recipient: "user-9".into(),
+            channel,
+            subject: Some("You're verified".into()),
+            body: "Hi Ada, your tier_2 verification was approved.".into(),
+        };
+        assert_eq!(
+            sent,
+            vec![
+                expected(NotificationChannel::Email),
+                expected(NotificationChannel::InApp)
+            ]
+        );
+
+        // Unmapped events send nothing; a payload missing a templated field
+        // is an error rather than a half-filled message
+        let unmapped = EventEnvelope::new("order.created", json!({ "user_id": "user-9" }));
+        assert!(consumer.handle(&unmapped).await.unwrap().is_empty());
+        let incomplete = EventEnvelope::new("verification.approved", json!({ "user_id": "user-9" }));
+        let err = consumer.handle(&incomplete).await.unwrap_err();
+        assert!(err.to_string().contains("first_name"), "{err}");
+        assert_eq!(sender.sent.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_consuming_an_event_records_a_lag_sample() {
+        use metrics_exporter_prometheus::PrometheusBuilder;
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let consumer = NotificationConsumer::new(
+            EventNotificationRules::new(),
+            NotificationDispatcher::new(
+                Arc::new(RecordingSender::default()),
+                Arc::new(InMemoryNotificationDedup::new()),
+            ),
+        );
+        let event = EventEnvelope::new("order.created", json!({ "user_id": "user-9" })).published();
+        consumer.handle(&event).await.unwrap();
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains(r#"event_consume_lag_seconds_count{event_type="order.created"} 1"#),
+            "{rendered}"
+        );
+    }
+}
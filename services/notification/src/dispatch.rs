@@ -0,0 +1,304 @@
+//! Notification dispatch
+//!
+//! Domain events reach this service at least once: a consumer that crashes
+//! before acknowledging gets the event again, and the outbox relay may
+//! publish a row twice.  [`NotificationDispatcher`] therefore remembers each
+//! `(event_id, recipient, channel)` it sent for a retention window and skips
+//! repeats, so a redelivered `escrow.released` doesn't text the seller
+//! twice.  One event still fans out to every recipient and channel it
+//! targets; only the exact same triple is deduplicated.
+//!
+//! A send that fails releases its dedup entry, so the redelivery that
+//! follows gets another try.  If the dedup store itself is unreachable the
+//! notification is sent anyway: a duplicate is a lesser evil than a
+//! notification that never arrives.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use error::AppError;
+use infrastructure::redis::{RedisKey, RedisPool};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// How long a sent notification is remembered unless configured otherwise
+pub const DEFAULT_DEDUP_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Channel a notification is delivered over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email,
+    Sms,
+    Push,
+    InApp,
+}
+
+impl fmt::Display for NotificationChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Email => "email",
+            Self::Sms => "sms",
+            Self::Push => "push",
+            Self::InApp => "in_app",
+        })
+    }
+}
+
+/// One message to one recipient over one channel, caused by an event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    /// Id of the domain event the notification is for
+    pub event_id: String,
+    /// User id the notification is addressed to
+    pub recipient: String,
+    pub channel: NotificationChannel,
+    pub subject: Option<String>,
+    pub body: String,
+}
+
+impl Notification {
+    /// Identity of the notification for deduplication
+    pub fn dedup_key(&self) -> String {
+        format!("{}:{}:{}", self.event_id, self.recipient, self.channel)
+    }
+}
+
+/// What [`NotificationDispatcher::dispatch`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchOutcome {
+    Sent,
+    /// Already sent within the retention window; nothing was sent
+    Duplicate,
+}
+
+/// Delivers notifications over their channel
+#[async_trait]
+pub trait NotificationSender: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<(), AppError>;
+}
+
+/// Remembers which notifications were sent
+#[async_trait]
+pub trait NotificationDedup: Send + Sync {
+    /// Record `key` for `retention`; `false` if it was already recorded
+    async fn first_delivery(&self, key: &str, retention: Duration) -> Result<bool, AppError>;
+
+    /// Drop `key`, so the notification can be sent again
+    async fn forget(&self, key: &str) -> Result<(), AppError>;
+}
+
+/// Dedup entries as Redis keys expiring with the retention window
+#[derive(Clone)]
+pub struct RedisNotificationDedup {
+    pool: RedisPool,
+    prefix: String,
+}
+
+impl RedisNotificationDedup {
+    pub fn new(pool: RedisPool, prefix: impl Into<String>) -> Self {
+        Self {
+            pool,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, key: &str) -> RedisKey {
+        RedisKey::with_prefix(&self.prefix, ["notification-sent", key])
+    }
+}
+
+#[async_trait]
+impl NotificationDedup for RedisNotificationDedup {
+    async fn first_delivery(&self, key: &str, retention: Duration) -> Result<bool, AppError> {
+        let mut conn = self.pool.connection().await?;
+        let set: Option<String> = redis::cmd("SET")
+            .arg(self.key(key).as_str())
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg((retention.as_millis() as u64).max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| AppError::infrastructure("notification_dedup", e.to_string()))?;
+        Ok(set.is_some())
+    }
+
+    async fn forget(&self, key: &str) -> Result<(), AppError> {
+        let mut conn = self.pool.connection().await?;
+        redis::cmd("DEL")
+            .arg(self.key(key).as_str())
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(|e| AppError::infrastructure("notification_dedup", e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// In-memory dedup for tests and single-node development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryNotificationDedup {
+    /// Expiry of each recorded key
+    sent: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl InMemoryNotificationDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NotificationDedup for InMemoryNotificationDedup {
+    async fn first_delivery(&self, key: &str, retention: Duration) -> Result<bool, AppError> {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        sent.retain(|_, expires_at| *expires_at > now);
+        if sent.contains_key(key) {
+            return Ok(false);
+        }
+        sent.insert(key.to_string(), now + retention);
+        Ok(true)
+    }
+
+    async fn forget(&self, key: &str) -> Result<(), AppError> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Ok(())
+    }
+}
+
+/// Sends notifications, skipping ones already sent
+#[derive(Clone)]
+pub struct NotificationDispatcher {
+    sender: Arc<dyn NotificationSender>,
+    dedup: Arc<dyn NotificationDedup>,
+    retention: Duration,
+}
+
+impl NotificationDispatcher {
+    pub fn new(sender: Arc<dyn NotificationSender>, dedup: Arc<dyn NotificationDedup>) -> Self {
+        Self {
+            sender,
+            dedup,
+            retention: DEFAULT_DEDUP_RETENTION,
+        }
+    }
+
+    /// Remember sent notifications for `retention` (a day by default); it
+    /// should exceed how late a redelivery can arrive
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Send `notification` unless it was already sent within the retention
+    /// window
+    pub async fn dispatch(&self, notification: &Notification) -> Result<DispatchOutcome, AppError> {
+        let key = notification.dedup_key();
+        match self.dedup.first_delivery(&key, self.retention).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(key = %key, "skipping duplicate notification");
+                return Ok(DispatchOutcome::Duplicate);
+            }
+            Err(e) => {
+                warn!(key = %key, error = %e, "notification dedup unavailable, sending anyway");
+            }
+        }
+
+        if let Err(e) = self.sender.send(notification).await {
+            if let Err(forget) = self.dedup.forget(&key).await {
+                warn!(key = %key, error = %forget, "failed to release dedup entry of unsent notification");
+            }
+            return Err(e);
+        }
+        Ok(DispatchOutcome::Sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RecordingSender;
+    use std::sync::atomic::AtomicUsize;
+
+    fn released(recipient: &str, channel: NotificationChannel) -> Notification {
+        Notification {
+            event_id: "evt_escrow_released_42".into(),
+            recipient: recipient.into(),
+            channel,
+            subject: None,
+            body: "Funds for order 42 have been released".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_same_event_delivered_twice_is_sent_once() {
+        let sender = Arc::new(RecordingSender::default());
+        let dispatcher =
+            NotificationDispatcher::new(sender.clone(), Arc::new(InMemoryNotificationDedup::new()));
+
+        let seller_sms = released("seller-1", NotificationChannel::Sms);
+        assert_eq!(
+            dispatcher.dispatch(&seller_sms).await.unwrap(),
+            DispatchOutcome::Sent
+        );
+        assert_eq!(
+            dispatcher.dispatch(&seller_sms).await.unwrap(),
+            DispatchOutcome::Duplicate
+        );
+        // Other channels and recipients of the same event still go out
+        for other in [
+            released("seller-1", NotificationChannel::Email),
+            released("buyer-7", NotificationChannel::Sms),
+        ] {
+            assert_eq!(
+                dispatcher.dispatch(&other).await.unwrap(),
+                DispatchOutcome::Sent
+            );
+        }
+
+        let sent = sender.sent.lock().unwrap();
+        assert_eq!(sent.iter().filter(|n| **n == seller_sms).count(), 1);
+        assert_eq!(sent.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_can_be_retried_on_redelivery() {
+        let sender = Arc::new(RecordingSender {
+            failures: AtomicUsize::new(1),
+            ..Default::default()
+        });
+        let dispatcher =
+            NotificationDispatcher::new(sender.clone(), Arc::new(InMemoryNotificationDedup::new()));
+        let notification = released("seller-1", NotificationChannel::Sms);
+
+        assert!(dispatcher.dispatch(&notification).await.is_err());
+        assert_eq!(
+            dispatcher.dispatch(&notification).await.unwrap(),
+            DispatchOutcome::Sent
+        );
+        assert_eq!(sender.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Redis instance (REDIS_URL)"]
+    async fn test_redis_dedup_records_each_key_once() {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+        let dedup = RedisNotificationDedup::new(RedisPool::new(&url).await.unwrap(), "test");
+        let key = format!("evt-{}:user-1:sms", std::process::id());
+        let retention = Duration::from_secs(5);
+
+        assert!(dedup.first_delivery(&key, retention).await.unwrap());
+        assert!(!dedup.first_delivery(&key, retention).await.unwrap());
+        dedup.forget(&key).await.unwrap();
+        assert!(dedup.first_delivery(&key, retention).await.unwrap());
+        dedup.forget(&key).await.unwrap();
+    }
+}
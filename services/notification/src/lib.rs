@@ -1,3 +1,8 @@
+pub mod consumer;
+pub mod dispatch;
 pub mod routes;
+pub mod rules;
+#[cfg(test)]
+mod testing;
 
 pub use routes::router;
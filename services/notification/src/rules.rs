@@ -0,0 +1,149 @@
+//! Declarative mapping from domain events to notifications
+//!
+//! [`EventNotificationRules`] says which notifications an event type
+//! causes: who receives them (a user id field of the payload), over which
+//! channels, and the subject and body templates.  Templates name payload
+//! fields in braces, so adding a notification for a new event is a rule in
+//! configuration rather than a new consumer:
+//!
+//! ```json
+//! {
+//!   "verification.approved": [{
+//!     "recipient": "user_id",
+//!     "channels": ["email", "in_app"],
+//!     "subject": "You're verified",
+//!     "body": "Hi {first_name}, your {level} verification was approved."
+//!   }]
+//! }
+//! ```
+//!
+//! Events without a rule cause no notification.
+
+use std::collections::HashMap;
+
+use common::events::EventEnvelope;
+use error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dispatch::{Notification, NotificationChannel};
+
+/// One notification an event type causes, sent over each of `channels`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    /// Payload field holding the id of the user to notify
+    pub recipient: String,
+    pub channels: Vec<NotificationChannel>,
+    /// Subject template, for channels that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Body template; `{field}` is replaced with that payload field
+    pub body: String,
+}
+
+impl NotificationRule {
+    pub fn new(
+        recipient: impl Into<String>,
+        channels: impl IntoIterator<Item = NotificationChannel>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            recipient: recipient.into(),
+            channels: channels.into_iter().collect(),
+            subject: None,
+            body: body.into(),
+        }
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+}
+
+/// Notification rules by event type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EventNotificationRules {
+    rules: HashMap<String, Vec<NotificationRule>>,
+}
+
+impl EventNotificationRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rules from a JSON object keyed by event type
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        serde_json::from_str(json)
+            .map_err(|e| AppError::validation(format!("invalid notification rules: {e}")))
+    }
+
+    /// Also notify according to `rule` on `event_type`
+    pub fn with_rule(mut self, event_type: impl Into<String>, rule: NotificationRule) -> Self {
+        self.rules.entry(event_type.into()).or_default().push(rule);
+        self
+    }
+
+    /// Rules of `event_type`
+    pub fn rules_for(&self, event_type: &str) -> &[NotificationRule] {
+        self.rules.get(event_type).map_or(&[], Vec::as_slice)
+    }
+
+    /// Notifications `event` causes, one per rule and channel
+    ///
+    /// Fails when a rule names a payload field the event doesn't carry, so
+    /// a rule that no longer matches its event is noticed rather than
+    /// sending half-filled messages.
+    pub fn notifications(&self, event: &EventEnvelope) -> Result<Vec<Notification>, AppError> {
+        let mut notifications = Vec::new();
+        for rule in self.rules_for(&event.event_type) {
+            let recipient = field(event, &rule.recipient)?;
+            let subject = rule
+                .subject
+                .as_deref()
+                .map(|subject| render(subject, event))
+                .transpose()?;
+            let body = render(&rule.body, event)?;
+            notifications.extend(rule.channels.iter().map(|&channel| Notification {
+                event_id: event.event_id.clone(),
+                recipient: recipient.clone(),
+                channel,
+                subject: subject.clone(),
+                body: body.clone(),
+            }));
+        }
+        Ok(notifications)
+    }
+}
+
+/// Payload field `name` of `event` as text
+fn field(event: &EventEnvelope, name: &str) -> Result<String, AppError> {
+    match event.payload.get(name) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(value @ (Value::Number(_) | Value::Bool(_))) => Ok(value.to_string()),
+        _ => Err(AppError::validation_with_field(
+            format!(
+                "{} event {} has no text field '{name}' for its notification",
+                event.event_type, event.event_id
+            ),
+            name,
+        )),
+    }
+}
+
+/// `template` with each `{field}` replaced by that payload field
+fn render(template: &str, event: &EventEnvelope) -> Result<String, AppError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&field(event, &rest[start + 1..start + len])?);
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
@@ -0,0 +1,28 @@
+//! Test doubles shared by this crate's tests
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use error::AppError;
+
+use crate::dispatch::{Notification, NotificationSender};
+
+/// Records sends; fails the first `failures` of them
+#[derive(Default)]
+pub(crate) struct RecordingSender {
+    pub(crate) sent: Mutex<Vec<Notification>>,
+    pub(crate) failures: AtomicUsize,
+}
+
+#[async_trait]
+impl NotificationSender for RecordingSender {
+    async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        if self.failures.load(Ordering::SeqCst) > 0 {
+            self.failures.fetch_sub(1, Ordering::SeqCst);
+            return Err(AppError::external("sms", "gateway timeout"));
+        }
+        self.sent.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Order service errors
+
+use error::{AppError, ServiceError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OrderError {
+    #[error("order {0} not found")]
+    NotFound(String),
+
+    #[error("order {0} already exists")]
+    AlreadyExists(String),
+
+    #[error("order has no line items")]
+    Empty,
+
+    #[error("quantity must be at least 1, got {0}")]
+    InvalidQuantity(u32),
+
+    #[error("cannot move order from {from} to {to}")]
+    InvalidTransition { from: String, to: String },
+
+    #[error("order {0} can no longer be cancelled")]
+    NotCancellable(String),
+}
+
+impl ServiceError for OrderError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "ORDER_NOT_FOUND",
+            Self::AlreadyExists(_) => "ORDER_ALREADY_EXISTS",
+            Self::Empty => "ORDER_EMPTY",
+            Self::InvalidQuantity(_) => "ORDER_INVALID_QUANTITY",
+            Self::InvalidTransition { .. } => "ORDER_INVALID_TRANSITION",
+            Self::NotCancellable(_) => "ORDER_NOT_CANCELLABLE",
+        }
+    }
+}
+
+impl From<OrderError> for AppError {
+    fn from(e: OrderError) -> Self {
+        let code = e.code();
+        match e {
+            OrderError::NotFound(id) => AppError::not_found("order", id),
+            OrderError::AlreadyExists(_) => AppError::conflict(e.to_string()),
+            OrderError::Empty => AppError::validation_with_field(e.to_string(), "items"),
+            OrderError::InvalidQuantity(_) => {
+                AppError::validation_with_field(e.to_string(), "quantity")
+            }
+            OrderError::InvalidTransition { .. } | OrderError::NotCancellable(_) => {
+                AppError::business(e.to_string(), code)
+            }
+        }
+    }
+}
+
+error::service_error!(OrderError);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use error::ServiceResult;
+    use error::http::{ApiError, ErrorCode};
+
+    fn cancel(id: &str) -> ServiceResult<(), OrderError> {
+        Err(OrderError::NotCancellable(id.to_string()))
+    }
+
+    fn cancel_handler(id: &str) -> Result<(), ApiError> {
+        cancel(id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_service_error_maps_to_api_error_with_stable_code() {
+        let err = ApiError::from(OrderError::NotFound("ord_42".into()));
+        assert_eq!(err.code, ErrorCode::NotFound);
+        assert_eq!(err.status_code, Some(StatusCode::NOT_FOUND));
+        assert_eq!(err.details.unwrap()["service_code"], "ORDER_NOT_FOUND");
+
+        let err = cancel_handler("ord_42").unwrap_err();
+        assert_eq!(err.status_code, Some(StatusCode::BAD_REQUEST));
+        let details = err.details.unwrap();
+        assert_eq!(details["service_code"], "ORDER_NOT_CANCELLABLE");
+        assert_eq!(details["business_code"], "ORDER_NOT_CANCELLABLE");
+    }
+}
@@ -1,3 +1,5 @@
+pub mod error;
 pub mod routes;
 
+pub use error::OrderError;
 pub use routes::router;